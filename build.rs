@@ -0,0 +1,110 @@
+// Copies the trust material embedded by the `embedded-roots` feature into
+// OUT_DIR, where src/roots.rs includes it. Files missing from the directory
+// are embedded empty, leaving the public sigstore roots in place.
+//
+// Also records the commit, time, target, profile and features of the build
+// for `sget version --verbose`, see src/version.rs.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The files read from `SGET_EMBED_DIR`.
+const EMBEDDED: [&str; 3] = ["policy.json", "fulcio.pem", "rekor.pem"];
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").expect("No manifest dir"));
+    build_info(&manifest_dir);
+    embed_roots(&manifest_dir);
+}
+
+fn embed_roots(manifest_dir: &Path) {
+    println!("cargo:rerun-if-env-changed=SGET_EMBED_DIR");
+    if env::var_os("CARGO_FEATURE_EMBEDDED_ROOTS").is_none() {
+        return;
+    }
+    let dir = manifest_dir.join(env::var_os("SGET_EMBED_DIR").unwrap_or_else(|| "embedded".into()));
+    let out = PathBuf::from(env::var_os("OUT_DIR").expect("No OUT_DIR"));
+    println!("cargo:rerun-if-changed={}", dir.display());
+    for name in EMBEDDED {
+        let path = dir.join(name);
+        println!("cargo:rerun-if-changed={}", path.display());
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => panic!("Cannot read {}: {}", path.display(), e),
+        };
+        fs::write(out.join(name), contents).expect("Cannot write embedded trust material");
+    }
+}
+
+/// The commit is taken from `SGET_GIT_COMMIT`, for builds from a source
+/// archive, or from git; the time from `SOURCE_DATE_EPOCH`, for reproducible
+/// builds, or the clock. `SGET_PROVENANCE` names the build's provenance, e.g.
+/// the URL of the attestation a release workflow publishes.
+fn build_info(manifest_dir: &Path) {
+    for var in ["SGET_GIT_COMMIT", "SOURCE_DATE_EPOCH", "SGET_PROVENANCE"] {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+    let git = manifest_dir.join(".git");
+    println!("cargo:rerun-if-changed={}", git.join("HEAD").display());
+    println!(
+        "cargo:rerun-if-changed={}",
+        git.join("packed-refs").display()
+    );
+    if let Ok(head) = fs::read_to_string(git.join("HEAD")) {
+        if let Some(reference) = head.trim().strip_prefix("ref: ") {
+            println!("cargo:rerun-if-changed={}", git.join(reference).display());
+        }
+    }
+
+    let commit = env::var("SGET_GIT_COMMIT").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(manifest_dir)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    });
+    let timestamp = env::var("SOURCE_DATE_EPOCH").unwrap_or_else(|_| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default()
+            .to_string()
+    });
+    // Optional dependencies are features too; only report those Cargo.toml
+    // declares in its [features] table.
+    let manifest = fs::read_to_string(manifest_dir.join("Cargo.toml")).unwrap_or_default();
+    let declared: Vec<&str> = manifest
+        .lines()
+        .skip_while(|line| line.trim() != "[features]")
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| line.split_once('=').map(|(name, _)| name.trim()))
+        .collect();
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(var, _)| {
+            var.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .filter(|feature| declared.contains(&feature.as_str()))
+        .collect();
+    features.sort();
+
+    let set = |name: &str, value: &str| println!("cargo:rustc-env={}={}", name, value);
+    set("SGET_BUILD_COMMIT", commit.as_deref().unwrap_or("unknown"));
+    set("SGET_BUILD_TIMESTAMP", &timestamp);
+    set("SGET_BUILD_FEATURES", &features.join(","));
+    set("SGET_BUILD_TARGET", &env::var("TARGET").unwrap_or_default());
+    set(
+        "SGET_BUILD_PROFILE",
+        &env::var("PROFILE").unwrap_or_default(),
+    );
+    if let Ok(provenance) = env::var("SGET_PROVENANCE") {
+        set("SGET_BUILD_PROVENANCE", &provenance);
+    }
+}
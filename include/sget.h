@@ -0,0 +1,85 @@
+/*
+ * Copyright 2021 The Sigstore Authors.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/*
+ * C bindings for fetching and verifying artifacts with sget.
+ *
+ * Build the library with
+ *     cargo rustc --lib --release --features ffi --crate-type cdylib
+ *
+ * All functions block. A client may be used from one thread at a time.
+ * Strings are NUL terminated UTF-8. Everything the library returns must be
+ * released with the matching *_free function.
+ */
+
+#ifndef SGET_H
+#define SGET_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SGET_ABI_VERSION 1
+
+typedef struct SgetClient SgetClient;
+typedef struct FetchResult FetchResult;
+
+/* The ABI version the library implements; compare with SGET_ABI_VERSION. */
+uint32_t sget_abi_version(void);
+
+/* The last error on this thread, or NULL. Valid until the next call. */
+const char *sget_last_error(void);
+
+/* A client trusting the local trust store, or NULL on error. */
+SgetClient *sget_client_new(void);
+void sget_client_free(SgetClient *client);
+
+/*
+ * Fetch and verify the artifact described by a JSON encoded manifest entry,
+ * e.g. {"url": "https://example.com/install.sh", "identity": "..."}.
+ * Returns NULL only for invalid arguments; verification failures are
+ * reported in the result.
+ */
+FetchResult *sget_fetch(const SgetClient *client, const char *entry);
+
+/* Whether the artifact verified. */
+bool sget_result_allowed(const FetchResult *result);
+
+/* The verified artifact, empty unless it verified. Valid until freed. */
+const uint8_t *sget_result_data(const FetchResult *result, size_t *len);
+
+/* A JSON report in the format of an audit log record. Valid until freed. */
+const char *sget_result_report(const FetchResult *result);
+
+void sget_result_free(FetchResult *result);
+
+/*
+ * Verify every artifact in the manifest at path. Returns a JSON array of
+ * reports, or NULL on error. Free it with sget_string_free.
+ */
+char *sget_verify_manifest(const SgetClient *client, const char *path);
+
+void sget_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SGET_H */
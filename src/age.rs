@@ -0,0 +1,329 @@
+//! Just enough of [age](https://age-encryption.org/v1) to decrypt files
+//! encrypted to X25519 recipients, so that private artifacts can be hosted in
+//! public places and only decrypted once they verify.
+
+use anyhow::{anyhow, Context, Result};
+use curve25519_dalek::{constants::X25519_BASEPOINT, montgomery::MontgomeryPoint, scalar::Scalar};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::{hkdf, hmac};
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use zeroize::Zeroizing;
+
+const INTRO: &[u8] = b"age-encryption.org/v1\n";
+const ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+const ARMOR_END: &str = "-----END AGE ENCRYPTED FILE-----";
+const SECRET_KEY_HRP: &str = "age-secret-key-";
+const X25519_INFO: &[u8] = b"age-encryption.org/v1/X25519";
+/// Stanza bodies are wrapped at this many base64 characters.
+const COLUMNS: usize = 64;
+/// Plaintext bytes per payload chunk.
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// How an artifact encrypted with age at rest is verified and decrypted.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AgeRequirements {
+    /// Local path of the age identity file to decrypt with, relative to the
+    /// manifest [default: the file named by `SGET_AGE_IDENTITY`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// Hex encoded SHA-256 digest of the plaintext. When given, the artifact
+    /// is decrypted before it is verified, and its signature and pins are
+    /// over the plaintext; otherwise they are over the ciphertext, and the
+    /// artifact is only decrypted once it verifies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plaintext_sha256: Option<String>,
+}
+
+/// An X25519 identity, the secret half of an age recipient.
+pub struct Identity {
+    secret: Zeroizing<[u8; 32]>,
+    recipient: [u8; 32],
+}
+
+impl Identity {
+    /// Parse an `AGE-SECRET-KEY-1...` identity.
+    pub fn parse(encoded: &str) -> Result<Self> {
+        let encoded = encoded.to_lowercase();
+        let (hrp, data) = bech32_decode(&encoded)?;
+        if hrp != SECRET_KEY_HRP {
+            return Err(anyhow!("Not an age X25519 identity"));
+        }
+        let mut secret = Zeroizing::new([0u8; 32]);
+        if data.len() != secret.len() {
+            return Err(anyhow!("Invalid age identity length"));
+        }
+        secret.copy_from_slice(&data);
+        let recipient = (X25519_BASEPOINT * clamped(&secret)).to_bytes();
+        Ok(Identity { secret, recipient })
+    }
+
+    /// The file key wrapped in an X25519 recipient stanza with the ephemeral
+    /// share `share`, if the stanza is for this identity.
+    fn file_key(&self, share: &[u8; 32], body: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+        let shared = Zeroizing::new((MontgomeryPoint(*share) * clamped(&self.secret)).to_bytes());
+        if shared.iter().all(|b| *b == 0) {
+            return None;
+        }
+        let mut salt = share.to_vec();
+        salt.extend_from_slice(&self.recipient);
+        let key = derive(shared.as_ref(), &salt, X25519_INFO).ok()?;
+        let mut file_key = Zeroizing::new(body.to_vec());
+        let len = open(&key, [0; 12], &mut file_key).ok()?.len();
+        file_key.truncate(len);
+        Some(file_key)
+    }
+}
+
+/// Parse an age identity file: one identity per line, with `#` comments.
+pub fn parse_identities(text: &str) -> Result<Vec<Identity>> {
+    let identities: Vec<Identity> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.starts_with("AGE-PLUGIN-") {
+            true => Err(anyhow!("age plugin identities are not supported")),
+            false => Identity::parse(line).context("Invalid age identity"),
+        })
+        .collect::<Result<_>>()?;
+    if identities.is_empty() {
+        return Err(anyhow!("No age identities"));
+    }
+    Ok(identities)
+}
+
+/// Decrypt the age file `data`, binary or armored, with the first of
+/// `identities` it was encrypted to.
+pub fn decrypt(data: &[u8], identities: &[Identity]) -> Result<Vec<u8>> {
+    if data.starts_with(ARMOR_BEGIN.as_bytes()) {
+        return decrypt(&dearmor(data)?, identities);
+    }
+    let invalid = || anyhow!("Invalid age header");
+    let mut rest = data
+        .strip_prefix(INTRO)
+        .ok_or_else(|| anyhow!("Not an age file"))?;
+    let mut stanzas = Vec::new();
+    let mac = loop {
+        let (line, next) = split_line(rest).ok_or_else(invalid)?;
+        rest = next;
+        if let Some(mac) = line.strip_prefix(b"--- ") {
+            break mac;
+        }
+        let args: Vec<&[u8]> = line
+            .strip_prefix(b"-> ")
+            .ok_or_else(invalid)?
+            .split(|b| *b == b' ')
+            .collect();
+        let mut body = Vec::new();
+        loop {
+            let (line, next) = split_line(rest).ok_or_else(invalid)?;
+            rest = next;
+            body.extend_from_slice(&decode_base64(line)?);
+            if line.len() < COLUMNS {
+                break;
+            }
+        }
+        stanzas.push((args, body));
+    };
+    let header = &data[..data.len() - rest.len() - mac.len() - 2];
+
+    let file_key = stanzas
+        .iter()
+        .filter_map(|(args, body)| match args.as_slice() {
+            [kind, share] if *kind == b"X25519" => {
+                let share: [u8; 32] = decode_base64(share).ok()?.try_into().ok()?;
+                Some((share, body))
+            }
+            _ => None,
+        })
+        .find_map(|(share, body)| {
+            identities
+                .iter()
+                .find_map(|identity| identity.file_key(&share, body))
+        })
+        .ok_or_else(|| anyhow!("The file is not encrypted to any of the age identities"))?;
+    let mac_key = derive(&file_key, &[], b"header")?;
+    hmac::verify(
+        &hmac::Key::new(hmac::HMAC_SHA256, mac_key.as_ref()),
+        header,
+        &decode_base64(mac)?,
+    )
+    .map_err(|_| anyhow!("The age header was altered"))?;
+
+    if rest.len() < 16 {
+        return Err(anyhow!("Truncated age payload"));
+    }
+    let (nonce, mut payload) = rest.split_at(16);
+    let key = derive(&file_key, nonce, b"payload")?;
+    let mut plaintext = Vec::with_capacity(payload.len());
+    let mut counter: u128 = 0;
+    loop {
+        let last = payload.len() <= CHUNK_SIZE + TAG_LEN;
+        let (chunk, next) = payload.split_at(payload.len().min(CHUNK_SIZE + TAG_LEN));
+        let mut nonce = [0u8; 12];
+        nonce[..11].copy_from_slice(&counter.to_be_bytes()[5..]);
+        nonce[11] = last as u8;
+        let mut chunk = chunk.to_vec();
+        let opened = open(&key, nonce, &mut chunk)
+            .map_err(|_| anyhow!("The age payload was altered or truncated"))?;
+        if opened.is_empty() && counter > 0 {
+            return Err(anyhow!("Invalid empty final age chunk"));
+        }
+        plaintext.extend_from_slice(opened);
+        if last {
+            return Ok(plaintext);
+        }
+        payload = next;
+        counter += 1;
+    }
+}
+
+/// The first line of `input` without its newline, and the rest.
+fn split_line(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = input.iter().position(|b| *b == b'\n')?;
+    Some((&input[..end], &input[end + 1..]))
+}
+
+fn decode_base64(encoded: &[u8]) -> Result<Vec<u8>> {
+    base64::decode_config(encoded, base64::STANDARD_NO_PAD)
+        .map_err(|e| anyhow!("Invalid base64 in age header: {}", e))
+}
+
+fn dearmor(data: &[u8]) -> Result<Vec<u8>> {
+    let text = std::str::from_utf8(data)?.trim();
+    let body = text
+        .strip_prefix(ARMOR_BEGIN)
+        .and_then(|text| text.strip_suffix(ARMOR_END))
+        .ok_or_else(|| anyhow!("Invalid armored age file"))?;
+    let body: String = body.split_whitespace().collect();
+    Ok(base64::decode(body)?)
+}
+
+fn clamped(secret: &[u8; 32]) -> Scalar {
+    let mut bits = *secret;
+    bits[0] &= 248;
+    bits[31] &= 127;
+    bits[31] |= 64;
+    Scalar::from_bits(bits)
+}
+
+/// HKDF-SHA-256 of `ikm` with `salt` and `info`, 32 bytes long.
+fn derive(ikm: &[u8], salt: &[u8], info: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    hkdf::Salt::new(hkdf::HKDF_SHA256, salt)
+        .extract(ikm)
+        .expand(&[info], hkdf::HKDF_SHA256)
+        .and_then(|okm| okm.fill(key.as_mut()))
+        .map_err(|_| anyhow!("Cannot derive key"))?;
+    Ok(key)
+}
+
+/// Open the ChaCha20-Poly1305 sealed `sealed` in place.
+fn open<'a>(key: &[u8; 32], nonce: [u8; 12], sealed: &'a mut [u8]) -> Result<&'a mut [u8]> {
+    let key = UnboundKey::new(&aead::CHACHA20_POLY1305, key)
+        .map_err(|_| anyhow!("Invalid ChaCha20-Poly1305 key"))?;
+    LessSafeKey::new(key)
+        .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::empty(), sealed)
+        .map_err(|_| anyhow!("Decryption failed"))
+}
+
+/// Decode the Bech32 string `encoded` into its human readable part and data.
+fn bech32_decode(encoded: &str) -> Result<(&str, Vec<u8>)> {
+    let invalid = || anyhow!("Invalid Bech32 encoding");
+    let (hrp, data) = encoded.rsplit_once('1').ok_or_else(invalid)?;
+    let values = data
+        .bytes()
+        .map(|c| BECH32_CHARSET.iter().position(|d| *d == c).map(|v| v as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    if hrp.is_empty() || values.len() < 6 {
+        return Err(invalid());
+    }
+    let expanded = hrp
+        .bytes()
+        .map(|c| c >> 5)
+        .chain(std::iter::once(0))
+        .chain(hrp.bytes().map(|c| c & 31));
+    if bech32_polymod(expanded.chain(values.iter().copied())) != 1 {
+        return Err(anyhow!("Invalid Bech32 checksum"));
+    }
+    // Regroup the 5-bit values, less the checksum, into bytes.
+    let mut bytes = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0);
+    for value in &values[..values.len() - 6] {
+        acc = (acc << 5 | *value as u32) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 5 || acc & ((1 << bits) - 1) != 0 {
+        return Err(invalid());
+    }
+    Ok((hrp, bytes))
+}
+
+fn bech32_polymod(values: impl Iterator<Item = u8>) -> u32 {
+    const GENERATORS: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    values.fold(1, |chk, value| {
+        let top = chk >> 25;
+        GENERATORS
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| top >> i & 1 == 1)
+            .fold((chk & 0x1ffffff) << 5 ^ value as u32, |chk, (_, g)| chk ^ g)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: &str = include_str!("../tests/test_data/age/identity.txt");
+    const SCRIPT: &[u8] = include_bytes!("../tests/test_data/age/script.sh");
+    const ENCRYPTED: &[u8] = include_bytes!("../tests/test_data/age/script.sh.age");
+    const LARGE: &[u8] = include_bytes!("../tests/test_data/age/large.bin.age");
+
+    #[test]
+    fn decrypt_age() {
+        let identities = parse_identities(IDENTITY).expect("Invalid identity");
+        let plaintext = decrypt(ENCRYPTED, &identities).expect("Cannot decrypt");
+        assert_eq!(plaintext, SCRIPT);
+
+        // Payloads span chunks, and armor is taken off.
+        let mut expected: Vec<u8> = (0..=255).cycle().take(256 * 256).collect();
+        expected.extend_from_slice(b"tail");
+        assert_eq!(
+            decrypt(LARGE, &identities).expect("Cannot decrypt"),
+            expected
+        );
+        let armored = format!(
+            "{}\n{}\n{}\n",
+            ARMOR_BEGIN,
+            base64::encode(ENCRYPTED),
+            ARMOR_END
+        );
+        assert_eq!(
+            decrypt(armored.as_bytes(), &identities).expect("Cannot decrypt"),
+            SCRIPT
+        );
+
+        let mut altered = ENCRYPTED.to_vec();
+        let last = altered.len() - 1;
+        altered[last] ^= 1;
+        assert!(decrypt(&altered, &identities).is_err());
+        assert!(decrypt(&ENCRYPTED[..ENCRYPTED.len() - 1], &identities).is_err());
+        let stranger = parse_identities(
+            "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX",
+        )
+        .expect("Invalid identity");
+        assert!(decrypt(ENCRYPTED, &stranger).is_err());
+        assert!(Identity::parse(
+            "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEY"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,444 @@
+use serde::{Deserialize, Serialize};
+use serde_plain::{derive_display_from_serialize, derive_fromstr_from_deserialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::fmt;
+use std::str::FromStr;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::der_parser::der::parse_der;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+use crate::error::{Result, SgetError};
+use crate::fips;
+use crate::keys::PublicKey;
+
+const OID_RSA: &str = "1.2.840.113549.1.1.1";
+const OID_EC_PUBLIC_KEY: &str = "1.2.840.10045.2.1";
+const OID_P256: &str = "1.2.840.10045.3.1.7";
+const OID_P384: &str = "1.3.132.0.34";
+const OID_ED25519: &str = "1.3.101.112";
+
+/// The kinds of key signatures may be made with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignatureAlgorithm {
+    EcdsaP256,
+    EcdsaP384,
+    Ed25519,
+    Rsa,
+}
+
+derive_display_from_serialize!(SignatureAlgorithm);
+derive_fromstr_from_deserialize!(SignatureAlgorithm);
+
+/// The hash functions signatures may be made over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+    Blake3,
+}
+
+derive_display_from_serialize!(HashAlgorithm);
+derive_fromstr_from_deserialize!(HashAlgorithm);
+
+/// A streaming hasher for the hash functions artifact digests may be declared
+/// with. SHA-1 is not one of them.
+pub enum Hasher {
+    Sha256(Sha256),
+    Sha384(Sha384),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher::Sha256(Sha256::new())
+    }
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Result<Self> {
+        match algorithm {
+            HashAlgorithm::Sha256 => Ok(Hasher::Sha256(Sha256::new())),
+            HashAlgorithm::Sha384 => Ok(Hasher::Sha384(Sha384::new())),
+            HashAlgorithm::Sha512 => Ok(Hasher::Sha512(Sha512::new())),
+            HashAlgorithm::Blake3 if fips::is_enabled() => Err(SgetError::DisallowedAlgorithm(
+                "blake3 artifact digests are not FIPS-approved".to_string(),
+            )),
+            HashAlgorithm::Blake3 => Ok(Hasher::Blake3(Box::default())),
+            HashAlgorithm::Sha1 => Err(SgetError::DisallowedAlgorithm(
+                "sha1 artifact digests are not supported".to_string(),
+            )),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha384(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// The hex encoded digest.
+    pub fn finalize_hex(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha384(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha512(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+impl std::io::Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The expected digest of an artifact, written `<algorithm>:<hex>`, e.g.
+/// `sha512:…`, or as bare hex for SHA-256, the default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArtifactDigest {
+    pub algorithm: HashAlgorithm,
+    /// Lower case hex encoded digest.
+    pub hex: String,
+}
+
+impl ArtifactDigest {
+    /// The digest of `data` with the same algorithm.
+    pub fn of(&self, data: &[u8]) -> Result<ArtifactDigest> {
+        let mut hasher = Hasher::new(self.algorithm)?;
+        hasher.update(data);
+        Ok(ArtifactDigest {
+            algorithm: self.algorithm,
+            hex: hasher.finalize_hex(),
+        })
+    }
+}
+
+impl FromStr for ArtifactDigest {
+    type Err = SgetError;
+    fn from_str(s: &str) -> Result<Self> {
+        let (algorithm, hex) = match s.split_once(':') {
+            Some((algorithm, hex)) => (
+                algorithm.parse().map_err(|_| {
+                    SgetError::DisallowedAlgorithm(format!(
+                        "Unsupported digest algorithm {}",
+                        algorithm
+                    ))
+                })?,
+                hex,
+            ),
+            None => (HashAlgorithm::Sha256, s),
+        };
+        // Fail on SHA-1 here rather than when hashing.
+        Hasher::new(algorithm)?;
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(SgetError::InvalidEntry(format!("Invalid digest {}", s)));
+        }
+        Ok(ArtifactDigest {
+            algorithm,
+            hex: hex.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl fmt::Display for ArtifactDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.hex)
+    }
+}
+
+/// The signature algorithms, hash functions and key sizes verification
+/// accepts, set in the `algorithms` section of the configuration.
+///
+/// The policy applies to the signatures on artifacts and to the certificate
+/// chains they are verified with; trust anchors' self-signatures are not
+/// checked. It only narrows what each signature format supports: OpenPGP and
+/// SSH signatures with SHA-1 and RSA keys under 2048 bits are never accepted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AlgorithmPolicy {
+    #[serde(default = "default_signature_algorithms")]
+    pub signature_algorithms: Vec<SignatureAlgorithm>,
+    #[serde(default = "default_hash_algorithms")]
+    pub hash_algorithms: Vec<HashAlgorithm>,
+    #[serde(default = "default_min_rsa_bits")]
+    pub min_rsa_bits: usize,
+}
+
+fn default_signature_algorithms() -> Vec<SignatureAlgorithm> {
+    vec![
+        SignatureAlgorithm::EcdsaP256,
+        SignatureAlgorithm::EcdsaP384,
+        SignatureAlgorithm::Ed25519,
+        SignatureAlgorithm::Rsa,
+    ]
+}
+
+fn default_hash_algorithms() -> Vec<HashAlgorithm> {
+    vec![
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha384,
+        HashAlgorithm::Sha512,
+    ]
+}
+
+fn default_min_rsa_bits() -> usize {
+    2048
+}
+
+impl Default for AlgorithmPolicy {
+    /// Every supported key type, SHA-2 and RSA keys of at least 2048 bits.
+    fn default() -> Self {
+        AlgorithmPolicy {
+            signature_algorithms: default_signature_algorithms(),
+            hash_algorithms: default_hash_algorithms(),
+            min_rsa_bits: default_min_rsa_bits(),
+        }
+    }
+}
+
+impl AlgorithmPolicy {
+    /// Check a signature made with a `key_bits` bit key of type `signature`
+    /// over a `hash` digest. In FIPS mode, the signature must also be
+    /// FIPS-approved.
+    pub fn check(
+        &self,
+        signature: SignatureAlgorithm,
+        hash: HashAlgorithm,
+        key_bits: usize,
+    ) -> Result<()> {
+        if fips::is_enabled() && !fips::is_approved(signature, hash, key_bits) {
+            return Err(SgetError::DisallowedAlgorithm(format!(
+                "{} with {} is not FIPS-approved",
+                signature, hash
+            )));
+        }
+        if !self.signature_algorithms.contains(&signature) {
+            return Err(SgetError::DisallowedAlgorithm(format!(
+                "{} signatures are not allowed",
+                signature
+            )));
+        }
+        if !self.hash_algorithms.contains(&hash) {
+            return Err(SgetError::DisallowedAlgorithm(format!(
+                "{} is not an allowed hash function",
+                hash
+            )));
+        }
+        if signature == SignatureAlgorithm::Rsa && key_bits < self.min_rsa_bits {
+            return Err(SgetError::DisallowedAlgorithm(format!(
+                "{} bit RSA key is shorter than the minimum of {} bits",
+                key_bits, self.min_rsa_bits
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check signatures made with a key from `sget keygen` or a Fulcio
+    /// certificate.
+    pub fn check_key(&self, key: &PublicKey) -> Result<()> {
+        match key {
+            PublicKey::EcdsaP256(_) => {
+                self.check(SignatureAlgorithm::EcdsaP256, HashAlgorithm::Sha256, 256)
+            }
+            PublicKey::Ed25519(_) => {
+                self.check(SignatureAlgorithm::Ed25519, HashAlgorithm::Sha512, 256)
+            }
+        }
+    }
+
+    /// Check the signature on `cert` by its issuer's key `issuer`.
+    pub(crate) fn check_certificate(
+        &self,
+        cert: &X509Certificate,
+        issuer: &SubjectPublicKeyInfo,
+    ) -> Result<()> {
+        let oid = cert.signature_algorithm.algorithm.to_id_string();
+        let hash = match oid.as_str() {
+            "1.2.840.113549.1.1.5" | "1.2.840.10045.4.1" => HashAlgorithm::Sha1,
+            "1.2.840.113549.1.1.11" | "1.2.840.10045.4.3.2" => HashAlgorithm::Sha256,
+            "1.2.840.113549.1.1.12" | "1.2.840.10045.4.3.3" => HashAlgorithm::Sha384,
+            "1.2.840.113549.1.1.13" | "1.2.840.10045.4.3.4" | OID_ED25519 => HashAlgorithm::Sha512,
+            other => {
+                return Err(SgetError::DisallowedAlgorithm(format!(
+                    "Unsupported certificate signature algorithm {}",
+                    other
+                )))
+            }
+        };
+        let (signature, key_bits) = key_algorithm(issuer)?;
+        self.check(signature, hash, key_bits)
+    }
+}
+
+/// The type and size in bits of a public key.
+pub(crate) fn key_algorithm(spki: &SubjectPublicKeyInfo) -> Result<(SignatureAlgorithm, usize)> {
+    let unsupported = || {
+        SgetError::DisallowedAlgorithm(format!(
+            "Unsupported public key algorithm {}",
+            spki.algorithm.algorithm.to_id_string()
+        ))
+    };
+    match spki.algorithm.algorithm.to_id_string().as_str() {
+        OID_RSA => {
+            let modulus = parse_der(spki.subject_public_key.data)
+                .ok()
+                .and_then(|(_, key)| {
+                    key.as_sequence()
+                        .ok()
+                        .and_then(|fields| fields.first().and_then(|n| n.as_slice().ok()))
+                })
+                .ok_or_else(unsupported)?;
+            Ok((SignatureAlgorithm::Rsa, bit_length(modulus)))
+        }
+        OID_EC_PUBLIC_KEY => {
+            let curve = spki
+                .algorithm
+                .parameters
+                .as_ref()
+                .and_then(|parameters| parameters.as_oid().ok())
+                .map(|oid| oid.to_id_string());
+            match curve.as_deref() {
+                Some(OID_P256) => Ok((SignatureAlgorithm::EcdsaP256, 256)),
+                Some(OID_P384) => Ok((SignatureAlgorithm::EcdsaP384, 384)),
+                _ => Err(unsupported()),
+            }
+        }
+        OID_ED25519 => Ok((SignatureAlgorithm::Ed25519, 256)),
+        _ => Err(unsupported()),
+    }
+}
+
+/// The number of significant bits of a big-endian unsigned integer.
+pub(crate) fn bit_length(value: &[u8]) -> usize {
+    match value.iter().position(|byte| *byte != 0) {
+        Some(start) => (value.len() - start) * 8 - value[start].leading_zeros() as usize,
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_core::{parse_certificate, pem_certificates, verify_chain};
+
+    #[test]
+    fn reject_weak_algorithms() {
+        let policy = AlgorithmPolicy::default();
+        assert!(policy
+            .check(SignatureAlgorithm::EcdsaP256, HashAlgorithm::Sha256, 256)
+            .is_ok());
+        assert!(policy
+            .check(SignatureAlgorithm::Rsa, HashAlgorithm::Sha1, 4096)
+            .is_err());
+        assert!(policy
+            .check(SignatureAlgorithm::Rsa, HashAlgorithm::Sha256, 1024)
+            .is_err());
+        let ed25519_only: AlgorithmPolicy =
+            serde_json::from_str(r#"{"signature_algorithms": ["ed25519"]}"#)
+                .expect("Invalid policy");
+        assert_eq!(ed25519_only.min_rsa_bits, 2048);
+        assert!(ed25519_only
+            .check(SignatureAlgorithm::EcdsaP256, HashAlgorithm::Sha256, 256)
+            .is_err());
+
+        // A leaf signed with SHA-1 by a 1024 bit RSA root.
+        let root = pem_certificates(include_bytes!("../tests/test_data/algorithms/root.pem"))
+            .expect("Invalid root");
+        let leaf = pem_certificates(include_bytes!("../tests/test_data/algorithms/leaf.pem"))
+            .expect("Invalid leaf");
+        let leaf = parse_certificate(&leaf[0]).expect("Invalid leaf");
+        assert!(matches!(
+            verify_chain(&leaf, &[], &root, &policy),
+            Err(SgetError::DisallowedAlgorithm(_))
+        ));
+        let legacy = AlgorithmPolicy {
+            hash_algorithms: vec![HashAlgorithm::Sha1],
+            min_rsa_bits: 1024,
+            ..AlgorithmPolicy::default()
+        };
+        // FIPS mode refuses SHA-1 whatever the policy allows.
+        assert_eq!(
+            verify_chain(&leaf, &[], &root, &legacy).is_ok(),
+            !fips::is_enabled()
+        );
+    }
+
+    #[test]
+    fn parse_artifact_digests() {
+        let sha256: ArtifactDigest = hex::encode(Sha256::digest(b"echo hi"))
+            .parse()
+            .expect("Invalid digest");
+        assert_eq!(sha256.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(sha256.of(b"echo hi").expect("Cannot hash"), sha256);
+
+        let sha512: ArtifactDigest = format!("sha512:{}", hex::encode(Sha512::digest(b"echo hi")))
+            .parse()
+            .expect("Invalid digest");
+        assert_eq!(sha512.algorithm, HashAlgorithm::Sha512);
+        assert_eq!(sha512.of(b"echo hi").expect("Cannot hash"), sha512);
+        assert_ne!(sha512.of(b"echo bye").expect("Cannot hash"), sha512);
+
+        assert!("sha1:da39a3ee".parse::<ArtifactDigest>().is_err());
+        assert!("md5:af13".parse::<ArtifactDigest>().is_err());
+        assert!("sha512:not-hex".parse::<ArtifactDigest>().is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn parse_blake3_digests() {
+        let blake3: ArtifactDigest =
+            "blake3:AF1349B9F5F9A1A6A0404DEA36DCC9499BCB25C9ADC112B7CC9A93CAE41F3262"
+                .parse()
+                .expect("Invalid digest");
+        assert_eq!(blake3.algorithm, HashAlgorithm::Blake3);
+        assert_eq!(blake3.of(b"").expect("Cannot hash"), blake3);
+        assert_ne!(blake3.of(b"echo hi").expect("Cannot hash"), blake3);
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn blake3_test_vectors() {
+        let hash = |data: &[u8]| {
+            let mut hasher = Hasher::new(HashAlgorithm::Blake3).expect("No hasher");
+            hasher.update(data);
+            hasher.finalize_hex()
+        };
+        assert_eq!(
+            hash(b"abc"),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        // From the official test vectors, whose inputs repeat 0..251.
+        for (len, expected) in [
+            (
+                1,
+                "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+            ),
+            (
+                1024,
+                "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+            ),
+            (
+                1025,
+                "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+            ),
+        ] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            assert_eq!(hash(&data), expected);
+        }
+    }
+}
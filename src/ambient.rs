@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Context, Result};
+use http::header::AUTHORIZATION;
+use serde::Deserialize;
+use std::fmt;
+
+use crate::transport::{self, Request, Transport};
+
+/// The audience Fulcio expects of identity tokens.
+pub const AUDIENCE: &str = "sigstore";
+
+/// A CI system that gives its jobs OIDC identity tokens, so that pipelines
+/// can sign keyless without a browser flow or a stored secret.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Provider {
+    GitHubActions,
+    GitLabCi,
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Provider::GitHubActions => "GitHub Actions",
+            Provider::GitLabCi => "GitLab CI",
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct GitHubToken {
+    value: String,
+}
+
+/// The CI system the process runs in, judging by the environment variables
+/// `var` returns.
+pub fn provider(var: &dyn Fn(&str) -> Option<String>) -> Option<Provider> {
+    if var("GITHUB_ACTIONS").as_deref() == Some("true") {
+        Some(Provider::GitHubActions)
+    } else if var("GITLAB_CI").as_deref() == Some("true") {
+        Some(Provider::GitLabCi)
+    } else {
+        None
+    }
+}
+
+/// An identity token for [`AUDIENCE`] from the CI system the process runs
+/// in, if any, with environment variables from `var`.
+///
+/// GitHub Actions jobs request one from the `ACTIONS_ID_TOKEN_REQUEST_URL`
+/// endpoint, which needs the `id-token: write` permission. GitLab CI jobs
+/// have no such endpoint; they declare an ID token in the job instead, which
+/// is passed as `SIGSTORE_ID_TOKEN`:
+///
+/// ```yaml
+/// id_tokens:
+///   SIGSTORE_ID_TOKEN:
+///     aud: sigstore
+/// ```
+pub async fn identity_token(
+    transport: &dyn Transport,
+    var: &dyn Fn(&str) -> Option<String>,
+) -> Result<Option<(Provider, String)>> {
+    let provider = match provider(var) {
+        Some(provider) => provider,
+        None => return Ok(None),
+    };
+    match provider {
+        Provider::GitHubActions => {
+            let (url, bearer) = match (
+                var("ACTIONS_ID_TOKEN_REQUEST_URL"),
+                var("ACTIONS_ID_TOKEN_REQUEST_TOKEN"),
+            ) {
+                (Some(url), Some(bearer)) => (url, bearer),
+                _ => {
+                    return Err(anyhow!(
+                        "This GitHub Actions job cannot request an identity token; grant it `permissions: id-token: write`"
+                    ))
+                }
+            };
+            let separator = if url.contains('?') { '&' } else { '?' };
+            let request = Request::get(format!("{}{}audience={}", url, separator, AUDIENCE))
+                .header(AUTHORIZATION, format!("bearer {}", bearer))
+                .body(Vec::new())?;
+            let response = transport.send(request).await?;
+            let token: GitHubToken = transport::json(&response)
+                .context("Cannot get an identity token from GitHub Actions")?;
+            Ok(Some((provider, token.value)))
+        }
+        Provider::GitLabCi => Err(anyhow!(
+            "GitLab CI jobs declare an identity token for keyless signing: add `id_tokens: {{SIGSTORE_ID_TOKEN: {{aud: {}}}}}` to the job",
+            AUDIENCE
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Response;
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+
+    /// The GitHub Actions token endpoint.
+    struct TokenEndpoint;
+
+    #[async_trait]
+    impl Transport for TokenEndpoint {
+        async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+            let authorized = request
+                .headers()
+                .get(AUTHORIZATION)
+                .is_some_and(|value| value == "bearer r3quest");
+            let response = Response::builder();
+            Ok(match request.uri().to_string().as_str() {
+                "https://actions.example/token?api-version=2.0&audience=sigstore" if authorized => {
+                    response.body(br#"{"count":1,"value":"eyJ.id.token"}"#.to_vec())?
+                }
+                _ => response.status(403).body(Vec::new())?,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn detect_ci_tokens() {
+        let detect = |vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> = vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            async move {
+                let var = |name: &str| vars.get(name).cloned();
+                identity_token(&TokenEndpoint, &var).await
+            }
+        };
+
+        assert!(detect(&[]).await.expect("Detection failed").is_none());
+        let github = [
+            ("GITHUB_ACTIONS", "true"),
+            (
+                "ACTIONS_ID_TOKEN_REQUEST_URL",
+                "https://actions.example/token?api-version=2.0",
+            ),
+            ("ACTIONS_ID_TOKEN_REQUEST_TOKEN", "r3quest"),
+        ];
+        assert_eq!(
+            detect(&github).await.expect("No token"),
+            Some((Provider::GitHubActions, "eyJ.id.token".to_string()))
+        );
+
+        // Without the id-token permission, or with the wrong request token.
+        assert!(detect(&github[..1]).await.is_err());
+        let mut wrong = github;
+        wrong[2].1 = "wrong";
+        assert!(detect(&wrong).await.is_err());
+        assert!(detect(&[("GITLAB_CI", "true")]).await.is_err());
+    }
+}
@@ -0,0 +1,456 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, SgetError};
+use crate::identities::RequiredSigner;
+use crate::keys::PublicKey;
+use crate::provenance::{pae, Envelope};
+use crate::targets;
+use crate::verify_core::{self, Signer, TrustRoots};
+
+/// Payload type of the DSSE envelopes approvals are signed as.
+pub const APPROVAL_PAYLOAD_TYPE: &str = "application/vnd.sget.approval+json";
+
+/// How long an approval request can be approved and used for.
+pub const REQUEST_LIFETIME_HOURS: i64 = 24;
+
+/// Artifacts that may only run once a second operator has approved them, set
+/// in the system policy:
+///
+/// ```yaml
+/// approvals:
+///   - pattern: https://example.com/scripts/prod/**
+///     approvers:
+///       - identity: "*@example.com"
+///         issuer: https://accounts.google.com
+///     approver_keys:
+///       - |
+///         -----BEGIN PUBLIC KEY-----
+///         ...
+///         -----END PUBLIC KEY-----
+/// ```
+///
+/// Patterns, and approver identities, are globs as in
+/// [`TargetRule`](crate::targets::TargetRule). An artifact matching any rule
+/// needs an approval, by an approver every matching rule allows, and never
+/// by the artifact's own signer.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApprovalRule {
+    pub pattern: String,
+    /// Keyless identities allowed to approve.
+    #[serde(default)]
+    pub approvers: Vec<RequiredSigner>,
+    /// PEM public keys, from `sget keygen`, allowed to approve.
+    #[serde(default)]
+    pub approver_keys: Vec<String>,
+}
+
+impl ApprovalRule {
+    pub fn applies_to(&self, location: &str) -> bool {
+        targets::glob_matches(&self.pattern, location)
+    }
+
+    fn allows(&self, approver: &Signer) -> bool {
+        self.approvers
+            .iter()
+            .any(|required| required.matches(approver))
+            || (approver.identity.is_none()
+                && self
+                    .keys()
+                    .any(|key| key.key_id().is_ok_and(|key_id| key_id == approver.key_id)))
+    }
+
+    fn keys(&self) -> impl Iterator<Item = PublicKey> + '_ {
+        self.approver_keys
+            .iter()
+            .filter_map(|pem| PublicKey::from_pem(pem).ok())
+    }
+}
+
+/// What an approver is asked to approve: running one artifact, by digest,
+/// from one source, until the request expires.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    /// URL, path or OCI reference the artifact was fetched from.
+    pub source: String,
+    /// Hex SHA-256 digest of the artifact.
+    pub sha256: String,
+    /// The verified signer of the artifact.
+    pub signer: String,
+    /// Who asked to run it, as `user@host`.
+    pub requested_by: String,
+    pub requested_at: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+}
+
+impl ApprovalRequest {
+    /// A request to run the artifact with SHA-256 `sha256` from `source`,
+    /// signed by `signer`, made at `now`.
+    pub fn new(
+        source: &str,
+        sha256: &str,
+        signer: &Signer,
+        requested_by: &str,
+        now: DateTime<Utc>,
+    ) -> Self {
+        ApprovalRequest {
+            source: source.to_string(),
+            sha256: sha256.to_string(),
+            signer: signer.subject().to_string(),
+            requested_by: requested_by.to_string(),
+            requested_at: now,
+            expires: now + Duration::hours(REQUEST_LIFETIME_HOURS),
+        }
+    }
+}
+
+/// The rules of `rules` that apply to the artifact at `location`; running it
+/// needs an approval if there are any.
+pub fn rules_for<'a>(rules: &'a [ApprovalRule], location: &str) -> Vec<&'a ApprovalRule> {
+    rules
+        .iter()
+        .filter(|rule| rule.applies_to(location))
+        .collect()
+}
+
+/// Verify the approval `raw`, a DSSE envelope signed by the approver, for
+/// running the artifact with SHA-256 `sha256` from `source` signed by
+/// `signer`, and return the approver. The approval must be of a request for
+/// that artifact that has not expired at `now`, and its approver must be
+/// allowed by every rule of `rules` and differ from `signer`.
+pub fn verify_approval(
+    raw: &[u8],
+    source: &str,
+    sha256: &str,
+    signer: &Signer,
+    rules: &[&ApprovalRule],
+    roots: &TrustRoots,
+    now: DateTime<Utc>,
+) -> Result<Signer> {
+    let invalid = SgetError::Unapproved;
+    let envelope: Envelope =
+        serde_json::from_slice(raw).map_err(|e| invalid(format!("Invalid approval: {}", e)))?;
+    let (request, approver) =
+        open(&envelope, rules, roots).map_err(|e| invalid(format!("Invalid approval: {:#}", e)))?;
+    if request.source != source || !request.sha256.eq_ignore_ascii_case(sha256) {
+        return Err(invalid(format!(
+            "The approval is for {} with SHA-256 {}",
+            request.source, request.sha256
+        )));
+    }
+    if request.expires < now {
+        return Err(invalid(format!(
+            "The approval request expired at {}",
+            request.expires
+        )));
+    }
+    if approver.subject() == signer.subject() {
+        return Err(invalid(format!(
+            "{} signed the artifact and cannot approve it too",
+            approver.subject()
+        )));
+    }
+    if let Some(rule) = rules.iter().find(|rule| !rule.allows(&approver)) {
+        return Err(invalid(format!(
+            "{} may not approve {}",
+            approver.subject(),
+            rule.pattern
+        )));
+    }
+    Ok(approver)
+}
+
+/// Check that the artifact with SHA-256 `sha256` from `source`, signed by
+/// `signer`, may run under the approval rules `rules`: no rule applies, and
+/// the approver is `None`, or one of `approvals` is valid for it.
+pub fn check_approvals(
+    approvals: &[Vec<u8>],
+    source: &str,
+    sha256: &str,
+    signer: &Signer,
+    rules: &[ApprovalRule],
+    roots: &TrustRoots,
+    now: DateTime<Utc>,
+) -> Result<Option<Signer>> {
+    let rules = rules_for(rules, source);
+    if rules.is_empty() {
+        return Ok(None);
+    }
+    let mut error = SgetError::Unapproved(format!(
+        "{} needs the approval of a second operator",
+        source
+    ));
+    for raw in approvals {
+        match verify_approval(raw, source, sha256, signer, &rules, roots, now) {
+            Ok(approver) => return Ok(Some(approver)),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+/// Verify the signature of an approval envelope, by a Fulcio certificate or
+/// one of the approver keys of `rules`, and return its request and signer.
+fn open(
+    envelope: &Envelope,
+    rules: &[&ApprovalRule],
+    roots: &TrustRoots,
+) -> anyhow::Result<(ApprovalRequest, Signer)> {
+    if envelope.payload_type != APPROVAL_PAYLOAD_TYPE {
+        return Err(anyhow!("Unexpected payload type {}", envelope.payload_type));
+    }
+    let payload = base64::decode(&envelope.payload)?;
+    let signed = pae(&envelope.payload_type, &payload);
+    let approver = envelope.signatures.iter().find_map(|signature| {
+        let sig = base64::decode(&signature.sig).ok()?;
+        match &signature.cert {
+            Some(cert) => {
+                let (identity, issuer) = verify_core::verify_certificate_signature(
+                    cert.as_bytes(),
+                    &signed,
+                    &sig,
+                    roots,
+                )
+                .ok()?;
+                Some(Signer {
+                    key_id: String::new(),
+                    identity: Some(identity),
+                    issuer,
+                    integrated_time: None,
+                })
+            }
+            None => rules
+                .iter()
+                .flat_map(|rule| rule.keys())
+                .find(|key| key.verify(&signed, &sig).is_ok())
+                .and_then(|key| key.key_id().ok())
+                .map(|key_id| Signer {
+                    key_id,
+                    identity: None,
+                    issuer: None,
+                    integrated_time: None,
+                }),
+        }
+    });
+    let approver = approver.ok_or_else(|| anyhow!("No valid signature by an approver"))?;
+    Ok((serde_json::from_slice(&payload)?, approver))
+}
+
+/// The approval of `request`: a DSSE envelope signed by `key`, with its
+/// Fulcio `certificate` chain for keyless signatures.
+pub fn sign_request(
+    request: &ApprovalRequest,
+    key: &crate::keys::SigningKey,
+    certificate: Option<String>,
+) -> anyhow::Result<Envelope> {
+    let payload = serde_json::to_vec(request)?;
+    let signature = key.sign(&pae(APPROVAL_PAYLOAD_TYPE, &payload));
+    Ok(Envelope {
+        payload_type: APPROVAL_PAYLOAD_TYPE.to_string(),
+        payload: base64::encode(&payload),
+        signatures: vec![crate::provenance::EnvelopeSignature {
+            keyid: match &certificate {
+                Some(_) => String::new(),
+                None => key.public_key().key_id()?,
+            },
+            sig: base64::encode(&signature),
+            cert: certificate,
+        }],
+    })
+}
+
+/// The `--approval` flag of commands that run scripts.
+#[cfg(feature = "native")]
+pub(crate) fn arg() -> clap::Arg<'static> {
+    clap::Arg::new("approval")
+        .long("approval")
+        .value_name("FILE")
+        .takes_value(true)
+        .multiple_occurrences(true)
+        .about("Approval from `sget approve`, for scripts the system policy only runs with a second operator's approval")
+}
+
+/// The approvals given with `--approval`.
+#[cfg(feature = "native")]
+pub(crate) fn read_approvals(matches: &clap::ArgMatches) -> anyhow::Result<Vec<Vec<u8>>> {
+    use anyhow::Context;
+
+    matches
+        .values_of("approval")
+        .into_iter()
+        .flatten()
+        .map(|path| std::fs::read(path).with_context(|| format!("Cannot read {}", path)))
+        .collect()
+}
+
+#[cfg(feature = "native")]
+pub(crate) fn command() -> clap::App<'static> {
+    use clap::{App, Arg};
+
+    App::new("approve")
+        .about("Approve a request to run an artifact that needs a second operator's approval")
+        .arg(
+            Arg::new("request")
+                .value_name("REQUEST")
+                .required(true)
+                .about("The approval request written by the sget that wants to run the artifact"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .value_name("FILE")
+                .takes_value(true)
+                .about("Where to write the approval [default: REQUEST with .approval instead of .request]"),
+        )
+        .arg(
+            Arg::new("key")
+                .short('k')
+                .long("key")
+                .value_name("KEY_FILE")
+                .takes_value(true)
+                .about("Private key generated by `sget keygen`, or keychain:NAME; signs keyless with Fulcio when omitted"),
+        )
+        .arg(
+            Arg::new("identity-token")
+                .long("identity-token")
+                .value_name("TOKEN")
+                .takes_value(true)
+                .about("OIDC identity token for keyless signing [env: SIGSTORE_ID_TOKEN; in GitHub Actions, the job's own token]"),
+        )
+        .arg(
+            Arg::new("yes")
+                .long("yes")
+                .short('y')
+                .takes_value(false)
+                .about("Approve without asking for confirmation"),
+        )
+}
+
+#[cfg(feature = "native")]
+pub(crate) async fn run(matches: &clap::ArgMatches) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use std::io::{BufRead, Write};
+
+    let path = matches
+        .value_of("request")
+        .ok_or_else(|| anyhow!("No approval request given"))?;
+    let raw = std::fs::read(path).with_context(|| format!("Cannot read {}", path))?;
+    let request: ApprovalRequest = serde_json::from_slice(&raw)
+        .with_context(|| format!("Invalid approval request {}", path))?;
+    if request.expires < Utc::now() {
+        return Err(anyhow!("The request expired at {}", request.expires));
+    }
+    println!("Source:       {}", request.source);
+    println!("SHA-256:      {}", request.sha256);
+    println!("Signed by:    {}", request.signer);
+    println!(
+        "Requested by: {} at {}",
+        request.requested_by, request.requested_at
+    );
+    println!("Expires:      {}", request.expires);
+    if !matches.is_present("yes") {
+        print!("Approve running it? [y/N] ");
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().lock().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Err(anyhow!("Not approved"));
+        }
+    }
+
+    let options = crate::sign::SignOptions {
+        key: matches.value_of("key").map(String::from),
+        identity_token: matches.value_of("identity-token").map(String::from),
+        ..crate::sign::SignOptions::default()
+    };
+    let (key, certificate) = crate::sign::signing_key(&options).await?;
+    let envelope = sign_request(&request, &key, certificate)?;
+    let output = match matches.value_of("output") {
+        Some(output) => output.to_string(),
+        None => match path.strip_suffix(".request") {
+            Some(stem) => format!("{}.approval", stem),
+            None => format!("{}.approval", path),
+        },
+    };
+    std::fs::write(&output, serde_json::to_vec(&envelope)?)
+        .with_context(|| format!("Cannot write {}", output))?;
+    println!("Approval written to {}", output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+
+    #[test]
+    fn verify_approvals() {
+        let approver = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let other = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let rule = ApprovalRule {
+            pattern: "https://example.com/prod/**".to_string(),
+            approvers: Vec::new(),
+            approver_keys: vec![approver.public_key().to_pem().expect("Cannot encode key")],
+        };
+        let rules = vec![&rule];
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let source = "https://example.com/prod/install.sh";
+        let digest = "ab".repeat(32);
+        let signer = Signer {
+            key_id: "cd".repeat(32),
+            identity: None,
+            issuer: None,
+            integrated_time: None,
+        };
+        let now = Utc::now();
+        let request = ApprovalRequest::new(source, &digest, &signer, "ops@build-1", now);
+        let approve = |key: &SigningKey, request: &ApprovalRequest| {
+            serde_json::to_vec(&sign_request(request, key, None).expect("Cannot sign"))
+                .expect("Cannot encode")
+        };
+        let verify = |raw: &[u8], signer: &Signer, now| {
+            verify_approval(raw, source, &digest, signer, &rules, &roots, now)
+        };
+
+        // Artifacts no rule applies to need no approval; the others do.
+        let all = std::slice::from_ref(&rule);
+        let dev = "https://example.com/dev/install.sh";
+        assert!(matches!(
+            check_approvals(&[], dev, &digest, &signer, all, &roots, now),
+            Ok(None)
+        ));
+        assert!(matches!(
+            check_approvals(&[], source, &digest, &signer, all, &roots, now),
+            Err(SgetError::Unapproved(_))
+        ));
+
+        let approval = approve(&approver, &request);
+        let verified = verify(&approval, &signer, now).expect("Not approved");
+        assert_eq!(
+            verified.key_id,
+            approver.public_key().key_id().expect("No key ID")
+        );
+
+        // Only the approver keys count, only for this artifact, and only
+        // until the request expires.
+        assert!(verify(&approve(&other, &request), &signer, now).is_err());
+        let mut elsewhere = request.clone();
+        elsewhere.sha256 = "ef".repeat(32);
+        assert!(verify(&approve(&approver, &elsewhere), &signer, now).is_err());
+        let later = now + Duration::hours(REQUEST_LIFETIME_HOURS + 1);
+        assert!(matches!(
+            verify(&approval, &signer, later),
+            Err(SgetError::Unapproved(_))
+        ));
+
+        // The signer of the artifact cannot approve it.
+        let own = Signer {
+            key_id: verified.key_id.clone(),
+            ..signer
+        };
+        assert!(verify(&approval, &own, now).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{env, fs};
+
+use crate::keys::SigningKey;
+use crate::provenance::{pae, Envelope, EnvelopeSignature, IN_TOTO_PAYLOAD_TYPE};
+use crate::sign::{self, SignOptions};
+use crate::utils;
+
+/// Predicate type of execution attestations.
+pub const EXECUTION_PREDICATE_TYPE: &str = "https://sigstore.dev/sget/execution/v0.1";
+
+const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v0.1";
+
+/// The predicate of an execution attestation: which script ran where, as
+/// whom and when. The script itself is the subject of the statement,
+/// identified by its SHA-256 digest.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Execution {
+    /// URL, path or OCI reference the script was fetched from.
+    pub source: String,
+    pub host: String,
+    /// The local user that ran the script.
+    pub user: String,
+    pub executed_at: DateTime<Utc>,
+    pub exit_code: Option<i32>,
+    pub sget_version: String,
+}
+
+impl Execution {
+    /// An execution of the script from `source` on this host, by the current
+    /// user, now.
+    pub fn new(source: &str, exit_code: Option<i32>) -> Self {
+        Execution {
+            source: source.to_string(),
+            host: host_name(),
+            user: user_name(),
+            executed_at: Utc::now(),
+            exit_code,
+            sget_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// The in-toto statement that the script with SHA-256 `digest` was
+    /// executed.
+    pub fn statement(&self, digest: &str) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&json!({
+            "_type": STATEMENT_TYPE,
+            "subject": [{ "name": self.source, "digest": { "sha256": digest } }],
+            "predicateType": EXECUTION_PREDICATE_TYPE,
+            "predicate": self,
+        }))?)
+    }
+}
+
+/// The current user on this host, as `user@host`.
+pub(crate) fn requester() -> String {
+    format!("{}@{}", user_name(), host_name())
+}
+
+fn host_name() -> String {
+    env::var("HOSTNAME")
+        .or_else(|_| env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn user_name() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// A signed execution attestation, and its Rekor entry if uploaded.
+pub struct Attestation {
+    pub envelope: Envelope,
+    pub entry: Option<crate::rekor::LogEntry>,
+}
+
+/// The DSSE envelope of the attestation that the script with SHA-256
+/// `digest` was executed, signed by `key`, with its Fulcio `certificate`
+/// chain for keyless signatures.
+pub fn sign_statement(
+    digest: &str,
+    execution: &Execution,
+    key: &SigningKey,
+    certificate: Option<String>,
+) -> Result<Envelope> {
+    let payload = execution.statement(digest)?;
+    let signature = key.sign(&pae(IN_TOTO_PAYLOAD_TYPE, &payload));
+    Ok(Envelope {
+        payload_type: IN_TOTO_PAYLOAD_TYPE.to_string(),
+        payload: base64::encode(&payload),
+        signatures: vec![EnvelopeSignature {
+            keyid: match &certificate {
+                Some(_) => String::new(),
+                None => key.public_key().key_id()?,
+            },
+            sig: base64::encode(&signature),
+            cert: certificate,
+        }],
+    })
+}
+
+/// Sign the attestation that the script with SHA-256 `digest` was executed,
+/// with the key of `options` or keyless, and upload the signature to Rekor
+/// if `upload`.
+pub async fn attest(
+    digest: &str,
+    execution: &Execution,
+    options: &SignOptions,
+    upload: bool,
+) -> Result<Attestation> {
+    let (key, certificate) = sign::signing_key(options).await?;
+    let envelope = sign_statement(digest, execution, &key, certificate.clone())?;
+    let entry = if upload {
+        let payload = base64::decode(&envelope.payload)?;
+        let signed = pae(IN_TOTO_PAYLOAD_TYPE, &payload);
+        let signature = base64::decode(&envelope.signatures[0].sig)?;
+        let digest = hex::encode(Sha256::digest(&signed));
+        Some(sign::upload(options, &key, certificate.as_deref(), &digest, &signature).await?)
+    } else {
+        None
+    };
+    Ok(Attestation { envelope, entry })
+}
+
+/// Append the envelope of `attestation` to `path` as a JSON line, the format
+/// attestations are fetched in, and write its Rekor bundle to `path.bundle`.
+pub fn write(path: &str, attestation: &Attestation) -> Result<()> {
+    use std::io::Write;
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Cannot open {}", path))?;
+    let mut line = serde_json::to_vec(&attestation.envelope)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    println!("Execution attestation written to {}", path);
+    if let Some(entry) = &attestation.entry {
+        let bundle_path = format!("{}.bundle", path);
+        fs::write(&bundle_path, serde_json::to_vec(&entry.to_bundle()?)?)?;
+        println!(
+            "Uploaded to Rekor at index {}, bundle written to {}",
+            entry.log_index, bundle_path
+        );
+    }
+    Ok(())
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("attest")
+        .about("Attest that a script was executed on this host, e.g. after running it with sget")
+        .arg(
+            Arg::new("file")
+                .value_name("FILE")
+                .required(true)
+                .about("The script that was executed"),
+        )
+        .arg(
+            Arg::new("source")
+                .long("source")
+                .value_name("SOURCE")
+                .takes_value(true)
+                .about("URL or OCI reference the script was fetched from [default: FILE]"),
+        )
+        .arg(
+            Arg::new("exit-code")
+                .long("exit-code")
+                .value_name("CODE")
+                .takes_value(true)
+                .about("Exit code of the execution"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .value_name("OUT_FILE")
+                .takes_value(true)
+                .about("Where to append the signed attestation [default: FILE.execution.intoto.jsonl]"),
+        )
+        .arg(
+            Arg::new("key")
+                .short('k')
+                .long("key")
+                .value_name("KEY_FILE")
+                .takes_value(true)
+                .about("Private key generated by `sget keygen`, or keychain:NAME; signs keyless with Fulcio when omitted"),
+        )
+        .arg(
+            Arg::new("identity-token")
+                .long("identity-token")
+                .value_name("TOKEN")
+                .takes_value(true)
+                .about("OIDC identity token for keyless signing [env: SIGSTORE_ID_TOKEN; in GitHub Actions, the job's own token]"),
+        )
+        .arg(
+            Arg::new("upload")
+                .long("upload")
+                .takes_value(false)
+                .about("Upload the attestation signature to Rekor"),
+        )
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    let file = matches
+        .value_of("file")
+        .ok_or_else(|| anyhow!("No script given"))?;
+    let exit_code = matches
+        .value_of("exit-code")
+        .map(|code| code.parse().context("Invalid exit code"))
+        .transpose()?;
+    let digest = utils::sha256_file(std::path::Path::new(file))
+        .with_context(|| format!("Cannot read {}", file))?;
+    let execution = Execution::new(matches.value_of("source").unwrap_or(file), exit_code);
+    let options = SignOptions {
+        key: matches.value_of("key").map(String::from),
+        identity_token: matches.value_of("identity-token").map(String::from),
+        ..SignOptions::default()
+    };
+    let attestation = attest(&digest, &execution, &options, matches.is_present("upload")).await?;
+    let output = matches
+        .value_of("output")
+        .map(String::from)
+        .unwrap_or_else(|| format!("{}.execution.intoto.jsonl", file));
+    write(&output, &attestation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::KeyAlgorithm;
+    use crate::keys::SigningKey;
+    use crate::provenance::open_envelope;
+    use crate::verify::TrustRoots;
+
+    #[test]
+    fn attest_execution() {
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let execution = Execution::new("ghcr.io/example/install:v1", Some(0));
+        let envelope = sign_statement("ab12", &execution, &key, None).expect("Cannot attest");
+
+        let roots = TrustRoots::builtin().expect("No built-in roots");
+        let (statement, identity) =
+            open_envelope(&envelope, Some(&key.public_key()), &roots).expect("Invalid attestation");
+        assert!(identity.is_none());
+        assert!(statement.is_about("ab12"));
+        assert_eq!(statement.predicate_type, EXECUTION_PREDICATE_TYPE);
+        let recorded: Execution =
+            serde_json::from_value(statement.predicate).expect("Invalid predicate");
+        assert_eq!(recorded, execution);
+
+        let other = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        assert!(open_envelope(&envelope, Some(&other.public_key()), &roots).is_err());
+    }
+}
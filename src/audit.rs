@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::error::SgetError;
+use crate::state;
+use crate::verify::Signer;
+
+/// What sget did with an artifact.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Fetch,
+    Verify,
+    Run,
+}
+
+/// Whether the artifact was accepted.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Allow,
+    Deny,
+}
+
+/// One line of the audit log.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub action: Action,
+    /// URL, path or OCI reference of the artifact.
+    pub source: String,
+    /// Hex encoded SHA-256 digest of the artifact.
+    pub digest: Option<String>,
+    /// Identity or key ID of the verified signer.
+    pub signer: Option<String>,
+    pub issuer: Option<String>,
+    /// Version of the policy the artifact was fetched under.
+    pub policy_version: Option<u64>,
+    pub decision: Decision,
+    /// Short failure classification, see `FailureReason::label`.
+    pub reason: Option<String>,
+    /// Full error message for denied artifacts.
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(action: Action, source: &str) -> Self {
+        AuditRecord {
+            timestamp: Utc::now(),
+            action,
+            source: source.to_string(),
+            digest: None,
+            signer: None,
+            issuer: None,
+            policy_version: None,
+            decision: Decision::Allow,
+            reason: None,
+            error: None,
+        }
+    }
+
+    /// A record of verifying `source`, allowed or denied depending on `outcome`.
+    pub fn verification(
+        action: Action,
+        source: &str,
+        digest: Option<String>,
+        outcome: Result<&Signer, &SgetError>,
+    ) -> Self {
+        let mut record = AuditRecord::new(action, source);
+        record.digest = digest;
+        match outcome {
+            Ok(signer) => {
+                record.signer = Some(signer.subject().to_string());
+                record.issuer = signer.issuer.clone();
+            }
+            Err(e) => {
+                record.decision = Decision::Deny;
+                record.reason = Some(
+                    e.reason()
+                        .map_or("other", |reason| reason.label())
+                        .to_string(),
+                );
+                record.error = Some(e.describe());
+            }
+        }
+        record
+    }
+}
+
+/// An append-only JSON Lines file of `AuditRecord`s.
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        AuditLog { path: path.into() }
+    }
+
+    /// The audit log named by `SGET_AUDIT_LOG`, the `audit_log` config setting,
+    /// or `audit.jsonl` in the state directory, in that order.
+    pub fn open(config: &Config) -> Result<Self> {
+        if let Some(path) = env::var_os("SGET_AUDIT_LOG") {
+            return Ok(Self::new(path));
+        }
+        if let Some(path) = &config.audit_log {
+            return Ok(Self::new(path));
+        }
+        Ok(Self::new(state::state_dir()?.join("audit.jsonl")))
+    }
+
+    pub fn record(&self, record: &AuditRecord) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        // A single append of the whole line keeps concurrent writers from
+        // interleaving records.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(&line))
+            .with_context(|| format!("Cannot write audit log {}", self.path.display()))
+    }
+
+    /// Like [`AuditLog::record`], for callers that should carry on if the log
+    /// cannot be written.
+    pub fn record_or_warn(&self, record: &AuditRecord) {
+        if let Err(e) = self.record(record) {
+            eprintln!("Warning: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_records() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let log = AuditLog::new(dir.path().join("logs").join("audit.jsonl"));
+        let signer = Signer {
+            key_id: "abcd".to_string(),
+            identity: Some("me@example.com".to_string()),
+            issuer: Some("https://accounts.example.com".to_string()),
+            integrated_time: None,
+        };
+        let allowed = AuditRecord::verification(
+            Action::Verify,
+            "https://example.com/a.sh",
+            Some("00".repeat(32)),
+            Ok(&signer),
+        );
+        let denied = AuditRecord::verification(
+            Action::Fetch,
+            "https://example.com/b.sh",
+            None,
+            Err(&SgetError::DigestMismatch {
+                expected: "00".to_string(),
+                actual: "11".to_string(),
+            }),
+        );
+        log.record(&allowed).expect("Cannot record");
+        log.record(&denied).expect("Cannot record");
+
+        let raw = fs::read_to_string(dir.path().join("logs").join("audit.jsonl"))
+            .expect("Cannot read log");
+        let records: Vec<AuditRecord> = raw
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Invalid record"))
+            .collect();
+        assert_eq!(records, vec![allowed, denied]);
+        assert_eq!(records[0].signer.as_deref(), Some("me@example.com"));
+        assert_eq!(records[1].decision, Decision::Deny);
+        assert_eq!(records[1].reason.as_deref(), Some("digest"));
+    }
+}
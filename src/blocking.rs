@@ -0,0 +1,102 @@
+use crate::error::Result;
+use std::path::Path;
+use std::process::ExitStatus;
+use tokio::runtime::{self, Runtime};
+
+use crate::approval::ApprovalRequest;
+use crate::client::{self, VerifiedArtifact, Verifier};
+use crate::fetch;
+use crate::manifest::{EntryResult, ManifestEntry};
+use crate::transport;
+
+fn runtime() -> Result<Runtime> {
+    Ok(runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?)
+}
+
+/// A blocking [`client::SgetClient`], for callers without a tokio runtime.
+///
+/// Like `reqwest::blocking`, it runs its own runtime and must not be used from
+/// async code; use the async client there instead. To cancel a blocked call
+/// from another thread, give the inner client a token with
+/// [`client::SgetClient::with_cancellation`].
+pub struct SgetClient {
+    inner: client::SgetClient,
+    runtime: Runtime,
+}
+
+impl SgetClient {
+    pub fn new(inner: client::SgetClient) -> Result<Self> {
+        Ok(SgetClient {
+            inner,
+            runtime: runtime()?,
+        })
+    }
+
+    pub fn verifier(&self) -> &Verifier {
+        self.inner.verifier()
+    }
+
+    /// See [`client::SgetClient::fetch`].
+    pub fn fetch(&self, entry: &ManifestEntry) -> Result<VerifiedArtifact> {
+        self.runtime.block_on(self.inner.fetch(entry))
+    }
+
+    /// See [`client::SgetClient::fetch_manifest`].
+    pub fn fetch_manifest(&self, path: &Path) -> Result<Vec<EntryResult>> {
+        self.runtime.block_on(self.inner.fetch_manifest(path))
+    }
+
+    /// See [`client::SgetClient::approval_request`].
+    pub fn approval_request(&self, artifact: &VerifiedArtifact) -> Option<ApprovalRequest> {
+        self.inner.approval_request(artifact)
+    }
+
+    /// See [`client::SgetClient::execute`].
+    pub fn execute(&self, artifact: &VerifiedArtifact, interactive: bool) -> Result<ExitStatus> {
+        self.inner.execute(artifact, interactive)
+    }
+}
+
+/// See [`fetch::fetch`].
+pub fn fetch(location: &str, base: &Path) -> Result<Vec<u8>> {
+    let transport = transport::default_transport();
+    runtime()?.block_on(fetch::fetch(transport.as_ref(), location, base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use std::fs;
+
+    #[test]
+    fn fetch_without_runtime() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        fs::write(dir.path().join("a.sh"), b"echo a").expect("Cannot write artifact");
+        fs::write(
+            dir.path().join("a.sh.sig"),
+            base64::encode(key.sign(b"echo a")),
+        )
+        .expect("Cannot write signature");
+        fs::write(
+            dir.path().join("sget.pub"),
+            key.public_key().to_pem().expect("Cannot encode key"),
+        )
+        .expect("Cannot write key");
+
+        assert_eq!(
+            fetch("a.sh", dir.path()).expect("Cannot fetch"),
+            b"echo a".to_vec()
+        );
+        let inner = client::SgetClient::new(Verifier::sigstore().expect("Cannot load roots"))
+            .with_base(dir.path());
+        let client = SgetClient::new(inner).expect("Cannot start runtime");
+        let mut entry = ManifestEntry::new("a.sh");
+        entry.key = Some("sget.pub".to_string());
+        let artifact = client.fetch(&entry).expect("Cannot verify");
+        assert_eq!(artifact.data(), b"echo a");
+    }
+}
@@ -0,0 +1,599 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
+
+use crate::keygen;
+use crate::keys::{self, KeyAlgorithm, PublicKey, SigningKey};
+use crate::pipeline::Pipeline;
+use crate::policy::{self, Policy, Signature};
+use crate::signature::SignatureVerifiers;
+use crate::timestamp::SignedTimestamp;
+use crate::trust::TrustStore;
+use crate::utils::from_now;
+use crate::verify::{self, TrustRoots};
+
+/// The state of a ceremony, kept in its directory.
+const STATE: &str = "ceremony.json";
+/// The `signed` section of the candidate root, exactly as it is signed.
+const CANDIDATE: &str = "root.candidate.json";
+/// Directory of collected signatures, one file per key ID.
+const SIGNATURES: &str = "signatures";
+
+/// A root key ceremony in progress. Key holders add their public keys, the
+/// candidate root is produced from them, and holders sign it, possibly on
+/// other machines, until the threshold is met.
+#[derive(Serialize, Deserialize)]
+pub struct Ceremony {
+    pub namespace: String,
+    pub version: NonZeroU64,
+    pub threshold: NonZeroU64,
+    pub expires: DateTime<Utc>,
+    /// Public keys by key ID.
+    pub keys: BTreeMap<String, KeyHolder>,
+}
+
+/// A key holder taking part in a ceremony.
+#[derive(Serialize, Deserialize)]
+pub struct KeyHolder {
+    pub name: String,
+    /// The PEM encoded public key.
+    pub public: String,
+}
+
+/// A ceremony directory.
+pub struct CeremonyDir {
+    dir: PathBuf,
+}
+
+impl CeremonyDir {
+    pub fn new(dir: &Path) -> Self {
+        CeremonyDir {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Start a ceremony in an empty or missing directory.
+    pub fn init(&self, ceremony: &Ceremony) -> Result<()> {
+        if self.dir.join(STATE).exists() {
+            return Err(anyhow!(
+                "A ceremony was already started in {}",
+                self.dir.display()
+            ));
+        }
+        fs::create_dir_all(self.dir.join(SIGNATURES))?;
+        self.save(ceremony)
+    }
+
+    pub fn load(&self) -> Result<Ceremony> {
+        let path = self.dir.join(STATE);
+        let raw = fs::read(&path).with_context(|| format!("Cannot read {}", path.display()))?;
+        serde_json::from_slice(&raw).with_context(|| format!("Invalid ceremony {}", path.display()))
+    }
+
+    fn save(&self, ceremony: &Ceremony) -> Result<()> {
+        fs::write(self.dir.join(STATE), serde_json::to_vec_pretty(ceremony)?)?;
+        Ok(())
+    }
+
+    /// The candidate root, if it was produced.
+    pub fn candidate(&self) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.dir.join(CANDIDATE)) {
+            Ok(raw) => Ok(Some(raw)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn require_candidate(&self) -> Result<Vec<u8>> {
+        self.candidate()?
+            .ok_or_else(|| anyhow!("No candidate root yet; run `sget policy ceremony candidate`"))
+    }
+
+    /// Add a key holder's public key. Keys can only be added before the
+    /// candidate root is produced.
+    pub fn add_key(&self, name: &str, key: &PublicKey) -> Result<String> {
+        if self.candidate()?.is_some() {
+            return Err(anyhow!(
+                "The candidate root was already produced; start a new ceremony to change its keys"
+            ));
+        }
+        let mut ceremony = self.load()?;
+        let keyid = key.key_id()?;
+        if ceremony.keys.contains_key(&keyid) {
+            return Err(anyhow!("Key {} was already added", keyid));
+        }
+        ceremony.keys.insert(
+            keyid.clone(),
+            KeyHolder {
+                name: name.to_string(),
+                public: key.to_pem()?,
+            },
+        );
+        self.save(&ceremony)?;
+        Ok(keyid)
+    }
+
+    /// Produce the candidate root from the keys added so far, all of which
+    /// form its root role. Returns the hex encoded SHA-256 digest of the
+    /// candidate, which holders should compare out of band before signing.
+    pub fn produce_candidate(&self) -> Result<String> {
+        if self.candidate()?.is_some() {
+            return Err(anyhow!("The candidate root was already produced"));
+        }
+        let ceremony = self.load()?;
+        if (ceremony.keys.len() as u64) < ceremony.threshold.get() {
+            return Err(anyhow!(
+                "The threshold is {} but only {} key(s) were added",
+                ceremony.threshold,
+                ceremony.keys.len()
+            ));
+        }
+        let mut keys = serde_json::Map::new();
+        for (keyid, holder) in &ceremony.keys {
+            let key = PublicKey::from_pem(&holder.public)?.to_policy_key()?;
+            let mut key = serde_json::to_value(key)?;
+            key["name"] = json!(holder.name);
+            keys.insert(keyid.clone(), key);
+        }
+        let keyids: Vec<&String> = ceremony.keys.keys().collect();
+        let signed = json!({
+            "consistent_snapshot": false,
+            "expires": ceremony.expires,
+            "keys": keys,
+            "namespace": ceremony.namespace,
+            "roles": { "root": { "keyids": keyids, "threshold": ceremony.threshold } },
+            "spec_version": policy::SPEC_VERSION,
+            "version": ceremony.version,
+        })
+        .to_string();
+        fs::write(self.dir.join(CANDIDATE), &signed)?;
+        Ok(hex::encode(Sha256::digest(signed.as_bytes())))
+    }
+
+    /// Check a signature over the candidate and keep it.
+    pub fn add_signature(&self, signature: Signature) -> Result<()> {
+        let candidate = self.require_candidate()?;
+        let ceremony = self.load()?;
+        let holder = ceremony
+            .keys
+            .get(&signature.keyid)
+            .ok_or_else(|| anyhow!("Key {} is not part of the ceremony", signature.keyid))?;
+        PublicKey::from_pem(&holder.public)?
+            .verify(&candidate, &base64::decode(&signature.sig)?)
+            .with_context(|| format!("Invalid signature by {}", holder.name))?;
+        fs::write(
+            self.signature_path(&signature.keyid),
+            serde_json::to_vec_pretty(&signature)?,
+        )?;
+        Ok(())
+    }
+
+    fn signature_path(&self, keyid: &str) -> PathBuf {
+        self.dir.join(SIGNATURES).join(format!("{}.json", keyid))
+    }
+
+    /// The signatures collected so far, by key ID.
+    pub fn signatures(&self) -> Result<BTreeMap<String, Signature>> {
+        let ceremony = self.load()?;
+        let mut signatures = BTreeMap::new();
+        for keyid in ceremony.keys.keys() {
+            if let Ok(raw) = fs::read(self.signature_path(keyid)) {
+                signatures.insert(keyid.clone(), serde_json::from_slice(&raw)?);
+            }
+        }
+        Ok(signatures)
+    }
+
+    /// Assemble the signed root from the candidate and collected signatures,
+    /// and verify it as any client would.
+    pub fn finish(&self, roots: &TrustRoots, now: DateTime<Utc>) -> Result<(Vec<u8>, Policy)> {
+        let candidate = String::from_utf8(self.require_candidate()?)?;
+        let signatures: Vec<Signature> = self.signatures()?.into_values().collect();
+        let root = format!(
+            "{{\"signatures\":{},\"signed\":{}}}",
+            serde_json::to_string(&signatures)?,
+            candidate
+        )
+        .into_bytes();
+        let policy = verify::verify_policy(
+            &root,
+            roots,
+            &SignatureVerifiers::default(),
+            &Pipeline::default(),
+            now,
+        )
+        .context("The assembled root does not verify")?;
+        Ok((root, policy))
+    }
+}
+
+/// Sign `candidate` with `key`.
+pub fn sign_candidate(candidate: &[u8], key: &SigningKey) -> Result<Signature> {
+    Ok(Signature {
+        keyid: key.public_key().key_id()?,
+        sig: base64::encode(key.sign(candidate)),
+        cert: String::new(),
+        chain: Vec::new(),
+        timestamp: None,
+    })
+}
+
+pub(crate) fn command() -> App<'static> {
+    let dir = || {
+        Arg::new("dir")
+            .about("Ceremony directory")
+            .required(true)
+            .index(1)
+    };
+    App::new("ceremony")
+        .about("Walk key holders through creating and signing a new root policy")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("init")
+                .about("Start a ceremony in a new directory")
+                .arg(dir())
+                .arg(
+                    Arg::new("namespace")
+                        .long("namespace")
+                        .value_name("NAMESPACE")
+                        .required(true)
+                        .about("Namespace of the root policy"),
+                )
+                .arg(
+                    Arg::new("threshold")
+                        .long("threshold")
+                        .value_name("N")
+                        .required(true)
+                        .about("Number of key holders who must sign the root"),
+                )
+                .arg(
+                    Arg::new("expires")
+                        .long("expires")
+                        .value_name("DURATION")
+                        .default_value("365d")
+                        .about("How long the root is valid for, e.g. 365d"),
+                )
+                .arg(
+                    Arg::new("version")
+                        .long("version")
+                        .value_name("VERSION")
+                        .default_value("1")
+                        .about("Version of the root; one more than the root it replaces"),
+                ),
+        )
+        .subcommand(
+            App::new("add-key")
+                .about("Add a key holder's public key, or generate a key pair for them")
+                .arg(dir())
+                .arg(
+                    Arg::new("holder")
+                        .long("holder")
+                        .value_name("NAME")
+                        .required(true)
+                        .about("Name of the key holder"),
+                )
+                .arg(
+                    Arg::new("public")
+                        .long("public")
+                        .value_name("FILE")
+                        .conflicts_with("generate")
+                        .required_unless_present("generate")
+                        .about("PEM encoded public key, e.g. exported from a hardware token"),
+                )
+                .arg(
+                    Arg::new("generate")
+                        .long("generate")
+                        .value_name("PREFIX")
+                        .about("Generate a key pair and write it to PREFIX.key and PREFIX.pub"),
+                )
+                .arg(
+                    Arg::new("algorithm")
+                        .long("algorithm")
+                        .value_name("ALGORITHM")
+                        .possible_values(["ecdsa-p256", "ed25519"])
+                        .default_value("ecdsa-p256")
+                        .requires("generate")
+                        .about("Algorithm of the generated key"),
+                ),
+        )
+        .subcommand(
+            App::new("candidate")
+                .about("Produce the candidate root from the added keys")
+                .arg(dir()),
+        )
+        .subcommand(
+            App::new("sign")
+                .about("Sign the candidate root with a private key from `sget keygen`")
+                .arg(dir())
+                .arg(
+                    Arg::new("key")
+                        .long("key")
+                        .value_name("KEY")
+                        .required(true)
+                        .about("Private key file, or keychain:NAME for one in the platform keychain; an encrypted one's passphrase is read from SGET_KEY_PASSPHRASE or prompted for"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .value_name("FILE")
+                        .about("Write a detached signature to FILE instead of adding it"),
+                ),
+        )
+        .subcommand(
+            App::new("add-signature")
+                .about("Add a detached signature over the candidate root")
+                .arg(dir())
+                .arg(
+                    Arg::new("signature")
+                        .about("Signature from `ceremony sign --output`, or with --keyid a raw DER or base64 signature, e.g. made with a hardware token")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::new("keyid")
+                        .long("keyid")
+                        .value_name("KEYID")
+                        .about("Key ID of a raw signature"),
+                )
+                .arg(
+                    Arg::new("timestamp")
+                        .long("timestamp")
+                        .value_name("FILE")
+                        .about("RFC 3161 time-stamp response or Rekor bundle for the signature, proving when it was made"),
+                ),
+        )
+        .subcommand(
+            App::new("status")
+                .about("Show the keys and signatures collected so far")
+                .arg(dir()),
+        )
+        .subcommand(
+            App::new("finish")
+                .about("Assemble the signed root and verify it")
+                .arg(dir())
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .value_name("FILE")
+                        .default_value("root.json")
+                        .about("Where to write the root policy"),
+                ),
+        )
+}
+
+/// Read a detached signature: the JSON written by `ceremony sign --output`,
+/// or a raw DER or base64 signature by `keyid`.
+fn read_signature(path: &str, keyid: Option<&str>) -> Result<Signature> {
+    let raw = fs::read(path).with_context(|| format!("Cannot read {}", path))?;
+    let keyid = match keyid {
+        Some(keyid) => keyid,
+        None => {
+            return serde_json::from_slice(&raw)
+                .context("Invalid signature file; pass --keyid for raw signatures")
+        }
+    };
+    let text = String::from_utf8_lossy(&raw);
+    let sig = match base64::decode(text.trim()) {
+        Ok(_) => text.trim().to_string(),
+        Err(_) => base64::encode(&raw),
+    };
+    Ok(Signature {
+        keyid: keyid.to_string(),
+        sig,
+        cert: String::new(),
+        chain: Vec::new(),
+        timestamp: None,
+    })
+}
+
+pub(crate) fn run(matches: &ArgMatches) -> Result<()> {
+    let (name, m) = matches
+        .subcommand()
+        .ok_or_else(|| anyhow!("No ceremony subcommand"))?;
+    let dir = CeremonyDir::new(Path::new(
+        m.value_of("dir")
+            .ok_or_else(|| anyhow!("No ceremony directory given"))?,
+    ));
+    let value = |name| {
+        m.value_of(name)
+            .ok_or_else(|| anyhow!("No --{} given", name))
+    };
+    match name {
+        "init" => {
+            let number = |name| -> Result<NonZeroU64> {
+                value(name)?
+                    .parse()
+                    .map_err(|_| anyhow!("--{} must be a positive number", name))
+            };
+            dir.init(&Ceremony {
+                namespace: value("namespace")?.to_string(),
+                version: number("version")?,
+                threshold: number("threshold")?,
+                expires: from_now(value("expires")?)?,
+                keys: BTreeMap::new(),
+            })?;
+            println!(
+                "Ceremony started. Next, each key holder runs `sget policy ceremony add-key`."
+            );
+        }
+        "add-key" => {
+            let key = match m.value_of("generate") {
+                Some(prefix) => {
+                    let algorithm: KeyAlgorithm = value("algorithm")?.parse()?;
+                    keygen::generate_key_pair(algorithm, prefix)?
+                }
+                None => {
+                    let path = value("public")?;
+                    let pem = fs::read_to_string(path)
+                        .with_context(|| format!("Cannot read {}", path))?;
+                    PublicKey::from_pem(&pem)?
+                }
+            };
+            let keyid = dir.add_key(value("holder")?, &key)?;
+            let ceremony = dir.load()?;
+            println!(
+                "Added key {} ({} key(s), threshold {})",
+                keyid,
+                ceremony.keys.len(),
+                ceremony.threshold
+            );
+        }
+        "candidate" => {
+            let digest = dir.produce_candidate()?;
+            println!("Candidate root written; its SHA-256 digest is {}", digest);
+            println!("Key holders should confirm this digest out of band before signing.");
+        }
+        "sign" => {
+            let candidate = dir.require_candidate()?;
+            let path = value("key")?;
+            println!(
+                "Signing candidate root with SHA-256 digest {}",
+                hex::encode(Sha256::digest(&candidate))
+            );
+            let key = keys::read_signing_key(path)?;
+            let signature = sign_candidate(&candidate, &key)?;
+            match m.value_of("output") {
+                Some(output) => {
+                    fs::write(output, serde_json::to_vec_pretty(&signature)?)?;
+                    println!("Detached signature written to {}", output);
+                }
+                None => {
+                    dir.add_signature(signature)?;
+                    println!("Signature added");
+                }
+            }
+        }
+        "add-signature" => {
+            let mut signature = read_signature(value("signature")?, m.value_of("keyid"))?;
+            if let Some(path) = m.value_of("timestamp") {
+                let raw = fs::read(path).with_context(|| format!("Cannot read {}", path))?;
+                signature.timestamp = Some(SignedTimestamp::from_bytes(&raw)?);
+            }
+            dir.add_signature(signature)?;
+            println!("Signature added");
+        }
+        "status" => {
+            let ceremony = dir.load()?;
+            let signatures = dir.signatures()?;
+            println!(
+                "Namespace {}, version {}, expires {}",
+                ceremony.namespace, ceremony.version, ceremony.expires
+            );
+            match dir.candidate()? {
+                Some(candidate) => println!(
+                    "Candidate root SHA-256 digest {}",
+                    hex::encode(Sha256::digest(&candidate))
+                ),
+                None => println!("No candidate root yet"),
+            }
+            for (keyid, holder) in &ceremony.keys {
+                let signed = match signatures.get(keyid) {
+                    Some(signature) if signature.timestamp.is_some() => "signed, timestamped",
+                    Some(_) => "signed",
+                    None => "not signed",
+                };
+                println!("{}\t{}\t{}", holder.name, keyid, signed);
+            }
+            println!(
+                "{} of {} required signatures",
+                signatures.len(),
+                ceremony.threshold
+            );
+        }
+        "finish" => {
+            let roots = TrustRoots::load(&TrustStore::open()?)?;
+            let (root, policy) = dir.finish(&roots, Utc::now())?;
+            let output = value("output")?;
+            fs::write(output, root)?;
+            println!(
+                "Root policy version {} for {} written to {}",
+                policy.signed.version, policy.signed.namespace, output
+            );
+        }
+        _ => return Err(anyhow!("Unknown ceremony subcommand")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_ceremony() {
+        let tmp = tempfile::tempdir().expect("Cannot create temp dir");
+        let dir = CeremonyDir::new(&tmp.path().join("ceremony"));
+        dir.init(&Ceremony {
+            namespace: "example.com/scripts".to_string(),
+            version: NonZeroU64::new(1).expect("Zero version"),
+            threshold: NonZeroU64::new(2).expect("Zero threshold"),
+            expires: "2030-01-01T00:00:00Z".parse().expect("Invalid date"),
+            keys: BTreeMap::new(),
+        })
+        .expect("Cannot start ceremony");
+        let keys: Vec<SigningKey> = [
+            KeyAlgorithm::EcdsaP256,
+            KeyAlgorithm::EcdsaP256,
+            KeyAlgorithm::EcdsaP256,
+        ]
+        .iter()
+        .map(|algorithm| SigningKey::generate(*algorithm).expect("Cannot generate key"))
+        .collect();
+
+        dir.add_key("alice", &keys[0].public_key())
+            .expect("Cannot add key");
+        // The candidate needs at least as many keys as the threshold.
+        assert!(dir.produce_candidate().is_err());
+        dir.add_key("bob", &keys[1].public_key())
+            .expect("Cannot add key");
+        assert!(dir.add_key("bob", &keys[1].public_key()).is_err());
+        dir.add_key("carol", &keys[2].public_key())
+            .expect("Cannot add key");
+        dir.produce_candidate().expect("Cannot produce candidate");
+        let candidate = dir
+            .candidate()
+            .expect("Cannot read candidate")
+            .expect("No candidate");
+
+        // Keys are fixed once the candidate exists, and only signatures by
+        // the ceremony's keys over the candidate are accepted.
+        let stranger = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        assert!(dir.add_key("mallory", &stranger.public_key()).is_err());
+        assert!(dir
+            .add_signature(sign_candidate(&candidate, &stranger).expect("Cannot sign"))
+            .is_err());
+        let forged = sign_candidate(b"something else", &keys[0]).expect("Cannot sign");
+        assert!(dir.add_signature(forged).is_err());
+
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let now = "2025-01-01T00:00:00Z".parse().expect("Invalid date");
+        dir.add_signature(sign_candidate(&candidate, &keys[0]).expect("Cannot sign"))
+            .expect("Cannot add signature");
+        assert!(dir.finish(&roots, now).is_err());
+
+        // A detached raw signature, as made with a hardware token.
+        let detached = tmp.path().join("bob.sig");
+        fs::write(&detached, keys[1].sign(&candidate)).expect("Cannot write signature");
+        let signature = read_signature(
+            detached.to_str().expect("Invalid path"),
+            Some(&keys[1].public_key().key_id().expect("No key id")),
+        )
+        .expect("Cannot read signature");
+        dir.add_signature(signature).expect("Cannot add signature");
+
+        let (_, policy) = dir.finish(&roots, now).expect("Cannot finish ceremony");
+        assert_eq!(policy.signed.keys.len(), 3);
+        assert_eq!(
+            policy.key_label(&keys[0].public_key().key_id().expect("No key id")),
+            "'alice'"
+        );
+        assert_eq!(policy.signed.namespace, "example.com/scripts");
+    }
+}
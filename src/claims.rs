@@ -0,0 +1,322 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::error::{Result, SgetError};
+use crate::verify_core::{parse_certificate, pem_certificates, BlobSignature, Signer};
+
+/// Fulcio certificate extensions exposed to claim policies, by claim name.
+const FULCIO_CLAIMS: &[(&str, &str)] = &[
+    ("1.3.6.1.4.1.57264.1.1", "issuer"),
+    ("1.3.6.1.4.1.57264.1.2", "github_workflow_trigger"),
+    ("1.3.6.1.4.1.57264.1.3", "github_workflow_sha"),
+    ("1.3.6.1.4.1.57264.1.4", "github_workflow_name"),
+    ("1.3.6.1.4.1.57264.1.5", "github_workflow_repository"),
+    ("1.3.6.1.4.1.57264.1.6", "github_workflow_ref"),
+];
+
+/// Rego query evaluated when a manifest entry does not name one.
+pub const DEFAULT_QUERY: &str = "data.sget.allow";
+
+/// A Rego or CUE policy a manifest entry's signature must satisfy, for checks
+/// that pinning an identity cannot express, such as "the workflow ref must
+/// match `refs/tags/*` and the repository must be `acme/infra`". The policy
+/// is evaluated against a [`ClaimsInput`] with the `opa` or `cue` command,
+/// which must be installed.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ClaimRequirements {
+    /// URL or path of the policy; its extension, `.rego` or `.cue`, selects
+    /// the language.
+    pub policy: String,
+    /// The Rego query that must evaluate to `true` [default: data.sget.allow].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+}
+
+/// The language of a claim policy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClaimLanguage {
+    Rego,
+    Cue,
+}
+
+impl ClaimRequirements {
+    pub fn language(&self) -> Result<ClaimLanguage> {
+        let path = self.policy.split(['?', '#']).next().unwrap_or_default();
+        if path.ends_with(".rego") {
+            Ok(ClaimLanguage::Rego)
+        } else if path.ends_with(".cue") {
+            Ok(ClaimLanguage::Cue)
+        } else {
+            Err(SgetError::InvalidEntry(format!(
+                "Claim policy {} is neither .rego nor .cue",
+                self.policy
+            )))
+        }
+    }
+}
+
+/// What a claim policy is evaluated against: the artifact, its verified
+/// signer, the claims of the signing certificate and the Rekor entry.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ClaimsInput {
+    pub artifact: ArtifactClaims,
+    pub signer: SignerClaims,
+    /// Certificate claims by name: `subject`, `issuer`, and the GitHub
+    /// workflow claims Fulcio records, such as `github_workflow_ref`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rekor: Option<RekorClaims>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ArtifactClaims {
+    pub url: String,
+    /// Hex encoded SHA-256 digest of the artifact.
+    pub sha256: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SignerClaims {
+    pub key_id: String,
+    pub identity: Option<String>,
+    pub issuer: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RekorClaims {
+    pub integrated_time: i64,
+    pub log_index: i64,
+    pub log_id: String,
+}
+
+impl ClaimsInput {
+    /// The input for the artifact at `url` with SHA-256 `digest`, signed by
+    /// `signer` with `signature`.
+    pub fn new(
+        url: &str,
+        digest: &str,
+        signer: &Signer,
+        signature: Option<&BlobSignature>,
+    ) -> Result<Self> {
+        let certificate = signature
+            .and_then(|sig| sig.certificate.as_ref())
+            .map(|chain| {
+                let leaf = pem_certificates(chain.as_bytes())?
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| {
+                        SgetError::InvalidMaterial(anyhow!("Empty certificate chain"))
+                    })?;
+                certificate_claims(&leaf)
+            })
+            .transpose()?;
+        Ok(ClaimsInput {
+            artifact: ArtifactClaims {
+                url: url.to_string(),
+                sha256: digest.to_string(),
+            },
+            signer: SignerClaims {
+                key_id: signer.key_id.clone(),
+                identity: signer.identity.clone(),
+                issuer: signer.issuer.clone(),
+            },
+            certificate,
+            rekor: signature
+                .and_then(|sig| sig.bundle.as_ref())
+                .map(|bundle| RekorClaims {
+                    integrated_time: bundle.payload.integrated_time,
+                    log_index: bundle.payload.log_index,
+                    log_id: bundle.payload.log_id.clone(),
+                }),
+        })
+    }
+}
+
+/// The claims of a DER encoded Fulcio certificate, by name.
+pub fn certificate_claims(der: &[u8]) -> Result<BTreeMap<String, String>> {
+    let cert = parse_certificate(der)?;
+    let mut claims = BTreeMap::new();
+    if let Ok((subject, _)) = crate::verify_core::certificate_identity(&cert) {
+        claims.insert("subject".to_string(), subject);
+    }
+    for ext in cert.extensions() {
+        let oid = ext.oid.to_id_string();
+        if let Some((_, name)) = FULCIO_CLAIMS.iter().find(|(id, _)| *id == oid) {
+            claims.insert(
+                name.to_string(),
+                String::from_utf8_lossy(ext.value).into_owned(),
+            );
+        }
+    }
+    Ok(claims)
+}
+
+/// Evaluate the claim policy `policy` of `requirements` against `input`.
+#[cfg(feature = "native")]
+pub fn evaluate(
+    requirements: &ClaimRequirements,
+    policy: &[u8],
+    input: &ClaimsInput,
+) -> Result<()> {
+    match requirements.language()? {
+        ClaimLanguage::Rego => evaluate_rego(
+            "opa",
+            policy,
+            requirements.query.as_deref().unwrap_or(DEFAULT_QUERY),
+            input,
+        ),
+        ClaimLanguage::Cue => evaluate_cue("cue", policy, input),
+    }
+}
+
+/// Write the policy and input to a temporary directory for `program`.
+#[cfg(feature = "native")]
+fn stage_files(
+    policy: &[u8],
+    extension: &str,
+    input: &ClaimsInput,
+) -> anyhow::Result<(tempfile::TempDir, std::path::PathBuf, std::path::PathBuf)> {
+    let dir = tempfile::tempdir()?;
+    let policy_path = dir.path().join(format!("policy.{}", extension));
+    let input_path = dir.path().join("input.json");
+    std::fs::write(&policy_path, policy)?;
+    std::fs::write(&input_path, serde_json::to_vec(input)?)?;
+    Ok((dir, policy_path, input_path))
+}
+
+#[cfg(feature = "native")]
+fn run(command: &mut std::process::Command, program: &str) -> Result<std::process::Output> {
+    command.output().map_err(|e| {
+        SgetError::ClaimPolicy(format!(
+            "Cannot run {}, which claim policies need: {}",
+            program, e
+        ))
+    })
+}
+
+/// Evaluate `query` with `opa eval`; it must be `true`.
+#[cfg(feature = "native")]
+fn evaluate_rego(program: &str, policy: &[u8], query: &str, input: &ClaimsInput) -> Result<()> {
+    let (_dir, policy_path, input_path) = stage_files(policy, "rego", input)?;
+    let output = run(
+        std::process::Command::new(program)
+            .args(["eval", "--format", "raw", "--data"])
+            .arg(&policy_path)
+            .arg("--input")
+            .arg(&input_path)
+            .arg(query),
+        program,
+    )?;
+    let result = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        return Err(SgetError::ClaimPolicy(format!(
+            "Cannot evaluate {}: {}",
+            query,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    if result.trim() != "true" {
+        return Err(SgetError::ClaimPolicy(format!(
+            "{} is {}",
+            query,
+            match result.trim() {
+                "" => "undefined",
+                other => other,
+            }
+        )));
+    }
+    Ok(())
+}
+
+/// Validate the input against the policy's constraints with `cue vet`.
+#[cfg(feature = "native")]
+fn evaluate_cue(program: &str, policy: &[u8], input: &ClaimsInput) -> Result<()> {
+    let (_dir, policy_path, input_path) = stage_files(policy, "cue", input)?;
+    let output = run(
+        std::process::Command::new(program)
+            .arg("vet")
+            .arg(&policy_path)
+            .arg(&input_path),
+        program,
+    )?;
+    if !output.status.success() {
+        return Err(SgetError::ClaimPolicy(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(git_ref: &str) -> ClaimsInput {
+        let mut certificate = BTreeMap::new();
+        certificate.insert("github_workflow_ref".to_string(), git_ref.to_string());
+        ClaimsInput {
+            artifact: ArtifactClaims {
+                url: "https://example.com/install.sh".to_string(),
+                sha256: "00".to_string(),
+            },
+            signer: SignerClaims {
+                key_id: "abcd".to_string(),
+                identity: None,
+                issuer: None,
+            },
+            certificate: Some(certificate),
+            rekor: None,
+        }
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "native"))]
+    fn evaluate_with_opa() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let requirements = ClaimRequirements {
+            policy: "https://example.com/claims.rego".to_string(),
+            query: None,
+        };
+        assert_eq!(
+            requirements.language().expect("No language"),
+            ClaimLanguage::Rego
+        );
+        assert!(ClaimRequirements {
+            policy: "claims.json".to_string(),
+            query: None,
+        }
+        .language()
+        .is_err());
+
+        // Stands in for opa: allows inputs for tags, and checks the query.
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let opa = dir.path().join("opa");
+        std::fs::write(
+            &opa,
+            "#!/bin/sh\n[ \"$8\" = data.sget.allow ] || exit 2\ngrep -q refs/tags/ \"$7\" && echo true || echo false\n",
+        )
+        .expect("Cannot write opa");
+        std::fs::set_permissions(&opa, std::fs::Permissions::from_mode(0o755))
+            .expect("Cannot make opa executable");
+        let opa = opa.to_str().expect("Invalid path");
+
+        let policy = b"package sget\nallow { startswith(input.certificate.github_workflow_ref, \"refs/tags/\") }\n";
+        evaluate_rego(opa, policy, DEFAULT_QUERY, &input("refs/tags/v1.0"))
+            .expect("Policy not satisfied");
+        assert!(matches!(
+            evaluate_rego(opa, policy, DEFAULT_QUERY, &input("refs/heads/main")),
+            Err(SgetError::ClaimPolicy(_))
+        ));
+        assert!(evaluate_rego(opa, policy, "data.other", &input("refs/tags/v1.0")).is_err());
+        assert!(evaluate_rego(
+            "/nonexistent/opa",
+            policy,
+            DEFAULT_QUERY,
+            &input("refs/tags/v1")
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,737 @@
+//
+// Copyright 2021 The Sigstore Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+use clap::{App, Arg, ArgMatches};
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitStatus;
+
+use crate::audit::{self, Action, AuditRecord};
+use crate::fetch::OciSource;
+use crate::notation::{self, NotationTrust};
+use crate::pipeline::Pipeline;
+use crate::sigstore_env::SigstoreEnv;
+use crate::system_policy::SystemPolicy;
+use crate::transport::{IpFamily, SourceHeaders};
+use crate::trust::TrustStore;
+use crate::verify::{Signer, TrustRoots};
+use crate::{
+    approval, attest, config, delta, doctor, execution_log, expiry, explain, fetch, fips, inspect,
+    keychain, keygen, mirror, policies, profile, runner, sbom, script_bundle, selfupdate, serve,
+    sign, transport, trust, utils, verify, version, watch,
+};
+
+async fn pull(reference: OciSource, file_name: &str) -> Result<()> {
+    let transport = transport::default_transport();
+    let image = fetch::pull_oci(transport.as_ref(), &reference).await?;
+    let cwd = env::current_dir()?;
+    File::create(cwd.join(file_name))?.write_all(&image[..])?;
+    println!("Success! Pulled the script!");
+    Ok(())
+}
+
+/// Run the script pulled to `path` from `source`, signed by `signer` if its
+/// signature was verified, through the same approval checks and logs as every
+/// other run. Returns its exit status and hex encoded SHA-256 digest.
+fn execute_pulled(
+    path: &Path,
+    source: &str,
+    signer: Option<&Signer>,
+    approvals: &[Vec<u8>],
+    roots: &TrustRoots,
+    records: &runner::Records,
+    interactive: bool,
+) -> Result<(ExitStatus, String)> {
+    let path = env::current_dir()?.join(path);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700))?;
+    }
+    let digest = utils::sha256_file(&path)?;
+    let script = runner::Script {
+        source,
+        digest: &digest,
+        signer,
+    };
+    let (status, approver) =
+        runner::run(&script, &path, None, interactive, approvals, roots, records)?;
+    if let Some(approver) = approver {
+        println!("Approved by {}", approver.subject());
+    }
+    Ok((status, digest))
+}
+
+/// Verify the notation signatures of `reference` against the notation trust
+/// policy, and return a reference to the verified manifest by digest and its
+/// signer.
+async fn verify_notation(reference: &OciSource) -> Result<(OciSource, Signer)> {
+    let transport = transport::default_transport();
+    let digest = fetch::resolve_oci(transport.as_ref(), reference).await?;
+    let envelopes = fetch::pull_notation_signatures(transport.as_ref(), reference, &digest).await?;
+    let mut trust = NotationTrust::load(&NotationTrust::config_dir()?)?;
+    trust.algorithms = config::Config::load()?.algorithms;
+    let repository = reference.repository();
+    let signer = notation::verify_notation(
+        &envelopes,
+        &digest,
+        &repository,
+        &trust,
+        &Pipeline::default(),
+        chrono::Utc::now(),
+    )?;
+    SystemPolicy::load()?.check(&signer)?;
+    println!("Verified notation signature by {}", signer.subject());
+    Ok((OciSource::in_repository(&repository, &digest)?, signer))
+}
+
+/// Fetch the SBOM attestations of the pulled script at `outfile`, verify them
+/// against the trust store, and save the SBOM.
+async fn save_sbom(matches: &ArgMatches, repository: &str, outfile: &str) -> Result<()> {
+    let digest = utils::sha256_file(Path::new(outfile))?;
+    let transport = transport::default_transport();
+    let raw = match matches.value_of("sbom") {
+        Some(location) => fetch::fetch(transport.as_ref(), location, Path::new("")).await?,
+        None => fetch::pull_oci_attestations(transport.as_ref(), repository, &digest).await?,
+    };
+    let roots = TrustRoots::load(&TrustStore::open()?)?;
+    let sbom = sbom::verify_sbom(&raw, &digest, None, &roots, None, &Pipeline::default())?;
+    let out = matches
+        .value_of("sbom-out")
+        .map(String::from)
+        .unwrap_or_else(|| format!("{}.{}", outfile, sbom.format.extension()));
+    std::fs::write(&out, &sbom.document)?;
+    println!("SBOM written to {}", out);
+    Ok(())
+}
+
+/// Sign an attestation that the script from `source` with SHA-256 `digest`
+/// was executed, and append it to `path`.
+async fn attest_execution(
+    matches: &ArgMatches,
+    path: &str,
+    source: &str,
+    digest: &str,
+    exit_code: Option<i32>,
+) -> Result<()> {
+    let options = sign::SignOptions {
+        key: matches.value_of("attest-key").map(String::from),
+        ..sign::SignOptions::default()
+    };
+    let execution = attest::Execution::new(source, exit_code);
+    let attestation = attest::attest(
+        digest,
+        &execution,
+        &options,
+        matches.is_present("attest-upload"),
+    )
+    .await?;
+    attest::write(path, &attestation)
+}
+
+/// Arguments of subcommands that name what is fetched.
+const LOCATION_ARGS: [&str; 7] = [
+    "file",
+    "input",
+    "bundle",
+    "policy",
+    "signature",
+    "certificate",
+    "key",
+];
+
+/// The origins, `<scheme>://<authority>/`, of the remote artifacts named on
+/// the command line: http(s) `locations`, and the registry of `reference`.
+fn artifact_origins(locations: &[&str], reference: Option<&str>) -> Vec<String> {
+    let mut origins: Vec<String> = locations
+        .iter()
+        .filter(|location| fetch::is_remote(location))
+        .filter_map(|location| location.parse::<http::Uri>().ok())
+        .filter_map(|uri| Some(format!("{}://{}/", uri.scheme_str()?, uri.authority()?)))
+        .collect();
+    origins.extend(
+        reference
+            .and_then(|reference| reference.parse::<OciSource>().ok())
+            .and_then(|source| source.origin()),
+    );
+    origins.sort();
+    origins.dedup();
+    origins
+}
+
+/// `--header` values, sent only to the origins of the artifacts named on the
+/// command line, never to Fulcio, Rekor or other hosts.
+fn header_sources(headers: &[(String, String)], origins: &[String]) -> Vec<SourceHeaders> {
+    origins
+        .iter()
+        .map(|origin| SourceHeaders {
+            url: Some(origin.clone()),
+            headers: headers.iter().cloned().collect(),
+        })
+        .collect()
+}
+
+/// Set up the proxy, retry policy and request headers of the shared
+/// transport from the configuration and `--header` flags.
+fn configure_transport(matches: &ArgMatches) -> Result<()> {
+    let config = config::Config::load()?;
+    let keychain = keychain::Keychain;
+    let mut proxy = config.proxy.with_env();
+    if let Some(password) = &proxy.password {
+        proxy.password = Some(keychain::resolve(&keychain, password)?.to_string());
+    }
+    transport::use_proxy(&proxy)?;
+    transport::use_retry(&config.retry)?;
+    let mut sources = config.sources;
+    for source in &mut sources {
+        for value in source.headers.values_mut() {
+            *value = keychain::resolve(&keychain, value)?.to_string();
+        }
+    }
+    if let Some(headers) = matches.values_of("header") {
+        let headers = headers
+            .map(SourceHeaders::parse_header)
+            .collect::<Result<Vec<_>>>()?;
+        let locations: Vec<&str> = LOCATION_ARGS
+            .iter()
+            .filter_map(|name| matches.value_of(name))
+            .collect();
+        let origins = artifact_origins(&locations, matches.value_of("oci-registry"));
+        if origins.is_empty() {
+            eprintln!("Warning: --header is only sent to the artifact's server, and no remote artifact was given");
+        }
+        sources.extend(header_sources(&headers, &origins));
+    }
+    transport::use_headers(&sources)
+}
+
+async fn run_bundle(matches: &ArgMatches) -> Result<()> {
+    let fetched = script_bundle::fetch_to_workdir(matches).await?;
+    println!(
+        "Verified bundle of {} files signed by {}",
+        fetched.bundle.files.len(),
+        fetched.signer.subject()
+    );
+    if matches.is_present("noexec") {
+        println!("Files written to {}", fetched.workdir.display());
+        return Ok(());
+    }
+    if let Some(approver) = script_bundle::execute(matches, &fetched)? {
+        println!("Approved by {}", approver.subject());
+    }
+    Ok(())
+}
+
+async fn run_subcommand(name: &str, matches: &ArgMatches) -> Result<()> {
+    match name {
+        "approve" => approval::run(matches).await,
+        "attest" => attest::run(matches).await,
+        "delta" => {
+            let delta = delta::run(matches)?;
+            println!(
+                "Delta of {} bytes ({} for the new version) written to {}",
+                delta.size,
+                delta.target_size,
+                delta.path.display()
+            );
+            Ok(())
+        }
+        "doctor" => doctor::run(matches).await,
+        "explain" => explain::run(matches).await,
+        "inspect" => inspect::run(matches).await,
+        "keychain" => keychain::run(matches),
+        "keygen" => keygen::run(matches),
+        "log" => {
+            let head = execution_log::run(matches)?;
+            println!("Execution log intact: {} entries", head.entries);
+            println!("Head: {}", head.hash);
+            Ok(())
+        }
+        "mirror" => mirror::run(matches).await,
+        "sign" => sign::run(matches).await,
+        "policy" => policies::run(matches).await,
+        "run-bundle" => run_bundle(matches).await,
+        "trust" => trust::run(matches),
+        "self-update" => selfupdate::run(matches).await,
+        "serve" => serve::run(matches).await,
+        "verify" => verify::run(matches).await,
+        "verify-blob" => verify::run_blob(matches).await,
+        "version" => version::run(matches),
+        "watch" => watch::run(matches).await,
+        other => Err(anyhow::anyhow!("Unknown subcommand: {}", other)),
+    }
+}
+
+/// Run the sget command line.
+pub async fn main() {
+    let matches = App::new("sget")
+        .version("0.1")
+        .author("Sigstore Developers")
+        .about("Secure script retrieval and execution")
+        .license("Apache-2.0")
+        .arg(
+            Arg::new("oci-registry")
+                .about("OCI registry namespace, oci-layout:<DIR>[#<TAG>] for a local OCI image layout, or containers-storage:<IMAGE> for an artifact podman pulled")
+                .index(1),
+        )
+        .arg(
+            Arg::new("noexec")
+                .short('n')
+                .long("noexec")
+                .takes_value(false)
+                .requires("oci-registry")
+                .about("Do not execute script"),
+        )
+        .arg(
+            Arg::new("outfile")
+                .short('f')
+                .long("outfile")
+                .value_name("OUT_FILE")
+                .requires("oci-registry")
+                .about("Save script to file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("interactive")
+                .short('i')
+                .long("interactive")
+                .takes_value(false)
+                .conflicts_with("noexec")
+                .about("Displays executing script's stdout to console"),
+        )
+        .arg(approval::arg().conflicts_with("noexec"))
+        .arg(
+            Arg::new("fips")
+                .long("fips")
+                .takes_value(false)
+                .global(true)
+                .about("Only use FIPS-approved algorithms, refusing anything else"),
+        )
+        .arg(
+            Arg::new("offline")
+                .long("offline")
+                .takes_value(false)
+                .global(true)
+                .about("Never access the network; everything must be local, and anything remote is an error"),
+        )
+        .arg(
+            Arg::new("strict-expiry-warnings")
+                .long("strict-expiry-warnings")
+                .takes_value(false)
+                .global(true)
+                .about("Fail when a policy or certificate expires within the configured warning window"),
+        )
+        .arg(
+            Arg::new("header")
+                .long("header")
+                .short('H')
+                .value_name("NAME: VALUE")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .global(true)
+                .about("Send this header to the server of the artifact, e.g. 'Authorization: Bearer TOKEN'; prefer per-source headers in the configuration"),
+        )
+        .arg(
+            Arg::new("sigstore-env")
+                .long("sigstore-env")
+                .value_name("ENV")
+                .takes_value(true)
+                .possible_values(["production", "staging"])
+                .global(true)
+                .about("Sign with the Fulcio and Rekor of this sigstore environment, and verify against its trust store [default: production]"),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("NAME")
+                .takes_value(true)
+                .global(true)
+                .about("Use the policy sources, endpoints, identity constraints and state directory of this profile from the configuration [env: SGET_PROFILE]"),
+        )
+        .arg(
+            Arg::new("http1")
+                .long("http1")
+                .takes_value(false)
+                .global(true)
+                .about("Only use HTTP/1.1, for proxies and middleboxes that break HTTP/2"),
+        )
+        .arg(
+            Arg::new("ipv4")
+                .long("ipv4")
+                .short('4')
+                .takes_value(false)
+                .global(true)
+                .conflicts_with("ipv6")
+                .about("Only connect over IPv4, where IPv6 is broken"),
+        )
+        .arg(
+            Arg::new("ipv6")
+                .long("ipv6")
+                .short('6')
+                .takes_value(false)
+                .global(true)
+                .about("Only connect over IPv6, where IPv4 is broken"),
+        )
+        .arg(
+            Arg::new("verify-notation")
+                .long("verify-notation")
+                .takes_value(false)
+                .requires("oci-registry")
+                .about("Require a notation signature trusted by the notation trust policy [env: NOTATION_CONFIG]"),
+        )
+        .arg(
+            Arg::new("require-sbom")
+                .long("require-sbom")
+                .takes_value(false)
+                .requires("outfile")
+                .about(
+                    "Require a keyless signed SPDX or CycloneDX SBOM attestation for the script",
+                ),
+        )
+        .arg(
+            Arg::new("sbom")
+                .long("sbom")
+                .value_name("LOCATION")
+                .takes_value(true)
+                .requires("require-sbom")
+                .about(
+                    "URL or path of the SBOM attestations [default: the script's OCI attestations]",
+                ),
+        )
+        .arg(
+            Arg::new("sbom-out")
+                .long("sbom-out")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("require-sbom")
+                .about("Save the SBOM to FILE [default: next to the script]"),
+        )
+        .arg(
+            Arg::new("attest")
+                .long("attest")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("outfile")
+                .conflicts_with("noexec")
+                .about("After the script succeeds, append a signed in-toto attestation of its execution to FILE"),
+        )
+        .arg(
+            Arg::new("attest-key")
+                .long("attest-key")
+                .value_name("KEY_FILE")
+                .takes_value(true)
+                .requires("attest")
+                .about("Private key to sign the execution attestation with [default: keyless, with SIGSTORE_ID_TOKEN or, in GitHub Actions, the job's own token]"),
+        )
+        .arg(
+            Arg::new("attest-upload")
+                .long("attest-upload")
+                .takes_value(false)
+                .requires("attest")
+                .about("Upload the execution attestation signature to Rekor"),
+        )
+        .subcommand(approval::command())
+        .subcommand(attest::command())
+        .subcommand(delta::command())
+        .subcommand(doctor::command())
+        .subcommand(explain::command())
+        .subcommand(inspect::command())
+        .subcommand(keychain::command())
+        .subcommand(keygen::command())
+        .subcommand(execution_log::command())
+        .subcommand(mirror::command())
+        .subcommand(sign::command())
+        .subcommand(policies::command())
+        .subcommand(script_bundle::command())
+        .subcommand(trust::command())
+        .subcommand(selfupdate::command())
+        .subcommand(serve::command())
+        .subcommand(verify::command())
+        .subcommand(verify::blob_command())
+        .subcommand(version::command())
+        .subcommand(watch::command())
+        .get_matches();
+
+    // Selected first, so that flags override the profile's settings.
+    let profile = matches
+        .subcommand()
+        .and_then(|(_, sub_matches)| sub_matches.value_of("profile"))
+        .or_else(|| matches.value_of("profile"))
+        .map(String::from)
+        .or_else(|| {
+            env::var(profile::PROFILE_ENV)
+                .ok()
+                .filter(|name| !name.is_empty())
+        });
+    if let Some(name) = profile {
+        if let Err(e) = profile::select(&name) {
+            eprintln!("Error: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+    if matches.is_present("fips") {
+        fips::enable();
+    }
+    if let Some(env) = matches.value_of("sigstore-env") {
+        SigstoreEnv::select(env.parse().unwrap_or(SigstoreEnv::Production));
+    }
+    if matches.is_present("http1") {
+        transport::force_http1();
+    }
+    if matches.is_present("ipv4") {
+        transport::pin_ip_family(IpFamily::Ipv4);
+    }
+    if matches.is_present("ipv6") {
+        transport::pin_ip_family(IpFamily::Ipv6);
+    }
+    if matches.is_present("offline") {
+        transport::go_offline();
+    }
+    if matches.is_present("strict-expiry-warnings") {
+        expiry::make_strict();
+    }
+    let configured = match matches.subcommand() {
+        Some((_, sub_matches)) => configure_transport(sub_matches),
+        None => configure_transport(&matches),
+    };
+    if let Err(e) = configured {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(1);
+    }
+    if let Some((name, sub_matches)) = matches.subcommand() {
+        if sub_matches.is_present("fips") {
+            fips::enable();
+        }
+        if let Some(env) = sub_matches.value_of("sigstore-env") {
+            SigstoreEnv::select(env.parse().unwrap_or(SigstoreEnv::Production));
+        }
+        if sub_matches.is_present("http1") {
+            transport::force_http1();
+        }
+        if sub_matches.is_present("ipv4") {
+            transport::pin_ip_family(IpFamily::Ipv4);
+        }
+        if sub_matches.is_present("ipv6") {
+            transport::pin_ip_family(IpFamily::Ipv6);
+        }
+        if sub_matches.is_present("offline") {
+            transport::go_offline();
+        }
+        if sub_matches.is_present("strict-expiry-warnings") {
+            expiry::make_strict();
+        }
+        if let Err(e) = run_subcommand(name, sub_matches).await {
+            eprintln!("Error: {:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(o) = matches.value_of("oci-registry") {
+        println!("OCI registry: {}", o);
+    }
+    if let Some(f) = matches.value_of("outfile") {
+        println!("Output file: {}", f);
+    }
+
+    // TO DO: need better error handling in place of unwrap
+    let mut reference: OciSource = matches.value_of("oci-registry").unwrap().parse().unwrap(); //#[allow_ci]
+    let mut signer = None;
+    if matches.is_present("verify-notation") {
+        match verify_notation(&reference).await {
+            Ok((verified, verified_signer)) => {
+                reference = verified;
+                signer = Some(verified_signer);
+            }
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let outfile = matches.value_of("outfile").unwrap(); //#[allow_ci]
+    let source = reference.to_string();
+    let repository = reference.repository();
+    if let Err(e) = pull(reference, outfile).await {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+    if matches.is_present("require-sbom") {
+        if let Err(e) = save_sbom(&matches, &repository, outfile).await {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+    let audit = config::Config::load().and_then(|config| audit::AuditLog::open(&config));
+    let digest = utils::sha256_file(Path::new(outfile)).ok();
+    let record = |action| {
+        let mut record = AuditRecord::new(action, &source);
+        record.digest = digest.clone();
+        match &audit {
+            Ok(audit) => audit.record_or_warn(&record),
+            Err(e) => eprintln!("Warning: {:#}", e),
+        }
+    };
+    record(Action::Fetch);
+    if !matches.is_present("noexec") {
+        let executions =
+            config::Config::load().and_then(|config| execution_log::ExecutionLog::open(&config));
+        if let Err(e) = &executions {
+            eprintln!("Warning: {:#}", e);
+        }
+        let records = runner::Records {
+            audit: audit.as_ref().ok(),
+            executions: executions.as_ref().ok(),
+        };
+        let ran = approval::read_approvals(&matches)
+            .and_then(|approvals| Ok((approvals, TrustRoots::load(&TrustStore::open()?)?)))
+            .and_then(|(approvals, roots)| {
+                execute_pulled(
+                    Path::new(outfile),
+                    &source,
+                    signer.as_ref(),
+                    &approvals,
+                    &roots,
+                    &records,
+                    matches.is_present("interactive"),
+                )
+            });
+        let (status, digest) = match ran {
+            Ok(ran) => ran,
+            Err(e) => {
+                eprintln!("Error: sget script execution failed: {:#}", e);
+                std::process::exit(1);
+            }
+        };
+        println!("\nsget script execution succeeded");
+        if let (Some(path), true) = (matches.value_of("attest"), status.success()) {
+            if let Err(e) = attest_execution(&matches, path, &source, &digest, status.code()).await
+            {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{get, HeaderTransport, Transport};
+    use async_trait::async_trait;
+    use http::{Request, Response};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn scope_headers_to_artifact() {
+        struct Echo;
+        #[async_trait]
+        impl Transport for Echo {
+            async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+                let auth = request
+                    .headers()
+                    .get("authorization")
+                    .map(|value| value.as_bytes().to_vec())
+                    .unwrap_or_default();
+                Ok(Response::new(auth))
+            }
+        }
+        let origins = artifact_origins(
+            &["https://cdn.corp.example/scripts/install.sh", "local.sig"],
+            Some("ghcr.io/corp/scripts:v1"),
+        );
+        assert_eq!(origins, ["https://cdn.corp.example/", "https://ghcr.io/"]);
+        let header =
+            SourceHeaders::parse_header("Authorization: Bearer s3cret").expect("Invalid header");
+        let transport = HeaderTransport::new(Arc::new(Echo), &header_sources(&[header], &origins))
+            .expect("Invalid source");
+        let auth = |request: Request<Vec<u8>>| {
+            let transport = &transport;
+            async move {
+                transport
+                    .send(request)
+                    .await
+                    .expect("Request failed")
+                    .into_body()
+            }
+        };
+        let url = |url: &str| get(url).expect("Invalid request");
+
+        assert_eq!(
+            auth(url("https://cdn.corp.example/scripts/install.sh")).await,
+            b"Bearer s3cret"
+        );
+        assert_eq!(
+            auth(url("https://ghcr.io/v2/corp/scripts/manifests/v1")).await,
+            b"Bearer s3cret"
+        );
+        let env = SigstoreEnv::Production;
+        assert!(auth(url(env.fulcio_url())).await.is_empty());
+        assert!(auth(url(env.rekor_url())).await.is_empty());
+        // The identity token sent to Fulcio is kept.
+        let mut request = url("https://cdn.corp.example/api/v1/signingCert");
+        request.headers_mut().insert(
+            "authorization",
+            "Bearer oidc".parse().expect("Invalid header"),
+        );
+        assert_eq!(auth(request).await, b"Bearer oidc");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn approve_pulled_script() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let script = dir.path().join("script.sh");
+        let marker = dir.path().join("ran");
+        std::fs::write(&script, format!("#!/bin/sh\ntouch {}\n", marker.display()))
+            .expect("Cannot write script");
+        let executions = execution_log::ExecutionLog::new(dir.path().join("executions.jsonl"));
+        let records = runner::Records {
+            audit: None,
+            executions: Some(&executions),
+        };
+        let source = "ghcr.io/corp/prod/install:v1";
+        let mut roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let run =
+            |roots: &TrustRoots| execute_pulled(&script, source, None, &[], roots, &records, false);
+
+        // A rule requires approval, which an unsigned script cannot get.
+        roots.system.approvals.push(approval::ApprovalRule {
+            pattern: "ghcr.io/corp/prod/**".to_string(),
+            approvers: Vec::new(),
+            approver_keys: Vec::new(),
+        });
+        let err = run(&roots).expect_err("Ran without approval");
+        assert!(matches!(
+            err.downcast_ref::<crate::error::SgetError>(),
+            Some(crate::error::SgetError::Unapproved(_))
+        ));
+        assert!(!marker.exists());
+        assert_eq!(executions.verify().expect("Broken log").entries, 0);
+
+        roots.system.approvals.clear();
+        let (status, digest) = run(&roots).expect("Cannot run");
+        assert!(status.success());
+        assert!(marker.exists());
+        assert_eq!(
+            digest,
+            utils::sha256_file(&script).expect("Cannot hash script")
+        );
+        assert_eq!(executions.verify().expect("Broken log").entries, 1);
+    }
+}
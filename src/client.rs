@@ -0,0 +1,875 @@
+use chrono::Utc;
+use futures_util::stream::{self, StreamExt};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::Arc;
+
+use crate::algorithms::AlgorithmPolicy;
+use crate::approval::{self, ApprovalRequest};
+use crate::audit::{Action, AuditLog, AuditRecord};
+use crate::error::{Result, SgetError};
+use crate::execution_log::ExecutionLog;
+use crate::fetch::is_remote;
+use crate::keys::PublicKey;
+use crate::manifest::{self, EntryResult, Manifest, ManifestEntry, Material};
+use crate::observer::{ObservedStages, ObservedTransport, Observer, ProgressEvent};
+use crate::pipeline::{Pipeline, StageHook};
+use crate::policy::{Policy, SigstoreOidcKey};
+use crate::policy_set::{self, PolicyLevel, PolicySet};
+use crate::runner;
+use crate::signature::{SignatureVerifier, SignatureVerifiers};
+use crate::transport::{self, Transport};
+use crate::trust::TrustStore;
+use crate::utils;
+use crate::verify::{self, BlobSignature, Signer, TrustRoots};
+
+pub use tokio_util::sync::CancellationToken;
+
+/// Verifies artifacts and root policies against a set of trust roots and the
+/// requirements set with [`VerifierBuilder`]. A verifier is meant to be built
+/// once and reused for many artifacts.
+pub struct Verifier {
+    roots: TrustRoots,
+    verifiers: SignatureVerifiers,
+    pipeline: Pipeline,
+    policy: Option<Policy>,
+    policy_set: Option<(PolicySet, String)>,
+    identity: Option<SigstoreOidcKey>,
+    require_rekor: bool,
+    offline: bool,
+}
+
+impl Verifier {
+    /// A verifier trusting `roots`, without further requirements.
+    pub fn new(roots: TrustRoots) -> Self {
+        Verifier {
+            roots,
+            verifiers: SignatureVerifiers::default(),
+            pipeline: Pipeline::default(),
+            policy: None,
+            policy_set: None,
+            identity: None,
+            require_rekor: false,
+            offline: false,
+        }
+    }
+
+    pub fn builder() -> VerifierBuilder {
+        VerifierBuilder::default()
+    }
+
+    /// A verifier trusting the public sigstore instance.
+    pub fn sigstore() -> Result<Self> {
+        Ok(Self::new(TrustRoots::sigstore()?))
+    }
+
+    /// A verifier trusting the local trust store managed by `sget trust`.
+    pub fn from_trust_store() -> Result<Self> {
+        Ok(Self::new(TrustRoots::load(&TrustStore::open()?)?))
+    }
+
+    pub fn roots(&self) -> &TrustRoots {
+        &self.roots
+    }
+
+    /// The verified root policy whose keys artifacts must be signed by, if any.
+    pub fn policy(&self) -> Option<&Policy> {
+        self.policy.as_ref()
+    }
+
+    /// Whether artifacts may only be read from local paths.
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Verify a detached signature over `data`, see [`verify::verify_blob`], and
+    /// check the signer against the verifier's requirements.
+    pub fn verify_blob(&self, data: &[u8], signature: &BlobSignature) -> Result<Signer> {
+        let signer = verify::verify_blob(
+            data,
+            signature,
+            &self.roots,
+            self.identity.as_ref(),
+            &self.pipeline,
+        )?;
+        self.check_signer(&signer)?;
+        Ok(signer)
+    }
+
+    /// Verify a signed root policy as of now, see [`verify::verify_policy`].
+    pub fn verify_policy(&self, raw: &[u8]) -> Result<Policy> {
+        verify::verify_policy(
+            raw,
+            &self.roots,
+            &self.verifiers,
+            &self.pipeline,
+            Utc::now(),
+        )
+    }
+
+    /// Check a signer whose signature has already been verified against the
+    /// requirements not covered by [`verify::verify_blob`].
+    pub fn check_signer(&self, signer: &Signer) -> Result<()> {
+        self.check_signer_for(signer, None)
+    }
+
+    /// Like [`Verifier::check_signer`], for the artifact at `location`, whose
+    /// signer must also meet the root policies' target rules for it.
+    pub fn check_signer_for(&self, signer: &Signer, location: Option<&str>) -> Result<()> {
+        self.roots.check_signer_for(signer, location)?;
+        if self.require_rekor && signer.integrated_time.is_none() {
+            return Err(SgetError::TransparencyLogError(
+                "Signature is not in the Rekor transparency log".to_string(),
+            ));
+        }
+        if let Some(expected) = &self.identity {
+            let issuer_matches =
+                expected.issuer.is_empty() || signer.issuer.as_deref() == Some(&expected.issuer);
+            if signer.identity.as_deref() != Some(expected.identity.as_str()) || !issuer_matches {
+                return Err(SgetError::IdentityMismatch {
+                    expected: expected.identity.clone(),
+                    actual: signer.subject().to_string(),
+                });
+            }
+        }
+        if let Some((set, namespace)) = &self.policy_set {
+            set.resolve(namespace)
+                .ok_or_else(|| {
+                    SgetError::UntrustedSigner(format!("No policy applies to {}", namespace))
+                })?
+                .check(signer)?;
+        }
+        if let Some(policy) = &self.policy {
+            policy_set::check_namespace(policy, location)?;
+            if !policy_set::policy_trusts(policy, location, signer) {
+                return Err(SgetError::UntrustedSigner(format!(
+                    "{} is not a key of the {} policy{}",
+                    signer.subject(),
+                    policy.signed.namespace,
+                    location.map(|l| format!(" for {}", l)).unwrap_or_default()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Composes the requirements of a [`Verifier`].
+///
+/// Without [`VerifierBuilder::roots`] the public sigstore roots are trusted;
+/// Fulcio roots and Rekor keys added individually extend whichever roots are
+/// used.
+#[derive(Default)]
+pub struct VerifierBuilder {
+    roots: Option<TrustRoots>,
+    verifiers: SignatureVerifiers,
+    pipeline: Pipeline,
+    fulcio_roots: Vec<Vec<u8>>,
+    rekor_keys: Vec<PublicKey>,
+    algorithms: Option<AlgorithmPolicy>,
+    policy: Option<Vec<u8>>,
+    layered_policies: Vec<(PolicyLevel, Vec<u8>)>,
+    namespace: Option<String>,
+    identity: Option<SigstoreOidcKey>,
+    require_rekor: bool,
+    require_signing_time: bool,
+    offline: bool,
+}
+
+impl VerifierBuilder {
+    /// Trust `roots` instead of the public sigstore roots.
+    pub fn roots(mut self, roots: TrustRoots) -> Self {
+        self.roots = Some(roots);
+        self
+    }
+
+    /// Also trust the PEM encoded Fulcio root certificate `pem`.
+    pub fn fulcio_root(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.fulcio_roots.push(pem.into());
+        self
+    }
+
+    /// Also trust entries logged by the Rekor instance with `key`.
+    pub fn rekor_key(mut self, key: PublicKey) -> Self {
+        self.rekor_keys.push(key);
+        self
+    }
+
+    /// Only accept signatures made with the algorithms `algorithms` allows,
+    /// instead of those of the roots.
+    pub fn algorithms(mut self, algorithms: AlgorithmPolicy) -> Self {
+        self.algorithms = Some(algorithms);
+        self
+    }
+
+    /// Verify policy signatures by keys of `keytype` used with `scheme` with
+    /// `verifier`, see [`SignatureVerifiers::register`].
+    pub fn signature_verifier(
+        mut self,
+        keytype: &str,
+        scheme: &str,
+        verifier: Arc<dyn SignatureVerifier>,
+    ) -> Self {
+        self.verifiers.register(keytype, scheme, verifier);
+        self
+    }
+
+    /// Run `hook` around each verification stage, after any hooks added before.
+    pub fn stage_hook(mut self, hook: Arc<dyn StageHook>) -> Self {
+        self.pipeline.add(hook);
+        self
+    }
+
+    /// Only accept signers that are root keys of the signed root policy `raw`.
+    /// The policy itself is verified when the verifier is built.
+    pub fn policy(mut self, raw: impl Into<Vec<u8>>) -> Self {
+        self.policy = Some(raw.into());
+        self
+    }
+
+    /// Also enforce the signed root policy `raw` at `level` of a [`PolicySet`];
+    /// signers must be trusted by the effective policy of the namespace set
+    /// with [`VerifierBuilder::namespace`]. The policy is verified when the
+    /// verifier is built.
+    pub fn layered_policy(mut self, level: PolicyLevel, raw: impl Into<Vec<u8>>) -> Self {
+        self.layered_policies.push((level, raw.into()));
+        self
+    }
+
+    /// The namespace whose effective policy layered policies are resolved to.
+    /// The root policy set with [`VerifierBuilder::policy`] must cover it.
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Only accept keyless signatures by `identity`. An empty `issuer` matches
+    /// any issuer.
+    pub fn identity(mut self, identity: &str, issuer: &str) -> Self {
+        self.identity = Some(SigstoreOidcKey {
+            identity: identity.to_string(),
+            issuer: issuer.to_string(),
+        });
+        self
+    }
+
+    /// Require a Rekor bundle even for signatures made with a key. Keyless
+    /// signatures always require one.
+    pub fn require_rekor(mut self, required: bool) -> Self {
+        self.require_rekor = required;
+        self
+    }
+
+    /// Only accept root policy signatures with a signed timestamp, as the
+    /// system policy can also require.
+    pub fn require_signing_time(mut self, required: bool) -> Self {
+        self.require_signing_time = required;
+        self
+    }
+
+    /// Refuse to fetch anything over the network.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn build(self) -> Result<Verifier> {
+        let mut roots = match self.roots {
+            Some(roots) => roots,
+            None => TrustRoots::sigstore()?,
+        };
+        for pem in &self.fulcio_roots {
+            roots.fulcio_roots.extend(verify::pem_certificates(pem)?);
+        }
+        roots.rekor_keys.extend(self.rekor_keys);
+        if let Some(algorithms) = self.algorithms {
+            roots.algorithms = algorithms;
+        }
+        roots.system.require_signing_time |= self.require_signing_time;
+        let policy = match &self.policy {
+            Some(raw) => Some(verify::verify_policy(
+                raw,
+                &roots,
+                &self.verifiers,
+                &self.pipeline,
+                Utc::now(),
+            )?),
+            None => None,
+        };
+        if let (Some(policy), Some(namespace)) = (&policy, &self.namespace) {
+            if !policy.signed.namespace.covers(namespace) {
+                return Err(SgetError::InvalidPolicy(format!(
+                    "The policy for {} does not cover {}",
+                    policy.signed.namespace, namespace
+                )));
+            }
+        }
+        let policy_set = if self.layered_policies.is_empty() {
+            None
+        } else {
+            let namespace = self.namespace.ok_or_else(|| {
+                SgetError::InvalidPolicy("Layered policies need a namespace".to_string())
+            })?;
+            let mut set = PolicySet::default();
+            for (level, raw) in &self.layered_policies {
+                let policy = verify::verify_policy(
+                    raw,
+                    &roots,
+                    &self.verifiers,
+                    &self.pipeline,
+                    Utc::now(),
+                )?;
+                set.add(*level, policy);
+            }
+            Some((set, namespace))
+        };
+        Ok(Verifier {
+            roots,
+            verifiers: self.verifiers,
+            pipeline: self.pipeline,
+            policy,
+            policy_set,
+            identity: self.identity,
+            require_rekor: self.require_rekor,
+            offline: self.offline,
+        })
+    }
+}
+
+/// An artifact whose signature has been verified. It can only be obtained from
+/// [`SgetClient::fetch`], so [`SgetClient::execute`] only ever runs verified
+/// contents.
+pub struct VerifiedArtifact {
+    source: String,
+    data: Vec<u8>,
+    signer: Signer,
+    custom: Option<serde_json::Value>,
+}
+
+impl VerifiedArtifact {
+    /// The URL or path the artifact was fetched from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn signer(&self) -> &Signer {
+        &self.signer
+    }
+
+    /// The custom metadata of the manifest entry the artifact was fetched for.
+    pub fn custom(&self) -> Option<&serde_json::Value> {
+        self.custom.as_ref()
+    }
+}
+
+/// Fetches artifacts, verifies them and runs them, as the `sget` binary does.
+pub struct SgetClient {
+    verifier: Verifier,
+    transport: Arc<dyn Transport>,
+    base: PathBuf,
+    audit: Option<AuditLog>,
+    executions: Option<ExecutionLog>,
+    cancel: Option<CancellationToken>,
+    observer: Option<Arc<dyn Observer>>,
+    concurrency: usize,
+    approvals: Vec<Vec<u8>>,
+}
+
+impl SgetClient {
+    pub fn new(verifier: Verifier) -> Self {
+        SgetClient {
+            verifier,
+            transport: transport::default_transport(),
+            base: PathBuf::new(),
+            audit: None,
+            executions: None,
+            cancel: None,
+            observer: None,
+            concurrency: manifest::DEFAULT_CONCURRENCY,
+            approvals: Vec::new(),
+        }
+    }
+
+    /// Resolve relative artifact paths against `base` instead of the working
+    /// directory.
+    pub fn with_base(mut self, base: impl Into<PathBuf>) -> Self {
+        self.base = base.into();
+        self
+    }
+
+    /// Send remote requests with `transport` instead of the default reqwest client.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Record every fetch and run in `audit`.
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Append every run to the tamper-evident `executions` log.
+    pub fn with_execution_log(mut self, executions: ExecutionLog) -> Self {
+        self.executions = Some(executions);
+        self
+    }
+
+    /// Abandon fetches in progress with [`SgetError::Cancelled`] once `cancel`
+    /// is cancelled, e.g. from another thread or task. Cancelled fetches leave
+    /// nothing behind and are not audited.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Fetch and verify up to `concurrency` manifest entries at once, instead
+    /// of [`manifest::DEFAULT_CONCURRENCY`].
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Report downloads, verification stages and script runs to `observer`.
+    pub fn with_observer(mut self, observer: Arc<dyn Observer>) -> Self {
+        self.verifier
+            .pipeline
+            .add(Arc::new(ObservedStages(observer.clone())));
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Offer the approval `raw`, from `sget approve`, to artifacts that the
+    /// system policy only lets run with a second operator's approval.
+    pub fn with_approval(mut self, raw: impl Into<Vec<u8>>) -> Self {
+        self.approvals.push(raw.into());
+        self
+    }
+
+    pub fn verifier(&self) -> &Verifier {
+        &self.verifier
+    }
+
+    fn record(&self, record: AuditRecord) -> Result<()> {
+        match &self.audit {
+            Some(audit) => Ok(audit.record(&record)?),
+            None => Ok(()),
+        }
+    }
+
+    fn observe(&self, event: ProgressEvent) {
+        if let Some(observer) = &self.observer {
+            observer.event(&event);
+        }
+    }
+
+    /// Fetch and verify one entry, applying the verifier's requirements on top
+    /// of the entry's own.
+    async fn fetch_entry(
+        &self,
+        entry: &ManifestEntry,
+        base: &Path,
+    ) -> (Option<Material>, Result<Signer>) {
+        let (material, outcome) = self.fetch_and_verify(entry, base).await;
+        self.observe(ProgressEvent::Verified {
+            url: &entry.url,
+            outcome: outcome.as_ref(),
+        });
+        (material, outcome)
+    }
+
+    async fn fetch_and_verify(
+        &self,
+        entry: &ManifestEntry,
+        base: &Path,
+    ) -> (Option<Material>, Result<Signer>) {
+        if self.verifier.offline {
+            if let Some(remote) = entry
+                .locations()
+                .find(|location| is_remote(location) || location.starts_with("oci://"))
+            {
+                return (None, Err(SgetError::Offline(remote.to_string())));
+            }
+            if entry.revocation.is_some() {
+                let what = "Checking revocation";
+                return (None, Err(SgetError::Offline(what.to_string())));
+            }
+        }
+        let fetched = match &self.observer {
+            Some(observer) => {
+                let transport = ObservedTransport {
+                    inner: self.transport.as_ref(),
+                    observer: observer.as_ref(),
+                };
+                entry.fetch_material(&transport, base).await
+            }
+            None => entry.fetch_material(self.transport.as_ref(), base).await,
+        };
+        let material = match fetched {
+            Ok(material) => material,
+            Err(e) => return (None, Err(e)),
+        };
+        let outcome = entry
+            .verify_material(&material, &self.verifier.roots, &self.verifier.pipeline)
+            .and_then(|signer| {
+                self.verifier.check_signer_for(&signer, Some(&entry.url))?;
+                Ok(signer)
+            });
+        (Some(material), outcome)
+    }
+
+    /// Fetch the artifact described by `entry` with its signature material and
+    /// verify it. Failures can be told apart with [`SgetError::reason`].
+    pub async fn fetch(&self, entry: &ManifestEntry) -> Result<VerifiedArtifact> {
+        let (material, outcome) =
+            utils::cancellable(self.cancel.as_ref(), self.fetch_entry(entry, &self.base)).await?;
+        self.record(AuditRecord::verification(
+            Action::Fetch,
+            &entry.url,
+            material.as_ref().map(Material::digest),
+            outcome.as_ref(),
+        ))?;
+        let signer = outcome?;
+        let data = material.map(|material| material.data).unwrap_or_default();
+        Ok(VerifiedArtifact {
+            source: entry.url.clone(),
+            data: entry.plaintext(data, &self.base)?,
+            signer,
+            custom: entry.custom.clone(),
+        })
+    }
+
+    /// Fetch and verify every artifact listed in the manifest at `path`, up to
+    /// the client's concurrency at once. Relative locations in the manifest are
+    /// relative to the manifest. Results, and their audit records, are in
+    /// manifest order. When cancelled, no results are returned or audited.
+    pub async fn fetch_manifest(&self, path: &Path) -> Result<Vec<EntryResult>> {
+        let manifest = Manifest::load(path)?;
+        let base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let fetched: Vec<_> = utils::cancellable(
+            self.cancel.as_ref(),
+            stream::iter(&manifest.artifacts)
+                .map(|entry| self.fetch_entry(entry, &base))
+                .buffered(self.concurrency.max(1))
+                .collect(),
+        )
+        .await?;
+        let mut results = Vec::with_capacity(fetched.len());
+        for (entry, (material, outcome)) in manifest.artifacts.iter().zip(fetched) {
+            let result = EntryResult {
+                url: entry.url.clone(),
+                custom: entry.custom.clone(),
+                material,
+                outcome,
+            };
+            self.record(AuditRecord::verification(
+                Action::Verify,
+                &result.url,
+                result.digest(),
+                result.outcome.as_ref(),
+            ))?;
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    /// The request a second operator approves for `artifact` to run, if the
+    /// system policy requires one.
+    pub fn approval_request(&self, artifact: &VerifiedArtifact) -> Option<ApprovalRequest> {
+        let rules = &self.verifier.roots.system.approvals;
+        if approval::rules_for(rules, &artifact.source).is_empty() {
+            return None;
+        }
+        Some(ApprovalRequest::new(
+            &artifact.source,
+            &hex::encode(Sha256::digest(&artifact.data)),
+            &artifact.signer,
+            &crate::attest::requester(),
+            Utc::now(),
+        ))
+    }
+
+    /// Run a verified script and wait for it to exit. Unless `interactive`, its
+    /// standard streams are captured rather than inherited. Scripts the system
+    /// policy requires approval for only run with an approval offered with
+    /// [`SgetClient::with_approval`]; see [`SgetClient::approval_request`].
+    pub fn execute(&self, artifact: &VerifiedArtifact, interactive: bool) -> Result<ExitStatus> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("script");
+        fs::write(&path, &artifact.data)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o700))?;
+        }
+        let script = runner::Script {
+            source: &artifact.source,
+            digest: &hex::encode(Sha256::digest(&artifact.data)),
+            signer: Some(&artifact.signer),
+        };
+        let records = runner::Records {
+            audit: self.audit.as_ref(),
+            executions: self.executions.as_ref(),
+        };
+        self.observe(ProgressEvent::ExecutionStarted {
+            source: &artifact.source,
+        });
+        let ran = runner::run(
+            &script,
+            &path,
+            None,
+            interactive,
+            &self.approvals,
+            &self.verifier.roots,
+            &records,
+        );
+        self.observe(ProgressEvent::ExecutionFinished {
+            source: &artifact.source,
+            status: ran.as_ref().ok().map(|(status, _)| *status),
+        });
+        Ok(ran?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::FailureReason;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn fetch_and_execute() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let script = b"#!/bin/sh\nexit 3\n";
+        fs::write(dir.path().join("exit.sh"), script).expect("Cannot write script");
+        fs::write(
+            dir.path().join("exit.sh.sig"),
+            base64::encode(key.sign(script)),
+        )
+        .expect("Cannot write signature");
+        fs::write(
+            dir.path().join("sget.pub"),
+            key.public_key().to_pem().expect("Cannot encode key"),
+        )
+        .expect("Cannot write key");
+
+        let audit_path = dir.path().join("audit.jsonl");
+        let executions = ExecutionLog::new(dir.path().join("executions.jsonl"));
+        let client = SgetClient::new(Verifier::sigstore().expect("Cannot load roots"))
+            .with_base(dir.path())
+            .with_audit_log(AuditLog::new(&audit_path))
+            .with_execution_log(executions.clone());
+        let mut entry = ManifestEntry::new("exit.sh");
+        entry.key = Some("sget.pub".to_string());
+        let artifact = client.fetch(&entry).await.expect("Cannot verify");
+        assert_eq!(artifact.data(), script);
+
+        let status = client.execute(&artifact, false).expect("Cannot run");
+        assert_eq!(status.code(), Some(3));
+
+        entry.url = "missing.sh".to_string();
+        assert!(client.fetch(&entry).await.is_err());
+        let log = fs::read_to_string(&audit_path).expect("Cannot read audit log");
+        assert_eq!(log.lines().count(), 3);
+        let head = executions.verify().expect("Broken execution log");
+        assert_eq!(head.entries, 1);
+        // Only the run is recorded, not the fetches.
+        let entries = executions.entries().expect("Cannot read execution log");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].hash, head.hash);
+        assert!(entries[0].execution.source.ends_with("exit.sh"));
+        assert_eq!(
+            entries[0].execution.digest.as_deref(),
+            Some(hex::encode(Sha256::digest(script)).as_str())
+        );
+    }
+
+    /// Serves recorded responses by URL, and 404 for anything else.
+    struct Replay(std::collections::HashMap<String, Vec<u8>>);
+
+    #[async_trait::async_trait]
+    impl Transport for Replay {
+        async fn send(
+            &self,
+            request: transport::Request<Vec<u8>>,
+        ) -> anyhow::Result<transport::Response<Vec<u8>>> {
+            let response = transport::Response::builder();
+            Ok(match self.0.get(&request.uri().to_string()) {
+                Some(body) => response.body(body.clone())?,
+                None => response.status(404).body(Vec::new())?,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_with_custom_transport() {
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let url = "https://example.com/install.sh";
+        let replay = Replay(
+            vec![
+                (url.to_string(), b"echo hi".to_vec()),
+                (
+                    format!("{}.sig", url),
+                    base64::encode(key.sign(b"echo hi")).into_bytes(),
+                ),
+                (
+                    "https://example.com/sget.pub".to_string(),
+                    key.public_key()
+                        .to_pem()
+                        .expect("Cannot encode key")
+                        .into_bytes(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let client = SgetClient::new(Verifier::sigstore().expect("Cannot load roots"))
+            .with_transport(Arc::new(replay));
+        let mut entry = ManifestEntry::new(url);
+        entry.key = Some("https://example.com/sget.pub".to_string());
+        let artifact = client.fetch(&entry).await.expect("Cannot verify");
+        assert_eq!(artifact.data(), b"echo hi");
+
+        entry.url = "https://example.com/missing.sh".to_string();
+        let err = match client.fetch(&entry).await {
+            Ok(_) => panic!("Fetched a missing artifact"), //#[allow_ci]
+            Err(e) => e,
+        };
+        assert_eq!(err.reason(), Some(FailureReason::Fetch));
+    }
+
+    /// Never answers, like a stalled download.
+    struct Stalled;
+
+    #[async_trait::async_trait]
+    impl Transport for Stalled {
+        async fn send(
+            &self,
+            _request: transport::Request<Vec<u8>>,
+        ) -> anyhow::Result<transport::Response<Vec<u8>>> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn cancel_stalled_fetch() {
+        let cancel = CancellationToken::new();
+        let client = SgetClient::new(Verifier::sigstore().expect("Cannot load roots"))
+            .with_transport(Arc::new(Stalled))
+            .with_cancellation(cancel.clone());
+        let entry = ManifestEntry::new("https://example.com/install.sh");
+        let canceller = tokio::spawn(async move { cancel.cancel() });
+        assert!(matches!(
+            client.fetch(&entry).await,
+            Err(SgetError::Cancelled)
+        ));
+        canceller.await.expect("Canceller failed");
+    }
+
+    /// A root policy listing `key` as its only root key, signed by it.
+    fn single_key_policy(key: &SigningKey) -> Vec<u8> {
+        let public = key.public_key();
+        let id = public.key_id().expect("No key id");
+        let signed = serde_json::json!({
+            "consistent_snapshot": true,
+            "expires": "2100-01-01T00:00:00Z",
+            "keys": { &id: public.to_policy_key().expect("Cannot encode key") },
+            "namespace": "example.com/scripts",
+            "roles": { "root": { "keyids": [&id], "threshold": 1 } },
+            "spec_version": "1.0",
+            "version": 1,
+        })
+        .to_string();
+        let signature = serde_json::json!([{
+            "keyid": id,
+            "sig": base64::encode(key.sign(signed.as_bytes())),
+            "cert": "",
+        }]);
+        format!("{{\"signatures\":{},\"signed\":{}}}", signature, signed).into_bytes()
+    }
+
+    #[tokio::test]
+    async fn builder_requirements() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let trusted = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let other = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        for (name, key) in &[("trusted", &trusted), ("other", &other)] {
+            fs::write(dir.path().join(format!("{}.sh", name)), b"true").expect("Cannot write");
+            fs::write(
+                dir.path().join(format!("{}.sh.sig", name)),
+                base64::encode(key.sign(b"true")),
+            )
+            .expect("Cannot write signature");
+            fs::write(
+                dir.path().join(format!("{}.pub", name)),
+                key.public_key().to_pem().expect("Cannot encode key"),
+            )
+            .expect("Cannot write key");
+        }
+        let entry = |name: &str| {
+            let mut entry = ManifestEntry::new(&format!("{}.sh", name));
+            entry.key = Some(format!("{}.pub", name));
+            entry
+        };
+        let client = |verifier: VerifierBuilder| {
+            SgetClient::new(verifier.build().expect("Cannot build verifier")).with_base(dir.path())
+        };
+
+        // Artifacts under a root policy must come from its namespace.
+        let served = |name: &str| format!("https://example.com/scripts/{}.sh", name);
+        let replay = Replay(
+            [("trusted", &trusted), ("other", &other)]
+                .iter()
+                .flat_map(|(name, key)| {
+                    vec![
+                        (served(name), b"true".to_vec()),
+                        (
+                            format!("{}.sig", served(name)),
+                            base64::encode(key.sign(b"true")).into_bytes(),
+                        ),
+                    ]
+                })
+                .collect(),
+        );
+        let policy = client(Verifier::builder().policy(single_key_policy(&trusted)))
+            .with_transport(Arc::new(replay));
+        let remote = |name: &str| {
+            let mut entry = ManifestEntry::new(&served(name));
+            entry.key = Some(format!("{}.pub", name));
+            entry
+        };
+        assert!(policy.fetch(&remote("trusted")).await.is_ok());
+        assert!(policy.fetch(&remote("other")).await.is_err());
+        assert!(matches!(
+            policy.fetch(&entry("trusted")).await,
+            Err(SgetError::UntrustedSigner(_))
+        ));
+
+        let rekor = client(Verifier::builder().require_rekor(true));
+        assert!(rekor.fetch(&entry("trusted")).await.is_err());
+
+        let identity = client(Verifier::builder().identity("me@example.com", ""));
+        assert!(identity.fetch(&entry("trusted")).await.is_err());
+
+        let offline = client(Verifier::builder().offline(true));
+        assert!(offline.fetch(&entry("trusted")).await.is_ok());
+        let remote = ManifestEntry::new("https://example.com/install.sh");
+        let err = match offline.fetch(&remote).await {
+            Ok(_) => panic!("Offline verifier fetched a URL"), //#[allow_ci]
+            Err(e) => e,
+        };
+        assert!(matches!(err, SgetError::Offline(_)));
+    }
+}
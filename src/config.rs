@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::algorithms::AlgorithmPolicy;
+use crate::expiry;
+use crate::hooks::HookConfig;
+use crate::policy_set::PolicyLevel;
+use crate::profile::{self, Profile};
+use crate::transport::{ProxyConfig, RetryPolicy, SourceHeaders};
+
+/// User configuration, read from `config.yaml` in the platform config directory
+/// or from the file named by `SGET_CONFIG`.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Policy namespaces sget knows about, by name.
+    #[serde(default)]
+    pub namespaces: BTreeMap<String, NamespaceConfig>,
+    /// Commands and webhooks to notify of policy changes and failures.
+    #[serde(default)]
+    pub hooks: Vec<HookConfig>,
+    /// Where to append the audit log, instead of the state directory.
+    pub audit_log: Option<PathBuf>,
+    /// Where to keep the hash-chained log of executed scripts, instead of the
+    /// state directory.
+    pub execution_log: Option<PathBuf>,
+    /// The signature algorithms, hash functions and key sizes to accept.
+    #[serde(default)]
+    pub algorithms: AlgorithmPolicy,
+    /// Root policies enforced together, see
+    /// [`PolicySet`](crate::policy_set::PolicySet).
+    #[serde(default)]
+    pub policies: Vec<LayeredPolicy>,
+    /// The proxy to send requests through.
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// Headers to send to artifact sources, e.g. bearer tokens.
+    #[serde(default)]
+    pub sources: Vec<SourceHeaders>,
+    /// How failed requests are retried.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// How long before a policy or certificate expires to start warning about
+    /// it, e.g. `30d` [default: 14d].
+    pub expiry_warning: Option<String>,
+    /// Trust settings by project or organisation, see [`Profile`].
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A root policy and the level it is enforced at.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LayeredPolicy {
+    pub level: PolicyLevel,
+    /// URL or path of the signed policy.
+    pub policy: String,
+}
+
+/// Where the policy and target artifacts of a namespace live.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NamespaceConfig {
+    /// URL or path of the namespace's root policy.
+    pub policy: String,
+    /// URLs or paths of artifacts published under the namespace.
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// How long the same policy may be served before it is treated as a
+    /// possible freeze attack, e.g. `30d`. Publishers must re-sign the policy
+    /// with a new version within this window.
+    pub max_metadata_age: Option<String>,
+    /// URL or path of the namespace's TUF repository, holding the
+    /// `timestamp.json`, `snapshot.json` and `targets.json` metadata signed by
+    /// the roles of its root policy.
+    pub metadata: Option<String>,
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf> {
+        if let Some(path) = env::var_os("SGET_CONFIG") {
+            return Ok(PathBuf::from(path));
+        }
+        dirs::config_dir()
+            .map(|dir| dir.join("sget").join("config.yaml"))
+            .ok_or_else(|| anyhow!("Cannot determine config directory; set SGET_CONFIG"))
+    }
+
+    /// Load the configuration, with the selected profile applied; a missing
+    /// file is an empty configuration.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        let mut config = if path.exists() {
+            let raw = fs::read(&path).with_context(|| format!("Cannot read {}", path.display()))?;
+            serde_yaml::from_slice(&raw)
+                .with_context(|| format!("Invalid config {}", path.display()))?
+        } else {
+            Self::default()
+        };
+        if let Some((_, profile)) = profile::active() {
+            profile.apply(&mut config);
+        }
+        Ok(config)
+    }
+
+    /// The window configured by `expiry_warning`.
+    pub fn expiry_window(&self) -> Result<Duration> {
+        match &self.expiry_warning {
+            Some(window) => Ok(crate::utils::parse_duration(window)?),
+            None => Ok(Duration::days(expiry::DEFAULT_WARNING_DAYS)),
+        }
+    }
+
+    pub fn namespace(&self, name: &str) -> Result<&NamespaceConfig> {
+        self.namespaces
+            .get(name)
+            .ok_or_else(|| anyhow!("Namespace {} is not configured", name))
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Result};
+use oci_distribution::Reference;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::fetch::OciSource;
+
+/// Prefix of sources in the local containers-storage of podman, buildah and
+/// skopeo.
+pub const PREFIX: &str = "containers-storage:";
+
+/// Graph root of the system-wide store.
+const SYSTEM_ROOT: &str = "/var/lib/containers/storage";
+
+/// Configuration of the system-wide store.
+const SYSTEM_CONF: &str = "/etc/containers/storage.conf";
+
+/// Whether sget runs as root, and so shares podman's system-wide store.
+fn is_root() -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata("/proc/self").is_ok_and(|meta| meta.uid() == 0)
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// The string value of `key` in the storage.conf at `path`. Only the flat
+/// `key = "value"` lines of the `[storage]` table are read, which is where
+/// the graph root is set.
+fn conf_value(path: &Path, key: &str) -> Option<String> {
+    let conf = fs::read_to_string(path).ok()?;
+    let mut table = String::new();
+    for line in conf.lines().map(str::trim) {
+        if line.starts_with('[') {
+            table = line.trim_matches(|c| c == '[' || c == ']').to_string();
+            continue;
+        }
+        if table != "storage" {
+            continue;
+        }
+        let (name, value) = match line.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+        if name == key {
+            return Some(value.trim_matches('"').to_string()).filter(|v| !v.is_empty());
+        }
+    }
+    None
+}
+
+/// The graph root of the store podman uses for the current user, as set in
+/// `storage.conf` or by default: `/var/lib/containers/storage` for root and
+/// `~/.local/share/containers/storage` otherwise.
+pub fn graph_root() -> Result<PathBuf> {
+    let root = is_root();
+    let conf = match env::var_os("CONTAINERS_STORAGE_CONF") {
+        Some(conf) => Some(PathBuf::from(conf)),
+        None if root => Some(PathBuf::from(SYSTEM_CONF)),
+        None => dirs::config_dir().map(|dir| dir.join("containers/storage.conf")),
+    };
+    let key = if root {
+        "graphroot"
+    } else {
+        "rootless_storage_path"
+    };
+    if let Some(configured) = conf.and_then(|conf| conf_value(&conf, key)) {
+        return Ok(PathBuf::from(configured));
+    }
+    if root {
+        return Ok(PathBuf::from(SYSTEM_ROOT));
+    }
+    dirs::data_dir()
+        .map(|dir| dir.join("containers/storage"))
+        .ok_or_else(|| anyhow!("Cannot determine the containers-storage graph root"))
+}
+
+/// The source for `containers-storage:[<graph root>]<reference>`, as skopeo
+/// names images. Podman keeps pulled artifacts in the OCI image layout
+/// `artifacts` of the graph root, tagged with their fully qualified reference,
+/// so that they are verified without being downloaded again.
+pub fn source(spec: &str) -> Result<OciSource> {
+    let (root, name) = match spec.strip_prefix('[') {
+        Some(rest) => {
+            let (store, name) = rest
+                .split_once(']')
+                .ok_or_else(|| anyhow!("Unterminated store in {}{}", PREFIX, spec))?;
+            // `[driver@graphroot+runroot:options]`; only the graph root matters.
+            let store = store.rsplit_once('@').map_or(store, |(_, root)| root);
+            let store = store.split_once('+').map_or(store, |(root, _)| root);
+            (PathBuf::from(store), name)
+        }
+        None => (graph_root()?, spec),
+    };
+    let reference: Reference = name
+        .parse()
+        .map_err(|e| anyhow!("Invalid reference {}: {:?}", name, e))?;
+    let repository = format!("{}/{}", reference.registry(), reference.repository());
+    let tag = match (reference.digest(), reference.tag()) {
+        (Some(digest), _) => digest.to_string(),
+        (None, tag) => format!("{}:{}", repository, tag.unwrap_or("latest")),
+    };
+    Ok(OciSource::Layout {
+        dir: root.join("artifacts"),
+        tag: Some(tag),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_artifacts() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let source = source(&format!(
+            "[overlay@{}+/run/containers/storage]ghcr.io/acme/install",
+            dir.path().display()
+        ))
+        .expect("Invalid source");
+        assert_eq!(
+            source,
+            OciSource::Layout {
+                dir: dir.path().join("artifacts"),
+                tag: Some("ghcr.io/acme/install:latest".to_string()),
+            }
+        );
+
+        let conf = dir.path().join("storage.conf");
+        fs::write(
+            &conf,
+            "[storage]\ndriver = \"overlay\"\ngraphroot = \"/srv/containers\"\n\n[storage.options]\ngraphroot = \"/elsewhere\"\n",
+        )
+        .expect("Cannot write conf");
+        assert_eq!(
+            conf_value(&conf, "graphroot").as_deref(),
+            Some("/srv/containers")
+        );
+        assert_eq!(conf_value(&conf, "rootless_storage_path"), None);
+    }
+}
@@ -0,0 +1,394 @@
+use anyhow::anyhow;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+
+use crate::error::{Result, SgetError};
+
+/// Leading bytes of a delta.
+const MAGIC: &[u8] = b"SGETDELTA2";
+
+/// Length of the header: [`MAGIC`], two SHA-256 digests and the target length.
+const HEADER_LEN: usize = MAGIC.len() + 32 + 32 + 8;
+
+/// The longest target [`apply`] builds, so that a delta repeating the base
+/// cannot exhaust memory.
+const MAX_TARGET_LEN: usize = 1 << 30;
+
+/// Length of the base blocks [`create`] looks for in the new version.
+const BLOCK_SIZE: usize = 64;
+
+const COPY: u8 = b'C';
+const INSERT: u8 = b'I';
+
+/// Create a delta turning `base` into `target`. A delta is [`MAGIC`], the
+/// SHA-256 digests of the base and target, the big-endian u64 length of the
+/// target, and a sequence of instructions:
+/// `C` followed by a big-endian u64 offset and length copies that range of the
+/// base, and `I` followed by a big-endian u64 length inserts that many of the
+/// bytes that follow.
+pub fn create(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = MAGIC.to_vec();
+    delta.extend_from_slice(&Sha256::digest(base));
+    delta.extend_from_slice(&Sha256::digest(target));
+    delta.extend_from_slice(&(target.len() as u64).to_be_bytes());
+
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    for (i, block) in base.chunks_exact(BLOCK_SIZE).enumerate() {
+        blocks.entry(block).or_insert(i * BLOCK_SIZE);
+    }
+    let mut pending = Vec::new();
+    let mut pos = 0;
+    while pos < target.len() {
+        let offset = target
+            .get(pos..pos + BLOCK_SIZE)
+            .and_then(|window| blocks.get(window));
+        match offset {
+            Some(&offset) => {
+                let len = base[offset..]
+                    .iter()
+                    .zip(&target[pos..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                push_insert(&mut delta, &mut pending);
+                delta.push(COPY);
+                delta.extend_from_slice(&(offset as u64).to_be_bytes());
+                delta.extend_from_slice(&(len as u64).to_be_bytes());
+                pos += len;
+            }
+            None => {
+                pending.push(target[pos]);
+                pos += 1;
+            }
+        }
+    }
+    push_insert(&mut delta, &mut pending);
+    delta
+}
+
+fn push_insert(delta: &mut Vec<u8>, pending: &mut Vec<u8>) {
+    if !pending.is_empty() {
+        delta.push(INSERT);
+        delta.extend_from_slice(&(pending.len() as u64).to_be_bytes());
+        delta.append(pending);
+    }
+}
+
+fn invalid(reason: &str) -> SgetError {
+    SgetError::InvalidMaterial(anyhow!("Invalid delta: {}", reason))
+}
+
+/// Reads the fields of a delta.
+struct Reader<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.rest.len() < len {
+            return Err(invalid("truncated"));
+        }
+        let (taken, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Ok(taken)
+    }
+
+    fn length(&mut self) -> Result<usize> {
+        let bytes = self.take(8)?.try_into().map_err(|_| invalid("truncated"))?;
+        usize::try_from(u64::from_be_bytes(bytes)).map_err(|_| invalid("length overflow"))
+    }
+}
+
+/// The SHA-256 digests of the base and target of `delta`, hex encoded.
+pub fn digests(delta: &[u8]) -> Result<(String, String)> {
+    let mut reader = Reader { rest: delta };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(invalid("not a delta"));
+    }
+    Ok((hex::encode(reader.take(32)?), hex::encode(reader.take(32)?)))
+}
+
+/// Apply `delta` to `base`, checking that the base is the one the delta was
+/// made from and that the result has the target length and digest. Targets
+/// longer than [`MAX_TARGET_LEN`] are rejected before they are built.
+pub fn apply(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let (base_digest, target_digest) = digests(delta)?;
+    let actual = hex::encode(Sha256::digest(base));
+    if actual != base_digest {
+        return Err(SgetError::DigestMismatch {
+            expected: base_digest,
+            actual,
+        });
+    }
+    let mut reader = Reader {
+        rest: &delta[HEADER_LEN - 8..],
+    };
+    let target_len = reader.length()?;
+    if target_len > MAX_TARGET_LEN {
+        return Err(invalid("target too long"));
+    }
+    let mut target = Vec::with_capacity(target_len);
+    while let Ok(op) = reader.take(1) {
+        let range = match op[0] {
+            COPY => {
+                let offset = reader.length()?;
+                let len = reader.length()?;
+                offset
+                    .checked_add(len)
+                    .and_then(|end| base.get(offset..end))
+                    .ok_or_else(|| invalid("copy beyond the base"))?
+            }
+            INSERT => {
+                let len = reader.length()?;
+                reader.take(len)?
+            }
+            _ => return Err(invalid("unknown instruction")),
+        };
+        if range.len() > target_len - target.len() {
+            return Err(invalid("longer than its target"));
+        }
+        target.extend_from_slice(range);
+    }
+    if target.len() != target_len {
+        return Err(invalid("shorter than its target"));
+    }
+    let actual = hex::encode(Sha256::digest(&target));
+    if actual != target_digest {
+        return Err(SgetError::DigestMismatch {
+            expected: target_digest,
+            actual,
+        });
+    }
+    Ok(target)
+}
+
+#[cfg(feature = "native")]
+pub use self::cache::ArtifactCache;
+
+#[cfg(feature = "native")]
+mod cache {
+    use anyhow::{Context, Result};
+    use sha2::{Digest, Sha256};
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::state;
+
+    /// The last verified version of artifacts that have deltas, by URL, to
+    /// apply the next delta to. Versions are stored by SHA-256 digest, with
+    /// `index.json` mapping URLs to digests.
+    pub struct ArtifactCache {
+        dir: PathBuf,
+    }
+
+    impl ArtifactCache {
+        pub fn new(dir: impl Into<PathBuf>) -> Self {
+            ArtifactCache { dir: dir.into() }
+        }
+
+        /// The cache in the state directory.
+        pub fn open() -> Result<Self> {
+            Ok(Self::new(state::state_dir()?.join("cache")))
+        }
+
+        fn index(&self) -> BTreeMap<String, String> {
+            fs::read(self.dir.join("index.json"))
+                .ok()
+                .and_then(|raw| serde_json::from_slice(&raw).ok())
+                .unwrap_or_default()
+        }
+
+        /// The digest and contents of the cached version of `url`, if it is
+        /// still intact.
+        pub fn get(&self, url: &str) -> Option<(String, Vec<u8>)> {
+            let digest = self.index().remove(url)?;
+            let data = fs::read(self.dir.join(&digest)).ok()?;
+            if hex::encode(Sha256::digest(&data)) != digest {
+                return None;
+            }
+            Some((digest, data))
+        }
+
+        /// Cache `data` as the latest version of `url`, replacing the previous
+        /// version.
+        pub fn put(&self, url: &str, data: &[u8]) -> Result<()> {
+            fs::create_dir_all(&self.dir)
+                .with_context(|| format!("Cannot create {}", self.dir.display()))?;
+            let digest = hex::encode(Sha256::digest(data));
+            fs::write(self.dir.join(&digest), data)?;
+            let mut index = self.index();
+            let previous = index.insert(url.to_string(), digest.clone());
+            fs::write(self.dir.join("index.json"), serde_json::to_vec(&index)?)?;
+            if let Some(previous) = previous {
+                if previous != digest && !index.values().any(|d| *d == previous) {
+                    fs::remove_file(self.dir.join(previous)).ok();
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+static CACHE_HITS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+#[cfg(feature = "native")]
+static CACHE_MISSES: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// How many times in this process [`fetch_patched`] patched a cached version,
+/// and how many times it found none to patch or no usable delta.
+#[cfg(feature = "native")]
+pub fn cache_stats() -> (u64, u64) {
+    use std::sync::atomic::Ordering;
+
+    (
+        CACHE_HITS.load(Ordering::Relaxed),
+        CACHE_MISSES.load(Ordering::Relaxed),
+    )
+}
+
+/// Fetch the delta from the cached version of `url` to its latest version
+/// from `template`, with `{base}` replaced by the cached version's digest,
+/// and apply it. `None` if there is no cached version or no usable delta, so
+/// that the caller can fall back to a full download.
+#[cfg(feature = "native")]
+pub async fn fetch_patched(
+    transport: &dyn crate::transport::Transport,
+    base: &std::path::Path,
+    url: &str,
+    template: &str,
+) -> Option<Vec<u8>> {
+    use std::sync::atomic::Ordering;
+
+    let patched = async {
+        let (digest, previous) = ArtifactCache::open().ok()?.get(url)?;
+        let location = template.replace("{base}", &digest);
+        let delta = crate::fetch::fetch(transport, &location, base).await.ok()?;
+        apply(&previous, &delta).ok()
+    }
+    .await;
+    let counter = match patched {
+        Some(_) => &CACHE_HITS,
+        None => &CACHE_MISSES,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+    patched
+}
+
+#[cfg(feature = "native")]
+pub(crate) fn command() -> clap::App<'static> {
+    use clap::{App, Arg};
+
+    App::new("delta")
+        .about("Create a binary delta between two versions of an artifact, for clients with the old version cached")
+        .arg(
+            Arg::new("base")
+                .value_name("OLD")
+                .required(true)
+                .about("The previous version"),
+        )
+        .arg(
+            Arg::new("target")
+                .value_name("NEW")
+                .required(true)
+                .about("The new version"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .value_name("DELTA")
+                .takes_value(true)
+                .about("Where to write the delta [default: NEW.delta/<SHA-256 of OLD>]"),
+        )
+}
+
+/// A delta written by `sget delta`.
+#[cfg(feature = "native")]
+pub(crate) struct WrittenDelta {
+    pub size: usize,
+    /// The size of the new version.
+    pub target_size: usize,
+    pub path: std::path::PathBuf,
+}
+
+#[cfg(feature = "native")]
+pub(crate) fn run(matches: &clap::ArgMatches) -> anyhow::Result<WrittenDelta> {
+    use anyhow::Context;
+
+    let read = |name| -> anyhow::Result<Vec<u8>> {
+        let path = matches
+            .value_of(name)
+            .ok_or_else(|| anyhow!("No {} given", name))?;
+        std::fs::read(path).with_context(|| format!("Cannot read {}", path))
+    };
+    let (base, target) = (read("base")?, read("target")?);
+    let delta = create(&base, &target);
+    let output = match matches.value_of("output") {
+        Some(output) => std::path::PathBuf::from(output),
+        None => {
+            let dir = format!("{}.delta", matches.value_of("target").unwrap_or_default());
+            std::fs::create_dir_all(&dir)?;
+            std::path::Path::new(&dir).join(hex::encode(Sha256::digest(&base)))
+        }
+    };
+    std::fs::write(&output, &delta)?;
+    Ok(WrittenDelta {
+        size: delta.len(),
+        target_size: target.len(),
+        path: output,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let base: Vec<u8> = (0..20_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut target = base.clone();
+        target[1000..1010].copy_from_slice(b"0123456789");
+        target.splice(40_000..40_000, b"inserted".iter().copied());
+        target.truncate(70_000);
+        target.extend_from_slice(b"appended");
+
+        let delta = create(&base, &target);
+        assert!(delta.len() < target.len() / 100);
+        assert_eq!(apply(&base, &delta).expect("Cannot apply"), target);
+        let (base_digest, target_digest) = digests(&delta).expect("Invalid delta");
+        assert_eq!(base_digest, hex::encode(Sha256::digest(&base)));
+        assert_eq!(target_digest, hex::encode(Sha256::digest(&target)));
+
+        // Only the base it was made from can be patched.
+        assert!(matches!(
+            apply(&target, &delta),
+            Err(SgetError::DigestMismatch { .. })
+        ));
+        // Unrelated data round trips too, by insertion.
+        assert_eq!(
+            apply(b"", &create(b"", b"new")).expect("Cannot apply"),
+            b"new"
+        );
+        assert!(apply(&base, &delta[..delta.len() - 1]).is_err());
+
+        // Copies are checked against the target length as they are applied,
+        // and the target length against the limit.
+        let mut repeated = delta[..HEADER_LEN].to_vec();
+        for _ in 0..2 {
+            repeated.push(COPY);
+            repeated.extend_from_slice(&0u64.to_be_bytes());
+            repeated.extend_from_slice(&(base.len() as u64).to_be_bytes());
+        }
+        assert!(matches!(
+            apply(&base, &repeated),
+            Err(SgetError::InvalidMaterial(_))
+        ));
+        let mut huge = delta.clone();
+        huge[HEADER_LEN - 8..HEADER_LEN].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(matches!(
+            apply(&base, &huge),
+            Err(SgetError::InvalidMaterial(_))
+        ));
+    }
+}
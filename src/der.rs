@@ -0,0 +1,158 @@
+//! Just enough DER to read CRLs, OCSP responses and RFC 3161 time-stamp
+//! tokens, which x509-parser does not cover.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use ring::signature::{self as ring_signature, UnparsedPublicKey, VerificationAlgorithm};
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+// DER encoded object identifiers.
+pub(crate) const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+pub(crate) const OID_SHA384: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02];
+pub(crate) const OID_SHA512: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03];
+const OID_SHA256_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const OID_SHA384_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const OID_SHA512_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+
+// DER tags.
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const BIT_STRING: u8 = 0x03;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const NULL: u8 = 0x05;
+pub(crate) const OID: u8 = 0x06;
+pub(crate) const ENUMERATED: u8 = 0x0a;
+pub(crate) const GENERALIZED_TIME: u8 = 0x18;
+pub(crate) const SEQUENCE: u8 = 0x30;
+pub(crate) const SET: u8 = 0x31;
+/// The first context specific tag of a constructed element, `[0]`.
+pub(crate) const CONTEXT_0: u8 = 0xa0;
+
+/// A DER element: its tag, its contents and its whole encoding. Only single
+/// byte tags are supported.
+pub(crate) struct Element<'a> {
+    pub tag: u8,
+    pub contents: &'a [u8],
+    pub raw: &'a [u8],
+}
+
+pub(crate) fn element(input: &[u8]) -> Result<(Element<'_>, &[u8])> {
+    let truncated = || anyhow!("Truncated DER element");
+    let (&tag, rest) = input.split_first().ok_or_else(truncated)?;
+    let (&first, rest) = rest.split_first().ok_or_else(truncated)?;
+    let (len, rest) = match first {
+        0..=0x7f => (first as usize, rest),
+        0x81..=0x84 => {
+            let count = (first & 0x7f) as usize;
+            if rest.len() < count {
+                return Err(truncated());
+            }
+            let (len, rest) = rest.split_at(count);
+            (len.iter().fold(0, |len, b| len << 8 | *b as usize), rest)
+        }
+        _ => return Err(anyhow!("Unsupported DER length")),
+    };
+    if rest.len() < len {
+        return Err(truncated());
+    }
+    let header = input.len() - rest.len();
+    let element = Element {
+        tag,
+        contents: &rest[..len],
+        raw: &input[..header + len],
+    };
+    Ok((element, &rest[len..]))
+}
+
+/// The elements in the contents of a constructed element.
+pub(crate) fn children(mut contents: &[u8]) -> Result<Vec<Element<'_>>> {
+    let mut elements = Vec::new();
+    while !contents.is_empty() {
+        let (element, rest) = element(contents)?;
+        elements.push(element);
+        contents = rest;
+    }
+    Ok(elements)
+}
+
+/// The children of the constructed element `parent`, which must have `tag`.
+pub(crate) fn expect<'a>(parent: &Element<'a>, tag: u8) -> Result<Vec<Element<'a>>> {
+    if parent.tag != tag {
+        return Err(anyhow!("Unexpected DER tag {:#04x}", parent.tag));
+    }
+    children(parent.contents)
+}
+
+pub(crate) fn encode(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    let len = contents.len();
+    if len < 0x80 {
+        encoded.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        encoded.push(0x80 | (bytes.len() - skip) as u8);
+        encoded.extend_from_slice(&bytes[skip..]);
+    }
+    encoded.extend_from_slice(contents);
+    encoded
+}
+
+pub(crate) fn generalized_time(element: &Element) -> Result<DateTime<Utc>> {
+    if element.tag != GENERALIZED_TIME {
+        return Err(anyhow!("Expected a GeneralizedTime"));
+    }
+    let text = std::str::from_utf8(element.contents)?;
+    // Fractional seconds are allowed, but not needed here.
+    let seconds = text
+        .get(..14)
+        .ok_or_else(|| anyhow!("Invalid time {}", text))?;
+    if !text.ends_with('Z') {
+        return Err(anyhow!("Time {} is not in UTC", text));
+    }
+    let time = NaiveDateTime::parse_from_str(seconds, "%Y%m%d%H%M%S")?;
+    Ok(DateTime::from_utc(time, Utc))
+}
+
+/// The object identifier of the AlgorithmIdentifier `algorithm`.
+pub(crate) fn algorithm_oid<'a>(algorithm: &Element<'a>) -> Result<&'a [u8]> {
+    expect(algorithm, SEQUENCE)?
+        .into_iter()
+        .next()
+        .filter(|oid| oid.tag == OID)
+        .map(|oid| oid.contents)
+        .ok_or_else(|| anyhow!("Invalid algorithm identifier"))
+}
+
+/// The signature algorithm with the DER encoded object identifier `oid`.
+pub(crate) fn signature_algorithm(oid: &[u8]) -> Result<&'static dyn VerificationAlgorithm> {
+    Ok(match oid {
+        OID_SHA256_WITH_RSA => &ring_signature::RSA_PKCS1_2048_8192_SHA256,
+        OID_SHA384_WITH_RSA => &ring_signature::RSA_PKCS1_2048_8192_SHA384,
+        OID_SHA512_WITH_RSA => &ring_signature::RSA_PKCS1_2048_8192_SHA512,
+        OID_ECDSA_WITH_SHA256 => &ring_signature::ECDSA_P256_SHA256_ASN1,
+        OID_ECDSA_WITH_SHA384 => &ring_signature::ECDSA_P384_SHA384_ASN1,
+        OID_ED25519 => &ring_signature::ED25519,
+        _ => return Err(anyhow!("Unsupported signature algorithm")),
+    })
+}
+
+/// Verify the signature `signature`, a BIT STRING, made with the algorithm
+/// identified by `algorithm` over `signed` by `key`.
+pub(crate) fn verify_signed(
+    signed: &[u8],
+    algorithm: &Element,
+    signature: &Element,
+    key: &SubjectPublicKeyInfo,
+) -> Result<()> {
+    let verification = signature_algorithm(algorithm_oid(algorithm)?)?;
+    let signature = match (signature.tag, signature.contents.split_first()) {
+        (BIT_STRING, Some((0, signature))) => signature,
+        _ => return Err(anyhow!("Invalid signature")),
+    };
+    UnparsedPublicKey::new(verification, key.subject_public_key.data)
+        .verify(signed, signature)
+        .map_err(|_| anyhow!("Invalid signature"))
+}
@@ -0,0 +1,464 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use clap::{App, Arg, ArgMatches};
+use http::header::DATE;
+use http::StatusCode;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::identities::IdentityMap;
+use crate::profile;
+use crate::sigstore_env::SigstoreEnv;
+use crate::state;
+use crate::system_policy::SystemPolicy;
+use crate::transport::{self, Transport};
+use crate::trust::{TrustStore, TRUST_KINDS};
+use crate::verify::TrustRoots;
+
+/// The largest difference from Rekor's clock that is not reported. Fulcio
+/// certificates are valid for ten minutes, so larger skews soon break
+/// keyless signing and verification of fresh signatures.
+const MAX_CLOCK_SKEW_SECS: i64 = 60;
+
+/// How a check went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Ok,
+    Warning,
+    Failure,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Failure => "FAILED",
+        })
+    }
+}
+
+/// The outcome of one check, and what to do about it unless it went well.
+#[derive(Debug)]
+pub struct Finding {
+    pub check: String,
+    pub status: Status,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(check: &str, detail: impl Into<String>) -> Self {
+        Finding {
+            check: check.to_string(),
+            status: Status::Ok,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(check: &str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Finding {
+            status: Status::Warning,
+            hint: Some(hint.into()),
+            ..Self::ok(check, detail)
+        }
+    }
+
+    fn failure(check: &str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Finding {
+            status: Status::Failure,
+            ..Self::warning(check, detail, hint)
+        }
+    }
+}
+
+/// The proxy requests go through, without its credentials.
+fn proxy_finding() -> Finding {
+    let configured = Config::load()
+        .map(|config| config.proxy.with_env().url)
+        .unwrap_or_default();
+    let (url, source) = match configured {
+        Some(url) => (url, "the configuration"),
+        None => match ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy"]
+            .iter()
+            .find_map(|name| std::env::var(name).ok().filter(|url| !url.is_empty()))
+        {
+            Some(url) => (url, "the environment"),
+            None => return Finding::ok("proxy", "none; connecting directly"),
+        },
+    };
+    match reqwest::Url::parse(&url) {
+        Ok(mut url) => {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            Finding::ok("proxy", format!("{}, from {}", url, source))
+        }
+        Err(e) => Finding::failure(
+            "proxy",
+            format!("Invalid proxy URL from {}: {}", source, e),
+            "Fix the proxy URL, e.g. http://proxy.corp.example:3128",
+        ),
+    }
+}
+
+/// GET `url` and check that its status is `expected`, returning the server's
+/// clock from the `Date` header too.
+async fn check_endpoint(
+    transport: &dyn Transport,
+    check: &str,
+    url: &str,
+    expected: &[StatusCode],
+) -> (Finding, Option<DateTime<Utc>>) {
+    let hint = "Check network access to it; behind a proxy, set `proxy` in the configuration or HTTPS_PROXY";
+    let request = match transport::get(url) {
+        Ok(request) => request,
+        Err(e) => return (Finding::failure(check, format!("{:#}", e), hint), None),
+    };
+    match transport.send(request).await {
+        Ok(response) => {
+            let date = response
+                .headers()
+                .get(DATE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+                .map(|date| date.with_timezone(&Utc));
+            let finding = if expected.contains(&response.status()) {
+                Finding::ok(check, format!("{} reachable", url))
+            } else {
+                Finding::failure(
+                    check,
+                    format!("{} returned {}", url, response.status()),
+                    "A proxy or firewall may be intercepting the request",
+                )
+            };
+            (finding, date)
+        }
+        Err(e) => (
+            Finding::failure(check, format!("{}: {}", url, e.root_cause()), hint),
+            None,
+        ),
+    }
+}
+
+/// Compare the local clock, `now`, with `server`'s.
+fn check_clock(server: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Finding {
+    let server = match server {
+        Some(server) => server,
+        None => {
+            return Finding::warning(
+                "clock",
+                "Cannot compare the clock: Rekor sent no date",
+                "Check that Rekor is reachable",
+            )
+        }
+    };
+    let skew = now - server;
+    if skew.num_seconds().abs() <= MAX_CLOCK_SKEW_SECS {
+        return Finding::ok("clock", format!("within {}s of Rekor", MAX_CLOCK_SKEW_SECS));
+    }
+    let direction = if skew > Duration::zero() {
+        "ahead of"
+    } else {
+        "behind"
+    };
+    Finding::failure(
+        "clock",
+        format!(
+            "{}s {} Rekor",
+            skew.num_seconds().abs(),
+            direction
+        ),
+        "Synchronize the system clock, e.g. enable NTP; keyless certificates are only valid for ten minutes",
+    )
+}
+
+/// Check that sget can write to the directory `path`, creating it if needed,
+/// and that other users cannot.
+fn check_dir(check: &str, path: &Path) -> Finding {
+    let display = path.display();
+    if let Err(e) = fs::create_dir_all(path) {
+        return Finding::failure(
+            check,
+            format!("Cannot create {}: {}", display, e),
+            "Make its parent writable by this user, or set SGET_STATE_DIR",
+        );
+    }
+    if let Err(e) = tempfile::tempfile_in(path) {
+        return Finding::failure(
+            check,
+            format!("Cannot write to {}: {}", display, e),
+            format!(
+                "Make {} writable by this user, or set SGET_STATE_DIR",
+                display
+            ),
+        );
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)
+            .map(|m| m.permissions().mode())
+            .unwrap_or(0);
+        if mode & 0o002 != 0 {
+            return Finding::failure(
+                check,
+                format!("{} is writable by every user", display),
+                format!(
+                    "Anyone could change what sget trusts; run `chmod o-w {}`",
+                    display
+                ),
+            );
+        }
+    }
+    Finding::ok(check, display.to_string())
+}
+
+/// Check the configuration, the system policy, the identity map, and the
+/// entries and roots of the trust store.
+fn check_trust() -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let config = Config::path()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    findings.push(match Config::load() {
+        Ok(_) => Finding::ok("configuration", config),
+        Err(e) => Finding::failure(
+            "configuration",
+            format!("{:#}", e),
+            "Fix the configuration file",
+        ),
+    });
+    findings.push(match SystemPolicy::load() {
+        Ok(_) => Finding::ok("system policy", SystemPolicy::dir().display().to_string()),
+        Err(e) => Finding::failure(
+            "system policy",
+            format!("{:#}", e),
+            "Ask the administrator to fix the system policy",
+        ),
+    });
+    findings.push(match IdentityMap::load() {
+        Ok(_) => Finding::ok("identity map", "valid"),
+        Err(e) => Finding::failure("identity map", format!("{:#}", e), "Fix identities.yaml"),
+    });
+
+    let store = match TrustStore::open() {
+        Ok(store) => store,
+        Err(e) => {
+            findings.push(Finding::failure(
+                "trust store",
+                format!("{:#}", e),
+                "Set SGET_STATE_DIR",
+            ));
+            return findings;
+        }
+    };
+    for kind in TRUST_KINDS {
+        let entries = match store.list(kind) {
+            Ok(entries) => entries,
+            Err(e) => {
+                findings.push(Finding::failure(
+                    "trust store",
+                    format!("Cannot list {} entries: {:#}", kind, e),
+                    "Check the permissions of the trust store",
+                ));
+                continue;
+            }
+        };
+        for entry in entries {
+            let check = format!("trusted {} {}", kind, entry.name);
+            let valid = store
+                .read(kind, &entry.name)
+                .and_then(|contents| kind.validate(&contents));
+            findings.push(match valid {
+                Ok(()) => Finding::ok(&check, "valid"),
+                Err(e) => Finding::failure(
+                    &check,
+                    format!("{:#}", e),
+                    format!(
+                        "Replace it with `sget trust add {} {} FILE`, or remove it",
+                        kind, entry.name
+                    ),
+                ),
+            });
+        }
+    }
+    findings.push(match TrustRoots::load(&store) {
+        Ok(roots) => Finding::ok(
+            "trust roots",
+            format!(
+                "{} Fulcio root(s), {} Rekor key(s) for {}",
+                roots.fulcio_roots.len(),
+                roots.rekor_keys.len(),
+                SigstoreEnv::current()
+            ),
+        ),
+        Err(e) => Finding::failure(
+            "trust roots",
+            e.to_string(),
+            "Add roots with `sget trust add`",
+        ),
+    });
+    findings
+}
+
+/// Run every check: connectivity through `transport` to Fulcio, Rekor and
+/// `registries`, the clock, the state directories, and what sget trusts.
+pub async fn diagnose(transport: &dyn Transport, registries: &[String]) -> Vec<Finding> {
+    let mut findings = vec![proxy_finding()];
+    if let Some((name, _)) = profile::active() {
+        findings.push(Finding::ok("profile", name));
+    }
+    if transport::is_offline() {
+        findings.push(Finding::warning(
+            "network",
+            "Skipped: offline",
+            "Run without --offline to check connectivity",
+        ));
+    } else {
+        let fulcio = format!("{}/api/v1/rootCert", profile::fulcio_url());
+        findings.push(
+            check_endpoint(transport, "fulcio", &fulcio, &[StatusCode::OK])
+                .await
+                .0,
+        );
+        let rekor = format!("{}/api/v1/log", profile::rekor_url());
+        let (finding, date) = check_endpoint(transport, "rekor", &rekor, &[StatusCode::OK]).await;
+        let reachable = finding.status == Status::Ok;
+        findings.push(finding);
+        if reachable {
+            findings.push(check_clock(date, Utc::now()));
+        }
+        for registry in registries {
+            let host = match registry.as_str() {
+                "docker.io" => "registry-1.docker.io",
+                registry => registry,
+            };
+            let url = format!("https://{}/v2/", host);
+            // Registries answer 401 to anonymous clients, which still shows
+            // they are reachable.
+            let expected = [StatusCode::OK, StatusCode::UNAUTHORIZED];
+            let check = format!("registry {}", registry);
+            findings.push(check_endpoint(transport, &check, &url, &expected).await.0);
+        }
+    }
+    match state::state_dir() {
+        Ok(dir) => {
+            findings.push(check_dir("state directory", &dir));
+            findings.push(check_dir("cache directory", &dir.join("cache")));
+            findings.push(check_dir(
+                "trust store directory",
+                &dir.join(SigstoreEnv::current().trust_dir()),
+            ));
+        }
+        Err(e) => findings.push(Finding::failure(
+            "state directory",
+            format!("{:#}", e),
+            "Set SGET_STATE_DIR",
+        )),
+    }
+    findings.extend(check_trust());
+    findings
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("doctor")
+        .about("Diagnose the environment: connectivity to Fulcio, Rekor and registries, the clock, state directories, and the trust store")
+        .arg(
+            Arg::new("registry")
+                .long("registry")
+                .value_name("REGISTRY")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .about("Also check connectivity to this OCI registry, e.g. ghcr.io"),
+        )
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    let registries: Vec<String> = matches
+        .values_of("registry")
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect();
+    let transport = transport::default_transport();
+    let findings = diagnose(transport.as_ref(), &registries).await;
+    for finding in &findings {
+        println!(
+            "[{:>7}] {}: {}",
+            finding.status, finding.check, finding.detail
+        );
+        if let Some(hint) = &finding.hint {
+            println!("          {}", hint);
+        }
+    }
+    let failures = findings
+        .iter()
+        .filter(|finding| finding.status == Status::Failure)
+        .count();
+    if failures > 0 {
+        return Err(anyhow::anyhow!("{} check(s) failed", failures));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{Request, Response};
+    use async_trait::async_trait;
+
+    /// Answers every request with `status` and a `Date` header of `date`.
+    struct Server {
+        status: u16,
+        date: DateTime<Utc>,
+    }
+
+    #[async_trait]
+    impl Transport for Server {
+        async fn send(&self, _: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+            Ok(Response::builder()
+                .status(self.status)
+                .header(DATE, self.date.to_rfc2822())
+                .body(Vec::new())?)
+        }
+    }
+
+    #[tokio::test]
+    async fn diagnose_environment() {
+        let now = Utc::now();
+        let server = Server {
+            status: 401,
+            date: now - Duration::minutes(5),
+        };
+        let url = "https://ghcr.io/v2/";
+        let (registry, date) = check_endpoint(
+            &server,
+            "registry",
+            url,
+            &[StatusCode::OK, StatusCode::UNAUTHORIZED],
+        )
+        .await;
+        assert_eq!(registry.status, Status::Ok);
+        let (rekor, _) = check_endpoint(&server, "rekor", url, &[StatusCode::OK]).await;
+        assert_eq!(rekor.status, Status::Failure);
+
+        let skewed = check_clock(date, now);
+        assert_eq!(skewed.status, Status::Failure);
+        assert!(skewed.detail.contains("ahead of"), "{}", skewed.detail);
+        assert_eq!(check_clock(Some(now), now).status, Status::Ok);
+
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let state = dir.path().join("state");
+        assert_eq!(check_dir("state", &state).status, Status::Ok);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&state, fs::Permissions::from_mode(0o777))
+                .expect("Cannot change permissions");
+            assert_eq!(check_dir("state", &state).status, Status::Failure);
+        }
+    }
+}
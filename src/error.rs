@@ -0,0 +1,211 @@
+use chrono::{DateTime, Utc};
+use std::fmt;
+use thiserror::Error;
+
+use crate::pipeline::Stage;
+
+/// Why verifying an artifact failed, coarsely; see [`SgetError::reason`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FailureReason {
+    /// The artifact or its signature material could not be fetched.
+    Fetch,
+    /// The artifact does not have the pinned digest.
+    Digest,
+    /// The manifest entry itself is inconsistent.
+    Entry,
+    /// The signature, key, certificate or bundle could not be parsed.
+    Material,
+    /// The signature does not verify or the signer is not the expected one.
+    Signature,
+    /// A stage hook rejected the artifact.
+    Rejected,
+    /// The artifact has no acceptable build provenance.
+    Provenance,
+    /// The artifact's supply chain does not satisfy its in-toto layout.
+    Layout,
+    /// The artifact has no valid SBOM.
+    Sbom,
+    /// The artifact could not be decrypted.
+    Decryption,
+}
+
+impl FailureReason {
+    /// Short label for the reason, as used in metrics.
+    pub fn label(self) -> &'static str {
+        match self {
+            FailureReason::Fetch => "fetch",
+            FailureReason::Digest => "digest",
+            FailureReason::Entry => "entry",
+            FailureReason::Material => "material",
+            FailureReason::Signature => "signature",
+            FailureReason::Rejected => "rejected",
+            FailureReason::Provenance => "provenance",
+            FailureReason::Layout => "layout",
+            FailureReason::Sbom => "sbom",
+            FailureReason::Decryption => "decryption",
+        }
+    }
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureReason::Fetch => "fetch failed",
+            FailureReason::Digest => "digest mismatch",
+            FailureReason::Entry => "invalid entry",
+            FailureReason::Material => "invalid signature material",
+            FailureReason::Signature => "verification failed",
+            FailureReason::Rejected => "rejected by hook",
+            FailureReason::Provenance => "provenance check failed",
+            FailureReason::Layout => "layout check failed",
+            FailureReason::Sbom => "SBOM check failed",
+            FailureReason::Decryption => "decryption failed",
+        })
+    }
+}
+
+fn missing_keys(missing: &[String]) -> String {
+    match missing {
+        [] => String::new(),
+        [key] => format!("; required key {} missing", key),
+        keys => format!("; required keys {} missing", keys.join(", ")),
+    }
+}
+
+/// Errors returned by fetching and verification.
+#[derive(Debug, Error)]
+pub enum SgetError {
+    #[error("Cannot fetch {location}")]
+    Fetch {
+        location: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("Server returned something unexpected for {location}: {reason}")]
+    UnexpectedContent { location: String, reason: String },
+    #[error("Digest mismatch: expected {expected}, got {actual}")]
+    DigestMismatch { expected: String, actual: String },
+    #[error("Invalid manifest entry: {0}")]
+    InvalidEntry(String),
+    #[error("Invalid signature material")]
+    InvalidMaterial(#[source] anyhow::Error),
+    #[error("Invalid signature")]
+    InvalidSignature(#[source] anyhow::Error),
+    #[error("Untrusted certificate: {0}")]
+    UntrustedCertificate(String),
+    #[error("Signed by {actual}, expected {expected}")]
+    IdentityMismatch { expected: String, actual: String },
+    #[error("Untrusted signer: {0}")]
+    UntrustedSigner(String),
+    #[error("Transparency log error: {0}")]
+    TransparencyLogError(String),
+    #[error("Disallowed algorithm: {0}")]
+    DisallowedAlgorithm(String),
+    #[error("Policy expired at {0}")]
+    PolicyExpired(DateTime<Utc>),
+    #[error("The {role} role expired at {expires}")]
+    RoleExpired {
+        role: String,
+        expires: DateTime<Utc>,
+    },
+    #[error(
+        "Possible freeze attack: policy version {version} has been served unchanged since {since}"
+    )]
+    PossibleFreezeAttack { version: u64, since: DateTime<Utc> },
+    #[error(
+        "Policy has {found} of {required} required root signatures{}",
+        missing_keys(.missing)
+    )]
+    ThresholdNotMet {
+        found: usize,
+        required: u64,
+        /// The root keys without a valid signature, by name or key ID.
+        missing: Vec<String>,
+    },
+    #[error("Invalid policy: {0}")]
+    InvalidPolicy(String),
+    #[error(
+        "Policy spec version {0} is not supported; this sget reads spec version {}.x policies",
+        crate::policy::SPEC_MAJOR
+    )]
+    UnsupportedSpecVersion(String),
+    #[error("Invalid {role} metadata: {reason}")]
+    InvalidMetadata { role: String, reason: String },
+    #[error("Unacceptable provenance: {0}")]
+    Provenance(String),
+    #[error("In-toto layout not satisfied: {0}")]
+    Layout(String),
+    #[error("No valid SBOM: {0}")]
+    Sbom(String),
+    #[error("Cannot decrypt artifact")]
+    Decryption(#[source] anyhow::Error),
+    #[error("Claim policy not satisfied: {0}")]
+    ClaimPolicy(String),
+    #[error("Rejected during {stage}")]
+    Rejected {
+        stage: Stage,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("Not approved to run: {0}")]
+    Unapproved(String),
+    #[error("Offline: {0} needs network access")]
+    Offline(String),
+    #[error("Cancelled")]
+    Cancelled,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl SgetError {
+    /// The coarse classification of the error, if it is a verification failure.
+    pub fn reason(&self) -> Option<FailureReason> {
+        match self {
+            SgetError::Fetch { .. }
+            | SgetError::UnexpectedContent { .. }
+            | SgetError::Offline(_) => Some(FailureReason::Fetch),
+            SgetError::DigestMismatch { .. } => Some(FailureReason::Digest),
+            SgetError::InvalidEntry(_) => Some(FailureReason::Entry),
+            SgetError::InvalidMaterial(_) => Some(FailureReason::Material),
+            SgetError::InvalidSignature(_)
+            | SgetError::UntrustedCertificate(_)
+            | SgetError::IdentityMismatch { .. }
+            | SgetError::UntrustedSigner(_)
+            | SgetError::TransparencyLogError(_)
+            | SgetError::DisallowedAlgorithm(_)
+            | SgetError::ClaimPolicy(_) => Some(FailureReason::Signature),
+            SgetError::Rejected { .. } => Some(FailureReason::Rejected),
+            SgetError::Provenance(_) => Some(FailureReason::Provenance),
+            SgetError::Layout(_) => Some(FailureReason::Layout),
+            SgetError::Sbom(_) => Some(FailureReason::Sbom),
+            SgetError::Decryption(_) => Some(FailureReason::Decryption),
+            SgetError::PolicyExpired(_)
+            | SgetError::RoleExpired { .. }
+            | SgetError::PossibleFreezeAttack { .. }
+            | SgetError::ThresholdNotMet { .. }
+            | SgetError::InvalidPolicy(_)
+            | SgetError::UnsupportedSpecVersion(_)
+            | SgetError::InvalidMetadata { .. }
+            | SgetError::Unapproved(_)
+            | SgetError::Cancelled
+            | SgetError::Io(_)
+            | SgetError::Other(_) => None,
+        }
+    }
+
+    /// The error and all its sources, joined like anyhow's `{:#}`.
+    pub fn describe(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(error) = source {
+            message.push_str(": ");
+            message.push_str(&error.to_string());
+            source = error.source();
+        }
+        message
+    }
+}
+
+pub type Result<T, E = SgetError> = std::result::Result<T, E>;
@@ -0,0 +1,308 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::Path;
+use tar::{Builder, Header};
+
+use crate::manifest::EntryResult;
+use crate::verify::{certificate_pem, TrustRoots};
+
+/// Name of the index file at the root of an evidence bundle.
+pub const INDEX: &str = "evidence.json";
+
+/// The index of an evidence bundle. File fields are paths within the bundle.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Evidence {
+    pub sget_version: String,
+    pub created: DateTime<Utc>,
+    /// The manifest the artifacts were verified against.
+    pub manifest: String,
+    pub fulcio_roots: Vec<String>,
+    pub rekor_keys: Vec<String>,
+    pub artifacts: Vec<ArtifactEvidence>,
+}
+
+/// What was decided about one artifact, and the material it was decided on.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArtifactEvidence {
+    pub url: String,
+    pub sha256: Option<String>,
+    pub verified: bool,
+    pub signer: Option<String>,
+    pub issuer: Option<String>,
+    pub integrated_time: Option<i64>,
+    pub error: Option<String>,
+    pub signature: Option<String>,
+    pub key: Option<String>,
+    pub certificate: Option<String>,
+    pub bundle: Option<String>,
+    pub keyring: Option<String>,
+    pub allowed_signers: Option<String>,
+    /// CRLs and OCSP responses the certificate was checked against.
+    #[serde(default)]
+    pub crls: Vec<String>,
+    #[serde(default)]
+    pub ocsp_responses: Vec<String>,
+    pub provenance: Option<String>,
+    /// The in-toto layout, followed by its keys and links.
+    #[serde(default)]
+    pub layout: Vec<String>,
+    pub sbom: Option<String>,
+    pub claim_policy: Option<String>,
+    /// The custom metadata of the manifest entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<serde_json::Value>,
+}
+
+/// Adds files to a gzipped tarball.
+struct Archive {
+    builder: Builder<GzEncoder<File>>,
+    mtime: u64,
+}
+
+impl Archive {
+    fn add(&mut self, path: &str, contents: &[u8]) -> Result<String> {
+        let mut header = Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(self.mtime);
+        header.set_cksum();
+        self.builder
+            .append_data(&mut header, path, contents)
+            .with_context(|| format!("Cannot add {} to evidence bundle", path))?;
+        Ok(path.to_string())
+    }
+
+    fn add_optional(&mut self, path: &str, contents: &Option<Vec<u8>>) -> Result<Option<String>> {
+        contents
+            .as_ref()
+            .map(|contents| self.add(path, contents))
+            .transpose()
+    }
+}
+
+/// Write a gzipped tarball to `path` holding everything needed to re-verify
+/// `results` offline: the manifest, the trust roots, and each artifact's
+/// digest, signature material and outcome. Artifacts themselves are not
+/// included; they are identified by digest.
+pub fn export(
+    path: &Path,
+    manifest: &Path,
+    results: &[EntryResult],
+    roots: &TrustRoots,
+) -> Result<()> {
+    let created = Utc::now();
+    let file = File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+    let mut archive = Archive {
+        builder: Builder::new(GzEncoder::new(file, Compression::default())),
+        mtime: created.timestamp().max(0) as u64,
+    };
+
+    let mut evidence = Evidence {
+        sget_version: env!("CARGO_PKG_VERSION").to_string(),
+        created,
+        manifest: archive.add("manifest.yaml", &fs::read(manifest)?)?,
+        fulcio_roots: Vec::new(),
+        rekor_keys: Vec::new(),
+        artifacts: Vec::new(),
+    };
+    for (i, der) in roots.fulcio_roots.iter().enumerate() {
+        let name = format!("trust/fulcio-root-{}.pem", i);
+        evidence
+            .fulcio_roots
+            .push(archive.add(&name, certificate_pem(der).as_bytes())?);
+    }
+    for (i, key) in roots.rekor_keys.iter().enumerate() {
+        let name = format!("trust/rekor-key-{}.pem", i);
+        evidence
+            .rekor_keys
+            .push(archive.add(&name, key.to_pem()?.as_bytes())?);
+    }
+
+    for (i, result) in results.iter().enumerate() {
+        let dir = format!("artifacts/{}", i);
+        let mut artifact = ArtifactEvidence {
+            url: result.url.clone(),
+            sha256: result.digest(),
+            verified: result.outcome.is_ok(),
+            signer: None,
+            issuer: None,
+            integrated_time: None,
+            error: None,
+            signature: None,
+            key: None,
+            certificate: None,
+            bundle: None,
+            keyring: None,
+            allowed_signers: None,
+            crls: Vec::new(),
+            ocsp_responses: Vec::new(),
+            provenance: None,
+            layout: Vec::new(),
+            sbom: None,
+            claim_policy: None,
+            custom: result.custom.clone(),
+        };
+        match &result.outcome {
+            Ok(signer) => {
+                artifact.signer = Some(signer.subject().to_string());
+                artifact.issuer = signer.issuer.clone();
+                artifact.integrated_time = signer.integrated_time;
+            }
+            Err(e) => artifact.error = Some(e.describe()),
+        }
+        if let Some(material) = &result.material {
+            artifact.signature =
+                Some(archive.add(&format!("{}/signature", dir), &material.signature)?);
+            artifact.key = archive.add_optional(&format!("{}/key.pub", dir), &material.key)?;
+            artifact.certificate =
+                archive.add_optional(&format!("{}/certificate.pem", dir), &material.certificate)?;
+            artifact.bundle =
+                archive.add_optional(&format!("{}/bundle.json", dir), &material.bundle)?;
+            artifact.keyring =
+                archive.add_optional(&format!("{}/keyring.asc", dir), &material.keyring)?;
+            artifact.allowed_signers = archive.add_optional(
+                &format!("{}/allowed_signers", dir),
+                &material.allowed_signers,
+            )?;
+            if let Some(revocation) = &material.revocation {
+                for (i, crl) in revocation.crls.iter().enumerate() {
+                    artifact
+                        .crls
+                        .push(archive.add(&format!("{}/crl-{}.crl", dir, i), crl)?);
+                }
+                for (i, response) in revocation.ocsp_responses.iter().enumerate() {
+                    artifact
+                        .ocsp_responses
+                        .push(archive.add(&format!("{}/ocsp-{}.der", dir, i), response)?);
+                }
+            }
+            artifact.provenance = archive.add_optional(
+                &format!("{}/provenance.intoto.jsonl", dir),
+                &material.provenance,
+            )?;
+            artifact.sbom =
+                archive.add_optional(&format!("{}/sbom.intoto.jsonl", dir), &material.sbom)?;
+            artifact.claim_policy =
+                archive.add_optional(&format!("{}/claim-policy", dir), &material.claim_policy)?;
+            if let Some(layout) = &material.layout {
+                let dir = format!("{}/intoto", dir);
+                artifact
+                    .layout
+                    .push(archive.add(&format!("{}/root.layout", dir), &layout.layout)?);
+                for (i, key) in layout.keys.iter().enumerate() {
+                    artifact
+                        .layout
+                        .push(archive.add(&format!("{}/layout-key-{}.pub", dir, i), key)?);
+                }
+                for (name, link) in &layout.links {
+                    artifact
+                        .layout
+                        .push(archive.add(&format!("{}/{}", dir, name), link)?);
+                }
+            }
+        }
+        evidence.artifacts.push(artifact);
+    }
+
+    archive.add(INDEX, &serde_json::to_vec_pretty(&evidence)?)?;
+    archive.builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use crate::manifest::Material;
+    use crate::verify::Signer;
+    use anyhow::anyhow;
+    use flate2::read::GzDecoder;
+    use sha2::{Digest, Sha256};
+    use std::collections::BTreeMap;
+    use std::io::Read;
+
+    #[test]
+    fn export_evidence() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let manifest = dir.path().join("scripts.yaml");
+        fs::write(&manifest, "artifacts: []\n").expect("Cannot write manifest");
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let results = vec![
+            EntryResult {
+                url: "good.sh".to_string(),
+                custom: Some(serde_json::json!({ "owner": "platform", "channel": "beta" })),
+                material: Some(Material {
+                    data: b"echo good".to_vec(),
+                    signature: base64::encode(key.sign(b"echo good")).into_bytes(),
+                    key: Some(key.public_key().to_pem().expect("Cannot encode key").into()),
+                    certificate: None,
+                    bundle: None,
+                    keyring: None,
+                    allowed_signers: None,
+                    revocation: None,
+                    provenance: None,
+                    layout: None,
+                    sbom: None,
+                    claim_policy: None,
+                }),
+                outcome: Ok(Signer {
+                    key_id: "abcd".to_string(),
+                    identity: None,
+                    issuer: None,
+                    integrated_time: None,
+                }),
+            },
+            EntryResult {
+                url: "missing.sh".to_string(),
+                custom: None,
+                material: None,
+                outcome: Err(anyhow!("Cannot read missing.sh").into()),
+            },
+        ];
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let out = dir.path().join("evidence.tgz");
+        export(&out, &manifest, &results, &roots).expect("Cannot export");
+
+        let mut files = BTreeMap::new();
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(&out).expect("No bundle")));
+        for entry in archive.entries().expect("Invalid bundle") {
+            let mut entry = entry.expect("Invalid entry");
+            let name = entry.path().expect("Invalid path").display().to_string();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).expect("Cannot read entry");
+            files.insert(name, contents);
+        }
+        let evidence: Evidence =
+            serde_json::from_slice(&files[INDEX]).expect("Invalid evidence index");
+        assert_eq!(evidence.fulcio_roots.len(), 2);
+        assert_eq!(
+            evidence.artifacts[0].sha256.as_deref(),
+            Some(hex::encode(Sha256::digest(b"echo good")).as_str())
+        );
+        assert_eq!(evidence.artifacts[0].signer.as_deref(), Some("abcd"));
+        assert_eq!(
+            evidence.artifacts[0].custom,
+            Some(serde_json::json!({ "owner": "platform", "channel": "beta" }))
+        );
+        assert!(!evidence.artifacts[1].verified);
+        for path in evidence
+            .fulcio_roots
+            .iter()
+            .chain(&evidence.rekor_keys)
+            .chain(evidence.artifacts[0].signature.iter())
+            .chain(evidence.artifacts[0].key.iter())
+        {
+            assert!(files.contains_key(path), "{} missing from bundle", path);
+        }
+        // The exported roots must round trip.
+        let pem = &files[&evidence.fulcio_roots[0]];
+        assert_eq!(
+            crate::verify::pem_certificates(pem).expect("Invalid root"),
+            vec![roots.fulcio_roots[0].clone()]
+        );
+    }
+}
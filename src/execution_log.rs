@@ -0,0 +1,271 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::state;
+
+/// The `prev` hash of the first entry of a log.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// A script sget executed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Execution {
+    /// Position of the entry in the log, counting from 0.
+    pub index: u64,
+    pub timestamp: DateTime<Utc>,
+    /// URL, path or OCI reference of the script.
+    pub source: String,
+    /// Hex encoded SHA-256 digest of the script.
+    pub digest: Option<String>,
+    /// The local user that ran the script.
+    pub user: Option<String>,
+}
+
+/// One line of the execution log: an execution chained to the entry before
+/// it, so that changing or removing any entry but the last breaks the chain.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChainedEntry {
+    #[serde(flatten)]
+    pub execution: Execution,
+    /// `hash` of the previous entry, or zeros for the first.
+    pub prev: String,
+    /// Hex encoded SHA-256 of `prev`, a newline and the JSON of `execution`.
+    pub hash: String,
+}
+
+impl ChainedEntry {
+    fn new(execution: Execution, prev: String) -> Result<Self> {
+        let hash = chain_hash(&prev, &execution)?;
+        Ok(ChainedEntry {
+            execution,
+            prev,
+            hash,
+        })
+    }
+}
+
+fn chain_hash(prev: &str, execution: &Execution) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(prev.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(serde_json::to_vec(execution)?);
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// The state of an intact log.
+#[derive(Debug, PartialEq)]
+pub struct ChainHead {
+    pub entries: u64,
+    /// `hash` of the last entry, or zeros for an empty log.
+    pub hash: String,
+}
+
+/// A tamper-evident, append-only JSON Lines log of executed scripts, for
+/// hosts whose audit logs cannot be shipped elsewhere. Each entry includes
+/// the hash of the one before it, so edits and deletions are detected by
+/// [`ExecutionLog::verify`]. Truncating the end of the log is only detected
+/// against a previously recorded head hash.
+#[derive(Clone)]
+pub struct ExecutionLog {
+    path: PathBuf,
+}
+
+impl ExecutionLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        ExecutionLog { path: path.into() }
+    }
+
+    /// The log named by `SGET_EXECUTION_LOG`, the `execution_log` config
+    /// setting, or `executions.jsonl` in the state directory, in that order.
+    pub fn open(config: &Config) -> Result<Self> {
+        if let Some(path) = std::env::var_os("SGET_EXECUTION_LOG") {
+            return Ok(Self::new(path));
+        }
+        if let Some(path) = &config.execution_log {
+            return Ok(Self::new(path));
+        }
+        Ok(Self::new(state::state_dir()?.join("executions.jsonl")))
+    }
+
+    pub(crate) fn entries(&self) -> Result<Vec<ChainedEntry>> {
+        let raw = match fs::read_to_string(&self.path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Cannot read {}", self.path.display()))
+            }
+        };
+        raw.lines()
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("Invalid entry on line {}", i + 1))
+            })
+            .collect()
+    }
+
+    /// Append an entry for executing the script from `source` with SHA-256
+    /// `digest`, returning it.
+    pub fn append(&self, source: &str, digest: Option<String>) -> Result<ChainedEntry> {
+        let head = self.verify()?;
+        let entry = ChainedEntry::new(
+            Execution {
+                index: head.entries,
+                timestamp: Utc::now(),
+                source: source.to_string(),
+                digest,
+                user: std::env::var("USER")
+                    .or_else(|_| std::env::var("USERNAME"))
+                    .ok(),
+            },
+            head.hash,
+        )?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(&line))
+            .with_context(|| format!("Cannot write execution log {}", self.path.display()))?;
+        Ok(entry)
+    }
+
+    /// Check that every entry is chained to the one before it, and return the
+    /// head of the chain.
+    pub fn verify(&self) -> Result<ChainHead> {
+        let mut head = ChainHead {
+            entries: 0,
+            hash: GENESIS.to_string(),
+        };
+        for entry in self.entries()? {
+            let line = head.entries + 1;
+            if entry.execution.index != head.entries {
+                return Err(anyhow!(
+                    "Entry on line {} has index {}, expected {}",
+                    line,
+                    entry.execution.index,
+                    head.entries
+                ));
+            }
+            if entry.prev != head.hash {
+                return Err(anyhow!(
+                    "Entry on line {} does not follow the entry before it",
+                    line
+                ));
+            }
+            if chain_hash(&entry.prev, &entry.execution)? != entry.hash {
+                return Err(anyhow!("Entry on line {} has been modified", line));
+            }
+            head = ChainHead {
+                entries: head.entries + 1,
+                hash: entry.hash,
+            };
+        }
+        Ok(head)
+    }
+
+    /// Check that the entry with hash `hash` is in the chain, e.g. a head
+    /// recorded earlier, to detect truncation.
+    pub fn contains(&self, hash: &str) -> Result<bool> {
+        self.verify()?;
+        Ok(self
+            .entries()?
+            .iter()
+            .any(|entry| entry.hash.eq_ignore_ascii_case(hash)))
+    }
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("log")
+        .about("Inspect the local log of executed scripts")
+        .subcommand(
+            App::new("verify")
+                .about("Check that no entry of the execution log was modified or removed")
+                .arg(
+                    Arg::new("file")
+                        .long("file")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .about("The execution log [default: SGET_EXECUTION_LOG, the execution_log setting, or the state directory]"),
+                )
+                .arg(
+                    Arg::new("head")
+                        .long("head")
+                        .value_name("HASH")
+                        .takes_value(true)
+                        .about("A head hash printed earlier, which must still be in the log"),
+                ),
+        )
+}
+
+/// Run `sget log`, returning the head of the verified log.
+pub(crate) fn run(matches: &ArgMatches) -> Result<ChainHead> {
+    match matches.subcommand() {
+        Some(("verify", matches)) => {
+            let log = match matches.value_of("file") {
+                Some(path) => ExecutionLog::new(path),
+                None => ExecutionLog::open(&Config::load()?)?,
+            };
+            let head = log.verify()?;
+            if let Some(hash) = matches.value_of("head") {
+                if !log.contains(hash)? {
+                    return Err(anyhow!(
+                        "{} is not in the log; entries have been removed",
+                        hash
+                    ));
+                }
+            }
+            Ok(head)
+        }
+        _ => Err(anyhow!("Unknown log subcommand")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_tampering() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let path = dir.path().join("logs").join("executions.jsonl");
+        let log = ExecutionLog::new(&path);
+        assert_eq!(log.verify().expect("Empty log is intact").entries, 0);
+
+        let first = log
+            .append("ghcr.io/example/a:v1", Some("00".repeat(32)))
+            .expect("Cannot append");
+        let second = log
+            .append("ghcr.io/example/b:v1", None)
+            .expect("Cannot append");
+        assert_eq!(first.prev, GENESIS);
+        assert_eq!(second.prev, first.hash);
+        let head = log.verify().expect("Log is intact");
+        assert_eq!(head.entries, 2);
+        assert_eq!(head.hash, second.hash);
+        assert!(log.contains(&first.hash).expect("Log is intact"));
+
+        let raw = fs::read_to_string(&path).expect("Cannot read log");
+        fs::write(&path, raw.replace("example/a", "example/x")).expect("Cannot write log");
+        assert!(log.verify().is_err());
+
+        let second_line = raw.lines().nth(1).expect("No second line");
+        fs::write(&path, format!("{}\n", second_line)).expect("Cannot write log");
+        assert!(log.verify().is_err());
+
+        let first_line = raw.lines().next().expect("No first line");
+        fs::write(&path, format!("{}\n", first_line)).expect("Cannot write log");
+        assert!(log.verify().is_ok());
+        assert!(!log.contains(&second.hash).expect("Log is intact"));
+    }
+}
@@ -0,0 +1,226 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::policy::Policy;
+use crate::verify_core::{parse_certificate, TrustRoots};
+
+/// How long before a policy or certificate expires warnings start, unless
+/// configured otherwise.
+pub const DEFAULT_WARNING_DAYS: i64 = 14;
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Treat expiry warnings as errors for the rest of the process, so that
+/// automation fails while there is still time to rotate.
+pub fn make_strict() {
+    STRICT.store(true, Ordering::SeqCst);
+}
+
+/// Whether expiry warnings are errors, see [`make_strict`].
+pub fn is_strict() -> bool {
+    STRICT.load(Ordering::SeqCst)
+}
+
+/// What is about to expire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExpiringKind {
+    Policy,
+    Certificate,
+    Key,
+    Role,
+}
+
+/// Something still valid that expires within the warning window.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ExpiryWarning {
+    pub kind: ExpiringKind,
+    /// The namespace of a policy, the subject of a certificate, the
+    /// [label](crate::policy::Key::label) of a policy key or the name of a
+    /// policy role.
+    pub subject: String,
+    pub expires: DateTime<Utc>,
+    /// Whole days left at the time of the check.
+    pub days_left: i64,
+}
+
+impl ExpiryWarning {
+    /// A warning if `expires` is after `now` but no more than `window` after it.
+    fn check(
+        kind: ExpiringKind,
+        subject: String,
+        expires: DateTime<Utc>,
+        window: Duration,
+        now: DateTime<Utc>,
+    ) -> Option<Self> {
+        let left = expires.signed_duration_since(now);
+        if left <= Duration::zero() || left > window {
+            return None;
+        }
+        Some(ExpiryWarning {
+            kind,
+            subject,
+            expires,
+            days_left: left.num_days(),
+        })
+    }
+}
+
+/// Warn if `policy` expires within `window` of `now`.
+pub fn check_policy(
+    policy: &Policy,
+    window: Duration,
+    now: DateTime<Utc>,
+) -> Option<ExpiryWarning> {
+    ExpiryWarning::check(
+        ExpiringKind::Policy,
+        policy.signed.namespace.to_string(),
+        policy.signed.expires,
+        window,
+        now,
+    )
+}
+
+/// Warn for each key of `policy` that expires within `window` of `now`.
+pub fn check_keys(policy: &Policy, window: Duration, now: DateTime<Utc>) -> Vec<ExpiryWarning> {
+    let mut warnings: Vec<_> = policy
+        .signed
+        .keys
+        .iter()
+        .filter_map(|(keyid, key)| {
+            ExpiryWarning::check(
+                ExpiringKind::Key,
+                key.label(keyid),
+                key.expires()?,
+                window,
+                now,
+            )
+        })
+        .collect();
+    warnings.sort_by(|a, b| a.subject.cmp(&b.subject));
+    warnings
+}
+
+/// Warn for each role of `policy` that expires within `window` of `now`.
+pub fn check_roles(policy: &Policy, window: Duration, now: DateTime<Utc>) -> Vec<ExpiryWarning> {
+    let mut warnings: Vec<_> = policy
+        .signed
+        .roles
+        .iter()
+        .filter_map(|(name, role)| {
+            ExpiryWarning::check(ExpiringKind::Role, name.clone(), role.expires?, window, now)
+        })
+        .collect();
+    warnings.sort_by(|a, b| a.subject.cmp(&b.subject));
+    warnings
+}
+
+/// Warn for each DER encoded certificate that expires within `window` of
+/// `now`. Certificates that cannot be parsed are left to verification.
+pub fn check_certificates(
+    certificates: &[Vec<u8>],
+    window: Duration,
+    now: DateTime<Utc>,
+) -> Vec<ExpiryWarning> {
+    certificates
+        .iter()
+        .filter_map(|der| parse_certificate(der).ok())
+        .filter_map(|cert| {
+            let expires = Utc
+                .timestamp_opt(cert.validity().not_after.timestamp(), 0)
+                .single()?;
+            ExpiryWarning::check(
+                ExpiringKind::Certificate,
+                cert.subject().to_string(),
+                expires,
+                window,
+                now,
+            )
+        })
+        .collect()
+}
+
+/// Warn for the Fulcio roots, root policy and the root policy's keys and
+/// roles of `roots` that expire within `window` of `now`.
+pub fn check_roots(roots: &TrustRoots, window: Duration, now: DateTime<Utc>) -> Vec<ExpiryWarning> {
+    let mut warnings = check_certificates(&roots.fulcio_roots, window, now);
+    if let Some(policy) = &roots.policy {
+        warnings.extend(check_policy(policy, window, now));
+        warnings.extend(check_keys(policy, window, now));
+        warnings.extend(check_roles(policy, window, now));
+    }
+    warnings
+}
+
+/// Print `warnings` to stderr as JSON lines, and fail if expiry warnings are
+/// errors.
+#[cfg(feature = "native")]
+pub(crate) fn report(warnings: &[ExpiryWarning]) -> anyhow::Result<()> {
+    for warning in warnings {
+        eprintln!(
+            "{}",
+            serde_json::json!({ "warning": "expiry", "expiring": warning })
+        );
+    }
+    if is_strict() && !warnings.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} trust material(s) expire within the warning window",
+            warnings.len()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warn_within_window() {
+        let mut roots = TrustRoots::builtin().expect("No built-in roots");
+        let policy: Policy =
+            serde_json::from_str(include_str!("../tests/test_data/policy_good.json"))
+                .expect("Invalid policy");
+        let expires = policy.signed.expires;
+        roots.policy = Some(policy);
+        let window = Duration::days(DEFAULT_WARNING_DAYS);
+
+        let warnings = check_roots(&roots, window, expires - Duration::days(3));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, ExpiringKind::Policy);
+        assert_eq!(warnings[0].days_left, 3);
+
+        // Nothing is reported outside the window, nor once expired: that is
+        // an error, not a warning.
+        assert!(check_roots(&roots, window, expires - Duration::days(30)).is_empty());
+        assert!(check_roots(&roots, window, expires + Duration::days(1)).is_empty());
+
+        // The built-in Fulcio root is valid for years yet.
+        let now = Utc::now();
+        assert!(check_certificates(&roots.fulcio_roots, window, now).is_empty());
+        let near = check_certificates(&roots.fulcio_roots, Duration::days(365 * 100), now);
+        assert_eq!(near.len(), roots.fulcio_roots.len());
+        assert_eq!(near[0].kind, ExpiringKind::Certificate);
+
+        let mut policy = roots.policy.take().expect("No policy");
+        assert!(check_keys(&policy, window, expires - Duration::days(3)).is_empty());
+        if let Some(key) = policy.signed.keys.values_mut().next() {
+            key.metadata_mut().expires = Some(expires - Duration::days(10));
+        }
+        let warnings = check_keys(&policy, window, expires - Duration::days(13));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, ExpiringKind::Key);
+        assert_eq!(warnings[0].days_left, 3);
+
+        if let Some(role) = policy.signed.roles.get_mut("root") {
+            role.expires = Some(expires - Duration::days(20));
+        }
+        let warnings = check_roles(&policy, window, expires - Duration::days(25));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            (warnings[0].kind, warnings[0].subject.as_str()),
+            (ExpiringKind::Role, "root")
+        );
+    }
+}
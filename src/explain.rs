@@ -0,0 +1,883 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::algorithms::ArtifactDigest;
+use crate::error::SgetError;
+use crate::keys::PublicKey;
+use crate::policy::{self, Policy, RawPolicy, RawSigned, SigstoreOidcKey, SpecVersion};
+use crate::signature::SignatureVerifiers;
+use crate::verify_core::{
+    certificate_identity, parse_certificate, pem_certificates, verify_bundle, verify_chain,
+    verify_policy_signature, BlobSignature, Signer, TrustRoots,
+};
+
+/// How a check turned out.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// The check does not apply, or an earlier failure left nothing to check.
+    Skipped,
+}
+
+/// One step of a verification.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    /// What was checked, such as `threshold`.
+    pub name: String,
+    pub outcome: Outcome,
+    /// What was found.
+    pub detail: String,
+    /// For failed checks, what input would make the check pass.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+/// Every check of a verification in the order verification makes them. Unlike
+/// verification itself, explaining carries on past the first failure where
+/// the later checks still mean something.
+#[derive(Debug, Default, Serialize)]
+pub struct Explanation {
+    pub checks: Vec<Check>,
+}
+
+impl Explanation {
+    fn push(&mut self, name: &str, outcome: Outcome, detail: String, fix: Option<String>) {
+        self.checks.push(Check {
+            name: name.to_string(),
+            outcome,
+            detail,
+            fix,
+        });
+    }
+
+    fn pass(&mut self, name: &str, detail: String) {
+        self.push(name, Outcome::Passed, detail, None);
+    }
+
+    fn fail(&mut self, name: &str, detail: String, fix: String) {
+        self.push(name, Outcome::Failed, detail, Some(fix));
+    }
+
+    fn skip(&mut self, name: &str, detail: &str) {
+        self.push(name, Outcome::Skipped, detail.to_string(), None);
+    }
+
+    /// Whether no check failed.
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.outcome != Outcome::Failed)
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        for check in &self.checks {
+            let label = match check.outcome {
+                Outcome::Passed => "ok",
+                Outcome::Failed => "FAIL",
+                Outcome::Skipped => "skip",
+            };
+            writeln!(
+                f,
+                "  {:<4}  {:<width$}  {}",
+                label,
+                check.name,
+                check.detail,
+                width = width
+            )?;
+            if let Some(fix) = &check.fix {
+                writeln!(
+                    f,
+                    "  {:<4}  {:<width$}  to pass: {}",
+                    "",
+                    "",
+                    fix,
+                    width = width
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The error and all its sources.
+fn describe(error: &anyhow::Error) -> String {
+    format!("{:#}", error)
+}
+
+/// Walk through the checks of [`verify_policy_with_parent`]: the spec
+/// version, freshness, the parent include, which signatures are by root keys,
+/// whether each of those verifies, and the root threshold.
+///
+/// [`verify_policy_with_parent`]: crate::verify_core::verify_policy_with_parent
+pub fn explain_policy(
+    raw: &[u8],
+    parent: Option<&Policy>,
+    roots: &TrustRoots,
+    verifiers: &SignatureVerifiers,
+    now: DateTime<Utc>,
+) -> Explanation {
+    let mut explanation = Explanation::default();
+    let format_fix = "Pass the policy as published, a JSON object with `signed` and `signatures`";
+    let parsed = serde_json::from_slice::<RawPolicy>(raw).and_then(|raw_policy| {
+        let version: SpecVersion = serde_json::from_str(raw_policy.signed.get())?;
+        Ok(version.spec_version)
+    });
+    match parsed {
+        Ok(version) if policy::supports_spec_version(&version) => {
+            explanation.pass("spec version", format!("Spec version {}", version))
+        }
+        Ok(version) => {
+            explanation.fail(
+                "spec version",
+                format!(
+                    "Spec version {}, this sget reads {}.x",
+                    version,
+                    policy::SPEC_MAJOR
+                ),
+                "Upgrade sget to a release that reads this spec version".to_string(),
+            );
+            return explanation;
+        }
+        Err(_) => {}
+    }
+    let parsed = serde_json::from_slice::<RawPolicy>(raw).and_then(|raw_policy| {
+        let view: RawSigned = serde_json::from_str(raw_policy.signed.get())?;
+        let signatures: Vec<policy::Signature> = serde_json::from_str(raw_policy.signatures.get())?;
+        Ok((raw_policy, view, signatures))
+    });
+    let (raw_policy, view, signatures) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            explanation.fail("policy format", e.to_string(), format_fix.to_string());
+            return explanation;
+        }
+    };
+
+    if view.expires <= now {
+        explanation.fail(
+            "policy freshness",
+            format!("Expired at {}, it is now {}", view.expires, now),
+            "Fetch the current version of the policy, or have its root keys re-sign it with a later `expires`".to_string(),
+        );
+    } else {
+        explanation.pass("policy freshness", format!("Expires at {}", view.expires));
+    }
+
+    match (&view.include, parent) {
+        (Some(include), None) => explanation.fail(
+            "parent policy",
+            format!("Includes {}, which was not resolved", include.url),
+            format!(
+                "Make {} with SHA-256 {} available next to the policy",
+                include.url, include.sha256
+            ),
+        ),
+        (None, Some(_)) => explanation.fail(
+            "parent policy",
+            "A parent was given, but the policy includes none".to_string(),
+            "Verify the policy on its own".to_string(),
+        ),
+        (Some(include), Some(_)) => {
+            explanation.pass("parent policy", format!("Includes {}", include.url))
+        }
+        (None, None) => explanation.skip("parent policy", "Includes no parent"),
+    }
+
+    let role = match view
+        .roles
+        .get("root")
+        .or_else(|| parent.and_then(|parent| parent.signed.roles.get("root")))
+    {
+        Some(role) => role,
+        None => {
+            explanation.fail(
+                "root role",
+                "Policy has no root role".to_string(),
+                "Add a `root` role listing the keys that sign the policy and their threshold"
+                    .to_string(),
+            );
+            return explanation;
+        }
+    };
+
+    match role.expires {
+        Some(expires) if role.expired_at(now) => explanation.fail(
+            "root role freshness",
+            format!("The root role expired at {}", expires),
+            "Fetch the current version of the policy, or have its root keys extend the root role's `expires`".to_string(),
+        ),
+        Some(expires) => explanation.pass(
+            "root role freshness",
+            format!("The root role expires at {}", expires),
+        ),
+        None => {}
+    }
+
+    let threshold = role.threshold.get();
+    let (authorized, unauthorized): (Vec<_>, Vec<_>) = signatures
+        .iter()
+        .partition(|sig| role.keyids.contains(&sig.keyid));
+    let unauthorized: BTreeSet<&str> = unauthorized.iter().map(|sig| sig.keyid.as_str()).collect();
+    let label = |keyid: &str| view.key_label(parent, keyid);
+    let labels =
+        |keyids: &mut dyn Iterator<Item = &str>| keyids.map(&label).collect::<Vec<_>>().join(", ");
+
+    let signed = raw_policy.signed.get().as_bytes();
+    let mut valid = BTreeSet::new();
+    let mut results = Vec::with_capacity(authorized.len());
+    for sig in &authorized {
+        if valid.contains(sig.keyid.as_str()) {
+            results.push((sig.keyid.as_str(), None));
+            continue;
+        }
+        let result = verify_policy_signature(&view, parent, sig, signed, roots, verifiers, now);
+        if result.is_ok() {
+            valid.insert(sig.keyid.as_str());
+        }
+        results.push((sig.keyid.as_str(), Some(result)));
+    }
+    let met = valid.len() as u64 >= threshold;
+
+    if unauthorized.is_empty() {
+        explanation.pass(
+            "keyid authorization",
+            "Every signature is by a root key".to_string(),
+        );
+    } else {
+        let detail = format!(
+            "Signatures by {} are ignored, as they are not root keys",
+            labels(&mut unauthorized.iter().copied())
+        );
+        if met {
+            explanation.pass("keyid authorization", detail);
+        } else {
+            explanation.fail(
+                "keyid authorization",
+                detail,
+                format!(
+                    "Sign with the root keys ({}), or add the signing keys to `roles.root.keyids`",
+                    labels(&mut role.keyids.iter().map(String::as_str))
+                ),
+            );
+        }
+    }
+
+    // Invalid signatures only fail verification when the valid ones fall short.
+    for (keyid, result) in results {
+        let name = "signature";
+        let keyid = label(keyid);
+        match result {
+            None => explanation.skip(
+                name,
+                &format!("{}: repeated by the same key, counted once", keyid),
+            ),
+            Some(Ok(())) => explanation.pass(name, format!("{}: valid", keyid)),
+            Some(Err(e)) if met => {
+                explanation.skip(name, &format!("{}: ignored, {}", keyid, describe(&e)))
+            }
+            Some(Err(e)) => explanation.fail(
+                name,
+                format!("{}: {}", keyid, describe(&e)),
+                format!(
+                    "Have the holder of {} sign the current `signed` section again",
+                    keyid
+                ),
+            ),
+        }
+    }
+
+    if met {
+        explanation.pass(
+            "threshold",
+            format!(
+                "{} valid root signatures of the {} required",
+                valid.len(),
+                threshold
+            ),
+        );
+    } else {
+        let missing = labels(
+            &mut role
+                .keyids
+                .iter()
+                .map(String::as_str)
+                .filter(|keyid| !valid.contains(keyid)),
+        );
+        explanation.fail(
+            "threshold",
+            format!(
+                "{} valid root signatures of the {} required",
+                valid.len(),
+                threshold
+            ),
+            format!(
+                "Add {} more signature(s) by the root keys that have not signed: {}",
+                threshold - valid.len() as u64,
+                missing
+            ),
+        );
+    }
+
+    if let Err(e) = serde_json::from_slice::<Policy>(raw) {
+        explanation.fail("policy format", e.to_string(), format_fix.to_string());
+    }
+    explanation
+}
+
+/// Walk through the checks of [`verify_blob`] and the checks that follow it:
+/// the pinned `digests`, the certificate chain, the signature, the
+/// transparency log entry, the identity match, and finally the trust roots'
+/// rules for a signer of the artifact at `location`.
+///
+/// [`verify_blob`]: crate::verify_core::verify_blob
+pub fn explain_blob(
+    data: &[u8],
+    sig: &BlobSignature,
+    roots: &TrustRoots,
+    expected: Option<&SigstoreOidcKey>,
+    digests: &[String],
+    location: Option<&str>,
+) -> Explanation {
+    let mut explanation = Explanation::default();
+    explain_digests(&mut explanation, data, digests);
+
+    let signer = match (&sig.certificate, &sig.public_key) {
+        (Some(chain), _) => explain_certificate(&mut explanation, data, sig, chain, roots),
+        (None, Some(key)) => {
+            explanation.skip("certificate chain", "Signed with a key, not a certificate");
+            let signer = explain_signature(&mut explanation, data, sig, key, roots);
+            match &sig.bundle {
+                Some(_) => explain_log(&mut explanation, data, sig, roots),
+                None => explanation.skip("transparency log", "No bundle, none needed for keys"),
+            }
+            signer
+        }
+        (None, None) => {
+            explanation.fail(
+                "signer material",
+                "No certificate or public key to verify with".to_string(),
+                "Pass the signing certificate with --certificate or the public key with --key"
+                    .to_string(),
+            );
+            None
+        }
+    };
+
+    let signer = match signer {
+        Some(signer) => signer,
+        None => {
+            explanation.skip("identity", "No verified signer");
+            explanation.skip("signer policy", "No verified signer");
+            return explanation;
+        }
+    };
+    match expected {
+        None => explanation.skip(
+            "identity",
+            &format!("No identity pinned; signed by {}", signer.subject()),
+        ),
+        Some(expected) => explain_identity(&mut explanation, &signer, expected),
+    }
+    match roots.check_signer_for(&signer, location) {
+        Ok(()) => explanation.pass(
+            "signer policy",
+            format!("{} is allowed to sign", signer.subject()),
+        ),
+        Err(e) => explanation.fail(
+            "signer policy",
+            e.describe(),
+            format!(
+                "Allow {} in the system policy, identity map or root policy{}",
+                signer.subject(),
+                location.map(|l| format!(" for {}", l)).unwrap_or_default()
+            ),
+        ),
+    }
+    explanation
+}
+
+fn explain_digests(explanation: &mut Explanation, data: &[u8], digests: &[String]) {
+    if digests.is_empty() {
+        explanation.skip("digest", "No digest pinned");
+    }
+    for expected in digests {
+        let checked = expected
+            .parse::<ArtifactDigest>()
+            .and_then(|expected| Ok((expected.of(data)?, expected)));
+        match checked {
+            Ok((actual, expected)) if actual == expected => {
+                explanation.pass("digest", format!("Matches {}", expected))
+            }
+            Ok((actual, expected)) => explanation.fail(
+                "digest",
+                format!("Expected {}, got {}", expected, actual),
+                format!(
+                    "Fetch the artifact published with {}, or pin {} if this is the version to trust",
+                    expected, actual
+                ),
+            ),
+            Err(e) => explanation.fail(
+                "digest",
+                e.describe(),
+                "Pin a hex SHA-256 digest, sha384:HEX, sha512:HEX or blake3:HEX".to_string(),
+            ),
+        }
+    }
+}
+
+fn explain_certificate(
+    explanation: &mut Explanation,
+    data: &[u8],
+    sig: &BlobSignature,
+    chain: &str,
+    roots: &TrustRoots,
+) -> Option<Signer> {
+    let parsed = pem_certificates(chain.as_bytes()).and_then(|chain| {
+        let leaf = parse_certificate(&chain[0])?;
+        let key = PublicKey::from_der(leaf.public_key().raw).map_err(SgetError::InvalidMaterial)?;
+        let identity = certificate_identity(&leaf)?;
+        let validity = leaf.validity();
+        let validity = (
+            validity.not_before.timestamp(),
+            validity.not_after.timestamp(),
+        );
+        let chained = verify_chain(&leaf, &chain[1..], &roots.fulcio_roots, &roots.algorithms);
+        Ok((key, identity, validity, chained, leaf.issuer().to_string()))
+    });
+    let (key, (identity, issuer), (not_before, not_after), chained, leaf_issuer) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            explanation.fail(
+                "certificate chain",
+                e.describe(),
+                "Pass the PEM certificate written when signing with --certificate".to_string(),
+            );
+            return None;
+        }
+    };
+    let mut ok = chained.is_ok();
+    match chained {
+        Ok(()) => explanation.pass(
+            "certificate chain",
+            format!("Issued by {}, which chains to a trusted root", leaf_issuer),
+        ),
+        Err(e) => explanation.fail(
+            "certificate chain",
+            e.describe(),
+            format!(
+                "Trust the root that issued {} with `sget trust add fulcio-root NAME FILE`, or pass its intermediates after the certificate",
+                leaf_issuer
+            ),
+        ),
+    }
+
+    let mut signer = explain_signature(explanation, data, sig, &key, roots)?;
+    signer.identity = Some(identity);
+    signer.issuer = issuer;
+
+    match &sig.bundle {
+        None => {
+            explanation.fail(
+                "transparency log",
+                "Certificate signatures require a Rekor bundle".to_string(),
+                "Pass the bundle written when signing with --bundle".to_string(),
+            );
+            ok = false;
+        }
+        Some(bundle) => {
+            let time = bundle.payload.integrated_time;
+            if time < not_before || time > not_after {
+                ok = false;
+                explanation.fail(
+                    "certificate validity",
+                    format!(
+                        "Logged at {}, outside the certificate's validity from {} to {}",
+                        time, not_before, not_after
+                    ),
+                    "Pass the bundle logged when this certificate signed the artifact".to_string(),
+                );
+            } else {
+                explanation.pass(
+                    "certificate validity",
+                    "Logged while the certificate was valid".to_string(),
+                );
+            }
+        }
+    }
+    explain_log(explanation, data, sig, roots);
+    Some(signer).filter(|_| ok)
+}
+
+fn explain_signature(
+    explanation: &mut Explanation,
+    data: &[u8],
+    sig: &BlobSignature,
+    key: &PublicKey,
+    roots: &TrustRoots,
+) -> Option<Signer> {
+    let checked = roots.algorithms.check_key(key).and_then(|()| {
+        key.verify(data, &sig.signature)
+            .map_err(SgetError::InvalidSignature)
+    });
+    let key_id = key.key_id().ok().unwrap_or_default();
+    match checked {
+        Ok(()) => {
+            explanation.pass(
+                "signature",
+                format!("Made by key {} over the artifact", key_id),
+            );
+            Some(Signer {
+                key_id,
+                identity: None,
+                issuer: None,
+                integrated_time: sig.bundle.as_ref().map(|b| b.payload.integrated_time),
+            })
+        }
+        Err(e @ SgetError::DisallowedAlgorithm(_)) => {
+            explanation.fail(
+                "signature",
+                e.describe(),
+                "Sign with an algorithm the algorithm policy allows, or allow it in the configuration".to_string(),
+            );
+            None
+        }
+        Err(e) => {
+            explanation.fail(
+                "signature",
+                e.describe(),
+                format!(
+                    "Pass the signature key {} made over exactly this artifact with --signature",
+                    key_id
+                ),
+            );
+            None
+        }
+    }
+}
+
+fn explain_log(
+    explanation: &mut Explanation,
+    data: &[u8],
+    sig: &BlobSignature,
+    roots: &TrustRoots,
+) {
+    let bundle = match &sig.bundle {
+        Some(bundle) => bundle,
+        None => return,
+    };
+    match verify_bundle(bundle, data, &sig.signature, &roots.rekor_keys) {
+        Ok(()) => explanation.pass(
+            "transparency log",
+            format!("Logged at index {}", bundle.payload.log_index),
+        ),
+        Err(e) => {
+            let fix = if e.to_string().contains("untrusted log") {
+                format!(
+                    "Trust the key of log {} with `sget trust add rekor-key NAME FILE`",
+                    bundle.payload.log_id
+                )
+            } else {
+                "Pass the bundle logged for this signature over this artifact with --bundle"
+                    .to_string()
+            };
+            explanation.fail("transparency log", e.describe(), fix);
+        }
+    }
+}
+
+fn explain_identity(explanation: &mut Explanation, signer: &Signer, expected: &SigstoreOidcKey) {
+    let identity = match &signer.identity {
+        Some(identity) => identity,
+        None => {
+            explanation.fail(
+                "identity",
+                format!(
+                    "Expected {}, but key {} has no identity",
+                    expected.identity, signer.key_id
+                ),
+                "Verify a keyless signature, or drop --certificate-identity".to_string(),
+            );
+            return;
+        }
+    };
+    let issuer = signer.issuer.as_deref().unwrap_or("an unknown issuer");
+    let identity_ok = *identity == expected.identity;
+    let issuer_ok =
+        expected.issuer.is_empty() || signer.issuer.as_deref() == Some(&expected.issuer);
+    if identity_ok && issuer_ok {
+        explanation.pass(
+            "identity",
+            format!("Signed by {} issued by {}", identity, issuer),
+        );
+        return;
+    }
+    let mut fix = format!(
+        "If {} is the intended signer, pin --certificate-identity {}",
+        identity, identity
+    );
+    if let Some(issuer) = &signer.issuer {
+        fix.push_str(&format!(" --certificate-oidc-issuer {}", issuer));
+    }
+    explanation.fail(
+        "identity",
+        format!(
+            "Signed by {} issued by {}, expected {}{}",
+            identity,
+            issuer,
+            expected.identity,
+            match expected.issuer.as_str() {
+                "" => String::new(),
+                expected => format!(" issued by {}", expected),
+            }
+        ),
+        fix,
+    );
+}
+
+#[cfg(feature = "native")]
+pub(crate) fn command() -> clap::App<'static> {
+    use clap::{App, Arg};
+
+    App::new("explain")
+        .about("Walk through each check of verifying a policy or a blob, saying which failed and what would make it pass")
+        .arg(
+            Arg::new("file")
+                .value_name("FILE")
+                .required_unless_present("policy")
+                .about("URL or path of the blob whose verification to explain"),
+        )
+        .arg(
+            Arg::new("policy")
+                .long("policy")
+                .value_name("POLICY")
+                .takes_value(true)
+                .about("URL or path of a root policy whose verification to explain"),
+        )
+        .arg(
+            Arg::new("digest")
+                .long("digest")
+                .value_name("DIGEST")
+                .takes_value(true)
+                .about("Digest the blob is pinned to: hex SHA-256, sha384:HEX, sha512:HEX or blake3:HEX"),
+        )
+        .arg(
+            Arg::new("signature")
+                .long("signature")
+                .value_name("SIGNATURE")
+                .takes_value(true)
+                .about("Base64 encoded signature [default: FILE.sig, or from the bundle]"),
+        )
+        .arg(
+            Arg::new("certificate")
+                .long("certificate")
+                .alias("cert")
+                .value_name("CERTIFICATE")
+                .takes_value(true)
+                .about("PEM or base64 encoded PEM certificate [default: FILE.pem, or from the bundle]"),
+        )
+        .arg(
+            Arg::new("bundle")
+                .long("bundle")
+                .value_name("BUNDLE")
+                .takes_value(true)
+                .about("Rekor, cosign or sigstore bundle [default: FILE.bundle for keyless signatures]"),
+        )
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .value_name("KEY")
+                .takes_value(true)
+                .conflicts_with("certificate")
+                .about("PEM public key the blob was signed with"),
+        )
+        .arg(
+            Arg::new("certificate-identity")
+                .long("certificate-identity")
+                .value_name("IDENTITY")
+                .takes_value(true)
+                .conflicts_with("key")
+                .about("Required identity of the signing certificate"),
+        )
+        .arg(
+            Arg::new("certificate-oidc-issuer")
+                .long("certificate-oidc-issuer")
+                .value_name("ISSUER")
+                .takes_value(true)
+                .requires("certificate-identity")
+                .about("Required OIDC issuer of the signing certificate"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .takes_value(false)
+                .about("Print the checks as JSON"),
+        )
+}
+
+#[cfg(feature = "native")]
+pub(crate) async fn run(matches: &clap::ArgMatches) -> anyhow::Result<()> {
+    use crate::fetch::fetch;
+    use crate::manifest::ManifestEntry;
+    use crate::trust::TrustStore;
+    use crate::{transport, verify};
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    let value = |name| matches.value_of(name).map(String::from);
+    let roots = TrustRoots::load(&TrustStore::open()?)?;
+    let transport = transport::default_transport();
+    let mut explanations = BTreeMap::new();
+    if let Some(location) = matches.value_of("policy") {
+        let raw = fetch(transport.as_ref(), location, Path::new("")).await?;
+        let verifiers = SignatureVerifiers::default();
+        let now = Utc::now();
+        let parent = verify::resolve_parent(transport.as_ref(), &raw, location, &roots, now).await;
+        let explanation = match parent {
+            Ok(parent) => explain_policy(&raw, parent.as_ref(), &roots, &verifiers, now),
+            Err(e) => {
+                let mut explanation = Explanation::default();
+                explanation.fail(
+                    "parent policy",
+                    e.describe(),
+                    "Make the included policy available with the digest the policy pins, and valid in turn".to_string(),
+                );
+                explanation
+            }
+        };
+        explanations.insert(location.to_string(), explanation);
+    }
+    if let Some(file) = matches.value_of("file") {
+        let mut entry = ManifestEntry::new(file);
+        entry.digest = value("digest");
+        entry.signature = value("signature");
+        entry.certificate = value("certificate");
+        entry.bundle = value("bundle");
+        entry.key = value("key");
+        entry.identity = value("certificate-identity");
+        entry.issuer = value("certificate-oidc-issuer");
+        let material = entry
+            .fetch_material(transport.as_ref(), Path::new(""))
+            .await?;
+        let explanation = match material.parse() {
+            Ok(sig) => explain_blob(
+                &material.data,
+                &sig,
+                &roots,
+                entry.expected_identity().as_ref(),
+                entry.digest.as_slice(),
+                Some(file),
+            ),
+            Err(e) => {
+                let mut explanation = Explanation::default();
+                explanation.fail(
+                    "signer material",
+                    describe(&e),
+                    "Pass the signature, certificate, key and bundle written when signing"
+                        .to_string(),
+                );
+                explanation
+            }
+        };
+        explanations.insert(file.to_string(), explanation);
+    }
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&explanations)?);
+    } else {
+        for (location, explanation) in &explanations {
+            println!("{}", location);
+            print!("{}", explanation);
+        }
+    }
+    if explanations.values().all(Explanation::passed) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Verification failed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use std::fs;
+    use std::path::Path;
+
+    fn outcome(explanation: &Explanation, name: &str) -> Option<Outcome> {
+        explanation
+            .checks
+            .iter()
+            .find(|check| check.name == name)
+            .map(|check| check.outcome)
+    }
+
+    #[test]
+    fn explain_failures() {
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let raw = fs::read(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/test_data/policy_good.json"),
+        )
+        .expect("Cannot read good policy file");
+        let now = "2021-11-24T00:00:00Z".parse().expect("Invalid date");
+        let explanation = explain_policy(&raw, None, &roots, &SignatureVerifiers::default(), now);
+        assert!(!explanation.passed());
+        assert_eq!(
+            outcome(&explanation, "policy freshness"),
+            Some(Outcome::Passed)
+        );
+        let threshold = explanation
+            .checks
+            .iter()
+            .find(|check| check.name == "threshold")
+            .expect("No threshold check");
+        assert_eq!(threshold.outcome, Outcome::Failed);
+        assert_eq!(
+            threshold.detail,
+            "1 valid root signatures of the 2 required"
+        );
+        assert!(threshold
+            .fix
+            .as_deref()
+            .is_some_and(|fix| fix.starts_with("Add 1 more")));
+
+        let later = "2040-01-01T00:00:00Z".parse().expect("Invalid date");
+        let explanation = explain_policy(&raw, None, &roots, &SignatureVerifiers::default(), later);
+        assert_eq!(
+            outcome(&explanation, "policy freshness"),
+            Some(Outcome::Failed)
+        );
+
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let data = b"#!/bin/sh\necho hello\n";
+        let sig = BlobSignature {
+            signature: key.sign(data),
+            certificate: None,
+            public_key: Some(key.public_key()),
+            bundle: None,
+        };
+        let expected = SigstoreOidcKey {
+            identity: "release@example.com".to_string(),
+            issuer: String::new(),
+        };
+        let explanation =
+            explain_blob(data, &sig, &roots, Some(&expected), &["0".repeat(64)], None);
+        assert_eq!(outcome(&explanation, "digest"), Some(Outcome::Failed));
+        assert_eq!(outcome(&explanation, "signature"), Some(Outcome::Passed));
+        assert_eq!(outcome(&explanation, "identity"), Some(Outcome::Failed));
+        assert_eq!(
+            outcome(&explanation, "signer policy"),
+            Some(Outcome::Passed)
+        );
+
+        let explanation = explain_blob(b"tampered", &sig, &roots, None, &[], None);
+        assert_eq!(outcome(&explanation, "signature"), Some(Outcome::Failed));
+        assert_eq!(outcome(&explanation, "identity"), Some(Outcome::Skipped));
+    }
+}
@@ -0,0 +1,974 @@
+use anyhow::anyhow;
+use http::header::{
+    HeaderMap, ACCEPT, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, WWW_AUTHENTICATE,
+};
+use oci_distribution::Reference;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::algorithms::{ArtifactDigest, HashAlgorithm, Hasher};
+use crate::containers_storage;
+use crate::error::{Result, SgetError};
+use crate::keychain::{Keychain, RegistryCredentials};
+use crate::notation;
+use crate::provenance::Envelope;
+use crate::transport::{self, Request, Response, Transport};
+use crate::utils;
+
+/// Media types of the image manifests `pull_oci` understands.
+const MANIFEST_MEDIA_TYPES: &str =
+    "application/vnd.oci.image.manifest.v1+json, application/vnd.docker.distribution.manifest.v2+json";
+
+/// Media type of the referrers of a manifest, as returned by the referrers API.
+const INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+
+/// Media type of the layer holding a pushed script.
+const SCRIPT_MEDIA_TYPE: &str = "text/plain";
+
+/// Media type of the layers of a cosign attestation image.
+const DSSE_MEDIA_TYPE: &str = "application/vnd.dsse.envelope.v1+json";
+
+/// Layer annotations holding the certificate, and its chain, that signed a
+/// cosign attestation.
+const COSIGN_CERTIFICATE: &str = "dev.sigstore.cosign/certificate";
+const COSIGN_CHAIN: &str = "dev.sigstore.cosign/chain";
+
+/// Prefix of OCI image layout directory sources.
+pub const OCI_LAYOUT_PREFIX: &str = "oci-layout:";
+
+/// Annotation naming the tag of a manifest in an OCI image layout index.
+const REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// The location of an artifact read from standard input.
+pub const STDIN: &str = "-";
+
+/// Whether `location` refers to a remote resource rather than a local file.
+pub fn is_remote(location: &str) -> bool {
+    location.starts_with("https://") || location.starts_with("http://")
+}
+
+fn fetch_error(location: &str, source: impl Into<anyhow::Error>) -> SgetError {
+    match source.into().downcast::<SgetError>() {
+        Ok(offline @ SgetError::Offline(_)) => offline,
+        Ok(other) => SgetError::Fetch {
+            location: location.to_string(),
+            source: other.into(),
+        },
+        Err(source) => SgetError::Fetch {
+            location: location.to_string(),
+            source,
+        },
+    }
+}
+
+/// Media types served for login and error pages, which are never artifacts.
+const HTML_MEDIA_TYPES: &[&str] = &["text/html", "application/xhtml+xml"];
+
+/// What the response for an artifact must look like, so that a login page or
+/// a truncated download is reported as such rather than as a digest or
+/// signature mismatch.
+#[derive(Clone, Debug, Default)]
+pub struct ContentExpectations {
+    /// Acceptable media types, such as `text/x-shellscript` or `text/*`. Any
+    /// type but HTML is accepted when empty.
+    pub content_types: Vec<String>,
+    /// The declared length of the artifact in bytes.
+    pub length: Option<u64>,
+}
+
+impl ContentExpectations {
+    fn accepts(&self, media_type: &str) -> bool {
+        if self.content_types.is_empty() {
+            return !HTML_MEDIA_TYPES.contains(&media_type);
+        }
+        self.content_types.iter().any(|expected| {
+            let expected = expected.trim().to_ascii_lowercase();
+            match expected.strip_suffix("/*") {
+                Some(kind) => media_type
+                    .split_once('/')
+                    .is_some_and(|(actual, _)| actual == kind),
+                None => expected == media_type,
+            }
+        })
+    }
+
+    /// Check the response headers, if the artifact was fetched over http(s),
+    /// and the received `body` from `location`.
+    pub fn check(&self, location: &str, headers: Option<&HeaderMap>, body: &[u8]) -> Result<()> {
+        let unexpected = |reason: String| SgetError::UnexpectedContent {
+            location: location.to_string(),
+            reason,
+        };
+        let header = |name| {
+            headers
+                .and_then(|headers| headers.get(name))
+                .and_then(|value| value.to_str().ok())
+        };
+        if let Some(content_type) = header(CONTENT_TYPE) {
+            let media_type = content_type
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase();
+            if !self.accepts(&media_type) {
+                return Err(unexpected(format!("content type {}", media_type)));
+            }
+        }
+        if let Some(content_length) = header(CONTENT_LENGTH) {
+            let content_length: u64 = content_length
+                .trim()
+                .parse()
+                .map_err(|_| unexpected(format!("invalid Content-Length {}", content_length)))?;
+            if let Some(length) = self.length.filter(|length| *length != content_length) {
+                return Err(unexpected(format!(
+                    "Content-Length {}, but the artifact is {} bytes",
+                    content_length, length
+                )));
+            }
+            if content_length != body.len() as u64 {
+                return Err(unexpected(format!(
+                    "{} of {} bytes received",
+                    body.len(),
+                    content_length
+                )));
+            }
+        }
+        self.check_length(location, body.len() as u64)
+    }
+
+    /// Check that the artifact at `location` is `actual` bytes long, if its
+    /// length was declared.
+    fn check_length(&self, location: &str, actual: u64) -> Result<()> {
+        match self.length {
+            Some(length) if length != actual => Err(SgetError::UnexpectedContent {
+                location: location.to_string(),
+                reason: format!("{} bytes, but the artifact is {} bytes", actual, length),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Fetch the contents of an http(s) URL with `transport`, or of a local file.
+/// Relative file paths are resolved against `base`.
+pub async fn fetch(transport: &dyn Transport, location: &str, base: &Path) -> Result<Vec<u8>> {
+    fetch_expected(transport, location, base, None).await
+}
+
+/// Like [`fetch`], checking the response against `expected`, if given.
+pub async fn fetch_expected(
+    transport: &dyn Transport,
+    location: &str,
+    base: &Path,
+    expected: Option<&ContentExpectations>,
+) -> Result<Vec<u8>> {
+    if is_remote(location) {
+        let request = transport::get(location).map_err(|e| fetch_error(location, e))?;
+        let response = transport
+            .send(request)
+            .await
+            .map_err(|e| fetch_error(location, e))?;
+        if !response.status().is_success() {
+            return Err(fetch_error(location, anyhow!("{}", response.status())));
+        }
+        if let Some(expected) = expected {
+            expected.check(location, Some(response.headers()), response.body())?;
+        }
+        Ok(response.into_body())
+    } else if location == STDIN {
+        let display = "standard input";
+        let data = tokio::task::spawn_blocking(|| {
+            let mut data = Vec::new();
+            std::io::stdin().read_to_end(&mut data).map(|_| data)
+        })
+        .await
+        .map_err(|e| fetch_error(display, e))?
+        .map_err(|e| fetch_error(display, e))?;
+        if let Some(expected) = expected {
+            expected.check(display, None, &data)?;
+        }
+        Ok(data)
+    } else {
+        let path = base.join(location);
+        let display = path.display().to_string();
+        // Refuse files of the wrong length before reading them into memory.
+        if let Some(expected) = expected {
+            let metadata = fs::metadata(&path)
+                .await
+                .map_err(|e| fetch_error(&display, e))?;
+            expected.check_length(&display, metadata.len())?;
+        }
+        let data = fs::read(&path)
+            .await
+            .map_err(|e| fetch_error(&display, e))?;
+        if let Some(expected) = expected {
+            expected.check(&display, None, &data)?;
+        }
+        Ok(data)
+    }
+}
+
+/// The hex encoded SHA-256 digest of what [`fetch`] would return. Local files
+/// are hashed in chunks rather than read into memory whole.
+pub async fn fetch_digest(
+    transport: &dyn Transport,
+    location: &str,
+    base: &Path,
+) -> Result<String> {
+    Ok(
+        fetch_digest_with(transport, location, base, HashAlgorithm::Sha256)
+            .await?
+            .hex,
+    )
+}
+
+/// Like [`fetch_digest`], with `algorithm`.
+pub async fn fetch_digest_with(
+    transport: &dyn Transport,
+    location: &str,
+    base: &Path,
+    algorithm: HashAlgorithm,
+) -> Result<ArtifactDigest> {
+    let mut hasher = Hasher::new(algorithm)?;
+    if is_remote(location) {
+        hasher.update(&fetch(transport, location, base).await?);
+        return Ok(ArtifactDigest {
+            algorithm,
+            hex: hasher.finalize_hex(),
+        });
+    }
+    let path = base.join(location);
+    let display = path.display().to_string();
+    let hex = tokio::task::spawn_blocking(move || utils::hash_file(&path, hasher))
+        .await
+        .map_err(|e| fetch_error(&display, e))?
+        .map_err(|e| fetch_error(&display, e))?;
+    Ok(ArtifactDigest { algorithm, hex })
+}
+
+/// Where OCI artifacts are pulled from: a registry, or an OCI image layout
+/// directory, as exported by `oras copy --to-oci-layout` or `skopeo copy`,
+/// given as `oci-layout:<dir>[#<tag or digest>]`. Without a tag, the layout
+/// must hold a single manifest. Artifacts podman pulled are read from its
+/// store, given as `containers-storage:<reference>`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OciSource {
+    Registry(Reference),
+    Layout { dir: PathBuf, tag: Option<String> },
+}
+
+impl std::str::FromStr for OciSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        if let Some(spec) = s.strip_prefix(containers_storage::PREFIX) {
+            return containers_storage::source(spec);
+        }
+        match s.strip_prefix(OCI_LAYOUT_PREFIX) {
+            Some(layout) => {
+                let (dir, tag) = match layout.split_once('#') {
+                    Some((dir, tag)) => (dir, Some(tag.to_string())),
+                    None => (layout, None),
+                };
+                if dir.is_empty() {
+                    return Err(anyhow!("No OCI layout directory in {}", s));
+                }
+                Ok(OciSource::Layout {
+                    dir: PathBuf::from(dir),
+                    tag,
+                })
+            }
+            None => {
+                Ok(OciSource::Registry(s.parse().map_err(|e| {
+                    anyhow!("Invalid reference {}: {:?}", s, e)
+                })?))
+            }
+        }
+    }
+}
+
+impl From<Reference> for OciSource {
+    fn from(reference: Reference) -> Self {
+        OciSource::Registry(reference)
+    }
+}
+
+impl fmt::Display for OciSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OciSource::Registry(reference) => write!(f, "{}", reference.whole()),
+            OciSource::Layout { dir, tag: None } => {
+                write!(f, "{}{}", OCI_LAYOUT_PREFIX, dir.display())
+            }
+            OciSource::Layout {
+                dir,
+                tag: Some(tag),
+            } => {
+                write!(f, "{}{}#{}", OCI_LAYOUT_PREFIX, dir.display(), tag)
+            }
+        }
+    }
+}
+
+impl OciSource {
+    /// The origin requests for the artifact go to, `https://<host>/`, unless
+    /// it is local.
+    pub fn origin(&self) -> Option<String> {
+        match self {
+            OciSource::Registry(reference) => {
+                Some(format!("https://{}/", registry_host(reference)))
+            }
+            OciSource::Layout { .. } => None,
+        }
+    }
+
+    /// The repository holding the artifact, `<registry>/<repository>` or
+    /// `oci-layout:<dir>`, to find attestations in.
+    pub fn repository(&self) -> String {
+        match self {
+            OciSource::Registry(reference) => {
+                format!("{}/{}", reference.registry(), reference.repository())
+            }
+            OciSource::Layout { dir, .. } => format!("{}{}", OCI_LAYOUT_PREFIX, dir.display()),
+        }
+    }
+
+    /// The tag or digest of the artifact, if one was given.
+    fn tag(&self) -> Option<&str> {
+        match self {
+            OciSource::Registry(reference) => reference.digest().or_else(|| reference.tag()),
+            OciSource::Layout { tag, .. } => tag.as_deref(),
+        }
+    }
+
+    /// The artifact `tag`, which may also be a digest, in the repository
+    /// `repository` as returned by [`OciSource::repository`].
+    pub fn in_repository(repository: &str, tag: &str) -> anyhow::Result<Self> {
+        match repository.strip_prefix(OCI_LAYOUT_PREFIX) {
+            Some(_) => format!("{}#{}", repository, tag).parse(),
+            None if tag.starts_with("sha256:") => format!("{}@{}", repository, tag).parse(),
+            None => format!("{}:{}", repository, tag).parse(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ImageManifest {
+    layers: Vec<Descriptor>,
+}
+
+/// What a referrer manifest says about itself.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReferrerManifest {
+    #[serde(default)]
+    artifact_type: Option<String>,
+    #[serde(default)]
+    subject: Option<Descriptor>,
+    #[serde(default)]
+    config: Option<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct ImageIndex {
+    #[serde(default)]
+    manifests: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+    #[serde(default)]
+    artifact_type: Option<String>,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// An OCI distribution API session with one repository, anonymous unless
+/// the keychain has credentials for the registry, or the same requests
+/// answered from an OCI image layout directory.
+struct Registry<'a> {
+    transport: &'a dyn Transport,
+    base: String,
+    /// The registry, to look up credentials for.
+    registry: Option<String>,
+    token: Option<String>,
+    layout: Option<PathBuf>,
+}
+
+/// The host serving the registry API of `reference`'s registry.
+fn registry_host(reference: &Reference) -> &str {
+    match reference.registry() {
+        "docker.io" => "registry-1.docker.io",
+        registry => registry,
+    }
+}
+
+impl<'a> Registry<'a> {
+    /// A session with the repository of `source`.
+    fn new(transport: &'a dyn Transport, source: &OciSource) -> Self {
+        match source {
+            OciSource::Registry(reference) => {
+                let host = registry_host(reference);
+                Registry {
+                    transport,
+                    base: format!("https://{}/v2/{}", host, reference.repository()),
+                    registry: Some(reference.registry().to_string()),
+                    token: None,
+                    layout: None,
+                }
+            }
+            OciSource::Layout { dir, .. } => Registry {
+                transport,
+                base: dir.display().to_string(),
+                registry: None,
+                token: None,
+                layout: Some(dir.clone()),
+            },
+        }
+    }
+
+    /// The image manifest `tag`, which may also be a digest, and its digest.
+    async fn manifest(&mut self, tag: &str) -> anyhow::Result<(String, Vec<u8>)> {
+        let manifest = self
+            .get(&format!("manifests/{}", tag), Some(MANIFEST_MEDIA_TYPES))
+            .await?;
+        let digest = format!("sha256:{}", hex::encode(Sha256::digest(&manifest)));
+        if tag.starts_with("sha256:") && tag != digest {
+            return Err(anyhow!("Manifest {} has digest {}", tag, digest));
+        }
+        Ok((digest, manifest))
+    }
+
+    /// The layers of the image `tag`, which may also be a digest.
+    async fn layers(&mut self, tag: &str) -> anyhow::Result<Vec<Descriptor>> {
+        let (_, manifest) = self.manifest(tag).await?;
+        let manifest: ImageManifest = serde_json::from_slice(&manifest)?;
+        Ok(manifest.layers)
+    }
+
+    /// The manifests referring to the manifest with `digest`, `sha256:<hex>`,
+    /// that have `artifact_type`. Registries without the referrers API are
+    /// asked for the `sha256-<hex>` fallback tag instead.
+    async fn referrers(
+        &mut self,
+        digest: &str,
+        artifact_type: &str,
+    ) -> anyhow::Result<Vec<Descriptor>> {
+        if let Some(dir) = &self.layout {
+            return layout_referrers(dir, digest, artifact_type).await;
+        }
+        let query = serde_urlencoded::to_string([("artifactType", artifact_type)])?;
+        let index = match self
+            .get(
+                &format!("referrers/{}?{}", digest, query),
+                Some(INDEX_MEDIA_TYPE),
+            )
+            .await
+        {
+            Ok(index) => index,
+            Err(_) => {
+                let tag = digest.replacen(':', "-", 1);
+                match self
+                    .get(&format!("manifests/{}", tag), Some(INDEX_MEDIA_TYPE))
+                    .await
+                {
+                    Ok(index) => index,
+                    Err(_) => return Ok(Vec::new()),
+                }
+            }
+        };
+        let index: ImageIndex = serde_json::from_slice(&index)?;
+        Ok(index
+            .manifests
+            .into_iter()
+            .filter(|manifest| manifest.artifact_type.as_deref() == Some(artifact_type))
+            .collect())
+    }
+
+    /// The contents of `layer`, checked against its digest.
+    async fn blob(&mut self, layer: &Descriptor) -> Result<Vec<u8>> {
+        let data = self
+            .get(&format!("blobs/{}", layer.digest), None)
+            .await
+            .map_err(|e| fetch_error(&self.base, e))?;
+        let digest = format!("sha256:{}", hex::encode(Sha256::digest(&data)));
+        if digest != layer.digest {
+            return Err(SgetError::DigestMismatch {
+                expected: layer.digest.clone(),
+                actual: digest,
+            });
+        }
+        Ok(data)
+    }
+
+    /// GET `path` under the repository, fetching a bearer token first if the
+    /// registry asks for one.
+    async fn get(&mut self, path: &str, accept: Option<&str>) -> anyhow::Result<Vec<u8>> {
+        if let Some(dir) = &self.layout {
+            return layout_get(dir, path).await;
+        }
+        let url = format!("{}/{}", self.base, path);
+        let mut response = self.send(&url, accept).await?;
+        if response.status() == http::StatusCode::UNAUTHORIZED && self.token.is_none() {
+            self.token = Some(self.authenticate(&response).await?);
+            response = self.send(&url, accept).await?;
+        }
+        if !response.status().is_success() {
+            return Err(anyhow!("{} returned {}", url, response.status()));
+        }
+        Ok(response.into_body())
+    }
+
+    async fn send(&self, url: &str, accept: Option<&str>) -> anyhow::Result<Response<Vec<u8>>> {
+        let mut request = Request::get(url);
+        if let Some(accept) = accept {
+            request = request.header(ACCEPT, accept);
+        }
+        if let Some(token) = &self.token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        self.transport.send(request.body(Vec::new())?).await
+    }
+
+    /// Request a token from the realm named in a `WWW-Authenticate: Bearer`
+    /// challenge, with the registry's credentials from the keychain if there
+    /// are any. A keychain that cannot be read leaves the request anonymous.
+    async fn authenticate(&self, challenge: &Response<Vec<u8>>) -> anyhow::Result<String> {
+        let header = challenge
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| anyhow!("Registry requires unsupported authentication"))?;
+        let mut realm = None;
+        let mut params = Vec::new();
+        for param in header.split(',') {
+            let (key, value) = match param.trim().split_once('=') {
+                Some((key, value)) => (key, value.trim_matches('"')),
+                None => continue,
+            };
+            match key {
+                "realm" => realm = Some(value),
+                _ => params.push((key, value)),
+            }
+        }
+        let realm = realm.ok_or_else(|| anyhow!("Registry challenge has no realm"))?;
+        let url = format!("{}?{}", realm, serde_urlencoded::to_string(&params)?);
+        let mut request = Request::get(&url);
+        let credentials = self
+            .registry
+            .as_deref()
+            .and_then(|registry| RegistryCredentials::load(&Keychain, registry).ok())
+            .flatten();
+        if let Some(credentials) = credentials {
+            request = request.header(AUTHORIZATION, credentials.basic_auth().as_str());
+        }
+        let response = self.transport.send(request.body(Vec::new())?).await?;
+        let token: TokenResponse = transport::json(&response)?;
+        token
+            .token
+            .or(token.access_token)
+            .ok_or_else(|| anyhow!("Registry returned no token"))
+    }
+}
+
+/// The index of the OCI image layout at `dir`, checked to be one.
+async fn layout_index(dir: &Path) -> anyhow::Result<ImageIndex> {
+    let marker = fs::read(dir.join("oci-layout"))
+        .await
+        .map_err(|e| anyhow!("{} is not an OCI image layout: {}", dir.display(), e))?;
+    let marker: serde_json::Value = serde_json::from_slice(&marker)?;
+    if marker["imageLayoutVersion"] != "1.0.0" {
+        return Err(anyhow!(
+            "Unsupported OCI image layout version {}",
+            marker["imageLayoutVersion"]
+        ));
+    }
+    Ok(serde_json::from_slice(
+        &fs::read(dir.join("index.json")).await?,
+    )?)
+}
+
+/// The file of the blob with `digest`, `<algorithm>:<hex>`, in the OCI image
+/// layout at `dir`.
+fn layout_blob(dir: &Path, digest: &str) -> anyhow::Result<PathBuf> {
+    match digest.split_once(':') {
+        Some((algorithm, hex))
+            if !algorithm.is_empty()
+                && !hex.is_empty()
+                && algorithm.chars().all(|c| c.is_ascii_alphanumeric())
+                && hex.chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            Ok(dir.join("blobs").join(algorithm).join(hex))
+        }
+        _ => Err(anyhow!("Invalid digest {}", digest)),
+    }
+}
+
+/// Answer the distribution API request for `path` from the OCI image layout
+/// at `dir`: manifests by tag or digest, and blobs. A manifest without a tag
+/// is the only one in the layout.
+async fn layout_get(dir: &Path, path: &str) -> anyhow::Result<Vec<u8>> {
+    let digest = match (path.strip_prefix("manifests/"), path.strip_prefix("blobs/")) {
+        (Some(tag), _) if tag.starts_with("sha256:") => tag.to_string(),
+        (Some(tag), _) => {
+            let index = layout_index(dir).await?;
+            let manifests: Vec<&Descriptor> = match tag {
+                "" => index.manifests.iter().collect(),
+                tag => index
+                    .manifests
+                    .iter()
+                    .filter(|manifest| {
+                        manifest.annotations.get(REF_NAME).map(String::as_str) == Some(tag)
+                    })
+                    .collect(),
+            };
+            match manifests.as_slice() {
+                [manifest] => manifest.digest.clone(),
+                [] => return Err(anyhow!("{} has no manifest {}", dir.display(), tag)),
+                _ => {
+                    return Err(anyhow!(
+                        "{} has several manifests; name one with #<tag>",
+                        dir.display()
+                    ))
+                }
+            }
+        }
+        (None, Some(digest)) => digest.to_string(),
+        (None, None) => return Err(anyhow!("Cannot read {} from an OCI layout", path)),
+    };
+    let blob = layout_blob(dir, &digest)?;
+    fs::read(&blob)
+        .await
+        .map_err(|e| anyhow!("Cannot read {}: {}", blob.display(), e))
+}
+
+/// The manifests of the OCI image layout at `dir` that refer to the manifest
+/// with `digest` and have `artifact_type`.
+async fn layout_referrers(
+    dir: &Path,
+    digest: &str,
+    artifact_type: &str,
+) -> anyhow::Result<Vec<Descriptor>> {
+    let mut referrers = Vec::new();
+    for manifest in layout_index(dir).await?.manifests {
+        let raw = match fs::read(layout_blob(dir, &manifest.digest)?).await {
+            Ok(raw) => raw,
+            Err(_) => continue,
+        };
+        let referrer: ReferrerManifest = match serde_json::from_slice(&raw) {
+            Ok(referrer) => referrer,
+            Err(_) => continue,
+        };
+        let refers = referrer
+            .subject
+            .as_ref()
+            .is_some_and(|subject| subject.digest == digest);
+        let kind = referrer
+            .artifact_type
+            .or(referrer.config.map(|config| config.media_type));
+        if refers && kind.as_deref() == Some(artifact_type) {
+            referrers.push(Descriptor {
+                artifact_type: kind,
+                ..manifest
+            });
+        }
+    }
+    Ok(referrers)
+}
+
+/// Pull a script pushed to an OCI registry, or exported to an OCI image
+/// layout, as a single `text/plain` layer.
+pub async fn pull_oci(transport: &dyn Transport, source: &OciSource) -> Result<Vec<u8>> {
+    let whole = source.to_string();
+    let mut registry = Registry::new(transport, source);
+    let tag = default_tag(source);
+    let layer = registry
+        .layers(tag)
+        .await
+        .map_err(|e| fetch_error(&whole, e))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| fetch_error(&whole, anyhow!("Image has no layers")))?;
+    if layer.media_type != SCRIPT_MEDIA_TYPE {
+        return Err(fetch_error(
+            &whole,
+            anyhow!("Unexpected layer media type {}", layer.media_type),
+        ));
+    }
+    registry.blob(&layer).await
+}
+
+/// The tag or digest to pull from `source`: `latest` from registries, and the
+/// only manifest of layouts, unless one was given.
+fn default_tag(source: &OciSource) -> &str {
+    match source {
+        OciSource::Registry(_) => source.tag().unwrap_or("latest"),
+        OciSource::Layout { .. } => source.tag().unwrap_or_default(),
+    }
+}
+
+/// Resolve `source` to the digest of its manifest, `sha256:<hex>`.
+pub async fn resolve_oci(transport: &dyn Transport, source: &OciSource) -> Result<String> {
+    let whole = source.to_string();
+    let mut registry = Registry::new(transport, source);
+    let tag = default_tag(source);
+    let (digest, _) = registry
+        .manifest(tag)
+        .await
+        .map_err(|e| fetch_error(&whole, e))?;
+    Ok(digest)
+}
+
+/// Pull the JWS envelopes of the notation signatures of the manifest with
+/// `digest`, `sha256:<hex>`, in the repository of `source`.
+pub async fn pull_notation_signatures(
+    transport: &dyn Transport,
+    source: &OciSource,
+    digest: &str,
+) -> Result<Vec<Vec<u8>>> {
+    let whole = source.to_string();
+    let mut registry = Registry::new(transport, source);
+    let signatures = registry
+        .referrers(digest, notation::SIGNATURE_ARTIFACT_TYPE)
+        .await
+        .map_err(|e| fetch_error(&whole, e))?;
+    let mut envelopes = Vec::new();
+    for signature in &signatures {
+        let layers = registry
+            .layers(&signature.digest)
+            .await
+            .map_err(|e| fetch_error(&whole, e))?;
+        for layer in layers
+            .iter()
+            .filter(|layer| layer.media_type == notation::JWS_MEDIA_TYPE)
+        {
+            envelopes.push(registry.blob(layer).await?);
+        }
+    }
+    Ok(envelopes)
+}
+
+/// Pull the cosign attestations attached to the artifact with SHA-256 `digest`
+/// in `repository`, a registry repository or `oci-layout:<dir>`, as JSON
+/// Lines of DSSE envelopes. Certificates from the layer annotations are added
+/// to the envelope signatures.
+pub async fn pull_oci_attestations(
+    transport: &dyn Transport,
+    repository: &str,
+    digest: &str,
+) -> Result<Vec<u8>> {
+    let tag = format!("sha256-{}.att", digest);
+    let source = OciSource::in_repository(repository, &tag)
+        .map_err(|e| fetch_error(&format!("{}:{}", repository, tag), e))?;
+    let whole = source.to_string();
+    let mut registry = Registry::new(transport, &source);
+    let layers = registry
+        .layers(&format!("sha256-{}.att", digest))
+        .await
+        .map_err(|e| fetch_error(&whole, e))?;
+    let mut envelopes = Vec::new();
+    for layer in layers
+        .iter()
+        .filter(|layer| layer.media_type == DSSE_MEDIA_TYPE)
+    {
+        let data = registry.blob(layer).await?;
+        let mut envelope: Envelope =
+            serde_json::from_slice(&data).map_err(|e| fetch_error(&whole, e))?;
+        if let Some(certificate) = layer.annotations.get(COSIGN_CERTIFICATE) {
+            let mut chain = certificate.clone();
+            if let Some(rest) = layer.annotations.get(COSIGN_CHAIN) {
+                chain.push_str(rest);
+            }
+            for signature in &mut envelope.signatures {
+                signature.cert = Some(chain.clone());
+            }
+        }
+        serde_json::to_writer(&mut envelopes, &envelope).map_err(|e| fetch_error(&whole, e))?;
+        envelopes.push(b'\n');
+    }
+    Ok(envelopes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// A registry that requires an anonymous token for `example.com/scripts`.
+    struct FakeRegistry {
+        script: Vec<u8>,
+        requests: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Transport for FakeRegistry {
+        async fn send(&self, request: Request<Vec<u8>>) -> anyhow::Result<Response<Vec<u8>>> {
+            let url = request.uri().to_string();
+            self.requests.lock().expect("Poisoned").push(url.clone());
+            let authorized = request.headers().get(AUTHORIZATION).is_some();
+            let digest = format!("sha256:{}", hex::encode(Sha256::digest(&self.script)));
+            let response = Response::builder();
+            let response = match url.as_str() {
+                "https://auth.example.com/token?service=example.com&scope=repository%3Ascripts%3Apull" => {
+                    response.body(br#"{"token":"t0k3n"}"#.to_vec())
+                }
+                _ if !authorized => response.status(401).header(
+                    WWW_AUTHENTICATE,
+                    "Bearer realm=\"https://auth.example.com/token\",service=\"example.com\",scope=\"repository:scripts:pull\"",
+                ).body(Vec::new()),
+                "https://example.com/v2/scripts/manifests/v1" => response.body(
+                    serde_json::json!({
+                        "layers": [{ "mediaType": "text/plain", "digest": digest }],
+                    })
+                    .to_string()
+                    .into_bytes(),
+                ),
+                _ if url == format!("https://example.com/v2/scripts/blobs/{}", digest) => {
+                    response.body(self.script.clone())
+                }
+                _ => response.status(404).body(Vec::new()),
+            };
+            Ok(response?)
+        }
+    }
+
+    #[test]
+    fn check_content() {
+        let headers = |content_type: &str, length: usize| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, content_type.parse().expect("Invalid header"));
+            headers.insert(CONTENT_LENGTH, length.into());
+            headers
+        };
+        let url = "https://example.com/install.sh";
+        let script = b"echo hello";
+        let any = ContentExpectations::default();
+        any.check(url, Some(&headers("text/x-shellscript", 10)), script)
+            .expect("Unexpected content");
+        let login = b"<html>Sign in</html>";
+        assert!(matches!(
+            any.check(url, Some(&headers("text/html; charset=utf-8", 20)), login),
+            Err(SgetError::UnexpectedContent { .. })
+        ));
+        // A truncated download.
+        assert!(any
+            .check(url, Some(&headers("text/plain", 20)), script)
+            .is_err());
+
+        let expected = ContentExpectations {
+            content_types: vec!["text/*".to_string()],
+            length: Some(10),
+        };
+        expected
+            .check(url, Some(&headers("text/plain", 10)), script)
+            .expect("Unexpected content");
+        assert!(expected
+            .check(url, Some(&headers("application/octet-stream", 10)), script)
+            .is_err());
+        assert!(expected
+            .check(url, Some(&headers("text/plain", 11)), b"echo hello!")
+            .is_err());
+        expected
+            .check("install.sh", None, script)
+            .expect("Unexpected content");
+        assert!(expected.check("install.sh", None, b"echo").is_err());
+    }
+
+    #[tokio::test]
+    async fn pull_with_anonymous_token() {
+        let registry = FakeRegistry {
+            script: b"echo hello".to_vec(),
+            requests: Mutex::new(Vec::new()),
+        };
+        let reference: OciSource = "example.com/scripts:v1".parse().expect("Invalid reference");
+        let script = pull_oci(&registry, &reference).await.expect("Cannot pull");
+        assert_eq!(script, b"echo hello");
+        // Unauthorized manifest request, token, manifest, blob.
+        assert_eq!(registry.requests.lock().expect("Poisoned").len(), 4);
+
+        let missing: OciSource = "example.com/scripts:v2".parse().expect("Invalid reference");
+        assert!(matches!(
+            pull_oci(&registry, &missing).await,
+            Err(SgetError::Fetch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn pull_from_layout() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let blobs = dir.path().join("blobs/sha256");
+        std::fs::create_dir_all(&blobs).expect("Cannot create blobs");
+        let put = |data: &[u8]| {
+            let hex = hex::encode(Sha256::digest(data));
+            std::fs::write(blobs.join(&hex), data).expect("Cannot write blob");
+            format!("sha256:{}", hex)
+        };
+        let layer = put(b"echo offline");
+        let manifest = put(serde_json::json!({
+            "layers": [{ "mediaType": "text/plain", "digest": layer }],
+        })
+        .to_string()
+        .as_bytes());
+        std::fs::write(
+            dir.path().join("oci-layout"),
+            br#"{"imageLayoutVersion":"1.0.0"}"#,
+        )
+        .expect("Cannot write layout");
+        std::fs::write(
+            dir.path().join("index.json"),
+            serde_json::json!({
+                "manifests": [{
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "digest": manifest,
+                    "annotations": { REF_NAME: "v1" },
+                }],
+            })
+            .to_string(),
+        )
+        .expect("Cannot write index");
+
+        // No request reaches the transport.
+        let transport = crate::transport::OfflineTransport;
+        let layout = format!("oci-layout:{}", dir.path().display());
+        for source in [format!("{}#v1", layout), layout.clone()] {
+            let source: OciSource = source.parse().expect("Invalid source");
+            assert_eq!(
+                pull_oci(&transport, &source).await.expect("Cannot pull"),
+                b"echo offline"
+            );
+        }
+        let source: OciSource = format!("{}#v1", layout).parse().expect("Invalid source");
+        assert_eq!(
+            resolve_oci(&transport, &source)
+                .await
+                .expect("Cannot resolve"),
+            manifest
+        );
+        let missing: OciSource = format!("{}#v2", layout).parse().expect("Invalid source");
+        assert!(pull_oci(&transport, &missing).await.is_err());
+
+        // A tampered layer fails its digest.
+        std::fs::write(blobs.join(&layer[7..]), b"echo evil").expect("Cannot write blob");
+        assert!(matches!(
+            pull_oci(&transport, &source).await,
+            Err(SgetError::DigestMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,330 @@
+//! C bindings for fetching and verifying artifacts, declared in `include/sget.h`.
+//!
+//! Build the shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+//! Every function is blocking; a client may be used from one thread at a time.
+//! Strings passed in must be NUL terminated UTF-8, and everything returned must
+//! be released with the matching `*_free` function.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::path::Path;
+use std::ptr;
+
+use sha2::{Digest, Sha256};
+
+use crate::audit::{Action, AuditRecord, Decision};
+use crate::blocking::SgetClient;
+use crate::client::{self, Verifier};
+use crate::manifest::ManifestEntry;
+
+/// Bumped whenever `include/sget.h` changes incompatibly.
+pub const SGET_ABI_VERSION: u32 = 1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// The JSON encoding of `value` as a C string.
+fn json_string<T: serde::Serialize>(value: &T) -> Option<CString> {
+    match serde_json::to_string(value) {
+        Ok(json) => CString::new(json).ok(),
+        Err(e) => {
+            set_last_error(e.to_string());
+            None
+        }
+    }
+}
+
+/// Borrow a C string argument, recording an error if it is NULL or not UTF-8.
+///
+/// # Safety
+///
+/// `s` must be NULL or point to a NUL terminated string that outlives `'a`.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Option<&'a str> {
+    if s.is_null() {
+        set_last_error(format!("{} is NULL", name));
+        return None;
+    }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_last_error(format!("{} is not UTF-8", name));
+            None
+        }
+    }
+}
+
+/// The outcome of `sget_fetch`: the artifact, if it verified, and a report.
+pub struct FetchResult {
+    data: Vec<u8>,
+    allowed: bool,
+    report: CString,
+}
+
+/// The version of the C ABI the library implements.
+#[no_mangle]
+pub extern "C" fn sget_abi_version() -> u32 {
+    SGET_ABI_VERSION
+}
+
+/// The message of the last error on this thread, or NULL. The string is valid
+/// until the next call into the library on this thread.
+#[no_mangle]
+pub extern "C" fn sget_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// A client trusting the local trust store managed by `sget trust`, or NULL on
+/// error.
+#[no_mangle]
+pub extern "C" fn sget_client_new() -> *mut SgetClient {
+    let client = Verifier::from_trust_store()
+        .and_then(|verifier| SgetClient::new(client::SgetClient::new(verifier)));
+    match client {
+        Ok(client) => Box::into_raw(Box::new(client)),
+        Err(e) => {
+            set_last_error(e.describe());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free a client returned by `sget_client_new`.
+///
+/// # Safety
+///
+/// `client` must be NULL or a client returned by `sget_client_new` that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn sget_client_free(client: *mut SgetClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Fetch and verify the artifact described by `entry`, a JSON encoded manifest
+/// entry. Returns NULL only when the arguments are invalid; verification
+/// failures are reported in the result.
+///
+/// # Safety
+///
+/// `client` must be a live client from `sget_client_new` and `entry` a NUL
+/// terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sget_fetch(
+    client: *const SgetClient,
+    entry: *const c_char,
+) -> *mut FetchResult {
+    let client = match client.as_ref() {
+        Some(client) => client,
+        None => {
+            set_last_error("client is NULL".to_string());
+            return ptr::null_mut();
+        }
+    };
+    let entry: ManifestEntry = match str_arg(entry, "entry").map(serde_json::from_str) {
+        Some(Ok(entry)) => entry,
+        Some(Err(e)) => {
+            set_last_error(format!("Invalid manifest entry: {}", e));
+            return ptr::null_mut();
+        }
+        None => return ptr::null_mut(),
+    };
+    let (data, record) = match client.fetch(&entry) {
+        Ok(artifact) => {
+            let digest = hex::encode(Sha256::digest(artifact.data()));
+            let record = AuditRecord::verification(
+                Action::Fetch,
+                &entry.url,
+                Some(digest),
+                Ok(artifact.signer()),
+            );
+            (artifact.into_data(), record)
+        }
+        Err(e) => (
+            Vec::new(),
+            AuditRecord::verification(Action::Fetch, &entry.url, None, Err(&e)),
+        ),
+    };
+    let report = match json_string(&record) {
+        Some(report) => report,
+        None => return ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(FetchResult {
+        data,
+        allowed: record.decision == Decision::Allow,
+        report,
+    }))
+}
+
+/// Whether the artifact verified.
+///
+/// # Safety
+///
+/// `result` must be a live result from `sget_fetch`.
+#[no_mangle]
+pub unsafe extern "C" fn sget_result_allowed(result: *const FetchResult) -> bool {
+    matches!(result.as_ref(), Some(result) if result.allowed)
+}
+
+/// The verified artifact, empty unless it verified. Its length is stored in
+/// `len`. The data is valid until the result is freed.
+///
+/// # Safety
+///
+/// `result` must be a live result from `sget_fetch` and `len` must be valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sget_result_data(
+    result: *const FetchResult,
+    len: *mut usize,
+) -> *const u8 {
+    let data = result.as_ref().map_or(&[][..], |result| &result.data[..]);
+    if let Some(len) = len.as_mut() {
+        *len = data.len();
+    }
+    data.as_ptr()
+}
+
+/// The JSON report of the fetch, in the format of an audit log record. The
+/// string is valid until the result is freed.
+///
+/// # Safety
+///
+/// `result` must be a live result from `sget_fetch`.
+#[no_mangle]
+pub unsafe extern "C" fn sget_result_report(result: *const FetchResult) -> *const c_char {
+    result
+        .as_ref()
+        .map_or(ptr::null(), |result| result.report.as_ptr())
+}
+
+/// Free a result returned by `sget_fetch`.
+///
+/// # Safety
+///
+/// `result` must be NULL or a result from `sget_fetch` that has not been freed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn sget_result_free(result: *mut FetchResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+/// Verify every artifact in the manifest at `path` and return a JSON array of
+/// reports, one per artifact, or NULL on error. Free it with
+/// `sget_string_free`.
+///
+/// # Safety
+///
+/// `client` must be a live client from `sget_client_new` and `path` a NUL
+/// terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sget_verify_manifest(
+    client: *const SgetClient,
+    path: *const c_char,
+) -> *mut c_char {
+    let (client, path) = match (client.as_ref(), str_arg(path, "path")) {
+        (Some(client), Some(path)) => (client, path),
+        (None, _) => {
+            set_last_error("client is NULL".to_string());
+            return ptr::null_mut();
+        }
+        (_, None) => return ptr::null_mut(),
+    };
+    let results = match client.fetch_manifest(Path::new(path)) {
+        Ok(results) => results,
+        Err(e) => {
+            set_last_error(e.describe());
+            return ptr::null_mut();
+        }
+    };
+    let records: Vec<AuditRecord> = results
+        .iter()
+        .map(|result| {
+            AuditRecord::verification(
+                Action::Verify,
+                &result.url,
+                result.digest(),
+                result.outcome.as_ref(),
+            )
+        })
+        .collect();
+    json_string(&records).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Free a string returned by the library.
+///
+/// # Safety
+///
+/// `s` must be NULL or a string returned by `sget_verify_manifest` that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn sget_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use std::fs;
+
+    #[test]
+    fn fetch_over_c_abi() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let artifact = dir.path().join("a.sh");
+        fs::write(&artifact, b"echo a").expect("Cannot write artifact");
+        fs::write(
+            dir.path().join("a.sh.sig"),
+            base64::encode(key.sign(b"echo a")),
+        )
+        .expect("Cannot write signature");
+        let pem = dir.path().join("sget.pub");
+        fs::write(&pem, key.public_key().to_pem().expect("Cannot encode key"))
+            .expect("Cannot write key");
+
+        let client = Box::into_raw(Box::new(
+            SgetClient::new(client::SgetClient::new(
+                Verifier::sigstore().expect("Cannot load roots"),
+            ))
+            .expect("Cannot start runtime"),
+        ));
+        let entry = serde_json::json!({
+            "url": artifact.to_str().expect("Non UTF-8 path"),
+            "key": pem.to_str().expect("Non UTF-8 path"),
+        });
+        let entry = CString::new(entry.to_string()).expect("Invalid entry");
+        unsafe {
+            let result = sget_fetch(client, entry.as_ptr());
+            assert!(sget_result_allowed(result));
+            let mut len = 0;
+            let data = sget_result_data(result, &mut len);
+            assert_eq!(std::slice::from_raw_parts(data, len), b"echo a");
+            let report = CStr::from_ptr(sget_result_report(result));
+            let report: AuditRecord =
+                serde_json::from_slice(report.to_bytes()).expect("Invalid report");
+            assert_eq!(report.decision, Decision::Allow);
+            sget_result_free(result);
+
+            assert!(sget_fetch(client, CString::default().as_ptr()).is_null());
+            assert!(!sget_last_error().is_null());
+            sget_client_free(client);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::algorithms::{HashAlgorithm, SignatureAlgorithm};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Restrict the cryptography of this process to FIPS-approved algorithms:
+/// ECDSA over P-256 and P-384 and RSA keys of at least 2048 bits, with SHA-2,
+/// and PBKDF2 with AES-256 to encrypt private keys. Anything else, such as
+/// Ed25519 signatures, SHA-1 signatures or scrypt encrypted keys, is refused
+/// rather than used. There is no way to disable FIPS mode again.
+///
+/// FIPS mode restricts algorithms only; the ring and RustCrypto
+/// implementations sget is built on are not FIPS 140 validated modules.
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Whether FIPS mode was enabled with [`enable`] or by building with the
+/// `fips` feature.
+pub fn is_enabled() -> bool {
+    cfg!(feature = "fips") || ENABLED.load(Ordering::SeqCst)
+}
+
+/// Whether a signature made with a `key_bits` bit key of type `signature` over
+/// a `hash` digest is FIPS-approved.
+pub fn is_approved(signature: SignatureAlgorithm, hash: HashAlgorithm, key_bits: usize) -> bool {
+    let key = match signature {
+        SignatureAlgorithm::EcdsaP256 | SignatureAlgorithm::EcdsaP384 => true,
+        SignatureAlgorithm::Rsa => key_bits >= 2048,
+        SignatureAlgorithm::Ed25519 => false,
+    };
+    key && !matches!(hash, HashAlgorithm::Sha1 | HashAlgorithm::Blake3)
+}
+
+/// Fail if FIPS mode is enabled, as `what` is not FIPS-approved.
+pub(crate) fn refuse(what: &str) -> Result<()> {
+    if is_enabled() {
+        return Err(anyhow!("{} is not FIPS-approved", what));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::AlgorithmPolicy;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+
+    #[test]
+    fn approved_algorithms() {
+        assert!(is_approved(
+            SignatureAlgorithm::EcdsaP384,
+            HashAlgorithm::Sha384,
+            384
+        ));
+        assert!(is_approved(
+            SignatureAlgorithm::Rsa,
+            HashAlgorithm::Sha256,
+            3072
+        ));
+        assert!(!is_approved(
+            SignatureAlgorithm::Rsa,
+            HashAlgorithm::Sha1,
+            3072
+        ));
+        assert!(!is_approved(
+            SignatureAlgorithm::Rsa,
+            HashAlgorithm::Sha256,
+            1024
+        ));
+        assert!(!is_approved(
+            SignatureAlgorithm::Ed25519,
+            HashAlgorithm::Sha512,
+            256
+        ));
+
+        // Keys are encrypted with PBKDF2 in FIPS mode.
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let pem = key
+            .encrypt_pem("hunter2", true)
+            .expect("Cannot encrypt key");
+        let loaded = SigningKey::from_encrypted_pem(&pem, "hunter2").expect("Cannot decrypt key");
+        assert_eq!(loaded.public_key(), key.public_key());
+    }
+
+    /// Approved signatures verify in FIPS builds as in any other; run with
+    /// `--features fips` to check FIPS mode.
+    #[test]
+    fn accept_approved_signature() {
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let signature = key.sign(b"echo hi");
+        key.public_key()
+            .verify(b"echo hi", &signature)
+            .expect("Cannot verify signature");
+        assert!(key.public_key().verify(b"echo bye", &signature).is_err());
+        AlgorithmPolicy::default()
+            .check(SignatureAlgorithm::EcdsaP256, HashAlgorithm::Sha256, 256)
+            .expect("P-256 with SHA-256 refused");
+        let pem = key.to_encrypted_pem("hunter2").expect("Cannot encrypt key");
+        SigningKey::from_encrypted_pem(&pem, "hunter2").expect("Cannot decrypt key");
+    }
+
+    #[test]
+    #[cfg(feature = "fips")]
+    fn refuse_unapproved_algorithms() {
+        assert!(is_enabled());
+        assert!(SigningKey::generate(KeyAlgorithm::Ed25519).is_err());
+        assert!(AlgorithmPolicy::default()
+            .check(SignatureAlgorithm::Ed25519, HashAlgorithm::Sha512, 256)
+            .is_err());
+    }
+}
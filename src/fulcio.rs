@@ -0,0 +1,112 @@
+use anyhow::{anyhow, Result};
+use http::header::{HeaderValue, AUTHORIZATION};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::keys::SigningKey;
+use crate::transport::{self, Transport};
+
+pub const DEFAULT_FULCIO_URL: &str = "https://fulcio.sigstore.dev";
+
+// The claims of an OIDC identity token that Fulcio binds into the certificate.
+#[derive(Deserialize)]
+pub struct IdentityClaims {
+    pub email: Option<String>,
+    pub sub: String,
+    pub iss: String,
+}
+
+impl IdentityClaims {
+    /// Decode the (unverified) claims of a JWT; Fulcio verifies the token itself.
+    pub fn from_token(token: &str) -> Result<Self> {
+        let payload = token
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| anyhow!("Identity token is not a JWT"))?;
+        let raw = base64::decode_config(payload.trim_end_matches('='), base64::URL_SAFE_NO_PAD)?;
+        Ok(serde_json::from_slice(&raw)?)
+    }
+
+    /// The subject Fulcio expects proof of possession for.
+    pub fn subject(&self) -> &str {
+        self.email.as_deref().unwrap_or(&self.sub)
+    }
+}
+
+/// Request a short-lived signing certificate for `key` from Fulcio, returning
+/// the PEM encoded certificate chain (leaf first).
+pub async fn request_certificate(
+    transport: &dyn Transport,
+    fulcio_url: &str,
+    identity_token: &str,
+    key: &SigningKey,
+) -> Result<String> {
+    let claims = IdentityClaims::from_token(identity_token)?;
+    // Prove possession of the private key by signing the token subject.
+    let proof = key.sign(claims.subject().as_bytes());
+    let request = json!({
+        "publicKey": {
+            "content": base64::encode(key.public_key().to_der()?),
+            "algorithm": "ecdsa",
+        },
+        "signedEmailAddress": base64::encode(proof),
+    });
+    let url = format!("{}/api/v1/signingCert", fulcio_url.trim_end_matches('/'));
+    let mut request = transport::post_json(&url, &request)?;
+    request.headers_mut().insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {}", identity_token))?,
+    );
+    let response = transport.send(request).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Fulcio refused to issue a certificate ({}): {}",
+            response.status(),
+            transport::text(&response)
+        ));
+    }
+    Ok(String::from_utf8(response.into_body())?)
+}
+
+/// The first (leaf) certificate of a PEM encoded certificate chain.
+pub fn leaf_certificate(chain: &str) -> Result<String> {
+    const END: &str = "-----END CERTIFICATE-----";
+    chain
+        .find(END)
+        .map(|end| format!("{}\n", chain[..end + END.len()].trim_start()))
+        .ok_or_else(|| anyhow!("No certificate found in chain"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_identity_claims() {
+        let claims = base64::encode_config(
+            br#"{"iss":"https://oauth2.sigstore.dev/auth","sub":"1234","email":"jpenumak@redhat.com"}"#,
+            base64::URL_SAFE_NO_PAD,
+        );
+        let token = format!("eyJhbGciOiJSUzI1NiJ9.{}.c2ln", claims);
+        let claims = IdentityClaims::from_token(&token).expect("Cannot parse token");
+        assert_eq!(claims.subject(), "jpenumak@redhat.com");
+        assert_eq!(claims.iss, "https://oauth2.sigstore.dev/auth");
+    }
+
+    #[test]
+    fn leaf_of_chain() {
+        let chain = "-----BEGIN CERTIFICATE-----\nleaf\n-----END CERTIFICATE-----\n\
+                     -----BEGIN CERTIFICATE-----\nroot\n-----END CERTIFICATE-----\n";
+        let leaf = leaf_certificate(chain).expect("No leaf");
+        assert_eq!(
+            leaf,
+            "-----BEGIN CERTIFICATE-----\nleaf\n-----END CERTIFICATE-----\n"
+        );
+        assert!(leaf_certificate("").is_err());
+    }
+
+    #[test]
+    fn reject_non_jwt() {
+        assert!(IdentityClaims::from_token("not-a-token").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::error::SgetError;
+use crate::transport;
+
+/// The kinds of events hooks can subscribe to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    /// A watched policy has a new version.
+    PolicyVersion,
+    /// Keys were added to or removed from a watched policy.
+    KeyRotation,
+    /// A watched policy expired or is about to.
+    PolicyExpiry,
+    /// A watched policy has not changed for longer than its maximum age.
+    FreezeAttack,
+    /// An artifact failed verification.
+    VerificationFailure,
+}
+
+/// A hook from the `hooks` section of the config. Each hook either runs a
+/// command, with the event as JSON on stdin, or POSTs the event to a webhook.
+#[derive(Serialize, Deserialize)]
+pub struct HookConfig {
+    /// Events to fire on; all events when empty.
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    /// Program and arguments to run.
+    #[serde(default)]
+    pub exec: Vec<String>,
+    /// URL to POST the event to.
+    pub webhook: Option<String>,
+}
+
+/// The JSON payload passed to hooks.
+#[derive(Debug, Serialize)]
+pub struct Event {
+    pub kind: EventKind,
+    pub timestamp: DateTime<Utc>,
+    /// The watched namespace, for policy events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Human readable description of the event.
+    pub message: String,
+    /// Event specific fields.
+    pub details: serde_json::Value,
+}
+
+impl Event {
+    /// The event for `url` failing verification with `error`.
+    pub fn verification_failure(url: &str, error: &SgetError) -> Self {
+        let reason = error.reason().map(|reason| reason.label());
+        Event {
+            kind: EventKind::VerificationFailure,
+            timestamp: Utc::now(),
+            namespace: None,
+            message: format!("{} failed verification: {}", url, error.describe()),
+            details: serde_json::json!({
+                "url": url,
+                "reason": reason,
+                "error": error.describe(),
+            }),
+        }
+    }
+}
+
+impl HookConfig {
+    fn matches(&self, kind: EventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+
+    fn exec(argv: &[String], payload: &[u8]) -> Result<()> {
+        let (program, args) = argv
+            .split_first()
+            .ok_or_else(|| anyhow!("Hook has an empty exec"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Cannot run hook {}", program))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(payload)?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(anyhow!("Hook {} exited with {}", program, status));
+        }
+        Ok(())
+    }
+
+    async fn post(&self, url: &str, payload: Vec<u8>) -> Result<()> {
+        if transport::is_offline() {
+            return Err(SgetError::Offline(url.to_string()).into());
+        }
+        let response = transport::shared_client()?
+            .post(url)
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send()
+            .await
+            .with_context(|| format!("Cannot POST to {}", url))?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Webhook {} returned {}", url, response.status()));
+        }
+        Ok(())
+    }
+
+    async fn fire(&self, payload: &[u8]) -> Result<()> {
+        if !self.exec.is_empty() {
+            let (argv, stdin) = (self.exec.clone(), payload.to_vec());
+            tokio::task::spawn_blocking(move || Self::exec(&argv, &stdin)).await??;
+        }
+        if let Some(url) = &self.webhook {
+            self.post(url, payload.to_vec()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Run every hook subscribed to `event`. Hook failures are reported on stderr
+/// and never fail the caller.
+pub async fn fire(hooks: &[HookConfig], event: &Event) {
+    let payload = match serde_json::to_vec(event) {
+        Ok(payload) => payload,
+        Err(e) => {
+            eprintln!("Cannot encode hook event: {}", e);
+            return;
+        }
+    };
+    for hook in hooks.iter().filter(|hook| hook.matches(event.kind)) {
+        if let Err(e) = hook.fire(&payload).await {
+            eprintln!("Hook failed: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn event(kind: EventKind) -> Event {
+        Event {
+            kind,
+            timestamp: Utc::now(),
+            namespace: Some("tools".to_string()),
+            message: "policy version changed from 1 to 2".to_string(),
+            details: serde_json::json!({ "from": 1, "to": 2 }),
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exec_hook_receives_event() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let out = dir.path().join("event.json");
+        let hooks: Vec<HookConfig> = serde_yaml::from_str(&format!(
+            "- events: [policy-version]\n  exec: [sh, -c, 'cat > {}']\n",
+            out.display()
+        ))
+        .expect("Invalid hooks");
+
+        fire(&hooks, &event(EventKind::KeyRotation)).await;
+        assert!(!out.exists());
+
+        fire(&hooks, &event(EventKind::PolicyVersion)).await;
+        let payload: serde_json::Value =
+            serde_json::from_slice(&fs::read(&out).expect("Hook did not run"))
+                .expect("Invalid payload");
+        assert_eq!(payload["kind"], "policy-version");
+        assert_eq!(payload["namespace"], "tools");
+        assert_eq!(payload["details"]["to"], 2);
+    }
+}
@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, SgetError};
+use crate::targets;
+use crate::verify_core::Signer;
+
+/// The signers required for artifacts, by location pattern, read from
+/// `identities.yaml` next to the configuration. Keeping these constraints in
+/// one reviewed file replaces passing `--certificate-identity` and
+/// `--certificate-oidc-issuer` on every invocation:
+///
+/// ```yaml
+/// artifacts:
+///   - pattern: https://example.com/scripts/**
+///     signers:
+///       - identity: release@example.com
+///         issuer: https://accounts.google.com
+///   - pattern: https://example.com/scripts/ci/*.sh
+///     signers:
+///       - identity: https://github.com/acme/scripts/.github/workflows/*
+///         issuer: https://token.actions.githubusercontent.com
+/// ```
+///
+/// Patterns are globs over the artifact's location, and identities are globs
+/// too, both as in [`TargetRule`](crate::targets::TargetRule). The most
+/// specific pattern matching an artifact applies, and its signer must be one
+/// of the rule's signers. Artifacts no pattern matches are unconstrained.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityMap {
+    #[serde(default)]
+    pub artifacts: Vec<IdentityRule>,
+}
+
+/// The signers allowed for the artifacts matching a pattern.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityRule {
+    pub pattern: String,
+    pub signers: Vec<RequiredSigner>,
+}
+
+/// A keyless signer identity, and optionally the OIDC issuer that must have
+/// issued it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RequiredSigner {
+    pub identity: String,
+    #[serde(default)]
+    pub issuer: Option<String>,
+}
+
+impl RequiredSigner {
+    pub(crate) fn matches(&self, signer: &Signer) -> bool {
+        signer
+            .identity
+            .as_deref()
+            .is_some_and(|identity| targets::glob_matches(&self.identity, identity))
+            && self
+                .issuer
+                .as_ref()
+                .is_none_or(|issuer| signer.issuer.as_ref() == Some(issuer))
+    }
+}
+
+impl IdentityMap {
+    /// The most specific rule matching the artifact at `location`, if any.
+    pub fn rule_for(&self, location: &str) -> Option<&IdentityRule> {
+        // Search in reverse so that ties go to the rule listed first.
+        self.artifacts
+            .iter()
+            .rev()
+            .filter(|rule| targets::glob_matches(&rule.pattern, location))
+            .max_by_key(|rule| targets::specificity(&rule.pattern))
+    }
+
+    /// Check that `signer` may sign the artifact at `location`. Signers of
+    /// artifacts whose location is unknown are not constrained.
+    pub fn check(&self, signer: &Signer, location: Option<&str>) -> Result<()> {
+        let rule = match location.and_then(|location| self.rule_for(location)) {
+            Some(rule) => rule,
+            None => return Ok(()),
+        };
+        if !rule.signers.iter().any(|required| required.matches(signer)) {
+            return Err(SgetError::UntrustedSigner(format!(
+                "{} is not an identity allowed for {} by identities.yaml",
+                signer.subject(),
+                rule.pattern
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "native")]
+impl IdentityMap {
+    /// Load `identities.yaml` from the configuration directory; no file is an
+    /// empty map.
+    pub fn load() -> anyhow::Result<Self> {
+        let config = crate::config::Config::path()?;
+        let mut map = match config.parent() {
+            Some(dir) => Self::load_from(&dir.join("identities.yaml"))?,
+            None => Self::default(),
+        };
+        if let Some((_, profile)) = crate::profile::active() {
+            map.artifacts.extend(profile.identities.iter().cloned());
+        }
+        Ok(map)
+    }
+
+    pub fn load_from(path: &std::path::Path) -> anyhow::Result<Self> {
+        use anyhow::Context;
+
+        match std::fs::read(path) {
+            Ok(raw) => serde_yaml::from_slice(&raw)
+                .with_context(|| format!("Invalid identity map {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Cannot read {}", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(identity: &str, issuer: &str) -> Signer {
+        Signer {
+            key_id: String::new(),
+            identity: Some(identity.to_string()),
+            issuer: Some(issuer.to_string()),
+            integrated_time: None,
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn require_mapped_identities() {
+        let map: IdentityMap = serde_yaml::from_str(
+            r#"
+artifacts:
+  - pattern: https://example.com/scripts/**
+    signers:
+      - identity: release@example.com
+        issuer: https://accounts.google.com
+  - pattern: https://example.com/scripts/ci/*.sh
+    signers:
+      - identity: https://github.com/acme/scripts/.github/workflows/*
+"#,
+        )
+        .expect("Invalid identity map");
+        let google = "https://accounts.google.com";
+        let release = signer("release@example.com", google);
+        let workflow = signer(
+            "https://github.com/acme/scripts/.github/workflows/ci.yml",
+            "https://token.actions.githubusercontent.com",
+        );
+
+        let install = Some("https://example.com/scripts/install.sh");
+        assert!(map.check(&release, install).is_ok());
+        assert!(map.check(&workflow, install).is_err());
+        assert!(map
+            .check(
+                &signer("release@example.com", "https://evil.example"),
+                install
+            )
+            .is_err());
+
+        // The more specific pattern wins.
+        let build = Some("https://example.com/scripts/ci/build.sh");
+        assert!(map.check(&workflow, build).is_ok());
+        assert!(matches!(
+            map.check(&release, build),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+
+        // Unmapped and unknown locations are unconstrained.
+        assert!(map
+            .check(&workflow, Some("https://example.com/other/install.sh"))
+            .is_ok());
+        assert!(map.check(&workflow, None).is_ok());
+    }
+}
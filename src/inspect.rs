@@ -0,0 +1,539 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::BTreeMap;
+use std::path::Path;
+use x509_parser::certificate::X509Certificate;
+
+use crate::algorithms::key_algorithm;
+use crate::fetch::fetch;
+use crate::keys::PublicKey;
+use crate::policy::{Key, Policy};
+use crate::rekor::{self, Bundle};
+use crate::transport;
+use crate::verify_core::{certificate_identity, parse_certificate, pem_certificates};
+
+/// Everything that can be told about an input without verifying it.
+#[derive(Debug, Serialize)]
+pub struct Inspection {
+    pub length: u64,
+    /// Hex encoded digests of the input, by algorithm.
+    pub digests: BTreeMap<&'static str, String>,
+    #[serde(flatten)]
+    pub contents: Contents,
+}
+
+/// What the input turned out to be.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Contents {
+    Policy(PolicySummary),
+    Certificates {
+        certificates: Vec<CertificateSummary>,
+    },
+    PublicKey {
+        algorithm: String,
+        key_id: String,
+    },
+    /// A sigstore, cosign or Rekor bundle.
+    Bundle(BundleSummary),
+    Signature(SignatureSummary),
+    /// Anything else, of which only the digests are known.
+    Artifact,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolicySummary {
+    pub namespace: Vec<String>,
+    pub version: u64,
+    pub spec_version: String,
+    pub expires: DateTime<Utc>,
+    pub expired: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include: Option<String>,
+    pub keys: BTreeMap<String, KeySummary>,
+    pub roles: BTreeMap<String, RoleSummary>,
+    pub target_rules: usize,
+    pub signatures: Vec<PolicySignature>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeySummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub keytype: String,
+    pub scheme: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoleSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub threshold: u64,
+    pub keyids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolicySignature {
+    pub keyid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<CertificateSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CertificateSummary {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub not_before: Option<DateTime<Utc>>,
+    pub not_after: Option<DateTime<Utc>>,
+    /// The email or URI a Fulcio certificate was issued to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oidc_issuer: Option<String>,
+    pub signature_algorithm: String,
+    pub public_key: String,
+    pub extensions: Vec<ExtensionSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtensionSummary {
+    pub oid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<&'static str>,
+    pub critical: bool,
+    /// The value as text when it is printable, hex encoded DER otherwise.
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureSummary>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub certificates: Vec<CertificateSummary>,
+    pub rekor: RekorSummary,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignatureSummary {
+    pub length: usize,
+    /// The encoding the signature's bytes suggest.
+    pub format: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RekorSummary {
+    pub log_index: i64,
+    pub log_id: String,
+    pub integrated_time: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry_kind: Option<String>,
+    /// The digest of the logged artifact, `<algorithm>:<hex>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_digest: Option<String>,
+}
+
+/// Names of the certificate extensions worth recognising.
+fn extension_name(oid: &str) -> Option<&'static str> {
+    Some(match oid {
+        "2.5.29.14" => "Subject Key Identifier",
+        "2.5.29.15" => "Key Usage",
+        "2.5.29.17" => "Subject Alternative Name",
+        "2.5.29.19" => "Basic Constraints",
+        "2.5.29.35" => "Authority Key Identifier",
+        "2.5.29.37" => "Extended Key Usage",
+        "1.3.6.1.5.5.7.1.1" => "Authority Information Access",
+        "1.3.6.1.4.1.11129.2.4.2" => "Certificate Transparency SCTs",
+        "1.3.6.1.4.1.57264.1.1" => "Fulcio OIDC Issuer",
+        "1.3.6.1.4.1.57264.1.2" => "Fulcio GitHub Workflow Trigger",
+        "1.3.6.1.4.1.57264.1.3" => "Fulcio GitHub Workflow SHA",
+        "1.3.6.1.4.1.57264.1.4" => "Fulcio GitHub Workflow Name",
+        "1.3.6.1.4.1.57264.1.5" => "Fulcio GitHub Workflow Repository",
+        "1.3.6.1.4.1.57264.1.6" => "Fulcio GitHub Workflow Ref",
+        "1.3.6.1.4.1.57264.1.8" => "Fulcio OIDC Issuer (v2)",
+        _ => return None,
+    })
+}
+
+fn signature_algorithm_name(oid: &str) -> String {
+    match oid {
+        "1.2.840.10045.4.3.2" => "ecdsa-with-SHA256",
+        "1.2.840.10045.4.3.3" => "ecdsa-with-SHA384",
+        "1.2.840.10045.4.3.4" => "ecdsa-with-SHA512",
+        "1.2.840.113549.1.1.11" => "sha256WithRSAEncryption",
+        "1.2.840.113549.1.1.12" => "sha384WithRSAEncryption",
+        "1.2.840.113549.1.1.13" => "sha512WithRSAEncryption",
+        "1.3.101.112" => "Ed25519",
+        other => other,
+    }
+    .to_string()
+}
+
+fn time(secs: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(secs, 0).single()
+}
+
+/// `bytes` as text if it is printable, as hex otherwise.
+fn printable(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.is_empty() && text.chars().all(|c| !c.is_control()) => text.to_string(),
+        _ => hex::encode(bytes),
+    }
+}
+
+fn certificate_summary(cert: &X509Certificate) -> CertificateSummary {
+    let (identity, oidc_issuer) = match certificate_identity(cert) {
+        Ok((identity, issuer)) => (Some(identity), issuer),
+        Err(_) => (None, None),
+    };
+    let public_key = match key_algorithm(cert.public_key()) {
+        Ok((algorithm, bits)) => format!("{} ({} bits)", algorithm, bits),
+        Err(_) => cert.public_key().algorithm.algorithm.to_id_string(),
+    };
+    CertificateSummary {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        serial: cert.tbs_certificate.raw_serial_as_string(),
+        not_before: time(cert.validity().not_before.timestamp()),
+        not_after: time(cert.validity().not_after.timestamp()),
+        identity,
+        oidc_issuer,
+        signature_algorithm: signature_algorithm_name(
+            &cert.signature_algorithm.algorithm.to_id_string(),
+        ),
+        public_key,
+        extensions: cert
+            .extensions()
+            .iter()
+            .map(|ext| {
+                let oid = ext.oid.to_id_string();
+                ExtensionSummary {
+                    name: extension_name(&oid),
+                    oid,
+                    critical: ext.critical,
+                    value: printable(ext.value),
+                }
+            })
+            .collect(),
+    }
+}
+
+/// The certificates of a PEM chain, which may itself be base64 encoded as in
+/// cosign bundles and policies.
+fn certificates(pem: &[u8]) -> Result<Vec<CertificateSummary>> {
+    let decoded;
+    let pem = match base64::decode(pem) {
+        Ok(pem) => {
+            decoded = pem;
+            &decoded
+        }
+        Err(_) => pem,
+    };
+    pem_certificates(pem)?
+        .iter()
+        .map(|der| Ok(certificate_summary(&parse_certificate(der)?)))
+        .collect()
+}
+
+fn signature_summary(encoded: &str) -> Option<SignatureSummary> {
+    let signature = base64::decode(encoded.trim()).ok()?;
+    let format = match signature.as_slice() {
+        [0x30, ..] if signature.len() <= 140 => "DER encoded ECDSA",
+        bytes if bytes.len() == 64 => "Ed25519",
+        bytes if bytes.len() >= 256 => "RSA",
+        _ => "unknown",
+    };
+    Some(SignatureSummary {
+        length: signature.len(),
+        format,
+    })
+}
+
+fn rekor_summary(bundle: &Bundle) -> RekorSummary {
+    let body: Option<Value> = base64::decode(&bundle.payload.body)
+        .ok()
+        .and_then(|body| serde_json::from_slice(&body).ok());
+    let entry_kind = body.as_ref().and_then(|body| {
+        Some(format!(
+            "{} {}",
+            body.get("kind")?.as_str()?,
+            body.get("apiVersion")?.as_str()?
+        ))
+    });
+    let artifact_digest = body.as_ref().and_then(|body| {
+        let hash = body.pointer("/spec/data/hash")?;
+        Some(format!(
+            "{}:{}",
+            hash.get("algorithm")?.as_str()?,
+            hash.get("value")?.as_str()?
+        ))
+    });
+    RekorSummary {
+        log_index: bundle.payload.log_index,
+        log_id: bundle.payload.log_id.clone(),
+        integrated_time: time(bundle.payload.integrated_time),
+        entry_kind,
+        artifact_digest,
+    }
+}
+
+fn key_summary(key: &Key) -> KeySummary {
+    let (identity, issuer) = match key {
+        Key::SigstoreOidc { keyval, .. } => (
+            Some(keyval.identity.clone()),
+            Some(keyval.issuer.clone()).filter(|issuer| !issuer.is_empty()),
+        ),
+        _ => (None, None),
+    };
+    KeySummary {
+        name: key.name().map(String::from),
+        description: key.description().map(String::from),
+        keytype: key.keytype().to_string(),
+        scheme: key.scheme().to_string(),
+        identity,
+        issuer,
+        expires: key.expires(),
+    }
+}
+
+fn policy_summary(policy: &Policy, now: DateTime<Utc>) -> PolicySummary {
+    let signed = &policy.signed;
+    PolicySummary {
+        namespace: signed.namespace.patterns().to_vec(),
+        version: signed.version.get(),
+        spec_version: signed.spec_version.clone(),
+        expires: signed.expires,
+        expired: signed.expires <= now,
+        include: signed.include.as_ref().map(|include| include.url.clone()),
+        keys: signed
+            .keys
+            .iter()
+            .map(|(keyid, key)| (keyid.clone(), key_summary(key)))
+            .collect(),
+        roles: signed
+            .roles
+            .iter()
+            .map(|(role, keys)| {
+                (
+                    role.clone(),
+                    RoleSummary {
+                        name: keys.name.clone(),
+                        description: keys.description.clone(),
+                        threshold: keys.threshold.get(),
+                        keyids: keys.keyids.clone(),
+                        expires: keys.expires,
+                    },
+                )
+            })
+            .collect(),
+        target_rules: signed.targets.len(),
+        signatures: policy
+            .signatures
+            .iter()
+            .map(|signature| PolicySignature {
+                keyid: signature.keyid.clone(),
+                certificate: signature
+                    .certificate_chain()
+                    .ok()
+                    .and_then(|pem| certificates(&pem).ok())
+                    .and_then(|chain| chain.into_iter().next()),
+            })
+            .collect(),
+    }
+}
+
+/// Work out what `raw` is, trying the formats sget reads in turn, and
+/// describe it.
+pub fn inspect(raw: &[u8], now: DateTime<Utc>) -> Inspection {
+    let digests = BTreeMap::from([
+        ("sha256", hex::encode(Sha256::digest(raw))),
+        ("sha384", hex::encode(Sha384::digest(raw))),
+        ("sha512", hex::encode(Sha512::digest(raw))),
+    ]);
+    Inspection {
+        length: raw.len() as u64,
+        digests,
+        contents: contents(raw, now),
+    }
+}
+
+fn contents(raw: &[u8], now: DateTime<Utc>) -> Contents {
+    let text = String::from_utf8_lossy(raw);
+    if text.contains("-----BEGIN CERTIFICATE-----") {
+        if let Ok(certificates) = certificates(raw) {
+            return Contents::Certificates { certificates };
+        }
+    }
+    if text.contains("-----BEGIN PUBLIC KEY-----") {
+        if let Ok(key) = PublicKey::from_pem(&text) {
+            return Contents::PublicKey {
+                algorithm: key.algorithm().scheme().to_string(),
+                key_id: key.key_id().unwrap_or_default(),
+            };
+        }
+    }
+    if let Ok(bundle) = rekor::parse_bundle(raw) {
+        let embedded = rekor::embedded_signature(raw);
+        return Contents::Bundle(BundleSummary {
+            signature: embedded
+                .as_ref()
+                .and_then(|(signature, _)| signature_summary(signature)),
+            certificates: embedded
+                .and_then(|(_, cert)| cert)
+                .and_then(|cert| certificates(cert.as_bytes()).ok())
+                .unwrap_or_default(),
+            rekor: rekor_summary(&bundle),
+        });
+    }
+    if let Ok(policy) = serde_json::from_slice::<Policy>(raw) {
+        return Contents::Policy(policy_summary(&policy, now));
+    }
+    // cosign writes certificates as base64 encoded PEM.
+    if let Ok(certificates) = certificates(text.trim().as_bytes()) {
+        return Contents::Certificates { certificates };
+    }
+    if raw.len() <= 1024 {
+        if let Some(signature) = signature_summary(&text) {
+            return Contents::Signature(signature);
+        }
+    }
+    Contents::Artifact
+}
+
+/// Print `value` as indented `key: value` lines.
+fn print_human(value: &Value, indent: usize) {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                match value {
+                    Value::Object(_) | Value::Array(_) => {
+                        println!("{}{}:", pad, key);
+                        print_human(value, indent + 1);
+                    }
+                    value => println!("{}{}: {}", pad, key, scalar(value)),
+                }
+            }
+        }
+        Value::Array(items) if items.is_empty() => println!("{}(none)", pad),
+        Value::Array(items) => {
+            for item in items {
+                match item {
+                    Value::Object(_) | Value::Array(_) => {
+                        println!("{}-", pad);
+                        print_human(item, indent + 1);
+                    }
+                    item => println!("{}- {}", pad, scalar(item)),
+                }
+            }
+        }
+        value => println!("{}{}", pad, scalar(value)),
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => "-".to_string(),
+        value => value.to_string(),
+    }
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("inspect")
+        .about("Describe a policy, certificate, public key, signature, bundle or artifact without verifying it")
+        .arg(
+            Arg::new("input")
+                .value_name("INPUT")
+                .required(true)
+                .about("URL or path of what to inspect"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .takes_value(false)
+                .about("Print JSON instead of text"),
+        )
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    let input = matches
+        .value_of("input")
+        .ok_or_else(|| anyhow!("No input given"))?;
+    let transport = transport::default_transport();
+    let raw = fetch(transport.as_ref(), input, Path::new("")).await?;
+    let inspection = inspect(&raw, Utc::now());
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&inspection)?);
+    } else {
+        let mut value = serde_json::to_value(&inspection)?;
+        if let Some(kind) = value
+            .as_object_mut()
+            .and_then(|fields| fields.remove("kind"))
+        {
+            println!("kind: {}", scalar(&kind));
+        }
+        print_human(&value, 0);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inspect_inputs() {
+        let raw = std::fs::read("tests/test_data/policy_good.json").expect("No policy");
+        let inspection = inspect(&raw, Utc::now());
+        assert_eq!(inspection.length, raw.len() as u64);
+        let policy = match inspection.contents {
+            Contents::Policy(policy) => Some(policy),
+            _ => None,
+        }
+        .expect("Not a policy");
+        assert!(policy.roles.contains_key("root"));
+        assert!(policy
+            .keys
+            .values()
+            .any(|key| key.identity.as_deref() == Some("jyotsnap@bu.edu")));
+        let certificate = policy.signatures[0]
+            .certificate
+            .as_ref()
+            .expect("No certificate");
+        assert!(certificate.identity.is_some());
+        assert!(certificate
+            .extensions
+            .iter()
+            .any(|ext| ext.name == Some("Fulcio OIDC Issuer")));
+
+        let raw = std::fs::read("tests/test_data/algorithms/leaf.pem").expect("No certificate");
+        assert!(matches!(
+            inspect(&raw, Utc::now()).contents,
+            Contents::Certificates { .. }
+        ));
+        let inspection = inspect(b"#!/bin/sh\necho hello\n", Utc::now());
+        assert!(matches!(inspection.contents, Contents::Artifact));
+        assert_eq!(
+            inspection.digests["sha256"],
+            hex::encode(Sha256::digest(b"#!/bin/sh\necho hello\n"))
+        );
+    }
+}
@@ -0,0 +1,526 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::error::SgetError;
+use crate::keys::PublicKey;
+use crate::pipeline::{Pipeline, Stage, StageContext};
+use crate::policy::Key;
+use crate::signature::SignatureVerifiers;
+
+/// Where a manifest entry's in-toto layout, layout keys and links are.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct LayoutRequirements {
+    /// Location of the signed layout.
+    pub layout: String,
+    /// Locations of the PEM public keys that must all have signed the layout.
+    pub layout_keys: Vec<String>,
+    /// Location of the directory holding the link metadata, named
+    /// `<step>.<first 8 characters of the key ID>.link`. Defaults to the
+    /// layout's directory.
+    pub links: Option<String>,
+}
+
+/// A layout and the keys and links it is verified with, as fetched.
+pub struct LayoutMaterial {
+    pub layout: Vec<u8>,
+    /// PEM encoded layout keys.
+    pub keys: Vec<Vec<u8>>,
+    /// Link metadata by file name. Links that could not be fetched are absent.
+    pub links: BTreeMap<String, Vec<u8>>,
+}
+
+#[derive(Deserialize)]
+struct Metablock {
+    signed: Value,
+    signatures: Vec<MetablockSignature>,
+}
+
+#[derive(Deserialize)]
+struct MetablockSignature {
+    keyid: String,
+    /// The hex encoded signature over the canonical JSON of `signed`.
+    sig: String,
+}
+
+#[derive(Deserialize)]
+struct Layout {
+    #[serde(rename = "_type")]
+    kind: String,
+    expires: DateTime<Utc>,
+    #[serde(default)]
+    keys: HashMap<String, Key>,
+    steps: Vec<Step>,
+    #[serde(default)]
+    inspect: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct Step {
+    name: String,
+    #[serde(default)]
+    expected_materials: Vec<Vec<String>>,
+    #[serde(default)]
+    expected_products: Vec<Vec<String>>,
+    pubkeys: Vec<String>,
+    #[serde(default = "one")]
+    threshold: usize,
+}
+
+fn one() -> usize {
+    1
+}
+
+/// Artifact paths and their digests by algorithm.
+type Artifacts = BTreeMap<String, HashMap<String, String>>;
+
+#[derive(Deserialize, PartialEq)]
+struct Link {
+    #[serde(rename = "_type")]
+    kind: String,
+    name: String,
+    #[serde(default)]
+    materials: Artifacts,
+    #[serde(default)]
+    products: Artifacts,
+}
+
+impl Metablock {
+    fn parse(raw: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(raw)?)
+    }
+
+    /// The bytes the signatures are made over.
+    fn canonical_signed(&self) -> Result<Vec<u8>> {
+        // serde_json maps sort their keys and serialize without whitespace.
+        Ok(serde_json::to_vec(&self.signed)?)
+    }
+}
+
+/// `fnmatch` style matching of `*` and `?`, where `*` also matches `/`.
+pub(crate) fn glob_matches(pattern: &[u8], path: &[u8]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            glob_matches(rest, path) || (!path.is_empty() && glob_matches(pattern, &path[1..]))
+        }
+        (Some((b'?', rest)), Some((_, path))) => glob_matches(rest, path),
+        (Some((p, rest)), Some((c, path))) => p == c && glob_matches(rest, path),
+        _ => false,
+    }
+}
+
+fn in_prefix(prefix: Option<&str>, path: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{}/{}", prefix.trim_end_matches('/'), path),
+        None => path.to_string(),
+    }
+}
+
+/// The file names of the links for each step and authorized key of the
+/// layout, without verifying it.
+pub fn link_names(raw_layout: &[u8]) -> Result<Vec<String>> {
+    let layout: Layout = serde_json::from_value(Metablock::parse(raw_layout)?.signed)?;
+    Ok(layout
+        .steps
+        .iter()
+        .flat_map(|step| {
+            step.pubkeys
+                .iter()
+                .map(move |keyid| format!("{}.{:.8}.link", step.name, keyid))
+        })
+        .collect())
+}
+
+/// The links of each step, as verified against the layout.
+struct StepLinks<'a> {
+    layout: &'a Layout,
+    links: HashMap<&'a str, Link>,
+}
+
+impl StepLinks<'_> {
+    /// Apply an artifact rule of `step` to the artifacts in `queue`, removing
+    /// the ones it consumes.
+    fn apply(
+        &self,
+        step: &str,
+        link: &Link,
+        products: bool,
+        rule: &[String],
+        queue: &mut BTreeSet<String>,
+    ) -> Result<()> {
+        let artifacts = if products {
+            &link.products
+        } else {
+            &link.materials
+        };
+        let words: Vec<&str> = rule.iter().map(String::as_str).collect();
+        let matching = |queue: &BTreeSet<String>, pattern: &str| -> Vec<String> {
+            queue
+                .iter()
+                .filter(|path| glob_matches(pattern.as_bytes(), path.as_bytes()))
+                .cloned()
+                .collect()
+        };
+        let consumed: Vec<String> = match words.as_slice() {
+            [rule, words @ ..] if rule.eq_ignore_ascii_case("MATCH") => {
+                self.apply_match(words, queue, artifacts)?
+            }
+            [rule, pattern] => match rule.to_ascii_uppercase().as_str() {
+                "ALLOW" => matching(queue, pattern),
+                "DISALLOW" => match matching(queue, pattern).first() {
+                    Some(path) => return Err(anyhow!("Step {} disallows artifact {}", step, path)),
+                    None => Vec::new(),
+                },
+                "REQUIRE" if artifacts.contains_key(*pattern) => Vec::new(),
+                "REQUIRE" => return Err(anyhow!("Step {} requires artifact {}", step, pattern)),
+                "CREATE" => matching(queue, pattern)
+                    .into_iter()
+                    .filter(|path| !link.materials.contains_key(path))
+                    .collect(),
+                "DELETE" => matching(queue, pattern)
+                    .into_iter()
+                    .filter(|path| !link.products.contains_key(path))
+                    .collect(),
+                "MODIFY" => matching(queue, pattern)
+                    .into_iter()
+                    .filter(|path| {
+                        matches!(
+                            (link.materials.get(path), link.products.get(path)),
+                            (Some(before), Some(after)) if before != after
+                        )
+                    })
+                    .collect(),
+                _ => return Err(anyhow!("Invalid artifact rule {:?}", rule)),
+            },
+            _ => return Err(anyhow!("Invalid artifact rule {:?}", rule)),
+        };
+        for path in consumed {
+            queue.remove(&path);
+        }
+        Ok(())
+    }
+
+    /// `MATCH <pattern> [IN <prefix>] WITH (MATERIALS|PRODUCTS) [IN <prefix>]
+    /// FROM <step>`: consume the artifacts with the same digests as the
+    /// corresponding artifacts of another step.
+    fn apply_match(
+        &self,
+        words: &[&str],
+        queue: &BTreeSet<String>,
+        artifacts: &Artifacts,
+    ) -> Result<Vec<String>> {
+        let invalid = || anyhow!("Invalid MATCH rule {:?}", words);
+        let (pattern, words) = words.split_first().ok_or_else(invalid)?;
+        let (source_prefix, words) = match words {
+            ["IN", prefix, rest @ ..] => (Some(*prefix), rest),
+            rest => (None, rest),
+        };
+        let (other_products, words) = match words {
+            ["WITH", kind, rest @ ..] if kind.eq_ignore_ascii_case("MATERIALS") => (false, rest),
+            ["WITH", kind, rest @ ..] if kind.eq_ignore_ascii_case("PRODUCTS") => (true, rest),
+            _ => return Err(invalid()),
+        };
+        let (destination_prefix, from) = match words {
+            ["IN", prefix, "FROM", step] => (Some(*prefix), *step),
+            ["FROM", step] => (None, *step),
+            _ => return Err(invalid()),
+        };
+        let other = match self.links.get(from) {
+            Some(link) if other_products => &link.products,
+            Some(link) => &link.materials,
+            None => return Ok(Vec::new()),
+        };
+        let source_prefix =
+            source_prefix.map(|prefix| format!("{}/", prefix.trim_end_matches('/')));
+        Ok(queue
+            .iter()
+            .filter(|path| {
+                let relative = match &source_prefix {
+                    Some(prefix) => match path.strip_prefix(prefix.as_str()) {
+                        Some(relative) => relative,
+                        None => return false,
+                    },
+                    None => path.as_str(),
+                };
+                glob_matches(pattern.as_bytes(), relative.as_bytes())
+                    && other.get(&in_prefix(destination_prefix, relative)) == artifacts.get(*path)
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn check_rules(&self) -> Result<()> {
+        for step in &self.layout.steps {
+            // Only a step with a threshold of zero can have no link.
+            let link = match self.links.get(step.name.as_str()) {
+                Some(link) => link,
+                None => continue,
+            };
+            for (products, rules) in [
+                (false, &step.expected_materials),
+                (true, &step.expected_products),
+            ] {
+                let artifacts = if products {
+                    &link.products
+                } else {
+                    &link.materials
+                };
+                let mut queue: BTreeSet<String> = artifacts.keys().cloned().collect();
+                for rule in rules {
+                    self.apply(&step.name, link, products, rule, &mut queue)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verify a layout and its links: that every layout key signed the layout,
+/// that it has not expired, that each step has links signed by at least its
+/// threshold of authorized keys, that the links satisfy the artifact rules,
+/// and that some step produced the artifact with SHA-256 `digest`.
+fn check_layout(
+    material: &LayoutMaterial,
+    digest: &str,
+    verifiers: &SignatureVerifiers,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let metablock = Metablock::parse(&material.layout)?;
+    let signed = metablock.canonical_signed()?;
+    if material.keys.is_empty() {
+        return Err(anyhow!("No layout keys"));
+    }
+    for pem in &material.keys {
+        let key = PublicKey::from_pem(&String::from_utf8_lossy(pem))?;
+        let signed_by_key = metablock.signatures.iter().any(|signature| {
+            hex::decode(&signature.sig).is_ok_and(|sig| key.verify(&signed, &sig).is_ok())
+        });
+        if !signed_by_key {
+            return Err(anyhow!("Layout is not signed by {}", key.key_id()?));
+        }
+    }
+    let layout: Layout = serde_json::from_value(metablock.signed)?;
+    if layout.kind != "layout" {
+        return Err(anyhow!("Not a layout: {}", layout.kind));
+    }
+    if layout.expires <= now {
+        return Err(anyhow!("Layout expired at {}", layout.expires));
+    }
+    if !layout.inspect.is_empty() {
+        return Err(anyhow!("Layout inspections are not supported"));
+    }
+
+    let mut links = HashMap::new();
+    for step in &layout.steps {
+        let mut verified: Option<Link> = None;
+        let mut signers = BTreeSet::new();
+        for raw in material.links.values() {
+            let metablock = Metablock::parse(raw)?;
+            let link: Link = serde_json::from_value(metablock.signed.clone())?;
+            if link.kind != "link" || link.name != step.name {
+                continue;
+            }
+            let signed = metablock.canonical_signed()?;
+            let signer = metablock.signatures.iter().find(|signature| {
+                match layout.keys.get(&signature.keyid) {
+                    Some(key) if step.pubkeys.contains(&signature.keyid) => {
+                        hex::decode(&signature.sig)
+                            .is_ok_and(|sig| verifiers.verify(key, &signed, &sig).is_ok())
+                    }
+                    _ => false,
+                }
+            });
+            if let Some(signer) = signer {
+                if !signers.insert(signer.keyid.clone()) {
+                    continue;
+                }
+                match &verified {
+                    Some(first) if *first != link => {
+                        return Err(anyhow!("Links for step {} disagree", step.name))
+                    }
+                    Some(_) => {}
+                    None => verified = Some(link),
+                }
+            }
+        }
+        if signers.len() < step.threshold {
+            return Err(anyhow!(
+                "Step {} has links by {} of {} required keys",
+                step.name,
+                signers.len(),
+                step.threshold
+            ));
+        }
+        if let Some(link) = verified {
+            links.insert(step.name.as_str(), link);
+        }
+    }
+
+    let step_links = StepLinks {
+        layout: &layout,
+        links,
+    };
+    step_links.check_rules()?;
+    let produced = step_links.links.values().any(|link| {
+        link.products.values().any(
+            |digests| matches!(digests.get("sha256"), Some(d) if d.eq_ignore_ascii_case(digest)),
+        )
+    });
+    if !produced {
+        return Err(anyhow!("No step of the layout produced the artifact"));
+    }
+    Ok(())
+}
+
+/// Verify the in-toto layout and links of an artifact with SHA-256 `digest`,
+/// as the layout check stage of `pipeline`. Link signatures by keys of types
+/// without built-in support are checked by the verifier registered in
+/// `verifiers`. Layouts with inspections are rejected, as sget does not run
+/// them.
+pub fn verify_layout(
+    material: &LayoutMaterial,
+    digest: &str,
+    verifiers: &SignatureVerifiers,
+    pipeline: &Pipeline,
+    now: DateTime<Utc>,
+) -> crate::error::Result<()> {
+    let context = StageContext {
+        stage: Stage::LayoutCheck,
+        digest,
+        identity: None,
+    };
+    pipeline.run(context, || {
+        check_layout(material, digest, verifiers, now).map_err(|e| SgetError::Layout(e.to_string()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    fn metablock(key: &SigningKey, keyid: &str, signed: Value) -> Vec<u8> {
+        let sig = key.sign(&serde_json::to_vec(&signed).expect("Cannot encode"));
+        json!({
+            "signed": signed,
+            "signatures": [{ "keyid": keyid, "sig": hex::encode(sig) }],
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn link(
+        key: &SigningKey,
+        keyid: &str,
+        name: &str,
+        materials: Value,
+        products: Value,
+    ) -> Vec<u8> {
+        metablock(
+            key,
+            keyid,
+            json!({
+                "_type": "link",
+                "name": name,
+                "materials": materials,
+                "products": products,
+                "command": [],
+                "byproducts": {},
+                "environment": {},
+            }),
+        )
+    }
+
+    #[test]
+    fn verify_build_and_test_steps() {
+        let owner = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let functionary =
+            SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let keyid = functionary.public_key().key_id().expect("No key id");
+        let source = json!({ "sha256": hex::encode(Sha256::digest(b"src")) });
+        let script = hex::encode(Sha256::digest(b"echo built"));
+        let layout = metablock(
+            &owner,
+            "owner",
+            json!({
+                "_type": "layout",
+                "expires": "2100-01-01T00:00:00Z",
+                "keys": {
+                    keyid.clone(): functionary.public_key().to_policy_key().expect("Cannot encode key"),
+                },
+                "steps": [
+                    {
+                        "name": "build",
+                        "expected_materials": [["ALLOW", "src/*"], ["DISALLOW", "*"]],
+                        "expected_products": [["CREATE", "install.sh"], ["DISALLOW", "*"]],
+                        "pubkeys": [keyid.clone()],
+                        "threshold": 1,
+                    },
+                    {
+                        "name": "test",
+                        "expected_materials": [
+                            ["MATCH", "install.sh", "WITH", "PRODUCTS", "FROM", "build"],
+                            ["DISALLOW", "*"],
+                        ],
+                        "expected_products": [],
+                        "pubkeys": [keyid.clone()],
+                    },
+                ],
+                "inspect": [],
+            }),
+        );
+        let build = |script: &str| {
+            link(
+                &functionary,
+                &keyid,
+                "build",
+                json!({ "src/main.sh": source }),
+                json!({ "install.sh": { "sha256": script } }),
+            )
+        };
+        let test = link(
+            &functionary,
+            &keyid,
+            "test",
+            json!({ "install.sh": { "sha256": script } }),
+            json!({}),
+        );
+        let names = link_names(&layout).expect("Invalid layout");
+        assert_eq!(names[0], format!("build.{}.link", &keyid[..8]));
+        let mut material = LayoutMaterial {
+            layout,
+            keys: vec![owner
+                .public_key()
+                .to_pem()
+                .expect("Cannot encode key")
+                .into()],
+            links: vec![(names[0].clone(), build(&script)), (names[1].clone(), test)]
+                .into_iter()
+                .collect(),
+        };
+        let verify = |material: &LayoutMaterial| {
+            verify_layout(
+                material,
+                &script,
+                &SignatureVerifiers::default(),
+                &Pipeline::default(),
+                Utc::now(),
+            )
+        };
+        verify(&material).expect("Cannot verify layout");
+
+        // The tested script is not the one that was built.
+        let other = hex::encode(Sha256::digest(b"echo other"));
+        material.links.insert(names[0].clone(), build(&other));
+        assert!(matches!(verify(&material), Err(SgetError::Layout(_))));
+
+        // A missing step is below its threshold.
+        material.links.remove(&names[0]);
+        assert!(verify(&material).is_err());
+    }
+}
@@ -0,0 +1,479 @@
+use anyhow::{anyhow, Context, Result};
+use clap::{App, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use zeroize::Zeroizing;
+
+/// The service sget's secrets are filed under in the platform keychain.
+pub const SERVICE: &str = "sget";
+
+/// Prefix of values naming a keychain secret instead of holding the value,
+/// e.g. `--key keychain:release` or, in the configuration, a proxy password
+/// of `keychain:proxy`.
+pub const REFERENCE_PREFIX: &str = "keychain:";
+
+/// Somewhere to keep secrets: private keys, registry credentials and the
+/// passwords and tokens the configuration refers to. [`Keychain`] is the
+/// platform's; tests use their own.
+pub trait SecretStore: Send + Sync {
+    /// The secret stored for `account`, if any.
+    fn get(&self, account: &str) -> Result<Option<Zeroizing<String>>>;
+    /// Store `secret` for `account`, replacing any secret stored before.
+    fn set(&self, account: &str, secret: &str) -> Result<()>;
+    /// Remove the secret for `account`, if any.
+    fn delete(&self, account: &str) -> Result<()>;
+}
+
+/// The platform keychain: the login keychain on macOS, the Credential Manager
+/// on Windows, and the Secret Service, through `secret-tool`, elsewhere.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Keychain;
+
+impl SecretStore for Keychain {
+    fn get(&self, account: &str) -> Result<Option<Zeroizing<String>>> {
+        platform::get(SERVICE, account)
+            .with_context(|| format!("Cannot read {} from the keychain", account))
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<()> {
+        platform::set(SERVICE, account, secret)
+            .with_context(|| format!("Cannot store {} in the keychain", account))
+    }
+
+    fn delete(&self, account: &str) -> Result<()> {
+        platform::delete(SERVICE, account)
+            .with_context(|| format!("Cannot remove {} from the keychain", account))
+    }
+}
+
+/// The keychain secret `value` names, or `value` itself if it names none.
+pub fn resolve(store: &dyn SecretStore, value: &str) -> Result<Zeroizing<String>> {
+    match value.strip_prefix(REFERENCE_PREFIX) {
+        Some(account) => store
+            .get(account)?
+            .ok_or_else(|| anyhow!("No secret {} in the keychain", account)),
+        None => Ok(Zeroizing::new(value.to_string())),
+    }
+}
+
+/// The contents of the file at `location`, or of the keychain secret it
+/// names.
+pub fn read(store: &dyn SecretStore, location: &str) -> Result<Zeroizing<String>> {
+    if location.starts_with(REFERENCE_PREFIX) {
+        return resolve(store, location);
+    }
+    fs::read_to_string(location)
+        .map(Zeroizing::new)
+        .with_context(|| format!("Cannot read {}", location))
+}
+
+/// A username and password for an OCI registry, from `sget keychain login`.
+#[derive(Serialize, Deserialize)]
+pub struct RegistryCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl RegistryCredentials {
+    fn account(registry: &str) -> String {
+        format!("registry:{}", registry)
+    }
+
+    /// The credentials stored for `registry`, e.g. `ghcr.io`, if any.
+    pub fn load(store: &dyn SecretStore, registry: &str) -> Result<Option<Self>> {
+        match store.get(&Self::account(registry))? {
+            Some(raw) => Ok(Some(serde_json::from_str(&raw).with_context(|| {
+                format!("Invalid credentials for {} in the keychain", registry)
+            })?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save(&self, store: &dyn SecretStore, registry: &str) -> Result<()> {
+        let raw = Zeroizing::new(serde_json::to_string(self)?);
+        store.set(&Self::account(registry), &raw)
+    }
+
+    pub fn remove(store: &dyn SecretStore, registry: &str) -> Result<()> {
+        store.delete(&Self::account(registry))
+    }
+
+    /// The value of a Basic `Authorization` header.
+    pub fn basic_auth(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!(
+            "Basic {}",
+            base64::encode(format!("{}:{}", self.username, self.password))
+        ))
+    }
+}
+
+impl Drop for RegistryCredentials {
+    fn drop(&mut self) {
+        use zeroize::Zeroize;
+        self.password.zeroize();
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use anyhow::Result;
+    use security_framework::os::macos::keychain::SecKeychain;
+    use security_framework::os::macos::passwords::find_generic_password;
+    use zeroize::Zeroizing;
+
+    /// `errSecItemNotFound`.
+    const ITEM_NOT_FOUND: i32 = -25300;
+
+    pub fn get(service: &str, account: &str) -> Result<Option<Zeroizing<String>>> {
+        match find_generic_password(None, service, account) {
+            Ok((password, _)) => Ok(Some(Zeroizing::new(
+                String::from_utf8(password.to_vec())
+                    .map_err(|_| anyhow::anyhow!("The secret is not valid UTF-8"))?,
+            ))),
+            Err(e) if e.code() == ITEM_NOT_FOUND => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn set(service: &str, account: &str, secret: &str) -> Result<()> {
+        Ok(SecKeychain::default()?.set_generic_password(service, account, secret.as_bytes())?)
+    }
+
+    pub fn delete(service: &str, account: &str) -> Result<()> {
+        match find_generic_password(None, service, account) {
+            Ok((_, item)) => {
+                item.delete();
+                Ok(())
+            }
+            Err(e) if e.code() == ITEM_NOT_FOUND => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use anyhow::{anyhow, Result};
+    use std::io;
+    use std::ptr;
+    use winapi::shared::winerror::ERROR_NOT_FOUND;
+    use winapi::um::wincred::{
+        CredDeleteW, CredFree, CredReadW, CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE,
+        CRED_TYPE_GENERIC, PCREDENTIALW,
+    };
+    use zeroize::Zeroizing;
+
+    /// A NUL-terminated UTF-16 string.
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn target(service: &str, account: &str) -> Vec<u16> {
+        wide(&format!("{}:{}", service, account))
+    }
+
+    fn not_found(e: &io::Error) -> bool {
+        e.raw_os_error() == Some(ERROR_NOT_FOUND as i32)
+    }
+
+    pub fn get(service: &str, account: &str) -> Result<Option<Zeroizing<String>>> {
+        let target = target(service, account);
+        let mut credential: PCREDENTIALW = ptr::null_mut();
+        // SAFETY: `target` is NUL-terminated and outlives the call; on
+        // success `credential` points to a credential we free below.
+        let secret = unsafe {
+            if CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) == 0 {
+                let e = io::Error::last_os_error();
+                return if not_found(&e) {
+                    Ok(None)
+                } else {
+                    Err(e.into())
+                };
+            }
+            let blob = std::slice::from_raw_parts(
+                (*credential).CredentialBlob,
+                (*credential).CredentialBlobSize as usize,
+            );
+            let secret = Zeroizing::new(blob.to_vec());
+            CredFree(credential as *mut _);
+            secret
+        };
+        let secret =
+            std::str::from_utf8(&secret).map_err(|_| anyhow!("The secret is not valid UTF-8"))?;
+        Ok(Some(Zeroizing::new(secret.to_string())))
+    }
+
+    pub fn set(service: &str, account: &str, secret: &str) -> Result<()> {
+        let mut target = target(service, account);
+        let mut user = wide(account);
+        let mut blob = Zeroizing::new(secret.as_bytes().to_vec());
+        // SAFETY: an all-zero CREDENTIALW is valid, and the buffers it points
+        // to outlive the call.
+        unsafe {
+            let mut credential: CREDENTIALW = std::mem::zeroed();
+            credential.Type = CRED_TYPE_GENERIC;
+            credential.TargetName = target.as_mut_ptr();
+            credential.UserName = user.as_mut_ptr();
+            credential.CredentialBlobSize = blob.len() as u32;
+            credential.CredentialBlob = blob.as_mut_ptr();
+            credential.Persist = CRED_PERSIST_LOCAL_MACHINE;
+            if CredWriteW(&mut credential, 0) == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn delete(service: &str, account: &str) -> Result<()> {
+        let target = target(service, account);
+        // SAFETY: `target` is NUL-terminated and outlives the call.
+        if unsafe { CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) } == 0 {
+            let e = io::Error::last_os_error();
+            if !not_found(&e) {
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod platform {
+    use anyhow::{anyhow, Context, Result};
+    use std::io::Write;
+    use std::process::{Command, Output, Stdio};
+    use zeroize::Zeroizing;
+
+    /// Run `secret-tool` with `args`, writing `input` to its standard input.
+    fn secret_tool(args: &[&str], input: Option<&str>) -> Result<Output> {
+        let mut child = Command::new("secret-tool")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Cannot run secret-tool; install libsecret's tools for keychain support")?;
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin.write_all(input.as_bytes())?;
+        }
+        Ok(child.wait_with_output()?)
+    }
+
+    fn failure(output: &Output) -> anyhow::Error {
+        anyhow!(
+            "secret-tool failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+
+    pub fn get(service: &str, account: &str) -> Result<Option<Zeroizing<String>>> {
+        let output = secret_tool(&["lookup", "service", service, "account", account], None)?;
+        let stdout = Zeroizing::new(output.stdout.clone());
+        if !output.status.success() {
+            // A lookup that finds nothing fails without a message.
+            if output.stderr.is_empty() {
+                return Ok(None);
+            }
+            return Err(failure(&output));
+        }
+        let secret =
+            std::str::from_utf8(&stdout).map_err(|_| anyhow!("The secret is not valid UTF-8"))?;
+        Ok(Some(Zeroizing::new(secret.to_string())))
+    }
+
+    pub fn set(service: &str, account: &str, secret: &str) -> Result<()> {
+        let label = format!("--label={}: {}", service, account);
+        let args = ["store", &label, "service", service, "account", account];
+        let output = secret_tool(&args, Some(secret))?;
+        if !output.status.success() {
+            return Err(failure(&output));
+        }
+        Ok(())
+    }
+
+    pub fn delete(service: &str, account: &str) -> Result<()> {
+        let output = secret_tool(&["clear", "service", service, "account", account], None)?;
+        // Clearing nothing fails without a message too.
+        if !output.status.success() && !output.stderr.is_empty() {
+            return Err(failure(&output));
+        }
+        Ok(())
+    }
+}
+
+/// A secret from `file`, standard input for `-`, or typed at a prompt.
+fn read_secret(file: Option<&str>, prompt: &str) -> Result<Zeroizing<String>> {
+    let secret = match file {
+        Some("-") => {
+            let mut secret = Zeroizing::new(String::new());
+            std::io::stdin().read_to_string(&mut secret)?;
+            secret
+        }
+        Some(file) => Zeroizing::new(
+            fs::read_to_string(file).with_context(|| format!("Cannot read {}", file))?,
+        ),
+        None => Zeroizing::new(rpassword::read_password_from_tty(Some(prompt))?),
+    };
+    Ok(secret)
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("keychain")
+        .about("Keep private keys, registry credentials and configured secrets in the platform keychain instead of plaintext files")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("set")
+                .about("Store a secret, to be referred to as keychain:NAME, e.g. with --key or as a proxy password or header value in the configuration")
+                .arg(Arg::new("name").value_name("NAME").required(true))
+                .arg(
+                    Arg::new("from-file")
+                        .long("from-file")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .about("Read the secret from FILE, or standard input for -, instead of prompting; e.g. an existing private key"),
+                ),
+        )
+        .subcommand(
+            App::new("delete")
+                .about("Remove a secret")
+                .arg(Arg::new("name").value_name("NAME").required(true)),
+        )
+        .subcommand(
+            App::new("login")
+                .about("Store credentials for pulling from an OCI registry")
+                .arg(
+                    Arg::new("registry")
+                        .value_name("REGISTRY")
+                        .required(true)
+                        .about("The registry, e.g. ghcr.io"),
+                )
+                .arg(
+                    Arg::new("username")
+                        .short('u')
+                        .long("username")
+                        .value_name("USERNAME")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("password-stdin")
+                        .long("password-stdin")
+                        .takes_value(false)
+                        .about("Read the password or token from standard input instead of prompting"),
+                ),
+        )
+        .subcommand(
+            App::new("logout")
+                .about("Remove the credentials for an OCI registry")
+                .arg(Arg::new("registry").value_name("REGISTRY").required(true)),
+        )
+}
+
+pub(crate) fn run(matches: &ArgMatches) -> Result<()> {
+    let keychain = Keychain;
+    let arg = |m: &ArgMatches, name: &str| -> Result<String> {
+        m.value_of(name)
+            .map(String::from)
+            .ok_or_else(|| anyhow!("No {} given", name))
+    };
+    match matches.subcommand() {
+        Some(("set", m)) => {
+            let name = arg(m, "name")?;
+            let secret = read_secret(m.value_of("from-file"), "Secret: ")?;
+            keychain.set(&name, secret.trim_end_matches(['\r', '\n']))?;
+            println!("Stored; refer to it as {}{}", REFERENCE_PREFIX, name);
+        }
+        Some(("delete", m)) => {
+            let name = arg(m, "name")?;
+            keychain.delete(&name)?;
+            println!("Removed {}", name);
+        }
+        Some(("login", m)) => {
+            let registry = arg(m, "registry")?;
+            let file = m.is_present("password-stdin").then_some("-");
+            let password = read_secret(file, "Password: ")?;
+            let credentials = RegistryCredentials {
+                username: arg(m, "username")?,
+                password: password.trim_end_matches(['\r', '\n']).to_string(),
+            };
+            credentials.save(&keychain, &registry)?;
+            println!("Credentials for {} stored", registry);
+        }
+        Some(("logout", m)) => {
+            let registry = arg(m, "registry")?;
+            RegistryCredentials::remove(&keychain, &registry)?;
+            println!("Credentials for {} removed", registry);
+        }
+        _ => return Err(anyhow!("Unknown keychain subcommand")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Memory(Mutex<HashMap<String, String>>);
+
+    impl SecretStore for Memory {
+        fn get(&self, account: &str) -> Result<Option<Zeroizing<String>>> {
+            let secrets = self.0.lock().map_err(|_| anyhow!("Poisoned"))?;
+            Ok(secrets.get(account).cloned().map(Zeroizing::new))
+        }
+
+        fn set(&self, account: &str, secret: &str) -> Result<()> {
+            let mut secrets = self.0.lock().map_err(|_| anyhow!("Poisoned"))?;
+            secrets.insert(account.to_string(), secret.to_string());
+            Ok(())
+        }
+
+        fn delete(&self, account: &str) -> Result<()> {
+            let mut secrets = self.0.lock().map_err(|_| anyhow!("Poisoned"))?;
+            secrets.remove(account);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn resolve_secrets() {
+        let store = Memory::default();
+        store.set("proxy", "hunter2").expect("Cannot store");
+        assert_eq!(
+            *resolve(&store, "keychain:proxy").expect("No secret"),
+            "hunter2"
+        );
+        assert_eq!(*resolve(&store, "plain").expect("No value"), "plain");
+        assert!(resolve(&store, "keychain:missing").is_err());
+
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let path = dir.path().join("sget.key");
+        fs::write(&path, "PEM").expect("Cannot write key");
+        store.set("release", "KEYCHAIN PEM").expect("Cannot store");
+        assert_eq!(
+            *read(&store, &path.display().to_string()).expect("Cannot read"),
+            "PEM"
+        );
+        assert_eq!(
+            *read(&store, "keychain:release").expect("Cannot read"),
+            "KEYCHAIN PEM"
+        );
+
+        let credentials = RegistryCredentials {
+            username: "ci".to_string(),
+            password: "t0k3n".to_string(),
+        };
+        credentials.save(&store, "ghcr.io").expect("Cannot store");
+        let loaded = RegistryCredentials::load(&store, "ghcr.io")
+            .expect("Cannot load")
+            .expect("No credentials");
+        assert_eq!(loaded.username, "ci");
+        assert_eq!(*loaded.basic_auth(), "Basic Y2k6dDBrM24=");
+        RegistryCredentials::remove(&store, "ghcr.io").expect("Cannot remove");
+        assert!(RegistryCredentials::load(&store, "ghcr.io")
+            .expect("Cannot load")
+            .is_none());
+    }
+}
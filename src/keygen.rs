@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Result};
+use clap::{App, Arg, ArgMatches};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use crate::keychain::{Keychain, SecretStore, REFERENCE_PREFIX};
+use crate::keys::{self, KeyAlgorithm, PublicKey, SigningKey};
+
+pub(crate) fn command() -> App<'static> {
+    App::new("keygen")
+        .about("Generate a key pair for signing scripts and policies, the private key encrypted with a passphrase from SGET_KEY_PASSPHRASE or prompted for")
+        .arg(
+            Arg::new("algorithm")
+                .short('a')
+                .long("algorithm")
+                .value_name("ALGORITHM")
+                .possible_values(["ecdsa-p256", "ed25519"])
+                .default_value("ecdsa-p256")
+                .about("Key algorithm to generate"),
+        )
+        .arg(
+            Arg::new("output-key-prefix")
+                .short('o')
+                .long("output-key-prefix")
+                .value_name("PREFIX")
+                .default_value("sget")
+                .about("Write the key pair to PREFIX.key and PREFIX.pub"),
+        )
+        .arg(
+            Arg::new("name")
+                .long("name")
+                .value_name("NAME")
+                .about("Human-friendly name for the key in its policy entry, e.g. alice-yubikey"),
+        )
+        .arg(
+            Arg::new("keychain")
+                .long("keychain")
+                .value_name("NAME")
+                .takes_value(true)
+                .about("Keep the private key in the platform keychain as NAME, used as --key keychain:NAME, instead of writing PREFIX.key"),
+        )
+}
+
+pub(crate) fn run(matches: &ArgMatches) -> Result<()> {
+    let algorithm: KeyAlgorithm = matches
+        .value_of("algorithm")
+        .unwrap_or("ecdsa-p256")
+        .parse()?;
+    let prefix = matches.value_of("output-key-prefix").unwrap_or("sget");
+    let public = match matches.value_of("keychain") {
+        Some(name) => generate_keychain_key(algorithm, name, prefix)?,
+        None => generate_key_pair(algorithm, prefix)?,
+    };
+
+    // Print the entry to paste into the `keys` section of a policy.
+    let mut key = serde_json::to_value(public.to_policy_key()?)?;
+    if let Some(name) = matches.value_of("name") {
+        key["name"] = name.into();
+    }
+    let mut entry = HashMap::new();
+    entry.insert(public.key_id()?, key);
+    println!("{}", serde_json::to_string_pretty(&entry)?);
+    Ok(())
+}
+
+/// Generate a key pair, encrypting the private key with the passphrase in
+/// `SGET_KEY_PASSPHRASE` or prompted for, and write it to `PREFIX.key` and
+/// `PREFIX.pub`.
+pub(crate) fn generate_key_pair(algorithm: KeyAlgorithm, prefix: &str) -> Result<PublicKey> {
+    let private_path = format!("{}.key", prefix);
+    let public_path = format!("{}.pub", prefix);
+    for path in &[&private_path, &public_path] {
+        if Path::new(path).exists() {
+            return Err(anyhow!("Refusing to overwrite existing file {}", path));
+        }
+    }
+
+    let key = SigningKey::generate(algorithm)?;
+    let passphrase = keys::read_passphrase(true)?;
+    let public = key.public_key();
+
+    write_new_file(
+        &private_path,
+        key.to_encrypted_pem(&passphrase)?.as_bytes(),
+        0o600,
+    )?;
+    write_new_file(&public_path, public.to_pem()?.as_bytes(), 0o644)?;
+
+    let key_id = public.key_id()?;
+    println!("Private key written to {}", private_path);
+    println!("Public key written to {}", public_path);
+    println!("Key ID: {}", key_id);
+    Ok(public)
+}
+
+/// Generate a key pair, keep the private key in the platform keychain as
+/// `name`, and write the public key to `PREFIX.pub`. The keychain protects
+/// the private key, so it is not encrypted with a passphrase.
+pub(crate) fn generate_keychain_key(
+    algorithm: KeyAlgorithm,
+    name: &str,
+    prefix: &str,
+) -> Result<PublicKey> {
+    let public_path = format!("{}.pub", prefix);
+    if Path::new(&public_path).exists() {
+        return Err(anyhow!(
+            "Refusing to overwrite existing file {}",
+            public_path
+        ));
+    }
+    let keychain = Keychain;
+    if keychain.get(name)?.is_some() {
+        return Err(anyhow!(
+            "Refusing to overwrite {} in the keychain; remove it with `sget keychain delete {}` first",
+            name,
+            name
+        ));
+    }
+
+    let key = SigningKey::generate(algorithm)?;
+    let public = key.public_key();
+    keychain.set(name, &key.to_pem()?)?;
+    write_new_file(&public_path, public.to_pem()?.as_bytes(), 0o644)?;
+
+    println!(
+        "Private key stored in the keychain as {}{}",
+        REFERENCE_PREFIX, name
+    );
+    println!("Public key written to {}", public_path);
+    println!("Key ID: {}", public.key_id()?);
+    Ok(public)
+}
+
+fn write_new_file(path: &str, contents: &[u8], _mode: u32) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(_mode);
+    }
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
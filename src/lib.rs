@@ -0,0 +1,167 @@
+//
+// Copyright 2021 The Sigstore Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! sget fetches scripts and other artifacts, verifies their sigstore signatures
+//! and root policies, and only then hands them over or runs them.
+//!
+//! [`SgetClient`] covers the whole flow; [`Verifier`] checks signatures and
+//! policies on their own. Fetching is async on tokio, so many artifacts can be
+//! verified concurrently; [`blocking`] wraps it for synchronous callers.
+//! Fetching and verification fail with an [`SgetError`], whose variants say why
+//! an artifact was rejected.
+//!
+//! Policy, signature and digest verification live in [`verify_core`], which
+//! needs neither a filesystem nor a network. Building without the default
+//! `native` feature leaves only that core and the modules it uses, so it can be
+//! compiled to `wasm32-unknown-unknown` for browsers and plugin runtimes.
+//!
+//! ```no_run
+//! use sget::{ManifestEntry, SgetClient, Verifier};
+//!
+//! # async fn install() -> anyhow::Result<()> {
+//! let client = SgetClient::new(Verifier::from_trust_store()?);
+//! let mut entry = ManifestEntry::new("https://example.com/install.sh");
+//! entry.identity = Some("release@example.com".to_string());
+//! entry.issuer = Some("https://accounts.google.com".to_string());
+//! let script = client.fetch(&entry).await?;
+//! client.execute(&script, true)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod age;
+pub mod algorithms;
+#[cfg(feature = "native")]
+pub mod ambient;
+pub mod approval;
+#[cfg(feature = "native")]
+pub mod attest;
+#[cfg(feature = "native")]
+pub mod audit;
+#[cfg(feature = "native")]
+pub mod blocking;
+#[cfg(feature = "native")]
+mod ceremony;
+pub mod claims;
+#[cfg(feature = "native")]
+#[doc(hidden)]
+pub mod cli;
+#[cfg(feature = "native")]
+pub mod client;
+#[cfg(feature = "native")]
+pub mod config;
+#[cfg(feature = "native")]
+mod containers_storage;
+pub mod delta;
+mod der;
+#[cfg(feature = "native")]
+mod doctor;
+pub mod error;
+#[cfg(feature = "native")]
+pub mod evidence;
+#[cfg(feature = "native")]
+pub mod execution_log;
+pub mod expiry;
+pub mod explain;
+#[cfg(feature = "native")]
+pub mod fetch;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fips;
+#[cfg(feature = "native")]
+pub mod fulcio;
+#[cfg(feature = "native")]
+pub mod hooks;
+pub mod identities;
+#[cfg(feature = "native")]
+mod inspect;
+pub mod intoto;
+#[cfg(feature = "native")]
+pub mod keychain;
+#[cfg(feature = "native")]
+mod keygen;
+pub mod keys;
+#[cfg(feature = "native")]
+pub mod manifest;
+pub mod merkle;
+#[cfg(feature = "native")]
+mod metrics;
+#[cfg(feature = "native")]
+mod migrate;
+#[cfg(feature = "native")]
+mod mirror;
+pub mod notation;
+#[cfg(feature = "native")]
+pub mod observer;
+pub mod pgp;
+pub mod pipeline;
+#[cfg(feature = "native")]
+mod policies;
+pub mod policy;
+pub mod policy_set;
+#[cfg(feature = "native")]
+pub mod profile;
+#[cfg(feature = "native")]
+mod promote;
+pub mod provenance;
+pub mod rekor;
+pub mod revocation;
+pub mod roots;
+#[cfg(feature = "native")]
+mod runner;
+pub mod sbom;
+#[cfg(feature = "native")]
+mod scaffold;
+#[cfg(feature = "native")]
+pub mod script_bundle;
+#[cfg(feature = "native")]
+mod selfupdate;
+#[cfg(feature = "native")]
+mod serve;
+#[cfg(feature = "native")]
+pub mod sign;
+pub mod signature;
+pub mod sigstore_bundle;
+pub mod sigstore_env;
+pub mod ssh;
+#[cfg(feature = "native")]
+pub mod state;
+#[cfg(feature = "native")]
+pub mod storage;
+pub mod system_policy;
+pub mod targets;
+pub mod timestamp;
+#[cfg(feature = "native")]
+pub mod transport;
+#[cfg(feature = "native")]
+pub mod trust;
+pub mod tuf;
+#[cfg(feature = "native")]
+mod utils;
+#[cfg(feature = "native")]
+pub mod verify;
+pub mod verify_core;
+#[cfg(feature = "native")]
+mod version;
+#[cfg(feature = "native")]
+mod watch;
+
+#[cfg(feature = "native")]
+pub use client::{SgetClient, VerifiedArtifact, Verifier, VerifierBuilder};
+pub use error::{FailureReason, SgetError};
+#[cfg(feature = "native")]
+pub use manifest::{Manifest, ManifestEntry};
+pub use policy::Policy;
+pub use verify_core::{BlobSignature, Signer, TrustRoots};
@@ -13,105 +13,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-pub mod policy;
-mod utils;
-
-use clap::{App, Arg};
-use oci_distribution::{client, secrets::RegistryAuth, Client, Reference};
-use std::env;
-use std::fs::File;
-use std::io::Write;
-
-async fn pull(reference: Reference, file_name: &str) {
-    let config = client::ClientConfig {
-        protocol: client::ClientProtocol::Https,
-        accept_invalid_hostnames: false,
-        accept_invalid_certificates: false,
-        extra_root_certificates: Vec::new(),
-    };
-    let mut client = Client::new(config);
-    let auth: RegistryAuth = RegistryAuth::Anonymous;
-    let accepted_media_types = vec!["text/plain"];
-    let image = client
-        .pull(&reference, &auth, accepted_media_types)
-        .await
-        .unwrap() //#[allow_ci]
-        .layers
-        .into_iter()
-        .next()
-        .map(|layer| layer.data);
-    match image {
-        Some(image) => {
-            let cwd = env::current_dir().unwrap(); //#[allow_ci]
-            let file = File::create(cwd.join(file_name));
-            file.unwrap().write_all(&image[..]).ok(); //#[allow_ci]
-            println!("Success! Pulled the script!");
-        }
-        None => println!("Error!"),
-    }
-}
-
 // Example Usage: ./sget --noexec --outfile file.sh ghcr.io/jyotsna-penumaka/hello_sget:latest
 
 #[tokio::main]
 async fn main() {
-    let matches = App::new("sget")
-        .version("0.1")
-        .author("Sigstore Developers")
-        .about("Secure script retrieval and execution")
-        .license("Apache-2.0")
-        .arg(
-            Arg::new("oci-registry")
-                .about("OCI registry namespace")
-                .index(1),
-        )
-        .arg(
-            Arg::new("noexec")
-                .short('n')
-                .long("noexec")
-                .takes_value(false)
-                .requires("oci-registry")
-                .about("Do not execute script"),
-        )
-        .arg(
-            Arg::new("outfile")
-                .short('f')
-                .long("outfile")
-                .value_name("OUT_FILE")
-                .requires("oci-registry")
-                .about("Save script to file")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::new("interactive")
-                .short('i')
-                .long("interactive")
-                .takes_value(false)
-                .conflicts_with("noexec")
-                .about("Displays executing script's stdout to console"),
-        )
-        .get_matches();
-
-    if let Some(o) = matches.value_of("oci-registry") {
-        println!("OCI registry: {}", o);
-    }
-    if let Some(f) = matches.value_of("outfile") {
-        println!("Output file: {}", f);
-    }
-
-    // TO DO: need better error handling in place of unwrap
-    let reference: Reference = matches.value_of("oci-registry").unwrap().parse().unwrap(); //#[allow_ci]
-    let outfile = matches.value_of("outfile").unwrap(); //#[allow_ci]
-    pull(reference, outfile).await;
-    if !matches.is_present("noexec") {
-        // TODO: When we can retrieve the blob, remove the below two lines
-        // as these are temporary until we rig in the download / verify
-        // functions
-        let mut dir = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        dir.push("tests/test.sh");
-
-        utils::run_script(&dir.to_string_lossy(), matches.is_present("interactive"))
-            .expect("\n sget script execution failed");
-        println!("\nsget script execution succeeded");
-    }
+    sget::cli::main().await
 }
@@ -0,0 +1,935 @@
+use anyhow::Context;
+use chrono::Utc;
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+use crate::age::{self, AgeRequirements};
+use crate::algorithms::ArtifactDigest;
+use crate::claims::{self, ClaimRequirements, ClaimsInput};
+use crate::delta::{self, ArtifactCache};
+use crate::error::{Result, SgetError};
+use crate::fetch::{
+    fetch, fetch_digest_with, fetch_expected, is_remote, pull_oci_attestations,
+    ContentExpectations, OCI_LAYOUT_PREFIX, STDIN,
+};
+use crate::intoto::{self, LayoutMaterial, LayoutRequirements};
+use crate::keys::PublicKey;
+use crate::pgp::{self, Keyring, PgpRequirements};
+use crate::pipeline::{Pipeline, Stage, StageContext};
+use crate::policy::SigstoreOidcKey;
+use crate::provenance::{self, ProvenanceRequirements};
+use crate::rekor;
+use crate::revocation::{self, RevocationData, RevocationPolicy};
+use crate::sbom::{self, SbomRequirements};
+use crate::signature::SignatureVerifiers;
+use crate::ssh::{self, AllowedSigners, SshRequirements};
+use crate::transport::Transport;
+use crate::verify::{self, BlobSignature, Signer, TrustRoots};
+
+/// Prefix of attestation locations in an OCI repository. Attestations in an
+/// OCI image layout are given as `oci-layout:<dir>`.
+const OCI_PREFIX: &str = "oci://";
+
+/// How many manifest entries are fetched and verified at once by default.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// The environment variable naming the age identity file of encrypted
+/// entries that do not name one.
+pub const AGE_IDENTITY_ENV: &str = "SGET_AGE_IDENTITY";
+
+/// A list of artifacts to verify in one go.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub artifacts: Vec<ManifestEntry>,
+}
+
+/// An artifact and the signature material and constraints it is verified with.
+///
+/// Locations are http(s) URLs or paths relative to the manifest. Signature
+/// material defaults to the `sget sign` layout next to the artifact:
+/// `<url>.sig`, plus `<url>.pem` and `<url>.bundle` for keyless signatures.
+/// OpenPGP signatures default to `<url>.asc`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub url: String,
+    /// Expected hex encoded SHA-256 digest of the artifact.
+    pub sha256: Option<String>,
+    /// Expected digest of the artifact as `<algorithm>:<hex>`, for hash
+    /// functions other than SHA-256, e.g. `sha512:<hex>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Declared length of the artifact in bytes, checked against the
+    /// response before the artifact is hashed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+    /// Acceptable media types of the artifact's response, e.g.
+    /// `text/x-shellscript` or `text/*` [default: any but HTML].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content_types: Vec<String>,
+    /// Required signer identity for keyless signatures.
+    pub identity: Option<String>,
+    /// Required OIDC issuer of the signer identity.
+    pub issuer: Option<String>,
+    /// Public key for signatures made with a key from `sget keygen`.
+    pub key: Option<String>,
+    pub signature: Option<String>,
+    pub certificate: Option<String>,
+    pub bundle: Option<String>,
+    /// Verify an OpenPGP detached signature with a keyring instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pgp: Option<PgpRequirements>,
+    /// Verify an SSH signature made with `ssh-keygen -Y sign` instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh: Option<SshRequirements>,
+    /// Require SLSA provenance for the artifact.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ProvenanceRequirements>,
+    /// Check that the certificate of a keyless signature has not been revoked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation: Option<RevocationPolicy>,
+    /// Require the artifact's supply chain to satisfy an in-toto layout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intoto: Option<LayoutRequirements>,
+    /// Require a signed SBOM for the artifact.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sbom: Option<SbomRequirements>,
+    /// Where to fetch a binary delta from the cached previous version of the
+    /// artifact, with `{base}` standing for that version's SHA-256 digest,
+    /// e.g. `https://example.com/bundle.tar.delta/{base}`. Verified versions
+    /// are cached to apply the next delta to; without a cached version or a
+    /// delta for it, the whole artifact is fetched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<String>,
+    /// Require the signature to satisfy a Rego or CUE claim policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claims: Option<ClaimRequirements>,
+    /// The artifact is encrypted with age at rest, and decrypted with a local
+    /// identity before it is written or run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub age: Option<AgeRequirements>,
+    /// Opaque metadata for downstream automation, such as the owning team,
+    /// rollout channel or minimum sget version. sget does not interpret it,
+    /// but reports it with the entry's verification result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<serde_json::Value>,
+}
+
+impl Manifest {
+    /// Load a YAML (or JSON) manifest.
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        Ok(serde_yaml::from_slice(&raw)
+            .with_context(|| format!("Invalid manifest {}", path.display()))?)
+    }
+}
+
+/// An artifact and its signature material, as fetched.
+pub struct Material {
+    pub data: Vec<u8>,
+    /// The base64 encoded signature, or the OpenPGP or SSH signature for
+    /// entries verified with a keyring or allowed signers.
+    pub signature: Vec<u8>,
+    /// PEM encoded public key, for key-based entries.
+    pub key: Option<Vec<u8>>,
+    /// PEM encoded certificate chain, for keyless entries. May itself be base64
+    /// encoded, as cosign writes it.
+    pub certificate: Option<Vec<u8>>,
+    /// JSON encoded Rekor bundle, cosign bundle or sigstore bundle.
+    pub bundle: Option<Vec<u8>>,
+    /// OpenPGP public keys, for entries verified with a keyring.
+    pub keyring: Option<Vec<u8>>,
+    /// The `allowed_signers` file, for entries verified with SSH keys.
+    pub allowed_signers: Option<Vec<u8>>,
+    /// CRLs and OCSP responses, for entries checking certificate revocation.
+    pub revocation: Option<RevocationData>,
+    /// JSON Lines of DSSE envelopes, for entries requiring provenance.
+    pub provenance: Option<Vec<u8>>,
+    /// The in-toto layout, its keys and links, for entries requiring one.
+    pub layout: Option<LayoutMaterial>,
+    /// JSON Lines of DSSE envelopes, for entries requiring an SBOM.
+    pub sbom: Option<Vec<u8>>,
+    /// The claim policy, for entries requiring one.
+    pub claim_policy: Option<Vec<u8>>,
+}
+
+impl Material {
+    /// Hex encoded SHA-256 digest of the artifact.
+    pub fn digest(&self) -> String {
+        hex::encode(Sha256::digest(&self.data))
+    }
+
+    pub(crate) fn parse(&self) -> anyhow::Result<BlobSignature> {
+        let utf8 = |bytes: &[u8]| String::from_utf8(bytes.to_vec());
+        Ok(BlobSignature {
+            signature: base64::decode(utf8(&self.signature)?.trim())?,
+            certificate: self
+                .certificate
+                .as_deref()
+                .map(certificate_chain)
+                .transpose()?,
+            public_key: match &self.key {
+                Some(pem) => Some(PublicKey::from_pem(&utf8(pem)?)?),
+                None => None,
+            },
+            bundle: self
+                .bundle
+                .as_deref()
+                .map(rekor::parse_bundle)
+                .transpose()?,
+        })
+    }
+}
+
+/// A PEM encoded certificate chain, which may itself be base64 encoded.
+fn certificate_chain(bytes: &[u8]) -> anyhow::Result<String> {
+    let pem = String::from_utf8(bytes.to_vec())?;
+    if pem.trim_start().starts_with("-----BEGIN") {
+        Ok(pem)
+    } else {
+        Ok(String::from_utf8(base64::decode(pem.trim())?)?)
+    }
+}
+
+/// The outcome of verifying one manifest entry.
+pub struct EntryResult {
+    pub url: String,
+    /// The entry's custom metadata.
+    pub custom: Option<serde_json::Value>,
+    /// What was fetched for the entry, unless fetching failed.
+    pub material: Option<Material>,
+    pub outcome: Result<Signer>,
+}
+
+impl EntryResult {
+    /// Hex encoded SHA-256 digest of the artifact, if it was fetched.
+    pub fn digest(&self) -> Option<String> {
+        self.material.as_ref().map(Material::digest)
+    }
+}
+
+impl ManifestEntry {
+    /// An entry for `url` with no pins and the default signature locations.
+    pub fn new(url: &str) -> Self {
+        ManifestEntry {
+            url: url.to_string(),
+            sha256: None,
+            digest: None,
+            length: None,
+            content_types: Vec::new(),
+            identity: None,
+            issuer: None,
+            key: None,
+            signature: None,
+            certificate: None,
+            bundle: None,
+            pgp: None,
+            ssh: None,
+            revocation: None,
+            provenance: None,
+            intoto: None,
+            sbom: None,
+            delta: None,
+            claims: None,
+            age: None,
+            custom: None,
+        }
+    }
+
+    /// The artifact, delta template and signature material locations given
+    /// explicitly. Default sidecar locations are derived from `url`.
+    pub fn locations(&self) -> impl Iterator<Item = &String> {
+        let intoto = self.intoto.iter().flat_map(|intoto| {
+            std::iter::once(&intoto.layout)
+                .chain(&intoto.layout_keys)
+                .chain(&intoto.links)
+        });
+        std::iter::once(&self.url)
+            .chain(
+                vec![
+                    &self.key,
+                    &self.signature,
+                    &self.certificate,
+                    &self.bundle,
+                    &self.delta,
+                    self.provenance
+                        .as_ref()
+                        .map_or(&None, |provenance| &provenance.location),
+                    self.sbom.as_ref().map_or(&None, |sbom| &sbom.location),
+                ]
+                .into_iter()
+                .flatten(),
+            )
+            .chain(self.pgp.iter().map(|pgp| &pgp.keyring))
+            .chain(self.ssh.iter().map(|ssh| &ssh.allowed_signers))
+            .chain(self.claims.iter().map(|claims| &claims.policy))
+            .chain(intoto)
+    }
+
+    pub(crate) fn expected_identity(&self) -> Option<SigstoreOidcKey> {
+        self.identity.as_ref().map(|identity| SigstoreOidcKey {
+            identity: identity.clone(),
+            issuer: self.issuer.clone().unwrap_or_default(),
+        })
+    }
+
+    /// Check the digests the entry pins against its artifact, if it is a
+    /// local file, streaming it from disk so that a mismatching artifact is
+    /// refused without being read into memory. They are checked again, over
+    /// the bytes read, by [`ManifestEntry::verify_material`].
+    async fn check_local_digests(&self, transport: &dyn Transport, base: &Path) -> Result<()> {
+        // Encrypted artifacts' digests are of the plaintext.
+        if self.age.is_some() || is_remote(&self.url) || self.url == STDIN {
+            return Ok(());
+        }
+        for expected in self.sha256.iter().chain(&self.digest) {
+            let expected: ArtifactDigest = expected.parse()?;
+            let actual = fetch_digest_with(transport, &self.url, base, expected.algorithm).await?;
+            if actual != expected {
+                return Err(SgetError::DigestMismatch {
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch the artifact and its signature material.
+    pub async fn fetch_material(&self, transport: &dyn Transport, base: &Path) -> Result<Material> {
+        let sidecar = |explicit: &Option<String>, extension: &str| {
+            explicit
+                .clone()
+                .unwrap_or_else(|| format!("{}.{}", self.url, extension))
+        };
+        let fetch = |location: String| async move { fetch(transport, &location, base).await };
+        let expected = ContentExpectations {
+            content_types: self.content_types.clone(),
+            length: self.length,
+        };
+        if self.age.is_some() && self.delta.is_some() {
+            return Err(SgetError::InvalidEntry(
+                "Deltas are not supported for encrypted artifacts".to_string(),
+            ));
+        }
+        let patched = match &self.delta {
+            Some(template) => delta::fetch_patched(transport, base, &self.url, template).await,
+            None => None,
+        };
+        let data = match patched {
+            Some(data) => {
+                expected.check(&self.url, None, &data)?;
+                data
+            }
+            None => {
+                self.check_local_digests(transport, base).await?;
+                fetch_expected(transport, &self.url, base, Some(&expected)).await?
+            }
+        };
+        // Artifacts signed as plaintext are verified decrypted.
+        let data = match &self.age {
+            Some(age) if age.plaintext_sha256.is_some() => decrypt(age, &data, base)?,
+            _ => data,
+        };
+        let mut material = Material {
+            data,
+            signature: Vec::new(),
+            key: None,
+            certificate: None,
+            bundle: None,
+            keyring: None,
+            allowed_signers: None,
+            revocation: None,
+            provenance: None,
+            layout: None,
+            sbom: None,
+            claim_policy: None,
+        };
+        if let Some(pgp) = &self.pgp {
+            material.signature = fetch(sidecar(&self.signature, "asc")).await?;
+            material.keyring = Some(fetch(pgp.keyring.clone()).await?);
+        } else if let Some(ssh) = &self.ssh {
+            material.signature = fetch(sidecar(&self.signature, "sig")).await?;
+            material.allowed_signers = Some(fetch(ssh.allowed_signers.clone()).await?);
+        } else {
+            let signature = fetch(sidecar(&self.signature, "sig")).await;
+            let mut certificate = None;
+            let bundle = match &self.key {
+                Some(key) => {
+                    material.key = Some(fetch(key.clone()).await?);
+                    match &self.bundle {
+                        Some(bundle) => Some(fetch(bundle.clone()).await),
+                        None => None,
+                    }
+                }
+                None => {
+                    certificate = Some(fetch(sidecar(&self.certificate, "pem")).await);
+                    Some(fetch(sidecar(&self.bundle, "bundle")).await)
+                }
+            };
+            // Cosign and sigstore bundles carry the signature and certificate,
+            // so their default sidecars may be missing.
+            let embedded = match &bundle {
+                Some(Ok(bundle)) => rekor::embedded_signature(bundle),
+                _ => None,
+            };
+            material.signature = match (signature, &embedded) {
+                (Ok(signature), _) => signature,
+                (Err(_), Some((signature, _))) if self.signature.is_none() => {
+                    signature.clone().into_bytes()
+                }
+                (Err(e), _) => return Err(e),
+            };
+            material.certificate = match (certificate, embedded.and_then(|(_, cert)| cert)) {
+                (Some(Ok(certificate)), _) => Some(certificate),
+                (Some(Err(_)), Some(cert)) if self.certificate.is_none() => Some(cert.into_bytes()),
+                (Some(Err(e)), _) => return Err(e),
+                (None, _) => None,
+            };
+            material.bundle = bundle.transpose()?;
+            if let (Some(policy), Some(certificate)) = (&self.revocation, &material.certificate) {
+                let chain = certificate_chain(certificate).map_err(SgetError::InvalidMaterial)?;
+                material.revocation =
+                    Some(revocation::fetch_revocation_data(transport, &chain, policy).await?);
+            }
+        }
+        let digest = material.digest();
+        let attestations = |location: &Option<String>, extension: &str| {
+            let location = sidecar(location, extension);
+            let digest = &digest;
+            async move {
+                match location.strip_prefix(OCI_PREFIX) {
+                    Some(repository) => pull_oci_attestations(transport, repository, digest).await,
+                    None if location.starts_with(OCI_LAYOUT_PREFIX) => {
+                        pull_oci_attestations(transport, &location, digest).await
+                    }
+                    None => fetch(location).await,
+                }
+            }
+        };
+        if let Some(requirements) = &self.provenance {
+            material.provenance = Some(attestations(&requirements.location, "intoto.jsonl").await?);
+        }
+        if let Some(requirements) = &self.sbom {
+            material.sbom = Some(attestations(&requirements.location, "sbom.intoto.jsonl").await?);
+        }
+        if let Some(requirements) = &self.claims {
+            material.claim_policy = Some(fetch(requirements.policy.clone()).await?);
+        }
+        if let Some(requirements) = &self.intoto {
+            material.layout = Some(fetch_layout(transport, base, requirements).await?);
+        }
+        Ok(material)
+    }
+
+    /// Check fetched material against the entry's pins and the trust roots,
+    /// running the hooks of `pipeline` around each stage.
+    pub fn verify_material(
+        &self,
+        material: &Material,
+        roots: &TrustRoots,
+        pipeline: &Pipeline,
+    ) -> Result<Signer> {
+        if self.identity.is_some() && self.key.is_some() {
+            return Err(SgetError::InvalidEntry(
+                "Entry pins both an identity and a key".to_string(),
+            ));
+        }
+        let schemes = [
+            self.identity.is_some() || self.key.is_some(),
+            self.pgp.is_some(),
+            self.ssh.is_some(),
+        ];
+        if schemes.iter().filter(|pinned| **pinned).count() > 1 {
+            return Err(SgetError::InvalidEntry(
+                "Entry pins signers of more than one signature scheme".to_string(),
+            ));
+        }
+        if self.revocation.is_some() && (self.key.is_some() || schemes[1] || schemes[2]) {
+            return Err(SgetError::InvalidEntry(
+                "Revocation is only checked for keyless signatures".to_string(),
+            ));
+        }
+        let plaintext_sha256 = self.age.iter().flat_map(|age| &age.plaintext_sha256);
+        for expected in self
+            .sha256
+            .iter()
+            .chain(&self.digest)
+            .chain(plaintext_sha256)
+        {
+            verify::verify_digest(&material.data, expected, pipeline)?;
+        }
+        let (signer, public_key, blob) = if let Some(requirements) = &self.pgp {
+            let keyring = Keyring::parse(material.keyring.as_deref().unwrap_or_default())
+                .map_err(SgetError::InvalidMaterial)?;
+            let signer = pgp::verify_detached(
+                &material.data,
+                &material.signature,
+                &keyring,
+                &requirements.fingerprints,
+                &roots.algorithms,
+                Utc::now(),
+            )?;
+            (signer, None, None)
+        } else if let Some(requirements) = &self.ssh {
+            let allowed_signers =
+                AllowedSigners::parse(material.allowed_signers.as_deref().unwrap_or_default())
+                    .map_err(SgetError::InvalidMaterial)?;
+            let signer = ssh::verify_ssh(
+                &material.data,
+                &material.signature,
+                &allowed_signers,
+                requirements.principal.as_deref(),
+                requirements
+                    .namespace
+                    .as_deref()
+                    .unwrap_or(ssh::FILE_NAMESPACE),
+                &roots.algorithms,
+                Utc::now(),
+            )?;
+            (signer, None, None)
+        } else {
+            let mut sig = material.parse().map_err(SgetError::InvalidMaterial)?;
+            let signer = verify::verify_blob(
+                &material.data,
+                &sig,
+                roots,
+                self.expected_identity().as_ref(),
+                pipeline,
+            )?;
+            if let (Some(policy), Some(chain)) = (&self.revocation, &sig.certificate) {
+                revocation::verify_revocation(
+                    chain,
+                    roots,
+                    material
+                        .revocation
+                        .as_ref()
+                        .unwrap_or(&RevocationData::default()),
+                    policy,
+                    &material.digest(),
+                    pipeline,
+                    Utc::now(),
+                )?;
+            }
+            (signer, sig.public_key.take(), Some(sig))
+        };
+        if let Some(requirements) = &self.provenance {
+            provenance::verify_provenance(
+                material.provenance.as_deref().unwrap_or_default(),
+                &material.digest(),
+                public_key.as_ref(),
+                roots,
+                requirements,
+                pipeline,
+            )?;
+        }
+        if self.sbom.is_some() {
+            sbom::verify_sbom(
+                material.sbom.as_deref().unwrap_or_default(),
+                &material.digest(),
+                public_key.as_ref(),
+                roots,
+                signer.identity.as_deref(),
+                pipeline,
+            )?;
+        }
+        if let Some(layout) = &material.layout {
+            intoto::verify_layout(
+                layout,
+                &material.digest(),
+                &SignatureVerifiers::default(),
+                pipeline,
+                Utc::now(),
+            )?;
+        } else if self.intoto.is_some() {
+            return Err(SgetError::Layout("No layout was fetched".to_string()));
+        }
+        if let Some(requirements) = &self.claims {
+            let digest = material.digest();
+            let context = StageContext {
+                stage: Stage::ClaimCheck,
+                digest: &digest,
+                identity: signer.identity.as_deref(),
+            };
+            pipeline.run(context, || {
+                let input = ClaimsInput::new(&self.url, &digest, &signer, blob.as_ref())?;
+                claims::evaluate(
+                    requirements,
+                    material.claim_policy.as_deref().unwrap_or_default(),
+                    &input,
+                )
+            })?;
+        }
+        roots.check_signer_for(&signer, Some(&self.url))?;
+        Ok(signer)
+    }
+
+    /// The contents to write or run of the verified artifact `data`: decrypted,
+    /// if it is encrypted at rest and was verified as ciphertext.
+    pub fn plaintext(&self, data: Vec<u8>, base: &Path) -> Result<Vec<u8>> {
+        match &self.age {
+            Some(age) if age.plaintext_sha256.is_none() => decrypt(age, &data, base),
+            _ => Ok(data),
+        }
+    }
+
+    /// Fetch the artifact and its signature material and verify them, returning
+    /// the verified contents, decrypted if they are encrypted at rest.
+    pub async fn fetch_verified(
+        &self,
+        transport: &dyn Transport,
+        base: &Path,
+        roots: &TrustRoots,
+        pipeline: &Pipeline,
+    ) -> Result<(Vec<u8>, Signer)> {
+        let material = self.fetch_material(transport, base).await?;
+        let signer = self.verify_material(&material, roots, pipeline)?;
+        if self.delta.is_some() {
+            if let Err(e) =
+                ArtifactCache::open().and_then(|cache| cache.put(&self.url, &material.data))
+            {
+                eprintln!("Warning: cannot cache {}: {:#}", self.url, e);
+            }
+        }
+        Ok((self.plaintext(material.data, base)?, signer))
+    }
+}
+
+/// Decrypt the age-encrypted `data` with the identity file of `age`.
+fn decrypt(age: &AgeRequirements, data: &[u8], base: &Path) -> Result<Vec<u8>> {
+    let path = match &age.identity {
+        Some(path) if is_remote(path) => {
+            return Err(SgetError::InvalidEntry(
+                "age identities must be local files".to_string(),
+            ))
+        }
+        Some(path) => base.join(path),
+        None => env::var_os(AGE_IDENTITY_ENV)
+            .map(PathBuf::from)
+            .ok_or_else(|| {
+                SgetError::InvalidEntry(format!(
+                    "Entry is encrypted but names no age identity, and {} is not set",
+                    AGE_IDENTITY_ENV
+                ))
+            })?,
+    };
+    fs::read_to_string(&path)
+        .map(Zeroizing::new)
+        .with_context(|| format!("Cannot read {}", path.display()))
+        .and_then(|text| age::parse_identities(&text))
+        .and_then(|identities| age::decrypt(data, &identities))
+        .map_err(SgetError::Decryption)
+}
+
+/// Fetch an in-toto layout, its keys, and the links named after its steps and
+/// their keys. Links that cannot be fetched are left out; verification fails if
+/// that leaves a step short of its threshold.
+async fn fetch_layout(
+    transport: &dyn Transport,
+    base: &Path,
+    requirements: &LayoutRequirements,
+) -> Result<LayoutMaterial> {
+    let layout = fetch(transport, &requirements.layout, base).await?;
+    let mut keys = Vec::with_capacity(requirements.layout_keys.len());
+    for key in &requirements.layout_keys {
+        keys.push(fetch(transport, key, base).await?);
+    }
+    let links_dir = match &requirements.links {
+        Some(links) => links.trim_end_matches('/'),
+        None => requirements
+            .layout
+            .rsplit_once('/')
+            .map_or("", |(dir, _)| dir),
+    };
+    let mut links = BTreeMap::new();
+    for name in intoto::link_names(&layout).map_err(SgetError::InvalidMaterial)? {
+        let location = match links_dir {
+            "" => name.clone(),
+            dir => format!("{}/{}", dir, name),
+        };
+        if let Ok(link) = fetch(transport, &location, base).await {
+            links.insert(name, link);
+        }
+    }
+    Ok(LayoutMaterial {
+        layout,
+        keys,
+        links,
+    })
+}
+
+/// Verify every entry of the manifest at `path`, returning one result per entry
+/// in manifest order. Up to `concurrency` entries, including their transparency
+/// log lookups, are fetched and verified at once.
+pub async fn verify_manifest(
+    transport: &dyn Transport,
+    path: &Path,
+    roots: &TrustRoots,
+    pipeline: &Pipeline,
+    concurrency: usize,
+) -> Result<Vec<EntryResult>> {
+    let manifest = Manifest::load(path)?;
+    let base: PathBuf = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    Ok(stream::iter(&manifest.artifacts)
+        .map(|entry| verify_entry(transport, entry, &base, roots, pipeline))
+        .buffered(concurrency.max(1))
+        .collect()
+        .await)
+}
+
+async fn verify_entry(
+    transport: &dyn Transport,
+    entry: &ManifestEntry,
+    base: &Path,
+    roots: &TrustRoots,
+    pipeline: &Pipeline,
+) -> EntryResult {
+    let (material, outcome) = match entry.fetch_material(transport, base).await {
+        Ok(material) => {
+            let outcome = entry.verify_material(&material, roots, pipeline);
+            (Some(material), outcome)
+        }
+        Err(e) => (None, Err(e)),
+    };
+    EntryResult {
+        url: entry.url.clone(),
+        custom: entry.custom.clone(),
+        material,
+        outcome,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::FailureReason;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+
+    fn write_signed_artifact(dir: &Path, name: &str, data: &[u8], key: &SigningKey) {
+        fs::write(dir.join(name), data).expect("Cannot write artifact");
+        fs::write(
+            dir.join(format!("{}.sig", name)),
+            base64::encode(key.sign(data)),
+        )
+        .expect("Cannot write signature");
+    }
+
+    #[tokio::test]
+    async fn verify_manifest_entries() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        fs::write(
+            dir.path().join("sget.pub"),
+            key.public_key().to_pem().expect("Cannot encode key"),
+        )
+        .expect("Cannot write key");
+        write_signed_artifact(dir.path(), "good.sh", b"echo good", &key);
+        write_signed_artifact(dir.path(), "bad.sh", b"echo bad", &key);
+        fs::write(dir.path().join("bad.sh"), b"echo tampered").expect("Cannot tamper");
+
+        let manifest = format!(
+            "artifacts:\n\
+             - url: good.sh\n  key: sget.pub\n  sha256: {}\n\
+             - url: bad.sh\n  key: sget.pub\n\
+             - url: good.sh\n  key: sget.pub\n  sha256: {}\n\
+             - url: missing.sh\n  key: sget.pub\n",
+            hex::encode(Sha256::digest(b"echo good")),
+            "00".repeat(32),
+        );
+        let path = dir.path().join("scripts.yaml");
+        fs::write(&path, manifest).expect("Cannot write manifest");
+
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let transport = crate::transport::default_transport();
+        let results = verify_manifest(
+            transport.as_ref(),
+            &path,
+            &roots,
+            &Pipeline::default(),
+            DEFAULT_CONCURRENCY,
+        )
+        .await
+        .expect("Cannot verify manifest");
+        let reasons: Vec<Option<FailureReason>> = results
+            .iter()
+            .map(|r| r.outcome.as_ref().err().and_then(SgetError::reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                None,
+                Some(FailureReason::Signature),
+                Some(FailureReason::Digest),
+                Some(FailureReason::Fetch),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn check_local_artifacts_before_reading() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        write_signed_artifact(dir.path(), "good.sh", b"echo good", &key);
+        fs::write(
+            dir.path().join("sget.pub"),
+            key.public_key().to_pem().expect("Cannot encode key"),
+        )
+        .expect("Cannot write key");
+        let transport = crate::transport::default_transport();
+        let fetch = |entry: ManifestEntry| {
+            let transport = transport.clone();
+            let base = dir.path().to_path_buf();
+            async move { entry.fetch_material(transport.as_ref(), &base).await }
+        };
+
+        let mut entry = ManifestEntry::new("good.sh");
+        entry.key = Some("sget.pub".to_string());
+        entry.digest = Some(format!(
+            "sha512:{}",
+            hex::encode(sha2::Sha512::digest(b"echo good"))
+        ));
+        let material = fetch(entry.clone()).await.expect("Cannot fetch artifact");
+        assert_eq!(material.data, b"echo good");
+
+        entry.digest = Some(format!("sha512:{}", "00".repeat(64)));
+        assert!(matches!(
+            fetch(entry.clone()).await,
+            Err(SgetError::DigestMismatch { .. })
+        ));
+        entry.digest = None;
+        entry.length = Some(1 << 30);
+        assert!(matches!(
+            fetch(entry).await,
+            Err(SgetError::UnexpectedContent { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn fetch_encrypted_artifacts() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/test_data/age");
+        let read = |name: &str| fs::read(data.join(name)).expect("Cannot read test data");
+        let (script, encrypted) = (read("script.sh"), read("script.sh.age"));
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        fs::write(
+            dir.path().join("sget.pub"),
+            key.public_key().to_pem().expect("Cannot encode key"),
+        )
+        .expect("Cannot write key");
+        fs::write(dir.path().join("identity.txt"), read("identity.txt"))
+            .expect("Cannot write identity");
+        write_signed_artifact(dir.path(), "ciphertext.sh.age", &encrypted, &key);
+        fs::write(dir.path().join("plaintext.sh.age"), &encrypted).expect("Cannot write");
+        fs::write(
+            dir.path().join("plaintext.sh.age.sig"),
+            base64::encode(key.sign(&script)),
+        )
+        .expect("Cannot write signature");
+
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let transport = crate::transport::default_transport();
+        let fetch = |url: &str, age: AgeRequirements| {
+            let mut entry = ManifestEntry::new(url);
+            entry.key = Some("sget.pub".to_string());
+            entry.age = Some(age);
+            let (transport, roots, base) = (transport.as_ref(), &roots, dir.path());
+            async move {
+                entry
+                    .fetch_verified(transport, base, roots, &Pipeline::default())
+                    .await
+                    .map(|(data, _)| data)
+            }
+        };
+        let identity = Some("identity.txt".to_string());
+
+        // Signed as ciphertext, decrypted once verified.
+        let age = AgeRequirements {
+            identity: identity.clone(),
+            plaintext_sha256: None,
+        };
+        let fetched = fetch("ciphertext.sh.age", age).await;
+        assert_eq!(fetched.expect("Cannot fetch"), script);
+        // Signed as plaintext, decrypted first and pinned by digest.
+        let age = AgeRequirements {
+            identity: identity.clone(),
+            plaintext_sha256: Some(hex::encode(Sha256::digest(&script))),
+        };
+        let fetched = fetch("plaintext.sh.age", age).await;
+        assert_eq!(fetched.expect("Cannot fetch"), script);
+        let age = AgeRequirements {
+            identity,
+            plaintext_sha256: Some("00".repeat(32)),
+        };
+        let error = fetch("plaintext.sh.age", age).await.err();
+        assert_eq!(error.and_then(|e| e.reason()), Some(FailureReason::Digest));
+        // The wrong identity cannot decrypt.
+        let age = AgeRequirements {
+            identity: Some("sget.pub".to_string()),
+            plaintext_sha256: None,
+        };
+        let error = fetch("ciphertext.sh.age", age).await.err();
+        assert_eq!(
+            error.and_then(|e| e.reason()),
+            Some(FailureReason::Decryption)
+        );
+    }
+
+    /// Answers 404 after a short delay, counting the most requests in flight.
+    #[derive(Default)]
+    struct SlowMissing {
+        in_flight: std::sync::atomic::AtomicUsize,
+        most: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl Transport for SlowMissing {
+        async fn send(
+            &self,
+            _request: crate::transport::Request<Vec<u8>>,
+        ) -> anyhow::Result<crate::transport::Response<Vec<u8>>> {
+            use std::sync::atomic::Ordering;
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(crate::transport::Response::builder()
+                .status(404)
+                .body(Vec::new())?)
+        }
+    }
+
+    #[tokio::test]
+    async fn verify_entries_concurrently() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let urls: Vec<String> = (0..7)
+            .map(|i| format!("https://example.com/{}.sh", i))
+            .collect();
+        let manifest = Manifest {
+            artifacts: urls.iter().map(|url| ManifestEntry::new(url)).collect(),
+        };
+        let path = dir.path().join("scripts.yaml");
+        fs::write(
+            &path,
+            serde_yaml::to_string(&manifest).expect("Cannot encode manifest"),
+        )
+        .expect("Cannot write manifest");
+
+        let transport = SlowMissing::default();
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let results = verify_manifest(&transport, &path, &roots, &Pipeline::default(), 3)
+            .await
+            .expect("Cannot verify manifest");
+        let order: Vec<&str> = results.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(order, urls);
+        assert!(results
+            .iter()
+            .all(|r| r.outcome.as_ref().err().and_then(SgetError::reason)
+                == Some(FailureReason::Fetch)));
+        assert_eq!(transport.most.into_inner(), 3);
+    }
+}
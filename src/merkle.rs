@@ -0,0 +1,389 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+
+use crate::error::{Result, SgetError};
+
+/// Prefix of leaf hashes, so that a leaf cannot pass for an inner node.
+const LEAF: u8 = 0x00;
+/// Prefix of inner node hashes.
+const NODE: u8 = 0x01;
+
+/// Length of a SHA-256 digest.
+const HASH_LEN: usize = 32;
+
+/// A target split into fixed-size chunks, identified by the Merkle root over
+/// their hashes. Large targets list it in their targets metadata next to
+/// their length, so that every chunk can be verified as it arrives and an
+/// interrupted download resumes without hashing what it already has:
+///
+/// ```json
+/// "release/disk.img": {
+///   "length": 8589934592,
+///   "hashes": { "sha256": "3b0f..." },
+///   "merkle": { "chunk_size": 4194304, "root": "c71e..." }
+/// }
+/// ```
+///
+/// The chunk hashes themselves are published next to the target, as the
+/// concatenated 32 byte SHA-256 leaf hashes, and are checked against the root
+/// before any chunk is.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MerkleRoot {
+    /// Length of every chunk but the last, in bytes.
+    pub chunk_size: u64,
+    /// Hex encoded root of the tree over the chunk hashes.
+    pub root: String,
+}
+
+/// The leaf hash of a chunk.
+pub fn leaf(chunk: &[u8]) -> [u8; HASH_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF]);
+    hasher.update(chunk);
+    hasher.finalize().into()
+}
+
+fn node(left: &[u8], right: &[u8]) -> [u8; HASH_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// The root of the tree over `leaves`, pairing them level by level. An odd
+/// node out moves up a level unchanged.
+pub fn root(leaves: &[[u8; HASH_LEN]]) -> [u8; HASH_LEN] {
+    let mut level = leaves.to_vec();
+    if level.is_empty() {
+        return leaf(&[]);
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node(left, right),
+                [single] => *single,
+                _ => leaf(&[]),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// Number of chunks of a target of `length` bytes; an empty target has one
+/// empty chunk.
+pub fn chunk_count(length: u64, chunk_size: u64) -> u64 {
+    match length {
+        0 => 1,
+        length => length.div_ceil(chunk_size),
+    }
+}
+
+impl MerkleRoot {
+    /// The root of `data` split into chunks of `chunk_size`, and the chunk
+    /// hashes to publish next to it.
+    pub fn of(data: &[u8], chunk_size: u64) -> Result<(Self, Vec<u8>)> {
+        let size = usize::try_from(chunk_size)
+            .ok()
+            .filter(|size| *size > 0)
+            .ok_or_else(|| invalid("chunk size must be positive"))?;
+        let leaves: Vec<[u8; HASH_LEN]> = match data {
+            [] => vec![leaf(&[])],
+            data => data.chunks(size).map(leaf).collect(),
+        };
+        let merkle = MerkleRoot {
+            chunk_size,
+            root: hex::encode(root(&leaves)),
+        };
+        Ok((merkle, leaves.concat()))
+    }
+}
+
+fn invalid(reason: &str) -> SgetError {
+    SgetError::InvalidMaterial(anyhow!("Invalid chunk hashes: {}", reason))
+}
+
+/// The chunk hashes of a target of known length, checked against its Merkle
+/// root, against which each chunk is then verified on its own.
+#[derive(Debug)]
+pub struct ChunkHashes {
+    chunk_size: u64,
+    length: u64,
+    leaves: Vec<[u8; HASH_LEN]>,
+}
+
+impl ChunkHashes {
+    /// Check the published chunk hashes `raw` of a target of `length` bytes
+    /// against `merkle`.
+    pub fn new(merkle: &MerkleRoot, length: u64, raw: &[u8]) -> Result<Self> {
+        if merkle.chunk_size == 0 {
+            return Err(invalid("chunk size must be positive"));
+        }
+        let count = chunk_count(length, merkle.chunk_size);
+        if raw.len() as u64 != count * HASH_LEN as u64 {
+            return Err(invalid(&format!(
+                "expected {} hashes for {} bytes",
+                count, length
+            )));
+        }
+        let leaves: Vec<[u8; HASH_LEN]> = raw
+            .chunks_exact(HASH_LEN)
+            .map(|hash| {
+                let mut leaf = [0; HASH_LEN];
+                leaf.copy_from_slice(hash);
+                leaf
+            })
+            .collect();
+        let actual = hex::encode(root(&leaves));
+        if actual != merkle.root.to_lowercase() {
+            return Err(SgetError::DigestMismatch {
+                expected: merkle.root.clone(),
+                actual,
+            });
+        }
+        Ok(ChunkHashes {
+            chunk_size: merkle.chunk_size,
+            length,
+            leaves,
+        })
+    }
+
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    pub fn len(&self) -> u64 {
+        self.leaves.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// The byte range of chunk `index` within the target.
+    pub fn range(&self, index: u64) -> std::ops::Range<u64> {
+        let start = index * self.chunk_size;
+        start..(start + self.chunk_size).min(self.length)
+    }
+
+    /// Check that `chunk` is chunk `index` of the target.
+    pub fn verify(&self, index: u64, chunk: &[u8]) -> Result<()> {
+        let expected = usize::try_from(index)
+            .ok()
+            .and_then(|index| self.leaves.get(index))
+            .ok_or_else(|| invalid(&format!("no chunk {}", index)))?;
+        let range = self.range(index);
+        if chunk.len() as u64 != range.end - range.start {
+            return Err(SgetError::UnexpectedContent {
+                location: format!("chunk {}", index),
+                reason: format!(
+                    "{} bytes, but the chunk is {} bytes",
+                    chunk.len(),
+                    range.end - range.start
+                ),
+            });
+        }
+        let actual = leaf(chunk);
+        if actual != *expected {
+            return Err(SgetError::DigestMismatch {
+                expected: hex::encode(expected),
+                actual: hex::encode(actual),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "native")]
+pub use self::download::download;
+
+#[cfg(feature = "native")]
+mod download {
+    use anyhow::anyhow;
+    use http::header::{HeaderValue, RANGE};
+    use http::StatusCode;
+    use std::convert::TryFrom;
+    use std::fs::{self, OpenOptions};
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::path::{Path, PathBuf};
+
+    use super::{ChunkHashes, MerkleRoot};
+    use crate::error::{Result, SgetError};
+    use crate::fetch::{fetch, is_remote};
+    use crate::transport::{self, Transport};
+
+    fn fetch_error(location: &str, source: impl Into<anyhow::Error>) -> SgetError {
+        SgetError::Fetch {
+            location: location.to_string(),
+            source: source.into(),
+        }
+    }
+
+    /// Where an unfinished download of `dest` is kept.
+    fn partial(dest: &Path) -> PathBuf {
+        let mut partial = dest.as_os_str().to_owned();
+        partial.push(".part");
+        PathBuf::from(partial)
+    }
+
+    /// Download the target of `length` bytes at `location` into `dest`,
+    /// verifying each chunk against `merkle` as it arrives, with one range
+    /// request per chunk. The chunk hashes are fetched from `location` with
+    /// `.chunks` appended.
+    ///
+    /// Chunks are appended to `dest` with `.part` appended once verified, and
+    /// the partial file is renamed to `dest` once complete. An interrupted
+    /// download resumes after the last whole chunk of the partial file, which
+    /// was verified before it was written and is not hashed again. Returns
+    /// the number of chunks downloaded.
+    pub async fn download(
+        transport: &dyn Transport,
+        location: &str,
+        merkle: &MerkleRoot,
+        length: u64,
+        dest: &Path,
+    ) -> Result<u64> {
+        let raw = fetch(transport, &format!("{}.chunks", location), Path::new("")).await?;
+        let hashes = ChunkHashes::new(merkle, length, &raw)?;
+        let partial = partial(dest);
+        let display = partial.display().to_string();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&partial)
+            .map_err(|e| fetch_error(&display, e))?;
+        let have = file.metadata()?.len() / hashes.chunk_size();
+        let first = have.min(hashes.len());
+        file.set_len(hashes.range(first).start)?;
+        file.seek(SeekFrom::End(0))?;
+
+        let mut index = first;
+        while index < hashes.len() {
+            let range = hashes.range(index);
+            let (body, whole) = read_range(transport, location, range.clone()).await?;
+            if whole {
+                // The server ignored the range and sent the whole target:
+                // take the chunks still missing from it.
+                for index in index..hashes.len() {
+                    let range = hashes.range(index);
+                    let chunk = usize::try_from(range.start)
+                        .ok()
+                        .zip(usize::try_from(range.end).ok())
+                        .and_then(|(start, end)| body.get(start..end))
+                        .unwrap_or_default();
+                    hashes.verify(index, chunk)?;
+                    file.write_all(chunk)?;
+                }
+                break;
+            }
+            hashes.verify(index, &body)?;
+            file.write_all(&body)?;
+            index += 1;
+        }
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&partial, dest).map_err(|e| fetch_error(&display, e))?;
+        Ok(hashes.len() - first)
+    }
+
+    /// The bytes of `range` of the target at `location`, and whether the
+    /// whole target was returned instead.
+    async fn read_range(
+        transport: &dyn Transport,
+        location: &str,
+        range: std::ops::Range<u64>,
+    ) -> Result<(Vec<u8>, bool)> {
+        if !is_remote(location) {
+            let mut file = fs::File::open(location).map_err(|e| fetch_error(location, e))?;
+            file.seek(SeekFrom::Start(range.start))?;
+            let mut chunk = Vec::new();
+            file.take(range.end - range.start).read_to_end(&mut chunk)?;
+            return Ok((chunk, false));
+        }
+        let mut request = transport::get(location).map_err(|e| fetch_error(location, e))?;
+        // HTTP ranges are inclusive; an empty target has nothing to ask for.
+        if range.end > range.start {
+            let value = format!("bytes={}-{}", range.start, range.end - 1);
+            request.headers_mut().insert(
+                RANGE,
+                HeaderValue::from_str(&value).map_err(|e| fetch_error(location, e))?,
+            );
+        }
+        let response = transport
+            .send(request)
+            .await
+            .map_err(|e| fetch_error(location, e))?;
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => Ok((response.into_body(), false)),
+            status if status.is_success() => Ok((response.into_body(), true)),
+            status => Err(fetch_error(location, anyhow!("{}", status))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_chunks() {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let (merkle, raw) = MerkleRoot::of(&data, 4096).expect("Cannot hash");
+        let hashes = ChunkHashes::new(&merkle, data.len() as u64, &raw).expect("Invalid hashes");
+        assert_eq!(hashes.len(), 10);
+        for index in 0..hashes.len() {
+            let range = hashes.range(index);
+            hashes
+                .verify(index, &data[range.start as usize..range.end as usize])
+                .expect("Chunk does not verify");
+        }
+        assert_eq!(hashes.range(9), 36_864..40_000);
+
+        // A tampered chunk fails on its own, as does one in the wrong place.
+        let mut chunk = data[..4096].to_vec();
+        chunk[7] ^= 1;
+        assert!(matches!(
+            hashes.verify(0, &chunk),
+            Err(SgetError::DigestMismatch { .. })
+        ));
+        assert!(hashes.verify(1, &data[..4096]).is_err());
+
+        // Hashes that do not add up to the signed root are refused.
+        let mut forged = raw.clone();
+        forged[40] ^= 1;
+        assert!(ChunkHashes::new(&merkle, data.len() as u64, &forged).is_err());
+        assert!(ChunkHashes::new(&merkle, data.len() as u64 + 4096, &raw).is_err());
+
+        let (empty, raw) = MerkleRoot::of(b"", 4096).expect("Cannot hash");
+        let hashes = ChunkHashes::new(&empty, 0, &raw).expect("Invalid hashes");
+        hashes.verify(0, b"").expect("Empty chunk does not verify");
+    }
+
+    #[cfg(feature = "native")]
+    #[tokio::test]
+    async fn resume_download() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let data: Vec<u8> = (0..3000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let source = dir.path().join("disk.img");
+        std::fs::write(&source, &data).expect("Cannot write target");
+        let (merkle, raw) = MerkleRoot::of(&data, 1024).expect("Cannot hash");
+        std::fs::write(dir.path().join("disk.img.chunks"), &raw).expect("Cannot write hashes");
+
+        // Two chunks and a bit were downloaded before the interruption.
+        let dest = dir.path().join("out.img");
+        std::fs::write(dir.path().join("out.img.part"), &data[..2500]).expect("Cannot write");
+        let transport = crate::transport::default_transport();
+        let location = source.display().to_string();
+        let fetched = download(transport.as_ref(), &location, &merkle, 12_000, &dest)
+            .await
+            .expect("Cannot download");
+        assert_eq!(fetched, 10);
+        assert_eq!(std::fs::read(&dest).expect("Not downloaded"), data);
+        assert!(!dir.path().join("out.img.part").exists());
+    }
+}
@@ -0,0 +1,255 @@
+use async_trait::async_trait;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::delta;
+use crate::error::SgetError;
+use crate::profile;
+use crate::transport::{self, Transport};
+
+/// Upper bounds, in seconds, of the fetch duration histogram buckets.
+const DURATION_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// A Prometheus histogram with fixed buckets.
+#[derive(Default)]
+struct Histogram {
+    /// Number of observations in each of `DURATION_BUCKETS`, not cumulative.
+    buckets: [u64; DURATION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = DURATION_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    fn render(&self, out: &mut String, name: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in DURATION_BUCKETS.iter().zip(&self.buckets) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    requests: u64,
+    successes: u64,
+    /// Failures by `FailureReason::label`, or "other" when unclassified.
+    failures: BTreeMap<&'static str, u64>,
+    fetch_duration: Histogram,
+    rekor_duration: Histogram,
+}
+
+/// Counters exported by `sget serve` and `sget watch` on `/metrics`, in the
+/// Prometheus text exposition format.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+impl Metrics {
+    /// Record one artifact request, taking `elapsed` to fetch and verify.
+    pub fn record(&self, outcome: Result<(), &SgetError>, elapsed: Duration) {
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        inner.requests += 1;
+        match outcome {
+            Ok(()) => inner.successes += 1,
+            Err(e) => {
+                let reason = e.reason().map_or("other", |reason| reason.label());
+                *inner.failures.entry(reason).or_default() += 1;
+            }
+        }
+        inner.fetch_duration.observe(elapsed.as_secs_f64());
+    }
+
+    /// Record one request to the Rekor log, answered or not after `elapsed`.
+    pub fn record_rekor(&self, elapsed: Duration) {
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        inner.rekor_duration.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn render(&self) -> String {
+        let inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let mut out = String::new();
+        out.push_str("# HELP sget_requests_total Artifact requests handled.\n");
+        out.push_str("# TYPE sget_requests_total counter\n");
+        let _ = writeln!(out, "sget_requests_total {}", inner.requests);
+        out.push_str("# HELP sget_verifications_total Verification outcomes by failure reason.\n");
+        out.push_str("# TYPE sget_verifications_total counter\n");
+        let _ = writeln!(
+            out,
+            "sget_verifications_total{{result=\"success\"}} {}",
+            inner.successes
+        );
+        for (reason, count) in &inner.failures {
+            let _ = writeln!(
+                out,
+                "sget_verifications_total{{result=\"failure\",reason=\"{}\"}} {}",
+                reason, count
+            );
+        }
+        out.push_str("# HELP sget_fetch_duration_seconds Time to fetch and verify an artifact.\n");
+        out.push_str("# TYPE sget_fetch_duration_seconds histogram\n");
+        inner
+            .fetch_duration
+            .render(&mut out, "sget_fetch_duration_seconds");
+        let (hits, misses) = delta::cache_stats();
+        out.push_str("# HELP sget_delta_cache_total Artifacts with deltas by whether a cached version was patched.\n");
+        out.push_str("# TYPE sget_delta_cache_total counter\n");
+        let _ = writeln!(out, "sget_delta_cache_total{{result=\"hit\"}} {}", hits);
+        let _ = writeln!(out, "sget_delta_cache_total{{result=\"miss\"}} {}", misses);
+        out.push_str("# HELP sget_rekor_request_duration_seconds Time for the Rekor log to answer a request.\n");
+        out.push_str("# TYPE sget_rekor_request_duration_seconds histogram\n");
+        inner
+            .rekor_duration
+            .render(&mut out, "sget_rekor_request_duration_seconds");
+        out
+    }
+}
+
+/// Times the requests `inner` sends to the Rekor log into `metrics`.
+pub struct MeteredTransport {
+    inner: Arc<dyn Transport>,
+    metrics: Arc<Metrics>,
+    rekor: String,
+}
+
+impl MeteredTransport {
+    pub fn new(inner: Arc<dyn Transport>, metrics: Arc<Metrics>) -> Self {
+        MeteredTransport {
+            inner,
+            metrics,
+            rekor: profile::rekor_url().trim_end_matches('/').to_string() + "/",
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for MeteredTransport {
+    async fn send(
+        &self,
+        request: transport::Request<Vec<u8>>,
+    ) -> anyhow::Result<transport::Response<Vec<u8>>> {
+        self.send_with_progress(request, &|_, _| {}).await
+    }
+
+    async fn send_with_progress(
+        &self,
+        request: transport::Request<Vec<u8>>,
+        progress: &(dyn Fn(u64, Option<u64>) + Send + Sync),
+    ) -> anyhow::Result<transport::Response<Vec<u8>>> {
+        let rekor = request.uri().to_string().starts_with(&self.rekor);
+        let start = Instant::now();
+        let response = self.inner.send_with_progress(request, progress).await;
+        if rekor {
+            self.metrics.record_rekor(start.elapsed());
+        }
+        response
+    }
+}
+
+/// A server exporting `metrics` on `http://<addr>/metrics`, for commands
+/// without an HTTP server of their own. Fails if `addr` cannot be bound.
+pub fn exporter(
+    addr: &SocketAddr,
+    metrics: Arc<Metrics>,
+) -> anyhow::Result<impl Future<Output = hyper::Result<()>>> {
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let response = if req.uri().path() == "/metrics" {
+                    Response::new(Body::from(metrics.render()))
+                } else {
+                    let mut response = Response::new(Body::from("Not found\n"));
+                    *response.status_mut() = StatusCode::NOT_FOUND;
+                    response
+                };
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+    Ok(Server::try_bind(addr)?.serve(make_service))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn render_metrics() {
+        let metrics = Metrics::default();
+        metrics.record(Ok(()), Duration::from_millis(20));
+        let failure = SgetError::DigestMismatch {
+            expected: "00".to_string(),
+            actual: "11".to_string(),
+        };
+        metrics.record(Err(&failure), Duration::from_millis(300));
+        metrics.record(Err(&anyhow!("boom").into()), Duration::from_secs(60));
+
+        let text = metrics.render();
+        assert!(text.contains("sget_requests_total 3\n"));
+        assert!(text.contains("sget_verifications_total{result=\"success\"} 1\n"));
+        assert!(text.contains("sget_verifications_total{result=\"failure\",reason=\"digest\"} 1\n"));
+        assert!(text.contains("sget_verifications_total{result=\"failure\",reason=\"other\"} 1\n"));
+        assert!(text.contains("sget_fetch_duration_seconds_bucket{le=\"0.025\"} 1\n"));
+        assert!(text.contains("sget_fetch_duration_seconds_bucket{le=\"0.5\"} 2\n"));
+        assert!(text.contains("sget_fetch_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("sget_fetch_duration_seconds_count 3\n"));
+        assert!(text.contains("sget_delta_cache_total{result=\"hit\"} "));
+        assert!(text.contains("sget_rekor_request_duration_seconds_count 0\n"));
+    }
+
+    #[tokio::test]
+    async fn time_rekor_requests() {
+        struct Ok200;
+        #[async_trait]
+        impl Transport for Ok200 {
+            async fn send(
+                &self,
+                _: transport::Request<Vec<u8>>,
+            ) -> anyhow::Result<transport::Response<Vec<u8>>> {
+                Ok(transport::Response::new(Vec::new()))
+            }
+        }
+        let metrics = Arc::new(Metrics::default());
+        let transport = MeteredTransport::new(Arc::new(Ok200), metrics.clone());
+        let rekor = format!("{}/api/v1/log", profile::rekor_url());
+        for url in [rekor.as_str(), "https://example.com/install.sh"] {
+            transport
+                .send(transport::get(url).expect("Invalid request"))
+                .await
+                .expect("Request failed");
+        }
+        assert!(metrics
+            .render()
+            .contains("sget_rekor_request_duration_seconds_count 1\n"));
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, SubsecRound, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+use crate::fetch::fetch;
+use crate::policy::{self, Policy, SPEC_VERSION};
+use crate::transport;
+use crate::utils::from_now;
+
+/// How a policy of an earlier spec version is upgraded to the current one.
+pub struct Migration {
+    /// The new expiry [default: that of the old policy].
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl Migration {
+    /// The unsigned policy for the policy `raw` of an earlier spec version of
+    /// the same major version, and what was changed: legacy spellings
+    /// replaced, the spec version set to [`SPEC_VERSION`], the version bumped,
+    /// and the old signatures dropped, as the root keys must sign the
+    /// upgraded policy anew.
+    pub fn apply(&self, raw: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+        let policy: Value = serde_json::from_slice(raw).context("Invalid policy")?;
+        let mut signed = policy
+            .get("signed")
+            .cloned()
+            .ok_or_else(|| anyhow!("Policy has no signed section"))?;
+
+        let version = signed
+            .get("spec_version")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("Policy has no spec version"))?
+            .to_string();
+        let from = policy::parse_spec_version(&version)
+            .ok_or_else(|| anyhow!("Invalid spec version {}", version))?;
+        let current = policy::parse_spec_version(SPEC_VERSION)
+            .ok_or_else(|| anyhow!("Invalid spec version {}", SPEC_VERSION))?;
+        if from.0 != current.0 {
+            return Err(anyhow!(
+                "Cannot migrate spec version {} policies, only {}.x ones",
+                version,
+                current.0
+            ));
+        }
+        if from >= current {
+            return Err(anyhow!("The policy is already at spec version {}", version));
+        }
+        let mut changes = vec![format!("spec version {} -> {}", version, SPEC_VERSION)];
+
+        // Roles used to be named like their role type, e.g. `Root`.
+        if let Some(roles) = signed.get_mut("roles").and_then(Value::as_object_mut) {
+            let legacy: Vec<String> = roles
+                .keys()
+                .filter(|name| name.chars().any(char::is_uppercase))
+                .cloned()
+                .collect();
+            for name in legacy {
+                let renamed = name.to_lowercase();
+                if roles.contains_key(&renamed) {
+                    return Err(anyhow!("Policy has both a {} and a {} role", name, renamed));
+                }
+                if let Some(role) = roles.remove(&name) {
+                    roles.insert(renamed.clone(), role);
+                    changes.push(format!("role {} -> {}", name, renamed));
+                }
+            }
+        }
+
+        signed["spec_version"] = Value::from(SPEC_VERSION);
+        let version = signed
+            .get("version")
+            .and_then(Value::as_u64)
+            .and_then(|version| version.checked_add(1))
+            .ok_or_else(|| anyhow!("Policy has no valid version"))?;
+        signed["version"] = Value::from(version);
+        if let Some(expires) = self.expires {
+            signed["expires"] = serde_json::to_value(expires)?;
+        }
+
+        let migrated = format!("{{\"signatures\":[],\"signed\":{}}}", signed).into_bytes();
+        serde_json::from_slice::<Policy>(&migrated).context("Migrated to an invalid policy")?;
+        Ok((migrated, changes))
+    }
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("migrate")
+        .about("Upgrade a policy of an earlier spec version to an unsigned policy of the current one")
+        .arg(
+            Arg::new("policy")
+                .value_name("POLICY")
+                .required(true)
+                .about("URL or path of the policy to upgrade"),
+        )
+        .arg(
+            Arg::new("expires")
+                .long("expires")
+                .value_name("DURATION")
+                .takes_value(true)
+                .about("How long the upgraded policy is valid for, e.g. 365d [default: the old expiry]"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .value_name("FILE")
+                .required(true)
+                .about("Where to write the upgraded policy; must not exist"),
+        )
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    let location = matches
+        .value_of("policy")
+        .ok_or_else(|| anyhow!("No policy given"))?;
+    let output = matches
+        .value_of("output")
+        .ok_or_else(|| anyhow!("No --output given"))?;
+    if Path::new(output).exists() {
+        return Err(anyhow!("{} already exists", output));
+    }
+    let migration = Migration {
+        expires: matches
+            .value_of("expires")
+            .map(|expires| -> Result<_> { Ok(from_now(expires)?.trunc_subsecs(0)) })
+            .transpose()?,
+    };
+
+    let transport = transport::default_transport();
+    let raw = fetch(transport.as_ref(), location, Path::new("")).await?;
+    let (migrated, changes) = migration.apply(&raw)?;
+    fs::write(output, &migrated).with_context(|| format!("Cannot write {}", output))?;
+    let policy: Policy = serde_json::from_slice(&migrated)?;
+    println!(
+        "Upgraded {} to spec version {} as version {}, written to {}",
+        policy.signed.namespace, SPEC_VERSION, policy.signed.version, output
+    );
+    for change in changes {
+        println!("  {}", change);
+    }
+    let signed: Value = serde_json::from_slice(&migrated)?;
+    println!(
+        "The root keys sign its `signed` section, SHA-256 {}, to replace the old policy.",
+        hex::encode(Sha256::digest(signed["signed"].to_string().as_bytes()))
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn migrate_policy() {
+        let old = json!({
+            "signatures": [{ "keyid": "abcd", "sig": "", "cert": "" }],
+            "signed": {
+                "consistent_snapshot": false,
+                "expires": "2030-01-01T00:00:00Z",
+                "keys": {},
+                "namespace": "ghcr.io/acme",
+                "roles": { "Root": { "keyids": ["abcd"], "threshold": 1 } },
+                "spec_version": "1.0",
+                "version": 3,
+            },
+        })
+        .to_string();
+        let migration = Migration { expires: None };
+        let (raw, changes) = migration.apply(old.as_bytes()).expect("Cannot migrate");
+        assert_eq!(
+            changes,
+            vec!["spec version 1.0 -> 1.1", "role Root -> root"]
+        );
+        let policy: Policy = serde_json::from_slice(&raw).expect("Invalid policy");
+        assert!(policy.signatures.is_empty());
+        assert_eq!(policy.signed.spec_version, SPEC_VERSION);
+        assert_eq!(policy.signed.version.get(), 4);
+        assert_eq!(policy.signed.roles["root"].keyids, vec!["abcd"]);
+
+        // Current policies and other major versions are left alone.
+        assert!(migration.apply(&raw).is_err());
+        let newer = old.replace("\"1.0\"", "\"2.0\"");
+        assert!(migration.apply(newer.as_bytes()).is_err());
+    }
+}
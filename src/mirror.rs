@@ -0,0 +1,427 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{App, AppSettings, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::claims::ClaimLanguage;
+use crate::fetch::fetch;
+use crate::manifest::{self, Manifest, ManifestEntry, Material};
+use crate::pipeline::Pipeline;
+use crate::transport::{self, Transport};
+use crate::trust::TrustStore;
+use crate::verify::TrustRoots;
+
+/// Name of the index file at the root of a mirror.
+pub const INDEX: &str = "mirror.json";
+
+/// Name of the mirror's manifest, whose locations are all within the mirror.
+pub const MANIFEST: &str = "manifest.yaml";
+
+/// Name of the trust store export in a mirror.
+pub const TRUST_BUNDLE: &str = "trust.tar.gz";
+
+/// Directory of the mirrored artifacts and their signature material.
+const ARTIFACTS: &str = "artifacts";
+
+/// The index of a mirror. Paths are relative to the mirror's root.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MirrorIndex {
+    pub sget_version: String,
+    pub synced: DateTime<Utc>,
+    /// The manifest the mirror was synced from.
+    pub source: String,
+    pub manifest: String,
+    pub trust_bundle: String,
+    /// SHA-256 digest of the trust bundle, to give `sget trust import`.
+    pub trust_sha256: String,
+    pub artifacts: Vec<MirroredArtifact>,
+}
+
+/// Where an artifact of the source manifest is in the mirror.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MirroredArtifact {
+    pub url: String,
+    pub sha256: String,
+    pub path: String,
+}
+
+/// The last path segment of `url`, if it is a usable file name.
+fn file_name(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let name: String = path
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() || name.starts_with('.') {
+        "artifact".to_string()
+    } else {
+        name
+    }
+}
+
+/// Writes files below one directory of a mirror.
+struct Writer<'a> {
+    root: &'a Path,
+    dir: String,
+}
+
+impl Writer<'_> {
+    /// Write `contents` to `name` in the directory, returning its path
+    /// relative to the mirror's root.
+    fn write(&self, name: &str, contents: &[u8]) -> Result<String> {
+        let path = format!("{}/{}", self.dir, name);
+        let target = self.root.join(&path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Cannot create {}", parent.display()))?;
+        }
+        fs::write(&target, contents)
+            .with_context(|| format!("Cannot write {}", target.display()))?;
+        Ok(path)
+    }
+}
+
+/// Write the artifact `data` of `entry` and its verified `material` to the
+/// mirror directory `writer`, returning the entry with every location pointing
+/// into the mirror.
+fn write_entry(
+    writer: &Writer,
+    entry: &ManifestEntry,
+    material: &Material,
+    data: &[u8],
+) -> Result<ManifestEntry> {
+    let name = file_name(&entry.url);
+    let mut mirrored = entry.clone();
+    mirrored.url = writer.write(&name, data)?;
+    // The whole artifact is in the mirror, so there is nothing to patch.
+    mirrored.delta = None;
+    let extension = if entry.pgp.is_some() { "asc" } else { "sig" };
+    mirrored.signature =
+        Some(writer.write(&format!("{}.{}", name, extension), &material.signature)?);
+    if let Some(key) = &material.key {
+        mirrored.key = Some(writer.write("key.pub", key)?);
+    }
+    if let Some(certificate) = &material.certificate {
+        mirrored.certificate = Some(writer.write(&format!("{}.pem", name), certificate)?);
+    }
+    if let Some(bundle) = &material.bundle {
+        mirrored.bundle = Some(writer.write(&format!("{}.bundle", name), bundle)?);
+    }
+    if let (Some(pgp), Some(keyring)) = (&mut mirrored.pgp, &material.keyring) {
+        pgp.keyring = writer.write("keyring.gpg", keyring)?;
+    }
+    if let (Some(ssh), Some(signers)) = (&mut mirrored.ssh, &material.allowed_signers) {
+        ssh.allowed_signers = writer.write("allowed_signers", signers)?;
+    }
+    if let (Some(provenance), Some(statements)) = (&mut mirrored.provenance, &material.provenance) {
+        provenance.location = Some(writer.write(&format!("{}.intoto.jsonl", name), statements)?);
+    }
+    if let (Some(sbom), Some(statements)) = (&mut mirrored.sbom, &material.sbom) {
+        sbom.location = Some(writer.write(&format!("{}.sbom.intoto.jsonl", name), statements)?);
+    }
+    if let (Some(claims), Some(policy)) = (&mut mirrored.claims, &material.claim_policy) {
+        let file = match claims.language()? {
+            ClaimLanguage::Rego => "claims.rego",
+            ClaimLanguage::Cue => "claims.cue",
+        };
+        claims.policy = writer.write(file, policy)?;
+    }
+    if let (Some(intoto), Some(layout)) = (&mut mirrored.intoto, &material.layout) {
+        intoto.layout = writer.write("intoto/root.layout", &layout.layout)?;
+        intoto.layout_keys = layout
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| writer.write(&format!("intoto/key-{}.pub", i), key))
+            .collect::<Result<_>>()?;
+        for (link, contents) in &layout.links {
+            if file_name(link) != *link {
+                return Err(anyhow!("Invalid in-toto link name {:?}", link));
+            }
+            writer.write(&format!("intoto/links/{}", link), contents)?;
+        }
+        intoto.links = Some(format!("{}/intoto/links", writer.dir));
+    }
+    Ok(mirrored)
+}
+
+/// Fetch and verify every artifact of the manifest at `source` against
+/// `roots`, and write them with all of their signature material, a manifest
+/// pointing at them, and an export of `store` to the directory `dest`, for a
+/// host without network access to verify from with `sget trust import` and
+/// `sget verify --manifest`.
+///
+/// Nothing is written unless every artifact verifies, and the mirror is
+/// verified again from disk once written. Revocation checks need their CRLs
+/// or OCSP responders to be reachable from wherever the mirror is used.
+pub async fn sync(
+    transport: &dyn Transport,
+    source: &Path,
+    dest: &Path,
+    roots: &TrustRoots,
+    store: &TrustStore,
+    pipeline: &Pipeline,
+    concurrency: usize,
+) -> Result<MirrorIndex> {
+    let manifest = Manifest::load(source)?;
+    let base: PathBuf = source.parent().map(Path::to_path_buf).unwrap_or_default();
+    let results =
+        manifest::verify_manifest(transport, source, roots, pipeline, concurrency).await?;
+    let failures: Vec<String> = results
+        .iter()
+        .filter_map(|result| {
+            let e = result.outcome.as_ref().err()?;
+            Some(format!("{}: {}", result.url, e.describe()))
+        })
+        .collect();
+    if !failures.is_empty() {
+        return Err(anyhow!(
+            "Not mirroring; {} artifact(s) failed verification:\n{}",
+            failures.len(),
+            failures.join("\n")
+        ));
+    }
+
+    let artifacts = dest.join(ARTIFACTS);
+    if artifacts.exists() {
+        fs::remove_dir_all(&artifacts)
+            .with_context(|| format!("Cannot remove {}", artifacts.display()))?;
+    }
+    fs::create_dir_all(dest).with_context(|| format!("Cannot create {}", dest.display()))?;
+    let mut mirrored = Manifest {
+        artifacts: Vec::with_capacity(results.len()),
+    };
+    let mut index = MirrorIndex {
+        sget_version: env!("CARGO_PKG_VERSION").to_string(),
+        synced: Utc::now(),
+        source: source.display().to_string(),
+        manifest: MANIFEST.to_string(),
+        trust_bundle: TRUST_BUNDLE.to_string(),
+        trust_sha256: String::new(),
+        artifacts: Vec::with_capacity(results.len()),
+    };
+    for (i, (entry, result)) in manifest.artifacts.iter().zip(&results).enumerate() {
+        let material = result
+            .material
+            .as_ref()
+            .ok_or_else(|| anyhow!("No material for {}", entry.url))?;
+        // Artifacts signed as plaintext are verified decrypted; the mirror
+        // keeps them encrypted.
+        let ciphertext = match &entry.age {
+            Some(age) if age.plaintext_sha256.is_some() => {
+                Some(fetch(transport, &entry.url, &base).await?)
+            }
+            _ => None,
+        };
+        let writer = Writer {
+            root: dest,
+            dir: format!("{}/{}", ARTIFACTS, i),
+        };
+        let data = ciphertext.as_deref().unwrap_or(&material.data);
+        let entry = write_entry(&writer, entry, material, data)?;
+        index.artifacts.push(MirroredArtifact {
+            url: result.url.clone(),
+            sha256: material.digest(),
+            path: entry.url.clone(),
+        });
+        mirrored.artifacts.push(entry);
+    }
+    let path = dest.join(MANIFEST);
+    fs::write(&path, serde_yaml::to_string(&mirrored)?)
+        .with_context(|| format!("Cannot write {}", path.display()))?;
+    index.trust_sha256 = store.export(&dest.join(TRUST_BUNDLE))?;
+    let path = dest.join(INDEX);
+    fs::write(&path, serde_json::to_vec_pretty(&index)?)
+        .with_context(|| format!("Cannot write {}", path.display()))?;
+
+    let results = manifest::verify_manifest(
+        transport,
+        &dest.join(MANIFEST),
+        roots,
+        pipeline,
+        concurrency,
+    )
+    .await?;
+    for result in &results {
+        if let Err(e) = &result.outcome {
+            return Err(anyhow!(
+                "Mirrored {} does not verify: {}",
+                result.url,
+                e.describe()
+            ));
+        }
+    }
+    Ok(index)
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("mirror")
+        .about("Mirror verified artifacts for hosts without network access")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("sync")
+                .about("Fetch and verify the artifacts of a manifest, with their signature material and the trust store, into a directory")
+                .arg(
+                    Arg::new("manifest")
+                        .long("manifest")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .required(true)
+                        .about("YAML manifest listing the artifacts to mirror"),
+                )
+                .arg(
+                    Arg::new("dest")
+                        .long("dest")
+                        .value_name("DIR")
+                        .takes_value(true)
+                        .required(true)
+                        .about("Directory to write the mirror to; its previous artifacts are replaced"),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .value_name("N")
+                        .takes_value(true)
+                        .default_value("8")
+                        .about("How many artifacts to fetch and verify at once"),
+                ),
+        )
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("sync", m)) => {
+            let source = Path::new(m.value_of("manifest").unwrap_or_default());
+            let dest = Path::new(m.value_of("dest").unwrap_or_default());
+            let jobs = match m.value_of("jobs") {
+                Some(jobs) => jobs
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid number of jobs {}", jobs))?,
+                None => manifest::DEFAULT_CONCURRENCY,
+            };
+            let store = TrustStore::open()?;
+            let roots = TrustRoots::load(&store)?;
+            let transport = transport::default_transport();
+            let index = sync(
+                transport.as_ref(),
+                source,
+                dest,
+                &roots,
+                &store,
+                &Pipeline::default(),
+                jobs,
+            )
+            .await?;
+            for artifact in &index.artifacts {
+                println!("OK\t{}\t{}", artifact.url, artifact.path);
+            }
+            println!(
+                "Mirrored {} artifact(s) to {}",
+                index.artifacts.len(),
+                dest.display()
+            );
+            println!("To verify from the mirror on a disconnected host:");
+            println!(
+                "  sget trust import {} --sha256 {}",
+                dest.join(TRUST_BUNDLE).display(),
+                index.trust_sha256
+            );
+            println!("  sget verify --manifest {}", dest.join(MANIFEST).display());
+            Ok(())
+        }
+        _ => Err(anyhow!("Unknown mirror subcommand")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use crate::storage::MemoryStorage;
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn sync_mirror() {
+        let upstream = tempfile::tempdir().expect("Cannot create temp dir");
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        fs::write(
+            upstream.path().join("sget.pub"),
+            key.public_key().to_pem().expect("Cannot encode key"),
+        )
+        .expect("Cannot write key");
+        let data = b"echo mirrored";
+        fs::write(upstream.path().join("install.sh"), data).expect("Cannot write artifact");
+        fs::write(
+            upstream.path().join("install.sh.sig"),
+            base64::encode(key.sign(data)),
+        )
+        .expect("Cannot write signature");
+        let source = upstream.path().join("targets.yaml");
+        fs::write(
+            &source,
+            format!(
+                "artifacts:\n- url: install.sh\n  key: sget.pub\n  sha256: {}\n",
+                hex::encode(Sha256::digest(data))
+            ),
+        )
+        .expect("Cannot write manifest");
+
+        let dest = tempfile::tempdir().expect("Cannot create temp dir");
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let store = TrustStore::with_storage(Arc::new(MemoryStorage::default()));
+        let transport = transport::default_transport();
+        let sync = |dest: PathBuf| {
+            let (roots, store, transport, source) = (&roots, &store, &transport, &source);
+            async move {
+                sync(
+                    transport.as_ref(),
+                    source,
+                    &dest,
+                    roots,
+                    store,
+                    &Pipeline::default(),
+                    2,
+                )
+                .await
+            }
+        };
+        let index = sync(dest.path().to_path_buf()).await.expect("Cannot sync");
+        assert_eq!(index.artifacts[0].path, "artifacts/0/install.sh");
+
+        // The mirror verifies with the upstream directory gone.
+        upstream.close().expect("Cannot remove upstream");
+        let results = manifest::verify_manifest(
+            transport.as_ref(),
+            &dest.path().join(MANIFEST),
+            &roots,
+            &Pipeline::default(),
+            2,
+        )
+        .await
+        .expect("Cannot verify mirror");
+        assert!(results[0].outcome.is_ok());
+        assert_eq!(
+            fs::read(dest.path().join("artifacts/0/install.sh")).expect("Cannot read artifact"),
+            data
+        );
+        assert!(dest.path().join(TRUST_BUNDLE).exists());
+
+        // Nothing is mirrored unless everything verifies.
+        let failed = tempfile::tempdir().expect("Cannot create temp dir");
+        assert!(sync(failed.path().to_path_buf()).await.is_err());
+        assert!(!failed.path().join(MANIFEST).exists());
+    }
+}
@@ -0,0 +1,568 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use ring::signature::{self as ring_signature, UnparsedPublicKey};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+#[cfg(feature = "native")]
+use std::path::{Path, PathBuf};
+
+use crate::algorithms::{key_algorithm, AlgorithmPolicy, HashAlgorithm};
+use crate::error::SgetError;
+use crate::pipeline::{Pipeline, Stage, StageContext};
+use crate::verify_core::{parse_certificate, verify_chain, Signer};
+
+/// Artifact type of notation signature manifests.
+pub const SIGNATURE_ARTIFACT_TYPE: &str = "application/vnd.cncf.notary.signature";
+
+/// Media type of JWS signature envelopes. COSE envelopes are not supported.
+pub const JWS_MEDIA_TYPE: &str = "application/jose+json";
+
+const PAYLOAD_CONTENT_TYPE: &str = "application/vnd.cncf.notary.payload.v1+json";
+
+const SIGNING_SCHEME: &str = "io.cncf.notary.signingScheme";
+const SIGNING_TIME: &str = "io.cncf.notary.signingTime";
+const EXPIRY: &str = "io.cncf.notary.expiry";
+
+/// The signing schemes, and the type of trust store their chains end in.
+const SCHEMES: [(&str, &str); 2] = [
+    ("notary.x509", "ca"),
+    ("notary.x509.signingAuthority", "signingAuthority"),
+];
+
+/// A notation trust policy document, `trustpolicy.json`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustPolicyDocument {
+    pub version: String,
+    pub trust_policies: Vec<TrustPolicy>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustPolicy {
+    pub name: String,
+    /// `<registry>/<repository>` scopes, or `*` for all of them.
+    pub registry_scopes: Vec<String>,
+    pub signature_verification: SignatureVerification,
+    /// Trust stores as `<type>:<name>`, e.g. `ca:acme-rockets`.
+    #[serde(default)]
+    pub trust_stores: Vec<String>,
+    /// `x509.subject: <DN>` identities, or `*` for any.
+    #[serde(default)]
+    pub trusted_identities: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SignatureVerification {
+    /// `strict`, `permissive`, `audit` or `skip`.
+    pub level: String,
+    /// Per-check actions overriding the level's, e.g. `expiry: log`.
+    #[serde(default, rename = "override")]
+    pub overrides: HashMap<String, String>,
+}
+
+impl SignatureVerification {
+    /// Whether signature expiry is enforced. sget always enforces integrity and
+    /// authenticity, so the levels and overrides that only log them are
+    /// rejected.
+    fn enforces_expiry(&self) -> Result<bool> {
+        for check in ["integrity", "authenticity"] {
+            match self.overrides.get(check).map(String::as_str) {
+                None | Some("enforce") => {}
+                Some(action) => return Err(anyhow!("Cannot {} {} failures", action, check)),
+            }
+        }
+        let default = match self.level.as_str() {
+            "strict" => true,
+            "permissive" => false,
+            other => {
+                return Err(anyhow!(
+                    "Verification level {} does not enforce signatures",
+                    other
+                ))
+            }
+        };
+        Ok(match self.overrides.get("expiry").map(String::as_str) {
+            Some(action) => action == "enforce",
+            None => default,
+        })
+    }
+}
+
+/// A trust policy and the certificates of the trust stores it names.
+pub struct NotationTrust {
+    pub policy: TrustPolicyDocument,
+    /// DER encoded certificates by trust store, as `<type>:<name>`.
+    pub stores: HashMap<String, Vec<Vec<u8>>>,
+    /// The algorithms signatures and certificate chains may be made with.
+    pub algorithms: AlgorithmPolicy,
+}
+
+impl NotationTrust {
+    /// The policy for artifacts in `scope`, `<registry>/<repository>`. A policy
+    /// naming the scope takes precedence over a `*` policy.
+    fn policy_for(&self, scope: &str) -> Option<&TrustPolicy> {
+        let policies = &self.policy.trust_policies;
+        policies
+            .iter()
+            .find(|policy| policy.registry_scopes.iter().any(|s| s == scope))
+            .or_else(|| {
+                policies
+                    .iter()
+                    .find(|policy| policy.registry_scopes.iter().any(|s| s == "*"))
+            })
+    }
+}
+
+#[cfg(feature = "native")]
+impl NotationTrust {
+    /// The notation configuration directory: `NOTATION_CONFIG`, or `notation`
+    /// in the platform config directory.
+    pub fn config_dir() -> Result<PathBuf> {
+        if let Some(dir) = std::env::var_os("NOTATION_CONFIG") {
+            return Ok(PathBuf::from(dir));
+        }
+        dirs::config_dir()
+            .map(|dir| dir.join("notation"))
+            .ok_or_else(|| anyhow!("Cannot determine config directory; set NOTATION_CONFIG"))
+    }
+
+    /// Load `trustpolicy.json` and the x509 trust stores under `dir`, laid out
+    /// as `truststore/x509/<type>/<name>/*`.
+    pub fn load(dir: &Path) -> Result<Self> {
+        use anyhow::Context;
+
+        let path = dir.join("trustpolicy.json");
+        let raw =
+            std::fs::read(&path).with_context(|| format!("Cannot read {}", path.display()))?;
+        let policy = serde_json::from_slice(&raw)
+            .with_context(|| format!("Invalid trust policy {}", path.display()))?;
+        let mut stores = HashMap::new();
+        let x509 = dir.join("truststore").join("x509");
+        for kind in std::fs::read_dir(&x509).into_iter().flatten().flatten() {
+            for store in std::fs::read_dir(kind.path())?.flatten() {
+                let mut certificates = Vec::new();
+                for file in std::fs::read_dir(store.path())?.flatten() {
+                    let raw = std::fs::read(file.path())?;
+                    match crate::verify_core::pem_certificates(&raw) {
+                        Ok(pem) => certificates.extend(pem),
+                        Err(_) => certificates.push(raw),
+                    }
+                }
+                let name = format!(
+                    "{}:{}",
+                    kind.file_name().to_string_lossy(),
+                    store.file_name().to_string_lossy()
+                );
+                stores.insert(name, certificates);
+            }
+        }
+        Ok(NotationTrust {
+            policy,
+            stores,
+            algorithms: AlgorithmPolicy::default(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Jws {
+    payload: String,
+    protected: String,
+    header: JwsHeader,
+    signature: String,
+}
+
+#[derive(Deserialize)]
+struct JwsHeader {
+    /// The base64 encoded DER certificate chain, leaf first.
+    x5c: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ProtectedHeader {
+    alg: String,
+    #[serde(default)]
+    crit: Vec<String>,
+    cty: String,
+    #[serde(rename = "io.cncf.notary.signingScheme")]
+    signing_scheme: String,
+    #[serde(rename = "io.cncf.notary.expiry")]
+    expiry: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Payload {
+    target_artifact: TargetArtifact,
+}
+
+#[derive(Deserialize)]
+struct TargetArtifact {
+    digest: String,
+}
+
+fn decode(encoded: &str) -> Result<Vec<u8>> {
+    Ok(base64::decode_config(encoded, base64::URL_SAFE_NO_PAD)?)
+}
+
+/// Whether the subject of `leaf`, as in `C=US, ST=WA, O=Example`, has every
+/// attribute of the trusted identity's distinguished name.
+fn subject_matches(subject: &str, identity: &str) -> bool {
+    let attributes = |dn: &str| -> Vec<(String, String)> {
+        dn.split(',')
+            .filter_map(|attribute| attribute.split_once('='))
+            .map(|(key, value)| (key.trim().to_ascii_uppercase(), value.trim().to_string()))
+            .collect()
+    };
+    let subject = attributes(subject);
+    let identity = attributes(identity);
+    !identity.is_empty() && identity.iter().all(|attribute| subject.contains(attribute))
+}
+
+/// Verify one JWS envelope over the manifest with `digest`, `sha256:<hex>`.
+fn verify_envelope(
+    raw: &[u8],
+    digest: &str,
+    policy: &TrustPolicy,
+    enforce_expiry: bool,
+    trust: &NotationTrust,
+    pipeline: &Pipeline,
+    now: DateTime<Utc>,
+) -> crate::error::Result<Signer> {
+    let invalid = SgetError::InvalidMaterial;
+    let jws: Jws = serde_json::from_slice(raw).map_err(|e| invalid(e.into()))?;
+    let protected = decode(&jws.protected).map_err(invalid)?;
+    let header: ProtectedHeader =
+        serde_json::from_slice(&protected).map_err(|e| invalid(e.into()))?;
+    let fields: HashMap<String, Value> =
+        serde_json::from_slice(&protected).map_err(|e| invalid(e.into()))?;
+    for name in &header.crit {
+        if ![SIGNING_SCHEME, EXPIRY, SIGNING_TIME].contains(&name.as_str())
+            || !fields.contains_key(name)
+        {
+            return Err(invalid(anyhow!("Unsupported critical header {}", name)));
+        }
+    }
+    if header.cty != PAYLOAD_CONTENT_TYPE {
+        return Err(invalid(anyhow!("Unexpected payload type {}", header.cty)));
+    }
+    let store_type = SCHEMES
+        .iter()
+        .find(|(scheme, _)| *scheme == header.signing_scheme)
+        .map(|(_, store_type)| *store_type)
+        .ok_or_else(|| {
+            invalid(anyhow!(
+                "Unsupported signing scheme {}",
+                header.signing_scheme
+            ))
+        })?;
+
+    let chain = jws
+        .header
+        .x5c
+        .iter()
+        .map(base64::decode)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(e.into()))?;
+    let leaf_der = chain
+        .first()
+        .ok_or_else(|| invalid(anyhow!("Signature has no certificate")))?;
+    let leaf = parse_certificate(leaf_der)?;
+    let subject = leaf.subject().to_string();
+    let roots: Vec<Vec<u8>> = policy
+        .trust_stores
+        .iter()
+        .filter(|store| store.split(':').next() == Some(store_type))
+        .filter_map(|store| trust.stores.get(store))
+        .flatten()
+        .cloned()
+        .collect();
+    let context = StageContext {
+        stage: Stage::ChainValidation,
+        digest: digest.trim_start_matches("sha256:"),
+        identity: Some(&subject),
+    };
+    pipeline.run(context, || {
+        verify_chain(&leaf, &chain[1..], &roots, &trust.algorithms)?;
+        let validity = leaf.validity();
+        if now.timestamp() < validity.not_before.timestamp()
+            || now.timestamp() > validity.not_after.timestamp()
+        {
+            return Err(SgetError::UntrustedCertificate(
+                "Signing certificate is not valid now".to_string(),
+            ));
+        }
+        Ok(())
+    })?;
+
+    let (algorithm, hash): (&dyn ring_signature::VerificationAlgorithm, _) =
+        match header.alg.as_str() {
+            "PS256" => (
+                &ring_signature::RSA_PSS_2048_8192_SHA256,
+                HashAlgorithm::Sha256,
+            ),
+            "PS384" => (
+                &ring_signature::RSA_PSS_2048_8192_SHA384,
+                HashAlgorithm::Sha384,
+            ),
+            "PS512" => (
+                &ring_signature::RSA_PSS_2048_8192_SHA512,
+                HashAlgorithm::Sha512,
+            ),
+            "ES256" => (
+                &ring_signature::ECDSA_P256_SHA256_FIXED,
+                HashAlgorithm::Sha256,
+            ),
+            "ES384" => (
+                &ring_signature::ECDSA_P384_SHA384_FIXED,
+                HashAlgorithm::Sha384,
+            ),
+            other => {
+                return Err(invalid(anyhow!(
+                    "Unsupported signature algorithm {}",
+                    other
+                )))
+            }
+        };
+    let (key_type, key_bits) = key_algorithm(leaf.public_key())?;
+    trust.algorithms.check(key_type, hash, key_bits)?;
+    let signing_input = format!("{}.{}", jws.protected, jws.payload);
+    let signature = decode(&jws.signature).map_err(invalid)?;
+    UnparsedPublicKey::new(algorithm, &leaf.public_key().subject_public_key.data)
+        .verify(signing_input.as_bytes(), &signature)
+        .map_err(|_| SgetError::InvalidSignature(anyhow!("Invalid notation signature")))?;
+
+    let payload: Payload = serde_json::from_slice(&decode(&jws.payload).map_err(invalid)?)
+        .map_err(|e| invalid(e.into()))?;
+    if payload.target_artifact.digest != digest {
+        return Err(SgetError::InvalidSignature(anyhow!(
+            "Signature is for {}",
+            payload.target_artifact.digest
+        )));
+    }
+    if let Some(expiry) = header.expiry {
+        if enforce_expiry && now > expiry {
+            return Err(SgetError::InvalidSignature(anyhow!(
+                "Signature expired at {}",
+                expiry
+            )));
+        }
+    }
+    let trusted = policy.trusted_identities.iter().any(|identity| {
+        identity == "*"
+            || identity
+                .strip_prefix("x509.subject:")
+                .is_some_and(|dn| subject_matches(&subject, dn))
+    });
+    if !trusted {
+        return Err(SgetError::UntrustedSigner(format!(
+            "{} is not a trusted identity of notation policy {}",
+            subject, policy.name
+        )));
+    }
+    Ok(Signer {
+        key_id: hex::encode(Sha256::digest(leaf.public_key().raw)),
+        identity: Some(subject),
+        issuer: None,
+        integrated_time: None,
+    })
+}
+
+/// Verify the notation signatures of the manifest with `digest`,
+/// `sha256:<hex>`, in `scope`, `<registry>/<repository>`, against the trust
+/// policy for the scope. One valid signature is enough; if none is, the error
+/// for the last one is returned.
+pub fn verify_notation(
+    envelopes: &[Vec<u8>],
+    digest: &str,
+    scope: &str,
+    trust: &NotationTrust,
+    pipeline: &Pipeline,
+    now: DateTime<Utc>,
+) -> crate::error::Result<Signer> {
+    if trust.policy.version != "1.0" {
+        return Err(SgetError::InvalidPolicy(format!(
+            "Unsupported trust policy version {}",
+            trust.policy.version
+        )));
+    }
+    let policy = trust.policy_for(scope).ok_or_else(|| {
+        SgetError::InvalidPolicy(format!("No notation trust policy applies to {}", scope))
+    })?;
+    let enforce_expiry = policy
+        .signature_verification
+        .enforces_expiry()
+        .map_err(|e| SgetError::InvalidPolicy(format!("{}: {}", policy.name, e)))?;
+    let mut outcome = Err(SgetError::InvalidMaterial(anyhow!(
+        "No notation signatures found"
+    )));
+    for envelope in envelopes {
+        outcome = verify_envelope(
+            envelope,
+            digest,
+            policy,
+            enforce_expiry,
+            trust,
+            pipeline,
+            now,
+        );
+        if outcome.is_ok() {
+            break;
+        }
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_core::pem_certificates;
+    use ecdsa::signature::Signer as _;
+    use p256::pkcs8::FromPrivateKey;
+    use serde_json::json;
+
+    const CA: &str = include_str!("../tests/test_data/notation/ca.pem");
+    const LEAF: &str = include_str!("../tests/test_data/notation/leaf.pem");
+    const LEAF_KEY: &str = include_str!("../tests/test_data/notation/leaf.key");
+
+    fn sign(digest: &str, expiry: &str) -> Vec<u8> {
+        let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+        let protected = encode(
+            json!({
+                "alg": "ES256",
+                "crit": [SIGNING_SCHEME, EXPIRY],
+                "cty": PAYLOAD_CONTENT_TYPE,
+                SIGNING_SCHEME: "notary.x509",
+                SIGNING_TIME: "2023-01-01T00:00:00Z",
+                EXPIRY: expiry,
+            })
+            .to_string()
+            .as_bytes(),
+        );
+        let payload = encode(
+            json!({
+                "targetArtifact": {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "digest": digest,
+                    "size": 528,
+                },
+            })
+            .to_string()
+            .as_bytes(),
+        );
+        let key = p256::SecretKey::from_pkcs8_pem(LEAF_KEY).expect("Invalid key");
+        let signature: p256::ecdsa::Signature = p256::ecdsa::SigningKey::from(&key)
+            .sign(format!("{}.{}", protected, payload).as_bytes());
+        let chain: Vec<String> = [LEAF, CA]
+            .iter()
+            .map(|pem| base64::encode(&pem_certificates(pem.as_bytes()).expect("No cert")[0]))
+            .collect();
+        json!({
+            "payload": payload,
+            "protected": protected,
+            "header": { "x5c": chain, "io.cncf.notary.signingAgent": "notation/1.0.0" },
+            "signature": encode(signature.as_ref()),
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn trust(identity: &str) -> NotationTrust {
+        let policy = json!({
+            "version": "1.0",
+            "trustPolicies": [
+                {
+                    "name": "scripts",
+                    "registryScopes": ["example.com/scripts"],
+                    "signatureVerification": { "level": "strict" },
+                    "trustStores": ["ca:example"],
+                    "trustedIdentities": [identity],
+                },
+                {
+                    "name": "audit",
+                    "registryScopes": ["*"],
+                    "signatureVerification": { "level": "audit" },
+                },
+            ],
+        });
+        let mut stores = HashMap::new();
+        stores.insert(
+            "ca:example".to_string(),
+            pem_certificates(CA.as_bytes()).expect("No CA"),
+        );
+        NotationTrust {
+            policy: serde_json::from_value(policy).expect("Invalid trust policy"),
+            stores,
+            algorithms: AlgorithmPolicy::default(),
+        }
+    }
+
+    #[test]
+    fn verify_jws_signature() {
+        let digest = format!("sha256:{}", "ab".repeat(32));
+        let now = Utc::now();
+        let trust = trust("x509.subject: C=US, ST=WA, O=Example");
+        let verify = |envelopes: &[Vec<u8>], digest: &str, scope: &str, trust: &NotationTrust| {
+            verify_notation(envelopes, digest, scope, trust, &Pipeline::default(), now)
+        };
+        let good = sign(&digest, "2100-01-01T00:00:00Z");
+
+        let signer = verify(
+            std::slice::from_ref(&good),
+            &digest,
+            "example.com/scripts",
+            &trust,
+        )
+        .expect("Cannot verify signature");
+        assert_eq!(
+            signer.identity.as_deref(),
+            Some("C=US, ST=WA, O=Example, CN=release")
+        );
+        let expired = sign(&digest, "2020-01-01T00:00:00Z");
+        assert!(verify(
+            &[expired.clone(), good],
+            &digest,
+            "example.com/scripts",
+            &trust
+        )
+        .is_ok());
+        assert!(matches!(
+            verify(&[expired], &digest, "example.com/scripts", &trust),
+            Err(SgetError::InvalidSignature(_))
+        ));
+        let other = sign(
+            &format!("sha256:{}", "cd".repeat(32)),
+            "2100-01-01T00:00:00Z",
+        );
+        assert!(matches!(
+            verify(
+                std::slice::from_ref(&other),
+                &digest,
+                "example.com/scripts",
+                &trust
+            ),
+            Err(SgetError::InvalidSignature(_))
+        ));
+        assert!(matches!(
+            verify(
+                std::slice::from_ref(&other),
+                &digest,
+                "example.com/other",
+                &trust
+            ),
+            Err(SgetError::InvalidPolicy(_))
+        ));
+        assert!(matches!(
+            verify(
+                &[other],
+                &format!("sha256:{}", "cd".repeat(32)),
+                "example.com/scripts",
+                &self::trust("x509.subject: C=US, ST=WA, O=Someone Else"),
+            ),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+    }
+}
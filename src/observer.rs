@@ -0,0 +1,200 @@
+use async_trait::async_trait;
+use std::process::ExitStatus;
+use std::sync::Arc;
+
+use crate::error::SgetError;
+use crate::pipeline::{Stage, StageContext, StageHook};
+use crate::transport::{Request, Response, Transport};
+use crate::verify::Signer;
+
+/// What an [`SgetClient`](crate::SgetClient) is doing, as reported to an
+/// [`Observer`].
+#[derive(Debug)]
+pub enum ProgressEvent<'a> {
+    /// A remote artifact or piece of signature material is being downloaded.
+    DownloadStarted { location: &'a str },
+    /// More of a download has arrived. `total` is the expected size, if known.
+    DownloadProgress {
+        location: &'a str,
+        received: u64,
+        total: Option<u64>,
+    },
+    /// A download completed with an HTTP status, successful or not.
+    DownloadFinished {
+        location: &'a str,
+        status: u16,
+        size: u64,
+    },
+    /// A download failed without a response.
+    DownloadFailed {
+        location: &'a str,
+        error: &'a anyhow::Error,
+    },
+    /// A verification stage ran, see [`Stage`].
+    StageFinished {
+        stage: Stage,
+        digest: &'a str,
+        identity: Option<&'a str>,
+        outcome: Result<(), &'a SgetError>,
+    },
+    /// An artifact was verified, or rejected.
+    Verified {
+        url: &'a str,
+        outcome: Result<&'a Signer, &'a SgetError>,
+    },
+    /// A verified script is about to run.
+    ExecutionStarted { source: &'a str },
+    /// A script exited, or could not be run when `status` is `None`.
+    ExecutionFinished {
+        source: &'a str,
+        status: Option<ExitStatus>,
+    },
+}
+
+/// Receives [`ProgressEvent`]s, e.g. to render progress in a GUI or TUI.
+///
+/// Events are delivered synchronously from the task doing the work, so
+/// observers should return quickly.
+pub trait Observer: Send + Sync {
+    fn event(&self, event: &ProgressEvent);
+}
+
+/// Reports the downloads made through `inner`.
+pub(crate) struct ObservedTransport<'a> {
+    pub inner: &'a dyn Transport,
+    pub observer: &'a dyn Observer,
+}
+
+#[async_trait]
+impl Transport for ObservedTransport<'_> {
+    async fn send(&self, request: Request<Vec<u8>>) -> anyhow::Result<Response<Vec<u8>>> {
+        let location = request.uri().to_string();
+        let observer = self.observer;
+        observer.event(&ProgressEvent::DownloadStarted {
+            location: &location,
+        });
+        let progress = |received, total| {
+            observer.event(&ProgressEvent::DownloadProgress {
+                location: &location,
+                received,
+                total,
+            })
+        };
+        let result = self.inner.send_with_progress(request, &progress).await;
+        match &result {
+            Ok(response) => observer.event(&ProgressEvent::DownloadFinished {
+                location: &location,
+                status: response.status().as_u16(),
+                size: response.body().len() as u64,
+            }),
+            Err(error) => observer.event(&ProgressEvent::DownloadFailed {
+                location: &location,
+                error,
+            }),
+        }
+        result
+    }
+}
+
+/// Reports the outcome of each verification stage.
+pub(crate) struct ObservedStages(pub Arc<dyn Observer>);
+
+impl StageHook for ObservedStages {
+    fn after(&self, context: &StageContext, outcome: Result<(), &SgetError>) -> anyhow::Result<()> {
+        self.0.event(&ProgressEvent::StageFinished {
+            stage: context.stage,
+            digest: context.digest,
+            identity: context.identity,
+            outcome,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{SgetClient, Verifier};
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use crate::manifest::ManifestEntry;
+    use sha2::{Digest, Sha256};
+    use std::sync::Mutex;
+
+    /// Serves the same signed script for every artifact and signature URL.
+    struct Script {
+        data: Vec<u8>,
+        signature: Vec<u8>,
+        key: Vec<u8>,
+    }
+
+    #[async_trait]
+    impl Transport for Script {
+        async fn send(&self, request: Request<Vec<u8>>) -> anyhow::Result<Response<Vec<u8>>> {
+            let path = request.uri().path().to_string();
+            let body = match path.rsplit('.').next() {
+                Some("sig") => self.signature.clone(),
+                Some("pub") => self.key.clone(),
+                _ => self.data.clone(),
+            };
+            Ok(Response::new(body))
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Observer for Recorder {
+        fn event(&self, event: &ProgressEvent) {
+            let name = match event {
+                ProgressEvent::DownloadStarted { location } => format!("start {}", location),
+                ProgressEvent::DownloadProgress { .. } => return,
+                ProgressEvent::DownloadFinished { location, .. } => format!("done {}", location),
+                ProgressEvent::StageFinished { stage, outcome, .. } => {
+                    format!("{} ok={}", stage, outcome.is_ok())
+                }
+                ProgressEvent::Verified { url, outcome } => {
+                    format!("verified {} ok={}", url, outcome.is_ok())
+                }
+                other => format!("{:?}", other),
+            };
+            self.0.lock().expect("Poisoned").push(name);
+        }
+    }
+
+    #[tokio::test]
+    async fn observe_fetch() {
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let data = b"echo hi".to_vec();
+        let script = Script {
+            signature: base64::encode(key.sign(&data)).into_bytes(),
+            key: key
+                .public_key()
+                .to_pem()
+                .expect("Cannot encode key")
+                .into_bytes(),
+            data,
+        };
+        let recorder = Arc::new(Recorder::default());
+        let client = SgetClient::new(Verifier::sigstore().expect("Cannot load roots"))
+            .with_transport(Arc::new(script))
+            .with_observer(recorder.clone());
+        let mut entry = ManifestEntry::new("https://example.com/hi.sh");
+        entry.key = Some("https://example.com/sget.pub".to_string());
+        entry.sha256 = Some(hex::encode(Sha256::digest(b"echo hi")));
+        client.fetch(&entry).await.expect("Cannot verify");
+
+        assert_eq!(
+            recorder.0.lock().expect("Poisoned").as_slice(),
+            [
+                "start https://example.com/hi.sh",
+                "done https://example.com/hi.sh",
+                "start https://example.com/hi.sh.sig",
+                "done https://example.com/hi.sh.sig",
+                "start https://example.com/sget.pub",
+                "done https://example.com/sget.pub",
+                "digest check ok=true",
+                "verified https://example.com/hi.sh ok=true",
+            ]
+        );
+    }
+}
@@ -0,0 +1,1156 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use ecdsa::hazmat::VerifyPrimitive;
+use ring::signature::{
+    RsaPublicKeyComponents, RSA_PKCS1_2048_8192_SHA256, RSA_PKCS1_2048_8192_SHA512,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::algorithms::{self, AlgorithmPolicy, SignatureAlgorithm};
+use crate::error::SgetError;
+use crate::verify_core::Signer;
+
+const ARMOR_BEGIN: &str = "-----BEGIN PGP ";
+const ARMOR_END: &str = "-----END PGP ";
+
+const TAG_SIGNATURE: u8 = 2;
+const TAG_PUBLIC_KEY: u8 = 6;
+const TAG_USER_ID: u8 = 13;
+const TAG_PUBLIC_SUBKEY: u8 = 14;
+
+const SIG_BINARY: u8 = 0x00;
+const SIG_TEXT: u8 = 0x01;
+const SIG_CERTIFICATION_GENERIC: u8 = 0x10;
+const SIG_CERTIFICATION_POSITIVE: u8 = 0x13;
+const SIG_SUBKEY_BINDING: u8 = 0x18;
+const SIG_PRIMARY_KEY_BINDING: u8 = 0x19;
+const SIG_DIRECT_KEY: u8 = 0x1f;
+const SIG_KEY_REVOCATION: u8 = 0x20;
+const SIG_SUBKEY_REVOCATION: u8 = 0x28;
+
+const SUBPACKET_CREATION_TIME: u8 = 2;
+const SUBPACKET_KEY_EXPIRATION: u8 = 9;
+const SUBPACKET_ISSUER: u8 = 16;
+const SUBPACKET_KEY_FLAGS: u8 = 27;
+const SUBPACKET_EMBEDDED_SIGNATURE: u8 = 32;
+const SUBPACKET_ISSUER_FINGERPRINT: u8 = 33;
+
+const KEY_FLAG_SIGN: u8 = 0x02;
+
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_ED25519: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xda, 0x47, 0x0f, 0x01];
+
+/// Where a manifest entry's OpenPGP keys are, and which of them may sign it.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PgpRequirements {
+    /// Location of the ASCII armored or binary public keys, as written by
+    /// `gpg --export [--armor]`.
+    pub keyring: String,
+    /// Fingerprints of the primary keys allowed to sign the artifact. Empty
+    /// allows every key in the keyring.
+    #[serde(default)]
+    pub fingerprints: Vec<String>,
+}
+
+/// An OpenPGP packet.
+struct Packet<'a> {
+    tag: u8,
+    body: &'a [u8],
+}
+
+/// Reads the fields of a packet body.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.data.len() {
+            return Err(anyhow!("Truncated OpenPGP packet"));
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn number(&mut self, n: usize) -> Result<usize> {
+        Ok(self
+            .take(n)?
+            .iter()
+            .fold(0, |acc, byte| (acc << 8) | usize::from(*byte)))
+    }
+
+    /// A multiprecision integer: a 16-bit bit count and the big-endian value.
+    fn mpi(&mut self) -> Result<&'a [u8]> {
+        let bits = self.number(2)?;
+        self.take(bits.div_ceil(8))
+    }
+}
+
+/// Decode ASCII armor, leaving binary input as it is. Armored input may hold
+/// several blocks, e.g. keys exported one after the other.
+fn dearmor(raw: &[u8]) -> Result<Vec<u8>> {
+    let text = match std::str::from_utf8(raw) {
+        Ok(text) if text.trim_start().starts_with(ARMOR_BEGIN) => text,
+        _ => return Ok(raw.to_vec()),
+    };
+    let mut binary = Vec::new();
+    let mut lines = text.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if !line.starts_with(ARMOR_BEGIN) {
+            continue;
+        }
+        // Armor headers end at the first blank line.
+        for line in lines.by_ref() {
+            if line.is_empty() {
+                break;
+            }
+        }
+        let mut body = String::new();
+        for line in lines.by_ref() {
+            if line.starts_with(ARMOR_END) {
+                break;
+            }
+            // The optional CRC-24 checksum line.
+            if !line.starts_with('=') {
+                body.push_str(line);
+            }
+        }
+        binary.extend(base64::decode(&body)?);
+    }
+    Ok(binary)
+}
+
+/// Split binary OpenPGP data into packets, in either header format.
+fn packets(mut data: &[u8]) -> Result<Vec<Packet<'_>>> {
+    let mut packets = Vec::new();
+    while !data.is_empty() {
+        let mut reader = Reader { data };
+        let header = reader.byte()?;
+        if header & 0x80 == 0 {
+            return Err(anyhow!("Invalid OpenPGP packet header"));
+        }
+        let (tag, length) = if header & 0x40 != 0 {
+            let length = match reader.byte()? {
+                first @ 0..=191 => usize::from(first),
+                first @ 192..=223 => ((usize::from(first) - 192) << 8) + reader.number(1)? + 192,
+                255 => reader.number(4)?,
+                _ => return Err(anyhow!("Partial OpenPGP packet lengths are not supported")),
+            };
+            (header & 0x3f, length)
+        } else {
+            let length = match header & 0x03 {
+                0 => reader.number(1)?,
+                1 => reader.number(2)?,
+                2 => reader.number(4)?,
+                _ => reader.data.len(),
+            };
+            ((header >> 2) & 0x0f, length)
+        };
+        let body = reader.take(length)?;
+        packets.push(Packet { tag, body });
+        data = reader.data;
+    }
+    Ok(packets)
+}
+
+/// A hash algorithm signatures may be made with. SHA-1 and weaker ones are
+/// rejected.
+#[derive(Clone, Copy)]
+enum HashAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    fn from_id(id: u8) -> Result<Self> {
+        match id {
+            8 => Ok(HashAlgorithm::Sha256),
+            10 => Ok(HashAlgorithm::Sha512),
+            1..=3 => Err(anyhow!(
+                "OpenPGP signatures with MD5, SHA-1 or RIPEMD-160 are not accepted"
+            )),
+            other => Err(anyhow!("Unsupported OpenPGP hash algorithm {}", other)),
+        }
+    }
+
+    fn digest(self, message: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => Sha256::digest(message).to_vec(),
+            HashAlgorithm::Sha512 => Sha512::digest(message).to_vec(),
+        }
+    }
+
+    fn policy_name(self) -> algorithms::HashAlgorithm {
+        match self {
+            HashAlgorithm::Sha256 => algorithms::HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512 => algorithms::HashAlgorithm::Sha512,
+        }
+    }
+}
+
+/// The key material of a public key packet.
+enum KeyMaterial {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    EcdsaP256(p256::PublicKey),
+    Ed25519(ed25519_dalek::PublicKey),
+}
+
+/// A v4 public key or subkey.
+struct PgpKey {
+    /// The packet body, which fingerprints and binding signatures cover.
+    body: Vec<u8>,
+    fingerprint: Vec<u8>,
+    /// Creation time, in seconds since the epoch.
+    created: u32,
+    /// `None` for algorithms that cannot be verified with, such as
+    /// encryption-only subkeys.
+    material: Option<KeyMaterial>,
+}
+
+impl PgpKey {
+    fn parse(body: &[u8]) -> Result<Self> {
+        let mut reader = Reader { data: body };
+        if reader.byte()? != 4 {
+            return Err(anyhow!("Only v4 OpenPGP keys are supported"));
+        }
+        let created = reader.number(4)? as u32;
+        let material = match reader.byte()? {
+            1 | 3 => Some(KeyMaterial::Rsa {
+                n: reader.mpi()?.to_vec(),
+                e: reader.mpi()?.to_vec(),
+            }),
+            algorithm @ (19 | 22) => {
+                let oid_length = usize::from(reader.byte()?);
+                let oid = reader.take(oid_length)?;
+                let point = reader.mpi()?;
+                match (algorithm, oid, point) {
+                    (19, OID_P256, _) => Some(KeyMaterial::EcdsaP256(
+                        p256::PublicKey::from_sec1_bytes(point)
+                            .map_err(|_| anyhow!("Invalid OpenPGP P-256 key"))?,
+                    )),
+                    (22, OID_ED25519, [0x40, key @ ..]) => Some(KeyMaterial::Ed25519(
+                        ed25519_dalek::PublicKey::from_bytes(key)?,
+                    )),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let mut key = PgpKey {
+            body: body.to_vec(),
+            fingerprint: Vec::new(),
+            created,
+            material,
+        };
+        // v4 fingerprints are SHA-1, which is only used here to name keys.
+        key.fingerprint =
+            ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, &key.hashed())
+                .as_ref()
+                .to_vec();
+        Ok(key)
+    }
+
+    /// The key as hashed into certifications: a 0x99 octet, the two octet
+    /// length and the packet body.
+    fn hashed(&self) -> Vec<u8> {
+        let mut hashed = vec![0x99];
+        hashed.extend((self.body.len() as u16).to_be_bytes());
+        hashed.extend(&self.body);
+        hashed
+    }
+
+    /// The 64-bit key ID: the last eight octets of the fingerprint.
+    fn key_id(&self) -> &[u8] {
+        &self.fingerprint[self.fingerprint.len() - 8..]
+    }
+
+    /// Check the key and the hash of `signature` against `policy`.
+    fn check_algorithms(
+        &self,
+        signature: &PgpSignature,
+        policy: &AlgorithmPolicy,
+    ) -> crate::error::Result<()> {
+        let (algorithm, bits) = match &self.material {
+            Some(KeyMaterial::Rsa { n, .. }) => {
+                (SignatureAlgorithm::Rsa, algorithms::bit_length(n))
+            }
+            Some(KeyMaterial::EcdsaP256(_)) => (SignatureAlgorithm::EcdsaP256, 256),
+            Some(KeyMaterial::Ed25519(_)) => (SignatureAlgorithm::Ed25519, 256),
+            None => {
+                return Err(SgetError::DisallowedAlgorithm(
+                    "Unsupported OpenPGP key algorithm".to_string(),
+                ))
+            }
+        };
+        policy.check(algorithm, signature.hash.policy_name(), bits)
+    }
+
+    fn verify(&self, signature: &PgpSignature, message: &[u8]) -> Result<()> {
+        let message = [message, &signature.trailer()].concat();
+        let digest = signature.hash.digest(&message);
+        if digest[..2] != signature.digest_prefix {
+            return Err(anyhow!("OpenPGP signature digest does not match"));
+        }
+        let mut mpis = Reader {
+            data: &signature.mpis,
+        };
+        match (&self.material, signature.algorithm) {
+            (Some(KeyMaterial::Rsa { n, e }), 1 | 3) => {
+                let params = match signature.hash {
+                    HashAlgorithm::Sha256 => &RSA_PKCS1_2048_8192_SHA256,
+                    HashAlgorithm::Sha512 => &RSA_PKCS1_2048_8192_SHA512,
+                };
+                let value = mpis.mpi()?;
+                let padding = n.len().saturating_sub(value.len());
+                let sig = [&vec![0; padding][..], value].concat();
+                RsaPublicKeyComponents { n, e }
+                    .verify(params, &message, &sig)
+                    .map_err(|_| anyhow!("Invalid OpenPGP RSA signature"))
+            }
+            (Some(KeyMaterial::EcdsaP256(key)), 19) => {
+                let (r, s) = (scalar(mpis.mpi()?)?, scalar(mpis.mpi()?)?);
+                let sig = p256::ecdsa::Signature::from_scalars(r, s)?;
+                // ECDSA uses the leftmost bits of longer digests.
+                let z =
+                    p256::Scalar::from_bytes_reduced(p256::FieldBytes::from_slice(&digest[..32]));
+                key.as_affine()
+                    .verify_prehashed(&z, &sig)
+                    .map_err(|_| anyhow!("Invalid OpenPGP ECDSA signature"))
+            }
+            (Some(KeyMaterial::Ed25519(key)), 22) => {
+                let sig = [scalar(mpis.mpi()?)?, scalar(mpis.mpi()?)?].concat();
+                let sig = ed25519_dalek::Signature::from_bytes(&sig)?;
+                key.verify_strict(&digest, &sig)
+                    .map_err(|_| anyhow!("Invalid OpenPGP Ed25519 signature"))
+            }
+            _ => Err(anyhow!(
+                "OpenPGP key cannot verify signatures with algorithm {}",
+                signature.algorithm
+            )),
+        }
+    }
+}
+
+/// Left-pad a big-endian integer to 32 octets.
+fn scalar(value: &[u8]) -> Result<[u8; 32]> {
+    let mut padded = [0; 32];
+    let start = 32usize
+        .checked_sub(value.len())
+        .ok_or_else(|| anyhow!("OpenPGP signature value is too long"))?;
+    padded[start..].copy_from_slice(value);
+    Ok(padded)
+}
+
+/// A v4 signature packet.
+struct PgpSignature {
+    kind: u8,
+    algorithm: u8,
+    hash: HashAlgorithm,
+    /// The version, type, algorithms and hashed subpackets, which the
+    /// signature covers.
+    hashed: Vec<u8>,
+    /// Fingerprints or key IDs of the issuing key, from its subpackets.
+    issuers: Vec<Vec<u8>>,
+    /// Creation time, in seconds since the epoch.
+    created: Option<u32>,
+    /// For self-signatures, when the key expires, in seconds after its
+    /// creation. Zero means never.
+    key_expires: Option<u32>,
+    /// For self-signatures, the first octet of the key flags.
+    key_flags: Option<u8>,
+    /// Bodies of embedded signatures, such as the primary key binding
+    /// signature in a subkey binding signature.
+    embedded: Vec<Vec<u8>>,
+    digest_prefix: [u8; 2],
+    mpis: Vec<u8>,
+}
+
+impl PgpSignature {
+    fn parse(body: &[u8]) -> Result<Self> {
+        let mut reader = Reader { data: body };
+        if reader.byte()? != 4 {
+            return Err(anyhow!("Only v4 OpenPGP signatures are supported"));
+        }
+        let kind = reader.byte()?;
+        let algorithm = reader.byte()?;
+        let hash = HashAlgorithm::from_id(reader.byte()?)?;
+        let hashed_length = reader.number(2)?;
+        let hashed_subpackets = reader.take(hashed_length)?;
+        let hashed = body[..6 + hashed_length].to_vec();
+        let unhashed_length = reader.number(2)?;
+        let unhashed_subpackets = reader.take(unhashed_length)?;
+        let mut digest_prefix = [0; 2];
+        digest_prefix.copy_from_slice(reader.take(2)?);
+
+        let mut issuers = Vec::new();
+        let mut created = None;
+        let mut key_expires = None;
+        let mut key_flags = None;
+        let mut embedded = Vec::new();
+        for (subpackets, is_hashed) in [(hashed_subpackets, true), (unhashed_subpackets, false)] {
+            let mut reader = Reader { data: subpackets };
+            while !reader.data.is_empty() {
+                let length = match reader.byte()? {
+                    first @ 0..=191 => usize::from(first),
+                    first @ 192..=254 => {
+                        ((usize::from(first) - 192) << 8) + reader.number(1)? + 192
+                    }
+                    255 => reader.number(4)?,
+                };
+                let subpacket = reader.take(length)?;
+                let (&kind, data) = subpacket
+                    .split_first()
+                    .ok_or_else(|| anyhow!("Empty OpenPGP signature subpacket"))?;
+                match (kind & 0x7f, data) {
+                    (SUBPACKET_ISSUER, _) => issuers.push(data.to_vec()),
+                    (SUBPACKET_ISSUER_FINGERPRINT, [4, fingerprint @ ..]) => {
+                        issuers.push(fingerprint.to_vec())
+                    }
+                    // Times only count if the signature covers them.
+                    (SUBPACKET_CREATION_TIME, [_, _, _, _]) if is_hashed => {
+                        created = Some(Reader { data }.number(4)? as u32)
+                    }
+                    (SUBPACKET_KEY_EXPIRATION, [_, _, _, _]) if is_hashed => {
+                        key_expires = Some(Reader { data }.number(4)? as u32)
+                    }
+                    (SUBPACKET_KEY_FLAGS, [flags, ..]) if is_hashed => key_flags = Some(*flags),
+                    // Embedded signatures are verified on their own.
+                    (SUBPACKET_EMBEDDED_SIGNATURE, _) => embedded.push(data.to_vec()),
+                    (SUBPACKET_CREATION_TIME, _) => {}
+                    (other, _) if is_hashed && kind & 0x80 != 0 => {
+                        return Err(anyhow!(
+                            "Unsupported critical OpenPGP signature subpacket {}",
+                            other
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(PgpSignature {
+            kind,
+            algorithm,
+            hash,
+            hashed,
+            issuers,
+            created,
+            key_expires,
+            key_flags,
+            embedded,
+            digest_prefix,
+            mpis: reader.data.to_vec(),
+        })
+    }
+
+    /// What is appended to the signed data before hashing it.
+    fn trailer(&self) -> Vec<u8> {
+        let mut trailer = self.hashed.clone();
+        trailer.extend([0x04, 0xff]);
+        trailer.extend((self.hashed.len() as u32).to_be_bytes());
+        trailer
+    }
+
+    /// Whether this subkey binding signature binds `subkey` as a signing key:
+    /// it has the signing flag, and embeds a primary key binding signature by
+    /// `subkey` over `bound`, the primary key and subkey. Without the latter,
+    /// anyone could bind someone else's signing subkey to their own primary
+    /// key and claim its signatures.
+    fn binds_signing_key(&self, subkey: &PgpKey, bound: &[u8]) -> bool {
+        self.key_flags
+            .is_some_and(|flags| flags & KEY_FLAG_SIGN != 0)
+            && self.embedded.iter().any(|body| {
+                matches!(
+                    PgpSignature::parse(body),
+                    Ok(back) if back.kind == SIG_PRIMARY_KEY_BINDING
+                        && subkey.verify(&back, bound).is_ok()
+                )
+            })
+    }
+
+    /// Whether `key` may have made the signature, going by its issuer
+    /// subpackets. Signatures without any are tried against every key.
+    fn may_be_from(&self, key: &PgpKey) -> bool {
+        self.issuers.is_empty()
+            || self
+                .issuers
+                .iter()
+                .any(|issuer| issuer[..] == key.fingerprint[..] || issuer[..] == *key.key_id())
+    }
+}
+
+/// What the valid self-signatures of a key say about it.
+#[derive(Default)]
+struct SelfSignatures {
+    /// Creation time of the latest self-signature, and the key expiration it
+    /// sets. For subkeys, only binding signatures count, so a subkey without
+    /// one is not bound to its primary key.
+    latest: Option<(u32, Option<u32>)>,
+    revoked: bool,
+}
+
+impl SelfSignatures {
+    fn add(&mut self, signature: &PgpSignature) {
+        let created = signature.created.unwrap_or_default();
+        if self.latest.is_none_or(|(latest, _)| created >= latest) {
+            self.latest = Some((created, signature.key_expires));
+        }
+    }
+
+    /// When `key` expires, in seconds since the epoch.
+    fn expires(&self, key: &PgpKey) -> Option<i64> {
+        self.latest
+            .and_then(|(_, expires)| expires)
+            .filter(|expires| *expires != 0)
+            .map(|expires| i64::from(key.created) + i64::from(expires))
+    }
+}
+
+/// A primary key with the subkeys and self-signatures that follow it.
+struct TransferableKey {
+    primary: PgpKey,
+    signatures: SelfSignatures,
+    subkeys: Vec<(PgpKey, SelfSignatures)>,
+}
+
+/// What the signatures that follow a packet are about.
+enum Component<'a> {
+    Primary,
+    UserId(&'a [u8]),
+    Subkey,
+    Other,
+}
+
+/// A signing key, the primary key it belongs to, and whether it can still
+/// be used.
+struct KeyringEntry {
+    key: PgpKey,
+    primary_fingerprint: Vec<u8>,
+    /// Whether the key, or its primary key, was revoked.
+    revoked: bool,
+    /// When the key, or its primary key, expires, in seconds since the epoch.
+    expires: Option<i64>,
+}
+
+impl KeyringEntry {
+    /// Why the key cannot verify signatures at `now`, if it cannot.
+    fn unusable(&self, now: DateTime<Utc>) -> Option<String> {
+        let fingerprint = hex::encode(&self.key.fingerprint);
+        if self.revoked {
+            return Some(format!("OpenPGP key {} is revoked", fingerprint));
+        }
+        let expires = self.expires.filter(|expires| now.timestamp() >= *expires)?;
+        Some(format!(
+            "OpenPGP key {} expired at {}",
+            fingerprint,
+            Utc.timestamp_opt(expires, 0)
+                .single()
+                .map_or_else(|| expires.to_string(), |expires| expires.to_rfc3339())
+        ))
+    }
+}
+
+/// The keys of an OpenPGP keyring that signatures can be verified with:
+/// primary keys, and signing subkeys bound to them by a valid binding
+/// signature that the subkey signed back.
+/// Revocations, and the expiry set by each key's latest self-signature, are
+/// checked when verifying.
+pub struct Keyring {
+    keys: Vec<KeyringEntry>,
+}
+
+impl Keyring {
+    /// Parse ASCII armored or binary transferable public keys.
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let binary = dearmor(raw)?;
+        let mut transferable: Vec<TransferableKey> = Vec::new();
+        let mut component = Component::Other;
+        for packet in packets(&binary)? {
+            match packet.tag {
+                TAG_PUBLIC_KEY => {
+                    transferable.push(TransferableKey {
+                        primary: PgpKey::parse(packet.body)?,
+                        signatures: SelfSignatures::default(),
+                        subkeys: Vec::new(),
+                    });
+                    component = Component::Primary;
+                }
+                TAG_USER_ID => component = Component::UserId(packet.body),
+                TAG_PUBLIC_SUBKEY => {
+                    if let Some(key) = transferable.last_mut() {
+                        let subkey = PgpKey::parse(packet.body)?;
+                        key.subkeys.push((subkey, SelfSignatures::default()));
+                        component = Component::Subkey;
+                    }
+                }
+                TAG_SIGNATURE => {
+                    let key = match transferable.last_mut() {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    let signature = match PgpSignature::parse(packet.body) {
+                        Ok(signature) if signature.may_be_from(&key.primary) => signature,
+                        _ => continue,
+                    };
+                    let mut signed = key.primary.hashed();
+                    match (&component, signature.kind) {
+                        (_, SIG_KEY_REVOCATION | SIG_DIRECT_KEY) => {}
+                        (
+                            Component::UserId(user_id),
+                            SIG_CERTIFICATION_GENERIC..=SIG_CERTIFICATION_POSITIVE,
+                        ) => {
+                            signed.push(0xb4);
+                            signed.extend((user_id.len() as u32).to_be_bytes());
+                            signed.extend(*user_id);
+                        }
+                        (Component::Subkey, SIG_SUBKEY_BINDING | SIG_SUBKEY_REVOCATION) => {
+                            if let Some((subkey, _)) = key.subkeys.last() {
+                                signed.extend(subkey.hashed());
+                            }
+                        }
+                        _ => continue,
+                    }
+                    if key.primary.verify(&signature, &signed).is_err() {
+                        continue;
+                    }
+                    if signature.kind == SIG_SUBKEY_BINDING {
+                        match key.subkeys.last() {
+                            Some((subkey, _)) if signature.binds_signing_key(subkey, &signed) => {}
+                            _ => continue,
+                        }
+                    }
+                    let signatures = match signature.kind {
+                        SIG_SUBKEY_BINDING | SIG_SUBKEY_REVOCATION => {
+                            match key.subkeys.last_mut() {
+                                Some((_, signatures)) => signatures,
+                                None => continue,
+                            }
+                        }
+                        _ => &mut key.signatures,
+                    };
+                    match signature.kind {
+                        SIG_KEY_REVOCATION | SIG_SUBKEY_REVOCATION => signatures.revoked = true,
+                        _ => signatures.add(&signature),
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut keys = Vec::new();
+        for key in transferable {
+            let primary_fingerprint = key.primary.fingerprint.clone();
+            let revoked = key.signatures.revoked;
+            let expires = key.signatures.expires(&key.primary);
+            keys.push(KeyringEntry {
+                key: key.primary,
+                primary_fingerprint: primary_fingerprint.clone(),
+                revoked,
+                expires,
+            });
+            for (subkey, signatures) in key.subkeys {
+                if signatures.latest.is_none() {
+                    continue;
+                }
+                let subkey_expires = signatures.expires(&subkey);
+                keys.push(KeyringEntry {
+                    key: subkey,
+                    primary_fingerprint: primary_fingerprint.clone(),
+                    revoked: revoked || signatures.revoked,
+                    expires: expires.into_iter().chain(subkey_expires).min(),
+                });
+            }
+        }
+        if keys.is_empty() {
+            return Err(anyhow!("No OpenPGP public keys found"));
+        }
+        Ok(Keyring { keys })
+    }
+
+    /// The hex encoded fingerprints of the primary keys.
+    pub fn fingerprints(&self) -> Vec<String> {
+        let mut fingerprints: Vec<String> = self
+            .keys
+            .iter()
+            .map(|entry| hex::encode(&entry.primary_fingerprint))
+            .collect();
+        fingerprints.dedup();
+        fingerprints
+    }
+}
+
+/// Normalize a fingerprint as GnuPG prints it, with spaces or a `0x` prefix,
+/// to lowercase hex.
+pub fn normalize_fingerprint(fingerprint: &str) -> String {
+    let fingerprint: String = fingerprint
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match fingerprint.strip_prefix("0x") {
+        Some(stripped) => stripped.to_string(),
+        None => fingerprint,
+    }
+}
+
+/// Canonicalize line endings to CRLF, as text mode signatures are made over.
+fn canonical_text(data: &[u8]) -> Vec<u8> {
+    let mut text = Vec::with_capacity(data.len());
+    let mut previous = 0;
+    for &byte in data {
+        if byte == b'\n' && previous != b'\r' {
+            text.push(b'\r');
+        }
+        text.push(byte);
+        previous = byte;
+    }
+    text
+}
+
+/// Verify an ASCII armored or binary OpenPGP detached `signature` over `data`
+/// with the keys of `keyring`. If `fingerprints` is not empty, the signing
+/// key's primary key must be one of them. The key and hash of the signature
+/// must be allowed by `algorithms`, and the key neither revoked nor expired at
+/// `now`. The signer's key ID is the lowercase hex fingerprint of that primary
+/// key.
+pub fn verify_detached(
+    data: &[u8],
+    signature: &[u8],
+    keyring: &Keyring,
+    fingerprints: &[String],
+    algorithms: &AlgorithmPolicy,
+    now: DateTime<Utc>,
+) -> crate::error::Result<Signer> {
+    let binary = dearmor(signature).map_err(SgetError::InvalidMaterial)?;
+    let signatures = packets(&binary)
+        .map_err(SgetError::InvalidMaterial)?
+        .into_iter()
+        .filter(|packet| packet.tag == TAG_SIGNATURE)
+        .map(|packet| PgpSignature::parse(packet.body))
+        .collect::<Result<Vec<_>>>()
+        .map_err(SgetError::InvalidMaterial)?;
+    if signatures.is_empty() {
+        return Err(SgetError::InvalidMaterial(anyhow!(
+            "No OpenPGP signature found"
+        )));
+    }
+    let allowed: Vec<String> = fingerprints
+        .iter()
+        .map(|fingerprint| normalize_fingerprint(fingerprint))
+        .collect();
+    let mut errors = Vec::new();
+    let mut untrusted = Vec::new();
+    let mut unusable = Vec::new();
+    let mut disallowed = None;
+    for signature in &signatures {
+        let message = match signature.kind {
+            SIG_BINARY => data.to_vec(),
+            SIG_TEXT => canonical_text(data),
+            other => {
+                errors.push(format!("Not a document signature: type {:#04x}", other));
+                continue;
+            }
+        };
+        for entry in &keyring.keys {
+            if !signature.may_be_from(&entry.key) {
+                continue;
+            }
+            let fingerprint = hex::encode(&entry.primary_fingerprint);
+            match entry.key.verify(signature, &message) {
+                Ok(()) if allowed.is_empty() || allowed.contains(&fingerprint) => {
+                    if let Some(reason) = entry.unusable(now) {
+                        unusable.push(reason);
+                        continue;
+                    }
+                    match entry.key.check_algorithms(signature, algorithms) {
+                        Ok(()) => {
+                            return Ok(Signer {
+                                key_id: fingerprint,
+                                identity: None,
+                                issuer: None,
+                                integrated_time: None,
+                            })
+                        }
+                        Err(e) => {
+                            disallowed.get_or_insert(e);
+                        }
+                    }
+                }
+                Ok(()) => untrusted.push(fingerprint),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+    }
+    if let Some(e) = disallowed {
+        return Err(e);
+    }
+    if !unusable.is_empty() {
+        return Err(SgetError::UntrustedSigner(unusable.join("; ")));
+    }
+    if !untrusted.is_empty() {
+        return Err(SgetError::UntrustedSigner(format!(
+            "{} is not an allowed OpenPGP key",
+            untrusted.join(", ")
+        )));
+    }
+    if errors.is_empty() {
+        errors.push("No key in the keyring made the signature".to_string());
+    }
+    Err(SgetError::InvalidSignature(anyhow!(errors.join("; "))))
+}
+
+// The test keys are Ed25519, which FIPS builds refuse.
+#[cfg(all(test, not(feature = "fips")))]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Signer as _;
+    use rand_core::{OsRng, RngCore};
+
+    fn keypair() -> ed25519_dalek::Keypair {
+        let mut seed = [0; ed25519_dalek::SECRET_KEY_LENGTH];
+        OsRng.fill_bytes(&mut seed);
+        let secret = ed25519_dalek::SecretKey::from_bytes(&seed).expect("Invalid seed");
+        let public = (&secret).into();
+        ed25519_dalek::Keypair { secret, public }
+    }
+
+    fn packet(tag: u8, body: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0xc0 | tag, 0xff];
+        packet.extend((body.len() as u32).to_be_bytes());
+        packet.extend(body);
+        packet
+    }
+
+    fn mpi(value: &[u8]) -> Vec<u8> {
+        let value = match value.iter().position(|byte| *byte != 0) {
+            Some(start) => &value[start..],
+            None => &[],
+        };
+        let bits = value
+            .first()
+            .map_or(0, |first| value.len() * 8 - first.leading_zeros() as usize);
+        [&(bits as u16).to_be_bytes()[..], value].concat()
+    }
+
+    fn armor(kind: &str, binary: &[u8]) -> Vec<u8> {
+        format!(
+            "-----BEGIN PGP {kind}-----\nComment: test\n\n{}\n=AAAA\n-----END PGP {kind}-----\n",
+            base64::encode(binary),
+            kind = kind
+        )
+        .into_bytes()
+    }
+
+    fn ed25519_key(keypair: &ed25519_dalek::Keypair) -> Vec<u8> {
+        let mut body = vec![4, 0x61, 0x9c, 0x8b, 0x00, 22, OID_ED25519.len() as u8];
+        body.extend(OID_ED25519);
+        body.extend(mpi(&[&[0x40][..], keypair.public.as_bytes()].concat()));
+        body
+    }
+
+    fn sign(keypair: &ed25519_dalek::Keypair, issuer: &[u8], kind: u8, data: &[u8]) -> Vec<u8> {
+        sign_with(keypair, issuer, kind, data, &[])
+    }
+
+    /// A signature with the hashed subpackets `extra` after the issuer.
+    fn sign_with(
+        keypair: &ed25519_dalek::Keypair,
+        issuer: &[u8],
+        kind: u8,
+        data: &[u8],
+        extra: &[u8],
+    ) -> Vec<u8> {
+        packet(
+            TAG_SIGNATURE,
+            &signature_body(keypair, issuer, kind, data, extra),
+        )
+    }
+
+    /// The subpackets of a binding signature for a signing subkey: the key
+    /// flags, and the primary key binding signature by `subkey` over `bound`.
+    fn signing_subkey(subkey: &ed25519_dalek::Keypair, issuer: &[u8], bound: &[u8]) -> Vec<u8> {
+        let back = signature_body(subkey, issuer, SIG_PRIMARY_KEY_BINDING, bound, &[]);
+        let mut subpackets = vec![2, SUBPACKET_KEY_FLAGS, KEY_FLAG_SIGN, 255];
+        subpackets.extend((back.len() as u32 + 1).to_be_bytes());
+        subpackets.push(SUBPACKET_EMBEDDED_SIGNATURE);
+        subpackets.extend(back);
+        subpackets
+    }
+
+    fn signature_body(
+        keypair: &ed25519_dalek::Keypair,
+        issuer: &[u8],
+        kind: u8,
+        data: &[u8],
+        extra: &[u8],
+    ) -> Vec<u8> {
+        let mut subpackets = vec![22, SUBPACKET_ISSUER_FINGERPRINT, 4];
+        subpackets.extend(issuer);
+        subpackets.extend(extra);
+        let mut body = vec![4, kind, 22, 8];
+        body.extend((subpackets.len() as u16).to_be_bytes());
+        body.extend(subpackets);
+        let signature = PgpSignature::parse(&[&body[..], &[0, 0, 0, 0]].concat())
+            .expect("Cannot parse signature");
+        let digest = Sha256::digest(&[data, &signature.trailer()].concat());
+        let sig = keypair.sign(&digest).to_bytes();
+        body.extend([0, 0]);
+        body.extend(&digest[..2]);
+        body.extend(mpi(&sig[..32]));
+        body.extend(mpi(&sig[32..]));
+        body
+    }
+
+    #[test]
+    fn verify_ed25519_signature() {
+        let primary = keypair();
+        let subkey = keypair();
+        let primary_body = ed25519_key(&primary);
+        let subkey_body = ed25519_key(&subkey);
+        let primary_key = PgpKey::parse(&primary_body).expect("Cannot parse key");
+        let subkey_key = PgpKey::parse(&subkey_body).expect("Cannot parse subkey");
+        let bound = [primary_key.hashed(), subkey_key.hashed()].concat();
+        let bind = |extra: &[u8]| {
+            let binding = sign_with(
+                &primary,
+                &primary_key.fingerprint,
+                SIG_SUBKEY_BINDING,
+                &bound,
+                extra,
+            );
+            [
+                packet(TAG_PUBLIC_KEY, &primary_body),
+                packet(13, b"Release Signing <release@example.com>"),
+                packet(TAG_PUBLIC_SUBKEY, &subkey_body),
+                binding,
+            ]
+            .concat()
+        };
+        let keys = bind(&signing_subkey(&subkey, &subkey_key.fingerprint, &bound));
+        let keyring = Keyring::parse(&armor("PUBLIC KEY BLOCK", &keys)).expect("No keyring");
+        let fingerprint = hex::encode(&primary_key.fingerprint);
+        assert_eq!(keyring.fingerprints(), vec![fingerprint.clone()]);
+
+        let data = b"#!/bin/sh\necho hi\n";
+        let by_subkey = armor(
+            "SIGNATURE",
+            &sign(
+                &subkey,
+                &subkey_key.fingerprint,
+                SIG_TEXT,
+                &canonical_text(data),
+            ),
+        );
+        let signer = verify_detached(
+            data,
+            &by_subkey,
+            &keyring,
+            &[fingerprint.to_uppercase()],
+            &AlgorithmPolicy::default(),
+            Utc::now(),
+        )
+        .expect("Cannot verify signature");
+        assert_eq!(signer.key_id, fingerprint);
+        assert!(matches!(
+            verify_detached(
+                b"echo bye",
+                &by_subkey,
+                &keyring,
+                &[],
+                &AlgorithmPolicy::default(),
+                Utc::now()
+            ),
+            Err(SgetError::InvalidSignature(_))
+        ));
+        assert!(matches!(
+            verify_detached(
+                data,
+                &by_subkey,
+                &keyring,
+                &["00".repeat(20)],
+                &AlgorithmPolicy::default(),
+                Utc::now()
+            ),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+        let ecdsa_only = AlgorithmPolicy {
+            signature_algorithms: vec![SignatureAlgorithm::EcdsaP256],
+            ..AlgorithmPolicy::default()
+        };
+        assert!(matches!(
+            verify_detached(data, &by_subkey, &keyring, &[], &ecdsa_only, Utc::now()),
+            Err(SgetError::DisallowedAlgorithm(_))
+        ));
+
+        // Without its binding signature, the subkey is not part of the keyring.
+        let unbound = [
+            packet(TAG_PUBLIC_KEY, &primary_body),
+            packet(TAG_PUBLIC_SUBKEY, &subkey_body),
+        ]
+        .concat();
+        let unbound = Keyring::parse(&unbound).expect("No keyring");
+        assert!(verify_detached(
+            data,
+            &by_subkey,
+            &unbound,
+            &[],
+            &AlgorithmPolicy::default(),
+            Utc::now()
+        )
+        .is_err());
+
+        // Nor is it when the binding signature does not make it a signing
+        // key, or the subkey did not sign back, e.g. because someone else
+        // bound it to their primary key.
+        let not_signing = signing_subkey(&subkey, &subkey_key.fingerprint, &bound);
+        let not_signing = [&[2, SUBPACKET_KEY_FLAGS, 0x0c][..], &not_signing[3..]].concat();
+        let other = keypair();
+        for extra in [
+            vec![2, SUBPACKET_KEY_FLAGS, KEY_FLAG_SIGN],
+            not_signing,
+            signing_subkey(&other, &subkey_key.fingerprint, &bound),
+        ] {
+            let keyring = Keyring::parse(&bind(&extra)).expect("No keyring");
+            assert!(verify_detached(
+                data,
+                &by_subkey,
+                &keyring,
+                &[],
+                &AlgorithmPolicy::default(),
+                Utc::now()
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn reject_revoked_and_expired_keys() {
+        let primary = keypair();
+        let subkey = keypair();
+        let primary_body = ed25519_key(&primary);
+        let subkey_body = ed25519_key(&subkey);
+        let primary_key = PgpKey::parse(&primary_body).expect("Cannot parse key");
+        let subkey_key = PgpKey::parse(&subkey_body).expect("Cannot parse subkey");
+        let user_id = b"Release Signing <release@example.com>";
+        let certified = [
+            primary_key.hashed(),
+            vec![0xb4],
+            (user_id.len() as u32).to_be_bytes().to_vec(),
+            user_id.to_vec(),
+        ]
+        .concat();
+        let bound = [primary_key.hashed(), subkey_key.hashed()].concat();
+        let self_sign = |kind, data: &[u8], created: u32, expires: u32| {
+            let mut extra = vec![5, SUBPACKET_CREATION_TIME];
+            extra.extend((primary_key.created + created).to_be_bytes());
+            extra.extend([5, SUBPACKET_KEY_EXPIRATION]);
+            extra.extend(expires.to_be_bytes());
+            if kind == SIG_SUBKEY_BINDING {
+                extra.extend(signing_subkey(&subkey, &subkey_key.fingerprint, data));
+            }
+            sign_with(&primary, &primary_key.fingerprint, kind, data, &extra)
+        };
+        let keyring = |packets: &[Vec<u8>]| {
+            let keys = [
+                vec![
+                    packet(TAG_PUBLIC_KEY, &primary_body),
+                    packet(TAG_USER_ID, user_id),
+                ],
+                packets.to_vec(),
+            ]
+            .concat()
+            .concat();
+            Keyring::parse(&keys).expect("No keyring")
+        };
+        let data = b"#!/bin/sh\necho hi\n";
+        let by_subkey = sign(&subkey, &subkey_key.fingerprint, SIG_BINARY, data);
+        let day = 86_400;
+        let at = |seconds: u32| {
+            Utc.timestamp_opt(i64::from(primary_key.created + seconds), 0)
+                .single()
+                .expect("Invalid time")
+        };
+        let verify = |keyring: &Keyring, now| {
+            verify_detached(
+                data,
+                &by_subkey,
+                keyring,
+                &[],
+                &AlgorithmPolicy::default(),
+                now,
+            )
+        };
+        let subkey_packet = packet(TAG_PUBLIC_SUBKEY, &subkey_body);
+
+        // The subkey expires a day after its creation.
+        let expiring = keyring(&[
+            subkey_packet.clone(),
+            self_sign(SIG_SUBKEY_BINDING, &bound, 0, day),
+        ]);
+        assert!(verify(&expiring, at(day - 1)).is_ok());
+        assert!(matches!(
+            verify(&expiring, at(day)),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+
+        // The primary key expires after two days, which a later certification
+        // of its user ID extends to never; a later binding without expiry does
+        // the same for the subkey.
+        let extended = keyring(&[
+            self_sign(SIG_CERTIFICATION_POSITIVE, &certified, 0, 2 * day),
+            self_sign(SIG_CERTIFICATION_POSITIVE, &certified, 1, 0),
+            subkey_packet.clone(),
+            self_sign(SIG_SUBKEY_BINDING, &bound, 0, day),
+            self_sign(SIG_SUBKEY_BINDING, &bound, 1, 0),
+        ]);
+        assert!(verify(&extended, at(30 * day)).is_ok());
+        let primary_expired = keyring(&[
+            self_sign(SIG_CERTIFICATION_POSITIVE, &certified, 0, 2 * day),
+            subkey_packet.clone(),
+            self_sign(SIG_SUBKEY_BINDING, &bound, 0, 0),
+        ]);
+        assert!(verify(&primary_expired, at(day)).is_ok());
+        assert!(verify(&primary_expired, at(2 * day)).is_err());
+
+        // Revoking the subkey, or the primary key, revokes the subkey.
+        let binding = self_sign(SIG_SUBKEY_BINDING, &bound, 0, 0);
+        let subkey_revoked = keyring(&[
+            subkey_packet.clone(),
+            binding.clone(),
+            self_sign(SIG_SUBKEY_REVOCATION, &bound, 1, 0),
+        ]);
+        assert!(matches!(
+            verify(&subkey_revoked, at(1)),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+        let primary_revoked = Keyring::parse(
+            &[
+                packet(TAG_PUBLIC_KEY, &primary_body),
+                self_sign(SIG_KEY_REVOCATION, &primary_key.hashed(), 1, 0),
+                subkey_packet.clone(),
+                binding.clone(),
+            ]
+            .concat(),
+        )
+        .expect("No keyring");
+        assert!(verify(&primary_revoked, at(1)).is_err());
+
+        // Revocations only count when the primary key made them.
+        let forged = keyring(&[
+            subkey_packet,
+            binding,
+            sign_with(
+                &subkey,
+                &primary_key.fingerprint,
+                SIG_SUBKEY_REVOCATION,
+                &bound,
+                &[],
+            ),
+        ]);
+        assert!(verify(&forged, at(1)).is_ok());
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::Result;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::error::SgetError;
+
+/// The stages of verifying an artifact or policy that hooks run around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Parsing a root policy and checking its expiry and root signatures.
+    PolicyLoad,
+    /// Checking that a signing certificate chains to a trusted Fulcio or
+    /// Notation root.
+    ChainValidation,
+    /// Checking the Rekor bundle of a signature.
+    LogProof,
+    /// Checking an artifact against its pinned digest.
+    DigestCheck,
+    /// Checking the build provenance of an artifact.
+    ProvenanceCheck,
+    /// Checking the in-toto layout and links of an artifact.
+    LayoutCheck,
+    /// Checking the SBOM attestation of an artifact.
+    SbomCheck,
+    /// Evaluating a claim policy against the signature of an artifact.
+    ClaimCheck,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::PolicyLoad => "policy load",
+            Stage::ChainValidation => "chain validation",
+            Stage::LogProof => "log proof",
+            Stage::DigestCheck => "digest check",
+            Stage::ProvenanceCheck => "provenance check",
+            Stage::LayoutCheck => "layout check",
+            Stage::SbomCheck => "SBOM check",
+            Stage::ClaimCheck => "claim check",
+        })
+    }
+}
+
+/// What a stage is verifying.
+pub struct StageContext<'a> {
+    pub stage: Stage,
+    /// Hex encoded SHA-256 digest of the artifact or policy being verified.
+    pub digest: &'a str,
+    /// The identity in the signing certificate, if the signature has one.
+    pub identity: Option<&'a str>,
+}
+
+/// Runs before and after verification stages, e.g. to log or time them or to
+/// apply extra checks such as asking an allowlist service about the signer.
+///
+/// An error from either method rejects the artifact or policy with
+/// [`SgetError::Rejected`]. `after` runs whether or not the stage succeeded;
+/// when it failed, the stage's own error is reported and errors from `after`
+/// are ignored.
+pub trait StageHook: Send + Sync {
+    fn before(&self, _context: &StageContext) -> Result<()> {
+        Ok(())
+    }
+
+    fn after(&self, _context: &StageContext, _outcome: Result<(), &SgetError>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The stage hooks to run during verification, in registration order.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    hooks: Vec<Arc<dyn StageHook>>,
+}
+
+impl Pipeline {
+    pub fn add(&mut self, hook: Arc<dyn StageHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Run `stage` between the hooks' `before` and `after`.
+    pub(crate) fn run<T>(
+        &self,
+        context: StageContext,
+        stage: impl FnOnce() -> Result<T, SgetError>,
+    ) -> Result<T, SgetError> {
+        let rejected = |source| SgetError::Rejected {
+            stage: context.stage,
+            source,
+        };
+        for hook in &self.hooks {
+            hook.before(&context).map_err(rejected)?;
+        }
+        let result = stage();
+        let mut rejection = None;
+        for hook in &self.hooks {
+            if let Err(e) = hook.after(&context, result.as_ref().map(|_| ())) {
+                rejection.get_or_insert(e);
+            }
+        }
+        match (result, rejection) {
+            (Ok(_), Some(e)) => Err(rejected(e)),
+            (result, _) => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::Mutex;
+
+    /// Records the stages it sees and rejects one identity.
+    #[derive(Default)]
+    struct Allowlist {
+        seen: Mutex<Vec<String>>,
+    }
+
+    impl StageHook for Allowlist {
+        fn before(&self, context: &StageContext) -> Result<()> {
+            let mut seen = self.seen.lock().expect("Poisoned");
+            seen.push(format!("before {}", context.stage));
+            Ok(())
+        }
+
+        fn after(&self, context: &StageContext, outcome: Result<(), &SgetError>) -> Result<()> {
+            let mut seen = self.seen.lock().expect("Poisoned");
+            seen.push(format!("after {} ok={}", context.stage, outcome.is_ok()));
+            if context.identity == Some("mallory@example.com") {
+                return Err(anyhow!("mallory@example.com is not allowed"));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hooks_wrap_stages() {
+        let allowlist = Arc::new(Allowlist::default());
+        let mut pipeline = Pipeline::default();
+        pipeline.add(allowlist.clone());
+        let context = |identity| StageContext {
+            stage: Stage::ChainValidation,
+            digest: "00",
+            identity,
+        };
+
+        assert_eq!(
+            pipeline
+                .run(context(Some("alice@example.com")), || Ok(1))
+                .expect("Rejected"),
+            1
+        );
+        assert!(matches!(
+            pipeline.run(context(Some("mallory@example.com")), || Ok(2)),
+            Err(SgetError::Rejected {
+                stage: Stage::ChainValidation,
+                ..
+            })
+        ));
+        // A failed stage keeps its own error.
+        assert!(matches!(
+            pipeline.run(
+                context(Some("mallory@example.com")),
+                || -> Result<(), SgetError> {
+                    Err(SgetError::UntrustedCertificate("no".to_string()))
+                }
+            ),
+            Err(SgetError::UntrustedCertificate(_))
+        ));
+        assert_eq!(
+            allowlist.seen.lock().expect("Poisoned").as_slice(),
+            [
+                "before chain validation",
+                "after chain validation ok=true",
+                "before chain validation",
+                "after chain validation ok=true",
+                "before chain validation",
+                "after chain validation ok=false",
+            ]
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use clap::{App, Arg, ArgMatches};
+use std::path::Path;
+
+use crate::ceremony;
+use crate::config::{Config, LayeredPolicy, NamespaceConfig};
+use crate::expiry;
+use crate::fetch::{fetch, is_remote};
+use crate::merkle;
+use crate::migrate;
+use crate::pipeline::Pipeline;
+use crate::policy_set::PolicySet;
+use crate::promote;
+use crate::scaffold;
+use crate::signature::SignatureVerifiers;
+use crate::state;
+use crate::storage::{FileStorage, Storage};
+use crate::transport::{self, Transport};
+use crate::trust::TrustStore;
+use crate::tuf::{self, Refreshed, TrustedMetadata};
+use crate::verify::{self, TrustRoots};
+
+pub(crate) fn command() -> App<'static> {
+    App::new("policy")
+        .about("Inspect the root policies sget enforces")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("resolve")
+                .about("Show the effective policy of a namespace, merged from the configured policies")
+                .arg(
+                    Arg::new("namespace")
+                        .about("Namespace to resolve, e.g. ghcr.io/acme/web")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("policy")
+                        .long("policy")
+                        .value_name("LEVEL=LOCATION")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .about("Resolve against this system, org or project policy instead of the configured ones"),
+                ),
+        )
+        .subcommand(scaffold::command())
+        .subcommand(ceremony::command())
+        .subcommand(promote::command())
+        .subcommand(migrate::command())
+        .subcommand(
+            App::new("refresh")
+                .about("Fetch and verify the latest timestamp, snapshot and targets metadata of a namespace")
+                .arg(
+                    Arg::new("namespace")
+                        .about("Configured namespace with a metadata repository")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            App::new("fetch-target")
+                .about("Download a target file listed in the trusted targets metadata of a namespace, verifying it against its length and hashes")
+                .arg(
+                    Arg::new("namespace")
+                        .about("Configured namespace with a metadata repository")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("target")
+                        .about("Path of the target in the targets metadata")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .value_name("URL")
+                        .takes_value(true)
+                        .about("Where to download the target from [default: targets/<TARGET> in the metadata repository]"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .value_name("FILE")
+                        .required(true)
+                        .about("Where to write the target; targets with a Merkle root resume from FILE.part"),
+                ),
+        )
+}
+
+/// Fetch and verify the policies of `layers` against `roots`.
+pub(crate) async fn load_policy_set(
+    transport: &dyn Transport,
+    layers: &[LayeredPolicy],
+    roots: &TrustRoots,
+) -> Result<PolicySet> {
+    let mut set = PolicySet::default();
+    for layer in layers {
+        let raw = fetch(transport, &layer.policy, Path::new("")).await?;
+        let policy = verify::resolve_policy(
+            transport,
+            raw,
+            &layer.policy,
+            roots,
+            &SignatureVerifiers::default(),
+            &Pipeline::default(),
+            Utc::now(),
+        )
+        .await
+        .map_err(|e| anyhow!("{} policy {}: {}", layer.level, layer.policy, e))?;
+        set.add(layer.level, policy);
+    }
+    Ok(set)
+}
+
+/// Trusted TUF metadata is kept in the `tuf` directory of the state
+/// directory, one file per namespace.
+fn metadata_storage() -> Result<FileStorage> {
+    Ok(FileStorage::new(state::state_dir()?.join("tuf")))
+}
+
+/// The metadata repository of the configured namespace `name`, relative
+/// locations resolved against `base`.
+fn metadata_repository(name: &str, namespace: &NamespaceConfig, base: &Path) -> Result<String> {
+    let repository = namespace
+        .metadata
+        .as_deref()
+        .ok_or_else(|| anyhow!("Namespace {} has no metadata repository", name))?;
+    Ok(match is_remote(repository) {
+        true => repository.to_string(),
+        false => base.join(repository).to_string_lossy().into_owned(),
+    })
+}
+
+/// The metadata trusted for the namespace `name`.
+fn trusted_metadata(storage: &FileStorage, name: &str) -> Result<TrustedMetadata> {
+    Ok(match storage.get(&format!("{}.json", name))? {
+        Some(raw) => serde_json::from_slice(&raw)?,
+        None => TrustedMetadata::default(),
+    })
+}
+
+/// Verify the root policy of the configured namespace `name` and refresh its
+/// TUF metadata against it, keeping what is trusted in the state directory.
+/// Relative locations are resolved against `base`.
+pub(crate) async fn refresh_metadata(
+    transport: &dyn Transport,
+    name: &str,
+    namespace: &NamespaceConfig,
+    base: &Path,
+    roots: &TrustRoots,
+) -> Result<Refreshed> {
+    let repository = metadata_repository(name, namespace, base)?;
+    let now = Utc::now();
+    let verifiers = SignatureVerifiers::default();
+    let raw = fetch(transport, &namespace.policy, base).await?;
+    let root = verify::resolve_policy(
+        transport,
+        raw,
+        &namespace.policy,
+        roots,
+        &verifiers,
+        &Pipeline::default(),
+        now,
+    )
+    .await?;
+
+    let storage = metadata_storage()?;
+    let mut trusted = trusted_metadata(&storage, name)?;
+    // Whatever verified before a failure is kept, so a later refresh cannot
+    // roll it back.
+    let refreshed = tuf::refresh(
+        transport,
+        &repository,
+        &root,
+        &mut trusted,
+        roots,
+        &verifiers,
+        now,
+    )
+    .await;
+    storage.put(
+        &format!("{}.json", name),
+        &serde_json::to_vec_pretty(&trusted)?,
+    )?;
+    Ok(refreshed?)
+}
+
+fn parse_layer(value: &str) -> Result<LayeredPolicy> {
+    let (level, policy) = value
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected LEVEL=LOCATION, got {}", value))?;
+    Ok(LayeredPolicy {
+        level: level
+            .parse()
+            .map_err(|_| anyhow!("Unknown policy level {}", level))?,
+        policy: policy.to_string(),
+    })
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("resolve", m)) => {
+            let namespace = m
+                .value_of("namespace")
+                .ok_or_else(|| anyhow!("No namespace given"))?;
+            let config = Config::load()?;
+            let window = config.expiry_window()?;
+            let layers = match m.values_of("policy") {
+                Some(values) => values.map(parse_layer).collect::<Result<Vec<_>>>()?,
+                None => config.policies,
+            };
+            if layers.is_empty() {
+                return Err(anyhow!("No policies configured"));
+            }
+            let roots = TrustRoots::load(&TrustStore::open()?)?;
+            let transport = transport::default_transport();
+            let set = load_policy_set(transport.as_ref(), &layers, &roots).await?;
+            let now = Utc::now();
+            let mut warnings = expiry::check_roots(&roots, window, now);
+            for policy in set.policies() {
+                warnings.extend(expiry::check_policy(policy, window, now));
+                warnings.extend(expiry::check_keys(policy, window, now));
+                warnings.extend(expiry::check_roles(policy, window, now));
+            }
+            expiry::report(&warnings)?;
+            let resolved = set
+                .resolve(namespace)
+                .ok_or_else(|| anyhow!("No policy applies to {}", namespace))?;
+            println!("{}", serde_json::to_string_pretty(&resolved)?);
+            Ok(())
+        }
+        Some(("init", m)) => scaffold::run(m),
+        Some(("ceremony", m)) => ceremony::run(m),
+        Some(("promote", m)) => promote::run(m).await,
+        Some(("migrate", m)) => migrate::run(m).await,
+        Some(("refresh", m)) => {
+            let name = m
+                .value_of("namespace")
+                .ok_or_else(|| anyhow!("No namespace given"))?;
+            let config = Config::load()?;
+            // Relative locations in the config are relative to the config file.
+            let base = Config::path()?
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            let roots = TrustRoots::load(&TrustStore::open()?)?;
+            let transport = transport::default_transport();
+            let refreshed = refresh_metadata(
+                transport.as_ref(),
+                name,
+                config.namespace(name)?,
+                &base,
+                &roots,
+            )
+            .await?;
+            println!("{}", serde_json::to_string_pretty(&refreshed)?);
+            Ok(())
+        }
+        Some(("fetch-target", m)) => {
+            let value = |name| m.value_of(name).ok_or_else(|| anyhow!("No {} given", name));
+            let name = value("namespace")?;
+            let path = value("target")?;
+            let output = Path::new(value("output")?);
+            let config = Config::load()?;
+            let base = Config::path()?
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            let namespace = config.namespace(name)?;
+            let roots = TrustRoots::load(&TrustStore::open()?)?;
+            let transport = transport::default_transport();
+            refresh_metadata(transport.as_ref(), name, namespace, &base, &roots).await?;
+            let trusted = trusted_metadata(&metadata_storage()?, name)?;
+            let target = trusted
+                .targets
+                .as_ref()
+                .and_then(|targets| targets.targets.get(path))
+                .ok_or_else(|| anyhow!("{} lists no target {}", name, path))?;
+            let location = match m.value_of("url") {
+                Some(url) => url.to_string(),
+                None => format!(
+                    "{}/targets/{}",
+                    metadata_repository(name, namespace, &base)?.trim_end_matches('/'),
+                    path
+                ),
+            };
+            match &target.merkle {
+                Some(root) => {
+                    let fetched = merkle::download(
+                        transport.as_ref(),
+                        &location,
+                        root,
+                        target.length,
+                        output,
+                    )
+                    .await?;
+                    println!(
+                        "Verified {} chunk by chunk, {} chunks downloaded, written to {}",
+                        path,
+                        fetched,
+                        output.display()
+                    );
+                }
+                None => {
+                    let data = fetch(transport.as_ref(), &location, Path::new("")).await?;
+                    tuf::check_target(target, &data)?;
+                    std::fs::write(output, &data)?;
+                    println!("Verified {}, written to {}", path, output.display());
+                }
+            }
+            Ok(())
+        }
+        _ => Err(anyhow!("Unknown policy subcommand")),
+    }
+}
@@ -6,11 +6,42 @@ use p256::pkcs8::FromPublicKey;
 use serde::{Deserialize, Serialize};
 use serde_json::{value::RawValue, Value};
 use serde_plain::{derive_display_from_serialize, derive_fromstr_from_deserialize};
-use std::{collections::HashMap, convert::TryFrom, num::NonZeroU64};
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::TryFrom,
+    num::NonZeroU64,
+};
 use x509_parser::{parse_x509_certificate, pem::parse_x509_pem};
 
+use crate::targets::{self, TargetRule};
+use crate::timestamp::SignedTimestamp;
+
 pub type CosignVerificationKey = VerifyingKey<p256::NistP256>;
 
+/// The major version of the policy spec sget reads. Minor versions only add
+/// optional fields, which readers of an earlier minor version ignore, so any
+/// policy of this major version is read.
+pub const SPEC_MAJOR: u64 = 1;
+
+/// The spec version of the policies sget writes. Version 1.1 added target
+/// rules, includes, namespace lists, key and role expiry, key names and
+/// signing timestamps; `sget policy migrate` upgrades 1.0 policies.
+pub const SPEC_VERSION: &str = "1.1";
+
+/// The major and minor version of the spec version `version`, e.g. `(1, 0)`
+/// for `1.0`. A missing minor version is 0.
+pub fn parse_spec_version(version: &str) -> Option<(u64, u64)> {
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |minor| minor.parse().ok())?;
+    Some((major, minor))
+}
+
+/// Whether sget reads policies of spec version `version`.
+pub fn supports_spec_version(version: &str) -> bool {
+    matches!(parse_spec_version(version), Some((major, _)) if major == SPEC_MAJOR)
+}
+
 // A signed root policy object
 #[derive(Serialize, Deserialize)]
 pub struct Policy {
@@ -22,7 +53,33 @@ pub struct Policy {
 
 impl Policy {
     pub fn validate_expires(&self) -> chrono::Duration {
-        self.signed.expires.signed_duration_since(Utc::now())
+        self.validate_expires_at(Utc::now())
+    }
+
+    /// Time remaining until the policy expires, measured from `now`.
+    fn validate_expires_at(&self, now: DateTime<Utc>) -> chrono::Duration {
+        self.signed.expires.signed_duration_since(now)
+    }
+
+    /// How to refer to the key with ID `keyid` in messages, see
+    /// [`Key::label`].
+    pub fn key_label(&self, keyid: &str) -> String {
+        key_label(self.signed.keys.get(keyid).and_then(Key::name), keyid)
+    }
+
+    /// Flatten `parent`, the policy this one includes, into this policy. Its
+    /// keys and roles are inherited unless this policy has its own with the
+    /// same ID or name, and its targets unless this policy lists any.
+    pub fn inherit(&mut self, parent: Policy) {
+        for (keyid, key) in parent.signed.keys {
+            self.signed.keys.entry(keyid).or_insert(key);
+        }
+        for (name, role) in parent.signed.roles {
+            self.signed.roles.entry(name).or_insert(role);
+        }
+        if self.signed.targets.is_empty() {
+            self.signed.targets = parent.signed.targets;
+        }
     }
 
     /// Extract the public key from the policy
@@ -56,13 +113,94 @@ impl Policy {
 // 'signatures' and 'signed' fields. We must preserve this data as RawValues
 // in order for signature verification to work.
 #[derive(Serialize, Deserialize)]
-struct RawPolicy<'a> {
+pub struct RawPolicy<'a> {
     #[serde(borrow)]
     pub signatures: &'a RawValue,
     #[serde(borrow)]
     pub signed: &'a RawValue,
 }
 
+/// The spec version of the `signed` section of a serialized policy, read
+/// before anything else so that a policy of an unknown major version is
+/// rejected as such rather than for whatever its new schema breaks.
+#[derive(Deserialize)]
+pub struct SpecVersion {
+    pub spec_version: String,
+}
+
+/// A view of the `signed` section of a serialized policy for verifying its
+/// signatures. Keys are kept as raw JSON and only deserialized when looked up,
+/// so policies with thousands of keys are not materialized before their
+/// signatures are checked, nor afterwards beyond those the policy uses.
+#[derive(Deserialize)]
+pub struct RawSigned<'a> {
+    pub consistent_snapshot: bool,
+    pub expires: DateTime<Utc>,
+    #[serde(borrow, default)]
+    pub keys: HashMap<String, &'a RawValue>,
+    pub namespace: Namespace,
+    #[serde(default)]
+    pub roles: HashMap<String, RoleKeys>,
+    pub spec_version: String,
+    pub version: NonZeroU64,
+    #[serde(default)]
+    pub targets: Vec<TargetRule>,
+    #[serde(default)]
+    pub include: Option<PolicyInclude>,
+}
+
+impl RawSigned<'_> {
+    /// Deserialize the key with ID `keyid`, if the policy has one.
+    pub fn key(&self, keyid: &str) -> Result<Option<Key>> {
+        self.keys
+            .get(keyid)
+            .map(|raw| serde_json::from_str(raw.get()))
+            .transpose()
+            .map_err(|e| anyhow!("Invalid key {}: {}", keyid, e))
+    }
+
+    /// The `signed` section, with only the keys that its roles and target
+    /// rules refer to deserialized. Other keys are left out.
+    pub fn into_signed(self) -> Result<Signed> {
+        let referenced: BTreeSet<&str> = self
+            .roles
+            .values()
+            .flat_map(|role| &role.keyids)
+            .chain(self.targets.iter().flat_map(|rule| &rule.keyids))
+            .map(String::as_str)
+            .collect();
+        let mut keys = HashMap::new();
+        for keyid in referenced {
+            if let Some(key) = self.key(keyid)? {
+                keys.insert(keyid.to_string(), key);
+            }
+        }
+        Ok(Signed {
+            consistent_snapshot: self.consistent_snapshot,
+            expires: self.expires,
+            keys,
+            namespace: self.namespace,
+            roles: self.roles,
+            spec_version: self.spec_version,
+            version: self.version,
+            targets: self.targets,
+            include: self.include,
+        })
+    }
+
+    /// How to refer to the key with ID `keyid`, a key of this policy or of
+    /// `parent`, in messages, see [`Key::label`].
+    pub fn key_label(&self, parent: Option<&Policy>, keyid: &str) -> String {
+        let name = match self.key(keyid) {
+            Ok(Some(key)) => key.name().map(String::from),
+            _ => parent
+                .and_then(|parent| parent.signed.keys.get(keyid))
+                .and_then(|key| key.name().map(String::from)),
+        };
+        key_label(name.as_deref(), keyid)
+    }
+}
+
 // A signature and the key ID and certificate that made it.
 #[derive(Serialize, Deserialize)]
 pub struct Signature {
@@ -72,6 +210,26 @@ pub struct Signature {
     pub sig: String,
     // The base64 encoded certificate that was used to create the signature.
     pub cert: String,
+    // Base64 encoded PEM intermediates between the certificate and a Fulcio
+    // root, issuer first, for private deployments that rotate intermediates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chain: Vec<String>,
+    // Proof of when the signature was made, from Rekor or a timestamp
+    // authority.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<SignedTimestamp>,
+}
+
+impl Signature {
+    /// The PEM encoded certificate followed by its intermediates.
+    pub fn certificate_chain(&self) -> Result<Vec<u8>> {
+        let mut pem = base64::decode(&self.cert)?;
+        for intermediate in &self.chain {
+            pem.push(b'\n');
+            pem.extend(base64::decode(intermediate)?);
+        }
+        Ok(pem)
+    }
 }
 
 // The root policy indicated the trusted root keys.
@@ -79,11 +237,96 @@ pub struct Signature {
 pub struct Signed {
     pub consistent_snapshot: bool,
     pub expires: DateTime<Utc>,
+    /// Keys by ID. A policy that includes a parent may leave out the keys it
+    /// inherits.
+    #[serde(default)]
     pub keys: HashMap<String, Key>,
-    pub namespace: String,
+    pub namespace: Namespace,
+    #[serde(default)]
     pub roles: HashMap<String, RoleKeys>,
     pub spec_version: String,
     pub version: NonZeroU64,
+    /// Which keys may sign artifacts, by path glob.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TargetRule>,
+    /// The parent policy this policy inherits from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<PolicyInclude>,
+}
+
+/// The namespaces a root policy covers: one, or a list, so that one policy
+/// can serve several registries or hosts. Each may be a glob in the syntax of
+/// [target rules](TargetRule), such as `ghcr.io/acme/*`, and also covers the
+/// namespaces below the ones it matches.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Namespace {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Namespace {
+    pub fn patterns(&self) -> &[String] {
+        match self {
+            Namespace::One(pattern) => std::slice::from_ref(pattern),
+            Namespace::Many(patterns) => patterns,
+        }
+    }
+
+    /// The most specific pattern matching `namespace` or one of its parents,
+    /// if any.
+    pub fn covering(&self, namespace: &str) -> Option<&str> {
+        self.patterns()
+            .iter()
+            .map(|pattern| pattern.trim_end_matches('/'))
+            .filter(|pattern| {
+                targets::namespace_parents(namespace)
+                    .any(|parent| targets::glob_matches(pattern, parent))
+            })
+            .max_by_key(|pattern| specificity(pattern))
+    }
+
+    /// Whether `namespace` is one of the namespaces or below one of them.
+    pub fn covers(&self, namespace: &str) -> bool {
+        self.covering(namespace).is_some()
+    }
+}
+
+/// How specific the namespace pattern `pattern` is: the deeper, i.e. the more
+/// literal characters, the more specific, then exact namespaces over globs.
+pub(crate) fn specificity(pattern: &str) -> (usize, bool) {
+    let (exact, literals) = targets::specificity(pattern);
+    (literals, exact)
+}
+
+impl From<&str> for Namespace {
+    fn from(namespace: &str) -> Self {
+        Namespace::One(namespace.to_string())
+    }
+}
+
+impl PartialEq<&str> for Namespace {
+    fn eq(&self, other: &&str) -> bool {
+        self.patterns() == [*other]
+    }
+}
+
+impl std::fmt::Display for Namespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.patterns().join(", "))
+    }
+}
+
+/// A parent policy whose keys, roles and targets a policy inherits, pinned by
+/// digest. Organizations can keep one base policy and thin per-project
+/// policies that include it and override only what differs, usually the
+/// namespace and targets.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PolicyInclude {
+    /// URL or path of the parent policy, relative to the including policy.
+    pub url: String,
+    /// Hex encoded SHA-256 digest of the parent policy.
+    pub sha256: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -92,6 +335,33 @@ pub struct RoleKeys {
     pub keyids: Vec<String>,
     /// The threshold of signatures required to validate the role.
     pub threshold: NonZeroU64,
+    /// When the role stops being trusted, independently of the policy's own
+    /// expiry, so that short-lived roles such as timestamp can be made to
+    /// expire long before root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+    /// A human-friendly name for the role's key holders, such as
+    /// `release-managers`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// What the role is for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// How to refer to a key named `name` with ID `keyid` in messages.
+pub fn key_label(name: Option<&str>, keyid: &str) -> String {
+    match name {
+        Some(name) => format!("'{}'", name),
+        None => keyid.to_string(),
+    }
+}
+
+impl RoleKeys {
+    /// Whether the role has expired at `now`.
+    pub fn expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
 }
 
 #[derive(PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -115,8 +385,10 @@ impl TryFrom<&str> for RoleType {
 derive_display_from_serialize!(RoleType);
 derive_fromstr_from_deserialize!(RoleType);
 
+// Derived (de)serialization covers the built-in key types; the trait impls
+// below route any other key type to `Key::Other`.
 #[derive(Serialize, Deserialize)]
-#[serde(tag = "keytype")]
+#[serde(remote = "Self", tag = "keytype")]
 pub enum Key {
     /// A sigstore oidc key.
     #[serde(rename = "sigstore-oidc")]
@@ -125,11 +397,192 @@ pub enum Key {
         keyval: SigstoreOidcKey,
         /// Denotes the key's scheme
         scheme: String,
-        /// Any additional fields read during deserialization; will not be used.
-        // TODO: key_hash_algorithms
         #[serde(flatten)]
-        _extra: HashMap<String, Value>,
+        metadata: KeyMetadata,
+    },
+    /// A locally generated ECDSA P-256 key.
+    #[serde(rename = "ecdsa-sha2-nistp256")]
+    EcdsaP256 {
+        /// The PEM encoded public key.
+        keyval: PublicKeyVal,
+        /// Denotes the key's scheme
+        scheme: String,
+        #[serde(flatten)]
+        metadata: KeyMetadata,
     },
+    /// A locally generated Ed25519 key.
+    #[serde(rename = "ed25519")]
+    Ed25519 {
+        /// The PEM encoded public key.
+        keyval: PublicKeyVal,
+        /// Denotes the key's scheme
+        scheme: String,
+        #[serde(flatten)]
+        metadata: KeyMetadata,
+    },
+    /// An OpenPGP key, listed by fingerprint. Artifacts are verified with it
+    /// from a keyring; it cannot sign policies.
+    #[serde(rename = "pgp")]
+    Pgp {
+        /// The key's fingerprint.
+        keyval: PgpKeyVal,
+        /// Denotes the key's scheme
+        scheme: String,
+        #[serde(flatten)]
+        metadata: KeyMetadata,
+    },
+    /// An SSH key. Artifacts are verified with it against an allowed signers
+    /// file; policies are signed with `ssh-keygen -Y sign -n sget-policy`.
+    #[serde(rename = "ssh")]
+    Ssh {
+        /// The public key in the `authorized_keys` format.
+        keyval: PublicKeyVal,
+        /// Denotes the key's scheme
+        scheme: String,
+        #[serde(flatten)]
+        metadata: KeyMetadata,
+    },
+    /// A key type without built-in support, verified by a registered
+    /// [`SignatureVerifier`](crate::signature::SignatureVerifier).
+    #[serde(skip)]
+    Other(OtherKey),
+}
+
+/// The fields every key type has besides its `keytype`, `scheme` and
+/// `keyval`.
+#[derive(Default, Serialize, Deserialize)]
+pub struct KeyMetadata {
+    /// When the key stops being trusted, see [`Key::expires`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+    /// A human-friendly name for the key, see [`Key::name`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// What the key is used for or who holds it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Any additional fields of the key; not used by sget.
+    // TODO: key_hash_algorithms
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The `keytype`s with built-in support.
+const BUILTIN_KEY_TYPES: [&str; 5] = [
+    "sigstore-oidc",
+    "ecdsa-sha2-nistp256",
+    "ed25519",
+    "pgp",
+    "ssh",
+];
+
+impl Key {
+    /// The key type, as in the policy's `keytype` field.
+    pub fn keytype(&self) -> &str {
+        match self {
+            Key::SigstoreOidc { .. } => "sigstore-oidc",
+            Key::EcdsaP256 { .. } => "ecdsa-sha2-nistp256",
+            Key::Ed25519 { .. } => "ed25519",
+            Key::Pgp { .. } => "pgp",
+            Key::Ssh { .. } => "ssh",
+            Key::Other(key) => &key.keytype,
+        }
+    }
+
+    /// When the key stops being trusted, independently of the policy's own
+    /// expiry, so that a key suspected to be compromised can be retired before
+    /// the policy is rotated. Policy signatures by an expired key do not count,
+    /// and artifacts it signs are rejected unless their signature was logged
+    /// before it expired.
+    pub fn expires(&self) -> Option<DateTime<Utc>> {
+        self.metadata().expires
+    }
+
+    /// Whether the key has expired at `time`.
+    pub fn expired_at(&self, time: DateTime<Utc>) -> bool {
+        self.expires().is_some_and(|expires| expires <= time)
+    }
+
+    /// A human-friendly name for the key, such as `alice-yubikey`, shown
+    /// next to or instead of its key ID. Names are not unique and carry no
+    /// trust; roles and signatures always refer to keys by ID.
+    pub fn name(&self) -> Option<&str> {
+        self.metadata().name.as_deref()
+    }
+
+    /// What the key is used for or who holds it.
+    pub fn description(&self) -> Option<&str> {
+        self.metadata().description.as_deref()
+    }
+
+    /// The fields the key has whatever its type.
+    pub fn metadata(&self) -> &KeyMetadata {
+        match self {
+            Key::SigstoreOidc { metadata, .. }
+            | Key::EcdsaP256 { metadata, .. }
+            | Key::Ed25519 { metadata, .. }
+            | Key::Pgp { metadata, .. }
+            | Key::Ssh { metadata, .. } => metadata,
+            Key::Other(key) => &key.metadata,
+        }
+    }
+
+    /// The fields the key has whatever its type, for editing.
+    pub fn metadata_mut(&mut self) -> &mut KeyMetadata {
+        match self {
+            Key::SigstoreOidc { metadata, .. }
+            | Key::EcdsaP256 { metadata, .. }
+            | Key::Ed25519 { metadata, .. }
+            | Key::Pgp { metadata, .. }
+            | Key::Ssh { metadata, .. } => metadata,
+            Key::Other(key) => &mut key.metadata,
+        }
+    }
+
+    /// How to refer to the key with ID `keyid` in messages: its quoted name,
+    /// or the key ID for keys without one.
+    pub fn label(&self, keyid: &str) -> String {
+        key_label(self.name(), keyid)
+    }
+
+    /// The signature scheme the key is used with.
+    pub fn scheme(&self) -> &str {
+        match self {
+            Key::SigstoreOidc { scheme, .. }
+            | Key::EcdsaP256 { scheme, .. }
+            | Key::Ed25519 { scheme, .. }
+            | Key::Pgp { scheme, .. }
+            | Key::Ssh { scheme, .. } => scheme,
+            Key::Other(key) => &key.scheme,
+        }
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Key::Other(key) => key.serialize(serializer),
+            _ => Key::serialize(self, serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+        let value = Value::deserialize(deserializer)?;
+        let builtin = matches!(
+            value.get("keytype").and_then(Value::as_str),
+            Some(keytype) if BUILTIN_KEY_TYPES.contains(&keytype)
+        );
+        if builtin {
+            Key::deserialize(value).map_err(D::Error::custom)
+        } else {
+            OtherKey::deserialize(value)
+                .map(Key::Other)
+                .map_err(D::Error::custom)
+        }
+    }
 }
 
 derive_display_from_serialize!(Key);
@@ -144,6 +597,30 @@ pub struct SigstoreOidcKey {
     pub issuer: String,
 }
 
+/// A key of a type sget does not know, kept as it appears in the policy.
+#[derive(Serialize, Deserialize)]
+pub struct OtherKey {
+    pub keytype: String,
+    pub scheme: String,
+    pub keyval: Value,
+    #[serde(flatten)]
+    pub metadata: KeyMetadata,
+}
+
+#[derive(Serialize, Deserialize)]
+/// Represents a public key supplied directly in the policy.
+pub struct PublicKeyVal {
+    /// The PEM encoded public key, or for `ssh` keys the `authorized_keys` line.
+    pub public: String,
+}
+
+#[derive(Serialize, Deserialize)]
+/// Represents an OpenPGP key listed in the policy.
+pub struct PgpKeyVal {
+    /// The hex encoded v4 fingerprint of the primary key.
+    pub fingerprint: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -198,7 +675,31 @@ mod tests {
     fn validate_expiry_success() {
         let setup = Setup::new();
         let policy = setup.read_good_policy();
-        assert!(!policy.validate_expires().to_std().is_err());
+        // The good policy was signed on 2021-11-23 and expires on 2022-02-23.
+        let now = "2021-11-24T00:00:00Z".parse().unwrap(); //#[allow_ci]
+        assert!(policy.validate_expires_at(now).to_std().is_ok());
+    }
+
+    #[test]
+    fn certificate_chain_with_intermediates() {
+        let setup = Setup::new();
+        let mut policy = setup.read_good_policy();
+        let signature = &mut policy.signatures[0];
+        let leaf = crate::verify_core::pem_certificates(
+            &signature.certificate_chain().expect("Invalid chain"),
+        )
+        .expect("Invalid certificate");
+        assert_eq!(leaf.len(), 1);
+
+        signature.chain = vec![signature.cert.clone()];
+        let chain = crate::verify_core::pem_certificates(
+            &signature.certificate_chain().expect("Invalid chain"),
+        )
+        .expect("Invalid certificate");
+        assert_eq!(chain, vec![leaf[0].clone(), leaf[0].clone()]);
+        assert!(serde_json::to_string(&*signature)
+            .expect("Cannot serialize")
+            .contains("\"chain\""));
     }
 
     #[test]
@@ -243,4 +744,50 @@ mod tests {
         let outcome = policy.verify_signature(&pub_key.unwrap(), msg); //#[allow_ci]
         assert!(outcome.is_err());
     }
+
+    #[test]
+    fn key_metadata_round_trip() {
+        for key in [
+            r#"{"keytype":"ed25519","scheme":"ed25519","keyval":{"public":"x"},"name":"alice","description":"laptop","key_hash_algorithms":["sha256"]}"#,
+            r#"{"keytype":"other","scheme":"x","keyval":{},"expires":"2030-01-01T00:00:00Z","name":"bob"}"#,
+        ] {
+            let parsed: Key = serde_json::from_str(key).expect("Cannot parse key");
+            assert!(parsed.name().is_some());
+            assert_eq!(
+                serde_json::to_value(&parsed).expect("Cannot encode key"),
+                serde_json::from_str::<Value>(key).expect("Invalid JSON")
+            );
+        }
+    }
+
+    #[test]
+    fn lazy_signed_view() {
+        let setup = Setup::new();
+        let policy = setup.read_good_policy();
+        let raw_json = read(&setup.good_policy).expect("Cannot read good policy file");
+        let raw_policy: RawPolicy =
+            serde_json::from_slice(&raw_json).expect("Could not create Raw Policy");
+        let view: RawSigned =
+            serde_json::from_str(raw_policy.signed.get()).expect("Could not create view");
+        assert_eq!(view.expires, policy.signed.expires);
+        let (keyid, key) = policy.signed.keys.iter().next().expect("No keys");
+        let lazy = view.key(keyid).expect("Invalid key").expect("Missing key");
+        assert_eq!(
+            serde_json::to_value(&lazy).expect("Cannot encode key"),
+            serde_json::to_value(key).expect("Cannot encode key")
+        );
+        assert!(view.key("missing").expect("Invalid key").is_none());
+
+        // Keys are only deserialized when looked up.
+        let signed = r#"{"consistent_snapshot": true, "expires": "2022-02-23T00:00:00Z",
+            "namespace": "example.com", "spec_version": "1.0", "version": 1,
+            "roles": {"root": {"keyids": ["good"], "threshold": 1}},
+            "keys": {"good": {"keytype": "other", "scheme": "x", "keyval": {}}, "bad": 7}}"#;
+        let view: RawSigned = serde_json::from_str(signed).expect("Could not create view");
+        assert!(view.key("good").is_ok());
+        assert!(view.key("bad").is_err());
+        // Nor are keys that no role or target rule refers to.
+        let signed = view.into_signed().expect("Could not build signed");
+        assert_eq!(signed.keys.keys().collect::<Vec<_>>(), vec!["good"]);
+    }
 }
@@ -0,0 +1,400 @@
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_plain::{derive_display_from_serialize, derive_fromstr_from_deserialize};
+
+use crate::error::{Result, SgetError};
+use crate::pgp;
+use crate::policy::{self, Key, Policy};
+use crate::ssh::SshPublicKey;
+use crate::targets;
+use crate::verify_core::Signer;
+
+/// Name of the role listing keys a policy denies.
+pub const DENY_ROLE: &str = "deny";
+
+/// Where a policy sits in a [`PolicySet`], from least to most specific.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PolicyLevel {
+    /// Policies every user of the machine is held to.
+    System,
+    /// Policies of the organization publishing artifacts.
+    Org,
+    /// Policies of a single project.
+    Project,
+}
+
+derive_display_from_serialize!(PolicyLevel);
+derive_fromstr_from_deserialize!(PolicyLevel);
+
+/// Several verified root policies in force at once, e.g. a system, an
+/// organization and a project policy.
+///
+/// Which signers a namespace trusts is decided by [`PolicySet::resolve`]:
+///
+/// - A policy applies to its own namespaces and to the namespaces below them,
+///   so `ghcr.io/acme` applies to `ghcr.io/acme/web`, as does `ghcr.io/*`.
+/// - The root keys of the applicable policy with the most specific namespace
+///   are allowed; deeper namespaces are more specific, and exact namespaces
+///   more specific than globs of the same depth. When policies at several levels share that namespace, the
+///   most specific level wins: project over org over system.
+/// - Keys listed in the `deny` role of any applicable policy, at any level,
+///   are denied even where another policy allows them.
+#[derive(Default)]
+pub struct PolicySet {
+    policies: Vec<(PolicyLevel, Policy)>,
+}
+
+impl PolicySet {
+    /// Add a policy that has already been verified.
+    pub fn add(&mut self, level: PolicyLevel, policy: Policy) {
+        self.policies.push((level, policy));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// The policies in the set, in the order they were added.
+    pub fn policies(&self) -> impl Iterator<Item = &Policy> {
+        self.policies.iter().map(|(_, policy)| policy)
+    }
+
+    /// The effective policy for `namespace`, or `None` if no policy applies.
+    pub fn resolve(&self, namespace: &str) -> Option<ResolvedPolicy<'_>> {
+        let applicable: Vec<&(PolicyLevel, Policy)> = self
+            .policies
+            .iter()
+            .filter(|(_, policy)| policy.signed.namespace.covers(namespace))
+            .collect();
+        let (level, source) = applicable.iter().max_by_key(|(level, policy)| {
+            let pattern = policy.signed.namespace.covering(namespace);
+            (pattern.map(policy::specificity), *level)
+        })?;
+        Some(ResolvedPolicy {
+            namespace: namespace.to_string(),
+            source: PolicySource::new(*level, source),
+            allow: role_keys(*level, source, "root"),
+            deny: applicable
+                .iter()
+                .flat_map(|(level, policy)| role_keys(*level, policy, DENY_ROLE))
+                .collect(),
+        })
+    }
+}
+
+/// The keys of `role` in `policy`.
+fn role_keys<'a>(level: PolicyLevel, policy: &'a Policy, role: &str) -> Vec<ResolvedKey<'a>> {
+    let keyids = match policy.signed.roles.get(role) {
+        Some(role) => &role.keyids,
+        None => return Vec::new(),
+    };
+    keyids
+        .iter()
+        .filter_map(|keyid| {
+            policy.signed.keys.get(keyid).map(|key| ResolvedKey {
+                source: PolicySource::new(level, policy),
+                keyid,
+                key,
+            })
+        })
+        .collect()
+}
+
+/// The policy a resolved key or decision comes from.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PolicySource {
+    pub level: PolicyLevel,
+    pub namespace: String,
+    pub version: u64,
+}
+
+impl PolicySource {
+    fn new(level: PolicyLevel, policy: &Policy) -> Self {
+        PolicySource {
+            level,
+            namespace: policy.signed.namespace.to_string(),
+            version: policy.signed.version.get(),
+        }
+    }
+}
+
+/// A key allowed or denied by the effective policy.
+#[derive(Serialize)]
+pub struct ResolvedKey<'a> {
+    #[serde(flatten)]
+    pub source: PolicySource,
+    pub keyid: &'a str,
+    pub key: &'a Key,
+}
+
+/// The effective policy of a namespace, merged from a [`PolicySet`].
+#[derive(Serialize)]
+pub struct ResolvedPolicy<'a> {
+    pub namespace: String,
+    /// The policy whose root keys are allowed.
+    pub source: PolicySource,
+    pub allow: Vec<ResolvedKey<'a>>,
+    pub deny: Vec<ResolvedKey<'a>>,
+}
+
+impl ResolvedPolicy<'_> {
+    /// Check that `signer` is allowed and not denied.
+    pub fn check(&self, signer: &Signer) -> Result<()> {
+        if let Some(denied) = self.deny.iter().find(|k| key_matches(k.key, signer)) {
+            return Err(SgetError::UntrustedSigner(format!(
+                "{} is denied by the {} policy for {}",
+                signer.subject(),
+                denied.source.level,
+                denied.source.namespace
+            )));
+        }
+        if !self.allow.iter().any(|k| key_matches(k.key, signer)) {
+            return Err(SgetError::UntrustedSigner(format!(
+                "{} is not a key of the {} policy for {}",
+                signer.subject(),
+                self.source.level,
+                self.source.namespace
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Check that the artifact at `location` is under one of the namespaces of
+/// `policy`, matching URLs by host and path and OCI references by registry and
+/// repository, see [`targets::namespace_name`]. Artifacts that cannot be
+/// placed in a namespace, such as local paths and OCI image layouts, are
+/// refused. Without a location there is nothing to check.
+pub(crate) fn check_namespace(policy: &Policy, location: Option<&str>) -> Result<()> {
+    let location = match location {
+        Some(location) => location,
+        None => return Ok(()),
+    };
+    match targets::namespace_name(location) {
+        Some(name) if policy.signed.namespace.covers(&name) => Ok(()),
+        Some(_) => Err(SgetError::UntrustedSigner(format!(
+            "{} is not under the namespaces of the {} policy",
+            location, policy.signed.namespace
+        ))),
+        None => Err(SgetError::UntrustedSigner(format!(
+            "{} is not a URL or OCI reference, so cannot be placed under the namespaces of the {} policy",
+            location, policy.signed.namespace
+        ))),
+    }
+}
+
+/// Whether `signer` may sign the artifact at `location` under `policy`: it
+/// must be a key of the policy's most specific [target
+/// rule](crate::targets::TargetRule) for the artifact, or one of the root keys
+/// when no rule matches or the location is unknown.
+pub(crate) fn policy_trusts(policy: &Policy, location: Option<&str>, signer: &Signer) -> bool {
+    let rule = location.and_then(|location| {
+        targets::rule_for(
+            &policy.signed.targets,
+            &targets::target_path(&policy.signed.namespace, location),
+        )
+    });
+    let keyids = match rule {
+        Some(rule) => &rule.keyids,
+        None => match policy.signed.roles.get("root") {
+            Some(role) => &role.keyids,
+            None => return false,
+        },
+    };
+    keyids
+        .iter()
+        .filter_map(|keyid| policy.signed.keys.get(keyid))
+        .any(|key| key_matches(key, signer))
+}
+
+/// Whether `signer` made its signature with `key`, while the key had not
+/// expired: at the time the signature was logged, or now for signatures
+/// that were not logged.
+pub(crate) fn key_matches(key: &Key, signer: &Signer) -> bool {
+    let signed_at = signer
+        .integrated_time
+        .and_then(|time| Utc.timestamp_opt(time, 0).single())
+        .unwrap_or_else(Utc::now);
+    if key.expired_at(signed_at) {
+        return false;
+    }
+    match key {
+        Key::SigstoreOidc { keyval, .. } => {
+            signer.identity.as_deref() == Some(keyval.identity.as_str())
+                && (keyval.issuer.is_empty() || signer.issuer.as_deref() == Some(&keyval.issuer))
+        }
+        Key::EcdsaP256 { keyval, .. } | Key::Ed25519 { keyval, .. } => {
+            crate::keys::PublicKey::from_pem(&keyval.public)
+                .and_then(|key| key.key_id())
+                .ok()
+                .as_ref()
+                == Some(&signer.key_id)
+        }
+        Key::Ssh { keyval, .. } => {
+            SshPublicKey::from_openssh(&keyval.public)
+                .map(|key| key.fingerprint())
+                .ok()
+                .as_ref()
+                == Some(&signer.key_id)
+        }
+        Key::Pgp { keyval, .. } => pgp::normalize_fingerprint(&keyval.fingerprint) == signer.key_id,
+        // Artifact signatures are only ever verified with built-in keys.
+        Key::Other(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy(namespace: impl Into<serde_json::Value>, allow: &[&str], deny: &[&str]) -> Policy {
+        let key = |identity: &str| {
+            json!({
+                "keytype": "sigstore-oidc",
+                "scheme": "https://fulcio.sigstore.dev",
+                "keyval": { "identity": identity, "issuer": "" },
+            })
+        };
+        let keys: serde_json::Map<String, serde_json::Value> = allow
+            .iter()
+            .chain(deny)
+            .map(|identity| (identity.to_string(), key(identity)))
+            .collect();
+        serde_json::from_value(json!({
+            "signatures": [],
+            "signed": {
+                "consistent_snapshot": false,
+                "expires": "2030-01-01T00:00:00Z",
+                "keys": keys,
+                "namespace": namespace.into(),
+                "roles": {
+                    "root": { "keyids": allow, "threshold": 1 },
+                    "deny": { "keyids": deny, "threshold": 1 },
+                },
+                "spec_version": "1.0",
+                "version": 1,
+            },
+        }))
+        .expect("Invalid policy")
+    }
+
+    fn signer(identity: &str) -> Signer {
+        Signer {
+            key_id: String::new(),
+            identity: Some(identity.to_string()),
+            issuer: None,
+            integrated_time: None,
+        }
+    }
+
+    #[test]
+    fn resolve_precedence() {
+        let mut set = PolicySet::default();
+        set.add(
+            PolicyLevel::System,
+            policy("ghcr.io", &["ops@acme.dev"], &["mallory@acme.dev"]),
+        );
+        set.add(
+            PolicyLevel::Org,
+            policy("ghcr.io/acme", &["org@acme.dev", "mallory@acme.dev"], &[]),
+        );
+        set.add(
+            PolicyLevel::Project,
+            policy("ghcr.io/acme", &["dev@acme.dev"], &[]),
+        );
+
+        // The project policy wins the tie on the most specific namespace.
+        let web = set.resolve("ghcr.io/acme/web").expect("No policy");
+        assert_eq!(web.source.level, PolicyLevel::Project);
+        assert!(web.check(&signer("dev@acme.dev")).is_ok());
+        assert!(web.check(&signer("org@acme.dev")).is_err());
+        assert!(web.check(&signer("ops@acme.dev")).is_err());
+
+        // Deny overrides allow, whichever level denies.
+        let mut org = PolicySet::default();
+        org.add(
+            PolicyLevel::System,
+            policy("ghcr.io", &[], &["mallory@acme.dev"]),
+        );
+        org.add(
+            PolicyLevel::Org,
+            policy("ghcr.io/acme", &["mallory@acme.dev"], &[]),
+        );
+        let resolved = org.resolve("ghcr.io/acme").expect("No policy");
+        assert!(matches!(
+            resolved.check(&signer("mallory@acme.dev")),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+
+        // Only whole path segments match.
+        let other = set.resolve("ghcr.io/acme-evil").expect("No policy");
+        assert_eq!(other.source.level, PolicyLevel::System);
+        assert!(other.check(&signer("ops@acme.dev")).is_ok());
+        assert!(set.resolve("docker.io/acme").is_none());
+    }
+
+    #[test]
+    fn match_namespaces() {
+        let tools = policy(
+            vec!["ghcr.io/acme/tools", "ghcr.io/*/scripts", "example.com/ci/"],
+            &["ops@acme.dev"],
+            &[],
+        );
+        let namespace = &tools.signed.namespace;
+        assert!(namespace.covers("ghcr.io/acme/tools"));
+        assert!(namespace.covers("ghcr.io/acme/tools/lint"));
+        assert!(namespace.covers("ghcr.io/other/scripts/install"));
+        assert!(namespace.covers("example.com/ci"));
+        assert!(!namespace.covers("ghcr.io/acme/toolsevil"));
+        assert!(!namespace.covers("ghcr.io/acme/web"));
+        assert_eq!(
+            namespace.to_string(),
+            "ghcr.io/acme/tools, ghcr.io/*/scripts, example.com/ci/"
+        );
+
+        // Artifacts requested under other namespaces are refused, whoever
+        // signed them.
+        let ops = signer("ops@acme.dev");
+        let install = Some("https://ghcr.io/acme/scripts/install.sh");
+        check_namespace(&tools, install).expect("Namespace not covered");
+        assert!(policy_trusts(&tools, install, &ops));
+        assert!(matches!(
+            check_namespace(&tools, Some("https://ghcr.io/acme/web/install.sh")),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+        assert!(check_namespace(&tools, Some("install.sh")).is_err());
+        assert!(check_namespace(&tools, Some("oci-layout:/tmp/tools#v1")).is_err());
+        check_namespace(&tools, None).expect("No location to check");
+
+        // OCI references are matched by registry and repository.
+        let lint = Some("oci://ghcr.io/acme/tools/lint:v1");
+        check_namespace(&tools, lint).expect("Namespace not covered");
+        assert!(policy_trusts(&tools, lint, &ops));
+        check_namespace(
+            &tools,
+            Some("containers-storage:ghcr.io/other/scripts@sha256:af13"),
+        )
+        .expect("Namespace not covered");
+        assert!(matches!(
+            check_namespace(&tools, Some("oci://ghcr.io/acme/web:v1")),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+        assert!(check_namespace(&tools, Some("oci://ghcr.io/acme/toolsevil:v1")).is_err());
+
+        // The deepest matching namespace is the most specific, glob or not.
+        let mut set = PolicySet::default();
+        set.add(PolicyLevel::Project, tools);
+        set.add(
+            PolicyLevel::Project,
+            policy("ghcr.io/acme", &["dev@acme.dev"], &[]),
+        );
+        let scripts = set.resolve("ghcr.io/acme/scripts").expect("No policy");
+        assert!(scripts.source.namespace.starts_with("ghcr.io/acme/tools, "));
+        assert!(scripts.check(&ops).is_ok());
+        let web = set.resolve("ghcr.io/acme/web").expect("No policy");
+        assert!(web.check(&signer("dev@acme.dev")).is_ok());
+        assert!(set.resolve("docker.io/acme/scripts").is_none());
+    }
+}
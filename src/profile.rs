@@ -0,0 +1,161 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use crate::config::{Config, LayeredPolicy, NamespaceConfig};
+use crate::identities::IdentityRule;
+use crate::sigstore_env::SigstoreEnv;
+
+/// The environment variable naming the profile to use when `--profile` is
+/// not given.
+pub const PROFILE_ENV: &str = "SGET_PROFILE";
+
+/// The trust settings of one project or organisation, kept under `profiles` in
+/// the configuration and selected with `--profile NAME` or `SGET_PROFILE`:
+///
+/// ```yaml
+/// profiles:
+///   prod:
+///     fulcio_url: https://fulcio.corp.example
+///     rekor_url: https://rekor.corp.example
+///     policies:
+///       - level: org
+///         policy: https://corp.example/sget/root.json
+///     identities:
+///       - pattern: https://corp.example/scripts/**
+///         signers:
+///           - identity: release@corp.example
+/// ```
+///
+/// Each profile keeps its trust store, caches and logs in its own state
+/// directory, so the roots trusted for one organisation never apply to
+/// another's artifacts.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// The sigstore environment to select, unless `--sigstore-env` is given.
+    pub sigstore_env: Option<SigstoreEnv>,
+    /// The Fulcio instance to sign with [default: the sigstore environment's].
+    pub fulcio_url: Option<String>,
+    /// The Rekor log to upload signatures to [default: the sigstore
+    /// environment's].
+    pub rekor_url: Option<String>,
+    /// Root policies enforced instead of the top-level `policies`.
+    #[serde(default)]
+    pub policies: Vec<LayeredPolicy>,
+    /// Namespaces added to the top-level ones, replacing any of the same name.
+    #[serde(default)]
+    pub namespaces: BTreeMap<String, NamespaceConfig>,
+    /// Signers required for artifacts, in addition to those of
+    /// `identities.yaml`.
+    #[serde(default)]
+    pub identities: Vec<IdentityRule>,
+    /// Name of the profile's directory under `profiles` in the state
+    /// directory [default: the profile's name].
+    pub cache_namespace: Option<String>,
+}
+
+impl Profile {
+    /// Apply the profile's policy sources and namespaces to `config`.
+    pub fn apply(&self, config: &mut Config) {
+        if !self.policies.is_empty() {
+            config.policies = self.policies.clone();
+        }
+        config.namespaces.extend(self.namespaces.clone());
+    }
+}
+
+static ACTIVE: OnceLock<(String, Profile)> = OnceLock::new();
+
+/// Use the profile `name` of the configuration for the rest of this process.
+/// Must be called before anything reads the configuration or state directory.
+pub fn select(name: &str) -> Result<()> {
+    let profile = Config::load()?
+        .profiles
+        .remove(name)
+        .ok_or_else(|| anyhow!("Profile {} is not configured", name))?;
+    let namespace = profile.cache_namespace.as_deref().unwrap_or(name);
+    let valid = !namespace.is_empty()
+        && !namespace.starts_with('.')
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+    if !valid {
+        return Err(anyhow!("Invalid cache namespace {:?}", namespace));
+    }
+    if let Some(env) = profile.sigstore_env {
+        SigstoreEnv::select(env);
+    }
+    ACTIVE
+        .set((name.to_string(), profile))
+        .map_err(|_| anyhow!("A profile is already selected"))
+}
+
+/// The name and settings of the selected profile, if any.
+pub fn active() -> Option<(&'static str, &'static Profile)> {
+    ACTIVE.get().map(|(name, profile)| (name.as_str(), profile))
+}
+
+/// The state directory namespace of the selected profile, if any.
+pub fn cache_namespace() -> Option<&'static str> {
+    active().map(|(name, profile)| profile.cache_namespace.as_deref().unwrap_or(name))
+}
+
+/// The Fulcio instance to sign with.
+pub fn fulcio_url() -> String {
+    active()
+        .and_then(|(_, profile)| profile.fulcio_url.clone())
+        .unwrap_or_else(|| SigstoreEnv::current().fulcio_url().to_string())
+}
+
+/// The Rekor log to upload signatures to.
+pub fn rekor_url() -> String {
+    active()
+        .and_then(|(_, profile)| profile.rekor_url.clone())
+        .unwrap_or_else(|| SigstoreEnv::current().rekor_url().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy_set::PolicyLevel;
+
+    #[test]
+    fn apply_profile() {
+        let mut config: Config = serde_yaml::from_str(
+            "policies:\n\
+             - level: project\n  policy: personal.json\n\
+             namespaces:\n\
+             \x20 tools:\n    policy: tools.json\n\
+             \x20 shared:\n    policy: personal-shared.json\n\
+             profiles:\n\
+             \x20 prod:\n\
+             \x20   rekor_url: https://rekor.corp.example\n\
+             \x20   policies:\n\
+             \x20   - level: org\n      policy: https://corp.example/root.json\n\
+             \x20   namespaces:\n\
+             \x20     shared:\n        policy: corp-shared.json\n\
+             \x20   identities:\n\
+             \x20   - pattern: https://corp.example/**\n\
+             \x20     signers:\n\
+             \x20     - identity: release@corp.example\n",
+        )
+        .expect("Invalid config");
+        let profile = config.profiles["prod"].clone();
+        assert_eq!(
+            profile.rekor_url.as_deref(),
+            Some("https://rekor.corp.example")
+        );
+        assert_eq!(profile.identities.len(), 1);
+        profile.apply(&mut config);
+        assert_eq!(config.policies.len(), 1);
+        assert_eq!(config.policies[0].level, PolicyLevel::Org);
+        assert_eq!(config.namespaces["tools"].policy, "tools.json");
+        assert_eq!(config.namespaces["shared"].policy, "corp-shared.json");
+
+        assert!(serde_yaml::from_str::<Profile>("rekor: https://rekor.example").is_err());
+        // Selecting a profile is process-wide, so none is selected here.
+        assert!(active().is_none());
+    }
+}
@@ -0,0 +1,329 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, SubsecRound, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::num::NonZeroU64;
+use std::path::Path;
+
+use crate::fetch::fetch;
+use crate::pipeline::Pipeline;
+use crate::policy::Policy;
+use crate::signature::SignatureVerifiers;
+use crate::sigstore_env::SigstoreEnv;
+use crate::state;
+use crate::transport;
+use crate::trust::TrustStore;
+use crate::utils::from_now;
+use crate::verify::{self, TrustRoots};
+
+/// Replaces a staging namespace or URL prefix with its production
+/// counterpart, e.g. `ghcr.io/acme-staging=ghcr.io/acme`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mapping {
+    pub from: String,
+    pub to: String,
+}
+
+impl std::str::FromStr for Mapping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok(Mapping {
+                from: from.trim_end_matches('/').to_string(),
+                to: to.trim_end_matches('/').to_string(),
+            }),
+            _ => Err(anyhow!("Expected FROM=TO, got {}", s)),
+        }
+    }
+}
+
+/// `value` with the prefix of the longest mapping matching whole path
+/// segments replaced, or `None` if no mapping matches.
+fn retarget(value: &str, mappings: &[Mapping]) -> Option<String> {
+    mappings
+        .iter()
+        .filter_map(|mapping| {
+            let rest = value.strip_prefix(&mapping.from)?;
+            if rest.is_empty() || rest.starts_with('/') {
+                Some((mapping, rest))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|(mapping, _)| mapping.from.len())
+        .map(|(mapping, rest)| format!("{}{}", mapping.to, rest))
+}
+
+/// How a verified staging policy becomes a production policy.
+pub struct Promotion {
+    pub mappings: Vec<Mapping>,
+    /// The version of the production policy [default: the staging version
+    /// plus one].
+    pub version: Option<NonZeroU64>,
+    /// The new expiry [default: that of the staging policy].
+    pub expires: Option<DateTime<Utc>>,
+    /// The digest of the production parent policy, required when the
+    /// included parent is re-targeted.
+    pub include_sha256: Option<String>,
+}
+
+impl Promotion {
+    /// The unsigned production policy for the staging policy `raw`: every
+    /// namespace and the included parent re-targeted, the version bumped, and
+    /// the staging signatures dropped. Keys, roles and target rules carry
+    /// over unchanged.
+    pub fn apply(&self, raw: &[u8]) -> Result<Vec<u8>> {
+        let policy: Value = serde_json::from_slice(raw).context("Invalid policy")?;
+        let mut signed = policy
+            .get("signed")
+            .cloned()
+            .ok_or_else(|| anyhow!("Policy has no signed section"))?;
+
+        let namespace = signed
+            .get_mut("namespace")
+            .ok_or_else(|| anyhow!("Policy has no namespace"))?;
+        let patterns: Vec<&mut Value> = match namespace {
+            Value::Array(patterns) => patterns.iter_mut().collect(),
+            namespace => vec![namespace],
+        };
+        for pattern in patterns {
+            let staging = pattern.as_str().unwrap_or_default().to_string();
+            let production = retarget(&staging, &self.mappings)
+                .ok_or_else(|| anyhow!("No mapping re-targets namespace {}", staging))?;
+            *pattern = Value::String(production);
+        }
+
+        if let Some(include) = signed.get_mut("include").and_then(Value::as_object_mut) {
+            let url = include
+                .get("url")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            if let Some(production) = retarget(&url, &self.mappings) {
+                let sha256 = self.include_sha256.clone().ok_or_else(|| {
+                    anyhow!(
+                        "The included policy moves to {}; give its digest with --include-sha256",
+                        production
+                    )
+                })?;
+                include.insert("url".to_string(), Value::String(production));
+                include.insert("sha256".to_string(), Value::String(sha256));
+            }
+        }
+
+        let version = match self.version {
+            Some(version) => version.get(),
+            None => signed
+                .get("version")
+                .and_then(Value::as_u64)
+                .and_then(|version| version.checked_add(1))
+                .ok_or_else(|| anyhow!("Policy has no valid version"))?,
+        };
+        signed["version"] = Value::from(version);
+        if let Some(expires) = self.expires {
+            signed["expires"] = serde_json::to_value(expires)?;
+        }
+
+        let promoted = format!("{{\"signatures\":[],\"signed\":{}}}", signed).into_bytes();
+        serde_json::from_slice::<Policy>(&promoted).context("Promoted an invalid policy")?;
+        Ok(promoted)
+    }
+}
+
+/// The SHA-256 of the `signed` section of `policy`, which signers should
+/// compare out of band.
+fn signed_digest(policy: &[u8]) -> Result<String> {
+    let value: Value = serde_json::from_slice(policy)?;
+    Ok(hex::encode(Sha256::digest(
+        value["signed"].to_string().as_bytes(),
+    )))
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("promote")
+        .about("Turn a policy verified against the staging trust root into an unsigned production policy")
+        .arg(
+            Arg::new("policy")
+                .value_name("POLICY")
+                .required(true)
+                .about("URL or path of the signed staging policy"),
+        )
+        .arg(
+            Arg::new("map")
+                .long("map")
+                .value_name("FROM=TO")
+                .required(true)
+                .multiple_occurrences(true)
+                .about("Replace the staging namespace or URL prefix FROM with TO; repeat for several"),
+        )
+        .arg(
+            Arg::new("staging-trust")
+                .long("staging-trust")
+                .value_name("DIR")
+                .takes_value(true)
+                .about("Trust store holding the staging roots [default: that of --sigstore-env staging]"),
+        )
+        .arg(
+            Arg::new("version")
+                .long("version")
+                .value_name("VERSION")
+                .takes_value(true)
+                .about("Version of the production policy [default: the staging version plus one]"),
+        )
+        .arg(
+            Arg::new("expires")
+                .long("expires")
+                .value_name("DURATION")
+                .takes_value(true)
+                .about("How long the production policy is valid for, e.g. 365d [default: the staging expiry]"),
+        )
+        .arg(
+            Arg::new("include-sha256")
+                .long("include-sha256")
+                .value_name("DIGEST")
+                .takes_value(true)
+                .about("Digest of the production parent policy, when the included policy is re-targeted"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .value_name("FILE")
+                .required(true)
+                .about("Where to write the unsigned production policy; must not exist"),
+        )
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    let location = matches
+        .value_of("policy")
+        .ok_or_else(|| anyhow!("No policy given"))?;
+    let output = matches
+        .value_of("output")
+        .ok_or_else(|| anyhow!("No --output given"))?;
+    if Path::new(output).exists() {
+        return Err(anyhow!("{} already exists", output));
+    }
+    let promotion = Promotion {
+        mappings: matches
+            .values_of("map")
+            .map(|values| values.map(str::parse).collect::<Result<_>>())
+            .transpose()?
+            .unwrap_or_default(),
+        version: matches
+            .value_of("version")
+            .map(|version| {
+                version
+                    .parse()
+                    .map_err(|_| anyhow!("--version must be a positive number"))
+            })
+            .transpose()?,
+        expires: matches
+            .value_of("expires")
+            .map(|expires| -> Result<_> { Ok(from_now(expires)?.trunc_subsecs(0)) })
+            .transpose()?,
+        include_sha256: matches.value_of("include-sha256").map(String::from),
+    };
+
+    let store = match matches.value_of("staging-trust") {
+        Some(dir) => TrustStore::new(dir),
+        None => TrustStore::new(state::state_dir()?.join(SigstoreEnv::Staging.trust_dir())),
+    };
+    let roots = TrustRoots::load(&store).context("Cannot load the staging trust roots")?;
+    let transport = transport::default_transport();
+    let raw = fetch(transport.as_ref(), location, Path::new("")).await?;
+    let staging = verify::resolve_policy(
+        transport.as_ref(),
+        raw.clone(),
+        location,
+        &roots,
+        &SignatureVerifiers::default(),
+        &Pipeline::default(),
+        Utc::now(),
+    )
+    .await
+    .context("The staging policy does not verify")?;
+
+    let promoted = promotion.apply(&raw)?;
+    fs::write(output, &promoted).with_context(|| format!("Cannot write {}", output))?;
+    let policy: Policy = serde_json::from_slice(&promoted)?;
+    println!(
+        "Promoted {} version {} to {} version {}, written to {}",
+        staging.signed.namespace,
+        staging.signed.version,
+        policy.signed.namespace,
+        policy.signed.version,
+        output
+    );
+    println!(
+        "The root keys sign its `signed` section, SHA-256 {}, against the production roots.",
+        signed_digest(&promoted)?
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn promote_policy() {
+        let staging = json!({
+            "signatures": [{ "keyid": "abcd", "sig": "", "cert": "" }],
+            "signed": {
+                "consistent_snapshot": false,
+                "expires": "2030-01-01T00:00:00Z",
+                "keys": {},
+                "namespace": ["ghcr.io/acme-staging", "staging.acme.dev/scripts"],
+                "roles": { "root": { "keyids": ["abcd"], "threshold": 1 } },
+                "spec_version": "1.0",
+                "version": 3,
+                "include": {
+                    "url": "https://staging.acme.dev/policies/base.json",
+                    "sha256": "00",
+                },
+            },
+        })
+        .to_string();
+        let mut promotion = Promotion {
+            mappings: vec![
+                "ghcr.io/acme-staging=ghcr.io/acme"
+                    .parse()
+                    .expect("Invalid mapping"),
+                "staging.acme.dev=acme.dev"
+                    .parse()
+                    .expect("Invalid mapping"),
+                "https://staging.acme.dev=https://acme.dev"
+                    .parse()
+                    .expect("Invalid mapping"),
+            ],
+            version: None,
+            expires: None,
+            include_sha256: None,
+        };
+        // A moved parent must be pinned anew.
+        assert!(promotion.apply(staging.as_bytes()).is_err());
+        promotion.include_sha256 = Some("ff".to_string());
+
+        let raw = promotion.apply(staging.as_bytes()).expect("Cannot promote");
+        let policy: Policy = serde_json::from_slice(&raw).expect("Invalid policy");
+        assert!(policy.signatures.is_empty());
+        assert_eq!(
+            policy.signed.namespace.to_string(),
+            "ghcr.io/acme, acme.dev/scripts"
+        );
+        assert_eq!(policy.signed.version.get(), 4);
+        let include = policy.signed.include.expect("No include");
+        assert_eq!(include.url, "https://acme.dev/policies/base.json");
+        assert_eq!(include.sha256, "ff");
+
+        // Namespaces no mapping covers are refused rather than carried over.
+        promotion.mappings.remove(1);
+        assert!(promotion.apply(staging.as_bytes()).is_err());
+        assert_eq!(retarget("ghcr.io/acme-stagingx", &promotion.mappings), None);
+    }
+}
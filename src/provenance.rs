@@ -0,0 +1,393 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::error::SgetError;
+use crate::keys::PublicKey;
+use crate::pipeline::{Pipeline, Stage, StageContext};
+use crate::verify_core::{self, TrustRoots};
+
+/// DSSE payload type of in-toto statements.
+pub const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+const SLSA_PROVENANCE_V02: &str = "https://slsa.dev/provenance/v0.2";
+const SLSA_PROVENANCE_V1: &str = "https://slsa.dev/provenance/v1";
+
+/// Builders known to produce SLSA build level 3 provenance, by builder ID
+/// without the `@ref` suffix.
+const LEVEL_3_BUILDERS: &[&str] = &[
+    "https://github.com/slsa-framework/slsa-github-generator/.github/workflows/generator_generic_slsa3.yml",
+    "https://github.com/slsa-framework/slsa-github-generator/.github/workflows/generator_container_slsa3.yml",
+    "https://github.com/slsa-framework/slsa-github-generator/.github/workflows/builder_go_slsa3.yml",
+];
+
+/// What a manifest entry requires of the SLSA provenance of its artifact.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ProvenanceRequirements {
+    /// Where to fetch the provenance: a file of DSSE envelopes, one per line,
+    /// or `oci://<repository>` for the cosign attestations of the artifact's
+    /// digest in that repository. Defaults to `<url>.intoto.jsonl`.
+    pub location: Option<String>,
+    /// Required builder ID. Without an `@ref` suffix, any ref of the builder
+    /// matches.
+    pub builder: Option<String>,
+    /// Required source repository, e.g. `github.com/example/scripts`.
+    pub source: Option<String>,
+    /// Minimum SLSA build level of the builder.
+    #[serde(default)]
+    pub min_level: u8,
+    /// Build levels of builders other than the known SLSA 3 builders, by
+    /// builder ID without the `@ref` suffix. Other builders are level 1.
+    #[serde(default)]
+    pub builder_levels: HashMap<String, u8>,
+}
+
+/// A DSSE envelope.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub payload_type: String,
+    /// The base64 encoded payload.
+    pub payload: String,
+    pub signatures: Vec<EnvelopeSignature>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EnvelopeSignature {
+    #[serde(default)]
+    pub keyid: String,
+    /// The base64 encoded signature over the PAE of the payload.
+    pub sig: String,
+    /// PEM encoded signing certificate chain, for keyless signatures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert: Option<String>,
+}
+
+/// An in-toto statement about a set of subjects.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Statement {
+    subject: Vec<Subject>,
+    pub predicate_type: String,
+    pub predicate: Value,
+}
+
+#[derive(Deserialize)]
+struct Subject {
+    digest: HashMap<String, String>,
+}
+
+impl Statement {
+    /// Whether the statement is about an artifact with SHA-256 `digest`.
+    pub fn is_about(&self, digest: &str) -> bool {
+        self.subject.iter().any(
+            |subject| matches!(subject.digest.get("sha256"), Some(d) if d.eq_ignore_ascii_case(digest)),
+        )
+    }
+}
+
+/// How a verified artifact was built, according to its provenance.
+#[derive(Debug, PartialEq)]
+pub struct Provenance {
+    pub builder: String,
+    /// The normalized source repository, e.g. `github.com/example/scripts`.
+    pub source: Option<String>,
+    /// The ref of the source that was built, e.g. `refs/tags/v1.0.0`.
+    pub source_ref: Option<String>,
+    pub level: u8,
+}
+
+/// The DSSE pre-authentication encoding that envelope signatures are made over.
+pub fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut encoded = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    encoded.extend_from_slice(payload);
+    encoded
+}
+
+/// A builder ID or repository URI without its `@ref` suffix.
+fn without_ref(id: &str) -> &str {
+    id.rsplit_once('@').map_or(id, |(id, _)| id)
+}
+
+/// `git+https://github.com/example/scripts.git@refs/heads/main` as
+/// `github.com/example/scripts`.
+fn normalize_source(uri: &str) -> String {
+    let uri = without_ref(uri.trim_start_matches("git+"));
+    let uri = uri.split_once("://").map_or(uri, |(_, rest)| rest);
+    uri.trim_end_matches('/')
+        .trim_end_matches(".git")
+        .to_ascii_lowercase()
+}
+
+impl ProvenanceRequirements {
+    fn level(&self, builder: &str) -> u8 {
+        let builder = without_ref(builder);
+        match self.builder_levels.get(builder) {
+            Some(level) => *level,
+            None if LEVEL_3_BUILDERS.contains(&builder) => 3,
+            None => 1,
+        }
+    }
+
+    fn check(&self, provenance: &Provenance) -> Result<()> {
+        if let Some(expected) = &self.builder {
+            let actual = if expected.contains('@') {
+                provenance.builder.as_str()
+            } else {
+                without_ref(&provenance.builder)
+            };
+            if actual != expected {
+                return Err(anyhow!(
+                    "Built by {}, expected {}",
+                    provenance.builder,
+                    expected
+                ));
+            }
+        }
+        if let Some(expected) = &self.source {
+            if provenance.source.as_deref() != Some(normalize_source(expected).as_str()) {
+                return Err(anyhow!(
+                    "Built from {}, expected {}",
+                    provenance.source.as_deref().unwrap_or("an unknown source"),
+                    expected
+                ));
+            }
+        }
+        if provenance.level < self.min_level {
+            return Err(anyhow!(
+                "Built at SLSA level {}, at least {} is required",
+                provenance.level,
+                self.min_level
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parse JSON Lines of DSSE envelopes, skipping blank lines.
+pub(crate) fn envelopes(raw: &[u8]) -> impl Iterator<Item = Result<Envelope>> + '_ {
+    std::str::from_utf8(raw)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+}
+
+/// Verify the signature of an envelope by `key` or a Fulcio certificate and
+/// return its in-toto statement, and the certificate identity for keyless
+/// signatures.
+pub(crate) fn open_envelope(
+    envelope: &Envelope,
+    key: Option<&PublicKey>,
+    roots: &TrustRoots,
+) -> Result<(Statement, Option<String>)> {
+    if envelope.payload_type != IN_TOTO_PAYLOAD_TYPE {
+        return Err(anyhow!("Unexpected payload type {}", envelope.payload_type));
+    }
+    let payload = base64::decode(&envelope.payload)?;
+    let signed = pae(&envelope.payload_type, &payload);
+    let mut signer_identity = None;
+    let verified = envelope.signatures.iter().any(|signature| {
+        let sig = match base64::decode(&signature.sig) {
+            Ok(sig) => sig,
+            Err(_) => return false,
+        };
+        match (&signature.cert, key) {
+            (Some(cert), _) => {
+                match verify_core::verify_certificate_signature(
+                    cert.as_bytes(),
+                    &signed,
+                    &sig,
+                    roots,
+                ) {
+                    Ok((identity, _)) => {
+                        signer_identity = Some(identity);
+                        true
+                    }
+                    Err(_) => false,
+                }
+            }
+            (None, Some(key)) => key.verify(&signed, &sig).is_ok(),
+            (None, None) => false,
+        }
+    });
+    if !verified {
+        return Err(anyhow!("No valid signature"));
+    }
+    Ok((serde_json::from_slice(&payload)?, signer_identity))
+}
+
+/// Verify one envelope: its signature by `key` or a Fulcio certificate, that
+/// it is SLSA provenance for an artifact with SHA-256 `digest`, and that it
+/// meets `requirements`.
+fn verify_envelope(
+    envelope: &Envelope,
+    digest: &str,
+    key: Option<&PublicKey>,
+    roots: &TrustRoots,
+    requirements: &ProvenanceRequirements,
+) -> Result<Provenance> {
+    let (statement, signer_identity) = open_envelope(envelope, key, roots)?;
+    let predicate = &statement.predicate;
+    let (builder, source, source_ref) = match statement.predicate_type.as_str() {
+        SLSA_PROVENANCE_V02 => {
+            let source = &predicate["invocation"]["configSource"]["uri"];
+            let source_ref = source
+                .as_str()
+                .and_then(|uri| uri.rsplit_once('@'))
+                .map(|(_, source_ref)| source_ref.to_string());
+            (&predicate["builder"]["id"], source, source_ref)
+        }
+        SLSA_PROVENANCE_V1 => {
+            let workflow = &predicate["buildDefinition"]["externalParameters"]["workflow"];
+            (
+                &predicate["runDetails"]["builder"]["id"],
+                &workflow["repository"],
+                workflow["ref"].as_str().map(str::to_string),
+            )
+        }
+        other => return Err(anyhow!("Unsupported predicate type {}", other)),
+    };
+    if !statement.is_about(digest) {
+        return Err(anyhow!("Provenance is for a different artifact"));
+    }
+    let builder = builder
+        .as_str()
+        .ok_or_else(|| anyhow!("Provenance names no builder"))?
+        .to_string();
+    // A keyless signature must be by the builder itself.
+    if let Some(identity) = signer_identity {
+        if identity != builder {
+            return Err(anyhow!("Signed by {}, not builder {}", identity, builder));
+        }
+    }
+    let provenance = Provenance {
+        level: requirements.level(&builder),
+        source: source.as_str().map(normalize_source),
+        source_ref,
+        builder,
+    };
+    requirements.check(&provenance)?;
+    Ok(provenance)
+}
+
+/// Verify the provenance of an artifact with SHA-256 `digest`, given as JSON
+/// Lines of DSSE envelopes, as the provenance check stage of `pipeline`. The
+/// first envelope meeting `requirements` is accepted. Envelopes without a
+/// certificate are verified with `key`.
+pub fn verify_provenance(
+    raw: &[u8],
+    digest: &str,
+    key: Option<&PublicKey>,
+    roots: &TrustRoots,
+    requirements: &ProvenanceRequirements,
+    pipeline: &Pipeline,
+) -> crate::error::Result<Provenance> {
+    let context = StageContext {
+        stage: Stage::ProvenanceCheck,
+        digest,
+        identity: None,
+    };
+    pipeline.run(context, || {
+        let mut errors = Vec::new();
+        for envelope in envelopes(raw) {
+            let result = envelope
+                .and_then(|envelope| verify_envelope(&envelope, digest, key, roots, requirements));
+            match result {
+                Ok(provenance) => return Ok(provenance),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        if errors.is_empty() {
+            errors.push("No provenance found".to_string());
+        }
+        Err(SgetError::Provenance(errors.join("; ")))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use serde_json::json;
+
+    const GENERIC: &str = "https://github.com/slsa-framework/slsa-github-generator/.github/workflows/generator_generic_slsa3.yml@refs/tags/v1.2.0";
+
+    fn envelope(key: &SigningKey, digest: &str, builder: &str) -> String {
+        let statement = json!({
+            "_type": "https://in-toto.io/Statement/v0.1",
+            "subject": [{ "name": "install.sh", "digest": { "sha256": digest } }],
+            "predicateType": SLSA_PROVENANCE_V02,
+            "predicate": {
+                "builder": { "id": builder },
+                "invocation": {
+                    "configSource": { "uri": "git+https://github.com/example/scripts@refs/heads/main" },
+                },
+            },
+        })
+        .to_string();
+        let sig = key.sign(&pae(IN_TOTO_PAYLOAD_TYPE, statement.as_bytes()));
+        json!({
+            "payloadType": IN_TOTO_PAYLOAD_TYPE,
+            "payload": base64::encode(&statement),
+            "signatures": [{ "keyid": "", "sig": base64::encode(sig) }],
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn verify_slsa_provenance() {
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let public = key.public_key();
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let pipeline = Pipeline::default();
+        let digest = "ab".repeat(32);
+        let raw = format!(
+            "{}\n{}\n",
+            envelope(&key, &"cd".repeat(32), GENERIC),
+            envelope(&key, &digest, GENERIC)
+        );
+        let verify = |requirements: &ProvenanceRequirements| {
+            verify_provenance(
+                raw.as_bytes(),
+                &digest,
+                Some(&public),
+                &roots,
+                requirements,
+                &pipeline,
+            )
+        };
+
+        let mut requirements = ProvenanceRequirements {
+            builder: Some(without_ref(GENERIC).to_string()),
+            source: Some("https://github.com/example/scripts".to_string()),
+            min_level: 3,
+            ..ProvenanceRequirements::default()
+        };
+        let provenance = verify(&requirements).expect("Cannot verify provenance");
+        assert_eq!(provenance.level, 3);
+        assert_eq!(
+            provenance.source.as_deref(),
+            Some("github.com/example/scripts")
+        );
+        assert_eq!(provenance.source_ref.as_deref(), Some("refs/heads/main"));
+
+        requirements.source = Some("github.com/example/other".to_string());
+        assert!(matches!(
+            verify(&requirements),
+            Err(SgetError::Provenance(_))
+        ));
+        requirements.source = None;
+        requirements
+            .builder_levels
+            .insert(without_ref(GENERIC).to_string(), 2);
+        assert!(verify(&requirements).is_err());
+    }
+}
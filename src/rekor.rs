@@ -0,0 +1,199 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "native")]
+use serde_json::json;
+#[cfg(feature = "native")]
+use std::collections::HashMap;
+
+use crate::sigstore_bundle::SigstoreBundle;
+#[cfg(feature = "native")]
+use crate::transport::{self, Transport};
+
+pub const DEFAULT_REKOR_URL: &str = "https://rekor.sigstore.dev";
+
+// An entry as returned by the Rekor API, keyed by its UUID.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogEntry {
+    // The base64 encoded canonical body of the entry.
+    pub body: String,
+    pub integrated_time: i64,
+    #[serde(rename = "logID")]
+    pub log_id: String,
+    pub log_index: i64,
+    pub verification: Option<Verification>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Verification {
+    // The base64 encoded signature over the entry from the log.
+    pub signed_entry_timestamp: Option<String>,
+}
+
+/// A cosign compatible offline bundle proving inclusion in the transparency log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Bundle {
+    pub signed_entry_timestamp: String,
+    pub payload: BundlePayload,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundlePayload {
+    pub body: String,
+    pub integrated_time: i64,
+    pub log_index: i64,
+    #[serde(rename = "logID")]
+    pub log_id: String,
+}
+
+/// The bundle `cosign sign-blob --bundle` writes: the Rekor bundle together
+/// with the signature and, for keyless signatures, the certificate.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CosignBundle {
+    pub base64_signature: String,
+    /// The base64 encoded PEM certificate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert: Option<String>,
+    pub rekor_bundle: Bundle,
+}
+
+/// Parse a Rekor bundle as written by `sget sign`, or the Rekor bundle within
+/// a cosign or sigstore bundle.
+pub fn parse_bundle(raw: &[u8]) -> Result<Bundle> {
+    if let Ok(bundle) = SigstoreBundle::parse(raw) {
+        return bundle.rekor_bundle();
+    }
+    match serde_json::from_slice::<CosignBundle>(raw) {
+        Ok(cosign) => Ok(cosign.rekor_bundle),
+        Err(_) => Ok(serde_json::from_slice(raw)?),
+    }
+}
+
+/// The base64 encoded signature, and the certificate for keyless signatures,
+/// carried by a cosign or sigstore bundle. The certificate is PEM encoded, or
+/// base64 encoded PEM from cosign bundles.
+pub fn embedded_signature(raw: &[u8]) -> Option<(String, Option<String>)> {
+    if let Ok(bundle) = SigstoreBundle::parse(raw) {
+        let signature = bundle.signature().ok()?.to_string();
+        return Some((signature, bundle.certificate().ok()?));
+    }
+    let cosign: CosignBundle = serde_json::from_slice(raw).ok()?;
+    Some((cosign.base64_signature, cosign.cert))
+}
+
+impl LogEntry {
+    /// Convert the entry into an offline bundle, if Rekor returned a signed
+    /// entry timestamp for it.
+    pub fn to_bundle(&self) -> Result<Bundle> {
+        let set = self
+            .verification
+            .as_ref()
+            .and_then(|v| v.signed_entry_timestamp.clone())
+            .ok_or_else(|| anyhow!("Rekor entry is missing a signed entry timestamp"))?;
+        Ok(Bundle {
+            signed_entry_timestamp: set,
+            payload: BundlePayload {
+                body: self.body.clone(),
+                integrated_time: self.integrated_time,
+                log_index: self.log_index,
+                log_id: self.log_id.clone(),
+            },
+        })
+    }
+}
+
+/// Upload a `hashedrekord` entry for a detached signature over a SHA-256 digest.
+///
+/// `public_key` is the PEM encoded public key or certificate that verifies the signature.
+#[cfg(feature = "native")]
+pub async fn upload_hashedrekord(
+    transport: &dyn Transport,
+    rekor_url: &str,
+    digest_hex: &str,
+    signature: &[u8],
+    public_key: &str,
+) -> Result<LogEntry> {
+    let proposed = json!({
+        "apiVersion": "0.0.1",
+        "kind": "hashedrekord",
+        "spec": {
+            "signature": {
+                "content": base64::encode(signature),
+                "publicKey": { "content": base64::encode(public_key) },
+            },
+            "data": {
+                "hash": { "algorithm": "sha256", "value": digest_hex },
+            },
+        },
+    });
+    let url = format!("{}/api/v1/log/entries", rekor_url.trim_end_matches('/'));
+    let response = transport
+        .send(transport::post_json(&url, &proposed)?)
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Rekor rejected the entry ({}): {}",
+            response.status(),
+            transport::text(&response)
+        ));
+    }
+    let entries: HashMap<String, LogEntry> = transport::json(&response)?;
+    entries
+        .into_iter()
+        .next()
+        .map(|(_, entry)| entry)
+        .ok_or_else(|| anyhow!("Rekor returned no entry"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_to_bundle() {
+        let raw = r#"{
+            "body": "eyJhcGlWZXJzaW9uIjoiMC4wLjEifQ==",
+            "integratedTime": 1637699000,
+            "logID": "c0d23d6ad406973f9559f3ba2d1ca01f84147d8ffc5b8445c224f98b9591801d",
+            "logIndex": 885000,
+            "verification": { "signedEntryTimestamp": "MEUCIQ==" }
+        }"#;
+        let entry: LogEntry = serde_json::from_str(raw).expect("Cannot parse entry");
+        let bundle = entry.to_bundle().expect("Cannot create bundle");
+        assert_eq!(bundle.payload.log_index, 885000);
+
+        let encoded = serde_json::to_value(&bundle).expect("Cannot serialize bundle");
+        assert_eq!(encoded["SignedEntryTimestamp"], "MEUCIQ==");
+        assert_eq!(encoded["Payload"]["integratedTime"], 1637699000);
+    }
+
+    #[test]
+    fn parse_cosign_bundle() {
+        let rekor_bundle = r#"{
+            "SignedEntryTimestamp": "MEUCIQ==",
+            "Payload": { "body": "e30=", "integratedTime": 7, "logIndex": 1, "logID": "abcd" }
+        }"#;
+        let cosign = format!(
+            r#"{{"base64Signature": "MEQ=", "cert": "LS0t", "rekorBundle": {}}}"#,
+            rekor_bundle
+        );
+        let expected = parse_bundle(rekor_bundle.as_bytes()).expect("Cannot parse bundle");
+        assert_eq!(expected.payload.integrated_time, 7);
+        assert_eq!(
+            parse_bundle(cosign.as_bytes()).expect("Cannot parse cosign bundle"),
+            expected
+        );
+        assert!(parse_bundle(b"{}").is_err());
+    }
+
+    #[test]
+    fn entry_without_set_has_no_bundle() {
+        let raw = r#"{"body": "", "integratedTime": 0, "logID": "", "logIndex": 0}"#;
+        let entry: LogEntry = serde_json::from_str(raw).expect("Cannot parse entry");
+        assert!(entry.to_bundle().is_err());
+    }
+}
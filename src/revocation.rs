@@ -0,0 +1,527 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use serde_plain::derive_fromstr_from_deserialize;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::{DistributionPointName, GeneralName, ParsedExtension};
+use x509_parser::parse_x509_crl;
+
+use crate::der::{
+    element, encode, expect, generalized_time, verify_signed, Element, ENUMERATED, INTEGER, NULL,
+    OCTET_STRING, OID, OID_SHA256, SEQUENCE,
+};
+use crate::error::SgetError;
+use crate::pipeline::{Pipeline, Stage, StageContext};
+#[cfg(feature = "native")]
+use crate::transport::{Request, Transport};
+use crate::verify_core::{parse_certificate, pem_certificates, TrustRoots};
+
+/// How long an OCSP response without a next update time is trusted for.
+const MAX_OCSP_AGE_DAYS: i64 = 7;
+
+const OID_OCSP: &str = "1.3.6.1.5.5.7.48.1";
+
+// DER encoded object identifiers.
+const OID_OCSP_BASIC: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+const OID_SHA1: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+
+/// What to do when the revocation status of a certificate cannot be
+/// determined, e.g. because its CRL or OCSP responder is unreachable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RevocationMode {
+    /// Reject the signature.
+    HardFail,
+    /// Accept the signature. Certificates known to be revoked are still
+    /// rejected.
+    SoftFail,
+}
+
+derive_fromstr_from_deserialize!(RevocationMode);
+
+/// Revocation checks of the signing certificate of keyless signatures, for
+/// certificate authorities that publish CRLs or run OCSP responders.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RevocationPolicy {
+    pub mode: RevocationMode,
+    /// Download the CRLs named in the certificate.
+    #[serde(default = "enabled")]
+    pub crl: bool,
+    /// Ask the OCSP responders named in the certificate.
+    #[serde(default = "enabled")]
+    pub ocsp: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl RevocationPolicy {
+    pub fn new(mode: RevocationMode) -> Self {
+        RevocationPolicy {
+            mode,
+            crl: true,
+            ocsp: true,
+        }
+    }
+}
+
+/// The CRLs and OCSP responses fetched for a signing certificate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RevocationData {
+    /// DER encoded CRLs.
+    pub crls: Vec<Vec<u8>>,
+    /// DER encoded OCSP responses.
+    pub ocsp_responses: Vec<Vec<u8>>,
+}
+
+/// The http(s) CRL distribution points and OCSP responders of a certificate.
+#[derive(Debug, Default, PartialEq)]
+pub struct Endpoints {
+    pub crls: Vec<String>,
+    pub ocsp: Vec<String>,
+}
+
+pub fn endpoints(cert: &X509Certificate) -> Endpoints {
+    let http = |name: &GeneralName| match name {
+        GeneralName::URI(uri) if uri.starts_with("http://") || uri.starts_with("https://") => {
+            Some(uri.to_string())
+        }
+        _ => None,
+    };
+    let mut endpoints = Endpoints::default();
+    for extension in cert.extensions() {
+        match extension.parsed_extension() {
+            ParsedExtension::CRLDistributionPoints(points) => {
+                for point in points {
+                    if let Some(DistributionPointName::FullName(names)) = &point.distribution_point
+                    {
+                        endpoints.crls.extend(names.iter().filter_map(http));
+                    }
+                }
+            }
+            ParsedExtension::AuthorityInfoAccess(access) => endpoints.ocsp.extend(
+                access
+                    .accessdescs
+                    .iter()
+                    .filter(|access| access.access_method.to_id_string() == OID_OCSP)
+                    .filter_map(|access| http(&access.access_location)),
+            ),
+            _ => {}
+        }
+    }
+    endpoints
+}
+
+/// Whether `crl`, a DER encoded CRL by `issuer` current at `now`, lists
+/// `leaf`. Indirect and delta CRLs are not supported.
+fn crl_revokes(
+    crl: &[u8],
+    leaf: &X509Certificate,
+    issuer: &X509Certificate,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    let (_, parsed) = parse_x509_crl(crl).map_err(|e| anyhow!("Invalid CRL: {:?}", e))?;
+    if parsed.issuer() != leaf.issuer() {
+        return Err(anyhow!("CRL is by {}", parsed.issuer()));
+    }
+    if parsed
+        .extensions()
+        .iter()
+        .any(|extension| extension.critical)
+    {
+        return Err(anyhow!("CRL has an unsupported critical extension"));
+    }
+    let (outer, _) = element(crl)?;
+    match expect(&outer, SEQUENCE)?.as_slice() {
+        [signed, algorithm, signature] => {
+            verify_signed(signed.raw, algorithm, signature, issuer.public_key())?
+        }
+        _ => return Err(anyhow!("Invalid CRL")),
+    }
+    let next_update = parsed
+        .next_update()
+        .ok_or_else(|| anyhow!("CRL has no next update time"))?;
+    if now.timestamp() < parsed.last_update().timestamp()
+        || now.timestamp() > next_update.timestamp()
+    {
+        return Err(anyhow!("CRL is not current"));
+    }
+    let revoked = parsed
+        .iter_revoked_certificates()
+        .any(|revoked| revoked.user_certificate == leaf.tbs_certificate.serial);
+    Ok(revoked)
+}
+
+/// The hash of the issuer name and key in an OCSP certificate ID.
+fn cert_id_hash(algorithm: &'static digest::Algorithm, data: &[u8]) -> Vec<u8> {
+    digest::digest(algorithm, data).as_ref().to_vec()
+}
+
+/// The DER encoded OCSP request for `leaf`, with a SHA-1 certificate ID.
+/// `issuer_key_hash` is the SHA-1 hash of the issuer's public key.
+pub fn ocsp_request(leaf: &X509Certificate, issuer_key_hash: &[u8]) -> Vec<u8> {
+    let algorithm = encode(
+        SEQUENCE,
+        &[encode(OID, OID_SHA1), encode(NULL, &[])].concat(),
+    );
+    let cert_id = encode(
+        SEQUENCE,
+        &[
+            algorithm,
+            encode(
+                OCTET_STRING,
+                &cert_id_hash(&digest::SHA1_FOR_LEGACY_USE_ONLY, leaf.issuer().as_raw()),
+            ),
+            encode(OCTET_STRING, issuer_key_hash),
+            encode(INTEGER, leaf.tbs_certificate.raw_serial()),
+        ]
+        .concat(),
+    );
+    let request_list = encode(SEQUENCE, &encode(SEQUENCE, &cert_id));
+    encode(SEQUENCE, &encode(SEQUENCE, &request_list))
+}
+
+/// Whether the certificate ID of an OCSP response is for `leaf`.
+fn cert_id_matches(
+    cert_id: &Element,
+    leaf: &X509Certificate,
+    issuer: &X509Certificate,
+) -> Result<bool> {
+    let fields = expect(cert_id, SEQUENCE)?;
+    let (algorithm, name_hash, key_hash, serial) = match fields.as_slice() {
+        [algorithm, name_hash, key_hash, serial] => (algorithm, name_hash, key_hash, serial),
+        _ => return Err(anyhow!("Invalid OCSP certificate ID")),
+    };
+    let algorithm = match expect(algorithm, SEQUENCE)?.first().map(|oid| oid.contents) {
+        Some(OID_SHA1) => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        Some(OID_SHA256) => &digest::SHA256,
+        _ => return Err(anyhow!("Unsupported OCSP certificate ID hash")),
+    };
+    Ok(serial.contents == leaf.tbs_certificate.raw_serial()
+        && name_hash.contents == cert_id_hash(algorithm, leaf.issuer().as_raw())
+        && key_hash.contents
+            == cert_id_hash(algorithm, issuer.public_key().subject_public_key.data))
+}
+
+/// Whether the DER encoded OCSP `response`, signed by `issuer` or a responder
+/// it delegated to and current at `now`, says `leaf` is revoked.
+fn ocsp_revokes(
+    response: &[u8],
+    leaf: &X509Certificate,
+    issuer: &X509Certificate,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    let (outer, _) = element(response)?;
+    let outer = expect(&outer, SEQUENCE)?;
+    let (status, bytes) = match outer.as_slice() {
+        [status, bytes] if status.tag == ENUMERATED => (status, bytes),
+        [status] if status.tag == ENUMERATED => {
+            return Err(anyhow!(
+                "OCSP responder returned status {:?}",
+                status.contents
+            ))
+        }
+        _ => return Err(anyhow!("Invalid OCSP response")),
+    };
+    if status.contents != [0] {
+        return Err(anyhow!(
+            "OCSP responder returned status {:?}",
+            status.contents
+        ));
+    }
+    let bytes = expect(bytes, 0xa0)?;
+    let bytes = bytes
+        .first()
+        .ok_or_else(|| anyhow!("Invalid OCSP response"))?;
+    let basic = match expect(bytes, SEQUENCE)?.as_slice() {
+        [kind, basic] if kind.tag == OID && kind.contents == OID_OCSP_BASIC => {
+            element(basic.contents)?.0.raw
+        }
+        _ => return Err(anyhow!("Unsupported OCSP response type")),
+    };
+    let (basic, _) = element(basic)?;
+    let basic = expect(&basic, SEQUENCE)?;
+    let (data, algorithm, signature) = match basic.as_slice() {
+        [data, algorithm, signature, ..] => (data, algorithm, signature),
+        _ => return Err(anyhow!("Invalid OCSP response")),
+    };
+
+    // The issuer may sign responses itself or certify a responder to.
+    if verify_signed(data.raw, algorithm, signature, issuer.public_key()).is_err() {
+        let certs = match basic.get(3) {
+            Some(certs) => expect(certs, 0xa0)?,
+            None => Vec::new(),
+        };
+        let responders = match certs.first() {
+            Some(certs) => expect(certs, SEQUENCE)?,
+            None => Vec::new(),
+        };
+        let delegated = responders.iter().any(|responder| {
+            let responder = match parse_certificate(responder.raw) {
+                Ok(responder) => responder,
+                Err(_) => return false,
+            };
+            let validity = responder.validity();
+            responder.issuer() == issuer.subject()
+                && responder
+                    .verify_signature(Some(issuer.public_key()))
+                    .is_ok()
+                && responder
+                    .tbs_certificate
+                    .extended_key_usage()
+                    .is_some_and(|(_, usage)| usage.ocsp_signing)
+                && validity.not_before.timestamp() <= now.timestamp()
+                && now.timestamp() <= validity.not_after.timestamp()
+                && verify_signed(data.raw, algorithm, signature, responder.public_key()).is_ok()
+        });
+        if !delegated {
+            return Err(anyhow!("OCSP response is not signed by the issuer"));
+        }
+    }
+
+    let fields = expect(data, SEQUENCE)?;
+    let responses = fields
+        .iter()
+        .find(|field| field.tag == SEQUENCE)
+        .ok_or_else(|| anyhow!("OCSP response has no responses"))?;
+    for single in expect(responses, SEQUENCE)? {
+        let single = expect(&single, SEQUENCE)?;
+        let (cert_id, status, this_update) = match single.as_slice() {
+            [cert_id, status, this_update, ..] => (cert_id, status, this_update),
+            _ => return Err(anyhow!("Invalid OCSP response")),
+        };
+        if !cert_id_matches(cert_id, leaf, issuer)? {
+            continue;
+        }
+        let this_update = generalized_time(this_update)?;
+        let next_update = match single.get(3) {
+            Some(next) if next.tag == 0xa0 => match expect(next, 0xa0)?.first() {
+                Some(time) => generalized_time(time)?,
+                None => return Err(anyhow!("Invalid OCSP next update time")),
+            },
+            _ => this_update + Duration::days(MAX_OCSP_AGE_DAYS),
+        };
+        if now < this_update || now > next_update {
+            return Err(anyhow!("OCSP response is not current"));
+        }
+        return match status.tag {
+            0x80 => Ok(false),
+            0xa1 => Ok(true),
+            _ => Err(anyhow!("OCSP responder does not know the certificate")),
+        };
+    }
+    Err(anyhow!("OCSP response is for another certificate"))
+}
+
+/// Check the leaf of the PEM encoded certificate `chain`, which must already
+/// chain to one of `roots`, against the CRLs and OCSP responses in `data`, as
+/// part of the chain validation stage of `pipeline` for the artifact with
+/// `digest`. A revoked certificate is always rejected; a certificate whose
+/// status is unknown only in hard-fail mode.
+pub fn verify_revocation(
+    chain: &str,
+    roots: &TrustRoots,
+    data: &RevocationData,
+    policy: &RevocationPolicy,
+    digest: &str,
+    pipeline: &Pipeline,
+    now: DateTime<Utc>,
+) -> crate::error::Result<()> {
+    let chain = pem_certificates(chain.as_bytes())?;
+    let leaf = parse_certificate(&chain[0])?;
+    let subject = leaf.subject().to_string();
+    let context = StageContext {
+        stage: Stage::ChainValidation,
+        digest,
+        identity: Some(&subject),
+    };
+    pipeline.run(context, || {
+        let candidates = chain[1..]
+            .iter()
+            .chain(&roots.fulcio_roots)
+            .map(|der| parse_certificate(der))
+            .collect::<crate::error::Result<Vec<_>>>()?;
+        let issuer = candidates
+            .iter()
+            .find(|issuer| {
+                leaf.issuer() == issuer.subject()
+                    && leaf.verify_signature(Some(issuer.public_key())).is_ok()
+            })
+            .ok_or_else(|| {
+                SgetError::UntrustedCertificate(
+                    "Certificate does not chain to a trusted root".to_string(),
+                )
+            })?;
+
+        let crls = data
+            .crls
+            .iter()
+            .filter(|_| policy.crl)
+            .map(|crl| crl_revokes(crl, &leaf, issuer, now));
+        let responses = data
+            .ocsp_responses
+            .iter()
+            .filter(|_| policy.ocsp)
+            .map(|response| ocsp_revokes(response, &leaf, issuer, now));
+        let mut known = false;
+        let mut unknown = anyhow!("No CRL or OCSP response was fetched");
+        for status in crls.chain(responses) {
+            match status {
+                Ok(true) => {
+                    return Err(SgetError::UntrustedCertificate(format!(
+                        "Certificate {} was revoked",
+                        leaf.tbs_certificate.raw_serial_as_string()
+                    )))
+                }
+                Ok(false) => known = true,
+                Err(e) => unknown = e,
+            }
+        }
+        if !known && policy.mode == RevocationMode::HardFail {
+            return Err(SgetError::UntrustedCertificate(format!(
+                "Cannot tell whether certificate {} was revoked: {}",
+                leaf.tbs_certificate.raw_serial_as_string(),
+                unknown
+            )));
+        }
+        Ok(())
+    })
+}
+
+/// The SHA-1 hash of the public key of the certificate that issued `leaf`:
+/// from the issuer in `intermediates`, or else the leaf's authority key
+/// identifier, which is usually that hash.
+#[cfg(feature = "native")]
+fn issuer_key_hash(leaf: &X509Certificate, intermediates: &[Vec<u8>]) -> Option<Vec<u8>> {
+    let issuer = intermediates
+        .iter()
+        .filter_map(|der| parse_certificate(der).ok())
+        .find(|issuer| {
+            leaf.issuer() == issuer.subject()
+                && leaf.verify_signature(Some(issuer.public_key())).is_ok()
+        });
+    if let Some(issuer) = issuer {
+        let key = issuer.public_key().subject_public_key.data;
+        return Some(cert_id_hash(&digest::SHA1_FOR_LEGACY_USE_ONLY, key));
+    }
+    leaf.extensions()
+        .iter()
+        .find_map(|extension| match extension.parsed_extension() {
+            ParsedExtension::AuthorityKeyIdentifier(aki) => aki.key_identifier.as_ref(),
+            _ => None,
+        })
+        .map(|id| id.0.to_vec())
+}
+
+/// Fetch the CRLs and OCSP responses `policy` asks for, for the leaf of the
+/// PEM encoded certificate `chain`. Endpoints that cannot be reached are
+/// skipped; whether that is acceptable is decided by [`verify_revocation`].
+#[cfg(feature = "native")]
+pub async fn fetch_revocation_data(
+    transport: &dyn Transport,
+    chain: &str,
+    policy: &RevocationPolicy,
+) -> crate::error::Result<RevocationData> {
+    let chain = pem_certificates(chain.as_bytes())?;
+    let leaf = parse_certificate(&chain[0])?;
+    let endpoints = endpoints(&leaf);
+    let request = if policy.ocsp {
+        issuer_key_hash(&leaf, &chain[1..]).map(|hash| ocsp_request(&leaf, &hash))
+    } else {
+        None
+    };
+    let mut data = RevocationData::default();
+    let send = |request: Request<Vec<u8>>| async move {
+        let response = transport.send(request).await.ok()?;
+        match response.status().is_success() {
+            true => Some(response.into_body()),
+            false => None,
+        }
+    };
+    for url in endpoints.crls.iter().filter(|_| policy.crl) {
+        if let Ok(request) = crate::transport::get(url) {
+            data.crls.extend(send(request).await);
+        }
+    }
+    for url in endpoints.ocsp.iter().filter(|_| request.is_some()) {
+        let request = Request::post(url.as_str())
+            .header(http::header::CONTENT_TYPE, "application/ocsp-request")
+            .body(request.clone().unwrap_or_default());
+        if let Ok(request) = request {
+            data.ocsp_responses.extend(send(request).await);
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::AlgorithmPolicy;
+    use crate::identities::IdentityMap;
+    use crate::system_policy::SystemPolicy;
+    use chrono::TimeZone;
+
+    const CA: &str = include_str!("../tests/test_data/revocation/ca.pem");
+    const GOOD: &str = include_str!("../tests/test_data/revocation/good.pem");
+    const REVOKED: &str = include_str!("../tests/test_data/revocation/revoked.pem");
+    const CRL: &[u8] = include_bytes!("../tests/test_data/revocation/root.crl");
+    const GOOD_OCSP: &[u8] = include_bytes!("../tests/test_data/revocation/good.ocsp");
+    const REVOKED_OCSP: &[u8] = include_bytes!("../tests/test_data/revocation/revoked.ocsp");
+    const GOOD_REQUEST: &[u8] = include_bytes!("../tests/test_data/revocation/good.req");
+
+    #[test]
+    fn check_crl_and_ocsp() {
+        let roots = TrustRoots {
+            fulcio_roots: pem_certificates(CA.as_bytes()).expect("No CA"),
+            rekor_keys: Vec::new(),
+            timestamp_authorities: Vec::new(),
+            algorithms: AlgorithmPolicy::default(),
+            system: SystemPolicy::default(),
+            identities: IdentityMap::default(),
+            policy: None,
+        };
+        let now = Utc.ymd(2027, 1, 1).and_hms(0, 0, 0);
+        let hard = RevocationPolicy::new(RevocationMode::HardFail);
+        let soft = RevocationPolicy::new(RevocationMode::SoftFail);
+        let check = |chain: &str, crls: &[&[u8]], ocsp: &[&[u8]], policy: &RevocationPolicy| {
+            let data = RevocationData {
+                crls: crls.iter().map(|crl| crl.to_vec()).collect(),
+                ocsp_responses: ocsp.iter().map(|response| response.to_vec()).collect(),
+            };
+            verify_revocation(chain, &roots, &data, policy, "", &Pipeline::default(), now)
+        };
+
+        let der = pem_certificates(GOOD.as_bytes()).expect("No certificate");
+        let leaf = parse_certificate(&der[0]).expect("Invalid certificate");
+        assert_eq!(
+            endpoints(&leaf),
+            Endpoints {
+                crls: vec!["http://ca.example.com/root.crl".to_string()],
+                ocsp: vec!["http://ocsp.example.com".to_string()],
+            }
+        );
+        let ca = pem_certificates(CA.as_bytes()).expect("No CA");
+        let key_hash = issuer_key_hash(&leaf, &ca).expect("No issuer key hash");
+        assert_eq!(ocsp_request(&leaf, &key_hash), GOOD_REQUEST);
+
+        assert!(check(GOOD, &[CRL], &[], &hard).is_ok());
+        assert!(check(GOOD, &[], &[GOOD_OCSP], &hard).is_ok());
+        assert!(check(REVOKED, &[CRL], &[], &soft).is_err());
+        assert!(check(REVOKED, &[], &[REVOKED_OCSP], &soft).is_err());
+        // A response for another certificate says nothing about this one.
+        assert!(check(REVOKED, &[], &[GOOD_OCSP], &hard).is_err());
+        assert!(check(REVOKED, &[], &[GOOD_OCSP], &soft).is_ok());
+        assert!(check(GOOD, &[], &[], &hard).is_err());
+        let stale = Utc.ymd(2200, 1, 1).and_hms(0, 0, 0);
+        let data = RevocationData {
+            crls: vec![CRL.to_vec()],
+            ocsp_responses: Vec::new(),
+        };
+        assert!(
+            verify_revocation(GOOD, &roots, &data, &hard, "", &Pipeline::default(), stale).is_err()
+        );
+    }
+}
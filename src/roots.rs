@@ -0,0 +1,59 @@
+// Trust roots of the public sigstore instance, used when the local trust
+// store holds no Fulcio roots or Rekor keys of its own, unless others were
+// embedded at build time.
+
+/// The original sigstore Fulcio root, which issued certificates until October 2021.
+pub const FULCIO_ROOT_V0: &str = "-----BEGIN CERTIFICATE-----
+MIIB+DCCAX6gAwIBAgITNVkDZoCiofPDsy7dfm6geLbuhzAKBggqhkjOPQQDAzAq
+MRUwEwYDVQQKEwxzaWdzdG9yZS5kZXYxETAPBgNVBAMTCHNpZ3N0b3JlMB4XDTIx
+MDMwNzAzMjAyOVoXDTMxMDIyMzAzMjAyOVowKjEVMBMGA1UEChMMc2lnc3RvcmUu
+ZGV2MREwDwYDVQQDEwhzaWdzdG9yZTB2MBAGByqGSM49AgEGBSuBBAAiA2IABLSy
+A7Ii5k+pNO8ZEWY0ylemWDowOkNa3kL+GZE5Z5GWehL9/A9bRNA3RbrsZ5i0Jcas
+taRL7Sp5fp/jD5dxqc/UdTVnlvS16an+2Yfswe/QuLolRUCrcOE2+2iA5+tzd6Nm
+MGQwDgYDVR0PAQH/BAQDAgEGMBIGA1UdEwEB/wQIMAYBAf8CAQEwHQYDVR0OBBYE
+FMjFHQBBmiQpMlEk6w2uSu1KBtPsMB8GA1UdIwQYMBaAFMjFHQBBmiQpMlEk6w2u
+Su1KBtPsMAoGCCqGSM49BAMDA2gAMGUCMH8liWJfMui6vXXBhjDgY4MwslmN/TJx
+Ve/83WrFomwmNf056y1X48F9c4m3a3ozXAIxAJ1NH1ATTGGrAgpzvmDx+HMAKe+X
+0h0GqNHHlT/yH8GB+JrHlvMLhcSXNEb3Kh09hw==
+-----END CERTIFICATE-----
+";
+
+/// The sigstore Fulcio v1 root.
+pub const FULCIO_ROOT_V1: &str = "-----BEGIN CERTIFICATE-----
+MIIB9zCCAXygAwIBAgIUALZNAPFdxHPwjeDloDwyYChAO/4wCgYIKoZIzj0EAwMw
+KjEVMBMGA1UEChMMc2lnc3RvcmUuZGV2MREwDwYDVQQDEwhzaWdzdG9yZTAeFw0y
+MTEwMDcxMzU2NTlaFw0zMTEwMDUxMzU2NThaMCoxFTATBgNVBAoTDHNpZ3N0b3Jl
+LmRldjERMA8GA1UEAxMIc2lnc3RvcmUwdjAQBgcqhkjOPQIBBgUrgQQAIgNiAAT7
+XeFT4rb3PQGwS4IajtLk3/OlnpgangaBclYpsYBr5i+4ynB07ceb3LP0OIOZdxex
+X69c5iVuyJRQ+Hz05yi+UF3uBWAlHpiS5sh0+H2GHE7SXrk1EC5m1Tr19L9gg92j
+YzBhMA4GA1UdDwEB/wQEAwIBBjAPBgNVHRMBAf8EBTADAQH/MB0GA1UdDgQWBBRY
+wB5fkUWlZql6zJChkyLQKsXF+jAfBgNVHSMEGDAWgBRYwB5fkUWlZql6zJChkyLQ
+KsXF+jAKBggqhkjOPQQDAwNpADBmAjEAj1nHeXZp+13NWBNa+EDsDP8G1WWg1tCM
+WP/WHPqpaVo0jhsweNFZgSs0eE7wYI4qAjEA2WB9ot98sIkoF3vZYdd3/VtWB5b9
+TNMea7Ix/stJ5TfcLLeABLE4BNJOsQ4vnBHJ
+-----END CERTIFICATE-----
+";
+
+/// The public key of the sigstore Rekor transparency log.
+pub const REKOR_KEY: &str = "-----BEGIN PUBLIC KEY-----
+MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE2G2Y+2tabdTV5BcGiBIx0a9fAFwr
+kBbmLSGtks4L3qX6yYY0zufBnhC8Ur/iy55GhWP/9A/bY2LhC30M9+RYtw==
+-----END PUBLIC KEY-----
+";
+
+/// The root policy embedded with the `embedded-roots` feature, or empty.
+#[cfg(feature = "embedded-roots")]
+pub const EMBEDDED_POLICY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/policy.json"));
+/// PEM encoded Fulcio roots embedded with the `embedded-roots` feature, or empty.
+#[cfg(feature = "embedded-roots")]
+pub const EMBEDDED_FULCIO_ROOTS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/fulcio.pem"));
+/// PEM encoded Rekor keys embedded with the `embedded-roots` feature, or empty.
+#[cfg(feature = "embedded-roots")]
+pub const EMBEDDED_REKOR_KEYS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/rekor.pem"));
+
+#[cfg(not(feature = "embedded-roots"))]
+pub const EMBEDDED_POLICY: &[u8] = &[];
+#[cfg(not(feature = "embedded-roots"))]
+pub const EMBEDDED_FULCIO_ROOTS: &[u8] = &[];
+#[cfg(not(feature = "embedded-roots"))]
+pub const EMBEDDED_REKOR_KEYS: &[u8] = &[];
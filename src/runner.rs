@@ -0,0 +1,88 @@
+use anyhow::anyhow;
+use chrono::Utc;
+use std::path::Path;
+use std::process::ExitStatus;
+
+use crate::approval;
+use crate::audit::{Action, AuditLog, AuditRecord};
+use crate::error::{Result, SgetError};
+use crate::execution_log::ExecutionLog;
+use crate::utils;
+use crate::verify::{Signer, TrustRoots};
+
+/// A verified script about to run.
+pub(crate) struct Script<'a> {
+    /// URL, path or OCI reference the script was fetched from.
+    pub source: &'a str,
+    /// Hex encoded SHA-256 digest of the script.
+    pub digest: &'a str,
+    /// The verified signer, if the script's signature was checked.
+    pub signer: Option<&'a Signer>,
+}
+
+/// Where runs are recorded.
+#[derive(Default)]
+pub(crate) struct Records<'a> {
+    pub audit: Option<&'a AuditLog>,
+    pub executions: Option<&'a ExecutionLog>,
+}
+
+/// Check that `script` may run: no approval rule of the system policy
+/// applies to it, or one of `approvals` is valid for it. Returns the
+/// approver, if one was needed. Unsigned scripts cannot be approved.
+fn check_approvals(
+    script: &Script,
+    approvals: &[Vec<u8>],
+    roots: &TrustRoots,
+) -> Result<Option<Signer>> {
+    let rules = &roots.system.approvals;
+    match script.signer {
+        Some(signer) => approval::check_approvals(
+            approvals,
+            script.source,
+            script.digest,
+            signer,
+            rules,
+            roots,
+            Utc::now(),
+        ),
+        None if approval::rules_for(rules, script.source).is_empty() => Ok(None),
+        None => Err(SgetError::Unapproved(format!(
+            "{} needs the approval of a second operator, and is not signed",
+            script.source
+        ))),
+    }
+}
+
+/// Run `script`, saved at `path`, in `dir` [default: the current directory]
+/// once the approvals the system policy requires are among `approvals`. Every run path goes through here:
+/// the run is recorded in the audit and execution logs before the script
+/// starts, so that it cannot keep itself out of them. Returns the exit status
+/// and the approver, if one was needed.
+pub(crate) fn run(
+    script: &Script,
+    path: &Path,
+    dir: Option<&Path>,
+    interactive: bool,
+    approvals: &[Vec<u8>],
+    roots: &TrustRoots,
+    records: &Records,
+) -> Result<(ExitStatus, Option<Signer>)> {
+    let approver = check_approvals(script, approvals, roots)?;
+    if let Some(audit) = records.audit {
+        let mut record = AuditRecord::new(Action::Run, script.source);
+        record.digest = Some(script.digest.to_string());
+        record.signer = script.signer.map(|signer| signer.subject().to_string());
+        record.issuer = script.signer.and_then(|signer| signer.issuer.clone());
+        audit.record(&record)?;
+    }
+    if let Some(executions) = records.executions {
+        executions.append(script.source, Some(script.digest.to_string()))?;
+    }
+    let status = match dir {
+        Some(dir) => utils::run_script_in(path, dir, interactive),
+        None => utils::run_script(&path.to_string_lossy(), interactive),
+    }
+    .map_err(|e| anyhow!(e).context(format!("Cannot run {}", script.source)))?;
+    Ok((status, approver))
+}
@@ -0,0 +1,155 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SgetError;
+use crate::keys::PublicKey;
+use crate::pipeline::{Pipeline, Stage, StageContext};
+use crate::provenance::{self, Envelope};
+use crate::verify_core::TrustRoots;
+
+const SPDX_PREDICATE_TYPE: &str = "https://spdx.dev/Document";
+const CYCLONEDX_PREDICATE_TYPE: &str = "https://cyclonedx.org/bom";
+
+/// Where a manifest entry's SBOM attestations are.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SbomRequirements {
+    /// A file of DSSE envelopes, one per line, or `oci://<repository>` for the
+    /// cosign attestations of the artifact's digest in that repository.
+    /// Defaults to `<url>.sbom.intoto.jsonl`.
+    pub location: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SbomFormat {
+    Spdx,
+    CycloneDx,
+}
+
+impl SbomFormat {
+    /// The conventional file extension of documents in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            SbomFormat::Spdx => "spdx.json",
+            SbomFormat::CycloneDx => "cdx.json",
+        }
+    }
+}
+
+/// A verified SBOM.
+pub struct Sbom {
+    pub format: SbomFormat,
+    /// The JSON encoded SBOM document.
+    pub document: Vec<u8>,
+}
+
+/// Verify one envelope: its signature by `key` or a Fulcio certificate, and
+/// that it is an SPDX or CycloneDX attestation for an artifact with SHA-256
+/// `digest`.
+fn verify_envelope(
+    envelope: &Envelope,
+    digest: &str,
+    key: Option<&PublicKey>,
+    roots: &TrustRoots,
+    identity: Option<&str>,
+) -> Result<Sbom> {
+    let (statement, signer_identity) = provenance::open_envelope(envelope, key, roots)?;
+    let format = match statement.predicate_type.as_str() {
+        SPDX_PREDICATE_TYPE => SbomFormat::Spdx,
+        CYCLONEDX_PREDICATE_TYPE => SbomFormat::CycloneDx,
+        other => return Err(anyhow!("Not an SBOM: {}", other)),
+    };
+    if !statement.is_about(digest) {
+        return Err(anyhow!("SBOM is for a different artifact"));
+    }
+    if let (Some(expected), Some(actual)) = (identity, &signer_identity) {
+        if actual != expected {
+            return Err(anyhow!("SBOM signed by {}, expected {}", actual, expected));
+        }
+    }
+    Ok(Sbom {
+        format,
+        document: serde_json::to_vec_pretty(&statement.predicate)?,
+    })
+}
+
+/// Find the SBOM of an artifact with SHA-256 `digest` among JSON Lines of
+/// DSSE envelopes, as the SBOM check stage of `pipeline`. Envelopes without a
+/// certificate are verified with `key`; keyless ones must be signed by
+/// `identity`, if given.
+pub fn verify_sbom(
+    raw: &[u8],
+    digest: &str,
+    key: Option<&PublicKey>,
+    roots: &TrustRoots,
+    identity: Option<&str>,
+    pipeline: &Pipeline,
+) -> crate::error::Result<Sbom> {
+    let context = StageContext {
+        stage: Stage::SbomCheck,
+        digest,
+        identity,
+    };
+    pipeline.run(context, || {
+        let mut errors = Vec::new();
+        for envelope in provenance::envelopes(raw) {
+            let result = envelope
+                .and_then(|envelope| verify_envelope(&envelope, digest, key, roots, identity));
+            match result {
+                Ok(sbom) => return Ok(sbom),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        if errors.is_empty() {
+            errors.push("No SBOM found".to_string());
+        }
+        Err(SgetError::Sbom(errors.join("; ")))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use serde_json::json;
+
+    #[test]
+    fn verify_cyclonedx_attestation() {
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let digest = "ef".repeat(32);
+        let statement = json!({
+            "_type": "https://in-toto.io/Statement/v0.1",
+            "subject": [{ "name": "install.sh", "digest": { "sha256": digest } }],
+            "predicateType": CYCLONEDX_PREDICATE_TYPE,
+            "predicate": { "bomFormat": "CycloneDX", "specVersion": "1.4", "components": [] },
+        })
+        .to_string();
+        let sig = key.sign(&provenance::pae(
+            provenance::IN_TOTO_PAYLOAD_TYPE,
+            statement.as_bytes(),
+        ));
+        let raw = json!({
+            "payloadType": provenance::IN_TOTO_PAYLOAD_TYPE,
+            "payload": base64::encode(&statement),
+            "signatures": [{ "keyid": "", "sig": base64::encode(sig) }],
+        })
+        .to_string();
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let verify = |digest: &str| {
+            verify_sbom(
+                raw.as_bytes(),
+                digest,
+                Some(&key.public_key()),
+                &roots,
+                None,
+                &Pipeline::default(),
+            )
+        };
+
+        let sbom = verify(&digest).expect("Cannot verify SBOM");
+        assert_eq!(sbom.format, SbomFormat::CycloneDx);
+        let document: serde_json::Value =
+            serde_json::from_slice(&sbom.document).expect("Invalid document");
+        assert_eq!(document["bomFormat"], "CycloneDX");
+        assert!(matches!(verify(&"00".repeat(32)), Err(SgetError::Sbom(_))));
+    }
+}
@@ -0,0 +1,244 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, SubsecRound, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::num::NonZeroU64;
+use std::path::Path;
+
+use crate::policy::{self, Policy};
+use crate::utils::from_now;
+
+/// Scheme of keys for Fulcio identities.
+const FULCIO_SCHEME: &str = "https://fulcio.sigstore.dev";
+
+/// What `sget policy init` puts in a new root policy.
+pub struct Scaffold {
+    /// Namespaces the policy covers.
+    pub namespaces: Vec<String>,
+    /// Root identities and their OIDC issuers; an empty issuer matches any.
+    pub identities: Vec<(String, String)>,
+    pub threshold: NonZeroU64,
+    pub expires: DateTime<Utc>,
+    pub version: NonZeroU64,
+}
+
+/// The `sigstore-oidc` policy key of `identity` issued by `issuer`, and its
+/// key ID: the SHA-256 of the key's canonical JSON, as in existing policies.
+pub fn oidc_key(identity: &str, issuer: &str) -> Result<(String, Value)> {
+    let key = json!({
+        "keyid_hash_algorithms": ["sha256", "sha512"],
+        "keytype": "sigstore-oidc",
+        "keyval": { "identity": identity, "issuer": issuer },
+        "scheme": FULCIO_SCHEME,
+    });
+    // `json!` objects keep their keys sorted, so this is canonical.
+    let keyid = hex::encode(Sha256::digest(&serde_json::to_vec(&key)?));
+    Ok((keyid, key))
+}
+
+impl Scaffold {
+    /// The unsigned root policy, with the `signed` section exactly as it is to
+    /// be signed, checked to parse like any other policy.
+    pub fn policy(&self) -> Result<Vec<u8>> {
+        if self.identities.is_empty() {
+            return Err(anyhow!("A policy needs at least one identity"));
+        }
+        if self.threshold.get() > self.identities.len() as u64 {
+            return Err(anyhow!(
+                "The threshold is {} but only {} identities were given",
+                self.threshold,
+                self.identities.len()
+            ));
+        }
+        let mut keys = serde_json::Map::new();
+        let mut keyids = Vec::new();
+        for (identity, issuer) in &self.identities {
+            let (keyid, key) = oidc_key(identity, issuer)?;
+            if keys.insert(keyid.clone(), key).is_some() {
+                return Err(anyhow!("{} was given twice", identity));
+            }
+            keyids.push(keyid);
+        }
+        let namespace = match self.namespaces.as_slice() {
+            [namespace] => json!(namespace),
+            namespaces => json!(namespaces),
+        };
+        let signed = json!({
+            "_type": "root",
+            "consistent_snapshot": false,
+            "expires": self.expires,
+            "keys": keys,
+            "namespace": namespace,
+            "roles": { "root": { "keyids": keyids, "threshold": self.threshold } },
+            "spec_version": policy::SPEC_VERSION,
+            "version": self.version,
+        });
+        let policy = format!("{{\"signatures\":[],\"signed\":{}}}", signed).into_bytes();
+        serde_json::from_slice::<Policy>(&policy).context("Generated an invalid policy")?;
+        Ok(policy)
+    }
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("init")
+        .about("Generate an unsigned root policy trusting OIDC identities, ready to be signed")
+        .arg(
+            Arg::new("namespace")
+                .long("namespace")
+                .value_name("NAMESPACE")
+                .required(true)
+                .multiple_occurrences(true)
+                .about("Namespace the policy covers; repeat for several"),
+        )
+        .arg(
+            Arg::new("identity")
+                .long("identity")
+                .value_name("IDENTITY")
+                .required(true)
+                .multiple_occurrences(true)
+                .about("Email or URI of a root identity; repeat for several"),
+        )
+        .arg(
+            Arg::new("issuer")
+                .long("issuer")
+                .value_name("ISSUER")
+                .multiple_occurrences(true)
+                .about("OIDC issuer of the identities, once for all or once per identity [default: any issuer]"),
+        )
+        .arg(
+            Arg::new("threshold")
+                .long("threshold")
+                .value_name("N")
+                .about("Number of identities that must sign the policy [default: a majority]"),
+        )
+        .arg(
+            Arg::new("expires")
+                .long("expires")
+                .value_name("DURATION")
+                .default_value("365d")
+                .about("How long the policy is valid for, e.g. 365d"),
+        )
+        .arg(
+            Arg::new("version")
+                .long("version")
+                .value_name("VERSION")
+                .default_value("1")
+                .about("Version of the policy; one more than the policy it replaces"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .value_name("FILE")
+                .default_value("root.json")
+                .about("Where to write the policy; must not exist"),
+        )
+}
+
+pub(crate) fn run(matches: &ArgMatches) -> Result<()> {
+    let values = |name| -> Vec<String> {
+        matches
+            .values_of(name)
+            .map(|values| values.map(String::from).collect())
+            .unwrap_or_default()
+    };
+    let value = |name| {
+        matches
+            .value_of(name)
+            .ok_or_else(|| anyhow!("No --{} given", name))
+    };
+    let number = |value: &str, name| -> Result<NonZeroU64> {
+        value
+            .parse()
+            .map_err(|_| anyhow!("--{} must be a positive number", name))
+    };
+    let identities = values("identity");
+    let issuers = values("issuer");
+    let issuer = |i: usize| match issuers.as_slice() {
+        [] => Ok(String::new()),
+        [issuer] => Ok(issuer.clone()),
+        issuers if issuers.len() == identities.len() => Ok(issuers[i].clone()),
+        _ => Err(anyhow!(
+            "Give --issuer once for all identities or once per identity"
+        )),
+    };
+    let threshold = match matches.value_of("threshold") {
+        Some(threshold) => number(threshold, "threshold")?,
+        None => NonZeroU64::new(identities.len() as u64 / 2 + 1)
+            .ok_or_else(|| anyhow!("Invalid threshold"))?,
+    };
+    let scaffold = Scaffold {
+        namespaces: values("namespace"),
+        identities: identities
+            .iter()
+            .enumerate()
+            .map(|(i, identity)| Ok((identity.clone(), issuer(i)?)))
+            .collect::<Result<_>>()?,
+        threshold,
+        expires: from_now(value("expires")?)?.trunc_subsecs(0),
+        version: number(value("version")?, "version")?,
+    };
+    let policy = scaffold.policy()?;
+    let output = value("output")?;
+    if Path::new(output).exists() {
+        return Err(anyhow!("{} already exists", output));
+    }
+    fs::write(output, &policy).with_context(|| format!("Cannot write {}", output))?;
+    println!(
+        "Unsigned root policy for {} written to {}",
+        scaffold.namespaces.join(", "),
+        output
+    );
+    println!(
+        "{} of the {} identities must sign its `signed` section with their Fulcio certificates, then add the signatures to `signatures`.",
+        scaffold.threshold,
+        scaffold.identities.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Key;
+
+    #[test]
+    fn scaffold_policy() {
+        // The key ID of this identity in tests/test_data/policy_good.json.
+        let (keyid, _) = oidc_key("jyotsnap@bu.edu", "").expect("Cannot make key");
+        assert_eq!(
+            keyid,
+            "0dbdcea45bc3fa9091551690b89caa8bc322546b72fc6c766ccfa2be60547de6"
+        );
+
+        let mut scaffold = Scaffold {
+            namespaces: vec!["ghcr.io/acme".to_string()],
+            identities: vec![
+                (
+                    "release@acme.dev".to_string(),
+                    "https://accounts.google.com".to_string(),
+                ),
+                ("ops@acme.dev".to_string(), String::new()),
+            ],
+            threshold: NonZeroU64::new(2).expect("Zero threshold"),
+            expires: Utc::now() + chrono::Duration::days(365),
+            version: NonZeroU64::new(1).expect("Zero version"),
+        };
+        let raw = scaffold.policy().expect("Cannot scaffold policy");
+        let policy: Policy = serde_json::from_slice(&raw).expect("Invalid policy");
+        assert!(policy.signatures.is_empty());
+        assert_eq!(policy.signed.namespace, "ghcr.io/acme");
+        let root = &policy.signed.roles["root"];
+        assert_eq!(root.threshold.get(), 2);
+        assert_eq!(root.keyids.len(), 2);
+        assert!(root.keyids.iter().all(|keyid| matches!(
+            policy.signed.keys.get(keyid),
+            Some(Key::SigstoreOidc { .. })
+        )));
+
+        scaffold.threshold = NonZeroU64::new(3).expect("Zero threshold");
+        assert!(scaffold.policy().is_err());
+    }
+}
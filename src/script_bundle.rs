@@ -0,0 +1,392 @@
+use anyhow::anyhow;
+use clap::{App, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::approval::{self, ApprovalRequest};
+use crate::attest;
+use crate::audit::AuditLog;
+use crate::config::Config;
+use crate::error::{Result, SgetError};
+use crate::execution_log::ExecutionLog;
+use crate::fetch::{fetch, is_remote};
+use crate::manifest::ManifestEntry;
+use crate::pipeline::Pipeline;
+use crate::runner;
+use crate::transport::{self, Transport};
+use crate::trust::TrustStore;
+use crate::verify::{Signer, TrustRoots};
+
+/// A multi-file script: an entry point and the libraries and data files it
+/// needs, each pinned by digest. The bundle itself is signed like any other
+/// artifact, which covers every file through its pin:
+///
+/// ```yaml
+/// entrypoint: install.sh
+/// files:
+///   - path: install.sh
+///     sha256: 5f2b...
+///   - path: lib/common.sh
+///     sha256: 9c1e...
+///   - path: data/defaults.json
+///     url: https://cdn.example.com/defaults.json
+///     sha256: 03aa...
+/// ```
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptBundle {
+    /// The file to execute, one of `files`.
+    pub entrypoint: String,
+    pub files: Vec<BundleFile>,
+}
+
+/// A file of a script bundle.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BundleFile {
+    /// Where the file goes in the working directory; relative, without `..`.
+    pub path: String,
+    /// URL or path to fetch the file from, relative to the bundle [default:
+    /// `path`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Expected digest: hex SHA-256, or `<algorithm>:<hex>`.
+    pub sha256: String,
+}
+
+impl ScriptBundle {
+    /// Check that every path stays within the working directory and that the
+    /// entry point is one of the files.
+    pub fn validate(&self) -> Result<()> {
+        for file in &self.files {
+            let path = Path::new(&file.path);
+            let contained = path.components().count() > 0
+                && path.components().all(|c| matches!(c, Component::Normal(_)));
+            if !contained {
+                return Err(SgetError::InvalidEntry(format!(
+                    "Bundle file {} is outside the working directory",
+                    file.path
+                )));
+            }
+        }
+        if !self.files.iter().any(|file| file.path == self.entrypoint) {
+            return Err(SgetError::InvalidEntry(format!(
+                "Entry point {} is not a file of the bundle",
+                self.entrypoint
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// `location` relative to the bundle at `bundle`: remote locations and
+/// absolute paths as they are, others next to the bundle.
+fn resolve(bundle: &str, location: &str) -> String {
+    if is_remote(location) || Path::new(location).is_absolute() {
+        return location.to_string();
+    }
+    match bundle.rsplit_once('/') {
+        Some((dir, _)) => format!("{}/{}", dir, location),
+        None => location.to_string(),
+    }
+}
+
+/// Fetch and verify the bundle described by `entry`, then fetch every file
+/// it lists into `workdir`, verifying each against its pin. Nothing is
+/// written unless the bundle and all of its files verify. Returns the bundle,
+/// its signer and the hex SHA-256 digest of the bundle manifest.
+pub async fn fetch_bundle(
+    transport: &dyn Transport,
+    entry: &ManifestEntry,
+    roots: &TrustRoots,
+    pipeline: &Pipeline,
+    workdir: &Path,
+) -> Result<(ScriptBundle, Signer, String)> {
+    let (raw, signer) = entry
+        .fetch_verified(transport, Path::new(""), roots, pipeline)
+        .await?;
+    let bundle: ScriptBundle = serde_yaml::from_slice(&raw)
+        .map_err(|e| SgetError::InvalidEntry(format!("Invalid script bundle: {}", e)))?;
+    bundle.validate()?;
+    let mut contents = Vec::with_capacity(bundle.files.len());
+    for file in &bundle.files {
+        let location = resolve(&entry.url, file.url.as_deref().unwrap_or(&file.path));
+        let data = fetch(transport, &location, Path::new("")).await?;
+        crate::verify::verify_digest(&data, &file.sha256, pipeline)?;
+        contents.push(data);
+    }
+    for (file, data) in bundle.files.iter().zip(contents) {
+        let path = workdir.join(&file.path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| SgetError::Other(e.into()))?;
+        }
+        fs::write(&path, data).map_err(|e| SgetError::Other(e.into()))?;
+        #[cfg(unix)]
+        if file.path == bundle.entrypoint {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                .map_err(|e| SgetError::Other(e.into()))?;
+        }
+    }
+    Ok((bundle, signer, hex::encode(Sha256::digest(&raw))))
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("run-bundle")
+        .about("Fetch and verify a script bundle and all of its files into a fresh working directory, then run its entry point")
+        .arg(
+            Arg::new("bundle")
+                .value_name("BUNDLE")
+                .required(true)
+                .about("URL or path of the bundle manifest"),
+        )
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .value_name("KEY")
+                .takes_value(true)
+                .about("PEM public key the bundle was signed with"),
+        )
+        .arg(
+            Arg::new("certificate-identity")
+                .long("certificate-identity")
+                .value_name("IDENTITY")
+                .takes_value(true)
+                .conflicts_with("key")
+                .about("Required identity of the signing certificate"),
+        )
+        .arg(
+            Arg::new("certificate-oidc-issuer")
+                .long("certificate-oidc-issuer")
+                .value_name("ISSUER")
+                .takes_value(true)
+                .requires("certificate-identity")
+                .about("Required OIDC issuer of the signing certificate"),
+        )
+        .arg(
+            Arg::new("workdir")
+                .long("workdir")
+                .value_name("DIR")
+                .takes_value(true)
+                .about("Empty or new directory to put the files in [default: a temporary directory, removed afterwards]"),
+        )
+        .arg(
+            Arg::new("noexec")
+                .short('n')
+                .long("noexec")
+                .takes_value(false)
+                .requires("workdir")
+                .about("Do not run the entry point"),
+        )
+        .arg(
+            Arg::new("interactive")
+                .short('i')
+                .long("interactive")
+                .takes_value(false)
+                .conflicts_with("noexec")
+                .about("Displays the entry point's stdout on the console"),
+        )
+        .arg(approval::arg())
+}
+
+/// A verified bundle whose files have been written to `workdir`.
+pub(crate) struct FetchedBundle {
+    pub bundle: ScriptBundle,
+    pub signer: Signer,
+    pub url: String,
+    /// SHA-256 of the bundle file.
+    pub digest: String,
+    pub workdir: PathBuf,
+    roots: TrustRoots,
+    /// Removes `workdir` when dropped, unless `--workdir` was given.
+    _temporary: Option<tempfile::TempDir>,
+}
+
+/// Verify the bundle `sget run-bundle` was given and write its files.
+pub(crate) async fn fetch_to_workdir(matches: &ArgMatches) -> anyhow::Result<FetchedBundle> {
+    let value = |name| matches.value_of(name).map(String::from);
+    let mut entry = ManifestEntry::new(
+        matches
+            .value_of("bundle")
+            .ok_or_else(|| anyhow!("No bundle given"))?,
+    );
+    entry.key = value("key");
+    entry.identity = value("certificate-identity");
+    entry.issuer = value("certificate-oidc-issuer");
+
+    let mut temporary = None;
+    let workdir = match matches.value_of("workdir") {
+        Some(dir) => {
+            let dir = PathBuf::from(dir);
+            if dir.exists() && fs::read_dir(&dir)?.next().is_some() {
+                return Err(anyhow!("{} is not empty", dir.display()));
+            }
+            fs::create_dir_all(&dir)?;
+            dir
+        }
+        None => temporary.insert(tempfile::tempdir()?).path().to_path_buf(),
+    };
+    let roots = TrustRoots::load(&TrustStore::open()?)?;
+    let transport = transport::default_transport();
+    let (bundle, signer, digest) = fetch_bundle(
+        transport.as_ref(),
+        &entry,
+        &roots,
+        &Pipeline::default(),
+        &workdir,
+    )
+    .await?;
+    Ok(FetchedBundle {
+        bundle,
+        signer,
+        url: entry.url,
+        digest,
+        workdir,
+        roots,
+        _temporary: temporary,
+    })
+}
+
+/// Run the entry point of `fetched`, returning who approved it if approval
+/// was required.
+pub(crate) fn execute(
+    matches: &ArgMatches,
+    fetched: &FetchedBundle,
+) -> anyhow::Result<Option<Signer>> {
+    let FetchedBundle {
+        bundle,
+        signer,
+        url,
+        digest,
+        workdir,
+        roots,
+        ..
+    } = fetched;
+    let approvals = approval::read_approvals(matches)?;
+    let config = Config::load()?;
+    let audit = AuditLog::open(&config)?;
+    let executions = ExecutionLog::open(&config)?;
+    let script = runner::Script {
+        source: url,
+        digest,
+        signer: Some(signer),
+    };
+    let ran = runner::run(
+        &script,
+        &workdir.join(&bundle.entrypoint),
+        Some(workdir),
+        matches.is_present("interactive"),
+        &approvals,
+        roots,
+        &runner::Records {
+            audit: Some(&audit),
+            executions: Some(&executions),
+        },
+    );
+    let (status, approver) = match ran {
+        Ok(ran) => ran,
+        Err(e @ SgetError::Unapproved(_)) => {
+            let request = ApprovalRequest::new(
+                url,
+                digest,
+                signer,
+                &attest::requester(),
+                chrono::Utc::now(),
+            );
+            let path = format!("sget-{}.request", &digest[..12]);
+            fs::write(&path, serde_json::to_vec_pretty(&request)?)?;
+            return Err(anyhow::Error::new(e).context(format!(
+                "Approval request written to {}; run it with --approval once a second operator has approved it with `sget approve {}`",
+                path, path
+            )));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !status.success() {
+        return Err(anyhow!("{} failed: {}", bundle.entrypoint, status));
+    }
+    Ok(approver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+
+    #[tokio::test]
+    async fn fetch_bundle_files() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let key_path = dir.path().join("sget.pub");
+        fs::write(
+            &key_path,
+            key.public_key().to_pem().expect("Cannot encode key"),
+        )
+        .expect("Cannot write key");
+        fs::create_dir(dir.path().join("lib")).expect("Cannot create dir");
+        fs::write(dir.path().join("install.sh"), b". lib/common.sh").expect("Cannot write");
+        fs::write(dir.path().join("lib/common.sh"), b"echo common").expect("Cannot write");
+
+        let write_bundle = |lib: &str| {
+            let bundle = format!(
+                "entrypoint: install.sh\nfiles:\n\
+                 - path: install.sh\n  sha256: {}\n\
+                 - path: {}\n  sha256: {}\n",
+                hex::encode(Sha256::digest(b". lib/common.sh")),
+                lib,
+                hex::encode(Sha256::digest(b"echo common")),
+            );
+            fs::write(dir.path().join("bundle.yaml"), &bundle).expect("Cannot write bundle");
+            fs::write(
+                dir.path().join("bundle.yaml.sig"),
+                base64::encode(key.sign(bundle.as_bytes())),
+            )
+            .expect("Cannot write signature");
+        };
+        let mut entry = ManifestEntry::new(&dir.path().join("bundle.yaml").display().to_string());
+        entry.key = Some(key_path.display().to_string());
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let transport = transport::default_transport();
+        let fetch_into = |workdir: PathBuf| {
+            let (entry, roots, transport) = (&entry, &roots, &transport);
+            async move {
+                fetch_bundle(
+                    transport.as_ref(),
+                    entry,
+                    roots,
+                    &Pipeline::default(),
+                    &workdir,
+                )
+                .await
+            }
+        };
+
+        write_bundle("lib/common.sh");
+        let workdir = dir.path().join("work");
+        let (bundle, _, _) = fetch_into(workdir.clone())
+            .await
+            .expect("Cannot fetch bundle");
+        assert_eq!(bundle.files.len(), 2);
+        assert_eq!(
+            fs::read(workdir.join("lib/common.sh")).expect("Not fetched"),
+            b"echo common"
+        );
+
+        // A tampered file fails its pin, and nothing is written.
+        fs::write(dir.path().join("lib/common.sh"), b"echo evil").expect("Cannot write");
+        let tampered = dir.path().join("tampered");
+        assert!(matches!(
+            fetch_into(tampered.clone()).await,
+            Err(SgetError::DigestMismatch { .. })
+        ));
+        assert!(!tampered.exists());
+
+        write_bundle("../common.sh");
+        assert!(matches!(
+            fetch_into(dir.path().join("escaped")).await,
+            Err(SgetError::InvalidEntry(_))
+        ));
+    }
+}
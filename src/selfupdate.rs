@@ -0,0 +1,294 @@
+use anyhow::{anyhow, Context, Result};
+use clap::{App, Arg, ArgMatches};
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::SgetError;
+use crate::pipeline::Pipeline;
+use crate::policy::SigstoreOidcKey;
+use crate::provenance::{self, ProvenanceRequirements};
+use crate::rekor::Bundle;
+use crate::transport::{self, Transport};
+use crate::trust::TrustStore;
+use crate::verify::{self, BlobSignature, TrustRoots};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/sigstore/sget/releases/latest";
+
+/// Releases are signed keyless by the release workflow of the sget repository.
+const RELEASE_WORKFLOW: &str = "https://github.com/sigstore/sget/.github/workflows/release.yml";
+const GITHUB_ACTIONS_ISSUER: &str = "https://token.actions.githubusercontent.com";
+
+/// Release binaries are built by the SLSA generic generator from the sget
+/// repository, which attests to that in the release provenance.
+const RELEASE_BUILDER: &str =
+    "https://github.com/slsa-framework/slsa-github-generator/.github/workflows/generator_generic_slsa3.yml";
+const RELEASE_SOURCE: &str = "github.com/sigstore/sget";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    async fn download(&self, transport: &dyn Transport, name: &str) -> Result<Vec<u8>> {
+        let asset = self
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| anyhow!("Release {} has no asset {}", self.tag_name, name))?;
+        let response = transport
+            .send(transport::get(&asset.browser_download_url)?)
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Cannot download {}: {}",
+                asset.browser_download_url,
+                response.status()
+            ));
+        }
+        Ok(response.into_body())
+    }
+}
+
+/// The name of the release binary for the platform sget is running on.
+fn asset_name() -> String {
+    let suffix = if cfg!(windows) { ".exe" } else { "" };
+    format!("sget-{}-{}{}", env::consts::OS, env::consts::ARCH, suffix)
+}
+
+/// The identity the release workflow signs releases for `tag` with.
+fn release_identity(tag: &str) -> SigstoreOidcKey {
+    SigstoreOidcKey {
+        identity: format!("{}@refs/tags/{}", RELEASE_WORKFLOW, tag),
+        issuer: GITHUB_ACTIONS_ISSUER.to_string(),
+    }
+}
+
+/// What the provenance of the release binaries must show: that the SLSA
+/// generator built them from the sget repository.
+fn release_provenance() -> ProvenanceRequirements {
+    ProvenanceRequirements {
+        builder: Some(RELEASE_BUILDER.to_string()),
+        source: Some(RELEASE_SOURCE.to_string()),
+        min_level: 3,
+        ..ProvenanceRequirements::default()
+    }
+}
+
+/// Verify that `provenance`, JSON Lines of DSSE envelopes signed keyless by
+/// the builder, shows `binary` was built by [`release_provenance`] from `tag`.
+fn verify_release_provenance(
+    provenance: &[u8],
+    binary: &[u8],
+    tag: &str,
+    roots: &TrustRoots,
+    pipeline: &Pipeline,
+) -> Result<()> {
+    let digest = hex::encode(Sha256::digest(binary));
+    let built = provenance::verify_provenance(
+        provenance,
+        &digest,
+        None,
+        roots,
+        &release_provenance(),
+        pipeline,
+    )?;
+    let expected = format!("refs/tags/{}", tag);
+    if built.source_ref.as_deref() != Some(expected.as_str()) {
+        return Err(anyhow!(
+            "Built from {}, expected {}",
+            built.source_ref.as_deref().unwrap_or("an unknown ref"),
+            expected
+        ));
+    }
+    Ok(())
+}
+
+/// Atomically replace the executable at `target` with `contents`.
+///
+/// The new binary is written next to the target and renamed over it, so the
+/// target is never left partially written.
+pub fn replace_executable(target: &Path, contents: &[u8]) -> Result<()> {
+    let dir = target
+        .parent()
+        .ok_or_else(|| anyhow!("Cannot determine directory of {}", target.display()))?;
+    let staged = dir.join(format!(".sget-update-{}", std::process::id()));
+    fs::write(&staged, contents).with_context(|| format!("Cannot write to {}", dir.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+    // Windows cannot replace a running executable, but can rename it away.
+    #[cfg(windows)]
+    {
+        let old = target.with_extension("old.exe");
+        let _ = fs::remove_file(&old);
+        fs::rename(target, &old)?;
+    }
+    fs::rename(&staged, target).map_err(|e| {
+        let _ = fs::remove_file(&staged);
+        // Put the running binary back rather than leave nothing at `target`.
+        #[cfg(windows)]
+        let _ = fs::rename(target.with_extension("old.exe"), target);
+        anyhow!("Cannot replace {}: {}", target.display(), e)
+    })
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("self-update")
+        .about("Update sget to the latest signed release")
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .takes_value(false)
+                .about("Only report whether an update is available"),
+        )
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    if transport::is_offline() {
+        return Err(SgetError::Offline(RELEASES_URL.to_string()).into());
+    }
+    let transport = transport::default_transport();
+    let response = transport.send(transport::get(RELEASES_URL)?).await?;
+    let release: Release = transport::json(&response)?;
+
+    let current = Version::parse(env!("CARGO_PKG_VERSION"))?;
+    let latest = Version::parse(release.tag_name.trim_start_matches('v'))
+        .with_context(|| format!("Unexpected release tag {}", release.tag_name))?;
+    if latest <= current {
+        println!("sget {} is up to date", current);
+        return Ok(());
+    }
+    if matches.is_present("check") {
+        println!("sget {} is available (running {})", latest, current);
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let transport = transport.as_ref();
+    let binary = release.download(transport, &name).await?;
+    let signature = release
+        .download(transport, &format!("{}.sig", name))
+        .await?;
+    let certificate = release
+        .download(transport, &format!("{}.pem", name))
+        .await?;
+    let bundle = release
+        .download(transport, &format!("{}.bundle", name))
+        .await?;
+    let attestation = release
+        .download(transport, &format!("{}.intoto.jsonl", name))
+        .await?;
+    let sig = BlobSignature {
+        signature: base64::decode(String::from_utf8(signature)?.trim())?,
+        certificate: Some(String::from_utf8(certificate)?),
+        public_key: None,
+        bundle: Some(serde_json::from_slice::<Bundle>(&bundle)?),
+    };
+
+    // Only replace ourselves with a binary built from the release tag by the
+    // SLSA generator and signed by our release workflow.
+    let roots = TrustRoots::load(&TrustStore::open()?)?;
+    let pipeline = Pipeline::default();
+    let expected = release_identity(&release.tag_name);
+    verify::verify_blob(&binary, &sig, &roots, Some(&expected), &pipeline)
+        .context("Release signature verification failed")?;
+    verify_release_provenance(&attestation, &binary, &release.tag_name, &roots, &pipeline)
+        .context("Release provenance verification failed")?;
+
+    let target: PathBuf = env::current_exe()?.canonicalize()?;
+    replace_executable(&target, &binary)?;
+    println!("Updated sget {} -> {}", current, latest);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_identity_pins_tag() {
+        let identity = release_identity("v0.2.0");
+        assert_eq!(
+            identity.identity,
+            "https://github.com/sigstore/sget/.github/workflows/release.yml@refs/tags/v0.2.0"
+        );
+        assert_eq!(identity.issuer, GITHUB_ACTIONS_ISSUER);
+    }
+
+    #[test]
+    fn release_provenance_needs_builder_signature() {
+        use crate::keys::{KeyAlgorithm, SigningKey};
+        use serde_json::json;
+
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let binary = b"sget";
+        let statement = json!({
+            "_type": "https://in-toto.io/Statement/v0.1",
+            "subject": [{ "name": "sget", "digest": { "sha256": hex::encode(Sha256::digest(binary)) } }],
+            "predicateType": "https://slsa.dev/provenance/v0.2",
+            "predicate": {
+                "builder": { "id": format!("{}@refs/tags/v1.2.0", RELEASE_BUILDER) },
+                "invocation": {
+                    "configSource": { "uri": "git+https://github.com/sigstore/sget@refs/tags/v0.2.0" },
+                },
+            },
+        })
+        .to_string();
+        let sig = key.sign(&provenance::pae(
+            provenance::IN_TOTO_PAYLOAD_TYPE,
+            statement.as_bytes(),
+        ));
+        let envelope = json!({
+            "payloadType": provenance::IN_TOTO_PAYLOAD_TYPE,
+            "payload": base64::encode(&statement),
+            "signatures": [{ "keyid": "", "sig": base64::encode(sig) }],
+        })
+        .to_string();
+
+        // Provenance that is right in every way but not signed keyless by the
+        // builder is refused.
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        assert!(verify_release_provenance(
+            envelope.as_bytes(),
+            binary,
+            "v0.2.0",
+            &roots,
+            &Pipeline::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn replace_executable_in_place() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let target = dir.path().join("sget");
+        fs::write(&target, b"old").expect("Cannot write target");
+
+        replace_executable(&target, b"new").expect("Cannot replace executable");
+        assert_eq!(fs::read(&target).expect("Cannot read target"), b"new");
+        let mode = fs::metadata(&target)
+            .expect("No metadata")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+        // Nothing is left behind next to the target.
+        assert_eq!(fs::read_dir(dir.path()).expect("Cannot list").count(), 1);
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::{anyhow, Result};
+use clap::{App, Arg, ArgMatches};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::audit::{Action, AuditLog, AuditRecord};
+use crate::config::Config;
+use crate::error::SgetError;
+use crate::fetch::is_remote;
+use crate::hooks::{self, Event, HookConfig};
+use crate::manifest::ManifestEntry;
+use crate::metrics::{MeteredTransport, Metrics};
+use crate::pipeline::Pipeline;
+use crate::transport::{self, Transport};
+use crate::trust::TrustStore;
+use crate::verify::{Signer, TrustRoots};
+
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8377";
+
+/// Parse the query of an `/artifact` request into the entry to verify. The
+/// parameters are the fields of a manifest entry, e.g.
+/// `/artifact?url=https://example.com/install.sh&identity=me@example.com`.
+///
+/// Only remote locations are accepted, so clients cannot read local files
+/// through the daemon.
+pub fn parse_request(query: Option<&str>) -> Result<ManifestEntry> {
+    let entry: ManifestEntry = serde_urlencoded::from_str(query.unwrap_or_default())
+        .map_err(|e| anyhow!("Invalid query: {}", e))?;
+    for location in entry.locations() {
+        if !is_remote(location) {
+            return Err(anyhow!("{} is not an http(s) URL", location));
+        }
+    }
+    Ok(entry)
+}
+
+fn text(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body + "\n"));
+    *response.status_mut() = status;
+    response
+}
+
+/// What the daemon shares between requests.
+struct State {
+    transport: Arc<dyn Transport>,
+    roots: TrustRoots,
+    metrics: Arc<Metrics>,
+    hooks: Vec<HookConfig>,
+    audit: AuditLog,
+}
+
+async fn handle(req: Request<Body>, state: Arc<State>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET {
+        return Ok(text(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Only GET is supported".to_string(),
+        ));
+    }
+    let response = match req.uri().path() {
+        "/healthz" => text(StatusCode::OK, "ok".to_string()),
+        "/metrics" => Response::new(Body::from(state.metrics.render())),
+        "/artifact" => match parse_request(req.uri().query()) {
+            Err(e) => text(StatusCode::BAD_REQUEST, format!("{:#}", e)),
+            Ok(entry) => {
+                let start = Instant::now();
+                let result = entry
+                    .fetch_verified(
+                        state.transport.as_ref(),
+                        Path::new(""),
+                        &state.roots,
+                        &Pipeline::default(),
+                    )
+                    .await;
+                state
+                    .metrics
+                    .record(result.as_ref().map(|_| ()), start.elapsed());
+                let digest = result
+                    .as_ref()
+                    .ok()
+                    .map(|(data, _)| hex::encode(Sha256::digest(data)));
+                state.audit.record_or_warn(&AuditRecord::verification(
+                    Action::Fetch,
+                    &entry.url,
+                    digest,
+                    result.as_ref().map(|(_, signer)| signer),
+                ));
+                if let Err(e) = &result {
+                    hooks::fire(&state.hooks, &Event::verification_failure(&entry.url, e)).await;
+                }
+                artifact_response(&entry, result)
+            }
+        },
+        _ => text(StatusCode::NOT_FOUND, "Not found".to_string()),
+    };
+    Ok(response)
+}
+
+fn artifact_response(
+    entry: &ManifestEntry,
+    result: Result<(Vec<u8>, Signer), SgetError>,
+) -> Response<Body> {
+    match result {
+        Ok((data, signer)) => {
+            eprintln!("OK\t{}\t{}", entry.url, signer.subject());
+            let mut response = Response::new(Body::from(data));
+            if let Ok(value) = signer.subject().parse() {
+                response.headers_mut().insert("X-Sget-Signer", value);
+            }
+            response
+        }
+        Err(e) => {
+            eprintln!("FAILED\t{}\t{}", entry.url, e.describe());
+            text(StatusCode::FORBIDDEN, e.describe())
+        }
+    }
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("serve")
+        .about("Serve verified artifacts to local clients over HTTP")
+        .arg(
+            Arg::new("listen")
+                .long("listen")
+                .value_name("ADDRESS")
+                .default_value(DEFAULT_LISTEN)
+                .about("Address to listen on"),
+        )
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    let addr: SocketAddr = matches
+        .value_of("listen")
+        .unwrap_or(DEFAULT_LISTEN)
+        .parse()
+        .map_err(|e| anyhow!("Invalid listen address: {}", e))?;
+    let config = Config::load()?;
+    let metrics = Arc::new(Metrics::default());
+    let state = Arc::new(State {
+        transport: Arc::new(MeteredTransport::new(
+            transport::default_transport(),
+            metrics.clone(),
+        )),
+        roots: TrustRoots::load(&TrustStore::open()?)?,
+        metrics,
+        audit: AuditLog::open(&config)?,
+        hooks: config.hooks,
+    });
+
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, state.clone()))) }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    eprintln!("Serving verified artifacts on http://{}/artifact", addr);
+    server.await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_artifact_request() {
+        let entry = parse_request(Some(
+            "url=https%3A%2F%2Fexample.com%2Finstall.sh&identity=me%40example.com",
+        ))
+        .expect("Invalid request");
+        assert_eq!(entry.url, "https://example.com/install.sh");
+        assert_eq!(entry.identity.as_deref(), Some("me@example.com"));
+
+        assert!(parse_request(None).is_err());
+        assert!(parse_request(Some("url=%2Fetc%2Fpasswd")).is_err());
+        assert!(parse_request(Some(
+            "url=https%3A%2F%2Fexample.com%2Fa&key=..%2Fsecret.pub"
+        ))
+        .is_err());
+        assert!(parse_request(Some(
+            "url=https%3A%2F%2Fexample.com%2Fa&delta=%2Ftmp%2F%7Bbase%7D"
+        ))
+        .is_err());
+        assert!(parse_request(Some(
+            "url=https%3A%2F%2Fexample.com%2Fa&delta=https%3A%2F%2Fexample.com%2Fa.delta%2F%7Bbase%7D"
+        ))
+        .is_ok());
+    }
+}
@@ -0,0 +1,268 @@
+use anyhow::{anyhow, Context, Result};
+use clap::{App, Arg, ArgMatches};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::{env, fs};
+use tokio_util::sync::CancellationToken;
+
+use crate::keys::{self, KeyAlgorithm, SigningKey};
+use crate::sigstore_bundle::SigstoreBundle;
+use crate::transport::{self, Transport};
+use crate::{ambient, fulcio, profile, rekor, utils};
+
+pub(crate) fn command() -> App<'static> {
+    App::new("sign")
+        .about("Sign a script or other blob")
+        .arg(
+            Arg::new("file")
+                .about("File to sign")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::new("key")
+                .short('k')
+                .long("key")
+                .value_name("KEY_FILE")
+                .takes_value(true)
+                .about("Private key generated by `sget keygen`, or keychain:NAME for one in the platform keychain, whose passphrase is read from SGET_KEY_PASSPHRASE or prompted for; signs keyless with Fulcio when omitted"),
+        )
+        .arg(
+            Arg::new("output-signature")
+                .long("output-signature")
+                .value_name("SIG_FILE")
+                .takes_value(true)
+                .about("Where to write the base64 encoded signature [default: FILE.sig]"),
+        )
+        .arg(
+            Arg::new("output-certificate")
+                .long("output-certificate")
+                .value_name("CERT_FILE")
+                .takes_value(true)
+                .about("Where to write the Fulcio certificate when signing keyless [default: FILE.pem]"),
+        )
+        .arg(
+            Arg::new("bundle")
+                .long("bundle")
+                .value_name("BUNDLE_FILE")
+                .takes_value(true)
+                .about("Upload the signature to Rekor and write the offline bundle here"),
+        )
+        .arg(
+            Arg::new("sigstore-bundle")
+                .long("sigstore-bundle")
+                .value_name("BUNDLE_FILE")
+                .takes_value(true)
+                .about("Upload the signature to Rekor and write a standard sigstore bundle here, e.g. FILE.sigstore.json"),
+        )
+        .arg(
+            Arg::new("identity-token")
+                .long("identity-token")
+                .value_name("TOKEN")
+                .takes_value(true)
+                .about("OIDC identity token for keyless signing [env: SIGSTORE_ID_TOKEN; in GitHub Actions, the job's own token]"),
+        )
+        .arg(
+            Arg::new("fulcio-url")
+                .long("fulcio-url")
+                .value_name("URL")
+                .about("Fulcio certificate authority [default: that of the profile, or of --sigstore-env]"),
+        )
+        .arg(
+            Arg::new("rekor-url")
+                .long("rekor-url")
+                .value_name("URL")
+                .about("Rekor transparency log [default: that of the profile, or of --sigstore-env]"),
+        )
+}
+
+/// Where and how to sign a file. Outputs default to `FILE.sig`, `FILE.pem` and,
+/// for keyless signatures, `FILE.bundle`.
+pub struct SignOptions {
+    /// Private key from `sget keygen`; signs keyless with Fulcio when `None`.
+    pub key: Option<String>,
+    /// OIDC token for keyless signing, falling back to `SIGSTORE_ID_TOKEN` and
+    /// then to the token of the CI job, see [`ambient::identity_token`].
+    pub identity_token: Option<String>,
+    pub output_signature: Option<String>,
+    pub output_certificate: Option<String>,
+    pub bundle: Option<String>,
+    /// Where to write a bundle in the standard sigstore format, if anywhere.
+    pub sigstore_bundle: Option<String>,
+    pub fulcio_url: String,
+    pub rekor_url: String,
+    /// Transport for the Fulcio and Rekor requests.
+    pub transport: Arc<dyn Transport>,
+    /// Abandons the Fulcio and Rekor requests when cancelled.
+    pub cancel: Option<CancellationToken>,
+}
+
+impl Default for SignOptions {
+    fn default() -> Self {
+        SignOptions {
+            key: None,
+            identity_token: None,
+            output_signature: None,
+            output_certificate: None,
+            bundle: None,
+            sigstore_bundle: None,
+            fulcio_url: profile::fulcio_url(),
+            rekor_url: profile::rekor_url(),
+            transport: transport::default_transport(),
+            cancel: None,
+        }
+    }
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    let file = matches
+        .value_of("file")
+        .ok_or_else(|| anyhow!("No file to sign"))?;
+    let value = |name| matches.value_of(name).map(String::from);
+    let defaults = SignOptions::default();
+    let options = SignOptions {
+        key: value("key"),
+        identity_token: value("identity-token"),
+        output_signature: value("output-signature"),
+        output_certificate: value("output-certificate"),
+        bundle: value("bundle"),
+        sigstore_bundle: value("sigstore-bundle"),
+        fulcio_url: value("fulcio-url").unwrap_or(defaults.fulcio_url),
+        rekor_url: value("rekor-url").unwrap_or(defaults.rekor_url),
+        transport: defaults.transport,
+        cancel: None,
+    };
+    sign_file(file, &options).await
+}
+
+/// Sign `file` and write the signature material next to it. Nothing is written
+/// until the Fulcio and Rekor requests have succeeded, so a failed or cancelled
+/// signing leaves no partial material behind.
+pub async fn sign_file(file: &str, options: &SignOptions) -> Result<()> {
+    let data = fs::read(file).with_context(|| format!("Cannot read {}", file))?;
+    let digest = hex::encode(Sha256::digest(&data));
+
+    let (key, certificate) = signing_key(options).await?;
+    let signature = key.sign(&data);
+
+    // Keyless signatures are only meaningful with a transparency log entry.
+    let bundle_path = match (&options.bundle, &certificate) {
+        (Some(path), _) => Some(path.to_string()),
+        (None, Some(_)) if options.sigstore_bundle.is_none() => Some(format!("{}.bundle", file)),
+        (None, _) => None,
+    };
+    let entry = if bundle_path.is_some() || options.sigstore_bundle.is_some() {
+        Some(upload(options, &key, certificate.as_deref(), &digest, &signature).await?)
+    } else {
+        None
+    };
+
+    let sig_path = options
+        .output_signature
+        .clone()
+        .unwrap_or_else(|| format!("{}.sig", file));
+    fs::write(&sig_path, base64::encode(&signature))?;
+    println!("Signature written to {}", sig_path);
+
+    if let Some(chain) = &certificate {
+        let cert_path = options
+            .output_certificate
+            .clone()
+            .unwrap_or_else(|| format!("{}.pem", file));
+        fs::write(&cert_path, chain)?;
+        println!("Certificate written to {}", cert_path);
+    }
+
+    if let Some(entry) = &entry {
+        println!("Uploaded to Rekor at index {}", entry.log_index);
+    }
+    if let (Some(bundle_path), Some(entry)) = (&bundle_path, &entry) {
+        fs::write(bundle_path, serde_json::to_vec(&entry.to_bundle()?)?)?;
+        println!("Bundle written to {}", bundle_path);
+    }
+    if let (Some(bundle_path), Some(entry)) = (&options.sigstore_bundle, &entry) {
+        let bundle = SigstoreBundle::new(
+            &Sha256::digest(&data),
+            &signature,
+            certificate.as_deref(),
+            Some(&key.public_key()),
+            entry,
+        )?;
+        fs::write(bundle_path, serde_json::to_vec(&bundle)?)?;
+        println!("Sigstore bundle written to {}", bundle_path);
+    }
+    Ok(())
+}
+
+/// The key to sign with: the private key of `options`, or an ephemeral key
+/// together with the Fulcio certificate chain binding it to the identity of
+/// the OIDC token.
+pub(crate) async fn signing_key(options: &SignOptions) -> Result<(SigningKey, Option<String>)> {
+    match &options.key {
+        Some(key_file) => {
+            let key = keys::read_signing_key(key_file)?;
+            Ok((key, None))
+        }
+        None => {
+            let token = match (
+                options.identity_token.clone(),
+                env::var("SIGSTORE_ID_TOKEN"),
+            ) {
+                (Some(token), _) | (None, Ok(token)) => token,
+                (None, Err(_)) => {
+                    let var = |name: &str| env::var(name).ok();
+                    let ambient = ambient::identity_token(options.transport.as_ref(), &var);
+                    match utils::cancellable(options.cancel.as_ref(), ambient).await?? {
+                        Some((provider, token)) => {
+                            println!("Using the identity token of this {} job", provider);
+                            token
+                        }
+                        None => {
+                            return Err(anyhow!(
+                                "Keyless signing requires --identity-token or SIGSTORE_ID_TOKEN outside GitHub Actions and GitLab CI"
+                            ))
+                        }
+                    }
+                }
+            };
+            let claims = fulcio::IdentityClaims::from_token(&token)?;
+            println!("Signing as {} (issuer {})", claims.subject(), claims.iss);
+            // Keyless signing uses an ephemeral key bound to the identity by Fulcio.
+            let key = SigningKey::generate(KeyAlgorithm::EcdsaP256)?;
+            let request = fulcio::request_certificate(
+                options.transport.as_ref(),
+                &options.fulcio_url,
+                &token,
+                &key,
+            );
+            let chain = utils::cancellable(options.cancel.as_ref(), request).await??;
+            Ok((key, Some(chain)))
+        }
+    }
+}
+
+/// Upload a signature over the SHA-256 `digest` to Rekor, verified by the
+/// leaf of the `certificate` chain or, without one, the public key of `key`.
+pub(crate) async fn upload(
+    options: &SignOptions,
+    key: &SigningKey,
+    certificate: Option<&str>,
+    digest: &str,
+    signature: &[u8],
+) -> Result<rekor::LogEntry> {
+    if key.algorithm() != KeyAlgorithm::EcdsaP256 {
+        return Err(anyhow!("Rekor upload requires an ecdsa-p256 key"));
+    }
+    let verifier = match certificate {
+        Some(chain) => fulcio::leaf_certificate(chain)?,
+        None => key.public_key().to_pem()?,
+    };
+    let upload = rekor::upload_hashedrekord(
+        options.transport.as_ref(),
+        &options.rekor_url,
+        digest,
+        signature,
+        &verifier,
+    );
+    utils::cancellable(options.cancel.as_ref(), upload).await?
+}
@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::algorithms::AlgorithmPolicy;
+use crate::keys::PublicKey;
+use crate::policy::Key;
+use crate::ssh::{self, SshPublicKey, SshSignature};
+
+/// Verifies signatures made by policy keys of one key type and scheme.
+///
+/// Register implementations with [`SignatureVerifiers::register`] to accept
+/// policy keys sget has no built-in support for, such as post-quantum keys or
+/// keys held in an HSM that only the HSM vendor's library can verify for.
+pub trait SignatureVerifier: Send + Sync {
+    /// Verify `signature` over `msg` by `key`.
+    fn verify(&self, key: &Key, msg: &[u8], signature: &[u8]) -> Result<()>;
+}
+
+/// The built-in verifier for keys whose `keyval` holds a PEM public key.
+struct PemKeyVerifier;
+
+impl SignatureVerifier for PemKeyVerifier {
+    fn verify(&self, key: &Key, msg: &[u8], signature: &[u8]) -> Result<()> {
+        match key {
+            Key::EcdsaP256 { keyval, .. } | Key::Ed25519 { keyval, .. } => {
+                PublicKey::from_pem(&keyval.public)?.verify(msg, signature)
+            }
+            other => Err(anyhow!("Not a PEM key: {}", other.keytype())),
+        }
+    }
+}
+
+/// The built-in verifier for `ssh` keys, whose signatures are made with
+/// `ssh-keygen -Y sign` in the policy namespace.
+struct SshKeyVerifier;
+
+impl SignatureVerifier for SshKeyVerifier {
+    fn verify(&self, key: &Key, msg: &[u8], signature: &[u8]) -> Result<()> {
+        let public = match key {
+            Key::Ssh { keyval, .. } => SshPublicKey::from_openssh(&keyval.public)?,
+            other => return Err(anyhow!("Not an SSH key: {}", other.keytype())),
+        };
+        let signature = SshSignature::parse(signature)?;
+        if signature.public_key != public {
+            return Err(anyhow!("Signature is not by {}", public.fingerprint()));
+        }
+        signature.verify(msg, ssh::POLICY_NAMESPACE)?;
+        // Policy keys are declared by the policy itself, so only FIPS mode
+        // restricts their algorithms.
+        Ok(signature.check_algorithms(&AlgorithmPolicy::default())?)
+    }
+}
+
+/// Signature verifiers by key type and scheme.
+///
+/// `sigstore-oidc` keys are not looked up here: their signatures are verified
+/// against the certificate that accompanies them.
+#[derive(Clone)]
+pub struct SignatureVerifiers {
+    verifiers: HashMap<(String, String), Arc<dyn SignatureVerifier>>,
+}
+
+impl Default for SignatureVerifiers {
+    /// The built-in verifiers for `ecdsa-sha2-nistp256`, `ed25519` and `ssh`
+    /// keys.
+    fn default() -> Self {
+        let mut verifiers = SignatureVerifiers {
+            verifiers: HashMap::new(),
+        };
+        let pem: Arc<dyn SignatureVerifier> = Arc::new(PemKeyVerifier);
+        verifiers.register("ecdsa-sha2-nistp256", "", pem.clone());
+        verifiers.register("ed25519", "", pem);
+        verifiers.register("ssh", "", Arc::new(SshKeyVerifier));
+        verifiers
+    }
+}
+
+impl SignatureVerifiers {
+    /// Verify keys of `keytype` used with `scheme` with `verifier`, replacing
+    /// any verifier registered for them before. An empty `scheme` covers every
+    /// scheme without a verifier of its own.
+    pub fn register(&mut self, keytype: &str, scheme: &str, verifier: Arc<dyn SignatureVerifier>) {
+        self.verifiers
+            .insert((keytype.to_string(), scheme.to_string()), verifier);
+    }
+
+    /// Verify `signature` over `msg` with the verifier registered for `key`.
+    pub fn verify(&self, key: &Key, msg: &[u8], signature: &[u8]) -> Result<()> {
+        let keytype = key.keytype().to_string();
+        self.verifiers
+            .get(&(keytype.clone(), key.scheme().to_string()))
+            .or_else(|| self.verifiers.get(&(keytype, String::new())))
+            .ok_or_else(|| {
+                anyhow!(
+                    "No verifier for {} keys with scheme {}",
+                    key.keytype(),
+                    key.scheme()
+                )
+            })?
+            .verify(key, msg, signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Pipeline;
+    use crate::verify_core::{self, TrustRoots};
+    use sha2::{Digest, Sha256};
+
+    /// A toy scheme whose signatures are the SHA-256 of a salt and the message.
+    struct SaltedDigest;
+
+    impl SignatureVerifier for SaltedDigest {
+        fn verify(&self, key: &Key, msg: &[u8], signature: &[u8]) -> Result<()> {
+            let salt = match key {
+                Key::Other(key) => key.keyval["salt"].as_str().unwrap_or_default(),
+                _ => return Err(anyhow!("Not a salted digest key")),
+            };
+            let expected = Sha256::new().chain(salt).chain(msg).finalize();
+            if expected.as_slice() != signature {
+                return Err(anyhow!("Verification failed"));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn register_key_type() {
+        let signed = serde_json::json!({
+            "consistent_snapshot": true,
+            "expires": "2100-01-01T00:00:00Z",
+            "keys": {
+                "k1": { "keytype": "salted-digest", "scheme": "sha256", "keyval": { "salt": "pepper" } },
+            },
+            "namespace": "example.com/scripts",
+            "roles": { "root": { "keyids": ["k1"], "threshold": 1 } },
+            "spec_version": "1.0",
+            "version": 1,
+        })
+        .to_string();
+        let sig = Sha256::new().chain("pepper").chain(&signed).finalize();
+        let raw = format!(
+            r#"{{"signatures":[{{"keyid":"k1","sig":"{}","cert":""}}],"signed":{}}}"#,
+            base64::encode(sig),
+            signed
+        );
+
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let now = chrono::Utc::now();
+        let mut verifiers = SignatureVerifiers::default();
+        assert!(verify_core::verify_policy(
+            raw.as_bytes(),
+            &roots,
+            &verifiers,
+            &Pipeline::default(),
+            now
+        )
+        .is_err());
+        verifiers.register("salted-digest", "sha256", Arc::new(SaltedDigest));
+        let policy = verify_core::verify_policy(
+            raw.as_bytes(),
+            &roots,
+            &verifiers,
+            &Pipeline::default(),
+            now,
+        )
+        .expect("Cannot verify policy");
+
+        // Unknown keys survive a round trip unchanged.
+        let key = serde_json::to_value(&policy.signed.keys["k1"]).expect("Cannot encode key");
+        assert_eq!(key["keytype"], "salted-digest");
+        assert_eq!(key["keyval"]["salt"], "pepper");
+    }
+}
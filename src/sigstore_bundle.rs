@@ -0,0 +1,285 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::keys::PublicKey;
+use crate::rekor::{Bundle, BundlePayload, LogEntry};
+use crate::verify_core::{certificate_pem, pem_certificates};
+
+/// Media type of the bundles sget writes: a signature with its Rekor inclusion
+/// promise.
+pub const MEDIA_TYPE_V01: &str = "application/vnd.dev.sigstore.bundle+json;version=0.1";
+
+const MEDIA_TYPE_PREFIX: &str = "application/vnd.dev.sigstore.bundle";
+
+/// A bundle in the standard sigstore format, as in the JSON encoding of the
+/// `dev.sigstore.bundle.v1.Bundle` protobuf message. Bytes are base64 encoded
+/// and 64-bit integers are strings, following the protobuf JSON mapping.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigstoreBundle {
+    pub media_type: String,
+    pub verification_material: VerificationMaterial,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_signature: Option<MessageSignature>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dsse_envelope: Option<Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationMaterial {
+    /// The signing certificate followed by its chain, up to version 0.2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x509_certificate_chain: Option<CertificateChain>,
+    /// The signing certificate, from version 0.3.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<RawBytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<PublicKeyIdentifier>,
+    #[serde(default)]
+    pub tlog_entries: Vec<TransparencyLogEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CertificateChain {
+    pub certificates: Vec<RawBytes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawBytes {
+    pub raw_bytes: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PublicKeyIdentifier {
+    #[serde(default)]
+    pub hint: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransparencyLogEntry {
+    pub log_index: String,
+    pub log_id: LogId,
+    pub kind_version: KindVersion,
+    pub integrated_time: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inclusion_promise: Option<InclusionPromise>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inclusion_proof: Option<Value>,
+    pub canonicalized_body: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogId {
+    pub key_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KindVersion {
+    pub kind: String,
+    pub version: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionPromise {
+    pub signed_entry_timestamp: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageSignature {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_digest: Option<HashOutput>,
+    pub signature: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HashOutput {
+    pub algorithm: String,
+    pub digest: String,
+}
+
+impl SigstoreBundle {
+    /// Parse a bundle of any version, rejecting other JSON documents.
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let bundle: SigstoreBundle = serde_json::from_slice(raw)?;
+        if !bundle.media_type.starts_with(MEDIA_TYPE_PREFIX) {
+            return Err(anyhow!("Not a sigstore bundle: {}", bundle.media_type));
+        }
+        Ok(bundle)
+    }
+
+    /// A version 0.1 bundle for a signature over a blob with SHA-256 `digest`,
+    /// made by the certificate `chain` or by `key`, and logged as `entry`.
+    pub fn new(
+        digest: &[u8],
+        signature: &[u8],
+        chain: Option<&str>,
+        key: Option<&PublicKey>,
+        entry: &LogEntry,
+    ) -> Result<Self> {
+        let rekor = entry.to_bundle()?;
+        let body: Value = serde_json::from_slice(&base64::decode(&entry.body)?)?;
+        let field = |name: &str| {
+            body[name]
+                .as_str()
+                .map(String::from)
+                .ok_or_else(|| anyhow!("Rekor entry has no {}", name))
+        };
+        let x509_certificate_chain = chain
+            .map(|chain| -> Result<_> {
+                let certificates = pem_certificates(chain.as_bytes())?
+                    .iter()
+                    .map(|der| RawBytes {
+                        raw_bytes: base64::encode(der),
+                    })
+                    .collect();
+                Ok(CertificateChain { certificates })
+            })
+            .transpose()?;
+        let public_key = match (chain, key) {
+            (None, Some(key)) => Some(PublicKeyIdentifier {
+                hint: key.key_id()?,
+            }),
+            _ => None,
+        };
+        Ok(SigstoreBundle {
+            media_type: MEDIA_TYPE_V01.to_string(),
+            verification_material: VerificationMaterial {
+                x509_certificate_chain,
+                certificate: None,
+                public_key,
+                tlog_entries: vec![TransparencyLogEntry {
+                    log_index: entry.log_index.to_string(),
+                    log_id: LogId {
+                        key_id: base64::encode(hex::decode(&entry.log_id)?),
+                    },
+                    kind_version: KindVersion {
+                        kind: field("kind")?,
+                        version: field("apiVersion")?,
+                    },
+                    integrated_time: entry.integrated_time.to_string(),
+                    inclusion_promise: Some(InclusionPromise {
+                        signed_entry_timestamp: rekor.signed_entry_timestamp,
+                    }),
+                    inclusion_proof: None,
+                    canonicalized_body: entry.body.clone(),
+                }],
+            },
+            message_signature: Some(MessageSignature {
+                message_digest: Some(HashOutput {
+                    algorithm: "SHA2_256".to_string(),
+                    digest: base64::encode(digest),
+                }),
+                signature: base64::encode(signature),
+            }),
+            dsse_envelope: None,
+        })
+    }
+
+    /// The base64 encoded signature over the blob.
+    pub fn signature(&self) -> Result<&str> {
+        match &self.message_signature {
+            Some(signature) => Ok(&signature.signature),
+            None => Err(anyhow!("Bundles of DSSE envelopes are not supported")),
+        }
+    }
+
+    /// The PEM encoded signing certificate and chain, for keyless signatures.
+    pub fn certificate(&self) -> Result<Option<String>> {
+        let material = &self.verification_material;
+        let certificates = match (&material.x509_certificate_chain, &material.certificate) {
+            (Some(chain), _) => chain.certificates.iter().collect(),
+            (None, Some(certificate)) => vec![certificate],
+            (None, None) => return Ok(None),
+        };
+        let mut pem = String::new();
+        for certificate in certificates {
+            pem.push_str(&certificate_pem(&base64::decode(&certificate.raw_bytes)?));
+        }
+        Ok(Some(pem))
+    }
+
+    /// The Rekor bundle of the first log entry with an inclusion promise.
+    /// Entries with only an inclusion proof are not supported yet.
+    pub fn rekor_bundle(&self) -> Result<Bundle> {
+        let (entry, promise) = self
+            .verification_material
+            .tlog_entries
+            .iter()
+            .find_map(|entry| Some((entry, entry.inclusion_promise.as_ref()?)))
+            .ok_or_else(|| anyhow!("Bundle has no log entry with an inclusion promise"))?;
+        Ok(Bundle {
+            signed_entry_timestamp: promise.signed_entry_timestamp.clone(),
+            payload: BundlePayload {
+                body: entry.canonicalized_body.clone(),
+                integrated_time: entry.integrated_time.parse()?,
+                log_index: entry.log_index.parse()?,
+                log_id: hex::encode(base64::decode(&entry.log_id.key_id)?),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use crate::rekor::Verification;
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn round_trip_key_bundle() {
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let signature = key.sign(b"echo hi");
+        let body = json!({ "apiVersion": "0.0.1", "kind": "hashedrekord", "spec": {} });
+        let entry = LogEntry {
+            body: base64::encode(body.to_string()),
+            integrated_time: 1637699000,
+            log_id: "c0d23d6ad406973f9559f3ba2d1ca01f84147d8ffc5b8445c224f98b9591801d".to_string(),
+            log_index: 885000,
+            verification: Some(Verification {
+                signed_entry_timestamp: Some("MEUCIQ==".to_string()),
+            }),
+        };
+        let bundle = SigstoreBundle::new(
+            &Sha256::digest(b"echo hi"),
+            &signature,
+            None,
+            Some(&key.public_key()),
+            &entry,
+        )
+        .expect("Cannot create bundle");
+        let raw = serde_json::to_vec(&bundle).expect("Cannot encode bundle");
+        let encoded: Value = serde_json::from_slice(&raw).expect("Invalid JSON");
+        assert_eq!(
+            encoded["verificationMaterial"]["tlogEntries"][0]["logIndex"],
+            "885000"
+        );
+        assert_eq!(
+            encoded["verificationMaterial"]["tlogEntries"][0]["kindVersion"]["kind"],
+            "hashedrekord"
+        );
+
+        let parsed = SigstoreBundle::parse(&raw).expect("Cannot parse bundle");
+        assert_eq!(
+            parsed.signature().expect("No signature"),
+            base64::encode(&signature)
+        );
+        assert_eq!(parsed.certificate().expect("Invalid certificate"), None);
+        assert_eq!(
+            parsed.rekor_bundle().expect("No Rekor bundle"),
+            entry.to_bundle().expect("No Rekor bundle")
+        );
+        assert!(SigstoreBundle::parse(
+            b"{\"mediaType\": \"text/plain\", \"verificationMaterial\": {}}"
+        )
+        .is_err());
+    }
+}
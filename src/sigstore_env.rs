@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use serde_plain::{derive_display_from_serialize, derive_fromstr_from_deserialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A sigstore deployment: the Fulcio certificate authority and Rekor log that
+/// signing uses, and the trust roots verification starts from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SigstoreEnv {
+    /// The public-good sigstore instance.
+    Production,
+    /// sigstore's staging instance, for testing signing and verification
+    /// without writing to the production log. Its roots are not built in.
+    Staging,
+}
+
+derive_display_from_serialize!(SigstoreEnv);
+derive_fromstr_from_deserialize!(SigstoreEnv);
+
+static STAGING: AtomicBool = AtomicBool::new(false);
+
+impl SigstoreEnv {
+    /// Use `env` for the rest of this process. Must be called before any
+    /// signing options or trust roots are created.
+    pub fn select(env: SigstoreEnv) {
+        STAGING.store(env == SigstoreEnv::Staging, Ordering::SeqCst);
+    }
+
+    /// The environment chosen with [`SigstoreEnv::select`], production by
+    /// default.
+    pub fn current() -> SigstoreEnv {
+        if STAGING.load(Ordering::SeqCst) {
+            SigstoreEnv::Staging
+        } else {
+            SigstoreEnv::Production
+        }
+    }
+
+    pub fn fulcio_url(&self) -> &'static str {
+        match self {
+            SigstoreEnv::Production => "https://fulcio.sigstore.dev",
+            SigstoreEnv::Staging => "https://fulcio.sigstage.dev",
+        }
+    }
+
+    pub fn rekor_url(&self) -> &'static str {
+        match self {
+            SigstoreEnv::Production => "https://rekor.sigstore.dev",
+            SigstoreEnv::Staging => "https://rekor.sigstage.dev",
+        }
+    }
+
+    /// Name of the trust store directory in the state directory, so that
+    /// staging roots are never mixed with production ones.
+    pub fn trust_dir(&self) -> &'static str {
+        match self {
+            SigstoreEnv::Production => "trust",
+            SigstoreEnv::Staging => "trust-staging",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn staging_endpoints() {
+        // Selecting an environment is process-wide, so only the parsed values
+        // are checked here.
+        let staging: SigstoreEnv = "staging".parse().expect("Unknown environment");
+        assert_eq!(staging.fulcio_url(), "https://fulcio.sigstage.dev");
+        assert_eq!(staging.rekor_url(), "https://rekor.sigstage.dev");
+        assert_ne!(staging.trust_dir(), SigstoreEnv::Production.trust_dir());
+        assert_eq!(SigstoreEnv::current(), SigstoreEnv::Production);
+        assert!("qa".parse::<SigstoreEnv>().is_err());
+    }
+}
@@ -0,0 +1,561 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use ecdsa::signature::Verifier as _;
+use ring::signature::{
+    RsaPublicKeyComponents, RSA_PKCS1_2048_8192_SHA256, RSA_PKCS1_2048_8192_SHA512,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::algorithms::{self, AlgorithmPolicy, HashAlgorithm, SignatureAlgorithm};
+use crate::error::SgetError;
+use crate::intoto::glob_matches;
+use crate::verify_core::Signer;
+
+const MAGIC: &[u8] = b"SSHSIG";
+const ARMOR_BEGIN: &str = "-----BEGIN SSH SIGNATURE-----";
+const ARMOR_END: &str = "-----END SSH SIGNATURE-----";
+
+/// The namespace `ssh-keygen -Y sign -n file` signs files in.
+pub const FILE_NAMESPACE: &str = "file";
+
+/// The namespace policy signatures by `ssh` keys are made in.
+pub const POLICY_NAMESPACE: &str = "sget-policy";
+
+/// Where a manifest entry's allowed signers are, and who must have signed it.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SshRequirements {
+    /// Location of the `allowed_signers` file, as read by `ssh-keygen -Y verify`.
+    pub allowed_signers: String,
+    /// Principal the signature must be from. Any principal of the allowed
+    /// signers is accepted if unset.
+    pub principal: Option<String>,
+    /// Namespace the signature was made in. Defaults to `file`.
+    pub namespace: Option<String>,
+}
+
+/// Reads the fields of the SSH wire format.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.data.len() {
+            return Err(anyhow!("Truncated SSH data"));
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn uint32(&mut self) -> Result<usize> {
+        Ok(self
+            .take(4)?
+            .iter()
+            .fold(0, |acc, byte| (acc << 8) | usize::from(*byte)))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let length = self.uint32()?;
+        self.take(length)
+    }
+
+    fn text(&mut self) -> Result<&'a str> {
+        Ok(std::str::from_utf8(self.string()?)?)
+    }
+
+    /// A positive multiple precision integer, without its sign octet.
+    fn mpint(&mut self) -> Result<&'a [u8]> {
+        let value = self.string()?;
+        let start = value
+            .iter()
+            .position(|byte| *byte != 0)
+            .unwrap_or(value.len());
+        Ok(&value[start..])
+    }
+}
+
+fn string(value: &[u8]) -> Vec<u8> {
+    let mut encoded = (value.len() as u32).to_be_bytes().to_vec();
+    encoded.extend(value);
+    encoded
+}
+
+/// An SSH public key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SshPublicKey {
+    /// The key in the SSH wire format.
+    blob: Vec<u8>,
+}
+
+impl SshPublicKey {
+    /// Parse a key in the `authorized_keys` format: the key type, the base64
+    /// encoded key and an optional comment.
+    pub fn from_openssh(line: &str) -> Result<Self> {
+        let mut fields = line.split_whitespace();
+        let (keytype, encoded) = match (fields.next(), fields.next()) {
+            (Some(keytype), Some(encoded)) => (keytype, encoded),
+            _ => return Err(anyhow!("Invalid SSH public key")),
+        };
+        let key = SshPublicKey {
+            blob: base64::decode(encoded)?,
+        };
+        if key.keytype()? != keytype {
+            return Err(anyhow!("SSH public key is not of type {}", keytype));
+        }
+        Ok(key)
+    }
+
+    fn keytype(&self) -> Result<&str> {
+        Reader { data: &self.blob }.text()
+    }
+
+    /// The fingerprint as `ssh-keygen -l` prints it: `SHA256:` and the
+    /// unpadded base64 encoded SHA-256 digest of the key.
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "SHA256:{}",
+            base64::encode_config(Sha256::digest(&self.blob), base64::STANDARD_NO_PAD)
+        )
+    }
+
+    /// Verify a signature blob, as found in an SSH signature, over `message`.
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<()> {
+        let mut key = Reader { data: &self.blob };
+        let mut signature = Reader { data: signature };
+        let keytype = key.text()?;
+        let algorithm = signature.text()?;
+        let value = signature.string()?;
+        match (keytype, algorithm) {
+            ("ssh-ed25519", "ssh-ed25519") => {
+                let key = ed25519_dalek::PublicKey::from_bytes(key.string()?)?;
+                key.verify_strict(message, &ed25519_dalek::Signature::from_bytes(value)?)
+                    .map_err(|_| anyhow!("Invalid SSH Ed25519 signature"))
+            }
+            ("ecdsa-sha2-nistp256", "ecdsa-sha2-nistp256") => {
+                if key.text()? != "nistp256" {
+                    return Err(anyhow!("Invalid SSH ECDSA key"));
+                }
+                let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(key.string()?)
+                    .map_err(|_| anyhow!("Invalid SSH ECDSA key"))?;
+                let mut value = Reader { data: value };
+                let (r, s) = (scalar(value.mpint()?)?, scalar(value.mpint()?)?);
+                key.verify(message, &p256::ecdsa::Signature::from_scalars(r, s)?)
+                    .map_err(|_| anyhow!("Invalid SSH ECDSA signature"))
+            }
+            ("ssh-rsa", "rsa-sha2-256" | "rsa-sha2-512") => {
+                let e = key.mpint()?;
+                let n = key.mpint()?;
+                let params = match algorithm {
+                    "rsa-sha2-256" => &RSA_PKCS1_2048_8192_SHA256,
+                    _ => &RSA_PKCS1_2048_8192_SHA512,
+                };
+                RsaPublicKeyComponents { n, e }
+                    .verify(params, message, value)
+                    .map_err(|_| anyhow!("Invalid SSH RSA signature"))
+            }
+            ("ssh-rsa", "ssh-rsa") => {
+                Err(anyhow!("SSH RSA signatures with SHA-1 are not accepted"))
+            }
+            _ => Err(anyhow!(
+                "Unsupported SSH signature algorithm {} for {} keys",
+                algorithm,
+                keytype
+            )),
+        }
+    }
+}
+
+/// Left-pad a big-endian integer to 32 octets.
+fn scalar(value: &[u8]) -> Result<[u8; 32]> {
+    let mut padded = [0; 32];
+    let start = 32usize
+        .checked_sub(value.len())
+        .ok_or_else(|| anyhow!("SSH signature value is too long"))?;
+    padded[start..].copy_from_slice(value);
+    Ok(padded)
+}
+
+/// A signature made with `ssh-keygen -Y sign`, in the `SSHSIG` format.
+pub struct SshSignature {
+    pub public_key: SshPublicKey,
+    pub namespace: String,
+    hash_algorithm: String,
+    signature: Vec<u8>,
+}
+
+impl SshSignature {
+    /// Parse an armored or binary signature.
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let binary = match std::str::from_utf8(raw) {
+            Ok(text) if text.trim_start().starts_with(ARMOR_BEGIN) => {
+                let body: String = text
+                    .lines()
+                    .map(str::trim)
+                    .skip_while(|line| *line != ARMOR_BEGIN)
+                    .skip(1)
+                    .take_while(|line| *line != ARMOR_END)
+                    .collect();
+                base64::decode(body)?
+            }
+            _ => raw.to_vec(),
+        };
+        let mut reader = Reader { data: &binary };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(anyhow!("Not an SSH signature"));
+        }
+        if reader.uint32()? != 1 {
+            return Err(anyhow!("Unsupported SSH signature version"));
+        }
+        let public_key = SshPublicKey {
+            blob: reader.string()?.to_vec(),
+        };
+        let namespace = reader.text()?.to_string();
+        reader.string()?;
+        Ok(SshSignature {
+            public_key,
+            namespace,
+            hash_algorithm: reader.text()?.to_string(),
+            signature: reader.string()?.to_vec(),
+        })
+    }
+
+    /// Verify the signature over `data` in `namespace` by its own public key.
+    pub fn verify(&self, data: &[u8], namespace: &str) -> Result<()> {
+        if self.namespace != namespace {
+            return Err(anyhow!(
+                "SSH signature is for namespace {}, expected {}",
+                self.namespace,
+                namespace
+            ));
+        }
+        let digest = match self.hash_algorithm.as_str() {
+            "sha256" => Sha256::digest(data).to_vec(),
+            "sha512" => Sha512::digest(data).to_vec(),
+            other => return Err(anyhow!("Unsupported SSH signature hash {}", other)),
+        };
+        let message = [
+            MAGIC,
+            &string(namespace.as_bytes()),
+            &string(b""),
+            &string(self.hash_algorithm.as_bytes()),
+            &string(&digest),
+        ]
+        .concat();
+        self.public_key.verify(&message, &self.signature)
+    }
+
+    /// Check the key, the signature algorithm and the hash of the signed
+    /// data against `policy`.
+    pub(crate) fn check_algorithms(&self, policy: &AlgorithmPolicy) -> crate::error::Result<()> {
+        let unsupported =
+            |what: &str| SgetError::DisallowedAlgorithm(format!("Unsupported {}", what));
+        let mut key = Reader {
+            data: &self.public_key.blob,
+        };
+        let (algorithm, bits) = match key.text().map_err(|_| unsupported("SSH key"))? {
+            "ssh-ed25519" => (SignatureAlgorithm::Ed25519, 256),
+            "ecdsa-sha2-nistp256" => (SignatureAlgorithm::EcdsaP256, 256),
+            "ssh-rsa" => {
+                key.mpint().map_err(|_| unsupported("SSH key"))?;
+                let n = key.mpint().map_err(|_| unsupported("SSH key"))?;
+                (SignatureAlgorithm::Rsa, algorithms::bit_length(n))
+            }
+            other => return Err(unsupported(other)),
+        };
+        let mut signature = Reader {
+            data: &self.signature,
+        };
+        let signature_hash = match signature.text().map_err(|_| unsupported("SSH signature"))? {
+            "ssh-ed25519" | "rsa-sha2-512" => HashAlgorithm::Sha512,
+            "ecdsa-sha2-nistp256" | "rsa-sha2-256" => HashAlgorithm::Sha256,
+            other => return Err(unsupported(other)),
+        };
+        let data_hash = match self.hash_algorithm.as_str() {
+            "sha256" => HashAlgorithm::Sha256,
+            "sha512" => HashAlgorithm::Sha512,
+            other => return Err(unsupported(other)),
+        };
+        policy.check(algorithm, signature_hash, bits)?;
+        policy.check(algorithm, data_hash, bits)
+    }
+}
+
+/// One line of an `allowed_signers` file.
+struct AllowedSigner {
+    principals: Vec<String>,
+    namespaces: Option<Vec<String>>,
+    valid_after: Option<DateTime<Utc>>,
+    valid_before: Option<DateTime<Utc>>,
+    key: SshPublicKey,
+}
+
+impl AllowedSigner {
+    fn parse(line: &str) -> Result<Self> {
+        let (principals, rest) = split_field(line);
+        let (options, key) = match rest.split_whitespace().next() {
+            Some(keytype) if is_keytype(keytype) => ("", rest),
+            _ => split_field(rest),
+        };
+        let mut signer = AllowedSigner {
+            principals: principals.split(',').map(String::from).collect(),
+            namespaces: None,
+            valid_after: None,
+            valid_before: None,
+            key: SshPublicKey::from_openssh(key)?,
+        };
+        for option in split_options(options) {
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, value.trim_matches('"')),
+                None => (option, ""),
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "namespaces" => {
+                    signer.namespaces = Some(value.split(',').map(String::from).collect())
+                }
+                "valid-after" => signer.valid_after = Some(parse_time(value)?),
+                "valid-before" => signer.valid_before = Some(parse_time(value)?),
+                "cert-authority" => {
+                    return Err(anyhow!("Certificate authorities are not supported"))
+                }
+                other => return Err(anyhow!("Unknown allowed signers option {}", other)),
+            }
+        }
+        Ok(signer)
+    }
+
+    fn allows(&self, principal: Option<&str>, namespace: &str, now: DateTime<Utc>) -> bool {
+        let principal_matches = principal.is_none_or(|principal| {
+            self.principals
+                .iter()
+                .any(|pattern| glob_matches(pattern.as_bytes(), principal.as_bytes()))
+        });
+        let namespace_matches = self.namespaces.as_ref().is_none_or(|namespaces| {
+            namespaces
+                .iter()
+                .any(|pattern| glob_matches(pattern.as_bytes(), namespace.as_bytes()))
+        });
+        principal_matches
+            && namespace_matches
+            && self.valid_after.is_none_or(|after| now >= after)
+            && self.valid_before.is_none_or(|before| now <= before)
+    }
+}
+
+fn is_keytype(field: &str) -> bool {
+    field.starts_with("ssh-") || field.starts_with("ecdsa-sha2-") || field.starts_with("sk-")
+}
+
+/// Split off the first whitespace separated field, which may contain quoted
+/// whitespace.
+fn split_field(line: &str) -> (&str, &str) {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => return (&line[..i], line[i..].trim_start()),
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+/// Split comma separated options, keeping quoted commas.
+fn split_options(options: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in options.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                fields.push(&options[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(&options[start..]);
+    fields.retain(|field| !field.is_empty());
+    fields
+}
+
+/// Parse a `YYYYMMDD[HHMM[SS]][Z]` time. Times are taken to be UTC.
+fn parse_time(value: &str) -> Result<DateTime<Utc>> {
+    let digits = value.trim_end_matches(['Z', 'z']);
+    let padded = match digits.len() {
+        8 => format!("{}000000", digits),
+        12 => format!("{}00", digits),
+        _ => digits.to_string(),
+    };
+    let time = NaiveDateTime::parse_from_str(&padded, "%Y%m%d%H%M%S")
+        .map_err(|e| anyhow!("Invalid time {}: {}", value, e))?;
+    Ok(DateTime::from_utc(time, Utc))
+}
+
+/// The keys of an `allowed_signers` file. Lines with certificate authorities
+/// are not supported.
+pub struct AllowedSigners {
+    signers: Vec<AllowedSigner>,
+}
+
+impl AllowedSigners {
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let signers = std::str::from_utf8(raw)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| AllowedSigner::parse(line).map_err(|e| anyhow!("{}: {}", e, line)))
+            .collect::<Result<_>>()?;
+        Ok(AllowedSigners { signers })
+    }
+}
+
+/// Verify an SSH signature over `data` in `namespace`, like `ssh-keygen -Y
+/// verify`: the signing key must be allowed for `principal` (or any principal
+/// if `None`) and the namespace as of `now`, and its algorithms allowed by
+/// `algorithms`. The signer's key ID is the key's SHA-256 fingerprint.
+pub fn verify_ssh(
+    data: &[u8],
+    signature: &[u8],
+    allowed_signers: &AllowedSigners,
+    principal: Option<&str>,
+    namespace: &str,
+    algorithms: &AlgorithmPolicy,
+    now: DateTime<Utc>,
+) -> crate::error::Result<Signer> {
+    let signature = SshSignature::parse(signature).map_err(SgetError::InvalidMaterial)?;
+    signature
+        .verify(data, namespace)
+        .map_err(SgetError::InvalidSignature)?;
+    signature.check_algorithms(algorithms)?;
+    let fingerprint = signature.public_key.fingerprint();
+    let allowed = allowed_signers.signers.iter().any(|signer| {
+        signer.key == signature.public_key && signer.allows(principal, namespace, now)
+    });
+    if !allowed {
+        return Err(SgetError::UntrustedSigner(format!(
+            "{} is not an allowed signer{}",
+            fingerprint,
+            principal
+                .map(|principal| format!(" for {}", principal))
+                .unwrap_or_default()
+        )));
+    }
+    Ok(Signer {
+        key_id: fingerprint,
+        identity: None,
+        issuer: None,
+        integrated_time: None,
+    })
+}
+
+// The test keys are Ed25519, which FIPS builds refuse.
+#[cfg(all(test, not(feature = "fips")))]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Signer as _;
+    use rand_core::{OsRng, RngCore};
+
+    fn keypair() -> ed25519_dalek::Keypair {
+        let mut seed = [0; ed25519_dalek::SECRET_KEY_LENGTH];
+        OsRng.fill_bytes(&mut seed);
+        let secret = ed25519_dalek::SecretKey::from_bytes(&seed).expect("Invalid seed");
+        let public = (&secret).into();
+        ed25519_dalek::Keypair { secret, public }
+    }
+
+    fn sign(keypair: &ed25519_dalek::Keypair, data: &[u8], namespace: &str) -> (String, Vec<u8>) {
+        let key = [string(b"ssh-ed25519"), string(keypair.public.as_bytes())].concat();
+        let signed = [
+            MAGIC,
+            &string(namespace.as_bytes()),
+            &string(b""),
+            &string(b"sha512"),
+            &string(&Sha512::digest(data)),
+        ]
+        .concat();
+        let signature = [
+            string(b"ssh-ed25519"),
+            string(&keypair.sign(&signed).to_bytes()),
+        ]
+        .concat();
+        let blob = [
+            MAGIC,
+            &1u32.to_be_bytes(),
+            &string(&key),
+            &string(namespace.as_bytes()),
+            &string(b""),
+            &string(b"sha512"),
+            &string(&signature),
+        ]
+        .concat();
+        let armored = format!("{}\n{}\n{}\n", ARMOR_BEGIN, base64::encode(blob), ARMOR_END);
+        (
+            format!("ssh-ed25519 {} release", base64::encode(key)),
+            armored.into_bytes(),
+        )
+    }
+
+    #[test]
+    fn verify_allowed_signer() {
+        let data = b"#!/bin/sh\necho hi\n";
+        let (key, signature) = sign(&keypair(), data, FILE_NAMESPACE);
+        let allowed = AllowedSigners::parse(
+            format!(
+                "# release keys\n*@example.com namespaces=\"file,git\",valid-after=\"20200101\" {}\n",
+                key
+            )
+            .as_bytes(),
+        )
+        .expect("Cannot parse allowed signers");
+        let now = Utc::now();
+        let verify = |data: &[u8], principal, namespace| {
+            verify_ssh(
+                data,
+                &signature,
+                &allowed,
+                principal,
+                namespace,
+                &AlgorithmPolicy::default(),
+                now,
+            )
+        };
+
+        let signer = verify(data, Some("release@example.com"), FILE_NAMESPACE)
+            .expect("Cannot verify signature");
+        assert_eq!(
+            signer.key_id,
+            SshPublicKey::from_openssh(&key)
+                .expect("Invalid key")
+                .fingerprint()
+        );
+        assert!(verify(data, None, FILE_NAMESPACE).is_ok());
+        assert!(matches!(
+            verify(b"echo bye", None, FILE_NAMESPACE),
+            Err(SgetError::InvalidSignature(_))
+        ));
+        assert!(matches!(
+            verify(data, None, "git"),
+            Err(SgetError::InvalidSignature(_))
+        ));
+        assert!(matches!(
+            verify(data, Some("someone@example.org"), FILE_NAMESPACE),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+        let (_, other) = sign(&keypair(), data, FILE_NAMESPACE);
+        assert!(matches!(
+            verify_ssh(
+                data,
+                &other,
+                &allowed,
+                None,
+                FILE_NAMESPACE,
+                &AlgorithmPolicy::default(),
+                now
+            ),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+    }
+}
@@ -0,0 +1,22 @@
+use anyhow::{anyhow, Result};
+use std::env;
+use std::path::PathBuf;
+
+use crate::profile;
+
+/// The directory holding sget's persistent state (trust store, caches, logs).
+///
+/// Defaults to the platform data directory and can be overridden with
+/// `SGET_STATE_DIR`. A selected profile has its own directory below it.
+pub fn state_dir() -> Result<PathBuf> {
+    let dir = match env::var_os("SGET_STATE_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::data_dir()
+            .map(|dir| dir.join("sget"))
+            .ok_or_else(|| anyhow!("Cannot determine state directory; set SGET_STATE_DIR"))?,
+    };
+    Ok(match profile::cache_namespace() {
+        Some(namespace) => dir.join("profiles").join(namespace),
+        None => dir,
+    })
+}
@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Where sget persists its state, such as the trust store and watch snapshots.
+///
+/// Keys are relative paths with `/` separated segments, e.g. `policy/prod.json`.
+/// [`FileStorage`] maps them onto a directory; embedders can implement the
+/// trait to keep state in a database or object store, and [`MemoryStorage`]
+/// keeps it in memory for tests.
+pub trait Storage: Send + Sync {
+    /// The value stored under `key`, if any.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// Store `value` under `key`, replacing any previous value.
+    fn put(&self, key: &str, value: &[u8]) -> Result<()>;
+    /// Append `value` to whatever is stored under `key`.
+    fn append(&self, key: &str, value: &[u8]) -> Result<()>;
+    /// Remove `key`. Removing a missing key is not an error.
+    fn delete(&self, key: &str) -> Result<()>;
+    /// All keys starting with `prefix`, sorted.
+    fn list(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+/// Reject keys that are empty or could escape the storage root.
+fn check_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty()
+        && !key.contains('\\')
+        && key
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+    if !valid {
+        return Err(anyhow!("Invalid storage key: {:?}", key));
+    }
+    Ok(())
+}
+
+/// Storage in a directory, one file per key.
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileStorage { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        check_key(key)?;
+        Ok(self.root.join(key))
+    }
+
+    fn create_parent(path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+        }
+        Ok(())
+    }
+
+    fn walk(&self, dir: &Path, keys: &mut Vec<String>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.walk(&path, keys)?;
+            } else if let Ok(relative) = path.strip_prefix(&self.root) {
+                let segments: Option<Vec<&str>> =
+                    relative.iter().map(|segment| segment.to_str()).collect();
+                if let Some(segments) = segments {
+                    keys.push(segments.join("/"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path(key)?;
+        match fs::read(&path) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Cannot read {}", path.display())),
+        }
+    }
+
+    /// Values are written to a temporary file and renamed into place, so readers
+    /// never see a partial value.
+    fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+        let path = self.path(key)?;
+        Self::create_parent(&path)?;
+        let mut file = tempfile::NamedTempFile::new_in(path.parent().unwrap_or(&self.root))?;
+        file.write_all(value)?;
+        file.persist(&path)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+        Ok(())
+    }
+
+    fn append(&self, key: &str, value: &[u8]) -> Result<()> {
+        let path = self.path(key)?;
+        Self::create_parent(&path)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(value))
+            .with_context(|| format!("Cannot write {}", path.display()))
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let path = self.path(key)?;
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Cannot remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        if self.root.is_dir() {
+            self.walk(&self.root, &mut keys)?;
+        }
+        keys.retain(|key| key.starts_with(prefix));
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// Storage that lives only as long as the value.
+#[derive(Default)]
+pub struct MemoryStorage {
+    values: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    fn values(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Vec<u8>>> {
+        match self.values.lock() {
+            Ok(values) => values,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        check_key(key)?;
+        Ok(self.values().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+        check_key(key)?;
+        self.values().insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn append(&self, key: &str, value: &[u8]) -> Result<()> {
+        check_key(key)?;
+        self.values()
+            .entry(key.to_string())
+            .or_default()
+            .extend_from_slice(value);
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        check_key(key)?;
+        self.values().remove(key);
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        Ok(self
+            .values()
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exercise(storage: &dyn Storage) {
+        assert_eq!(storage.get("a/b.json").expect("Cannot get"), None);
+        storage.put("a/b.json", b"one").expect("Cannot put");
+        storage.put("a/b.json", b"two").expect("Cannot put");
+        storage.append("log", b"x\n").expect("Cannot append");
+        storage.append("log", b"y\n").expect("Cannot append");
+        storage.put("c.json", b"three").expect("Cannot put");
+
+        assert_eq!(
+            storage.get("a/b.json").expect("Cannot get"),
+            Some(b"two".to_vec())
+        );
+        assert_eq!(
+            storage.get("log").expect("Cannot get"),
+            Some(b"x\ny\n".to_vec())
+        );
+        assert_eq!(
+            storage.list("").expect("Cannot list"),
+            vec!["a/b.json", "c.json", "log"]
+        );
+        assert_eq!(storage.list("a/").expect("Cannot list"), vec!["a/b.json"]);
+
+        storage.delete("a/b.json").expect("Cannot delete");
+        storage.delete("a/b.json").expect("Cannot delete twice");
+        assert!(storage.list("a/").expect("Cannot list").is_empty());
+        for key in &["", "../escape", "a//b", "/abs", "a/./b"] {
+            assert!(storage.put(key, b"").is_err(), "{:?} accepted", key);
+        }
+    }
+
+    #[test]
+    fn file_and_memory_storage() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        exercise(&FileStorage::new(dir.path().join("state")));
+        exercise(&MemoryStorage::default());
+    }
+}
@@ -0,0 +1,175 @@
+use serde::Deserialize;
+
+use crate::approval::ApprovalRule;
+use crate::error::{Result, SgetError};
+use crate::verify_core::Signer;
+
+/// A verification floor set by the machine's administrator, read from
+/// `/etc/sget/policy.d/*.yaml` (`%ProgramData%\sget\policy.d` on Windows).
+///
+/// Every signature verified with roots from the trust store must meet it, and
+/// no flag, environment variable or user configuration relaxes it. When
+/// several files are present, the floor is the strictest combination of them.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SystemPolicy {
+    /// Require a Rekor inclusion proof for every signature.
+    #[serde(default)]
+    pub require_rekor: bool,
+    /// Only accept keyless signatures whose identity was issued by one of these
+    /// OIDC issuers. When set, signatures made with a key, which have no
+    /// issuer, are refused too.
+    #[serde(default)]
+    pub allowed_issuers: Vec<String>,
+    /// Only accept root policy signatures that carry a signed timestamp, so
+    /// that the key or certificate that made each is checked at the time it
+    /// was made.
+    #[serde(default)]
+    pub require_signing_time: bool,
+    /// Artifacts that only run with the approval of a second operator.
+    #[serde(default)]
+    pub approvals: Vec<ApprovalRule>,
+}
+
+impl SystemPolicy {
+    /// Combine with `other` so that both floors hold.
+    pub fn merge(&mut self, other: SystemPolicy) {
+        self.require_rekor |= other.require_rekor;
+        self.require_signing_time |= other.require_signing_time;
+        if self.allowed_issuers.is_empty() {
+            self.allowed_issuers = other.allowed_issuers;
+        } else if !other.allowed_issuers.is_empty() {
+            self.allowed_issuers
+                .retain(|issuer| other.allowed_issuers.contains(issuer));
+            if self.allowed_issuers.is_empty() {
+                // Disjoint issuer lists leave no signer acceptable.
+                self.allowed_issuers.push(String::new());
+            }
+        }
+        self.approvals.extend(other.approvals);
+    }
+
+    /// Check that `signer` meets the floor.
+    pub fn check(&self, signer: &Signer) -> Result<()> {
+        if self.require_rekor && signer.integrated_time.is_none() {
+            return Err(SgetError::TransparencyLogError(
+                "The system policy requires a Rekor inclusion proof".to_string(),
+            ));
+        }
+        if !self.allowed_issuers.is_empty()
+            && !signer
+                .issuer
+                .as_ref()
+                .is_some_and(|issuer| !issuer.is_empty() && self.allowed_issuers.contains(issuer))
+        {
+            return Err(SgetError::UntrustedSigner(format!(
+                "{} was not issued by an issuer the system policy allows",
+                signer.subject()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "native")]
+impl SystemPolicy {
+    /// The directory system policies are read from.
+    pub fn dir() -> std::path::PathBuf {
+        #[cfg(windows)]
+        {
+            let data = std::env::var_os("ProgramData").unwrap_or_else(|| "C:\\ProgramData".into());
+            std::path::Path::new(&data).join("sget").join("policy.d")
+        }
+        #[cfg(not(windows))]
+        {
+            std::path::PathBuf::from("/etc/sget/policy.d")
+        }
+    }
+
+    /// Load and merge the policies in [`SystemPolicy::dir`]; no directory is
+    /// an empty floor.
+    pub fn load() -> anyhow::Result<Self> {
+        Self::load_from(&Self::dir())
+    }
+
+    /// Load and merge every `.yaml` file in `dir`, in name order.
+    pub fn load_from(dir: &std::path::Path) -> anyhow::Result<Self> {
+        use anyhow::Context;
+
+        let mut files: Vec<_> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "yaml"))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).with_context(|| format!("Cannot read {}", dir.display())),
+        };
+        files.sort();
+        let mut policy = SystemPolicy::default();
+        for path in files {
+            let raw =
+                std::fs::read(&path).with_context(|| format!("Cannot read {}", path.display()))?;
+            policy.merge(
+                serde_yaml::from_slice(&raw)
+                    .with_context(|| format!("Invalid system policy {}", path.display()))?,
+            );
+        }
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(issuer: Option<&str>, integrated_time: Option<i64>) -> Signer {
+        Signer {
+            key_id: "abcd".to_string(),
+            identity: issuer.map(|_| "dev@acme.dev".to_string()),
+            issuer: issuer.map(String::from),
+            integrated_time,
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn enforce_floor() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        std::fs::write(dir.path().join("10-rekor.yaml"), "require_rekor: true\n")
+            .expect("Cannot write policy");
+        std::fs::write(
+            dir.path().join("20-issuers.yaml"),
+            "allowed_issuers: [https://accounts.google.com, https://token.actions.githubusercontent.com]\n",
+        )
+        .expect("Cannot write policy");
+        std::fs::write(
+            dir.path().join("30-issuers.yaml"),
+            "allowed_issuers: [https://token.actions.githubusercontent.com]\n",
+        )
+        .expect("Cannot write policy");
+        std::fs::write(dir.path().join("README"), "not a policy").expect("Cannot write");
+        let policy = SystemPolicy::load_from(dir.path()).expect("Cannot load policy");
+        assert!(policy.require_rekor);
+        assert_eq!(
+            policy.allowed_issuers,
+            vec!["https://token.actions.githubusercontent.com"]
+        );
+
+        let github = Some("https://token.actions.githubusercontent.com");
+        assert!(policy.check(&signer(github, Some(1))).is_ok());
+        assert!(matches!(
+            policy.check(&signer(github, None)),
+            Err(SgetError::TransparencyLogError(_))
+        ));
+        assert!(policy
+            .check(&signer(Some("https://accounts.google.com"), Some(1)))
+            .is_err());
+        assert!(policy.check(&signer(None, Some(1))).is_err());
+
+        assert_eq!(
+            SystemPolicy::load_from(&dir.path().join("missing")).expect("Cannot load"),
+            SystemPolicy::default()
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+
+use crate::policy::Namespace;
+
+/// Signer constraints for the artifacts whose path matches a glob, listed in
+/// the `targets` of a root policy.
+///
+/// Paths are relative to the policy's namespace: the artifact
+/// `https://example.com/scripts/ci/build.sh` under the namespace
+/// `example.com/scripts` has the path `ci/build.sh`. In patterns, `*` matches
+/// any characters but `/`, `?` matches one character but `/`, and `**`
+/// matches any characters, `/` included; `**/` also matches no directory at
+/// all, so `ci/**/*.sh` matches `ci/build.sh`.
+///
+/// When several rules match a path, the most specific one applies:
+///
+/// 1. a pattern without wildcards, i.e. the exact path, over any glob;
+/// 2. then the pattern with the most literal (non-wildcard) characters;
+/// 3. then the rule listed first.
+///
+/// Artifacts no rule matches may be signed by any root key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TargetRule {
+    pub path: String,
+    /// IDs of the policy keys that may sign matching artifacts.
+    pub keyids: Vec<String>,
+}
+
+impl TargetRule {
+    fn specificity(&self) -> (bool, usize) {
+        specificity(&self.path)
+    }
+}
+
+/// How specific the glob `pattern` is, ordered as described on [`TargetRule`]:
+/// whether it is an exact path, then its number of literal characters.
+pub(crate) fn specificity(pattern: &str) -> (bool, usize) {
+    let literals = pattern.chars().filter(|c| *c != '*' && *c != '?').count();
+    (literals == pattern.chars().count(), literals)
+}
+
+/// The most specific rule of `rules` matching `path`, if any.
+pub fn rule_for<'a>(rules: &'a [TargetRule], path: &str) -> Option<&'a TargetRule> {
+    // `max_by_key` returns the last maximum, so search in reverse to prefer
+    // the rule listed first.
+    rules
+        .iter()
+        .rev()
+        .filter(|rule| glob_matches(&rule.path, path))
+        .max_by_key(|rule| rule.specificity())
+}
+
+/// Prefix of artifacts in an OCI registry, `oci://<reference>`.
+const OCI_PREFIX: &str = "oci://";
+/// Prefix of artifacts in podman's store, `containers-storage:[<store>]<reference>`.
+const CONTAINERS_STORAGE_PREFIX: &str = "containers-storage:";
+
+/// The name of the artifact at `location` that namespaces are matched
+/// against: the host and path of an `https://` or `http://` URL, or
+/// `<registry>/<repository>` of an OCI reference given as `oci://<reference>`
+/// or `containers-storage:<reference>`. Local paths, standard input and OCI
+/// image layouts have none.
+pub fn namespace_name(location: &str) -> Option<String> {
+    if let Some(rest) = location
+        .strip_prefix("https://")
+        .or_else(|| location.strip_prefix("http://"))
+    {
+        return Some(rest.to_string());
+    }
+    let reference = match location.strip_prefix(OCI_PREFIX) {
+        Some(reference) => reference,
+        None => {
+            let spec = location.strip_prefix(CONTAINERS_STORAGE_PREFIX)?;
+            match spec.strip_prefix('[') {
+                Some(rest) => rest.split_once(']')?.1,
+                None => spec,
+            }
+        }
+    };
+    oci_repository(reference)
+}
+
+/// `<registry>/<repository>` of the OCI reference `reference`, with Docker
+/// Hub's defaults filled in as `docker pull` does.
+fn oci_repository(reference: &str) -> Option<String> {
+    let name = reference
+        .split_once('@')
+        .map_or(reference, |(name, _)| name);
+    // A tag follows the last colon if no slash does; other colons precede a
+    // registry's port.
+    let name = match name.rfind(':') {
+        Some(i) if !name[i..].contains('/') => &name[..i],
+        _ => name,
+    };
+    if name.is_empty() || name.starts_with('/') || name.ends_with('/') {
+        return None;
+    }
+    match name.split_once('/') {
+        Some(("index.docker.io", repository)) => Some(format!("docker.io/{}", repository)),
+        Some((registry, _)) if registry.contains(['.', ':']) || registry == "localhost" => {
+            Some(name.to_string())
+        }
+        Some(_) => Some(format!("docker.io/{}", name)),
+        None => Some(format!("docker.io/library/{}", name)),
+    }
+}
+
+/// The path of the artifact at `location` within `namespace`: its
+/// [`namespace_name`], or the location itself if it has none, without the
+/// shallowest namespace matching it, if it is under one of them.
+pub fn target_path(namespace: &Namespace, location: &str) -> String {
+    let rest = namespace_name(location).unwrap_or_else(|| location.to_string());
+    namespace_parents(&rest)
+        .skip(1)
+        .filter(|parent| {
+            namespace
+                .patterns()
+                .iter()
+                .any(|pattern| glob_matches(pattern.trim_end_matches('/'), parent))
+        })
+        .last()
+        .map(|parent| rest[parent.len() + 1..].to_string())
+        .unwrap_or_else(|| rest.clone())
+}
+
+/// `namespace` followed by its parents: `a/b/c`, `a/b`, then `a`.
+pub(crate) fn namespace_parents(namespace: &str) -> impl Iterator<Item = &str> {
+    std::iter::once(namespace).chain(
+        namespace
+            .rmatch_indices('/')
+            .map(move |(i, _)| &namespace[..i]),
+    )
+}
+
+/// Whether `path` matches the glob `pattern`.
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    matches(&pattern, &path)
+}
+
+fn matches(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` may match no directory.
+            if let ['/', after @ ..] = rest {
+                if matches(after, path) {
+                    return true;
+                }
+            }
+            (0..=path.len()).any(|skip| matches(rest, &path[skip..]))
+        }
+        ['*', rest @ ..] => {
+            let segment = path.iter().position(|c| *c == '/').unwrap_or(path.len());
+            (0..=segment).any(|skip| matches(rest, &path[skip..]))
+        }
+        ['?', rest @ ..] => match path {
+            [c, tail @ ..] if *c != '/' => matches(rest, tail),
+            _ => false,
+        },
+        [p, rest @ ..] => match path {
+            [c, tail @ ..] if c == p => matches(rest, tail),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(path: &str, keyid: &str) -> TargetRule {
+        TargetRule {
+            path: path.to_string(),
+            keyids: vec![keyid.to_string()],
+        }
+    }
+
+    #[test]
+    fn match_most_specific_rule() {
+        assert!(glob_matches("scripts/*.sh", "scripts/install.sh"));
+        assert!(!glob_matches("scripts/*.sh", "scripts/ci/install.sh"));
+        assert!(glob_matches("scripts/**/*.sh", "scripts/install.sh"));
+        assert!(glob_matches("scripts/**/*.sh", "scripts/ci/linux/build.sh"));
+        assert!(glob_matches("**", "anything/at/all"));
+        assert!(glob_matches("v?/run.sh", "v2/run.sh"));
+        assert!(!glob_matches("v?/run.sh", "v10/run.sh"));
+
+        let rules = [
+            rule("**", "everyone"),
+            rule("scripts/**", "scripts"),
+            rule("scripts/*.sh", "shell"),
+            rule("scripts/*.py", "python"),
+            rule("scripts/install.sh", "release"),
+            rule("scripts/**", "second"),
+        ];
+        let keyid = |path| rule_for(&rules, path).map(|rule| rule.keyids[0].as_str());
+        assert_eq!(keyid("scripts/install.sh"), Some("release"));
+        assert_eq!(keyid("scripts/build.sh"), Some("shell"));
+        assert_eq!(keyid("scripts/ci/build.sh"), Some("scripts"));
+        assert_eq!(keyid("README.md"), Some("everyone"));
+        assert_eq!(rule_for(&rules[2..4], "README.md"), None);
+
+        let scripts = Namespace::from("example.com/scripts");
+        assert_eq!(
+            target_path(&scripts, "https://example.com/scripts/ci/a.sh"),
+            "ci/a.sh"
+        );
+        assert_eq!(
+            target_path(&scripts, "https://example.com/scriptsevil/a.sh"),
+            "example.com/scriptsevil/a.sh"
+        );
+        assert_eq!(target_path(&scripts, "ci/a.sh"), "ci/a.sh");
+        let teams = Namespace::Many(vec![
+            "example.com/scripts".to_string(),
+            "ghcr.io/*/tools".to_string(),
+        ]);
+        assert_eq!(
+            target_path(&teams, "https://ghcr.io/acme/tools/ci/a.sh"),
+            "ci/a.sh"
+        );
+        assert_eq!(
+            target_path(&teams, "oci://ghcr.io/acme/tools/lint:v1"),
+            "lint"
+        );
+    }
+
+    #[test]
+    fn name_locations() {
+        let name = |location| namespace_name(location);
+        assert_eq!(
+            name("https://example.com/scripts/a.sh").as_deref(),
+            Some("example.com/scripts/a.sh")
+        );
+        assert_eq!(
+            name("oci://ghcr.io/acme/tools:v1").as_deref(),
+            Some("ghcr.io/acme/tools")
+        );
+        assert_eq!(
+            name("oci://localhost:5000/tools@sha256:af13").as_deref(),
+            Some("localhost:5000/tools")
+        );
+        assert_eq!(
+            name("oci://alpine").as_deref(),
+            Some("docker.io/library/alpine")
+        );
+        assert_eq!(
+            name("oci://acme/tools:v1").as_deref(),
+            Some("docker.io/acme/tools")
+        );
+        assert_eq!(
+            name("containers-storage:[overlay@/var/lib/containers]ghcr.io/acme/tools:v1")
+                .as_deref(),
+            Some("ghcr.io/acme/tools")
+        );
+        assert_eq!(name("oci-layout:/tmp/layout#v1"), None);
+        assert_eq!(name("scripts/a.sh"), None);
+        assert_eq!(name("-"), None);
+    }
+}
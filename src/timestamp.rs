@@ -0,0 +1,277 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use ring::digest;
+use ring::signature::{self as ring_signature, UnparsedPublicKey, VerificationAlgorithm};
+use serde::{Deserialize, Serialize};
+
+use crate::der::{
+    algorithm_oid, children, element, encode, expect, generalized_time, signature_algorithm,
+    Element, CONTEXT_0, INTEGER, OCTET_STRING, OID, OID_SHA256, OID_SHA384, OID_SHA512, SEQUENCE,
+    SET,
+};
+use crate::rekor::Bundle;
+use crate::verify_core::{parse_certificate, verify_bundle, verify_chain, TrustRoots};
+
+// DER encoded object identifiers.
+const OID_SIGNED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+const OID_TST_INFO: &[u8] = &[
+    0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x10, 0x01, 0x04,
+];
+const OID_MESSAGE_DIGEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x04];
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+/// Proof from a third party that a signature existed at some time, so that
+/// the key or certificate that made it can be judged at that time rather than
+/// when the signature is verified.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum SignedTimestamp {
+    /// The Rekor bundle of the signature's log entry, whose integrated time
+    /// the log signed.
+    Rekor { bundle: Bundle },
+    /// A base64 encoded RFC 3161 time-stamp token over the signature, or the
+    /// time-stamp response carrying it.
+    Rfc3161 { token: String },
+}
+
+impl SignedTimestamp {
+    /// Read a timestamp from `raw`: a Rekor bundle in JSON, or a DER encoded
+    /// RFC 3161 time-stamp token or response.
+    pub fn from_bytes(raw: &[u8]) -> Result<Self> {
+        match serde_json::from_slice(raw) {
+            Ok(bundle) => Ok(SignedTimestamp::Rekor { bundle }),
+            Err(_) => {
+                let (token, _) = element(raw)?;
+                if token.tag != SEQUENCE {
+                    return Err(anyhow!("Not a Rekor bundle or RFC 3161 time-stamp token"));
+                }
+                Ok(SignedTimestamp::Rfc3161 {
+                    token: base64::encode(token.raw),
+                })
+            }
+        }
+    }
+
+    /// Verify that the timestamp is for `signature` over `signed` and return
+    /// the time it attests to. Rekor bundles must be signed by one of the
+    /// roots' Rekor keys, time-stamp tokens by a timestamp authority that is
+    /// or chains to one of theirs.
+    pub fn verify(
+        &self,
+        signed: &[u8],
+        signature: &[u8],
+        roots: &TrustRoots,
+    ) -> Result<DateTime<Utc>> {
+        match self {
+            SignedTimestamp::Rekor { bundle } => {
+                verify_bundle(bundle, signed, signature, &roots.rekor_keys)?;
+                Utc.timestamp_opt(bundle.payload.integrated_time, 0)
+                    .single()
+                    .ok_or_else(|| anyhow!("Invalid integrated time"))
+            }
+            SignedTimestamp::Rfc3161 { token } => {
+                verify_token(&base64::decode(token)?, signature, roots)
+            }
+        }
+    }
+}
+
+fn digest_algorithm(oid: &[u8]) -> Result<&'static digest::Algorithm> {
+    match oid {
+        OID_SHA256 => Ok(&digest::SHA256),
+        OID_SHA384 => Ok(&digest::SHA384),
+        OID_SHA512 => Ok(&digest::SHA512),
+        _ => Err(anyhow!("Unsupported digest algorithm")),
+    }
+}
+
+/// The single child of the explicitly tagged `[0]` element `tagged`.
+fn explicit<'a>(tagged: &Element<'a>) -> Result<Element<'a>> {
+    expect(tagged, CONTEXT_0)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Empty explicit element"))
+}
+
+/// Verify the DER encoded RFC 3161 time-stamp token `token`, or a response
+/// carrying one, over `signature` and return its time.
+fn verify_token(token: &[u8], signature: &[u8], roots: &TrustRoots) -> Result<DateTime<Utc>> {
+    let invalid = || anyhow!("Invalid time-stamp token");
+    let (outer, _) = element(token)?;
+    let fields = expect(&outer, SEQUENCE)?;
+    // A TimeStampResp starts with its status, a ContentInfo with an OID.
+    let content_info = match fields.as_slice() {
+        [status, token, ..] if status.tag == SEQUENCE => {
+            match expect(status, SEQUENCE)?.first() {
+                Some(code) if code.tag == INTEGER && matches!(code.contents, [0] | [1]) => {}
+                _ => return Err(anyhow!("Time-stamp request was not granted")),
+            }
+            expect(token, SEQUENCE)?
+        }
+        _ => fields,
+    };
+    let signed_data = match content_info.as_slice() {
+        [kind, content] if kind.tag == OID && kind.contents == OID_SIGNED_DATA => {
+            explicit(content)?
+        }
+        _ => return Err(invalid()),
+    };
+    let signed_data = expect(&signed_data, SEQUENCE)?;
+    let (encapsulated, rest) = match signed_data.as_slice() {
+        [_version, _digests, encapsulated, rest @ ..] => (encapsulated, rest),
+        _ => return Err(invalid()),
+    };
+    let tst_info = match expect(encapsulated, SEQUENCE)?.as_slice() {
+        [kind, content] if kind.tag == OID && kind.contents == OID_TST_INFO => {
+            let content = explicit(content)?;
+            if content.tag != OCTET_STRING {
+                return Err(invalid());
+            }
+            content.contents
+        }
+        _ => return Err(anyhow!("Not a time-stamp token")),
+    };
+    let certificates = match rest.first() {
+        Some(certificates) if certificates.tag == CONTEXT_0 => children(certificates.contents)?,
+        _ => Vec::new(),
+    };
+    let signer_info = rest
+        .last()
+        .filter(|infos| infos.tag == SET)
+        .map(|infos| children(infos.contents))
+        .transpose()?
+        .and_then(|infos| infos.into_iter().next())
+        .ok_or_else(invalid)?;
+
+    // The token must be for this signature.
+    let (tst, _) = element(tst_info)?;
+    let tst = expect(&tst, SEQUENCE)?;
+    let (imprint, time) = match tst.as_slice() {
+        [_version, _policy, imprint, _serial, time, ..] => (imprint, generalized_time(time)?),
+        _ => return Err(invalid()),
+    };
+    match expect(imprint, SEQUENCE)?.as_slice() {
+        [algorithm, hashed] => {
+            let algorithm = digest_algorithm(algorithm_oid(algorithm)?)?;
+            if hashed.contents != digest::digest(algorithm, signature).as_ref() {
+                return Err(anyhow!("Time-stamp token is for a different signature"));
+            }
+        }
+        _ => return Err(invalid()),
+    }
+
+    // The authority signs attributes that include the digest of the TSTInfo.
+    let signer_info = expect(&signer_info, SEQUENCE)?;
+    let (algorithm, attributes, signature_algorithm_id, token_signature) =
+        match signer_info.as_slice() {
+            [_version, _sid, algorithm, attributes, signature_algorithm_id, token_signature, ..]
+                if attributes.tag == CONTEXT_0 && token_signature.tag == OCTET_STRING =>
+            {
+                (
+                    algorithm,
+                    attributes,
+                    signature_algorithm_id,
+                    token_signature,
+                )
+            }
+            _ => return Err(anyhow!("Time-stamp token has no signed attributes")),
+        };
+    let algorithm = digest_algorithm(algorithm_oid(algorithm)?)?;
+    let message_digest = children(attributes.contents)?
+        .iter()
+        .find_map(
+            |attribute| match expect(attribute, SEQUENCE).ok()?.as_slice() {
+                [kind, values] if kind.tag == OID && kind.contents == OID_MESSAGE_DIGEST => {
+                    expect(values, SET)
+                        .ok()?
+                        .first()
+                        .map(|value| value.contents)
+                }
+                _ => None,
+            },
+        )
+        .ok_or_else(invalid)?;
+    if message_digest != digest::digest(algorithm, tst_info).as_ref() {
+        return Err(anyhow!("Time-stamp token was altered"));
+    }
+    let verification: &dyn VerificationAlgorithm = match algorithm_oid(signature_algorithm_id)? {
+        OID_RSA_ENCRYPTION if algorithm == &digest::SHA256 => {
+            &ring_signature::RSA_PKCS1_2048_8192_SHA256
+        }
+        OID_RSA_ENCRYPTION if algorithm == &digest::SHA384 => {
+            &ring_signature::RSA_PKCS1_2048_8192_SHA384
+        }
+        OID_RSA_ENCRYPTION => &ring_signature::RSA_PKCS1_2048_8192_SHA512,
+        oid => signature_algorithm(oid)?,
+    };
+    // The attributes are signed with their SET OF tag, not the implicit one.
+    let signed_attributes = encode(SET, attributes.contents);
+
+    // The authority is trusted itself or chains to a trusted authority.
+    let included: Vec<Vec<u8>> = certificates.iter().map(|cert| cert.raw.to_vec()).collect();
+    let (authority_der, authority) = included
+        .iter()
+        .chain(&roots.timestamp_authorities)
+        .find_map(|der| {
+            let cert = parse_certificate(der).ok()?;
+            UnparsedPublicKey::new(verification, cert.public_key().subject_public_key.data)
+                .verify(&signed_attributes, token_signature.contents)
+                .ok()?;
+            Some((der, cert))
+        })
+        .ok_or_else(|| anyhow!("Time-stamp token is not signed by any of its certificates"))?;
+    let trusted = roots
+        .timestamp_authorities
+        .iter()
+        .any(|der| der == authority_der)
+        || verify_chain(
+            &authority,
+            &included,
+            &roots.timestamp_authorities,
+            &roots.algorithms,
+        )
+        .is_ok();
+    if !trusted {
+        return Err(anyhow!("Time-stamp token is not from a trusted authority"));
+    }
+    if !authority
+        .tbs_certificate
+        .extended_key_usage()
+        .is_some_and(|(_, usage)| usage.time_stamping)
+    {
+        return Err(anyhow!("Certificate is not for time-stamping"));
+    }
+    let validity = authority.validity();
+    if time.timestamp() < validity.not_before.timestamp()
+        || time.timestamp() > validity.not_after.timestamp()
+    {
+        return Err(anyhow!(
+            "Time-stamp is outside the authority certificate's validity period"
+        ));
+    }
+    Ok(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CA: &str = include_str!("../tests/test_data/timestamp/ca.pem");
+    const SIGNATURE: &[u8] = include_bytes!("../tests/test_data/timestamp/signature.bin");
+    const RESPONSE: &[u8] = include_bytes!("../tests/test_data/timestamp/response.tsr");
+
+    #[test]
+    fn verify_rfc3161() {
+        let mut roots = TrustRoots::sigstore().expect("No sigstore roots");
+        let timestamp = SignedTimestamp::from_bytes(RESPONSE).expect("Invalid response");
+        assert!(timestamp.verify(b"", SIGNATURE, &roots).is_err());
+
+        roots.timestamp_authorities =
+            crate::verify_core::pem_certificates(CA.as_bytes()).expect("No CA");
+        let time = timestamp
+            .verify(b"", SIGNATURE, &roots)
+            .expect("Timestamp does not verify");
+        assert_eq!(time, Utc.ymd(2026, 10, 15).and_hms(12, 25, 6));
+        assert!(timestamp.verify(b"", b"another signature", &roots).is_err());
+    }
+}
@@ -0,0 +1,936 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use http::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use http::{Method, Uri};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::error::SgetError;
+
+pub use http::{Request, Response};
+
+/// Sends the HTTP requests sget makes: artifact and signature material fetches,
+/// Fulcio and Rekor calls, and OCI registry pulls.
+///
+/// [`ReqwestTransport`] is used unless another transport is injected, e.g. to
+/// add authentication, to replay recorded responses in tests, or to route
+/// requests through a client the embedding application already configured.
+/// Non-success statuses are returned as responses, not errors.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>>;
+
+    /// Like `send`, calling `progress` with the number of body bytes received so
+    /// far and the expected total, if known, as the response arrives. The
+    /// default reports the whole body once it has been received.
+    async fn send_with_progress(
+        &self,
+        request: Request<Vec<u8>>,
+        progress: &(dyn Fn(u64, Option<u64>) + Send + Sync),
+    ) -> Result<Response<Vec<u8>>> {
+        let response = self.send(request).await?;
+        let len = response.body().len() as u64;
+        progress(len, Some(len));
+        Ok(response)
+    }
+}
+
+/// How long idle pooled connections are kept open for reuse.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Interval of TCP keep-alive probes on pooled connections.
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// How long connecting to a server, TLS handshake included, may take. Hyper
+/// races the second address family 300ms after the first; this bounds a
+/// family that accepts connections and then stalls.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+static HTTP1_ONLY: AtomicBool = AtomicBool::new(false);
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Guarantee that this process makes no network requests: from now on
+/// [`default_transport`] refuses every request with [`SgetError::Offline`],
+/// and so do webhooks and self-update. There is no way to go online again.
+pub fn go_offline() {
+    OFFLINE.store(true, Ordering::SeqCst);
+}
+
+/// Whether [`go_offline`] was called.
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::SeqCst)
+}
+
+static PROXY: OnceLock<reqwest::Proxy> = OnceLock::new();
+
+/// An HTTP proxy to send requests through, set in the `proxy` section of the
+/// configuration.
+///
+/// Without one, the `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` environment
+/// variables are honoured, including credentials in the proxy URL. Proxies
+/// are authenticated with Basic authentication; Digest, NTLM and SPNEGO are
+/// not supported.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    /// URL of the proxy, e.g. `http://proxy.corp.example:3128`. Overridden by
+    /// `SGET_PROXY`.
+    pub url: Option<String>,
+    /// Overridden by `SGET_PROXY_USERNAME`.
+    pub username: Option<String>,
+    /// Overridden by `SGET_PROXY_PASSWORD`. Prefer the environment variable,
+    /// or `keychain:NAME` for a password stored with `sget keychain set`, to
+    /// keeping the password in the configuration file.
+    pub password: Option<String>,
+    /// Hosts to connect to directly; each also matches its subdomains.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Apply the `SGET_PROXY*` environment variables on top of the
+    /// configuration.
+    pub fn with_env(mut self) -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        self.url = var("SGET_PROXY").or(self.url);
+        self.username = var("SGET_PROXY_USERNAME").or(self.username);
+        self.password = var("SGET_PROXY_PASSWORD").or(self.password);
+        self
+    }
+
+    /// Whether requests to `host` bypass the proxy.
+    fn bypasses(&self, host: &str) -> bool {
+        self.no_proxy.iter().any(|entry| {
+            let entry = entry.trim_start_matches('.');
+            host == entry
+                || host
+                    .strip_suffix(entry)
+                    .is_some_and(|rest| rest.ends_with('.'))
+        })
+    }
+
+    fn to_proxy(&self) -> Result<Option<reqwest::Proxy>> {
+        let url = match &self.url {
+            Some(url) => {
+                reqwest::Url::parse(url).map_err(|e| anyhow!("Invalid proxy URL {}: {}", url, e))?
+            }
+            None => return Ok(None),
+        };
+        let config = self.clone();
+        let mut proxy = reqwest::Proxy::custom(move |target| match target.host_str() {
+            Some(host) if config.bypasses(host) => None,
+            _ => Some(url.clone()),
+        });
+        match (&self.username, &self.password) {
+            (Some(username), password) => {
+                proxy = proxy.basic_auth(username, password.as_deref().unwrap_or_default())
+            }
+            (None, Some(_)) => return Err(anyhow!("Proxy password given without a username")),
+            (None, None) => {}
+        }
+        Ok(Some(proxy))
+    }
+}
+
+/// Send the shared client's requests through `config`'s proxy, if it names
+/// one. Must be called before the first request.
+pub fn use_proxy(config: &ProxyConfig) -> Result<()> {
+    if let Some(proxy) = config.to_proxy()? {
+        let _ = PROXY.set(proxy);
+    }
+    Ok(())
+}
+
+/// Make the shared client speak HTTP/1.1 only, for middleboxes that break
+/// HTTP/2. Must be called before the first request.
+pub fn force_http1() {
+    HTTP1_ONLY.store(true, Ordering::SeqCst);
+}
+
+/// An IP address family to connect over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl IpFamily {
+    /// The unspecified local address of the family, which restricts the
+    /// addresses connected to to that family.
+    fn local_address(self) -> IpAddr {
+        match self {
+            IpFamily::Ipv4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpFamily::Ipv6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        }
+    }
+}
+
+static PINNED_FAMILY: OnceLock<IpFamily> = OnceLock::new();
+
+/// Make the shared client connect over `family` only, for hosts where the
+/// other family is broken. Must be called before the first request.
+pub fn pin_ip_family(family: IpFamily) {
+    let _ = PINNED_FAMILY.set(family);
+}
+
+/// The family that worked after a dual-stack connection failed, which later
+/// requests use straight away.
+static FALLBACK_FAMILY: OnceLock<IpFamily> = OnceLock::new();
+
+fn build_client(family: Option<IpFamily>) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(concat!("sget/", env!("CARGO_PKG_VERSION")))
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE)
+        .connect_timeout(CONNECT_TIMEOUT);
+    if HTTP1_ONLY.load(Ordering::SeqCst) {
+        builder = builder.http1_only();
+    }
+    if let Some(proxy) = PROXY.get() {
+        builder = builder.proxy(proxy.clone());
+    }
+    if let Some(family) = family {
+        builder = builder.local_address(family.local_address());
+    }
+    builder
+        .build()
+        .map_err(|e| anyhow!("Cannot set up the HTTP client: {}", e))
+}
+
+/// A client built once per process, or the error building it failed with.
+type CachedClient = OnceLock<std::result::Result<reqwest::Client, String>>;
+
+/// The client in `cell`, built for `family` on first use. A failed build is
+/// reported by every use, rather than replaced by a client without the
+/// proxy, HTTP version or address family settings.
+fn cached_client(cell: &CachedClient, family: Option<IpFamily>) -> Result<reqwest::Client> {
+    cell.get_or_init(|| build_client(family).map_err(|e| e.to_string()))
+        .clone()
+        .map_err(|e| anyhow!(e))
+}
+
+/// A client like [`shared_client`] connecting over `family` only.
+fn family_client(family: IpFamily) -> Result<reqwest::Client> {
+    static IPV4: CachedClient = OnceLock::new();
+    static IPV6: CachedClient = OnceLock::new();
+    let cell = match family {
+        IpFamily::Ipv4 => &IPV4,
+        IpFamily::Ipv6 => &IPV6,
+    };
+    cached_client(cell, Some(family))
+}
+
+/// The `reqwest` client every default transport in the process shares, so that
+/// artifact fetches, Rekor and Fulcio calls and registry pulls reuse pooled
+/// keep-alive connections and TLS sessions instead of handshaking each time.
+/// HTTP/2 is negotiated with servers that support it, multiplexing requests
+/// to the same host over one connection, unless [`force_http1`] was called.
+/// Requests go through the proxy given to [`use_proxy`], if any, and connect
+/// over the family given to [`pin_ip_family`], if any.
+/// Fails if the client cannot be built with those settings.
+pub fn shared_client() -> Result<reqwest::Client> {
+    static CLIENT: CachedClient = OnceLock::new();
+    cached_client(&CLIENT, PINNED_FAMILY.get().copied())
+}
+
+/// The default [`Transport`], backed by a `reqwest` client. Clones share the
+/// client's connection pool.
+#[derive(Clone)]
+pub struct ReqwestTransport {
+    /// `None` for [`shared_client`], which is built on the first request.
+    client: Option<reqwest::Client>,
+    /// Whether to fall back to one address family at a time when connecting
+    /// over both fails.
+    fallback: bool,
+}
+
+impl Default for ReqwestTransport {
+    /// A transport sending requests with [`shared_client`]. Unless a family
+    /// was pinned, requests whose dual-stack connection fails are retried
+    /// over IPv4 and then IPv6, and the first family that works is used for
+    /// the rest of the process.
+    fn default() -> Self {
+        ReqwestTransport {
+            client: None,
+            fallback: PINNED_FAMILY.get().is_none(),
+        }
+    }
+}
+
+impl ReqwestTransport {
+    /// A transport sending requests with a preconfigured `client`.
+    pub fn new(client: reqwest::Client) -> Self {
+        ReqwestTransport {
+            client: Some(client),
+            fallback: false,
+        }
+    }
+}
+
+/// Whether `error` is a failure to connect, rather than of the request.
+fn connect_failed(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+#[async_trait]
+impl Transport for ReqwestTransport {
+    async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+        self.send_with_progress(request, &|_, _| {}).await
+    }
+
+    async fn send_with_progress(
+        &self,
+        request: Request<Vec<u8>>,
+        progress: &(dyn Fn(u64, Option<u64>) + Send + Sync),
+    ) -> Result<Response<Vec<u8>>> {
+        let client = match &self.client {
+            Some(client) => client.clone(),
+            None => shared_client()?,
+        };
+        if !self.fallback {
+            return send_with(&client, request, progress).await;
+        }
+        if let Some(family) = FALLBACK_FAMILY.get() {
+            return send_with(&family_client(*family)?, request, progress).await;
+        }
+        let error = match send_with(&client, copy_request(&request), progress).await {
+            Err(error) if connect_failed(&error) => error,
+            sent => return sent,
+        };
+        for family in [IpFamily::Ipv4, IpFamily::Ipv6] {
+            let client = family_client(family)?;
+            if let Ok(response) = send_with(&client, copy_request(&request), progress).await {
+                let _ = FALLBACK_FAMILY.set(family);
+                return Ok(response);
+            }
+        }
+        Err(error)
+    }
+}
+
+/// Send `request` with `client`, reporting the body as it arrives.
+async fn send_with(
+    client: &reqwest::Client,
+    request: Request<Vec<u8>>,
+    progress: &(dyn Fn(u64, Option<u64>) + Send + Sync),
+) -> Result<Response<Vec<u8>>> {
+    let (parts, body) = request.into_parts();
+    let mut response = client
+        .request(parts.method, parts.uri.to_string())
+        .headers(parts.headers)
+        .body(body)
+        .send()
+        .await?;
+    let mut builder = Response::builder().status(response.status());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = response.headers().clone();
+    }
+    let total = response.content_length();
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        progress(body.len() as u64, total);
+    }
+    Ok(builder.body(body)?)
+}
+
+/// Extra request headers for an artifact source, set in the `sources` section
+/// of the configuration, e.g. a bearer token for an authenticated CDN. A value
+/// of `keychain:NAME` is the secret stored as NAME with `sget keychain set`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SourceHeaders {
+    /// URL prefix of the source, e.g. `https://cdn.corp.example/scripts/`.
+    /// The headers are sent to no other URL. Without one, they are sent with
+    /// every request.
+    pub url: Option<String>,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl fmt::Debug for SourceHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Header values are credentials; never print them.
+        f.debug_struct("SourceHeaders")
+            .field("url", &self.url)
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl SourceHeaders {
+    /// Parse a `Name: value` header, as given to `--header`.
+    pub fn parse_header(header: &str) -> Result<(String, String)> {
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Expected NAME: VALUE, got a header without a colon"))?;
+        Ok((name.trim().to_string(), value.trim().to_string()))
+    }
+}
+
+/// Sources with their URL prefix and validated headers. Header values are
+/// marked sensitive, so that they are redacted wherever requests are printed.
+type CompiledSources = Vec<(Option<Uri>, HeaderMap)>;
+
+fn compile_sources(sources: &[SourceHeaders]) -> Result<CompiledSources> {
+    sources
+        .iter()
+        .map(|source| {
+            let prefix = source
+                .url
+                .as_ref()
+                .map(|url| {
+                    url.parse::<Uri>()
+                        .map_err(|e| anyhow!("Invalid source URL {}: {}", url, e))
+                })
+                .transpose()?;
+            let mut headers = HeaderMap::new();
+            for (name, value) in &source.headers {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| anyhow!("Invalid header name {:?}", name))?;
+                let mut value = HeaderValue::from_str(value)
+                    .map_err(|_| anyhow!("Invalid value for header {}", name))?;
+                value.set_sensitive(true);
+                headers.append(name, value);
+            }
+            Ok((prefix, headers))
+        })
+        .collect()
+}
+
+/// Whether `uri` is `prefix` or below it: same scheme and authority, and a
+/// path under the prefix's path, matching whole segments.
+fn within(prefix: &Uri, uri: &Uri) -> bool {
+    let (base, path) = (prefix.path(), uri.path());
+    prefix.scheme() == uri.scheme()
+        && prefix.authority() == uri.authority()
+        && (path == base
+            || base.ends_with('/') && path.starts_with(base)
+            || path
+                .strip_prefix(base)
+                .is_some_and(|rest| rest.starts_with('/')))
+}
+
+static SOURCE_HEADERS: OnceLock<CompiledSources> = OnceLock::new();
+
+/// Send the headers of `sources` with requests made with [`default_transport`]
+/// to each source. Must be called before the first request.
+pub fn use_headers(sources: &[SourceHeaders]) -> Result<()> {
+    let compiled = compile_sources(sources)?;
+    if !compiled.is_empty() {
+        let _ = SOURCE_HEADERS.set(compiled);
+    }
+    Ok(())
+}
+
+/// Adds the headers of the artifact sources a request is for, and sends it
+/// with an inner transport.
+pub struct HeaderTransport {
+    inner: Arc<dyn Transport>,
+    sources: CompiledSources,
+}
+
+impl HeaderTransport {
+    pub fn new(inner: Arc<dyn Transport>, sources: &[SourceHeaders]) -> Result<Self> {
+        Ok(HeaderTransport {
+            inner,
+            sources: compile_sources(sources)?,
+        })
+    }
+
+    fn add_headers(&self, request: &mut Request<Vec<u8>>) {
+        for (prefix, headers) in &self.sources {
+            if prefix
+                .as_ref()
+                .is_none_or(|prefix| within(prefix, request.uri()))
+            {
+                // Headers the request sets itself, such as the identity token
+                // Fulcio is sent, are never replaced.
+                for (name, value) in headers {
+                    if !request.headers().contains_key(name) {
+                        request.headers_mut().insert(name, value.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for HeaderTransport {
+    async fn send(&self, mut request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+        self.add_headers(&mut request);
+        self.inner.send(request).await
+    }
+
+    async fn send_with_progress(
+        &self,
+        mut request: Request<Vec<u8>>,
+        progress: &(dyn Fn(u64, Option<u64>) + Send + Sync),
+    ) -> Result<Response<Vec<u8>>> {
+        self.add_headers(&mut request);
+        self.inner.send_with_progress(request, progress).await
+    }
+}
+
+/// How failed requests are retried, set in the `retry` section of the
+/// configuration. Only idempotent requests are retried, after a connection
+/// error or a response with one of `statuses`, waiting `backoff_base`,
+/// doubled after each retry up to `backoff_cap`, or as long as the server's
+/// `Retry-After` asks within the cap. Interactive fetches want few quick
+/// attempts; batch verification of many artifacts a larger budget.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Attempts per request, the first included; 1 disables retries.
+    pub max_attempts: u32,
+    /// Retries allowed across all requests of one sget invocation, so that an
+    /// unreachable server does not multiply the time a batch takes.
+    pub budget: u32,
+    /// Delay before the first retry, e.g. `250ms`.
+    pub backoff_base: String,
+    /// The longest delay between attempts, e.g. `5s`.
+    pub backoff_cap: String,
+    /// HTTP statuses that are retried.
+    pub statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            budget: 10,
+            backoff_base: "250ms".to_string(),
+            backoff_cap: "5s".to_string(),
+            statuses: vec![408, 429, 500, 502, 503, 504],
+        }
+    }
+}
+
+/// A [`RetryPolicy`] with its durations parsed.
+#[derive(Clone)]
+struct CompiledRetry {
+    max_attempts: u32,
+    budget: u32,
+    base: Duration,
+    cap: Duration,
+    statuses: Vec<u16>,
+}
+
+impl RetryPolicy {
+    fn compile(&self) -> Result<CompiledRetry> {
+        let duration = |value: &str| -> Result<Duration> {
+            crate::utils::parse_duration(value)?
+                .to_std()
+                .map_err(|_| anyhow!("Negative retry backoff {}", value))
+        };
+        if self.max_attempts == 0 {
+            return Err(anyhow!("retry.max_attempts must be at least 1"));
+        }
+        Ok(CompiledRetry {
+            max_attempts: self.max_attempts,
+            budget: self.budget,
+            base: duration(&self.backoff_base)?,
+            cap: duration(&self.backoff_cap)?,
+            statuses: self.statuses.clone(),
+        })
+    }
+}
+
+static RETRY: OnceLock<CompiledRetry> = OnceLock::new();
+
+/// Retry requests made with [`default_transport`] as `policy` says, instead
+/// of the default policy. Must be called before the first request.
+pub fn use_retry(policy: &RetryPolicy) -> Result<()> {
+    let _ = RETRY.set(policy.compile()?);
+    Ok(())
+}
+
+/// Retries the requests an inner transport fails, within a retry budget
+/// shared by all requests sent with it.
+pub struct RetryTransport {
+    inner: Arc<dyn Transport>,
+    policy: CompiledRetry,
+    budget: AtomicU32,
+}
+
+impl RetryTransport {
+    pub fn new(inner: Arc<dyn Transport>, policy: &RetryPolicy) -> Result<Self> {
+        Ok(Self::compiled(inner, policy.compile()?))
+    }
+
+    fn compiled(inner: Arc<dyn Transport>, policy: CompiledRetry) -> Self {
+        RetryTransport {
+            inner,
+            budget: AtomicU32::new(policy.budget),
+            policy,
+        }
+    }
+
+    /// Take one retry from the budget, if any is left.
+    fn take_retry(&self) -> bool {
+        self.budget
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// How long to wait before retry number `retry`, counting from 0.
+    fn backoff(&self, retry: u32, response: Option<&Response<Vec<u8>>>) -> Duration {
+        let retry_after = response
+            .and_then(|response| response.headers().get(http::header::RETRY_AFTER))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        let exponential = self
+            .policy
+            .base
+            .checked_mul(1 << retry.min(16))
+            .unwrap_or(self.policy.cap);
+        retry_after.unwrap_or(exponential).min(self.policy.cap)
+    }
+
+    async fn send_retrying(
+        &self,
+        request: Request<Vec<u8>>,
+        progress: &(dyn Fn(u64, Option<u64>) + Send + Sync),
+    ) -> Result<Response<Vec<u8>>> {
+        if !request.method().is_idempotent() || self.policy.max_attempts == 1 {
+            return self.inner.send_with_progress(request, progress).await;
+        }
+        let mut retry = 0;
+        loop {
+            let result = self
+                .inner
+                .send_with_progress(copy_request(&request), progress)
+                .await;
+            let retryable = match &result {
+                Ok(response) => self.policy.statuses.contains(&response.status().as_u16()),
+                Err(e) => !matches!(e.downcast_ref::<SgetError>(), Some(SgetError::Offline(_))),
+            };
+            if !retryable || retry + 1 >= self.policy.max_attempts || !self.take_retry() {
+                return result;
+            }
+            tokio::time::sleep(self.backoff(retry, result.as_ref().ok())).await;
+            retry += 1;
+        }
+    }
+}
+
+/// A copy of `request` to send again.
+fn copy_request(request: &Request<Vec<u8>>) -> Request<Vec<u8>> {
+    let mut copy = Request::new(request.body().clone());
+    *copy.method_mut() = request.method().clone();
+    *copy.uri_mut() = request.uri().clone();
+    *copy.version_mut() = request.version();
+    *copy.headers_mut() = request.headers().clone();
+    copy
+}
+
+#[async_trait]
+impl Transport for RetryTransport {
+    async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+        self.send_retrying(request, &|_, _| {}).await
+    }
+
+    async fn send_with_progress(
+        &self,
+        request: Request<Vec<u8>>,
+        progress: &(dyn Fn(u64, Option<u64>) + Send + Sync),
+    ) -> Result<Response<Vec<u8>>> {
+        self.send_retrying(request, progress).await
+    }
+}
+
+/// Refuses every request with [`SgetError::Offline`].
+pub struct OfflineTransport;
+
+#[async_trait]
+impl Transport for OfflineTransport {
+    async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+        Err(SgetError::Offline(request.uri().to_string()).into())
+    }
+}
+
+/// The transport used when none is injected, shared by the whole process, or
+/// an [`OfflineTransport`] once the process went offline. Requests carry the
+/// headers given to [`use_headers`] and are retried as the policy given to
+/// [`use_retry`] says, or the default [`RetryPolicy`].
+pub fn default_transport() -> Arc<dyn Transport> {
+    if is_offline() {
+        return Arc::new(OfflineTransport);
+    }
+    static TRANSPORT: OnceLock<Arc<dyn Transport>> = OnceLock::new();
+    TRANSPORT
+        .get_or_init(|| {
+            let retry = RETRY
+                .get()
+                .cloned()
+                .or_else(|| RetryPolicy::default().compile().ok());
+            let mut inner: Arc<dyn Transport> = Arc::new(ReqwestTransport::default());
+            if let Some(retry) = retry {
+                inner = Arc::new(RetryTransport::compiled(inner, retry));
+            }
+            match SOURCE_HEADERS.get() {
+                Some(sources) => Arc::new(HeaderTransport {
+                    inner,
+                    sources: sources.clone(),
+                }),
+                None => inner,
+            }
+        })
+        .clone()
+}
+
+/// A GET request for `url` without a body.
+pub fn get(url: &str) -> Result<Request<Vec<u8>>> {
+    Ok(Request::get(url).body(Vec::new())?)
+}
+
+/// A POST request for `url` with `body` encoded as JSON.
+pub fn post_json(url: &str, body: &impl Serialize) -> Result<Request<Vec<u8>>> {
+    Ok(Request::builder()
+        .method(Method::POST)
+        .uri(url)
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+        .body(serde_json::to_vec(body)?)?)
+}
+
+/// Decode the JSON body of a successful response.
+pub fn json<T: DeserializeOwned>(response: &Response<Vec<u8>>) -> Result<T> {
+    if !response.status().is_success() {
+        return Err(anyhow!("Request failed with {}", response.status()));
+    }
+    Ok(serde_json::from_slice(response.body())?)
+}
+
+/// The body of a response as text, for error messages.
+pub fn text(response: &Response<Vec<u8>>) -> String {
+    String::from_utf8_lossy(response.body()).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn share_default_transport() {
+        assert!(Arc::ptr_eq(&default_transport(), &default_transport()));
+    }
+
+    #[tokio::test]
+    async fn refuse_requests_offline() {
+        let err = OfflineTransport
+            .send(get("https://example.com/install.sh").expect("Invalid request"))
+            .await
+            .expect_err("Offline transport sent a request");
+        assert!(matches!(
+            err.downcast_ref::<SgetError>(),
+            Some(SgetError::Offline(url)) if url == "https://example.com/install.sh"
+        ));
+    }
+
+    #[tokio::test]
+    async fn authenticate_to_proxy() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Cannot bind");
+        let config = ProxyConfig {
+            url: Some(format!(
+                "http://{}",
+                listener.local_addr().expect("No address")
+            )),
+            username: Some("alice".to_string()),
+            password: Some("hunter2".to_string()),
+            no_proxy: vec![".internal.example".to_string()],
+        };
+        assert!(config.bypasses("repo.internal.example"));
+        assert!(!config.bypasses("notinternal.example"));
+        let proxy = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("No connection");
+            let mut request = vec![0; 4096];
+            let len = stream.read(&mut request).expect("Cannot read request");
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                .expect("Cannot write response");
+            String::from_utf8_lossy(&request[..len]).into_owned()
+        });
+
+        let client = reqwest::Client::builder()
+            .proxy(config.to_proxy().expect("Invalid proxy").expect("No proxy"))
+            .build()
+            .expect("Cannot build client");
+        let response = ReqwestTransport::new(client)
+            .send(get("http://artifacts.example/install.sh").expect("Invalid request"))
+            .await
+            .expect("Request failed");
+        assert_eq!(response.body(), b"ok");
+        let request = proxy.join().expect("Proxy failed");
+        assert!(request.starts_with("GET http://artifacts.example/install.sh"));
+        // base64("alice:hunter2")
+        assert!(request
+            .to_lowercase()
+            .contains("proxy-authorization: basic "));
+        assert!(request.contains("YWxpY2U6aHVudGVyMg=="));
+    }
+
+    #[tokio::test]
+    async fn pin_address_family() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Cannot bind");
+        let url = format!("http://{}/", listener.local_addr().expect("No address"));
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("No connection");
+            let mut request = vec![0; 4096];
+            let len = stream.read(&mut request).expect("Cannot read request");
+            assert!(len > 0);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                .expect("Cannot write response");
+        });
+
+        // An IPv6 client has no address to connect to.
+        let err = ReqwestTransport::new(family_client(IpFamily::Ipv6).expect("No client"))
+            .send(get(&url).expect("Invalid request"))
+            .await
+            .expect_err("Connected over IPv4");
+        assert!(connect_failed(&err));
+        let response = ReqwestTransport::new(family_client(IpFamily::Ipv4).expect("No client"))
+            .send(get(&url).expect("Invalid request"))
+            .await
+            .expect("Request failed");
+        assert_eq!(response.body(), b"ok");
+        server.join().expect("Server failed");
+    }
+
+    #[tokio::test]
+    async fn add_source_headers() {
+        struct Echo;
+        #[async_trait]
+        impl Transport for Echo {
+            async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+                let auth = request
+                    .headers()
+                    .get("authorization")
+                    .map(|value| value.as_bytes().to_vec())
+                    .unwrap_or_default();
+                Ok(Response::new(auth))
+            }
+        }
+        let (name, value) =
+            SourceHeaders::parse_header("Authorization: Bearer s3cret").expect("Invalid header");
+        let source = SourceHeaders {
+            url: Some("https://cdn.corp.example/scripts".to_string()),
+            headers: BTreeMap::from([(name, value)]),
+        };
+        assert!(!format!("{:?}", source).contains("s3cret"));
+        let transport = HeaderTransport::new(Arc::new(Echo), &[source]).expect("Invalid source");
+        let auth = |url: &str| {
+            let transport = &transport;
+            let url = url.to_string();
+            async move {
+                transport
+                    .send(get(&url).expect("Invalid request"))
+                    .await
+                    .expect("Request failed")
+                    .into_body()
+            }
+        };
+        assert_eq!(
+            auth("https://cdn.corp.example/scripts/install.sh").await,
+            b"Bearer s3cret"
+        );
+        assert!(auth("https://cdn.corp.example/scriptsevil/x.sh")
+            .await
+            .is_empty());
+        assert!(auth("https://cdn.corp.example.evil.com/scripts/install.sh")
+            .await
+            .is_empty());
+        assert!(auth("http://cdn.corp.example/scripts/install.sh")
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn retry_within_budget() {
+        use std::sync::atomic::AtomicUsize;
+
+        /// Answers with `statuses` in turn, then 200.
+        struct Flaky {
+            statuses: Vec<u16>,
+            sent: AtomicUsize,
+        }
+        #[async_trait]
+        impl Transport for Flaky {
+            async fn send(&self, _: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+                let sent = self.sent.fetch_add(1, Ordering::SeqCst);
+                let status = self.statuses.get(sent).copied().unwrap_or(200);
+                Ok(Response::builder().status(status).body(Vec::new())?)
+            }
+        }
+        let flaky = |statuses: &[u16]| {
+            Arc::new(Flaky {
+                statuses: statuses.to_vec(),
+                sent: AtomicUsize::new(0),
+            })
+        };
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            budget: 3,
+            backoff_base: "1ms".to_string(),
+            backoff_cap: "2ms".to_string(),
+            ..RetryPolicy::default()
+        };
+        let request = || get("https://example.com/install.sh").expect("Invalid request");
+
+        let inner = flaky(&[503, 502]);
+        let transport = RetryTransport::new(inner.clone(), &policy).expect("Invalid policy");
+        let response = transport.send(request()).await.expect("Request failed");
+        assert_eq!(response.status(), 200);
+        assert_eq!(inner.sent.load(Ordering::SeqCst), 3);
+
+        // Statuses that are not retried are returned at once.
+        let inner = flaky(&[404]);
+        let transport = RetryTransport::new(inner.clone(), &policy).expect("Invalid policy");
+        let response = transport.send(request()).await.expect("Request failed");
+        assert_eq!(response.status(), 404);
+        assert_eq!(inner.sent.load(Ordering::SeqCst), 1);
+
+        // Attempts per request, then the budget, run out.
+        let inner = flaky(&[503; 5]);
+        let transport = RetryTransport::new(inner.clone(), &policy).expect("Invalid policy");
+        let response = transport.send(request()).await.expect("Request failed");
+        assert_eq!(response.status(), 503);
+        assert_eq!(inner.sent.load(Ordering::SeqCst), 3);
+        let response = transport.send(request()).await.expect("Request failed");
+        assert_eq!(response.status(), 503);
+        assert_eq!(inner.sent.load(Ordering::SeqCst), 5);
+
+        // POSTs are never retried.
+        let inner = flaky(&[503]);
+        let transport = RetryTransport::new(inner.clone(), &policy).expect("Invalid policy");
+        let post = post_json("https://rekor.example/api", &1).expect("Invalid request");
+        assert_eq!(
+            transport.send(post).await.expect("Request failed").status(),
+            503
+        );
+        assert_eq!(inner.sent.load(Ordering::SeqCst), 1);
+
+        assert!(RetryTransport::new(
+            inner,
+            &RetryPolicy {
+                max_attempts: 0,
+                ..RetryPolicy::default()
+            }
+        )
+        .is_err());
+    }
+}
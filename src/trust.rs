@@ -0,0 +1,661 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use clap::{App, Arg, ArgMatches};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fmt, str::FromStr};
+use x509_parser::{parse_x509_certificate, pem::parse_x509_pem};
+
+use crate::keys::PublicKey;
+use crate::policy::{Policy, SigstoreOidcKey};
+use crate::sigstore_env::SigstoreEnv;
+use crate::state;
+use crate::storage::{FileStorage, Storage};
+
+/// The kinds of trust material sget keeps in its trust store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrustKind {
+    /// A signed root policy.
+    Policy,
+    /// A PEM encoded Fulcio root (or intermediate) certificate.
+    FulcioRoot,
+    /// A PEM encoded Rekor public key.
+    RekorKey,
+    /// A PEM encoded timestamp authority (or CA) certificate.
+    TimestampAuthority,
+    /// A pinned signer identity and issuer.
+    Identity,
+}
+
+pub const TRUST_KINDS: [TrustKind; 5] = [
+    TrustKind::Policy,
+    TrustKind::FulcioRoot,
+    TrustKind::RekorKey,
+    TrustKind::TimestampAuthority,
+    TrustKind::Identity,
+];
+
+impl TrustKind {
+    fn extension(&self) -> &'static str {
+        match self {
+            TrustKind::Policy | TrustKind::Identity => "json",
+            TrustKind::FulcioRoot | TrustKind::RekorKey | TrustKind::TimestampAuthority => "pem",
+        }
+    }
+
+    /// Check that `contents` is well formed for this kind before trusting it.
+    pub(crate) fn validate(&self, contents: &[u8]) -> Result<()> {
+        match self {
+            TrustKind::Policy => {
+                let policy: Policy =
+                    serde_json::from_slice(contents).context("Not a valid policy")?;
+                if policy.validate_expires().to_std().is_err() {
+                    return Err(anyhow!("Policy expired at {}", policy.signed.expires));
+                }
+            }
+            TrustKind::FulcioRoot | TrustKind::TimestampAuthority => {
+                let (_, pem) = parse_x509_pem(contents)
+                    .map_err(|e| anyhow!("Error parsing PEM certificate: {:?}", e))?;
+                parse_x509_certificate(&pem.contents)
+                    .map_err(|e| anyhow!("Error parsing certificate: {:?}", e))?;
+            }
+            TrustKind::RekorKey => {
+                PublicKey::from_pem(std::str::from_utf8(contents)?)?;
+            }
+            TrustKind::Identity => {
+                serde_json::from_slice::<SigstoreOidcKey>(contents)
+                    .context("Not a valid identity")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TrustKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "policy" => Ok(TrustKind::Policy),
+            "fulcio-root" => Ok(TrustKind::FulcioRoot),
+            "rekor-key" => Ok(TrustKind::RekorKey),
+            "timestamp-authority" => Ok(TrustKind::TimestampAuthority),
+            "identity" => Ok(TrustKind::Identity),
+            other => Err(anyhow!("Unknown trust kind: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for TrustKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TrustKind::Policy => "policy",
+            TrustKind::FulcioRoot => "fulcio-root",
+            TrustKind::RekorKey => "rekor-key",
+            TrustKind::TimestampAuthority => "timestamp-authority",
+            TrustKind::Identity => "identity",
+        })
+    }
+}
+
+/// An entry in the trust store.
+pub struct TrustEntry {
+    pub kind: TrustKind,
+    pub name: String,
+    /// The storage key the entry is kept under.
+    pub key: String,
+    /// Hex encoded SHA-256 digest of the stored contents.
+    pub digest: String,
+}
+
+// A line in the trust store change log.
+#[derive(Serialize, Deserialize)]
+struct TrustChange<'a> {
+    time: String,
+    action: &'a str,
+    kind: String,
+    name: &'a str,
+    digest: &'a str,
+}
+
+/// Change log of the trust store, one JSON line per change.
+const CHANGE_LOG: &str = "changes.log";
+
+/// Name of the index file at the root of a trust bundle.
+pub const BUNDLE_INDEX: &str = "trust.json";
+
+/// The index of a trust bundle written by [`TrustStore::export`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrustBundle {
+    pub sget_version: String,
+    pub created: String,
+    pub entries: Vec<BundleEntry>,
+}
+
+/// An entry of a trust bundle, stored in the bundle under its trust store key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundleEntry {
+    pub kind: String,
+    pub name: String,
+    /// Hex encoded SHA-256 digest of the entry's contents.
+    pub sha256: String,
+}
+
+/// What importing a trust bundle did to an entry.
+#[derive(Debug, PartialEq)]
+pub enum ImportAction {
+    Added,
+    Replaced,
+    Unchanged,
+}
+
+/// Locally trusted policies, roots, keys and identities, stored as one value
+/// per entry under `<kind>/<name>.<extension>`. By default the storage is the
+/// `trust` directory of the state directory.
+pub struct TrustStore {
+    storage: Arc<dyn Storage>,
+}
+
+impl TrustStore {
+    /// A trust store in the directory `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self::with_storage(Arc::new(FileStorage::new(root)))
+    }
+
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Self {
+        Self { storage }
+    }
+
+    /// The trust store of the current sigstore environment in the default
+    /// state directory.
+    pub fn open() -> Result<Self> {
+        Ok(Self::new(
+            state::state_dir()?.join(SigstoreEnv::current().trust_dir()),
+        ))
+    }
+
+    fn entry_key(&self, kind: TrustKind, name: &str) -> Result<String> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+        if !valid {
+            return Err(anyhow!("Invalid trust entry name: {:?}", name));
+        }
+        Ok(format!("{}/{}.{}", kind, name, kind.extension()))
+    }
+
+    fn entry(kind: TrustKind, name: &str, key: String, contents: &[u8]) -> TrustEntry {
+        TrustEntry {
+            kind,
+            name: name.to_string(),
+            key,
+            digest: hex::encode(Sha256::digest(contents)),
+        }
+    }
+
+    /// Validate and store a new entry. Existing entries are never replaced.
+    pub fn add(&self, kind: TrustKind, name: &str, contents: &[u8]) -> Result<TrustEntry> {
+        kind.validate(contents)?;
+        let key = self.entry_key(kind, name)?;
+        if self.storage.get(&key)?.is_some() {
+            return Err(anyhow!("{} {} is already trusted", kind, name));
+        }
+        self.storage.put(&key, contents)?;
+        let entry = Self::entry(kind, name, key, contents);
+        self.record("add", &entry)?;
+        Ok(entry)
+    }
+
+    pub fn remove(&self, kind: TrustKind, name: &str) -> Result<TrustEntry> {
+        let entry = self.get(kind, name)?;
+        self.storage.delete(&entry.key)?;
+        self.record("remove", &entry)?;
+        Ok(entry)
+    }
+
+    pub fn get(&self, kind: TrustKind, name: &str) -> Result<TrustEntry> {
+        let key = self.entry_key(kind, name)?;
+        let contents = self.read_key(kind, name, &key)?;
+        Ok(Self::entry(kind, name, key, &contents))
+    }
+
+    fn read_key(&self, kind: TrustKind, name: &str, key: &str) -> Result<Vec<u8>> {
+        self.storage
+            .get(key)?
+            .ok_or_else(|| anyhow!("{} {} is not trusted", kind, name))
+    }
+
+    pub fn read(&self, kind: TrustKind, name: &str) -> Result<Vec<u8>> {
+        self.read_key(kind, name, &self.entry_key(kind, name)?)
+    }
+
+    /// All entries of the given kind, sorted by name.
+    pub fn list(&self, kind: TrustKind) -> Result<Vec<TrustEntry>> {
+        let prefix = format!("{}/", kind);
+        let suffix = format!(".{}", kind.extension());
+        let mut entries = Vec::new();
+        for key in self.storage.list(&prefix)? {
+            let name = match key
+                .strip_prefix(&prefix)
+                .and_then(|name| name.strip_suffix(&suffix))
+            {
+                Some(name) if !name.contains('/') => name.to_string(),
+                _ => continue,
+            };
+            entries.push(self.get(kind, &name)?);
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /// Write every entry to a gzipped tarball at `path`, to carry to hosts
+    /// without network access. Returns the hex encoded SHA-256 digest of the
+    /// bundle, which [`TrustStore::import`] must be given.
+    pub fn export(&self, path: &Path) -> Result<String> {
+        let file =
+            File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+        let created = Utc::now();
+        let mtime = created.timestamp().max(0) as u64;
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mut add = |key: &str, contents: &[u8]| -> Result<()> {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, key, contents)
+                .with_context(|| format!("Cannot add {} to trust bundle", key))
+        };
+
+        let mut bundle = TrustBundle {
+            sget_version: env!("CARGO_PKG_VERSION").to_string(),
+            created: created.to_rfc3339(),
+            entries: Vec::new(),
+        };
+        for kind in TRUST_KINDS {
+            for entry in self.list(kind)? {
+                add(&entry.key, &self.read_key(kind, &entry.name, &entry.key)?)?;
+                bundle.entries.push(BundleEntry {
+                    kind: kind.to_string(),
+                    name: entry.name,
+                    sha256: entry.digest,
+                });
+            }
+        }
+        add(BUNDLE_INDEX, &serde_json::to_vec_pretty(&bundle)?)?;
+        builder.into_inner()?.finish()?;
+        Ok(hex::encode(Sha256::digest(&fs::read(path)?)))
+    }
+
+    /// Import a bundle written by [`TrustStore::export`], whose SHA-256 digest
+    /// must be `sha256`. Every entry is checked against the bundle's index and
+    /// validated before any is stored, so a bad bundle changes nothing.
+    ///
+    /// New entries are added and changed entries replaced, except that a
+    /// policy is never replaced by one with a lower or equal version.
+    pub fn import(&self, path: &Path, sha256: &str) -> Result<Vec<(ImportAction, TrustEntry)>> {
+        let raw = fs::read(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        let digest = hex::encode(Sha256::digest(&raw));
+        if !digest.eq_ignore_ascii_case(sha256.trim_start_matches("sha256:")) {
+            return Err(anyhow!(
+                "Trust bundle digest mismatch: expected {}, got {}",
+                sha256,
+                digest
+            ));
+        }
+        let mut files = HashMap::new();
+        for file in tar::Archive::new(GzDecoder::new(raw.as_slice())).entries()? {
+            let mut file = file?;
+            let key = file.path()?.to_string_lossy().into_owned();
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents)?;
+            files.insert(key, contents);
+        }
+        let index: TrustBundle = serde_json::from_slice(
+            files
+                .get(BUNDLE_INDEX)
+                .ok_or_else(|| anyhow!("Trust bundle has no {}", BUNDLE_INDEX))?,
+        )
+        .context("Invalid trust bundle index")?;
+
+        let mut changes = Vec::new();
+        for item in &index.entries {
+            let kind: TrustKind = item.kind.parse()?;
+            let key = self.entry_key(kind, &item.name)?;
+            let contents = files
+                .get(&key)
+                .ok_or_else(|| anyhow!("Trust bundle has no {}", key))?;
+            let entry = Self::entry(kind, &item.name, key, contents);
+            if entry.digest != item.sha256 {
+                return Err(anyhow!("Digest mismatch for {} {}", kind, item.name));
+            }
+            kind.validate(contents)
+                .with_context(|| format!("Invalid {} {}", kind, item.name))?;
+            let action = match self.storage.get(&entry.key)? {
+                None => ImportAction::Added,
+                Some(old) if old == *contents => ImportAction::Unchanged,
+                Some(old) => {
+                    if kind == TrustKind::Policy {
+                        let version = |raw: &[u8]| -> Result<u64> {
+                            Ok(serde_json::from_slice::<Policy>(raw)?.signed.version.get())
+                        };
+                        if version(contents)? <= version(&old)? {
+                            return Err(anyhow!(
+                                "Trust bundle would roll back policy {}",
+                                item.name
+                            ));
+                        }
+                    }
+                    ImportAction::Replaced
+                }
+            };
+            changes.push((action, entry, contents));
+        }
+
+        let mut imported = Vec::new();
+        for (action, entry, contents) in changes {
+            if action != ImportAction::Unchanged {
+                self.storage.put(&entry.key, contents)?;
+                self.record("import", &entry)?;
+            }
+            imported.push((action, entry));
+        }
+        Ok(imported)
+    }
+
+    // Append a record of the change to the trust store's change log.
+    fn record(&self, action: &str, entry: &TrustEntry) -> Result<()> {
+        let change = TrustChange {
+            time: Utc::now().to_rfc3339(),
+            action,
+            kind: entry.kind.to_string(),
+            name: &entry.name,
+            digest: &entry.digest,
+        };
+        let line = format!("{}\n", serde_json::to_string(&change)?);
+        self.storage.append(CHANGE_LOG, line.as_bytes())
+    }
+}
+
+pub(crate) fn command() -> App<'static> {
+    let kind = || {
+        Arg::new("kind")
+            .about("Kind of trust material")
+            .possible_values([
+                "policy",
+                "fulcio-root",
+                "rekor-key",
+                "timestamp-authority",
+                "identity",
+            ])
+            .required(true)
+            .index(1)
+    };
+    let name = || {
+        Arg::new("name")
+            .about("Name of the trust entry")
+            .required(true)
+            .index(2)
+    };
+    App::new("trust")
+        .about("Manage locally trusted policies, roots, keys and identities")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("add")
+                .about(
+                    "Trust a new policy, Fulcio root, Rekor key, timestamp authority or identity",
+                )
+                .arg(kind())
+                .arg(name())
+                .arg(
+                    Arg::new("value")
+                        .about("File to trust, or the signer identity for `identity`")
+                        .required(true)
+                        .index(3),
+                )
+                .arg(
+                    Arg::new("issuer")
+                        .long("issuer")
+                        .value_name("ISSUER")
+                        .takes_value(true)
+                        .about("OIDC issuer of a pinned identity"),
+                ),
+        )
+        .subcommand(
+            App::new("export")
+                .about("Write the trust store to a bundle for hosts without network access")
+                .arg(
+                    Arg::new("bundle")
+                        .about("Trust bundle to write, e.g. bundle.tar.gz")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            App::new("import")
+                .about("Add or update trusted entries from a bundle made by `sget trust export`")
+                .arg(
+                    Arg::new("bundle")
+                        .about("Trust bundle to import")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("sha256")
+                        .long("sha256")
+                        .value_name("DIGEST")
+                        .takes_value(true)
+                        .required(true)
+                        .about("SHA-256 digest of the bundle, as printed by `sget trust export`"),
+                ),
+        )
+        .subcommand(
+            App::new("list").about("List trusted entries").arg(
+                Arg::new("kind")
+                    .about("Only list entries of this kind")
+                    .possible_values([
+                        "policy",
+                        "fulcio-root",
+                        "rekor-key",
+                        "timestamp-authority",
+                        "identity",
+                    ])
+                    .index(1),
+            ),
+        )
+        .subcommand(
+            App::new("remove")
+                .about("Stop trusting an entry")
+                .arg(kind())
+                .arg(name()),
+        )
+        .subcommand(
+            App::new("show")
+                .about("Print a trusted entry")
+                .arg(kind())
+                .arg(name()),
+        )
+}
+
+pub(crate) fn run(matches: &ArgMatches) -> Result<()> {
+    let store = TrustStore::open()?;
+    let kind_of = |m: &ArgMatches| -> Result<TrustKind> {
+        m.value_of("kind")
+            .ok_or_else(|| anyhow!("No trust kind given"))?
+            .parse()
+    };
+    let name_of = |m: &ArgMatches| -> Result<String> {
+        m.value_of("name")
+            .map(String::from)
+            .ok_or_else(|| anyhow!("No trust entry name given"))
+    };
+    match matches.subcommand() {
+        Some(("add", m)) => {
+            let kind = kind_of(m)?;
+            let value = m.value_of("value").unwrap_or_default();
+            let contents = match kind {
+                TrustKind::Identity => serde_json::to_vec_pretty(&SigstoreOidcKey {
+                    identity: value.to_string(),
+                    issuer: m.value_of("issuer").unwrap_or_default().to_string(),
+                })?,
+                _ => fs::read(value).with_context(|| format!("Cannot read {}", value))?,
+            };
+            let entry = store.add(kind, &name_of(m)?, &contents)?;
+            println!(
+                "Trusted {} {} (sha256:{})",
+                entry.kind, entry.name, entry.digest
+            );
+        }
+        Some(("export", m)) => {
+            let path = Path::new(m.value_of("bundle").unwrap_or_default());
+            let digest = store.export(path)?;
+            println!("Wrote {} (sha256:{})", path.display(), digest);
+        }
+        Some(("import", m)) => {
+            let path = Path::new(m.value_of("bundle").unwrap_or_default());
+            let sha256 = m.value_of("sha256").unwrap_or_default();
+            for (action, entry) in store.import(path, sha256)? {
+                println!(
+                    "{:?}\t{}\t{}\tsha256:{}",
+                    action, entry.kind, entry.name, entry.digest
+                );
+            }
+        }
+        Some(("list", m)) => {
+            let kinds = match m.value_of("kind") {
+                Some(kind) => vec![kind.parse()?],
+                None => TRUST_KINDS.to_vec(),
+            };
+            for kind in kinds {
+                for entry in store.list(kind)? {
+                    println!("{}\t{}\tsha256:{}", entry.kind, entry.name, entry.digest);
+                }
+            }
+        }
+        Some(("remove", m)) => {
+            let entry = store.remove(kind_of(m)?, &name_of(m)?)?;
+            println!("Removed {} {}", entry.kind, entry.name);
+        }
+        Some(("show", m)) => {
+            let contents = store.read(kind_of(m)?, &name_of(m)?)?;
+            print!("{}", String::from_utf8_lossy(&contents));
+        }
+        _ => return Err(anyhow!("Unknown trust subcommand")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use std::path::Path;
+
+    const CRATE: &str = env!("CARGO_MANIFEST_DIR");
+
+    fn read_changes(root: &Path) -> Vec<String> {
+        fs::read_to_string(root.join("changes.log"))
+            .expect("Cannot read change log")
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn add_list_remove_identity() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let store = TrustStore::new(dir.path());
+        let identity =
+            br#"{"identity": "jpenumak@redhat.com", "issuer": "https://github.com/login/oauth"}"#;
+
+        store
+            .add(TrustKind::Identity, "jyotsna", identity)
+            .expect("Cannot add identity");
+        assert!(store.add(TrustKind::Identity, "jyotsna", identity).is_err());
+
+        let listed = store.list(TrustKind::Identity).expect("Cannot list");
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "jyotsna");
+        assert_eq!(
+            store
+                .read(TrustKind::Identity, "jyotsna")
+                .expect("Cannot read"),
+            identity
+        );
+
+        store
+            .remove(TrustKind::Identity, "jyotsna")
+            .expect("Cannot remove");
+        assert!(store
+            .list(TrustKind::Identity)
+            .expect("Cannot list")
+            .is_empty());
+
+        let changes = read_changes(dir.path());
+        assert_eq!(changes.len(), 2);
+        assert!(changes[1].contains("\"action\":\"remove\""));
+    }
+
+    #[test]
+    fn export_import_bundle() {
+        let connected = TrustStore::with_storage(Arc::new(MemoryStorage::default()));
+        let root = fs::read(Path::new(CRATE).join("tests/test_data/notation/ca.pem"))
+            .expect("Cannot read root");
+        let identity = br#"{"identity": "jpenumak@redhat.com", "issuer": ""}"#;
+        connected
+            .add(TrustKind::FulcioRoot, "fulcio", &root)
+            .expect("Cannot add root");
+        connected
+            .add(TrustKind::Identity, "jyotsna", identity)
+            .expect("Cannot add identity");
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let bundle = dir.path().join("bundle.tar.gz");
+        let digest = connected.export(&bundle).expect("Cannot export");
+
+        let airgapped = TrustStore::with_storage(Arc::new(MemoryStorage::default()));
+        airgapped
+            .add(
+                TrustKind::Identity,
+                "jyotsna",
+                br#"{"identity": "old@redhat.com", "issuer": ""}"#,
+            )
+            .expect("Cannot add identity");
+        assert!(airgapped.import(&bundle, &"0".repeat(64)).is_err());
+        let imported = airgapped.import(&bundle, &digest).expect("Cannot import");
+        let actions: Vec<_> = imported.iter().map(|(action, _)| action).collect();
+        assert_eq!(actions, [&ImportAction::Added, &ImportAction::Replaced]);
+        assert_eq!(
+            airgapped
+                .read(TrustKind::Identity, "jyotsna")
+                .expect("Cannot read"),
+            identity
+        );
+        let again = airgapped.import(&bundle, &digest).expect("Cannot import");
+        assert!(again
+            .iter()
+            .all(|(action, _)| *action == ImportAction::Unchanged));
+    }
+
+    #[test]
+    fn reject_invalid_material() {
+        let store = TrustStore::with_storage(Arc::new(MemoryStorage::default()));
+        assert!(store
+            .add(TrustKind::FulcioRoot, "root", b"garbage")
+            .is_err());
+        assert!(store.add(TrustKind::RekorKey, "rekor", b"garbage").is_err());
+        assert!(store.add(TrustKind::Identity, "../escape", b"{}").is_err());
+
+        // The bad policy has long expired.
+        let bad_policy = fs::read(Path::new(CRATE).join("tests/test_data/policy_bad.json"))
+            .expect("Cannot read bad policy file");
+        assert!(store.add(TrustKind::Policy, "bad", &bad_policy).is_err());
+    }
+}
@@ -0,0 +1,631 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::num::NonZeroU64;
+
+use crate::algorithms::ArtifactDigest;
+use crate::error::{Result, SgetError};
+use crate::merkle::MerkleRoot;
+use crate::policy::{self, Policy, RawPolicy};
+use crate::signature::SignatureVerifiers;
+use crate::verify_core::{check_each, check_policy_signature, TrustRoots};
+
+/// Name of the role signing the timestamp metadata.
+pub const TIMESTAMP: &str = "timestamp";
+/// Name of the role signing the snapshot metadata.
+pub const SNAPSHOT: &str = "snapshot";
+/// Name of the role signing the targets metadata.
+pub const TARGETS: &str = "targets";
+
+/// The version, and optionally length and hashes, a metadata file listed by
+/// the timestamp or snapshot role must have.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetaFile {
+    pub version: NonZeroU64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+    /// Hex encoded digests by algorithm, e.g. `sha256`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hashes: BTreeMap<String, String>,
+}
+
+/// A target file listed by the targets role.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TargetFile {
+    pub length: u64,
+    /// Hex encoded digests by algorithm, e.g. `sha256`.
+    pub hashes: BTreeMap<String, String>,
+    /// Merkle root over fixed-size chunks, for targets large enough to be
+    /// verified chunk by chunk as they download.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle: Option<MerkleRoot>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<serde_json::Value>,
+}
+
+/// The `signed` section of timestamp, snapshot or targets metadata. The keys
+/// and thresholds of the three roles are those of the namespace's root policy.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// The role that signed the metadata.
+    #[serde(rename = "_type")]
+    pub role: String,
+    pub expires: DateTime<Utc>,
+    pub version: NonZeroU64,
+    /// Metadata files listed by the timestamp and snapshot roles, by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub meta: BTreeMap<String, MetaFile>,
+    /// Target files listed by the targets role, by path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, TargetFile>,
+}
+
+fn invalid(role: &str, reason: impl Into<String>) -> SgetError {
+    SgetError::InvalidMetadata {
+        role: role.to_string(),
+        reason: reason.into(),
+    }
+}
+
+impl Metadata {
+    /// What the metadata expects of the metadata file `name`.
+    fn listed(&self, name: &str) -> Result<&MetaFile> {
+        self.meta
+            .get(name)
+            .ok_or_else(|| invalid(&self.role, format!("{} is not listed", name)))
+    }
+
+    fn check_expiry(&self, now: DateTime<Utc>) -> Result<()> {
+        if self.expires <= now {
+            return Err(invalid(&self.role, format!("Expired at {}", self.expires)));
+        }
+        Ok(())
+    }
+}
+
+/// Verify that `raw` is unexpired metadata of `role`, signed by at least the
+/// threshold of the role's keys in `root`, where the role must not have
+/// expired either.
+pub fn verify_metadata(
+    raw: &[u8],
+    role: &str,
+    root: &Policy,
+    roots: &TrustRoots,
+    verifiers: &SignatureVerifiers,
+    now: DateTime<Utc>,
+) -> Result<Metadata> {
+    let envelope: RawPolicy =
+        serde_json::from_slice(raw).map_err(|e| invalid(role, e.to_string()))?;
+    let keys = root
+        .signed
+        .roles
+        .get(role)
+        .ok_or_else(|| invalid(role, format!("The root policy has no {} role", role)))?;
+    if let Some(expires) = keys.expires.filter(|_| keys.expired_at(now)) {
+        return Err(invalid(
+            role,
+            format!("The root policy's {} role expired at {}", role, expires),
+        ));
+    }
+    let signatures: Vec<policy::Signature> = serde_json::from_str(envelope.signatures.get())
+        .map_err(|e| invalid(role, e.to_string()))?;
+    let signed = envelope.signed.get().as_bytes();
+    // Parsed before it is trusted only for the expiry signatures are held to.
+    let metadata: Metadata =
+        serde_json::from_str(envelope.signed.get()).map_err(|e| invalid(role, e.to_string()))?;
+    let candidates: Vec<&policy::Signature> = signatures
+        .iter()
+        .filter(|sig| keys.keyids.contains(&sig.keyid))
+        .collect();
+    let valid = check_each(&candidates, |sig| {
+        root.signed.keys.get(&sig.keyid).is_some_and(|key| {
+            check_policy_signature(key, sig, signed, metadata.expires, roots, verifiers, now)
+                .is_ok()
+        })
+    });
+    let signers: BTreeSet<&str> = candidates
+        .iter()
+        .zip(valid)
+        .filter(|(_, valid)| *valid)
+        .map(|(sig, _)| sig.keyid.as_str())
+        .collect();
+    if (signers.len() as u64) < keys.threshold.get() {
+        return Err(invalid(
+            role,
+            format!(
+                "{} of {} required signatures",
+                signers.len(),
+                keys.threshold
+            ),
+        ));
+    }
+    if metadata.role != role {
+        return Err(invalid(
+            role,
+            format!("Signed as {} metadata", metadata.role),
+        ));
+    }
+    metadata.check_expiry(now)?;
+    Ok(metadata)
+}
+
+/// Check `raw` against the length and hashes `expected` lists for it.
+fn check_file(raw: &[u8], expected: &MetaFile, role: &str) -> Result<()> {
+    if expected
+        .length
+        .is_some_and(|length| length != raw.len() as u64)
+    {
+        return Err(invalid(role, "Length does not match"));
+    }
+    check_hashes(raw, &expected.hashes)
+}
+
+/// Check `raw` against the length and hashes of `target`.
+pub fn check_target(target: &TargetFile, raw: &[u8]) -> Result<()> {
+    if target.length != raw.len() as u64 {
+        return Err(SgetError::UnexpectedContent {
+            location: "target".to_string(),
+            reason: format!(
+                "{} bytes, but the target is {} bytes",
+                raw.len(),
+                target.length
+            ),
+        });
+    }
+    check_hashes(raw, &target.hashes)
+}
+
+fn check_hashes(raw: &[u8], hashes: &BTreeMap<String, String>) -> Result<()> {
+    for (algorithm, hex) in hashes {
+        let digest: ArtifactDigest = format!("{}:{}", algorithm, hex).parse()?;
+        let actual = digest.of(raw)?;
+        if actual != digest {
+            return Err(SgetError::DigestMismatch {
+                expected: digest.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The newest timestamp, snapshot and targets metadata verified for a
+/// namespace. Updates must be applied in TUF order: the timestamp, then the
+/// snapshot it lists, then the targets the snapshot lists. Each is checked
+/// against the version and hashes the previous one lists, and none of them
+/// may roll back to an older version than was trusted before.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrustedMetadata {
+    pub timestamp: Option<Metadata>,
+    pub snapshot: Option<Metadata>,
+    pub targets: Option<Metadata>,
+}
+
+impl TrustedMetadata {
+    /// Verify and trust new timestamp metadata. Returns `false` if it is the
+    /// version already trusted.
+    pub fn update_timestamp(
+        &mut self,
+        raw: &[u8],
+        root: &Policy,
+        roots: &TrustRoots,
+        verifiers: &SignatureVerifiers,
+        now: DateTime<Utc>,
+    ) -> Result<bool> {
+        let new = verify_metadata(raw, TIMESTAMP, root, roots, verifiers, now)?;
+        let snapshot = new.listed(&file_name(SNAPSHOT))?;
+        if let Some(old) = &self.timestamp {
+            if new.version < old.version {
+                return Err(rollback(TIMESTAMP, old.version, new.version));
+            }
+            if new.version == old.version {
+                return Ok(false);
+            }
+            let old_snapshot = old.listed(&file_name(SNAPSHOT))?;
+            if snapshot.version < old_snapshot.version {
+                return Err(rollback(SNAPSHOT, old_snapshot.version, snapshot.version));
+            }
+        }
+        self.timestamp = Some(new);
+        Ok(true)
+    }
+
+    /// The snapshot the trusted timestamp lists, if it is not trusted yet.
+    pub fn snapshot_needed(&self) -> Result<Option<&MetaFile>> {
+        let timestamp = self
+            .timestamp
+            .as_ref()
+            .ok_or_else(|| invalid(SNAPSHOT, "No timestamp is trusted yet"))?;
+        let expected = timestamp.listed(&file_name(SNAPSHOT))?;
+        Ok(match &self.snapshot {
+            Some(snapshot) if snapshot.version == expected.version => None,
+            _ => Some(expected),
+        })
+    }
+
+    /// Verify and trust new snapshot metadata, which must be the snapshot the
+    /// trusted timestamp lists.
+    pub fn update_snapshot(
+        &mut self,
+        raw: &[u8],
+        root: &Policy,
+        roots: &TrustRoots,
+        verifiers: &SignatureVerifiers,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let expected = match self.snapshot_needed()? {
+            Some(expected) => expected.clone(),
+            None => return Ok(()),
+        };
+        check_file(raw, &expected, SNAPSHOT)?;
+        let new = verify_metadata(raw, SNAPSHOT, root, roots, verifiers, now)?;
+        if new.version != expected.version {
+            return Err(invalid(
+                SNAPSHOT,
+                format!(
+                    "Version {} is not version {} listed by the timestamp",
+                    new.version, expected.version
+                ),
+            ));
+        }
+        if let Some(old) = &self.snapshot {
+            for (name, old_file) in &old.meta {
+                match new.meta.get(name) {
+                    Some(file) if file.version < old_file.version => {
+                        return Err(rollback(name, old_file.version, file.version))
+                    }
+                    Some(_) => {}
+                    None => return Err(invalid(SNAPSHOT, format!("{} is no longer listed", name))),
+                }
+            }
+        }
+        new.listed(&file_name(TARGETS))?;
+        self.snapshot = Some(new);
+        Ok(())
+    }
+
+    /// The targets the trusted snapshot lists, if they are not trusted yet.
+    pub fn targets_needed(&self) -> Result<Option<&MetaFile>> {
+        let snapshot = self
+            .snapshot
+            .as_ref()
+            .ok_or_else(|| invalid(TARGETS, "No snapshot is trusted yet"))?;
+        let expected = snapshot.listed(&file_name(TARGETS))?;
+        Ok(match &self.targets {
+            Some(targets) if targets.version == expected.version => None,
+            _ => Some(expected),
+        })
+    }
+
+    /// Verify and trust new targets metadata, which must be the targets the
+    /// trusted snapshot lists.
+    pub fn update_targets(
+        &mut self,
+        raw: &[u8],
+        root: &Policy,
+        roots: &TrustRoots,
+        verifiers: &SignatureVerifiers,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let expected = match self.targets_needed()? {
+            Some(expected) => expected.clone(),
+            None => return Ok(()),
+        };
+        check_file(raw, &expected, TARGETS)?;
+        let new = verify_metadata(raw, TARGETS, root, roots, verifiers, now)?;
+        if new.version != expected.version {
+            return Err(invalid(
+                TARGETS,
+                format!(
+                    "Version {} is not version {} listed by the snapshot",
+                    new.version, expected.version
+                ),
+            ));
+        }
+        self.targets = Some(new);
+        Ok(())
+    }
+
+    /// Fail unless all three roles are trusted and unexpired.
+    pub fn check_current(&self, now: DateTime<Utc>) -> Result<()> {
+        for (role, metadata) in [
+            (TIMESTAMP, &self.timestamp),
+            (SNAPSHOT, &self.snapshot),
+            (TARGETS, &self.targets),
+        ] {
+            metadata
+                .as_ref()
+                .ok_or_else(|| invalid(role, "Not trusted yet"))?
+                .check_expiry(now)?;
+        }
+        Ok(())
+    }
+}
+
+fn rollback(what: &str, trusted: NonZeroU64, new: NonZeroU64) -> SgetError {
+    invalid(
+        what,
+        format!("Version {} is older than trusted version {}", new, trusted),
+    )
+}
+
+/// The file name metadata of `role` is listed under.
+fn file_name(role: &str) -> String {
+    format!("{}.json", role)
+}
+
+/// The file name to fetch version `version` of the metadata of `role` from.
+/// With consistent snapshots, snapshot and targets metadata are published
+/// under version prefixed names so that a refresh never mixes versions.
+#[cfg(feature = "native")]
+fn versioned_file_name(role: &str, version: NonZeroU64, consistent_snapshot: bool) -> String {
+    match consistent_snapshot {
+        true => format!("{}.{}.json", version, role),
+        false => file_name(role),
+    }
+}
+
+/// The versions trusted after a [`refresh`].
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Refreshed {
+    pub timestamp: u64,
+    pub snapshot: u64,
+    pub targets: u64,
+    /// Whether any metadata was newer than what was trusted before.
+    pub updated: bool,
+}
+
+/// Fetch and verify the latest timestamp, snapshot and targets metadata from
+/// the TUF repository at `repository`, in that order, updating `trusted`.
+/// Snapshot and targets metadata are only fetched when the timestamp lists a
+/// version not trusted yet.
+#[cfg(feature = "native")]
+pub async fn refresh(
+    transport: &dyn crate::transport::Transport,
+    repository: &str,
+    root: &Policy,
+    trusted: &mut TrustedMetadata,
+    roots: &TrustRoots,
+    verifiers: &SignatureVerifiers,
+    now: DateTime<Utc>,
+) -> Result<Refreshed> {
+    use crate::fetch::fetch;
+    use std::path::Path;
+
+    let location = |name: &str| format!("{}/{}", repository.trim_end_matches('/'), name);
+    let consistent = root.signed.consistent_snapshot;
+
+    let raw = fetch(transport, &location(&file_name(TIMESTAMP)), Path::new("")).await?;
+    let mut updated = trusted.update_timestamp(&raw, root, roots, verifiers, now)?;
+    if let Some(expected) = trusted.snapshot_needed()? {
+        let name = versioned_file_name(SNAPSHOT, expected.version, consistent);
+        let raw = fetch(transport, &location(&name), Path::new("")).await?;
+        trusted.update_snapshot(&raw, root, roots, verifiers, now)?;
+        updated = true;
+    }
+    if let Some(expected) = trusted.targets_needed()? {
+        let name = versioned_file_name(TARGETS, expected.version, consistent);
+        let raw = fetch(transport, &location(&name), Path::new("")).await?;
+        trusted.update_targets(&raw, root, roots, verifiers, now)?;
+        updated = true;
+    }
+    trusted.check_current(now)?;
+    let version = |metadata: &Option<Metadata>| metadata.as_ref().map_or(0, |m| m.version.get());
+    Ok(Refreshed {
+        timestamp: version(&trusted.timestamp),
+        snapshot: version(&trusted.snapshot),
+        targets: version(&trusted.targets),
+        updated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use serde_json::{json, Value};
+    use sha2::Digest;
+
+    struct Repository {
+        keys: Vec<(String, SigningKey)>,
+        root: Policy,
+    }
+
+    impl Repository {
+        /// A root policy with one key for each of the three roles.
+        fn new() -> Self {
+            let keys: Vec<(String, SigningKey)> = (0..3)
+                .map(|_| {
+                    let key =
+                        SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+                    (key.public_key().key_id().expect("No key id"), key)
+                })
+                .collect();
+            let policy_keys: serde_json::Map<String, Value> = keys
+                .iter()
+                .map(|(id, key)| {
+                    let entry = key.public_key().to_policy_key().expect("Cannot encode key");
+                    (
+                        id.clone(),
+                        serde_json::to_value(entry).expect("Invalid key"),
+                    )
+                })
+                .collect();
+            let role = |index: usize| json!({ "keyids": [keys[index].0], "threshold": 1 });
+            let root = serde_json::from_value(json!({
+                "signatures": [],
+                "signed": {
+                    "consistent_snapshot": false,
+                    "expires": "2030-01-01T00:00:00Z",
+                    "keys": policy_keys,
+                    "namespace": "example.com/scripts",
+                    "roles": {
+                        "root": role(0),
+                        "timestamp": role(0),
+                        "snapshot": role(1),
+                        "targets": role(2),
+                    },
+                    "spec_version": "1.0",
+                    "version": 1,
+                },
+            }))
+            .expect("Invalid policy");
+            Repository { keys, root }
+        }
+
+        fn sign(&self, role: &str, signed: Value) -> Vec<u8> {
+            let index = [TIMESTAMP, SNAPSHOT, TARGETS]
+                .iter()
+                .position(|r| *r == role)
+                .expect("Unknown role");
+            let (id, key) = &self.keys[index];
+            let signed = signed.to_string();
+            format!(
+                "{{\"signatures\":[{}],\"signed\":{}}}",
+                json!({
+                    "keyid": id,
+                    "sig": base64::encode(key.sign(signed.as_bytes())),
+                    "cert": "",
+                }),
+                signed
+            )
+            .into_bytes()
+        }
+
+        fn targets(&self, version: u64) -> Vec<u8> {
+            self.sign(
+                TARGETS,
+                json!({
+                    "_type": TARGETS,
+                    "expires": "2030-01-01T00:00:00Z",
+                    "version": version,
+                    "targets": { "install.sh": { "length": 3, "hashes": { "sha256": "00" } } },
+                }),
+            )
+        }
+
+        fn snapshot(&self, version: u64, targets: &[u8], targets_version: u64) -> Vec<u8> {
+            self.sign(
+                SNAPSHOT,
+                json!({
+                    "_type": SNAPSHOT,
+                    "expires": "2030-01-01T00:00:00Z",
+                    "version": version,
+                    "meta": { "targets.json": {
+                        "version": targets_version,
+                        "length": targets.len(),
+                        "hashes": { "sha256": hex::encode(sha2::Sha256::digest(targets)) },
+                    } },
+                }),
+            )
+        }
+
+        fn timestamp(&self, version: u64, snapshot: &[u8], snapshot_version: u64) -> Vec<u8> {
+            self.sign(
+                TIMESTAMP,
+                json!({
+                    "_type": TIMESTAMP,
+                    "expires": "2030-01-01T00:00:00Z",
+                    "version": version,
+                    "meta": { "snapshot.json": {
+                        "version": snapshot_version,
+                        "hashes": { "sha512": hex::encode(sha2::Sha512::digest(snapshot)) },
+                    } },
+                }),
+            )
+        }
+    }
+
+    #[test]
+    fn update_in_tuf_order() {
+        let repo = Repository::new();
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let verifiers = SignatureVerifiers::default();
+        let now = "2025-01-01T00:00:00Z".parse().expect("Invalid date");
+        let mut trusted = TrustedMetadata::default();
+
+        let targets = repo.targets(1);
+        let snapshot = repo.snapshot(1, &targets, 1);
+        let timestamp = repo.timestamp(1, &snapshot, 1);
+
+        // The snapshot cannot be trusted before the timestamp listing it.
+        assert!(trusted
+            .update_snapshot(&snapshot, &repo.root, &roots, &verifiers, now)
+            .is_err());
+        assert!(trusted
+            .update_timestamp(&timestamp, &repo.root, &roots, &verifiers, now)
+            .expect("Cannot update timestamp"));
+        // Only the snapshot the timestamp lists is accepted.
+        assert!(matches!(
+            trusted.update_snapshot(&targets, &repo.root, &roots, &verifiers, now),
+            Err(SgetError::DigestMismatch { .. })
+        ));
+        trusted
+            .update_snapshot(&snapshot, &repo.root, &roots, &verifiers, now)
+            .expect("Cannot update snapshot");
+        trusted
+            .update_targets(&targets, &repo.root, &roots, &verifiers, now)
+            .expect("Cannot update targets");
+        trusted.check_current(now).expect("Metadata is not current");
+        assert!(trusted
+            .targets
+            .as_ref()
+            .is_some_and(|targets| targets.targets.contains_key("install.sh")));
+
+        // The same timestamp again changes nothing.
+        assert!(!trusted
+            .update_timestamp(&timestamp, &repo.root, &roots, &verifiers, now)
+            .expect("Cannot update timestamp"));
+
+        // A newer timestamp listing an older snapshot is a rollback.
+        let old = repo.timestamp(2, &snapshot, 1);
+        trusted
+            .timestamp
+            .as_mut()
+            .expect("No timestamp")
+            .meta
+            .insert(
+                file_name(SNAPSHOT),
+                MetaFile {
+                    version: NonZeroU64::new(2).expect("Zero version"),
+                    length: None,
+                    hashes: BTreeMap::new(),
+                },
+            );
+        assert!(matches!(
+            trusted.update_timestamp(&old, &repo.root, &roots, &verifiers, now),
+            Err(SgetError::InvalidMetadata { .. })
+        ));
+
+        // Metadata signed by a key of another role does not meet the threshold.
+        let forged = repo.sign(
+            SNAPSHOT,
+            json!({
+                "_type": TIMESTAMP,
+                "expires": "2030-01-01T00:00:00Z",
+                "version": 3,
+                "meta": { "snapshot.json": { "version": 3 } },
+            }),
+        );
+        assert!(trusted
+            .update_timestamp(&forged, &repo.root, &roots, &verifiers, now)
+            .is_err());
+
+        let expired = "2031-01-01T00:00:00Z".parse().expect("Invalid date");
+        assert!(trusted.check_current(expired).is_err());
+
+        // A role expires on its own, before the root policy.
+        let mut repo = Repository::new();
+        if let Some(role) = repo.root.signed.roles.get_mut(TIMESTAMP) {
+            role.expires = Some("2024-12-31T00:00:00Z".parse().expect("Invalid date"));
+        }
+        let targets = repo.targets(1);
+        let snapshot = repo.snapshot(1, &targets, 1);
+        let timestamp = repo.timestamp(1, &snapshot, 1);
+        assert!(matches!(
+            TrustedMetadata::default()
+                .update_timestamp(&timestamp, &repo.root, &roots, &verifiers, now),
+            Err(SgetError::InvalidMetadata { .. })
+        ));
+    }
+}
@@ -1,13 +1,37 @@
-use std::io::Error;
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Utc};
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader, Error};
+use std::path::Path;
 use std::process::{Command, ExitStatus, Stdio};
+use tokio_util::sync::CancellationToken;
+
+use crate::algorithms::Hasher;
+use crate::error::SgetError;
 
 pub(crate) fn run_script(path: &str, interactive: bool) -> Result<ExitStatus, Error> {
     // TODO: we can feed in args for the script by using the following
     // command.arg("some-flag");
+    run_command(Command::new(path), interactive)
+}
+
+/// Run the script at `path` with `dir` as its working directory.
+pub(crate) fn run_script_in(
+    path: &Path,
+    dir: &Path,
+    interactive: bool,
+) -> Result<ExitStatus, Error> {
+    let mut command = Command::new(path);
+    command.current_dir(dir);
+    run_command(command, interactive)
+}
+
+fn run_command(mut command: Command, interactive: bool) -> Result<ExitStatus, Error> {
     let mut childproc = if interactive {
-        Command::new(path).spawn()?
+        command.spawn()?
     } else {
-        Command::new(path)
+        command
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -18,6 +42,77 @@ pub(crate) fn run_script(path: &str, interactive: bool) -> Result<ExitStatus, Er
     childproc.wait()
 }
 
+/// Size of the chunks `hash_file` reads.
+const HASH_CHUNK_SIZE: usize = 1 << 20;
+
+/// The hex encoded SHA-256 digest of the file at `path`.
+pub(crate) fn sha256_file(path: &Path) -> Result<String, Error> {
+    hash_file(path, Hasher::default())
+}
+
+/// The hex encoded digest of the file at `path` with `hasher`, streamed
+/// through a fixed size buffer so that multi-gigabyte artifacts are never
+/// held in memory at once.
+pub(crate) fn hash_file(path: &Path, mut hasher: Hasher) -> Result<String, Error> {
+    let file = File::open(path)?;
+    io::copy(
+        &mut BufReader::with_capacity(HASH_CHUNK_SIZE, file),
+        &mut hasher,
+    )?;
+    Ok(hasher.finalize_hex())
+}
+
+/// Run `future` to completion, or drop it once `cancel` is cancelled.
+pub(crate) async fn cancellable<F: Future>(
+    cancel: Option<&CancellationToken>,
+    future: F,
+) -> Result<F::Output, SgetError> {
+    match cancel {
+        Some(cancel) => tokio::select! {
+            biased;
+            _ = cancel.cancelled() => Err(SgetError::Cancelled),
+            output = future => Ok(output),
+        },
+        None => Ok(future.await),
+    }
+}
+
+/// The longest duration `parse_duration` accepts, 100 years, so that adding it
+/// to the current time cannot overflow.
+const MAX_DURATION_DAYS: i64 = 36_500;
+
+/// Parse a duration such as `500ms`, `90s`, `15m`, `1h` or `7d`, of up to
+/// `MAX_DURATION_DAYS`.
+pub(crate) fn parse_duration(s: &str) -> Result<Duration, SgetError> {
+    let s = s.trim();
+    let invalid = |reason: &str| SgetError::Other(anyhow!("Duration {} {}", s, reason));
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| invalid("has no unit"))?;
+    let (amount, unit) = s.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid("is invalid"))?;
+    let unit_ms: i64 = match unit {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        _ => return Err(invalid("has an unknown unit")),
+    };
+    amount
+        .checked_mul(unit_ms)
+        .filter(|ms| *ms <= MAX_DURATION_DAYS * 86_400_000)
+        .map(Duration::milliseconds)
+        .ok_or_else(|| invalid("is longer than 100 years"))
+}
+
+/// The time `duration`, as accepted by `parse_duration`, from now.
+pub(crate) fn from_now(duration: &str) -> Result<DateTime<Utc>, SgetError> {
+    Utc::now()
+        .checked_add_signed(parse_duration(duration)?)
+        .ok_or_else(|| SgetError::Other(anyhow!("Duration {} is out of range", duration)))
+}
+
 #[test]
 fn execute_script_fail() {
     assert_eq!(
@@ -35,3 +130,48 @@ fn execute_script_success() {
     let res = run_script(&dir.to_string_lossy(), false);
     assert!(res.unwrap().success()); //#[allow_ci]
 }
+
+#[test]
+fn parse_durations() {
+    assert_eq!(
+        parse_duration("90s").expect("Invalid"),
+        Duration::seconds(90)
+    );
+    assert_eq!(parse_duration("1h").expect("Invalid"), Duration::hours(1));
+    assert_eq!(parse_duration("7d").expect("Invalid"), Duration::days(7));
+    assert_eq!(
+        parse_duration("250ms").expect("Invalid"),
+        Duration::milliseconds(250)
+    );
+    assert!(parse_duration("7").is_err());
+    assert!(parse_duration("7w").is_err());
+    assert!(parse_duration("36500d").is_ok());
+    assert!(parse_duration("36501d").is_err());
+    assert!(parse_duration("99999999999d").is_err());
+    assert!(parse_duration("99999999999999999999s").is_err());
+    assert!(from_now("99999999999d").is_err());
+}
+
+#[test]
+fn hash_file_in_chunks() {
+    use sha2::{Digest, Sha256, Sha512};
+
+    let data: Vec<u8> = (0..HASH_CHUNK_SIZE * 2 + 7).map(|i| i as u8).collect();
+    let file = tempfile::NamedTempFile::new().expect("Cannot create file");
+    std::fs::write(file.path(), &data).expect("Cannot write file");
+    assert_eq!(
+        sha256_file(file.path()).expect("Cannot hash file"),
+        hex::encode(Sha256::digest(&data))
+    );
+    assert!(sha256_file(Path::new("i_dont_exist.txt")).is_err());
+    let empty = tempfile::NamedTempFile::new().expect("Cannot create file");
+    assert_eq!(
+        sha256_file(empty.path()).expect("Cannot hash file"),
+        hex::encode(Sha256::digest(b""))
+    );
+    let sha512 = Hasher::new(crate::algorithms::HashAlgorithm::Sha512).expect("No SHA-512");
+    assert_eq!(
+        hash_file(file.path(), sha512).expect("Cannot hash file"),
+        hex::encode(Sha512::digest(&data))
+    );
+}
@@ -0,0 +1,699 @@
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::age::AgeRequirements;
+use crate::audit::{Action, AuditLog, AuditRecord};
+use crate::claims::ClaimRequirements;
+use crate::config::Config;
+use crate::error::{Result, SgetError};
+use crate::evidence;
+use crate::expiry;
+use crate::fetch::{fetch, is_remote, STDIN};
+use crate::hooks::{self, Event};
+use crate::identities::IdentityMap;
+use crate::keys::PublicKey;
+use crate::manifest::{self, ManifestEntry};
+use crate::pgp::PgpRequirements;
+use crate::pipeline::Pipeline;
+use crate::policy::{Policy, PolicyInclude};
+use crate::revocation::RevocationPolicy;
+use crate::sign::{self, SignOptions};
+use crate::signature::SignatureVerifiers;
+use crate::sigstore_env::SigstoreEnv;
+use crate::ssh::SshRequirements;
+use crate::system_policy::SystemPolicy;
+use crate::transport::{self, Transport};
+use crate::trust::{TrustKind, TrustStore};
+
+pub use crate::verify_core::{
+    canonical_payload, certificate_identity, certificate_pem, pem_certificates, verify_blob,
+    verify_bundle, verify_digest, verify_policy, verify_policy_with_parent, BlobSignature, Signer,
+    TrustRoots,
+};
+
+/// Longest chain of included policies [`resolve_policy`] follows.
+const MAX_POLICY_INCLUDES: usize = 8;
+
+// Just the include of a policy, read before it is verified.
+#[derive(Deserialize)]
+struct IncludeView<'a> {
+    #[serde(borrow)]
+    signed: &'a serde_json::value::RawValue,
+}
+
+#[derive(Deserialize)]
+struct SignedInclude {
+    #[serde(default)]
+    include: Option<PolicyInclude>,
+}
+
+fn included(raw: &[u8]) -> Result<Option<PolicyInclude>> {
+    let invalid = |e: serde_json::Error| SgetError::InvalidPolicy(e.to_string());
+    let view: IncludeView = serde_json::from_slice(raw).map_err(invalid)?;
+    let signed: SignedInclude = serde_json::from_str(view.signed.get()).map_err(invalid)?;
+    Ok(signed.include)
+}
+
+/// Verify the policy `raw` fetched from `location`, together with the chain of
+/// parent policies it includes, and return it flattened. Each parent is
+/// fetched relative to the policy including it, must match the digest pinned
+/// there, and is verified in turn, starting from the base policy.
+pub async fn resolve_policy(
+    transport: &dyn Transport,
+    raw: Vec<u8>,
+    location: &str,
+    roots: &TrustRoots,
+    verifiers: &SignatureVerifiers,
+    pipeline: &Pipeline,
+    now: DateTime<Utc>,
+) -> Result<Policy> {
+    let mut location = location.to_string();
+    let mut include = included(&raw)?;
+    let mut chain = vec![raw];
+    while let Some(parent) = include {
+        if chain.len() > MAX_POLICY_INCLUDES {
+            return Err(SgetError::InvalidPolicy(format!(
+                "More than {} policies are included",
+                MAX_POLICY_INCLUDES
+            )));
+        }
+        location = relative_location(&location, &parent.url);
+        let raw = fetch(transport, &location, Path::new("")).await?;
+        let digest = hex::encode(Sha256::digest(&raw));
+        if !digest.eq_ignore_ascii_case(&parent.sha256) {
+            return Err(SgetError::InvalidPolicy(format!(
+                "Included policy {} has digest {}, not the pinned {}",
+                location, digest, parent.sha256
+            )));
+        }
+        include = included(&raw)?;
+        chain.push(raw);
+    }
+    let mut policy = None;
+    for raw in chain.iter().rev() {
+        policy = Some(verify_policy_with_parent(
+            raw, policy, roots, verifiers, pipeline, now,
+        )?);
+    }
+    policy.ok_or_else(|| SgetError::InvalidPolicy("No policy".to_string()))
+}
+
+/// The flattened parent of the policy `raw` fetched from `location`, if it
+/// includes one, resolved like [`resolve_policy`] resolves the whole chain.
+pub(crate) async fn resolve_parent(
+    transport: &dyn Transport,
+    raw: &[u8],
+    location: &str,
+    roots: &TrustRoots,
+    now: DateTime<Utc>,
+) -> Result<Option<Policy>> {
+    let parent = match included(raw)? {
+        Some(parent) => parent,
+        None => return Ok(None),
+    };
+    let location = relative_location(location, &parent.url);
+    let raw = fetch(transport, &location, Path::new("")).await?;
+    let digest = hex::encode(Sha256::digest(&raw));
+    if !digest.eq_ignore_ascii_case(&parent.sha256) {
+        return Err(SgetError::InvalidPolicy(format!(
+            "Included policy {} has digest {}, not the pinned {}",
+            location, digest, parent.sha256
+        )));
+    }
+    let policy = resolve_policy(
+        transport,
+        raw,
+        &location,
+        roots,
+        &SignatureVerifiers::default(),
+        &Pipeline::default(),
+        now,
+    )
+    .await?;
+    Ok(Some(policy))
+}
+
+/// `location` resolved against the location `base` of the document naming it.
+fn relative_location(base: &str, location: &str) -> String {
+    if is_remote(location) || Path::new(location).is_absolute() {
+        return location.to_string();
+    }
+    match base.rfind('/') {
+        Some(end) => format!("{}/{}", &base[..end], location),
+        None => location.to_string(),
+    }
+}
+
+impl TrustRoots {
+    /// The roots in the local trust store. The [built-in](TrustRoots::builtin)
+    /// roots are used for whichever of the Fulcio roots or Rekor keys the trust
+    /// store has none of; timestamp authorities are only trusted when added to
+    /// it. The algorithm policy is that of the user
+    /// configuration, the system policy is read from [`SystemPolicy::dir`], and
+    /// the identity map from `identities.yaml` next to the configuration.
+    /// The staging environment has no built-in roots, so its trust store must
+    /// hold some.
+    pub fn load(store: &TrustStore) -> Result<Self> {
+        let mut roots = Self::builtin()?;
+        roots.algorithms = Config::load()?.algorithms;
+        roots.system = SystemPolicy::load()?;
+        roots.identities = IdentityMap::load()?;
+        let fulcio = store.list(TrustKind::FulcioRoot)?;
+        if !fulcio.is_empty() {
+            roots.fulcio_roots.clear();
+            for entry in fulcio {
+                roots.fulcio_roots.extend(pem_certificates(
+                    &store.read(TrustKind::FulcioRoot, &entry.name)?,
+                )?);
+            }
+        }
+        let rekor = store.list(TrustKind::RekorKey)?;
+        if !rekor.is_empty() {
+            roots.rekor_keys = rekor
+                .iter()
+                .map(|entry| {
+                    let pem = store.read(TrustKind::RekorKey, &entry.name)?;
+                    Ok(PublicKey::from_pem(&String::from_utf8_lossy(&pem))?)
+                })
+                .collect::<Result<_>>()?;
+        }
+        for entry in store.list(TrustKind::TimestampAuthority)? {
+            roots.timestamp_authorities.extend(pem_certificates(
+                &store.read(TrustKind::TimestampAuthority, &entry.name)?,
+            )?);
+        }
+        if roots.fulcio_roots.is_empty() && roots.rekor_keys.is_empty() {
+            return Err(anyhow!(
+                "No {} trust roots; add them with `sget --sigstore-env {} trust add`",
+                SigstoreEnv::current(),
+                SigstoreEnv::current()
+            )
+            .into());
+        }
+        Ok(roots)
+    }
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("verify")
+        .about("Verify signed artifacts")
+        .arg(
+            Arg::new("manifest")
+                .short('m')
+                .long("manifest")
+                .value_name("MANIFEST")
+                .takes_value(true)
+                .required(true)
+                .about("YAML manifest listing the artifacts to verify"),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .takes_value(true)
+                .default_value("8")
+                .about("How many artifacts to fetch and verify at once"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .takes_value(false)
+                .about("Print the results as JSON, including each entry's custom metadata"),
+        )
+        .arg(
+            Arg::new("export-evidence")
+                .long("export-evidence")
+                .value_name("FILE")
+                .takes_value(true)
+                .about("Write a .tgz bundle of everything needed to re-verify offline"),
+        )
+        .arg(
+            Arg::new("sign-evidence")
+                .long("sign-evidence")
+                .takes_value(false)
+                .requires("export-evidence")
+                .about("Sign the evidence bundle, keyless unless --signing-key is given"),
+        )
+        .arg(
+            Arg::new("signing-key")
+                .long("signing-key")
+                .value_name("KEY_FILE")
+                .takes_value(true)
+                .requires("sign-evidence")
+                .about("Private key generated by `sget keygen`, or keychain:NAME, to sign the evidence with"),
+        )
+        .arg(
+            Arg::new("identity-token")
+                .long("identity-token")
+                .value_name("TOKEN")
+                .takes_value(true)
+                .requires("sign-evidence")
+                .about("OIDC identity token for keyless signing [env: SIGSTORE_ID_TOKEN; in GitHub Actions, the job's own token]"),
+        )
+}
+
+pub(crate) fn blob_command() -> App<'static> {
+    App::new("verify-blob")
+        .about("Verify a blob signed with `sget sign`, `cosign sign-blob`, `gpg --detach-sign` or `ssh-keygen -Y sign`")
+        .arg(
+            Arg::new("file")
+                .value_name("FILE")
+                .required(true)
+                .about("URL or path of the blob to verify, or - to read it from standard input"),
+        )
+        .arg(
+            Arg::new("digest")
+                .long("digest")
+                .value_name("DIGEST")
+                .takes_value(true)
+                .about("Also require this digest of the blob: hex SHA-256, sha384:HEX, sha512:HEX or blake3:HEX"),
+        )
+        .arg(
+            Arg::new("signature")
+                .long("signature")
+                .value_name("SIGNATURE")
+                .takes_value(true)
+                .about("Base64 encoded signature [default: FILE.sig, or from the bundle]"),
+        )
+        .arg(
+            Arg::new("certificate")
+                .long("certificate")
+                .alias("cert")
+                .value_name("CERTIFICATE")
+                .takes_value(true)
+                .about(
+                    "PEM or base64 encoded PEM certificate [default: FILE.pem, or from the bundle]",
+                ),
+        )
+        .arg(
+            Arg::new("bundle")
+                .long("bundle")
+                .value_name("BUNDLE")
+                .takes_value(true)
+                .about("Rekor, cosign or sigstore bundle [default: FILE.bundle for keyless signatures]"),
+        )
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .value_name("KEY")
+                .takes_value(true)
+                .conflicts_with("certificate")
+                .about("PEM public key the blob was signed with"),
+        )
+        .arg(
+            Arg::new("certificate-identity")
+                .long("certificate-identity")
+                .value_name("IDENTITY")
+                .takes_value(true)
+                .conflicts_with("key")
+                .about("Required identity of the signing certificate"),
+        )
+        .arg(
+            Arg::new("certificate-oidc-issuer")
+                .long("certificate-oidc-issuer")
+                .value_name("ISSUER")
+                .takes_value(true)
+                .requires("certificate-identity")
+                .about("Required OIDC issuer of the signing certificate"),
+        )
+        .arg(
+            Arg::new("keyring")
+                .long("keyring")
+                .value_name("KEYRING")
+                .takes_value(true)
+                .conflicts_with_all(&["key", "certificate", "bundle", "certificate-identity"])
+                .about("OpenPGP public keys to verify an armored signature with [default signature: FILE.asc]"),
+        )
+        .arg(
+            Arg::new("fingerprint")
+                .long("fingerprint")
+                .value_name("FINGERPRINT")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .requires("keyring")
+                .about("Fingerprint of an OpenPGP key allowed to sign the blob"),
+        )
+        .arg(
+            Arg::new("allowed-signers")
+                .long("allowed-signers")
+                .value_name("FILE")
+                .takes_value(true)
+                .conflicts_with_all(&["key", "certificate", "bundle", "certificate-identity", "keyring"])
+                .about("SSH allowed_signers file to verify an SSH signature with"),
+        )
+        .arg(
+            Arg::new("principal")
+                .long("principal")
+                .value_name("PRINCIPAL")
+                .takes_value(true)
+                .requires("allowed-signers")
+                .about("Required principal of the SSH signer [default: any allowed signer]"),
+        )
+        .arg(
+            Arg::new("namespace")
+                .long("namespace")
+                .value_name("NAMESPACE")
+                .takes_value(true)
+                .requires("allowed-signers")
+                .about("Namespace of the SSH signature [default: file]"),
+        )
+        .arg(
+            Arg::new("revocation")
+                .long("revocation")
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(["hard-fail", "soft-fail"])
+                .conflicts_with_all(&["key", "keyring", "allowed-signers"])
+                .about("Check the signing certificate against its CRLs and OCSP responders, failing when its status is unknown in hard-fail mode"),
+        )
+        .arg(
+            Arg::new("claim-policy")
+                .long("claim-policy")
+                .value_name("POLICY")
+                .takes_value(true)
+                .conflicts_with_all(&["keyring", "allowed-signers"])
+                .about("Rego (.rego) or CUE (.cue) policy the certificate claims, Rekor entry and blob must satisfy; needs opa or cue"),
+        )
+        .arg(
+            Arg::new("claim-query")
+                .long("claim-query")
+                .value_name("QUERY")
+                .takes_value(true)
+                .requires("claim-policy")
+                .about("Rego query that must be true [default: data.sget.allow]"),
+        )
+        .arg(
+            Arg::new("age-identity")
+                .long("age-identity")
+                .value_name("FILE")
+                .takes_value(true)
+                .about("The blob is encrypted with age: decrypt it with this identity file once it verifies"),
+        )
+        .arg(
+            Arg::new("age-plaintext-sha256")
+                .long("age-plaintext-sha256")
+                .value_name("DIGEST")
+                .takes_value(true)
+                .requires("age-identity")
+                .about("The blob was signed before it was encrypted: decrypt it first and require this SHA-256 of the plaintext"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .value_name("FILE")
+                .takes_value(true)
+                .about("Write the verified blob, decrypted if it is encrypted, to FILE"),
+        )
+        .arg(
+            Arg::new("stdout")
+                .long("stdout")
+                .takes_value(false)
+                .conflicts_with("output")
+                .about("Write the verified blob, decrypted if it is encrypted, to standard output only once every check has passed, e.g. to pipe it to sh"),
+        )
+}
+
+pub(crate) async fn run_blob(matches: &ArgMatches) -> anyhow::Result<()> {
+    let value = |name| matches.value_of(name).map(String::from);
+    let mut entry = ManifestEntry::new(
+        matches
+            .value_of("file")
+            .ok_or_else(|| anyhow!("No file given"))?,
+    );
+    entry.digest = value("digest");
+    entry.signature = value("signature");
+    entry.certificate = value("certificate");
+    entry.bundle = value("bundle");
+    entry.key = value("key");
+    entry.identity = value("certificate-identity");
+    entry.issuer = value("certificate-oidc-issuer");
+    entry.pgp = value("keyring").map(|keyring| PgpRequirements {
+        keyring,
+        fingerprints: matches
+            .values_of("fingerprint")
+            .map(|values| values.map(String::from).collect())
+            .unwrap_or_default(),
+    });
+    entry.ssh = value("allowed-signers").map(|allowed_signers| SshRequirements {
+        allowed_signers,
+        principal: value("principal"),
+        namespace: value("namespace"),
+    });
+    if let Some(mode) = matches.value_of("revocation") {
+        entry.revocation = Some(RevocationPolicy::new(mode.parse()?));
+    }
+    entry.claims = value("claim-policy").map(|policy| ClaimRequirements {
+        policy,
+        query: value("claim-query"),
+    });
+    entry.age = value("age-identity").map(|identity| AgeRequirements {
+        identity: Some(identity),
+        plaintext_sha256: value("age-plaintext-sha256"),
+    });
+    // Signature material defaults to files named after the blob.
+    if entry.url == STDIN && entry.signature.is_none() && entry.bundle.is_none() {
+        return Err(anyhow!(
+            "A blob read from standard input needs --signature or --bundle"
+        ));
+    }
+    let roots = TrustRoots::load(&TrustStore::open()?)?;
+    expiry::report(&expiry::check_roots(
+        &roots,
+        Config::load()?.expiry_window()?,
+        Utc::now(),
+    ))?;
+    let transport = transport::default_transport();
+    let (data, signer) = entry
+        .fetch_verified(
+            transport.as_ref(),
+            Path::new(""),
+            &roots,
+            &Pipeline::default(),
+        )
+        .await
+        .inspect_err(|_| {
+            if entry.pgp.is_none() && entry.ssh.is_none() {
+                eprintln!("Run `sget explain` with the same arguments to see which checks failed");
+            }
+        })?;
+    if matches.is_present("stdout") {
+        // Standard output carries only the blob, for the next command in a
+        // pipeline.
+        eprintln!("Verified OK\t{}", signer.subject());
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(&data)
+            .and_then(|_| stdout.flush())
+            .context("Cannot write the blob to standard output")?;
+        return Ok(());
+    }
+    println!("Verified OK\t{}", signer.subject());
+    if let Some(output) = matches.value_of("output") {
+        fs::write(output, &data).with_context(|| format!("Cannot write {}", output))?;
+    }
+    Ok(())
+}
+
+/// A verification result as printed by `sget verify --json`.
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    url: &'a str,
+    sha256: Option<String>,
+    verified: bool,
+    signer: Option<&'a str>,
+    issuer: Option<&'a str>,
+    integrated_time: Option<i64>,
+    error: Option<String>,
+    custom: Option<&'a serde_json::Value>,
+}
+
+impl<'a> JsonResult<'a> {
+    fn new(result: &'a manifest::EntryResult) -> Self {
+        let signer = result.outcome.as_ref().ok();
+        JsonResult {
+            url: &result.url,
+            sha256: result.digest(),
+            verified: signer.is_some(),
+            signer: signer.map(Signer::subject),
+            issuer: signer.and_then(|signer| signer.issuer.as_deref()),
+            integrated_time: signer.and_then(|signer| signer.integrated_time),
+            error: result.outcome.as_ref().err().map(|e| e.describe()),
+            custom: result.custom.as_ref(),
+        }
+    }
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> anyhow::Result<()> {
+    let path = matches
+        .value_of("manifest")
+        .ok_or_else(|| anyhow!("No manifest given"))?;
+    let roots = TrustRoots::load(&TrustStore::open()?)?;
+    let config = Config::load()?;
+    expiry::report(&expiry::check_roots(
+        &roots,
+        config.expiry_window()?,
+        Utc::now(),
+    ))?;
+    let audit = AuditLog::open(&config)?;
+    let jobs = match matches.value_of("jobs") {
+        Some(jobs) => jobs
+            .parse()
+            .map_err(|_| anyhow!("Invalid number of jobs {}", jobs))?,
+        None => manifest::DEFAULT_CONCURRENCY,
+    };
+    let transport = transport::default_transport();
+    let results = manifest::verify_manifest(
+        transport.as_ref(),
+        Path::new(path),
+        &roots,
+        &Pipeline::default(),
+        jobs,
+    )
+    .await?;
+
+    let json = matches.is_present("json");
+    let mut failed = 0;
+    let mut report = Vec::new();
+    for result in &results {
+        audit.record(&AuditRecord::verification(
+            Action::Verify,
+            &result.url,
+            result.digest(),
+            result.outcome.as_ref(),
+        ))?;
+        match &result.outcome {
+            Ok(signer) if !json => println!("OK\t{}\t{}", result.url, signer.subject()),
+            Ok(_) => {}
+            Err(e) => {
+                failed += 1;
+                if !json {
+                    println!("FAILED\t{}\t{}", result.url, e.describe());
+                }
+                hooks::fire(&config.hooks, &Event::verification_failure(&result.url, e)).await;
+            }
+        }
+        report.push(JsonResult::new(result));
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{} verified, {} failed", results.len() - failed, failed);
+    }
+    if let Some(out) = matches.value_of("export-evidence") {
+        evidence::export(Path::new(out), Path::new(path), &results, &roots)?;
+        println!("Evidence written to {}", out);
+        if matches.is_present("sign-evidence") {
+            let options = SignOptions {
+                key: matches.value_of("signing-key").map(String::from),
+                identity_token: matches.value_of("identity-token").map(String::from),
+                ..SignOptions::default()
+            };
+            sign::sign_file(out, &options).await?;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {} artifacts failed verification",
+            failed,
+            results.len()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use serde_json::json;
+
+    fn sign_policy(key: &SigningKey, signed: serde_json::Value) -> Vec<u8> {
+        let signed = signed.to_string();
+        let signatures = json!([{
+            "keyid": key.public_key().key_id().expect("No key id"),
+            "sig": base64::encode(key.sign(signed.as_bytes())),
+            "cert": "",
+        }]);
+        format!("{{\"signatures\":{},\"signed\":{}}}", signatures, signed).into_bytes()
+    }
+
+    #[tokio::test]
+    async fn resolve_included_policy() {
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let key = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let id = key.public_key().key_id().expect("No key id");
+        let base = sign_policy(
+            &key,
+            json!({
+                "consistent_snapshot": false,
+                "expires": "2100-01-01T00:00:00Z",
+                "keys": { &id: key.public_key().to_policy_key().expect("Cannot encode key") },
+                "namespace": "example.com",
+                "roles": { "root": { "keyids": [&id], "threshold": 1 } },
+                "spec_version": "1.0",
+                "version": 1,
+                "targets": [{ "path": "**", "keyids": [&id] }],
+            }),
+        );
+        std::fs::write(dir.path().join("base.json"), &base).expect("Cannot write policy");
+        // The project policy only sets its namespace, and is signed with the
+        // inherited root key.
+        let project = |sha256: String| {
+            sign_policy(
+                &key,
+                json!({
+                    "consistent_snapshot": false,
+                    "expires": "2100-01-01T00:00:00Z",
+                    "namespace": "example.com/web",
+                    "spec_version": "1.0",
+                    "version": 1,
+                    "include": { "url": "base.json", "sha256": sha256 },
+                }),
+            )
+        };
+        let location = dir.path().join("web.json").display().to_string();
+        let resolve = |raw: Vec<u8>| {
+            let location = location.clone();
+            async move {
+                resolve_policy(
+                    transport::default_transport().as_ref(),
+                    raw,
+                    &location,
+                    &TrustRoots::sigstore().expect("Cannot load roots"),
+                    &SignatureVerifiers::default(),
+                    &Pipeline::default(),
+                    Utc::now(),
+                )
+                .await
+            }
+        };
+
+        let web = project(hex::encode(Sha256::digest(&base)));
+        let policy = resolve(web.clone()).await.expect("Cannot resolve policy");
+        assert_eq!(policy.signed.namespace, "example.com/web");
+        assert!(policy.signed.keys.contains_key(&id));
+        assert_eq!(policy.signed.roles["root"].keyids, vec![id.clone()]);
+        assert_eq!(policy.signed.targets.len(), 1);
+
+        assert!(matches!(
+            resolve(project("00".repeat(32))).await,
+            Err(SgetError::InvalidPolicy(_))
+        ));
+        assert!(verify_policy(
+            &web,
+            &TrustRoots::sigstore().expect("Cannot load roots"),
+            &SignatureVerifiers::default(),
+            &Pipeline::default(),
+            Utc::now(),
+        )
+        .is_err());
+    }
+}
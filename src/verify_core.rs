@@ -0,0 +1,1150 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::parse_x509_certificate;
+use x509_parser::pem::Pem;
+
+use crate::algorithms::{AlgorithmPolicy, ArtifactDigest};
+use crate::error::{Result, SgetError};
+use crate::identities::IdentityMap;
+use crate::keys::PublicKey;
+use crate::pipeline::{Pipeline, Stage, StageContext};
+use crate::policy::{self, Key, Policy, RawPolicy, RawSigned, SigstoreOidcKey, SpecVersion};
+use crate::policy_set;
+use crate::rekor::Bundle;
+use crate::roots;
+use crate::signature::SignatureVerifiers;
+use crate::sigstore_env::SigstoreEnv;
+use crate::system_policy::SystemPolicy;
+
+/// Fulcio certificate extension holding the OIDC issuer of the signer.
+const OID_FULCIO_ISSUER: &str = "1.3.6.1.4.1.57264.1.1";
+
+/// Certificate chains longer than this are rejected.
+const MAX_CHAIN_DEPTH: usize = 5;
+
+/// The Fulcio roots and Rekor keys signatures are verified against, the
+/// algorithms they may be made with, and the system policy, identity map and
+/// root policy signers must meet.
+pub struct TrustRoots {
+    /// DER encoded Fulcio root certificates.
+    pub fulcio_roots: Vec<Vec<u8>>,
+    pub rekor_keys: Vec<PublicKey>,
+    /// DER encoded certificates of the timestamp authorities whose RFC 3161
+    /// time-stamp tokens are trusted, or of CAs that issue them.
+    pub timestamp_authorities: Vec<Vec<u8>>,
+    pub algorithms: AlgorithmPolicy,
+    pub system: SystemPolicy,
+    pub identities: IdentityMap,
+    /// A verified root policy whose root keys every signer must be.
+    pub policy: Option<Policy>,
+}
+
+impl TrustRoots {
+    /// The roots of the public sigstore instance.
+    pub fn sigstore() -> Result<Self> {
+        Ok(Self {
+            fulcio_roots: vec![
+                pem_certificates(roots::FULCIO_ROOT_V0.as_bytes())?.remove(0),
+                pem_certificates(roots::FULCIO_ROOT_V1.as_bytes())?.remove(0),
+            ],
+            rekor_keys: vec![PublicKey::from_pem(roots::REKOR_KEY)?],
+            timestamp_authorities: Vec::new(),
+            algorithms: AlgorithmPolicy::default(),
+            system: SystemPolicy::default(),
+            identities: IdentityMap::default(),
+            policy: None,
+        })
+    }
+
+    /// The roots built into sget: the public sigstore roots, replaced by any
+    /// Fulcio roots, Rekor keys and root policy embedded with the
+    /// `embedded-roots` feature. An embedded policy must verify against the
+    /// roots and not have expired.
+    ///
+    /// No roots are built in for the staging environment.
+    pub fn builtin() -> Result<Self> {
+        if SigstoreEnv::current() == SigstoreEnv::Staging {
+            return Ok(Self {
+                fulcio_roots: Vec::new(),
+                rekor_keys: Vec::new(),
+                ..Self::sigstore()?
+            });
+        }
+        let mut roots = Self::sigstore()?;
+        if !roots::EMBEDDED_FULCIO_ROOTS.is_empty() {
+            roots.fulcio_roots = pem_certificates(roots::EMBEDDED_FULCIO_ROOTS)?;
+        }
+        if !roots::EMBEDDED_REKOR_KEYS.is_empty() {
+            roots.rekor_keys = pem_public_keys(roots::EMBEDDED_REKOR_KEYS)?;
+        }
+        if !roots::EMBEDDED_POLICY.is_empty() {
+            roots.policy = Some(verify_policy(
+                roots::EMBEDDED_POLICY,
+                &roots,
+                &SignatureVerifiers::default(),
+                &Pipeline::default(),
+                Utc::now(),
+            )?);
+        }
+        Ok(roots)
+    }
+
+    /// Check a verified signer against the system policy and the root policy.
+    /// Without a location, the identity map does not apply.
+    pub fn check_signer(&self, signer: &Signer) -> Result<()> {
+        self.check_signer_for(signer, None)
+    }
+
+    /// Like [`TrustRoots::check_signer`], for the artifact at `location`,
+    /// whose signer must also meet the identity map and the root policy's
+    /// target rules for it.
+    pub fn check_signer_for(&self, signer: &Signer, location: Option<&str>) -> Result<()> {
+        self.system.check(signer)?;
+        self.identities.check(signer, location)?;
+        if let Some(policy) = &self.policy {
+            policy_set::check_namespace(policy, location)?;
+            if !policy_set::policy_trusts(policy, location, signer) {
+                return Err(SgetError::UntrustedSigner(format!(
+                    "{} is not a key of the {} policy{}",
+                    signer.subject(),
+                    policy.signed.namespace,
+                    location.map(|l| format!(" for {}", l)).unwrap_or_default()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A detached signature over a blob together with the material that verifies it.
+pub struct BlobSignature {
+    /// The raw signature bytes (DER for ECDSA).
+    pub signature: Vec<u8>,
+    /// The PEM encoded signing certificate, followed by any intermediates.
+    pub certificate: Option<String>,
+    /// The public key for signatures made with a key from `sget keygen`.
+    pub public_key: Option<PublicKey>,
+    /// Proof of inclusion in the Rekor transparency log.
+    pub bundle: Option<Bundle>,
+}
+
+/// The signer of a successfully verified signature.
+#[derive(Debug, PartialEq)]
+pub struct Signer {
+    /// The key ID of the key that made the signature.
+    pub key_id: String,
+    /// The identity (email or URI) bound to the certificate, for keyless signatures.
+    pub identity: Option<String>,
+    /// The OIDC issuer of the identity, for keyless signatures.
+    pub issuer: Option<String>,
+    /// When the signature was entered into the transparency log, if known.
+    pub integrated_time: Option<i64>,
+}
+
+impl Signer {
+    /// The identity for keyless signatures, otherwise the key ID.
+    pub fn subject(&self) -> &str {
+        self.identity.as_deref().unwrap_or(&self.key_id)
+    }
+}
+
+/// Verify a detached signature over `data`.
+///
+/// Keyless signatures must chain up to one of the Fulcio roots and carry a Rekor
+/// bundle proving the signature was made while the certificate was valid. When
+/// `expected` is given, the certificate identity and issuer must match it; an empty
+/// expected issuer matches any issuer. Chain validation and the Rekor bundle check
+/// run between the hooks of `pipeline`.
+pub fn verify_blob(
+    data: &[u8],
+    sig: &BlobSignature,
+    roots: &TrustRoots,
+    expected: Option<&SigstoreOidcKey>,
+    pipeline: &Pipeline,
+) -> Result<Signer> {
+    let digest = hex::encode(Sha256::digest(data));
+    let mut signer = match (&sig.certificate, &sig.public_key) {
+        (Some(chain), _) => {
+            let chain = pem_certificates(chain.as_bytes())?;
+            let leaf = parse_certificate(&chain[0])?;
+            let identity = certificate_identity(&leaf).ok();
+            let context = StageContext {
+                stage: Stage::ChainValidation,
+                digest: &digest,
+                identity: identity.as_ref().map(|(identity, _)| identity.as_str()),
+            };
+            pipeline.run(context, || {
+                verify_chain(&leaf, &chain[1..], &roots.fulcio_roots, &roots.algorithms)
+            })?;
+            let key =
+                PublicKey::from_der(leaf.public_key().raw).map_err(SgetError::InvalidMaterial)?;
+            roots.algorithms.check_key(&key)?;
+            key.verify(data, &sig.signature)
+                .map_err(SgetError::InvalidSignature)?;
+
+            let bundle = sig.bundle.as_ref().ok_or_else(|| {
+                SgetError::TransparencyLogError(
+                    "Certificate signatures require a Rekor bundle".to_string(),
+                )
+            })?;
+            let validity = leaf.validity();
+            let time = bundle.payload.integrated_time;
+            if time < validity.not_before.timestamp() || time > validity.not_after.timestamp() {
+                return Err(SgetError::UntrustedCertificate(
+                    "Signature was logged outside the certificate's validity period".to_string(),
+                ));
+            }
+            let (identity, issuer) = certificate_identity(&leaf)?;
+            Signer {
+                key_id: key.key_id()?,
+                identity: Some(identity),
+                issuer,
+                integrated_time: None,
+            }
+        }
+        (None, Some(key)) => {
+            roots.algorithms.check_key(key)?;
+            key.verify(data, &sig.signature)
+                .map_err(SgetError::InvalidSignature)?;
+            Signer {
+                key_id: key.key_id()?,
+                identity: None,
+                issuer: None,
+                integrated_time: None,
+            }
+        }
+        (None, None) => {
+            return Err(SgetError::InvalidMaterial(anyhow!(
+                "No certificate or public key to verify with"
+            )))
+        }
+    };
+
+    if let Some(bundle) = &sig.bundle {
+        let context = StageContext {
+            stage: Stage::LogProof,
+            digest: &digest,
+            identity: signer.identity.as_deref(),
+        };
+        pipeline.run(context, || {
+            verify_bundle(bundle, data, &sig.signature, &roots.rekor_keys)
+        })?;
+        signer.integrated_time = Some(bundle.payload.integrated_time);
+    }
+
+    if let Some(expected) = expected {
+        if signer.identity.as_deref() != Some(expected.identity.as_str()) {
+            return Err(SgetError::IdentityMismatch {
+                expected: expected.identity.clone(),
+                actual: signer
+                    .identity
+                    .unwrap_or_else(|| "a key without identity".to_string()),
+            });
+        }
+        if !expected.issuer.is_empty() && signer.issuer.as_deref() != Some(&expected.issuer) {
+            return Err(SgetError::IdentityMismatch {
+                expected: format!("{} issued by {}", expected.identity, expected.issuer),
+                actual: format!(
+                    "{} issued by {}",
+                    expected.identity,
+                    signer.issuer.as_deref().unwrap_or("an unknown issuer")
+                ),
+            });
+        }
+    }
+    Ok(signer)
+}
+
+/// Check that `data` has the digest `expected`, an [`ArtifactDigest`] such as
+/// hex encoded SHA-256 or `sha512:<hex>`, as the digest check stage of
+/// `pipeline`.
+pub fn verify_digest(data: &[u8], expected: &str, pipeline: &Pipeline) -> Result<()> {
+    let expected: ArtifactDigest = expected.parse()?;
+    let actual = expected.of(data)?;
+    let context = StageContext {
+        stage: Stage::DigestCheck,
+        digest: &actual.hex,
+        identity: None,
+    };
+    pipeline.run(context, || {
+        if actual != expected {
+            return Err(SgetError::DigestMismatch {
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+        Ok(())
+    })
+}
+
+/// PEM encode a DER certificate.
+pub fn certificate_pem(der: &[u8]) -> String {
+    let encoded = base64::encode(der);
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem
+}
+
+/// Decode every certificate in a PEM document.
+pub fn pem_certificates(pem: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let certs = Pem::iter_from_buffer(pem)
+        .map(|pem| {
+            pem.map(|pem| pem.contents).map_err(|e| {
+                SgetError::InvalidMaterial(anyhow!("Error parsing PEM certificate: {:?}", e))
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(SgetError::InvalidMaterial(anyhow!("No certificate found")));
+    }
+    Ok(certs)
+}
+
+/// The public keys in a PEM buffer.
+fn pem_public_keys(pem: &[u8]) -> Result<Vec<PublicKey>> {
+    Pem::iter_from_buffer(pem)
+        .map(|pem| {
+            pem.map_err(|e| anyhow!("Error parsing PEM public key: {:?}", e))
+                .and_then(|pem| PublicKey::from_der(&pem.contents))
+                .map_err(SgetError::InvalidMaterial)
+        })
+        .collect()
+}
+
+pub(crate) fn parse_certificate(der: &[u8]) -> Result<X509Certificate<'_>> {
+    parse_x509_certificate(der)
+        .map(|(_, cert)| cert)
+        .map_err(|e| SgetError::InvalidMaterial(anyhow!("Error parsing certificate: {:?}", e)))
+}
+
+/// Check that `leaf` chains up to one of `roots`, possibly via `intermediates`,
+/// with signatures `algorithms` allows.
+pub(crate) fn verify_chain(
+    leaf: &X509Certificate,
+    intermediates: &[Vec<u8>],
+    roots: &[Vec<u8>],
+    algorithms: &AlgorithmPolicy,
+) -> Result<()> {
+    let roots = roots
+        .iter()
+        .map(|der| parse_certificate(der))
+        .collect::<Result<Vec<_>>>()?;
+    let intermediates = intermediates
+        .iter()
+        .map(|der| parse_certificate(der))
+        .collect::<Result<Vec<_>>>()?;
+
+    let signed_by = |cert: &X509Certificate, issuer: &X509Certificate| {
+        cert.issuer() == issuer.subject()
+            && cert.verify_signature(Some(issuer.public_key())).is_ok()
+    };
+    let mut current = leaf;
+    for _ in 0..MAX_CHAIN_DEPTH {
+        if let Some(root) = roots.iter().find(|root| signed_by(current, root)) {
+            return algorithms.check_certificate(current, root.public_key());
+        }
+        let issuer = intermediates
+            .iter()
+            .find(|cert| cert.tbs_certificate.is_ca() && signed_by(current, cert))
+            .ok_or_else(|| {
+                SgetError::UntrustedCertificate(
+                    "Certificate does not chain to a trusted root".to_string(),
+                )
+            })?;
+        algorithms.check_certificate(current, issuer.public_key())?;
+        current = issuer;
+    }
+    Err(SgetError::UntrustedCertificate(
+        "Certificate chain is too long".to_string(),
+    ))
+}
+
+/// Verify `signature` over `msg` by the leaf of the PEM encoded certificate
+/// chain `pem`, which must chain to a Fulcio root, and return the identity and
+/// issuer of the leaf. Without a Rekor bundle there is no trusted signing time,
+/// so the certificate's validity period is not checked.
+pub fn verify_certificate_signature(
+    pem: &[u8],
+    msg: &[u8],
+    signature: &[u8],
+    roots: &TrustRoots,
+) -> Result<(String, Option<String>)> {
+    let chain = pem_certificates(pem)?;
+    let leaf = parse_certificate(&chain[0])?;
+    verify_chain(&leaf, &chain[1..], &roots.fulcio_roots, &roots.algorithms)?;
+    let key = PublicKey::from_der(leaf.public_key().raw).map_err(SgetError::InvalidMaterial)?;
+    roots.algorithms.check_key(&key)?;
+    key.verify(msg, signature)
+        .map_err(SgetError::InvalidSignature)?;
+    certificate_identity(&leaf)
+}
+
+/// The identity (email or URI) and OIDC issuer a Fulcio certificate was issued to.
+pub fn certificate_identity(cert: &X509Certificate) -> Result<(String, Option<String>)> {
+    let identity = cert
+        .tbs_certificate
+        .subject_alternative_name()
+        .and_then(|(_, san)| {
+            san.general_names.iter().find_map(|name| match name {
+                GeneralName::RFC822Name(email) => Some(email.to_string()),
+                GeneralName::URI(uri) => Some(uri.to_string()),
+                _ => None,
+            })
+        })
+        .ok_or_else(|| {
+            SgetError::UntrustedCertificate("Certificate has no identity".to_string())
+        })?;
+    let issuer = cert
+        .extensions()
+        .iter()
+        .find(|ext| ext.oid.to_id_string() == OID_FULCIO_ISSUER)
+        .map(|ext| String::from_utf8_lossy(ext.value).into_owned());
+    Ok((identity, issuer))
+}
+
+/// The canonical JSON encoding of a bundle payload that Rekor signs.
+pub fn canonical_payload(bundle: &Bundle) -> Result<Vec<u8>> {
+    // serde_json maps sort their keys and serialize without whitespace.
+    let value: Value = serde_json::to_value(&bundle.payload).map_err(anyhow::Error::from)?;
+    Ok(serde_json::to_vec(&value).map_err(anyhow::Error::from)?)
+}
+
+/// Verify the signed entry timestamp of a bundle and that the logged entry is
+/// for this exact signature over this exact data.
+pub fn verify_bundle(
+    bundle: &Bundle,
+    data: &[u8],
+    signature: &[u8],
+    rekor_keys: &[PublicKey],
+) -> Result<()> {
+    let log_error = |message: String| SgetError::TransparencyLogError(message);
+    let log_key = rekor_keys
+        .iter()
+        .find(|key| key.key_id().ok().as_deref() == Some(bundle.payload.log_id.as_str()))
+        .ok_or_else(|| {
+            log_error(format!(
+                "Bundle is from an untrusted log {}",
+                bundle.payload.log_id
+            ))
+        })?;
+    let set = base64::decode(&bundle.signed_entry_timestamp)
+        .map_err(|e| log_error(format!("Invalid signed entry timestamp: {}", e)))?;
+    log_key
+        .verify(&canonical_payload(bundle)?, &set)
+        .map_err(|e| log_error(format!("Invalid signed entry timestamp: {}", e)))?;
+
+    let body: Value = base64::decode(&bundle.payload.body)
+        .map_err(anyhow::Error::from)
+        .and_then(|body| Ok(serde_json::from_slice(&body)?))
+        .map_err(|e| log_error(format!("Invalid Rekor entry: {}", e)))?;
+    let spec = &body["spec"];
+    let logged_digest = spec["data"]["hash"]["value"].as_str();
+    if logged_digest != Some(hex::encode(Sha256::digest(data)).as_str()) {
+        return Err(log_error(
+            "Rekor entry is for a different artifact".to_string(),
+        ));
+    }
+    let logged_signature = spec["signature"]["content"].as_str();
+    if logged_signature != Some(base64::encode(signature).as_str()) {
+        return Err(log_error(
+            "Rekor entry is for a different signature".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Verify a signed root policy: it must not have expired at `now`, and at least
+/// the root role's threshold of keys must have signed it.
+///
+/// Signatures by `sigstore-oidc` keys need a certificate that chains to a Fulcio
+/// root and was issued to the key's identity. Unless a signature carries a
+/// signed timestamp, the certificate's validity period cannot be checked.
+/// Signatures by other keys are checked by the verifier registered in `verifiers` for their key type.
+/// Verification runs as the policy load stage of `pipeline`.
+///
+/// Policies that include a parent must be verified with
+/// [`verify_policy_with_parent`] instead.
+pub fn verify_policy(
+    raw: &[u8],
+    roots: &TrustRoots,
+    verifiers: &SignatureVerifiers,
+    pipeline: &Pipeline,
+    now: DateTime<Utc>,
+) -> Result<Policy> {
+    verify_policy_with_parent(raw, None, roots, verifiers, pipeline, now)
+}
+
+/// Like [`verify_policy`], for a policy that includes `parent`. The parent must
+/// already be verified and flattened, and the caller must have checked it
+/// against the digest the policy pins. The policy's own root role, or the
+/// inherited one if it has none, must have signed it. Returns the policy
+/// flattened with [`Policy::inherit`].
+pub fn verify_policy_with_parent(
+    raw: &[u8],
+    parent: Option<Policy>,
+    roots: &TrustRoots,
+    verifiers: &SignatureVerifiers,
+    pipeline: &Pipeline,
+    now: DateTime<Utc>,
+) -> Result<Policy> {
+    let digest = hex::encode(Sha256::digest(raw));
+    let context = StageContext {
+        stage: Stage::PolicyLoad,
+        digest: &digest,
+        identity: None,
+    };
+    pipeline.run(context, || {
+        let mut policy = load_policy(raw, parent.as_ref(), roots, verifiers, now)?;
+        if let Some(parent) = parent {
+            policy.inherit(parent);
+        }
+        Ok(policy)
+    })
+}
+
+/// Signatures are checked against a [`RawSigned`] view of the policy, so only
+/// the root role and the keys that signed are deserialized before the policy
+/// is trusted, and only the keys its roles and target rules use after.
+fn load_policy(
+    raw: &[u8],
+    parent: Option<&Policy>,
+    roots: &TrustRoots,
+    verifiers: &SignatureVerifiers,
+    now: DateTime<Utc>,
+) -> Result<Policy> {
+    let invalid = |e: serde_json::Error| SgetError::InvalidPolicy(e.to_string());
+    let raw_policy: RawPolicy = serde_json::from_slice(raw).map_err(invalid)?;
+    let version: SpecVersion = serde_json::from_str(raw_policy.signed.get()).map_err(invalid)?;
+    if !policy::supports_spec_version(&version.spec_version) {
+        return Err(SgetError::UnsupportedSpecVersion(version.spec_version));
+    }
+    let view: RawSigned = serde_json::from_str(raw_policy.signed.get()).map_err(invalid)?;
+    if view.expires <= now {
+        return Err(SgetError::PolicyExpired(view.expires));
+    }
+    match (&view.include, parent) {
+        (Some(include), None) => {
+            return Err(SgetError::InvalidPolicy(format!(
+                "Policy includes {}, which was not resolved",
+                include.url
+            )))
+        }
+        (None, Some(_)) => {
+            return Err(SgetError::InvalidPolicy(
+                "Policy does not include a parent".to_string(),
+            ))
+        }
+        _ => {}
+    }
+    let role = view
+        .roles
+        .get("root")
+        .or_else(|| parent.and_then(|parent| parent.signed.roles.get("root")))
+        .ok_or_else(|| SgetError::InvalidPolicy("Policy has no root role".to_string()))?;
+    if let Some(expires) = role.expires.filter(|_| role.expired_at(now)) {
+        return Err(SgetError::RoleExpired {
+            role: "root".to_string(),
+            expires,
+        });
+    }
+    let signatures: Vec<policy::Signature> =
+        serde_json::from_str(raw_policy.signatures.get()).map_err(invalid)?;
+    let signed = raw_policy.signed.get().as_bytes();
+    let candidates: Vec<&policy::Signature> = signatures
+        .iter()
+        .filter(|sig| role.keyids.contains(&sig.keyid))
+        .collect();
+    let valid = check_each(&candidates, |sig| {
+        verify_policy_signature(&view, parent, sig, signed, roots, verifiers, now).is_ok()
+    });
+    let signers: BTreeSet<&str> = candidates
+        .iter()
+        .zip(valid)
+        .filter(|(_, valid)| *valid)
+        .map(|(sig, _)| sig.keyid.as_str())
+        .collect();
+    let threshold = role.threshold.get();
+    if (signers.len() as u64) < threshold {
+        return Err(SgetError::ThresholdNotMet {
+            found: signers.len(),
+            required: threshold,
+            missing: role
+                .keyids
+                .iter()
+                .filter(|keyid| !signers.contains(keyid.as_str()))
+                .map(|keyid| view.key_label(parent, keyid))
+                .collect(),
+        });
+    }
+    Ok(Policy {
+        signatures,
+        signed: view
+            .into_signed()
+            .map_err(|e| SgetError::InvalidPolicy(e.to_string()))?,
+    })
+}
+
+/// Run `check` on every item, returning its results in the order of `items`.
+/// Items are split between up to one scoped thread per CPU, since policies
+/// with many signatures spend most of their verification time checking them;
+/// any chunk whose thread cannot be started is checked on the calling thread.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn check_each<T: Sync>(items: &[T], check: impl Fn(&T) -> bool + Sync) -> Vec<bool> {
+    let threads = std::thread::available_parallelism()
+        .map_or(1, std::num::NonZeroUsize::get)
+        .min(items.len());
+    if threads <= 1 {
+        return items.iter().map(check).collect();
+    }
+    let check = &check;
+    std::thread::scope(|scope| {
+        let chunks: Vec<_> = items
+            .chunks(items.len().div_ceil(threads))
+            .map(|chunk| {
+                let handle = std::thread::Builder::new()
+                    .spawn_scoped(scope, move || chunk.iter().map(check).collect::<Vec<_>>());
+                (chunk, handle)
+            })
+            .collect();
+        chunks
+            .into_iter()
+            .flat_map(|(chunk, handle)| match handle {
+                Ok(handle) => handle
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e)),
+                Err(_) => chunk.iter().map(check).collect(),
+            })
+            .collect()
+    })
+}
+
+/// Run `check` on every item, returning its results in the order of `items`.
+/// Threads are not available to `wasm32-unknown-unknown`.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn check_each<T>(items: &[T], check: impl Fn(&T) -> bool) -> Vec<bool> {
+    items.iter().map(check).collect()
+}
+
+pub(crate) fn verify_policy_signature(
+    view: &RawSigned,
+    parent: Option<&Policy>,
+    sig: &policy::Signature,
+    signed: &[u8],
+    roots: &TrustRoots,
+    verifiers: &SignatureVerifiers,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    match view.key(&sig.keyid)? {
+        Some(key) => check_policy_signature(&key, sig, signed, view.expires, roots, verifiers, now),
+        None => match parent.and_then(|parent| parent.signed.keys.get(&sig.keyid)) {
+            Some(key) => {
+                check_policy_signature(key, sig, signed, view.expires, roots, verifiers, now)
+            }
+            None => Err(anyhow!("Unknown key {}", sig.keyid)),
+        },
+    }
+}
+
+/// Check a policy signature by `key` over `signed`, a policy or metadata that
+/// expires at `expires`.
+///
+/// A signature with a signed timestamp must have been made before `expires`,
+/// and its key must not have expired, nor its certificate been outside its
+/// validity period, at the time it was made. Without one the key's expiry is
+/// judged at `now` and a certificate's validity cannot be checked; the system
+/// policy may refuse such signatures.
+pub(crate) fn check_policy_signature(
+    key: &Key,
+    sig: &policy::Signature,
+    signed: &[u8],
+    expires: DateTime<Utc>,
+    roots: &TrustRoots,
+    verifiers: &SignatureVerifiers,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let signature = base64::decode(&sig.sig)?;
+    let signed_at = match &sig.timestamp {
+        Some(timestamp) => {
+            let time = timestamp
+                .verify(signed, &signature, roots)
+                .map_err(|e| anyhow!("Invalid timestamp: {}", e))?;
+            if time > expires {
+                return Err(anyhow!(
+                    "Signature was made at {}, after the policy expired at {}",
+                    time,
+                    expires
+                ));
+            }
+            Some(time)
+        }
+        None if roots.system.require_signing_time => {
+            return Err(anyhow!(
+                "The system policy requires a signed timestamp on signature by {}",
+                sig.keyid
+            ))
+        }
+        None => None,
+    };
+    let at = signed_at.unwrap_or(now);
+    if let Some(expires) = key.expires().filter(|_| key.expired_at(at)) {
+        return Err(anyhow!(
+            "Key {} expired at {}",
+            key.label(&sig.keyid),
+            expires
+        ));
+    }
+    match key {
+        Key::SigstoreOidc { keyval, .. } => {
+            let pem = sig.certificate_chain()?;
+            let (identity, issuer) = verify_certificate_signature(&pem, signed, &signature, roots)?;
+            if identity != keyval.identity
+                || (!keyval.issuer.is_empty() && issuer.as_deref() != Some(&keyval.issuer))
+            {
+                return Err(anyhow!("Certificate is not for {}", keyval.identity));
+            }
+            if let Some(time) = signed_at {
+                let chain = pem_certificates(&pem)?;
+                let validity = parse_certificate(&chain[0])?.validity().clone();
+                if time.timestamp() < validity.not_before.timestamp()
+                    || time.timestamp() > validity.not_after.timestamp()
+                {
+                    return Err(anyhow!(
+                        "Signature was made outside the certificate's validity period"
+                    ));
+                }
+            }
+            Ok(())
+        }
+        key => verifiers.verify(key, signed, &signature),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyAlgorithm, SigningKey};
+    use crate::rekor::BundlePayload;
+    use crate::timestamp::SignedTimestamp;
+    use serde_json::json;
+    use std::fs;
+    use std::path::Path;
+
+    const CRATE: &str = env!("CARGO_MANIFEST_DIR");
+
+    fn policy_certificate() -> Vec<u8> {
+        let raw = fs::read(Path::new(CRATE).join("tests/test_data/policy_good.json"))
+            .expect("Cannot read good policy file");
+        let policy: Policy = serde_json::from_slice(&raw).expect("Cannot deserialize policy");
+        let pem = base64::decode(&policy.signatures[0].cert).expect("Invalid cert encoding");
+        pem_certificates(&pem)
+            .expect("Invalid certificate")
+            .remove(0)
+    }
+
+    fn make_bundle(log: &SigningKey, data: &[u8], signature: &[u8], time: i64) -> Bundle {
+        let body = json!({
+            "apiVersion": "0.0.1",
+            "kind": "hashedrekord",
+            "spec": {
+                "signature": { "content": base64::encode(signature) },
+                "data": { "hash": { "algorithm": "sha256", "value": hex::encode(Sha256::digest(data)) } },
+            },
+        });
+        let mut bundle = Bundle {
+            signed_entry_timestamp: String::new(),
+            payload: BundlePayload {
+                body: base64::encode(body.to_string()),
+                integrated_time: time,
+                log_id: log.public_key().key_id().expect("No key id"),
+                log_index: 1,
+            },
+        };
+        let payload = canonical_payload(&bundle).expect("Cannot encode payload");
+        bundle.signed_entry_timestamp = base64::encode(log.sign(&payload));
+        bundle
+    }
+
+    #[test]
+    fn policy_certificate_chains_to_sigstore_root() {
+        let der = policy_certificate();
+        let (_, cert) = parse_x509_certificate(&der).expect("Cannot parse certificate");
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        assert!(verify_chain(&cert, &[], &roots.fulcio_roots, &roots.algorithms).is_ok());
+        assert!(verify_chain(&cert, &[], &roots.fulcio_roots[1..], &roots.algorithms).is_err());
+
+        let (identity, issuer) = certificate_identity(&cert).expect("No identity");
+        assert_eq!(identity, "jpenumak@redhat.com");
+        assert_eq!(issuer.as_deref(), Some("https://github.com/login/oauth"));
+    }
+
+    #[test]
+    fn canonical_payload_sorts_keys() {
+        let log = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let bundle = make_bundle(&log, b"data", b"sig", 7);
+        let payload = String::from_utf8(canonical_payload(&bundle).expect("Cannot encode"))
+            .expect("Not UTF-8");
+        assert!(payload.starts_with("{\"body\":"));
+        assert!(payload.contains(",\"integratedTime\":7,\"logID\":"));
+        assert!(payload.ends_with(",\"logIndex\":1}"));
+    }
+
+    #[test]
+    fn verify_key_signature_with_bundle() {
+        let log = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let signer = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key");
+        let roots = TrustRoots {
+            fulcio_roots: Vec::new(),
+            rekor_keys: vec![log.public_key()],
+            timestamp_authorities: Vec::new(),
+            algorithms: AlgorithmPolicy::default(),
+            system: SystemPolicy::default(),
+            identities: IdentityMap::default(),
+            policy: None,
+        };
+        let data = b"#!/bin/sh\necho hello\n";
+        let signature = signer.sign(data);
+        let mut sig = BlobSignature {
+            signature: signature.clone(),
+            certificate: None,
+            public_key: Some(signer.public_key()),
+            bundle: Some(make_bundle(&log, data, &signature, 1637699000)),
+        };
+
+        let verified = verify_blob(data, &sig, &roots, None, &Pipeline::default())
+            .expect("Verification failed");
+        assert_eq!(verified.integrated_time, Some(1637699000));
+        assert_eq!(
+            verified.key_id,
+            signer.public_key().key_id().expect("No key id")
+        );
+
+        // Tampered data, a bundle for other data, or an untrusted log all fail.
+        assert!(matches!(
+            verify_blob(b"tampered", &sig, &roots, None, &Pipeline::default()),
+            Err(SgetError::InvalidSignature(_))
+        ));
+        sig.bundle = Some(make_bundle(&log, b"other", &signature, 1637699000));
+        assert!(matches!(
+            verify_blob(data, &sig, &roots, None, &Pipeline::default()),
+            Err(SgetError::TransparencyLogError(_))
+        ));
+        let untrusted = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("No key");
+        sig.bundle = Some(make_bundle(&untrusted, data, &signature, 1637699000));
+        assert!(matches!(
+            verify_blob(data, &sig, &roots, None, &Pipeline::default()),
+            Err(SgetError::TransparencyLogError(_))
+        ));
+
+        // Keys carry no identity, so identity constraints can never be met.
+        sig.bundle = None;
+        let expected = SigstoreOidcKey {
+            identity: "jpenumak@redhat.com".to_string(),
+            issuer: String::new(),
+        };
+        assert!(matches!(
+            verify_blob(data, &sig, &roots, Some(&expected), &Pipeline::default()),
+            Err(SgetError::IdentityMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn check_signatures_in_order() {
+        let items: Vec<usize> = (0..101).collect();
+        let results = check_each(&items, |i| i % 3 == 0);
+        let expected: Vec<bool> = items.iter().map(|i| i % 3 == 0).collect();
+        assert_eq!(results, expected);
+        assert!(check_each(&[] as &[usize], |_| true).is_empty());
+    }
+
+    #[test]
+    fn verify_policy_threshold() {
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let verifiers = SignatureVerifiers::default();
+        let now = "2021-11-24T00:00:00Z".parse().expect("Invalid date");
+
+        // The test policy carries one valid signature of the two its root role needs.
+        let raw = fs::read(Path::new(CRATE).join("tests/test_data/policy_good.json"))
+            .expect("Cannot read good policy file");
+        match verify_policy(&raw, &roots, &verifiers, &Pipeline::default(), now) {
+            Err(SgetError::ThresholdNotMet {
+                found, required, ..
+            }) => {
+                assert_eq!((found, required), (1, 2))
+            }
+            _ => panic!("Threshold not enforced"), //#[allow_ci]
+        }
+
+        let keys: Vec<SigningKey> = vec![
+            SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key"),
+            SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("Cannot generate key"),
+        ];
+        let ids: Vec<String> = keys
+            .iter()
+            .map(|key| key.public_key().key_id().expect("No key id"))
+            .collect();
+        let policy_keys: serde_json::Map<String, Value> = keys
+            .iter()
+            .zip(&ids)
+            .map(|(key, id)| {
+                let entry = key.public_key().to_policy_key().expect("Cannot encode key");
+                let mut entry = serde_json::to_value(entry).expect("Invalid key");
+                if *id == ids[1] {
+                    entry["name"] = json!("alice-yubikey");
+                }
+                (id.clone(), entry)
+            })
+            .collect();
+        let signed = json!({
+            "consistent_snapshot": true,
+            "expires": "2030-01-01T00:00:00Z",
+            "keys": policy_keys,
+            "namespace": "example.com/scripts",
+            "roles": { "root": { "keyids": ids, "threshold": 2 } },
+            "spec_version": "1.0",
+            "version": 1,
+        })
+        .to_string();
+        let signature = |key: &SigningKey, id: &str| {
+            json!({
+                "keyid": id,
+                "sig": base64::encode(key.sign(signed.as_bytes())),
+                "cert": "",
+            })
+        };
+        let policy = |signatures: Vec<Value>| {
+            format!(
+                "{{\"signatures\":{},\"signed\":{}}}",
+                Value::from(signatures),
+                signed
+            )
+        };
+
+        let both = policy(vec![
+            signature(&keys[0], &ids[0]),
+            signature(&keys[1], &ids[1]),
+        ]);
+        assert!(verify_policy(
+            both.as_bytes(),
+            &roots,
+            &verifiers,
+            &Pipeline::default(),
+            now
+        )
+        .is_ok());
+        let expired = "2031-01-01T00:00:00Z".parse().expect("Invalid date");
+        assert!(matches!(
+            verify_policy(
+                both.as_bytes(),
+                &roots,
+                &verifiers,
+                &Pipeline::default(),
+                expired
+            ),
+            Err(SgetError::PolicyExpired(_))
+        ));
+        // Policies of another major spec version are rejected before anything else.
+        let newer = both.replace("\"spec_version\":\"1.0\"", "\"spec_version\":\"2.0\"");
+        let error = verify_policy(
+            newer.as_bytes(),
+            &roots,
+            &verifiers,
+            &Pipeline::default(),
+            expired,
+        )
+        .err()
+        .expect("Spec version not checked");
+        assert_eq!(
+            error.to_string(),
+            "Policy spec version 2.0 is not supported; this sget reads spec version 1.x policies"
+        );
+        // A key signing twice counts once, and the message names the key missing.
+        let repeated = policy(vec![
+            signature(&keys[0], &ids[0]),
+            signature(&keys[0], &ids[0]),
+        ]);
+        let error = verify_policy(
+            repeated.as_bytes(),
+            &roots,
+            &verifiers,
+            &Pipeline::default(),
+            now,
+        )
+        .err()
+        .expect("Threshold not enforced");
+        assert!(matches!(error, SgetError::ThresholdNotMet { found: 1, .. }));
+        assert_eq!(
+            error.to_string(),
+            "Policy has 1 of 2 required root signatures; required key 'alice-yubikey' missing"
+        );
+    }
+
+    #[test]
+    fn expired_keys() {
+        let roots = TrustRoots::sigstore().expect("Cannot load roots");
+        let signing = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("No key");
+        let mut key = signing.public_key().to_policy_key().expect("No policy key");
+        key.metadata_mut().expires = Some("2025-01-01T00:00:00Z".parse().expect("Invalid date"));
+        let signed = b"{\"version\":1}";
+        let sig = policy::Signature {
+            keyid: "release".to_string(),
+            sig: base64::encode(signing.sign(signed)),
+            cert: String::new(),
+            chain: Vec::new(),
+            timestamp: None,
+        };
+        let check = |now: &str| {
+            let now = now.parse().expect("Invalid date");
+            let expires = "2030-01-01T00:00:00Z".parse().expect("Invalid date");
+            check_policy_signature(
+                &key,
+                &sig,
+                signed,
+                expires,
+                &roots,
+                &Default::default(),
+                now,
+            )
+        };
+        assert!(check("2024-12-31T00:00:00Z").is_ok());
+        assert!(check("2025-01-01T00:00:00Z").is_err());
+
+        // Artifacts logged before the key expired are still trusted.
+        let signer = |integrated_time| Signer {
+            key_id: signing.public_key().key_id().expect("No key id"),
+            identity: None,
+            issuer: None,
+            integrated_time: Some(integrated_time),
+        };
+        assert!(policy_set::key_matches(&key, &signer(1700000000)));
+        assert!(!policy_set::key_matches(&key, &signer(1740000000)));
+    }
+
+    #[test]
+    fn signing_time() {
+        let log = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("No key");
+        let mut roots = TrustRoots::sigstore().expect("Cannot load roots");
+        roots.rekor_keys = vec![log.public_key()];
+        let signing = SigningKey::generate(KeyAlgorithm::EcdsaP256).expect("No key");
+        let mut key = signing.public_key().to_policy_key().expect("No policy key");
+        key.metadata_mut().expires = Some("2025-01-01T00:00:00Z".parse().expect("Invalid date"));
+        let signed = b"{\"version\":1}";
+        let signature = signing.sign(signed);
+        let timestamped = |time| policy::Signature {
+            keyid: "release".to_string(),
+            sig: base64::encode(&signature),
+            cert: String::new(),
+            chain: Vec::new(),
+            timestamp: Some(SignedTimestamp::Rekor {
+                bundle: make_bundle(&log, signed, &signature, time),
+            }),
+        };
+        let expires = "2030-01-01T00:00:00Z".parse().expect("Invalid date");
+        let now = "2026-01-01T00:00:00Z".parse().expect("Invalid date");
+        let check = |sig: &policy::Signature, roots: &TrustRoots, expires| {
+            check_policy_signature(&key, sig, signed, expires, roots, &Default::default(), now)
+        };
+
+        // Signed in 2023, before the key expired.
+        let sig = timestamped(1700000000);
+        assert!(check(&sig, &roots, expires).is_ok());
+        // Signed after the key expired, or after the policy did.
+        assert!(check(&timestamped(1740000000), &roots, expires).is_err());
+        let early = "2023-01-01T00:00:00Z".parse().expect("Invalid date");
+        assert!(check(&sig, &roots, early).is_err());
+        // A timestamp for another signature proves nothing.
+        let mut other = timestamped(1700000000);
+        other.sig = base64::encode(signing.sign(b"{\"version\":2}"));
+        assert!(check(&other, &roots, expires).is_err());
+
+        let untimed = policy::Signature {
+            timestamp: None,
+            ..timestamped(0)
+        };
+        assert!(check(&untimed, &roots, expires).is_err());
+        // Keys that never expire only need a timestamp when the system policy
+        // requires one.
+        let lasting = signing.public_key().to_policy_key().expect("No policy key");
+        let check = |roots: &TrustRoots| {
+            check_policy_signature(
+                &lasting,
+                &untimed,
+                signed,
+                expires,
+                roots,
+                &Default::default(),
+                now,
+            )
+        };
+        assert!(check(&roots).is_ok());
+        roots.system.require_signing_time = true;
+        assert!(check(&roots).is_err());
+    }
+
+    #[test]
+    fn check_signer_against_root_policy() {
+        let mut roots = TrustRoots::builtin().expect("Cannot load roots");
+        assert!(!roots.fulcio_roots.is_empty());
+        roots.policy = Some(
+            serde_json::from_value(json!({
+                "signatures": [],
+                "signed": {
+                    "consistent_snapshot": false,
+                    "expires": "2030-01-01T00:00:00Z",
+                    "keys": {
+                        "dev": {
+                            "keytype": "sigstore-oidc",
+                            "scheme": "https://fulcio.sigstore.dev",
+                            "keyval": { "identity": "dev@acme.dev", "issuer": "" },
+                        },
+                        "ops": {
+                            "keytype": "sigstore-oidc",
+                            "scheme": "https://fulcio.sigstore.dev",
+                            "keyval": { "identity": "ops@acme.dev", "issuer": "" },
+                        },
+                    },
+                    "namespace": "ghcr.io/acme",
+                    "roles": { "root": { "keyids": ["dev"], "threshold": 1 } },
+                    "spec_version": "1.0",
+                    "version": 1,
+                    "targets": [{ "path": "deploy/*.sh", "keyids": ["ops"] }],
+                },
+            }))
+            .expect("Invalid policy"),
+        );
+        let signer = |identity: &str| Signer {
+            key_id: String::new(),
+            identity: Some(identity.to_string()),
+            issuer: None,
+            integrated_time: Some(1),
+        };
+        assert!(roots.check_signer(&signer("dev@acme.dev")).is_ok());
+        assert!(matches!(
+            roots.check_signer(&signer("mallory@acme.dev")),
+            Err(SgetError::UntrustedSigner(_))
+        ));
+
+        // Only the ops key may sign deploy scripts.
+        let deploy = Some("https://ghcr.io/acme/deploy/prod.sh");
+        assert!(roots
+            .check_signer_for(&signer("ops@acme.dev"), deploy)
+            .is_ok());
+        assert!(roots
+            .check_signer_for(&signer("dev@acme.dev"), deploy)
+            .is_err());
+        assert!(roots
+            .check_signer_for(
+                &signer("dev@acme.dev"),
+                Some("https://ghcr.io/acme/build.sh")
+            )
+            .is_ok());
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::rekor::Bundle;
+use crate::utils;
+
+/// How this sget was built, recorded by build.rs, and what it can tell about
+/// its own binary, so that incident responders can confirm exactly which
+/// sget verified an artifact.
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// The git commit built, or `unknown`.
+    pub commit: &'static str,
+    pub built_at: Option<DateTime<Utc>>,
+    pub target: &'static str,
+    pub profile: &'static str,
+    pub features: Vec<&'static str>,
+    /// Where the build's provenance is published, if the build named it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary: Option<BinaryInfo>,
+}
+
+/// The running executable.
+#[derive(Debug, Serialize)]
+pub struct BinaryInfo {
+    pub path: PathBuf,
+    /// Hex encoded SHA-256 digest of the executable.
+    pub sha256: String,
+    /// The Rekor entry of the release signature, from the `.bundle` file
+    /// released with the binary, if it is installed next to it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rekor: Option<RekorReference>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RekorReference {
+    pub log_index: i64,
+    pub log_id: String,
+    pub integrated_time: i64,
+}
+
+impl BuildInfo {
+    /// The build information of this sget, without its binary.
+    pub fn current() -> Self {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            commit: env!("SGET_BUILD_COMMIT"),
+            built_at: env!("SGET_BUILD_TIMESTAMP")
+                .parse()
+                .ok()
+                .and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+            target: env!("SGET_BUILD_TARGET"),
+            profile: env!("SGET_BUILD_PROFILE"),
+            features: env!("SGET_BUILD_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .collect(),
+            provenance: option_env!("SGET_BUILD_PROVENANCE"),
+            binary: None,
+        }
+    }
+
+    /// Add what can be learned about the running executable.
+    pub fn with_binary(mut self) -> Self {
+        self.binary = std::env::current_exe()
+            .ok()
+            .and_then(|path| binary_info(&path).ok());
+        self
+    }
+}
+
+fn binary_info(path: &Path) -> Result<BinaryInfo> {
+    let mut bundle = path.as_os_str().to_owned();
+    bundle.push(".bundle");
+    Ok(BinaryInfo {
+        path: path.to_path_buf(),
+        sha256: utils::sha256_file(path)?,
+        rekor: rekor_reference(Path::new(&bundle)),
+    })
+}
+
+/// The Rekor entry recorded in the bundle at `path`, if it can be read.
+fn rekor_reference(path: &Path) -> Option<RekorReference> {
+    let bundle: Bundle = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+    Some(RekorReference {
+        log_index: bundle.payload.log_index,
+        log_id: bundle.payload.log_id,
+        integrated_time: bundle.payload.integrated_time,
+    })
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("version")
+        .about("Print the version of sget")
+        .arg(
+            Arg::new("verbose")
+                .long("verbose")
+                .short('v')
+                .takes_value(false)
+                .about("Print the commit, build time, features and provenance of the build, and the digest of the binary, as JSON"),
+        )
+}
+
+pub(crate) fn run(matches: &ArgMatches) -> Result<()> {
+    let info = BuildInfo::current();
+    if !matches.is_present("verbose") {
+        println!("sget {} ({})", info.version, info.commit);
+        return Ok(());
+    }
+    println!("{}", serde_json::to_string_pretty(&info.with_binary())?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rekor::BundlePayload;
+
+    #[test]
+    fn describe_build() {
+        let info = BuildInfo::current();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.commit.is_empty());
+        assert!(info.built_at.is_some());
+        assert!(info.features.contains(&"native"));
+
+        let dir = tempfile::tempdir().expect("Cannot create temp dir");
+        let binary = dir.path().join("sget");
+        std::fs::write(&binary, b"binary").expect("Cannot write binary");
+        let described = binary_info(&binary).expect("Cannot describe binary");
+        assert_eq!(
+            described.sha256,
+            utils::sha256_file(&binary).expect("No digest")
+        );
+        assert_eq!(described.rekor, None);
+
+        let bundle = Bundle {
+            signed_entry_timestamp: String::new(),
+            payload: BundlePayload {
+                body: String::new(),
+                integrated_time: 1_650_000_000,
+                log_index: 42,
+                log_id: "c0d2".to_string(),
+            },
+        };
+        std::fs::write(
+            dir.path().join("sget.bundle"),
+            serde_json::to_vec(&bundle).expect("Cannot encode bundle"),
+        )
+        .expect("Cannot write bundle");
+        let described = binary_info(&binary).expect("Cannot describe binary");
+        assert_eq!(described.rekor.map(|rekor| rekor.log_index), Some(42));
+    }
+}
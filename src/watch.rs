@@ -0,0 +1,524 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::audit::{Action, AuditLog, AuditRecord, Decision};
+use crate::config::{Config, NamespaceConfig};
+use crate::error::{FailureReason, SgetError};
+use crate::fetch::{fetch, fetch_digest};
+use crate::hooks::{self, Event, EventKind};
+use crate::metrics::{self, MeteredTransport, Metrics};
+use crate::policies;
+use crate::policy::{key_label, Policy};
+use crate::state;
+use crate::storage::{FileStorage, Storage};
+use crate::transport::{self, Transport};
+use crate::trust::TrustStore;
+use crate::tuf::Refreshed;
+use crate::utils::parse_duration;
+use crate::verify::TrustRoots;
+
+/// What watch mode knows about a namespace after a refresh.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub policy_version: Option<u64>,
+    pub policy_expires: Option<DateTime<Utc>>,
+    /// When the current policy version was first fetched.
+    #[serde(default)]
+    pub policy_refreshed: Option<DateTime<Utc>>,
+    pub key_ids: BTreeSet<String>,
+    /// The names of the keys that have one, by key ID.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub key_names: BTreeMap<String, String>,
+    /// Hex encoded SHA-256 digests of the targets, by location.
+    pub targets: BTreeMap<String, String>,
+}
+
+/// A change noticed between two refreshes.
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    PolicyVersion { from: Option<u64>, to: u64 },
+    KeyAdded { keyid: String, name: Option<String> },
+    KeyRemoved { keyid: String, name: Option<String> },
+    PolicyExpiring(DateTime<Utc>),
+    PolicyExpired(DateTime<Utc>),
+    TargetChanged { target: String, digest: String },
+    TargetRemoved(String),
+    MetadataRefreshed(Refreshed),
+    RefreshFailed { what: String, error: String },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::PolicyVersion {
+                from: Some(from),
+                to,
+            } => {
+                write!(f, "policy version changed from {} to {}", from, to)
+            }
+            Change::PolicyVersion { from: None, to } => write!(f, "policy version is {}", to),
+            Change::KeyAdded { keyid, name } => {
+                write!(f, "key added: {}", key_label(name.as_deref(), keyid))
+            }
+            Change::KeyRemoved { keyid, name } => {
+                write!(f, "key removed: {}", key_label(name.as_deref(), keyid))
+            }
+            Change::PolicyExpiring(at) => write!(f, "policy expires soon, at {}", at),
+            Change::PolicyExpired(at) => write!(f, "policy expired at {}", at),
+            Change::TargetChanged { target, digest } => {
+                write!(f, "target {} changed, now sha256:{}", target, digest)
+            }
+            Change::TargetRemoved(target) => write!(f, "target {} is no longer watched", target),
+            Change::MetadataRefreshed(refreshed) => write!(
+                f,
+                "metadata refreshed to timestamp {}, snapshot {}, targets {}",
+                refreshed.timestamp, refreshed.snapshot, refreshed.targets
+            ),
+            Change::RefreshFailed { what, error } => {
+                write!(f, "cannot refresh {}: {}", what, error)
+            }
+        }
+    }
+}
+
+impl Change {
+    /// The hook event for this change, if hooks can subscribe to it.
+    fn event(&self, namespace: &str, now: DateTime<Utc>) -> Option<Event> {
+        let (kind, details) = match self {
+            Change::PolicyVersion {
+                from: Some(from),
+                to,
+            } => (EventKind::PolicyVersion, json!({ "from": from, "to": to })),
+            Change::KeyAdded { keyid, name } => (
+                EventKind::KeyRotation,
+                json!({ "added": keyid, "name": name }),
+            ),
+            Change::KeyRemoved { keyid, name } => (
+                EventKind::KeyRotation,
+                json!({ "removed": keyid, "name": name }),
+            ),
+            Change::PolicyExpiring(at) => (
+                EventKind::PolicyExpiry,
+                json!({ "expires": at, "expired": false }),
+            ),
+            Change::PolicyExpired(at) => (
+                EventKind::PolicyExpiry,
+                json!({ "expires": at, "expired": true }),
+            ),
+            _ => return None,
+        };
+        Some(Event {
+            kind,
+            timestamp: now,
+            namespace: Some(namespace.to_string()),
+            message: self.to_string(),
+            details,
+        })
+    }
+}
+
+impl Snapshot {
+    fn record_policy(&mut self, policy: &Policy, previous: &Snapshot, now: DateTime<Utc>) {
+        let version = policy.signed.version.get();
+        self.policy_refreshed = match previous.policy_version == Some(version) {
+            true => previous.policy_refreshed.or(Some(now)),
+            false => Some(now),
+        };
+        self.policy_version = Some(version);
+        self.policy_expires = Some(policy.signed.expires);
+        self.key_ids = policy.signed.keys.keys().cloned().collect();
+        self.key_names = policy
+            .signed
+            .keys
+            .iter()
+            .filter_map(|(keyid, key)| Some((keyid.clone(), key.name()?.to_string())))
+            .collect();
+    }
+
+    /// Fail if the policy version has not changed for longer than `max_age`,
+    /// whether the remote kept serving it or could not be refreshed, as an
+    /// attacker may be withholding a newer policy.
+    pub fn check_freshness(&self, now: DateTime<Utc>, max_age: Duration) -> Result<(), SgetError> {
+        if let (Some(version), Some(since)) = (self.policy_version, self.policy_refreshed) {
+            if now - since > max_age {
+                return Err(SgetError::PossibleFreezeAttack { version, since });
+            }
+        }
+        Ok(())
+    }
+
+    /// The changes from `self` to `new`, including expiry warnings for `new`
+    /// relative to `now`.
+    pub fn changes(&self, new: &Snapshot, now: DateTime<Utc>, warn: Duration) -> Vec<Change> {
+        let mut changes = Vec::new();
+        if let Some(version) = new.policy_version {
+            if self.policy_version != Some(version) {
+                changes.push(Change::PolicyVersion {
+                    from: self.policy_version,
+                    to: version,
+                });
+            }
+            // Only report rotations against a policy we have seen before.
+            if self.policy_version.is_some() {
+                for id in new.key_ids.difference(&self.key_ids) {
+                    changes.push(Change::KeyAdded {
+                        keyid: id.clone(),
+                        name: new.key_names.get(id).cloned(),
+                    });
+                }
+                for id in self.key_ids.difference(&new.key_ids) {
+                    changes.push(Change::KeyRemoved {
+                        keyid: id.clone(),
+                        name: self.key_names.get(id).cloned(),
+                    });
+                }
+            }
+        }
+        if let Some(expires) = new.policy_expires {
+            if expires <= now {
+                changes.push(Change::PolicyExpired(expires));
+            } else if expires - now <= warn {
+                changes.push(Change::PolicyExpiring(expires));
+            }
+        }
+        for (target, digest) in &new.targets {
+            if self.targets.get(target) != Some(digest) {
+                changes.push(Change::TargetChanged {
+                    target: target.clone(),
+                    digest: digest.clone(),
+                });
+            }
+        }
+        for target in self.targets.keys() {
+            if !new.targets.contains_key(target) {
+                changes.push(Change::TargetRemoved(target.clone()));
+            }
+        }
+        changes
+    }
+}
+
+/// Fetch the namespace's policy and targets. Anything that cannot be fetched
+/// keeps its previous state and is reported as a failed refresh. Target fetches
+/// are recorded in the audit log.
+async fn refresh(
+    transport: &dyn Transport,
+    namespace: &NamespaceConfig,
+    base: &Path,
+    previous: &Snapshot,
+    audit: &AuditLog,
+    metrics: &Metrics,
+    now: DateTime<Utc>,
+) -> (Snapshot, Vec<Change>) {
+    let mut snapshot = Snapshot::default();
+    let mut failures = Vec::new();
+
+    let policy = async {
+        let raw = fetch(transport, &namespace.policy, base).await?;
+        serde_json::from_slice::<Policy>(&raw).context("Invalid policy")
+    };
+    match policy.await {
+        Ok(policy) => snapshot.record_policy(&policy, previous, now),
+        Err(e) => {
+            snapshot.policy_version = previous.policy_version;
+            snapshot.policy_expires = previous.policy_expires;
+            snapshot.policy_refreshed = previous.policy_refreshed;
+            snapshot.key_ids = previous.key_ids.clone();
+            snapshot.key_names = previous.key_names.clone();
+            failures.push(Change::RefreshFailed {
+                what: namespace.policy.clone(),
+                error: format!("{:#}", e),
+            });
+        }
+    }
+
+    for target in &namespace.targets {
+        let mut record = AuditRecord::new(Action::Fetch, target);
+        record.policy_version = snapshot.policy_version;
+        let start = Instant::now();
+        let fetched = fetch_digest(transport, target, base).await;
+        metrics.record(fetched.as_ref().map(|_| ()), start.elapsed());
+        match fetched {
+            Ok(digest) => {
+                record.digest = Some(digest.clone());
+                snapshot.targets.insert(target.clone(), digest);
+            }
+            Err(e) => {
+                record.decision = Decision::Deny;
+                record.reason = Some(FailureReason::Fetch.label().to_string());
+                record.error = Some(e.describe());
+                if let Some(digest) = previous.targets.get(target) {
+                    snapshot.targets.insert(target.clone(), digest.clone());
+                }
+                failures.push(Change::RefreshFailed {
+                    what: target.clone(),
+                    error: format!("{:#}", e),
+                });
+            }
+        }
+        audit.record_or_warn(&record);
+    }
+    (snapshot, failures)
+}
+
+/// Snapshots are kept in the `watch` directory of the state directory, one per
+/// namespace.
+fn snapshot_storage() -> Result<FileStorage> {
+    Ok(FileStorage::new(state::state_dir()?.join("watch")))
+}
+
+fn snapshot_key(namespace: &str) -> String {
+    format!("{}.json", namespace)
+}
+
+fn load_snapshot(storage: &dyn Storage, namespace: &str) -> Result<Snapshot> {
+    match storage.get(&snapshot_key(namespace))? {
+        Some(raw) => Ok(serde_json::from_slice(&raw)?),
+        None => Ok(Snapshot::default()),
+    }
+}
+
+fn save_snapshot(storage: &dyn Storage, namespace: &str, snapshot: &Snapshot) -> Result<()> {
+    storage.put(
+        &snapshot_key(namespace),
+        &serde_json::to_vec_pretty(snapshot)?,
+    )
+}
+
+pub(crate) fn command() -> App<'static> {
+    App::new("watch")
+        .about("Periodically refresh a namespace's policy and targets and report changes")
+        .arg(
+            Arg::new("namespace")
+                .long("namespace")
+                .value_name("NAMESPACE")
+                .takes_value(true)
+                .required(true)
+                .about("Configured namespace to watch"),
+        )
+        .arg(
+            Arg::new("interval")
+                .long("interval")
+                .value_name("DURATION")
+                .default_value("1h")
+                .about("Time between refreshes, e.g. 30m or 1h"),
+        )
+        .arg(
+            Arg::new("expiry-warning")
+                .long("expiry-warning")
+                .value_name("DURATION")
+                .default_value("7d")
+                .about("Report policies expiring within this window"),
+        )
+        .arg(
+            Arg::new("once")
+                .long("once")
+                .takes_value(false)
+                .about("Refresh once and exit"),
+        )
+        .arg(
+            Arg::new("metrics-listen")
+                .long("metrics-listen")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .conflicts_with("once")
+                .about("Export Prometheus metrics on http://ADDRESS/metrics"),
+        )
+}
+
+pub(crate) async fn run(matches: &ArgMatches) -> Result<()> {
+    let name = matches
+        .value_of("namespace")
+        .ok_or_else(|| anyhow!("No namespace given"))?;
+    let interval = parse_duration(matches.value_of("interval").unwrap_or("1h"))?
+        .to_std()
+        .map_err(|_| anyhow!("Interval must be positive"))?;
+    let warn = parse_duration(matches.value_of("expiry-warning").unwrap_or("7d"))?;
+    let config = Config::load()?;
+    let namespace = config.namespace(name)?;
+    let max_age = namespace
+        .max_metadata_age
+        .as_deref()
+        .map(parse_duration)
+        .transpose()?;
+    // Relative locations in the config are relative to the config file.
+    let base = Config::path()?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let audit = AuditLog::open(&config)?;
+    let metrics = Arc::new(Metrics::default());
+    if let Some(addr) = matches.value_of("metrics-listen") {
+        let addr: SocketAddr = addr
+            .parse()
+            .map_err(|e| anyhow!("Invalid metrics address: {}", e))?;
+        tokio::spawn(metrics::exporter(&addr, metrics.clone())?);
+        eprintln!("Exporting metrics on http://{}/metrics", addr);
+    }
+    let transport: Arc<dyn Transport> = Arc::new(MeteredTransport::new(
+        transport::default_transport(),
+        metrics.clone(),
+    ));
+    let snapshots = snapshot_storage()?;
+    let mut previous = load_snapshot(&snapshots, name)?;
+    let roots = match namespace.metadata {
+        Some(_) => Some(TrustRoots::load(&TrustStore::open()?)?),
+        None => None,
+    };
+
+    loop {
+        let now = Utc::now();
+        let (snapshot, mut noticed) = refresh(
+            transport.as_ref(),
+            namespace,
+            &base,
+            &previous,
+            &audit,
+            &metrics,
+            now,
+        )
+        .await;
+        if let (Some(roots), Some(metadata)) = (&roots, &namespace.metadata) {
+            match policies::refresh_metadata(transport.as_ref(), name, namespace, &base, roots)
+                .await
+            {
+                Ok(refreshed) if refreshed.updated => {
+                    noticed.push(Change::MetadataRefreshed(refreshed))
+                }
+                Ok(_) => {}
+                Err(e) => noticed.push(Change::RefreshFailed {
+                    what: metadata.clone(),
+                    error: format!("{:#}", e),
+                }),
+            }
+        }
+        for change in previous
+            .changes(&snapshot, now, warn)
+            .iter()
+            .chain(&noticed)
+        {
+            println!("{} {}: {}", now.to_rfc3339(), name, change);
+            if let Some(event) = change.event(name, now) {
+                hooks::fire(&config.hooks, &event).await;
+            }
+        }
+        save_snapshot(&snapshots, name, &snapshot)?;
+        if let Some(Err(e)) = max_age.map(|max_age| snapshot.check_freshness(now, max_age)) {
+            println!("{} {}: {}", now.to_rfc3339(), name, e);
+            let event = Event {
+                kind: EventKind::FreezeAttack,
+                timestamp: now,
+                namespace: Some(name.to_string()),
+                message: e.to_string(),
+                details: json!({
+                    "version": snapshot.policy_version,
+                    "since": snapshot.policy_refreshed,
+                }),
+            };
+            hooks::fire(&config.hooks, &event).await;
+            return Err(e.into());
+        }
+        previous = snapshot;
+
+        if matches.is_present("once") {
+            return Ok(());
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(version: u64, keys: &[&str], expires: &str) -> Snapshot {
+        Snapshot {
+            policy_version: Some(version),
+            policy_expires: Some(expires.parse().expect("Invalid date")),
+            policy_refreshed: None,
+            key_ids: keys.iter().map(|k| k.to_string()).collect(),
+            key_names: keys
+                .iter()
+                .map(|k| (k.to_string(), format!("{}-yubikey", k)))
+                .collect(),
+            targets: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn detect_rotation_and_expiry() {
+        let now = "2022-01-01T00:00:00Z".parse().expect("Invalid date");
+        let old = snapshot(1, &["a", "b"], "2022-06-01T00:00:00Z");
+        let new = snapshot(2, &["b", "c"], "2022-01-03T00:00:00Z");
+
+        let changes = old.changes(&new, now, Duration::days(7));
+        assert_eq!(
+            changes,
+            vec![
+                Change::PolicyVersion {
+                    from: Some(1),
+                    to: 2
+                },
+                Change::KeyAdded {
+                    keyid: "c".to_string(),
+                    name: Some("c-yubikey".to_string()),
+                },
+                Change::KeyRemoved {
+                    keyid: "a".to_string(),
+                    name: Some("a-yubikey".to_string()),
+                },
+                Change::PolicyExpiring("2022-01-03T00:00:00Z".parse().expect("Invalid date")),
+            ]
+        );
+        assert_eq!(changes[1].to_string(), "key added: 'c-yubikey'");
+        assert!(new.changes(&new, now, Duration::days(1)).is_empty());
+    }
+
+    #[test]
+    fn detect_target_changes() {
+        let now = Utc::now();
+        let mut old = Snapshot::default();
+        old.targets.insert("a.sh".to_string(), "11".to_string());
+        old.targets.insert("b.sh".to_string(), "22".to_string());
+        let mut new = Snapshot::default();
+        new.targets.insert("a.sh".to_string(), "33".to_string());
+
+        let changes = old.changes(&new, now, Duration::days(7));
+        assert_eq!(
+            changes,
+            vec![
+                Change::TargetChanged {
+                    target: "a.sh".to_string(),
+                    digest: "33".to_string()
+                },
+                Change::TargetRemoved("b.sh".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_freeze_attack() {
+        let mut served = snapshot(3, &["a"], "2023-01-01T00:00:00Z");
+        served.policy_refreshed = Some("2022-01-01T00:00:00Z".parse().expect("Invalid date"));
+        let soon = "2022-01-05T00:00:00Z".parse().expect("Invalid date");
+        let later = "2022-01-09T00:00:00Z".parse().expect("Invalid date");
+
+        assert!(served.check_freshness(soon, Duration::days(7)).is_ok());
+        assert!(matches!(
+            served.check_freshness(later, Duration::days(7)),
+            Err(SgetError::PossibleFreezeAttack { version: 3, .. })
+        ));
+        assert!(Snapshot::default()
+            .check_freshness(later, Duration::days(7))
+            .is_ok());
+    }
+}